@@ -2,8 +2,8 @@
 
 use super::ast;
 use super::parser::{emit_error, emit_warn};
-use std::collections::HashMap;
-use super::name::Name;
+use std::collections::{HashMap, HashSet};
+use super::name::{Ident, Name};
 use super::loader;
 use std::sync::atomic::{AtomicBool, Ordering};
 
@@ -79,9 +79,9 @@ impl Scope{
         if let Some(previous) = self.cur().get(&local) {
             if !is_module || !previous.is_module || fqn != previous.name {
 
-                emit_error(format!("conflicting local name '{}'", local), &[
-                       (loc.clone(), "declared here"),
-                       (previous.loc.clone(), "also declared here"),
+                emit_error(msg!("conflicting_local_name", name = local), &[
+                       (loc.clone(), msg!("conflicting_local_name.here")),
+                       (previous.loc.clone(), msg!("conflicting_local_name.previous")),
                 ]);
                 std::process::exit(9);
             }
@@ -159,10 +159,10 @@ impl Scope{
             | "unsigned"
             => {
                 let nuname = Name(vec![
-                    String::new(),
-                    "ext".to_string(),
-                    "<stddef.h>".to_string(),
-                    name.to_string(),
+                    "".into(),
+                    "ext".into(),
+                    "<stddef.h>".into(),
+                    name.to_string().into(),
                 ]);
                 debug!("  {} => {}", *name, nuname);
                 *name = nuname;
@@ -171,29 +171,29 @@ impl Scope{
             _ => (),
         };
 
-        let mut rhs : Vec<String> = name.0.clone();
+        let mut rhs : Vec<Ident> = name.0.clone();
         let lhs = rhs.remove(0);
 
         match self.get(&lhs) {
             None => {
                 if inbody {
                     if name.len() > 1 {
-                        emit_error(format!("possibly undefined name '{}'", lhs), &[
-                              (t.loc.clone(), "cannot use :: notation to reference names not tracked by zz"),
+                        emit_error(msg!("possibly_undefined_name", name = lhs), &[
+                              (t.loc.clone(), msg!("possibly_undefined_name.note")),
                         ]);
                         ABORT.store(true, Ordering::Relaxed);
                     }
                 } else {
-                    emit_error(format!("undefined name '{}'", lhs), &[
-                               (t.loc.clone(), "used in this scope"),
+                    emit_error(msg!("undefined_name", name = lhs), &[
+                               (t.loc.clone(), msg!("undefined_name.note")),
                     ]);
                     ABORT.store(true, Ordering::Relaxed);
                 }
             },
             Some(v) => {
                 if rhs.len() != 0  && !v.subtypes {
-                    emit_error(format!("resolving '{}' as member is not possible", name), &[
-                        (t.loc.clone(), format!("'{}' is not a module", lhs))
+                    emit_error(msg!("not_a_module_member", name = name), &[
+                        (t.loc.clone(), msg!("not_a_module_member.note", name = lhs))
                     ]);
                     ABORT.store(true, Ordering::Relaxed);
                 }
@@ -210,9 +210,9 @@ impl Scope{
                 */
 
                 if rhs.len() == 0 && v.is_module {
-                    emit_error(format!("cannot use module '{}' as a type", v.name), &[
-                           (t.loc.clone(), format!("cannot use module '{}' as a type", name)),
-                           (v.loc.clone(), format!("if you wanted to import '{}' as a type, use ::{{{}}} here", name, name)),
+                    emit_error(msg!("module_as_type", name = v.name), &[
+                           (t.loc.clone(), msg!("module_as_type", name = name)),
+                           (v.loc.clone(), msg!("module_as_type.suggestion", name = name)),
                     ]);
                     ABORT.store(true, Ordering::Relaxed);
                 }
@@ -227,9 +227,12 @@ impl Scope{
 }
 
 
-fn abs_import(imported_from: &Name, import: &ast::Import, all_modules: &HashMap<Name, loader::Module>) -> Name {
+/// the module an import of imported_from names. current is the module abs works on, which is not in all_modules
+fn abs_import(imported_from: &Name, import: &ast::Import, all_modules: &HashMap<Name, loader::Module>, current: &Name) -> Name {
+    let known = |name: &Name| all_modules.contains_key(name) || name == current;
+
     if import.name.is_absolute() {
-        if all_modules.contains_key(&import.name) {
+        if known(&import.name) {
             debug!("  import abs {} => {}", import.name, import.name);
             return import.name.clone();
         }
@@ -252,7 +255,7 @@ fn abs_import(imported_from: &Name, import: &ast::Import, all_modules: &HashMap<
         let mut search = imported_from.clone();
         search.pop();
         search.0.extend(import.name.0.clone());
-        if all_modules.contains_key(&search) {
+        if known(&search) {
             if &search != imported_from {
                 debug!("  import rel {} => {}", import.name, search);
                 return search;
@@ -261,8 +264,8 @@ fn abs_import(imported_from: &Name, import: &ast::Import, all_modules: &HashMap<
 
         // /search
         let mut search = import.name.clone();
-        search.0.insert(0, String::new());
-        if all_modules.contains_key(&search) {
+        search.0.insert(0, "".into());
+        if known(&search) {
             if &search != imported_from {
                 debug!("  import aabs {} => {}", import.name, search);
                 return search;
@@ -272,7 +275,7 @@ fn abs_import(imported_from: &Name, import: &ast::Import, all_modules: &HashMap<
         // /root/current/search
         let mut search = imported_from.clone();
         search.0.extend(import.name.0.clone());
-        if all_modules.contains_key(&search) {
+        if known(&search) {
             if &search != imported_from {
                 debug!("  import aabs/lib {} => {}", import.name, search);
                 return search;
@@ -281,7 +284,7 @@ fn abs_import(imported_from: &Name, import: &ast::Import, all_modules: &HashMap<
 
         // self
         let mut search = import.name.clone();
-        search.0.insert(0, String::new());
+        search.0.insert(0, "".into());
         if &search == imported_from  {
             if &search != imported_from {
                 debug!("  import self abs {} => {}", import.name, search);
@@ -304,8 +307,8 @@ fn abs_import(imported_from: &Name, import: &ast::Import, all_modules: &HashMap<
         }
     }
 
-    emit_error(format!("cannot find module '{}'", import.name), &[
-        (import.loc.clone(), "imported here"),
+    emit_error(msg!("module_not_found", name = import.name), &[
+        (import.loc.clone(), msg!("imported_here")),
     ]);
     std::process::exit(9);
 }
@@ -316,10 +319,22 @@ fn check_abs_available(
     all_modules: &HashMap<Name, loader::Module>,
     loc: &ast::Location,
     selfname: &Name
+) {
+    check_abs_available_via(fqn, this_vis, all_modules, loc, selfname, &mut Vec::new())
+}
+
+/// via are the modules re-exporting fqn so far, with where they import it
+fn check_abs_available_via(
+    fqn: &mut Name,
+    this_vis: &ast::Visibility,
+    all_modules: &HashMap<Name, loader::Module>,
+    loc: &ast::Location,
+    selfname: &Name,
+    via: &mut Vec<(Name, ast::Location)>,
 ) {
     if !fqn.is_absolute() && fqn.len() > 1 {
-        emit_warn(format!("relative name {} not resolved. likely due to previous error", fqn), &[
-            (loc.clone(), "this type is unresolved"),
+        emit_warn(msg!("unresolved_relative_name", name = fqn), &[
+            (loc.clone(), msg!("unresolved_relative_name.note")),
         ]);
         ABORT.store(true, Ordering::Relaxed);
         return;
@@ -361,8 +376,8 @@ fn check_abs_available(
 
     let module = match all_modules.get(&module_name) {
         None => {
-            emit_error(format!("cannot find module '{}' during abs of module '{}'", module_name, selfname), &[
-                   (loc.clone(), "expected to be in scope here"),
+            emit_error(msg!("module_not_found_in_abs", name = module_name, module = selfname), &[
+                   (loc.clone(), msg!("module_not_found_in_abs.note")),
             ]);
             std::process::exit(9);
         },
@@ -373,16 +388,23 @@ fn check_abs_available(
     for local2 in &module.locals {
         if local2.name == local_name {
             if local2.vis == ast::Visibility::Object {
-                emit_error(format!("the type '{}' in '{}' is private", local_name, module_name), &[
-                       (loc.clone(), "cannot use private type"),
-                       (local2.loc.clone(), "add 'pub' to share this type"),
+                emit_error(msg!("private_type", name = local_name, module = module_name), &[
+                       (loc.clone(), msg!("private_type.note")),
+                       (local2.loc.clone(), msg!("private_type.suggestion")),
+                ]);
+                ABORT.store(true, Ordering::Relaxed);
+            }
+            if local2.vis == ast::Visibility::Package && module_name.0.get(1) != selfname.0.get(1) {
+                emit_error(msg!("package_private", name = local_name, module = module_name, package = module_name.0[1]), &[
+                       (loc.clone(), msg!("package_private.note", package = selfname.0[1])),
+                       (local2.loc.clone(), msg!("package_private.suggestion")),
                 ]);
                 ABORT.store(true, Ordering::Relaxed);
             }
             if this_vis == &ast::Visibility::Export && local2.vis != ast::Visibility::Export {
-                emit_error(format!("the type '{}' in '{}' is not exported", local_name, module_name), &[
-                       (loc.clone(), "cannot use an unexported type here"),
-                       (local2.loc.clone(), "suggestion: export this type"),
+                emit_error(msg!("unexported_type", name = local_name, module = module_name), &[
+                       (loc.clone(), msg!("unexported_type.note")),
+                       (local2.loc.clone(), msg!("unexported_type.suggestion")),
                 ]);
                 ABORT.store(true, Ordering::Relaxed);
             }
@@ -392,42 +414,63 @@ fn check_abs_available(
 
 
     for import2 in &module.imports {
-        let importname = abs_import(&module.name, &import2, all_modules);
+        let importname = abs_import(&module.name, &import2, all_modules, selfname);
 
         if import2.vis == ast::Visibility::Object {
             continue;
         }
+        if import2.vis == ast::Visibility::Package && module_name.0.get(1) != selfname.0.get(1) {
+            continue;
+        }
         for (local3, local3_as) in &import2.local {
-            if let Some(local3_as) = &local3_as  {
-                if local3_as == &local_name {
-                    *fqn = Name::from(&format!("{}::{}", importname, local3));
-                    return check_abs_available(
-                        fqn,
-                        this_vis,
-                        all_modules,
-                        loc,
-                        selfname);
-                }
-            }
-            if local3 == &local_name {
+            let renamed = local3_as.as_ref() == Some(&local_name);
+            if renamed || local3 == &local_name {
+                let mut next = fqn.clone();
+                next.pop();
+                via.push((next, import2.loc.clone()));
                 *fqn = Name::from(&format!("{}::{}", importname, local3));
-                return check_abs_available(
+                if import_cycle(fqn, loc, via) {
+                    return;
+                }
+                return check_abs_available_via(
                     fqn,
                     this_vis,
                     all_modules,
                     loc,
-                    selfname);
+                    selfname,
+                    via);
             }
         }
     }
 
-    emit_error(format!("module '{}' does not contain '{}'", module_name, local_name), &[
-        (loc.clone(), "imported here"),
+    emit_error(msg!("module_does_not_contain", module = module_name, name = local_name), &[
+        (loc.clone(), msg!("imported_here")),
     ]);
     ABORT.store(true, Ordering::Relaxed);
 
 }
 
+/// whether fqn is re-exported by a module that re-exports it again further down via.
+/// reports the chain, since it can never end in an actual declaration
+fn import_cycle(fqn: &Name, loc: &ast::Location, via: &[(Name, ast::Location)]) -> bool {
+    let mut module = fqn.clone();
+    module.pop();
+    let start = match via.iter().position(|(m, _)| m == &module) {
+        Some(v) => v,
+        None => return false,
+    };
+    let local = fqn.0.last().cloned().unwrap_or_default();
+    let mut chain : Vec<String> = via[start..].iter().map(|(m, _)| m.to_string()).collect();
+    chain.push(module.to_string());
+
+    let mut estack = vec![(loc.clone(), msg!("imported_here"))];
+    for (i, (m, at)) in via[start..].iter().enumerate() {
+        estack.push((at.clone(), msg!("import_cycle.note", module = m, from = chain[i + 1])));
+    }
+    emit_error(msg!("import_cycle", name = local, chain = chain.join(" -> ")), &estack);
+    ABORT.store(true, Ordering::Relaxed);
+    true
+}
 
 fn abs_expr(
     expr: &mut ast::Expression,
@@ -596,30 +639,45 @@ pub fn abs(md: &mut ast::Module, all_modules: &HashMap<Name, loader::Module>, ex
     let mut newimports = Vec::new();
     for import in &mut md.imports {
 
-        let mut fqn  = abs_import(&md.name, &import, all_modules);
+        let mut fqn  = abs_import(&md.name, &import, all_modules, &md.name);
+
+        // an interface module stands for the backend zz.toml selects, see interface.rs
+        if let Some(loader::Module::ZZ(interface)) = all_modules.get(&fqn) {
+            if interface.interface.is_some() {
+                match &interface.backend {
+                    Some(backend) => fqn = backend.clone(),
+                    None => {
+                        emit_error(msg!("interface_no_backend", name = fqn), &[
+                            (import.loc.clone(), msg!("interface_no_backend.note", local = fqn.0.last().unwrap())),
+                        ]);
+                        std::process::exit(9);
+                    }
+                }
+            }
+        }
 
         // make ext includes absolute, so they dont conflict later
         if fqn.0[1] == "ext" {
-            let mut expr = import.name.0[2].clone();
+            let mut expr = import.name.0[2].to_string();
             if expr.starts_with("\"") && expr.len() > 2 {
                 let path = &expr[1..expr.len() - 1];
-                let path = std::path::Path::new(&import.loc.file).parent().expect("ICE: include path resolver").join(path);
+                let path = std::path::Path::new(&*import.loc.file.name()).parent().expect("ICE: include path resolver").join(path);
                 let path = match std::fs::canonicalize(&path) {
                     Ok(v) => v,
                     Err(e) => {
-                        emit_error(format!("path resolve error"), &[
+                        emit_error(msg!("path_resolve_error"), &[
                             (import.loc.clone(), format!("{} : {:?}", e, path)),
                         ]);
                         std::process::exit(9);
                     }
                 };
                 expr = path.to_string_lossy().into();
-                fqn.0[2] = format!("\"{}\"", expr);
+                fqn.0[2] = format!("\"{}\"", expr).into();
             }
         }
 
 
-        let local_module_name = import.alias.clone().unwrap_or(import.name.0.last().unwrap().clone());
+        let local_module_name = import.alias.clone().unwrap_or(import.name.0.last().unwrap().to_string());
 
         if import.local.len() == 0 {
             scope.insert(local_module_name, fqn.clone(), &import.loc, true, true);
@@ -629,7 +687,17 @@ pub fn abs(md: &mut ast::Module, all_modules: &HashMap<Name, loader::Module>, ex
                 let mut nn_o = fqn.clone();
                 nn_o.push(local.clone());
                 let mut nn = nn_o.clone();
-                check_abs_available(&mut nn, &import.vis, all_modules, &import.loc, &md.name);
+                let mut via = Vec::new();
+                check_abs_available_via(&mut nn, &import.vis, all_modules, &import.loc, &md.name, &mut via);
+
+                // re-exported back to this module, which doesn't declare it either
+                let mut owner = nn.clone();
+                let name = owner.pop().unwrap();
+                if owner == md.name && nn != nn_o && !md.locals.iter().any(|l| l.name == name) {
+                    via.insert(0, (md.name.clone(), import.loc.clone()));
+                    import_cycle(&nn, &import.loc, &via);
+                }
+
                 if nn_o == nn {
                     new_import_local.push((local.clone(), import_as.clone()));
                 } else {
@@ -657,11 +725,17 @@ pub fn abs(md: &mut ast::Module, all_modules: &HashMap<Name, loader::Module>, ex
     md.imports.extend(newimports);
 
 
+    // structs with methods declared in them, which are locals named struct::method
+    let with_methods : HashSet<String> = md.locals.iter()
+        .filter(|l| matches!(l.def, ast::Def::Function{..}))
+        .filter_map(|l| l.name.split("::").next().filter(|s| s.len() < l.name.len()).map(|s| s.to_string()))
+        .collect();
+
     let mut new_locals = Vec::new();
     // round one, just get all local defs
     for ast in &mut md.locals {
         let mut ns = md.name.clone();
-        ns.0.push(ast.name.clone());
+        ns.push(ast.name.clone());
         match &mut ast.def {
             ast::Def::Enum{names,..} => {
                 let mut value = 0;
@@ -688,10 +762,8 @@ pub fn abs(md: &mut ast::Module, all_modules: &HashMap<Name, loader::Module>, ex
                                 ptr:    Vec::new(),
                                 tail:   ast::Tail::None,
                             },
-                            expr: ast::Expression::Literal{
-                                loc:    ast.loc.clone(),
-                                v:      format!("{}", value.unwrap()),
-                            },
+                            expr: ast::Expression::literal(ast.loc.clone(), format!("{}", value.unwrap())),
+                            comptime: false,
                         }
                     });
                     let mut ns = md.name.clone();
@@ -700,6 +772,10 @@ pub fn abs(md: &mut ast::Module, all_modules: &HashMap<Name, loader::Module>, ex
                 }
                 scope.insert(ast.name.clone(), ns, &ast.loc, false, true);
             }
+            ast::Def::Struct{..} => {
+                let subtypes = with_methods.contains(&ast.name);
+                scope.insert(ast.name.clone(), ns, &ast.loc, false, subtypes);
+            }
             _ => {
                 scope.insert(ast.name.clone(), ns, &ast.loc, false, false);
             }
@@ -732,6 +808,9 @@ pub fn abs(md: &mut ast::Module, all_modules: &HashMap<Name, loader::Module>, ex
                     if let ast::Type::Other(ref mut name) = &mut ret.typed.t{
                         check_abs_available(name, &ast.vis, all_modules, &ret.typed.loc, &md.name);
                     }
+                    if let Some(array) = &mut ret.array {
+                        abs_expr(array, &scope, false, all_modules, &md.name);
+                    }
                 }
 
 
@@ -757,6 +836,14 @@ pub fn abs(md: &mut ast::Module, all_modules: &HashMap<Name, loader::Module>, ex
                 abs_args(args, &mut scope, &ast.vis, all_modules, &md.name);
                 scope.pop();
             }
+            ast::Def::Closure{fntype, target, ..} => {
+                for typed in vec![fntype, target] {
+                    scope.abs(typed, false);
+                    if let ast::Type::Other(ref mut name) = &mut typed.t{
+                        check_abs_available(name, &ast.vis, all_modules, &typed.loc, &md.name);
+                    }
+                }
+            }
             ast::Def::Theory{ret, args, ..} => {
                 if let Some(ret) = ret {
                     scope.abs(&mut ret.typed, false);
@@ -785,13 +872,39 @@ pub fn abs(md: &mut ast::Module, all_modules: &HashMap<Name, loader::Module>, ex
                             abs_expr(array, &scope, false, all_modules, &md.name);
                         }
                     }
+                    if let Some((lo, hi)) = &mut field.range {
+                        abs_expr(lo, &scope, false, all_modules, &md.name);
+                        abs_expr(hi, &scope, false, all_modules, &md.name);
+                    }
+                    if let Some(default) = &mut field.default {
+                        abs_expr(default, &scope, false, all_modules, &md.name);
+                    }
+
+                    if let Some(bits) = field.bits {
+                        match field.typed.t.bitfield_width() {
+                            Some(width) if field.typed.ptr.is_empty() => {
+                                if bits > width {
+                                    emit_error(msg!("bitfield_width", name = field.name, bits = bits), &[
+                                        (field.loc.clone(), msg!("bitfield_width.note", t = field.typed, width = width)),
+                                    ]);
+                                    std::process::exit(9);
+                                }
+                            }
+                            _ => {
+                                emit_error(msg!("bitfield_type", name = field.name, t = field.typed), &[
+                                    (field.loc.clone(), msg!("bitfield_type.note")),
+                                ]);
+                                std::process::exit(9);
+                            }
+                        }
+                    }
 
                     match field.typed.tail {
                         ast::Tail::None | ast::Tail::Static(_, _) => {},
                         ast::Tail::Bind(_,_) | ast::Tail::Dynamic => {
                             if i != fieldslen - 1 {
-                                emit_error(format!("nested tail must be last field"), &[
-                                    (field.loc.clone(), format!("field {} is non static tail, but not the last field", field.name)),
+                                emit_error(msg!("nested_tail_not_last"), &[
+                                    (field.loc.clone(), msg!("nested_tail_not_last.note", name = field.name)),
                                 ]);
                                 std::process::exit(9);
                             }
@@ -801,14 +914,29 @@ pub fn abs(md: &mut ast::Module, all_modules: &HashMap<Name, loader::Module>, ex
             }
             ast::Def::Enum{..} => {
             }
-            ast::Def::Macro{body, ..} => {
+            ast::Def::TypeAlias{typed} => {
+                scope.abs(typed, false);
+                if let ast::Type::Other(ref mut name) = &mut typed.t{
+                    check_abs_available(name, &ast.vis, all_modules, &typed.loc, &md.name);
+                }
+            }
+            ast::Def::Macro{args, body} => {
+                // arguments stay relative names, so macros.rs can substitute them
+                scope.push();
+                for arg in args.iter() {
+                    scope.insert(arg.clone(), Name(vec![arg.into()]), &ast.loc, false, false);
+                }
                 abs_block(body, &scope,all_modules, &md.name);
+                scope.pop();
             }
             ast::Def::Testcase{fields, ..} => {
                 for (_, expr) in fields {
                     abs_expr(expr, &scope, false, all_modules, &md.name);
                 }
             }
+            ast::Def::Test{body, ..} => {
+                abs_block(body, &scope,all_modules, &md.name);
+            }
             ast::Def::Include{needs,..} => {
                 for (t,_) in needs {
                     scope.abs(t, false);
@@ -829,21 +957,21 @@ pub fn abs(md: &mut ast::Module, all_modules: &HashMap<Name, loader::Module>, ex
             if let Some(previous) = ext.ext.get(&import.name) {
                 if let ast::Def::Include{inline,..} = previous.def {
                     if inline != import.inline {
-                        emit_error(format!("conflicting import modes"), &[
-                            (import.loc.clone(), format!("{} here", if inline {"inlined"} else {"included"})),
-                            (previous.loc.clone(), format!("previously {} here", if inline {"inlined"} else {"included"})),
+                        emit_error(msg!("conflicting_import_modes"), &[
+                            (import.loc.clone(), msg!(if import.inline {"conflicting_import_modes.inlined"} else {"conflicting_import_modes.included"})),
+                            (previous.loc.clone(), msg!(if inline {"conflicting_import_modes.prev_inlined"} else {"conflicting_import_modes.prev_included"})),
                         ]);
                         std::process::exit(9);
                     }
                 }
             }
 
-            let mut expr = import.name.0[2].clone();
+            let mut expr = import.name.0[2].to_string();
             if import.inline {
                 if !expr.starts_with("\"") || !expr.ends_with("\"") || expr.len() < 3 {
                     emit_error(
-                        "cannot inline non-relative include",
-                        &[(import.loc.clone(), format!("'{}' is not a relative include", expr))]
+                        msg!("inline_not_relative"),
+                        &[(import.loc.clone(), msg!("inline_not_relative.note", name = expr))]
                         );
                     std::process::exit(9);
                 }
@@ -895,14 +1023,14 @@ fn abs_args(
             ast::Tail::None => {
             },
             ast::Tail::Dynamic => {
-                emit_error(format!("missing tail binding "), &[
-                           (arg.loc.clone(), "+ without a name makes no sense in this context"),
+                emit_error(msg!("missing_tail_binding"), &[
+                           (arg.loc.clone(), msg!("missing_tail_binding.dynamic")),
                 ]);
                 std::process::exit(9);
             },
             ast::Tail::Static(_, _) => {
-                emit_error(format!("missing tail binding "), &[
-                           (arg.loc.clone(), "+ with static size makes no sense in this context"),
+                emit_error(msg!("missing_tail_binding"), &[
+                           (arg.loc.clone(), msg!("missing_tail_binding.static")),
                 ]);
                 std::process::exit(9);
             }
@@ -0,0 +1,256 @@
+/// type aliases
+
+use super::ast;
+use super::loader;
+use super::name::Name;
+use super::parser::{emit_error, abort};
+use std::collections::HashMap;
+
+/// all aliases, by absolute name, resolved to a type that is not an alias
+#[derive(Default)]
+pub struct Aliases(HashMap<Name, ast::Typed>);
+
+/// the type an alias names, with the pointers and tail written where it is used
+fn substitute(target: &ast::Typed, used: &ast::Typed) -> ast::Typed {
+    let mut ptr = target.ptr.clone();
+    ptr.extend(used.ptr.iter().cloned());
+    ast::Typed {
+        t:      target.t.clone(),
+        loc:    used.loc.clone(),
+        ptr,
+        tail:   match used.tail {
+            ast::Tail::None => target.tail.clone(),
+            _               => used.tail.clone(),
+        },
+    }
+}
+
+impl Aliases {
+    pub fn collect(modules: &HashMap<Name, loader::Module>) -> Aliases {
+        let mut declared = HashMap::new();
+        for (_, md) in modules {
+            let md = match md {
+                loader::Module::ZZ(md) => md,
+                loader::Module::C(_) => continue,
+            };
+            for local in &md.locals {
+                if let ast::Def::TypeAlias{typed} = &local.def {
+                    let mut name = md.name.clone();
+                    name.push(local.name.clone());
+                    declared.insert(name, (typed.clone(), local.loc.clone()));
+                }
+            }
+        }
+
+        let mut names : Vec<&Name> = declared.keys().collect();
+        names.sort_unstable();
+
+        let mut aliases = Aliases::default();
+        for name in names {
+            let (mut typed, loc) = declared[name].clone();
+            let mut through = vec![name.clone()];
+            loop {
+                let next = match &typed.t {
+                    ast::Type::Other(n) if declared.contains_key(n) => n.clone(),
+                    _ => break,
+                };
+                if through.contains(&next) {
+                    emit_error(msg!("alias_cycle", name = name), &[
+                        (loc.clone(), msg!("alias_cycle.note", through = next)),
+                    ]);
+                    abort();
+                }
+                typed = substitute(&declared[&next].0, &typed);
+                through.push(next);
+            }
+            aliases.0.insert(name.clone(), typed);
+        }
+        aliases
+    }
+
+    fn typed(&self, typed: &mut ast::Typed) {
+        if let ast::Type::Other(name) = &typed.t {
+            if let Some(target) = self.0.get(name) {
+                *typed = substitute(target, typed);
+            }
+        }
+    }
+
+    fn args(&self, args: &mut Vec<ast::NamedArg>) {
+        for arg in args {
+            self.typed(&mut arg.typed);
+        }
+    }
+
+    fn block(&self, block: &mut ast::Block) {
+        for stm in &mut block.statements {
+            self.statement(stm);
+        }
+    }
+
+    fn statement(&self, stm: &mut ast::Statement) {
+        match stm {
+            ast::Statement::Mark{lhs, ..} => self.expr(lhs),
+            ast::Statement::Label{..}
+            | ast::Statement::Break{..}
+            | ast::Statement::Continue{..}
+            | ast::Statement::CBlock{..} => {}
+            ast::Statement::Block(b) | ast::Statement::Unsafe(b) => self.block(b),
+            ast::Statement::For{e1, e2, e3, body} => {
+                for s in e1.iter_mut().chain(e3.iter_mut()) {
+                    self.statement(s);
+                }
+                if let Some(e2) = e2 {
+                    self.expr(e2);
+                }
+                self.block(body);
+            }
+            ast::Statement::While{expr, body} => {
+                self.expr(expr);
+                self.block(body);
+            }
+            ast::Statement::If{branches} => {
+                for (_, e, body) in branches {
+                    if let Some(e) = e {
+                        self.expr(e);
+                    }
+                    self.block(body);
+                }
+            }
+            ast::Statement::Assign{lhs, rhs, ..} => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            ast::Statement::Var{typed, array, assign, ..} => {
+                self.typed(typed);
+                if let Some(Some(size)) = array {
+                    self.expr(size);
+                }
+                if let Some(assign) = assign {
+                    self.expr(assign);
+                }
+            }
+            ast::Statement::Expr{expr, ..} => self.expr(expr),
+            ast::Statement::Return{expr, ..} => {
+                if let Some(expr) = expr {
+                    self.expr(expr);
+                }
+            }
+            ast::Statement::Switch{expr, cases, default, ..} => {
+                self.expr(expr);
+                for (conds, body) in cases {
+                    for cond in conds {
+                        self.expr(cond);
+                    }
+                    self.block(body);
+                }
+                if let Some(default) = default {
+                    self.block(default);
+                }
+            }
+        }
+    }
+
+    fn expr(&self, expr: &mut ast::Expression) {
+        match expr {
+            // a name is not a type position. sizeof(FileDescriptor) uses the typedef
+            ast::Expression::Name(_)
+            | ast::Expression::LiteralString{..}
+            | ast::Expression::LiteralChar{..}
+            | ast::Expression::Literal{..} => {}
+            ast::Expression::MemberAccess{lhs, ..} => self.expr(lhs),
+            ast::Expression::ArrayAccess{lhs, rhs, ..} | ast::Expression::Infix{lhs, rhs, ..} => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            ast::Expression::Call{name, args, ..} => {
+                self.expr(name);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            ast::Expression::Cast{into, expr, ..} => {
+                self.typed(into);
+                self.expr(expr);
+            }
+            ast::Expression::UnaryPost{expr, ..}
+            | ast::Expression::UnaryPre{expr, ..} => self.expr(expr),
+            ast::Expression::StructInit{typed, fields, ..} => {
+                self.typed(typed);
+                for (_, e) in fields {
+                    self.expr(e);
+                }
+            }
+            ast::Expression::ArrayInit{fields, ..} => {
+                for e in fields {
+                    self.expr(e);
+                }
+            }
+        }
+    }
+}
+
+/// replace all aliases used as types in md
+pub fn expand(md: &mut ast::Module, aliases: &Aliases) {
+    if aliases.0.is_empty() {
+        return;
+    }
+    for local in &mut md.locals {
+        match &mut local.def {
+            ast::Def::Static{typed, expr, array, ..} => {
+                aliases.typed(typed);
+                aliases.expr(expr);
+                if let Some(Some(size)) = array {
+                    aliases.expr(size);
+                }
+            }
+            ast::Def::Const{typed, expr, ..} => {
+                aliases.typed(typed);
+                aliases.expr(expr);
+            }
+            ast::Def::Function{ret, args, body, callassert, calleffect, callattests, ..} => {
+                if let Some(ret) = ret {
+                    aliases.typed(&mut ret.typed);
+                }
+                aliases.args(args);
+                for expr in callassert.iter_mut().chain(calleffect.iter_mut()).chain(callattests.iter_mut()) {
+                    aliases.expr(expr);
+                }
+                aliases.block(body);
+            }
+            ast::Def::Theory{ret, args, ..} | ast::Def::Fntype{ret, args, ..} => {
+                if let Some(ret) = ret {
+                    aliases.typed(&mut ret.typed);
+                }
+                aliases.args(args);
+            }
+            ast::Def::Closure{fntype, target, ..} => {
+                aliases.typed(fntype);
+                aliases.typed(target);
+            }
+            ast::Def::Struct{fields, ..} => {
+                for field in fields {
+                    aliases.typed(&mut field.typed);
+                    if let Some(Some(size)) = &mut field.array {
+                        aliases.expr(size);
+                    }
+                    if let Some(default) = &mut field.default {
+                        aliases.expr(default);
+                    }
+                }
+            }
+            ast::Def::TypeAlias{typed} => {
+                aliases.typed(typed);
+            }
+            ast::Def::Macro{body, ..} | ast::Def::Test{body, ..} => {
+                aliases.block(body);
+            }
+            ast::Def::Testcase{fields} => {
+                for (_, expr) in fields {
+                    aliases.expr(expr);
+                }
+            }
+            ast::Def::Enum{..} | ast::Def::Include{..} => {}
+        }
+    }
+}
@@ -0,0 +1,137 @@
+/// all modules of a build as one c file and header, zz build --amalgamate
+
+use super::archive;
+use super::ast;
+use super::emitter;
+use super::flatten;
+use super::make;
+use super::name::Name;
+use super::project::Project;
+use std::collections::HashMap;
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static ARCHIVE: Mutex<Option<archive::Format>> = Mutex::new(None);
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// write into an archive instead of plain files
+pub fn archive(format: archive::Format) {
+    *ARCHIVE.lock().unwrap() = Some(format);
+}
+
+fn outdir(stage: &make::Stage) -> String {
+    let dir = format!("target/{}/amalgamation", stage);
+    fs::create_dir_all(&dir).expect("create target dir");
+    dir
+}
+
+pub fn outname(project: &Project, header: bool) -> (bool, String) {
+    let cxx = project.std.as_ref().map(|std| std.contains("c++")).unwrap_or(false);
+    if header {
+        (cxx, format!("{}.h", project.name))
+    } else if cxx {
+        (cxx, format!("{}.cpp", project.name))
+    } else {
+        (cxx, format!("{}.c", project.name))
+    }
+}
+
+/// one module with the declarations of all modules.
+/// each module lists what it uses in an order that works for it.
+/// keeping the first of each declaration keeps that order for all of them,
+/// except that a declaration that is needed complete later moves to where it is needed complete.
+pub fn merge(project: &str, mut modules: Vec<flatten::Module>) -> flatten::Module {
+    modules.sort_by(|a, b| a.name.cmp(&b.name));
+
+    // import aliases are per module, so they are left out. in one file everything uses its full name
+    let mut merged = flatten::Module {
+        name:   Name(vec!["".into(), project.into()]),
+        ..Default::default()
+    };
+
+    let mut d : Vec<Option<(ast::Local, flatten::TypeComplete)>> = Vec::new();
+    let mut at : HashMap<String, usize> = HashMap::new();
+
+    for module in modules {
+        merged.sources.extend(module.sources);
+        merged.c_names.extend(module.c_names);
+        merged.deprecated.extend(module.deprecated);
+        merged.overrides.extend(module.overrides);
+        for (name, variants) in module.typevariants {
+            merged.typevariants.entry(name).or_default().extend(variants);
+        }
+        for (local, complete) in module.d {
+            if let ast::Def::Testcase{..} = local.def {
+                continue;
+            }
+            match at.get(&local.name) {
+                Some(&i) => {
+                    let was_complete = matches!(d[i], Some((_, flatten::TypeComplete::Complete)));
+                    if was_complete || complete != flatten::TypeComplete::Complete {
+                        continue;
+                    }
+                    d[i] = None;
+                }
+                None => (),
+            }
+            at.insert(local.name.clone(), d.len());
+            d.push(Some((local, complete)));
+        }
+    }
+
+    merged.d = d.into_iter().flatten().collect();
+    merged
+}
+
+/// write target/<stage>/amalgamation/<project>.c and .h, or the archive with both
+pub fn write(project: &Project, stage: &make::Stage, modules: Vec<flatten::Module>) {
+    let merged = merge(&project.name, modules);
+
+    let mut header = merged.clone();
+    header.d.retain(|(local, _)| match local.def {
+        ast::Def::Function{..} => local.vis == ast::Visibility::Export,
+        _ => true,
+    });
+
+    let dir = outdir(stage);
+    let format = *ARCHIVE.lock().unwrap();
+    let format = match format {
+        Some(format) => format,
+        None => {
+            for (module, h) in vec![(header, true), (merged, false)] {
+                let (cxx, name) = outname(project, h);
+                let p = format!("{}/{}", dir, name);
+                let f = fs::File::create(&p).expect(&format!("cannot create {}", p));
+                emitter::Emitter::amalgamation(project, stage.clone(), module, h, cxx, p, Box::new(f)).emit();
+            }
+            info!("amalgamation written to {}", dir);
+            return;
+        }
+    };
+
+    let path = format!("{}/{}.{}", dir, project.name, format.extension());
+    let ar = archive::Archive::create(&path, format).expect(&format!("cannot create {}", path));
+    let ar = Arc::new(Mutex::new(ar));
+    for (module, h) in vec![(header, true), (merged, false)] {
+        let (cxx, name) = outname(project, h);
+        ar.lock().unwrap().begin(&name).expect(&format!("cannot write {}", path));
+        let out = Box::new(archive::Writer(ar.clone()));
+        emitter::Emitter::amalgamation(project, stage.clone(), module, h, cxx, format!("{}:{}", path, name), out).emit();
+        ar.lock().unwrap().end().expect(&format!("cannot write {}", path));
+    }
+    let ar = match Arc::try_unwrap(ar) {
+        Ok(ar) => ar.into_inner().unwrap(),
+        Err(_) => unreachable!("the emitters are done with the archive"),
+    };
+    ar.finish().expect(&format!("cannot write {}", path));
+    info!("amalgamation written to {}", path);
+}
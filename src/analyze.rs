@@ -0,0 +1,220 @@
+/// which language features each module uses
+
+use super::ast;
+use super::loader;
+use super::name::Name;
+use super::parser::emit_error;
+use super::project::Policy;
+use super::vla;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+pub const FEATURES : &[&str] = &["vla", "labels", "varargs", "ext", "unsafe", "inline_c"];
+
+/// where each feature is used, by feature
+pub type Used = BTreeMap<&'static str, Vec<ast::Location>>;
+
+fn block(block: &ast::Block, used: &mut Used) {
+    for stm in &block.statements {
+        statement(stm, used);
+    }
+}
+
+fn statement(stm: &ast::Statement, used: &mut Used) {
+    match stm {
+        ast::Statement::Mark{lhs, ..} => expr(lhs, used),
+        ast::Statement::Label{loc, ..} => used.entry("labels").or_default().push(loc.clone()),
+        ast::Statement::CBlock{loc, ..} => used.entry("inline_c").or_default().push(loc.clone()),
+        ast::Statement::Break{..} | ast::Statement::Continue{..} => {}
+        ast::Statement::Block(b) => block(b, used),
+        // blocks only know where they end
+        ast::Statement::Unsafe(b) => {
+            used.entry("unsafe").or_default().push(b.end.clone());
+            block(b, used);
+        }
+        ast::Statement::For{e1, e2, e3, body} => {
+            for s in e1.iter().chain(e3.iter()) {
+                statement(s, used);
+            }
+            if let Some(e2) = e2 {
+                expr(e2, used);
+            }
+            block(body, used);
+        }
+        ast::Statement::While{expr: e, body} => {
+            expr(e, used);
+            block(body, used);
+        }
+        ast::Statement::If{branches} => {
+            for (_, e, body) in branches {
+                if let Some(e) = e {
+                    expr(e, used);
+                }
+                block(body, used);
+            }
+        }
+        ast::Statement::Assign{lhs, rhs, ..} => {
+            expr(lhs, used);
+            expr(rhs, used);
+        }
+        ast::Statement::Var{loc, array, assign, ..} => {
+            if let Some(Some(size)) = array {
+                expr(size, used);
+                if !vla::constant(size) {
+                    used.entry("vla").or_default().push(loc.clone());
+                }
+            }
+            if let Some(assign) = assign {
+                expr(assign, used);
+            }
+        }
+        ast::Statement::Expr{expr: e, ..} => expr(e, used),
+        ast::Statement::Return{expr: e, ..} => {
+            if let Some(e) = e {
+                expr(e, used);
+            }
+        }
+        ast::Statement::Switch{expr: e, cases, default, ..} => {
+            expr(e, used);
+            for (conds, body) in cases {
+                for cond in conds {
+                    expr(cond, used);
+                }
+                block(body, used);
+            }
+            if let Some(default) = default {
+                block(default, used);
+            }
+        }
+    }
+}
+
+fn expr(e: &ast::Expression, used: &mut Used) {
+    match e {
+        ast::Expression::Name(_)
+        | ast::Expression::LiteralString{..}
+        | ast::Expression::LiteralChar{..}
+        | ast::Expression::Literal{..} => {}
+        ast::Expression::MemberAccess{lhs, ..} => expr(lhs, used),
+        ast::Expression::ArrayAccess{lhs, rhs, ..} | ast::Expression::Infix{lhs, rhs, ..} => {
+            expr(lhs, used);
+            expr(rhs, used);
+        }
+        ast::Expression::Call{name, args, loc, ..} => {
+            if vla::alloca(name).is_some() {
+                used.entry("vla").or_default().push(loc.clone());
+            }
+            expr(name, used);
+            for arg in args {
+                expr(arg, used);
+            }
+        }
+        ast::Expression::Cast{expr: inner, ..}
+        | ast::Expression::UnaryPost{expr: inner, ..}
+        | ast::Expression::UnaryPre{expr: inner, ..} => expr(inner, used),
+        ast::Expression::StructInit{fields, ..} => {
+            for (_, e) in fields {
+                expr(e, used);
+            }
+        }
+        ast::Expression::ArrayInit{fields, ..} => {
+            for e in fields {
+                expr(e, used);
+            }
+        }
+    }
+}
+
+/// the features md uses
+pub fn features(md: &ast::Module) -> Used {
+    let mut used = Used::new();
+    for import in &md.imports {
+        if import.name.0.get(1).map(|s| s == "ext").unwrap_or(false) {
+            used.entry("ext").or_default().push(import.loc.clone());
+        }
+    }
+    for local in &md.locals {
+        match &local.def {
+            ast::Def::Function{body, vararg, ..} => {
+                if *vararg {
+                    used.entry("varargs").or_default().push(local.loc.clone());
+                }
+                block(body, &mut used);
+            }
+            ast::Def::Fntype{vararg: true, ..} => {
+                used.entry("varargs").or_default().push(local.loc.clone());
+            }
+            ast::Def::Test{body, ..} => block(body, &mut used),
+            _ => (),
+        }
+    }
+    used
+}
+
+/// print the features used by each module of the project
+pub fn print(names: &[Name], modules: &HashMap<Name, loader::Module>, project: &str) {
+    let width = names.iter().filter(|n| n.0[1] == project).map(|n| n.to_string().len()).max().unwrap_or(0);
+    for name in names {
+        if name.0[1] != project {
+            continue;
+        }
+        let md = match modules.get(name) {
+            Some(loader::Module::ZZ(md)) => md,
+            _ => continue,
+        };
+        let used = features(md);
+        let counts : Vec<String> = FEATURES.iter()
+            .filter_map(|f| used.get(f).map(|locs| format!("{} {}", f, locs.len())))
+            .collect();
+        let counts = if counts.is_empty() { "-".to_string() } else { counts.join(", ") };
+        println!("{:w$}  {}", name.to_string(), counts, w = width);
+    }
+}
+
+/// the path of a module source relative to the project root, with / between directories
+fn relative(source: &Path, root: &Path) -> String {
+    let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+    let source = source.canonicalize().unwrap_or_else(|_| source.to_path_buf());
+    let rel = source.strip_prefix(&root).unwrap_or(&source);
+    rel.components().map(|c| c.as_os_str().to_string_lossy().to_string()).collect::<Vec<_>>().join("/")
+}
+
+/// check the modules of the project against the policies of their directories. returns false if one uses a denied feature
+pub fn check(names: &[Name], modules: &HashMap<Name, loader::Module>, project: &str, root: &Path, policies: &BTreeMap<String, Policy>) -> bool {
+    let mut ok = true;
+    for (dir, policy) in policies {
+        for feature in &policy.deny {
+            if !FEATURES.contains(&feature.as_str()) {
+                error!("unknown feature '{}' in [policy.\"{}\"] of zz.toml. known features are {}", feature, dir, FEATURES.join(", "));
+                ok = false;
+            }
+        }
+    }
+    for name in names {
+        if name.0[1] != project {
+            continue;
+        }
+        let md = match modules.get(name) {
+            Some(loader::Module::ZZ(md)) => md,
+            _ => continue,
+        };
+        let path = relative(&md.source, root);
+        let mut used = None;
+        for (dir, policy) in policies {
+            let dir = dir.trim_end_matches('/');
+            if !(path == dir || path.starts_with(&format!("{}/", dir))) {
+                continue;
+            }
+            let used = used.get_or_insert_with(|| features(md));
+            for feature in &policy.deny {
+                for loc in used.get(feature.as_str()).into_iter().flatten() {
+                    emit_error(msg!("policy_denied", feature = feature, dir = dir), &[
+                        (loc.clone(), msg!("used_here")),
+                    ]);
+                    ok = false;
+                }
+            }
+        }
+    }
+    ok
+}
@@ -0,0 +1,215 @@
+/// the public api of a project, recorded for review
+
+use super::ast;
+use super::fmt::{named, typed};
+use super::loader;
+use super::name::Name;
+use super::parser::emit_error;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+pub const FILE : &str = "api.toml";
+
+/// declaration name to its description, by module
+pub type Surface = BTreeMap<String, BTreeMap<String, String>>;
+
+/// source text of an expression on one line
+fn text(expr: &ast::Expression) -> String {
+    expr.loc().text().split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn signature(head: String, args: &[ast::NamedArg], vararg: bool, ret: &Option<ast::AnonArg>) -> String {
+    let mut r : Vec<String> = args.iter().map(|a| named(&a.typed, &a.tags, &a.name)).collect();
+    if vararg {
+        r.push("...".to_string());
+    }
+    let mut s = format!("{}({})", head, r.join(", "));
+    if let Some(ret) = ret {
+        s.push_str(&format!(" -> {}", typed(&ret.typed)));
+        if let Some(array) = &ret.array {
+            s.push_str(&format!("[{}]", text(array)));
+        }
+    }
+    s
+}
+
+/// one line describing a declaration, or None if it is not part of the api
+pub fn describe(local: &ast::Local) -> Option<String> {
+    let vis = match local.vis {
+        ast::Visibility::Shared => "pub",
+        ast::Visibility::Package => return None,
+        ast::Visibility::Export => "export",
+        ast::Visibility::Object => return None,
+    };
+    let name = &local.name;
+    let s = match &local.def {
+        ast::Def::Function{ret, args, attr, vararg, callassert, ..} => {
+            let mut attr : Vec<&String> = attr.keys().collect();
+            attr.sort();
+            let mut head = format!("{} fn {}", vis, name);
+            for a in attr {
+                head.push_str(&format!(" {}", a));
+            }
+            let mut s = signature(head, args, *vararg, ret);
+            for expr in callassert {
+                s.push_str(&format!(" where {}", text(expr)));
+            }
+            s
+        }
+        ast::Def::Fntype{ret, args, vararg, ..} => {
+            signature(format!("{} fntype {}", vis, name), args, *vararg, ret)
+        }
+        ast::Def::Theory{ret, args, ..} => {
+            signature(format!("{} theory {}", vis, name), args, false, ret)
+        }
+        ast::Def::Closure{fntype, ..} => {
+            format!("{} closure {} {}", vis, typed(fntype), name)
+        }
+        ast::Def::Struct{fields, packed, tail, union, abi, ..} => {
+            let mut s = format!("{} {} {}", vis, if *union { "union" } else { "struct" }, name);
+            if let ast::Tail::Dynamic = tail {
+                s.push('+');
+            }
+            if *packed {
+                s.push_str(" packed");
+            }
+            if let Some(abi) = abi {
+                s.push_str(&format!(" @abi = {}", abi));
+            }
+            s.push_str(" {");
+            for field in fields {
+                s.push_str(&format!(" {}", named(&field.typed, &field.tags, &field.name)));
+                match &field.array {
+                    Some(Some(expr))    => s.push_str(&format!("[{}]", text(expr))),
+                    Some(None)          => s.push_str("[]"),
+                    None                => (),
+                }
+                if let Some(bits) = field.bits {
+                    s.push_str(&format!(" : {}", bits));
+                }
+                s.push(';');
+            }
+            s.push_str(" }");
+            s
+        }
+        ast::Def::Enum{names, flags} => {
+            let names : Vec<String> = names.iter().map(|(n, v)| match v {
+                Some(v) => format!("{} = {}", n, v),
+                None    => n.clone(),
+            }).collect();
+            let kind = if *flags { "flags enum" } else { "enum" };
+            format!("{} {} {} {{ {} }}", vis, kind, name, names.join(", "))
+        }
+        ast::Def::TypeAlias{typed: t} => {
+            format!("{} type {} = {}", vis, name, typed(t))
+        }
+        ast::Def::Const{typed: t, expr, ..} => {
+            format!("{} const {} {} = {}", vis, typed(t), name, text(expr))
+        }
+        ast::Def::Static{typed: t, tags: tg, storage, array, ..} => {
+            let storage = match storage {
+                ast::Storage::Static        => "static",
+                ast::Storage::ThreadLocal   => "thread_local",
+                ast::Storage::Atomic        => "atomic",
+                ast::Storage::Const         => "const",
+            };
+            let mut s = format!("{} {} {}", vis, storage, named(t, tg, name));
+            match array {
+                Some(Some(expr))    => s.push_str(&format!("[{}]", text(expr))),
+                Some(None)          => s.push_str("[]"),
+                None                => (),
+            }
+            s
+        }
+        ast::Def::Macro{args, ..} => {
+            format!("{} macro {}({})", vis, name, args.join(", "))
+        }
+        ast::Def::Testcase{..} | ast::Def::Test{..} | ast::Def::Include{..} => return None,
+    };
+    Some(s)
+}
+
+/// the api of the project's own modules
+pub fn surface(names: &[Name], modules: &HashMap<Name, loader::Module>, project: &str) -> Surface {
+    let mut surface = Surface::new();
+    for name in names {
+        if name.0[1] != project {
+            continue;
+        }
+        if let Some(loader::Module::ZZ(md)) = modules.get(name) {
+            let locals : BTreeMap<String, String> = md.locals.iter()
+                .filter_map(|local| Some((local.name.clone(), describe(local)?)))
+                .collect();
+            if !locals.is_empty() {
+                surface.insert(name.to_string(), locals);
+            }
+        }
+    }
+    surface
+}
+
+pub fn load(path: &Path) -> Option<Surface> {
+    let s = std::fs::read_to_string(path).ok()?;
+    match toml::from_str(&s) {
+        Ok(v) => Some(v),
+        Err(e) => {
+            error!("{}: {}", path.display(), e);
+            std::process::exit(9);
+        }
+    }
+}
+
+pub fn record(path: &Path, surface: &Surface) {
+    let s = toml::to_string(surface).expect("serialize api");
+    if let Err(e) = std::fs::write(path, s) {
+        error!("cannot write {}: {}", path.display(), e);
+        std::process::exit(9);
+    }
+}
+
+/// compare the api to the recorded one. returns false if it changed
+pub fn check(names: &[Name], modules: &HashMap<Name, loader::Module>, surface: &Surface, recorded: &Surface) -> bool {
+    let mut ok = true;
+    for name in names {
+        let md = match modules.get(name) {
+            Some(loader::Module::ZZ(md)) => md,
+            _ => continue,
+        };
+        let now = surface.get(&name.to_string());
+        let was = recorded.get(&name.to_string());
+        for local in &md.locals {
+            let now = match now.and_then(|m| m.get(&local.name)) {
+                Some(v) => v,
+                None => continue,
+            };
+            match was.and_then(|m| m.get(&local.name)) {
+                Some(was) if was == now => (),
+                Some(was) => {
+                    emit_error(msg!("api_changed", name = format!("{}::{}", name, local.name), file = FILE), &[
+                        (local.loc.clone(), msg!("api_changed.note", was = was)),
+                    ]);
+                    ok = false;
+                }
+                None => {
+                    emit_error(msg!("api_added", name = format!("{}::{}", name, local.name), file = FILE), &[
+                        (local.loc.clone(), msg!("api_added.note")),
+                    ]);
+                    ok = false;
+                }
+            }
+        }
+    }
+
+    let none = BTreeMap::new();
+    for (module, locals) in recorded {
+        let now = surface.get(module).unwrap_or(&none);
+        for (local, was) in locals {
+            if !now.contains_key(local) {
+                emit_error(msg!("api_removed", name = format!("{}::{}", module, local), file = FILE, was = was),
+                    &[] as &[(ast::Location, String)]);
+                ok = false;
+            }
+        }
+    }
+    ok
+}
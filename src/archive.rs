@@ -0,0 +1,225 @@
+/// tar and zip archives, each entry streamed in as it is written
+
+use std::fs;
+use std::io::{self, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Tar,
+    Zip,
+}
+
+impl Format {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Tar => "tar",
+            Format::Zip => "zip",
+        }
+    }
+}
+
+struct Entry {
+    name:   String,
+    // where its header starts
+    offset: u64,
+    size:   u64,
+    crc:    u32,
+}
+
+pub struct Archive {
+    format:     Format,
+    out:        io::BufWriter<fs::File>,
+    at:         u64,
+    entry:      Option<Entry>,
+    // the entries before, for the central directory of a zip
+    done:       Vec<Entry>,
+    crcs:       [u32; 256],
+}
+
+/// 1980-01-01, the earliest date a zip can have. entries carry no time so archives of the same build are the same
+const DOS_DATE : u16 = 0x21;
+
+/// entries are written without compression
+const STORED : u16 = 0;
+
+/// sizes and crc of a zip entry follow its contents, in a data descriptor
+const DESCRIPTOR : u16 = 0x08;
+
+impl Archive {
+    pub fn create(path: &str, format: Format) -> io::Result<Archive> {
+        let mut crcs = [0u32; 256];
+        for (i, crc) in crcs.iter_mut().enumerate() {
+            let mut c = i as u32;
+            for _ in 0..8 {
+                c = if c & 1 != 0 { 0xedb88320 ^ (c >> 1) } else { c >> 1 };
+            }
+            *crc = c;
+        }
+        Ok(Archive{
+            format,
+            out:    io::BufWriter::new(fs::File::create(path)?),
+            at:     0,
+            entry:  None,
+            done:   Vec::new(),
+            crcs,
+        })
+    }
+
+    fn put(&mut self, b: &[u8]) -> io::Result<()> {
+        self.out.write_all(b)?;
+        self.at += b.len() as u64;
+        Ok(())
+    }
+
+    /// start an entry. what is written to the archive until end is its contents
+    pub fn begin(&mut self, name: &str) -> io::Result<()> {
+        let entry = Entry{name: name.to_string(), offset: self.at, size: 0, crc: 0};
+        match self.format {
+            // the header has the size, it is written again by end
+            Format::Tar => self.put(&[0u8; 512])?,
+            Format::Zip => {
+                let mut h = Vec::new();
+                h.extend_from_slice(&0x04034b50u32.to_le_bytes());
+                h.extend_from_slice(&20u16.to_le_bytes());
+                h.extend_from_slice(&DESCRIPTOR.to_le_bytes());
+                h.extend_from_slice(&STORED.to_le_bytes());
+                h.extend_from_slice(&0u16.to_le_bytes());
+                h.extend_from_slice(&DOS_DATE.to_le_bytes());
+                // crc and sizes follow in the descriptor
+                h.extend_from_slice(&[0u8; 12]);
+                h.extend_from_slice(&(name.len() as u16).to_le_bytes());
+                h.extend_from_slice(&0u16.to_le_bytes());
+                h.extend_from_slice(name.as_bytes());
+                self.put(&h)?;
+            }
+        }
+        self.entry = Some(entry);
+        Ok(())
+    }
+
+    fn data(&mut self, b: &[u8]) -> io::Result<()> {
+        let mut entry = self.entry.take().expect("archive data outside of an entry");
+        let mut crc = !entry.crc;
+        for byte in b {
+            crc = self.crcs[((crc ^ *byte as u32) & 0xff) as usize] ^ (crc >> 8);
+        }
+        entry.crc = !crc;
+        entry.size += b.len() as u64;
+        self.entry = Some(entry);
+        self.put(b)
+    }
+
+    /// end the entry begin started
+    pub fn end(&mut self) -> io::Result<()> {
+        let entry = self.entry.take().expect("archive end outside of an entry");
+        match self.format {
+            Format::Tar => {
+                let pad = (512 - entry.size % 512) % 512;
+                self.put(&vec![0u8; pad as usize])?;
+                let header = tar_header(&entry)?;
+                self.out.seek(SeekFrom::Start(entry.offset))?;
+                self.out.write_all(&header)?;
+                self.out.seek(SeekFrom::Start(self.at))?;
+            }
+            Format::Zip => {
+                let size = zip32(entry.size)?;
+                let mut d = Vec::new();
+                d.extend_from_slice(&0x08074b50u32.to_le_bytes());
+                d.extend_from_slice(&entry.crc.to_le_bytes());
+                d.extend_from_slice(&size.to_le_bytes());
+                d.extend_from_slice(&size.to_le_bytes());
+                self.put(&d)?;
+            }
+        }
+        self.done.push(entry);
+        Ok(())
+    }
+
+    /// write what comes after the last entry
+    pub fn finish(mut self) -> io::Result<()> {
+        match self.format {
+            Format::Tar => self.put(&[0u8; 1024])?,
+            Format::Zip => {
+                let start = self.at;
+                let done = std::mem::replace(&mut self.done, Vec::new());
+                for entry in &done {
+                    let size = zip32(entry.size)?;
+                    let mut h = Vec::new();
+                    h.extend_from_slice(&0x02014b50u32.to_le_bytes());
+                    // made by unix, so the mode in the external attributes counts
+                    h.extend_from_slice(&(3u16 << 8 | 20).to_le_bytes());
+                    h.extend_from_slice(&20u16.to_le_bytes());
+                    h.extend_from_slice(&DESCRIPTOR.to_le_bytes());
+                    h.extend_from_slice(&STORED.to_le_bytes());
+                    h.extend_from_slice(&0u16.to_le_bytes());
+                    h.extend_from_slice(&DOS_DATE.to_le_bytes());
+                    h.extend_from_slice(&entry.crc.to_le_bytes());
+                    h.extend_from_slice(&size.to_le_bytes());
+                    h.extend_from_slice(&size.to_le_bytes());
+                    h.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+                    // extra, comment, disk, internal attributes
+                    h.extend_from_slice(&[0u8; 8]);
+                    h.extend_from_slice(&(0o100644u32 << 16).to_le_bytes());
+                    h.extend_from_slice(&zip32(entry.offset)?.to_le_bytes());
+                    h.extend_from_slice(entry.name.as_bytes());
+                    self.put(&h)?;
+                }
+                let mut e = Vec::new();
+                e.extend_from_slice(&0x06054b50u32.to_le_bytes());
+                e.extend_from_slice(&[0u8; 4]);
+                e.extend_from_slice(&(done.len() as u16).to_le_bytes());
+                e.extend_from_slice(&(done.len() as u16).to_le_bytes());
+                e.extend_from_slice(&zip32(self.at - start)?.to_le_bytes());
+                e.extend_from_slice(&zip32(start)?.to_le_bytes());
+                e.extend_from_slice(&0u16.to_le_bytes());
+                self.put(&e)?;
+            }
+        }
+        self.out.flush()
+    }
+}
+
+/// zip without the zip64 extension has 32 bit sizes and offsets
+fn zip32(v: u64) -> io::Result<u32> {
+    if v > u32::MAX as u64 {
+        return Err(io::Error::new(io::ErrorKind::Other, "larger than a zip can hold"));
+    }
+    Ok(v as u32)
+}
+
+/// a ustar header of a regular file
+fn tar_header(entry: &Entry) -> io::Result<[u8; 512]> {
+    if entry.name.len() > 100 {
+        return Err(io::Error::new(io::ErrorKind::Other, format!("{} is too long a name for tar", entry.name)));
+    }
+    let mut h = [0u8; 512];
+    h[..entry.name.len()].copy_from_slice(entry.name.as_bytes());
+    h[100..108].copy_from_slice(b"0000644\0");
+    h[108..116].copy_from_slice(b"0000000\0");
+    h[116..124].copy_from_slice(b"0000000\0");
+    h[124..136].copy_from_slice(format!("{:011o}\0", entry.size).as_bytes());
+    h[136..148].copy_from_slice(b"00000000000\0");
+    h[156] = b'0';
+    h[257..263].copy_from_slice(b"ustar\0");
+    h[263..265].copy_from_slice(b"00");
+    // the checksum is taken with its own field as spaces
+    h[148..156].copy_from_slice(b"        ");
+    let sum : u32 = h.iter().map(|b| *b as u32).sum();
+    h[148..156].copy_from_slice(format!("{:06o}\0 ", sum).as_bytes());
+    Ok(h)
+}
+
+/// writes into the entry an archive is in
+pub struct Writer(pub Arc<Mutex<Archive>>);
+
+impl Write for Writer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().data(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -3,34 +3,100 @@ use std::collections::HashSet;
 use std::path::PathBuf;
 use std::fmt;
 use super::name::Name;
+use serde::{Serialize, Deserialize};
+use serde::ser::SerializeStruct;
+use metrohash::MetroHash64;
+use std::hash::Hasher;
+use std::sync::Arc;
+use super::sourcemap::{self, FileId};
 
+/// stable id of a declaration, derived from its absolute name.
+/// unlike a location, it stays the same when code around the declaration changes,
+/// so tools can key on it across edits and builds.
+/// nodes are boxed, not allocated in an arena, so expressions and statements have no id
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct NodeId(pub u64);
+
+impl NodeId {
+    pub fn of(fqn: &Name) -> NodeId {
+        let mut hasher = MetroHash64::default();
+        for part in &fqn.0 {
+            hasher.write(part.as_bytes());
+            hasher.write(b"::");
+        }
+        NodeId(hasher.finish())
+    }
+}
+
+/// a range of bytes in a file of the source map
 #[derive(PartialEq, Clone, Debug)]
 pub struct Location {
-    pub file:   String,
-    pub span:   pest::Span<'static>,
+    pub file:       FileId,
+    pub start:      usize,
+    pub end:        usize,
+    // set on code copied out of a macro body, see macros.rs
+    pub expansion:  Option<Arc<Expansion>>,
+}
+
+/// a macro call that code was expanded from
+#[derive(PartialEq, Clone, Debug)]
+pub struct Expansion {
+    pub name:   Name,
+    pub call:   Location,
 }
 
 impl Location {
-    pub fn line(&self) -> usize {
-        self.span.start_pos().line_col().0
+    pub fn new(file: FileId, span: pest::Span) -> Self {
+        Self::at(file, span.start(), span.end())
     }
-    pub fn builtin() -> Self {
+    pub fn at(file: FileId, start: usize, end: usize) -> Self {
         Self {
-            file: "prelude".to_string(),
-            span: pest::Span::new(" ",0,1).unwrap(),
+            file,
+            start,
+            end,
+            expansion: None,
         }
     }
-    pub fn gen(here: &Location, gen: String) -> Self {
-        let (line, _col) = here.span.end_pos().line_col();
+    pub fn builtin() -> Self {
+        Self::at(FileId::BUILTIN, 0, 1)
+    }
 
-        let mut sx = "\n".repeat(line);
-        sx.push_str(&gen);
-        let sx = Box::leak(Box::new(sx)).as_str();
+    /// the source code at this location
+    pub fn text(&self) -> String {
+        sourcemap::text(self.file, self.start, self.end)
+    }
+    pub fn line(&self) -> usize {
+        self.line_col().0
+    }
+    pub fn line_col(&self) -> (usize, usize) {
+        sourcemap::line_col(self.file, self.start)
+    }
+    pub fn end_line_col(&self) -> (usize, usize) {
+        sourcemap::line_col(self.file, self.end)
+    }
 
-        Self {
-            file: here.file.clone(),
-            span: pest::Span::new(sx, line, line+gen.len()).unwrap(),
+    /// the macro calls this was expanded from, innermost first
+    pub fn expansions(&self) -> Vec<&Expansion> {
+        let mut r = Vec::new();
+        let mut next = self.expansion.as_deref();
+        while let Some(e) = next {
+            r.push(e);
+            next = e.call.expansion.as_deref();
         }
+        r
+    }
+}
+
+impl Serialize for Location {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (line, col) = self.line_col();
+        let mut s = serializer.serialize_struct("Location", 5)?;
+        s.serialize_field("file",  &*self.file.name())?;
+        s.serialize_field("line",  &line)?;
+        s.serialize_field("col",   &col)?;
+        s.serialize_field("start", &self.start)?;
+        s.serialize_field("end",   &self.end)?;
+        s.end()
     }
 }
 
@@ -40,25 +106,29 @@ impl std::fmt::Display for Location {
     }
 }
 
-#[derive(Default, Clone, Debug)]
+#[derive(Default, Clone, Debug, Serialize)]
 pub struct Tags(pub HashMap<String, HashMap<String, Location>>);
 
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub enum Storage {
     Static,
     ThreadLocal,
     Atomic,
+    // const with an array size, emitted as static const
+    Const,
 }
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize)]
 pub enum Visibility {
     Shared,
     Object,
     Export,
+    // pub(package), shared with the modules of the same project only
+    Package,
 }
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone, PartialEq, Serialize)]
 pub struct Import {
     pub name:   Name,
     pub alias:  Option<String>,
@@ -69,7 +139,7 @@ pub struct Import {
     pub needs:  Vec<(Typed, Location)>,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize)]
 pub enum Tail {
     None,
     Dynamic,
@@ -78,7 +148,7 @@ pub enum Tail {
 }
 
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub enum Def {
     Static {
         tags:       Tags,
@@ -86,10 +156,13 @@ pub enum Def {
         expr:       Expression,
         storage:    Storage,
         array:      Option<Option<Expression>>,
+        // initialized with comptime f(), see comptime.rs
+        comptime:   bool,
     },
     Const {
         typed:      Typed,
         expr:       Expression,
+        comptime:   bool,
     },
     Function {
         nameloc:    Location,
@@ -117,15 +190,32 @@ pub enum Def {
         attr:       HashMap<String, Location>,
         vararg:     bool,
     },
+    // replaced by a Function after abs, see closure.rs
+    Closure {
+        nameloc:    Location,
+        fntype:     Typed,
+        target:     Typed,
+        ctx:        String,
+        // declared in a function body, see closure.rs
+        inline:     bool,
+    },
     Struct {
         fields:     Vec<Field>,
         packed:     bool,
         tail:       Tail,
         union:      bool,
         impls:      HashMap<String, (Name, Location)>,
+        // layout version of a wire format struct, like @abi = 2. see layout.rs
+        abi:        Option<u64>,
     },
     Enum {
         names:      Vec<(String, Option<u64>)>,
+        // flags enum, every value a power of two. see flags.rs
+        flags:      bool,
+    },
+    // type A = B; replaced by B wherever a type is expected, see alias.rs
+    TypeAlias {
+        typed:      Typed,
     },
     Macro {
         args:       Vec<String>,
@@ -134,6 +224,11 @@ pub enum Def {
     Testcase {
         fields:     Vec<(String, Expression)>,
     },
+    // test "name" { }, lowered to a function in stages with tests, see unittest.rs
+    Test {
+        name:       String,
+        body:       Block,
+    },
     Include {
         expr:       String,
         loc:        Location,
@@ -144,7 +239,7 @@ pub enum Def {
 }
 
 
-#[derive(Clone)]
+#[derive(Clone, Serialize)]
 pub struct Local {
     pub name:       String,
     pub vis:        Visibility,
@@ -154,14 +249,14 @@ pub struct Local {
 }
 
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct Pointer {
     pub loc:    Location,
     pub tags:   Tags,
 }
 
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize)]
 pub enum Type {
     New,
     Elided,
@@ -203,6 +298,20 @@ pub enum Type {
 }
 
 impl Type {
+    /// the width of an integer type that can hold a bitfield.
+    /// int and uint are taken as 32 bit, like on every target zz supports
+    pub fn bitfield_width(&self) -> Option<u64> {
+        match self {
+            Type::Bool              => Some(1),
+            Type::U8  | Type::I8    => Some(8),
+            Type::U16 | Type::I16   => Some(16),
+            Type::U32 | Type::I32   => Some(32),
+            Type::U64 | Type::I64   => Some(64),
+            Type::Int | Type::UInt  => Some(32),
+            _ => None,
+        }
+    }
+
     pub fn signed(&self) -> bool {
         match self {
             Type::Elided
@@ -235,7 +344,7 @@ impl Type {
 }
 
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct Typed {
     pub t:      Type,
     pub loc:    Location,
@@ -290,21 +399,128 @@ impl std::fmt::Display for Typed{
     }
 }
 
-#[derive(Default, Clone)]
+#[derive(Default, Clone, Serialize)]
 pub struct Module {
     pub name:       Name,
     pub source:     PathBuf,
     pub locals:     Vec<Local>,
     pub imports:    Vec<Import>,
     pub sources:    HashSet<PathBuf>,
+    pub comments:   Vec<Location>,
+    pub edition:    super::edition::Edition,
+    // by local name
+    pub deprecated: HashMap<String, Deprecation>,
+    pub unstable:   HashMap<String, Unstable>,
+    pub derive:     HashMap<String, Derive>,
+    pub overrides:  HashMap<String, Override>,
+    // the c symbols of export functions, from @symbol and symbol_prefix. see symbols.rs
+    pub symbols:    HashMap<String, Symbol>,
+    // symbol_prefix from the zz.toml of this module's package
+    pub symbol_prefix:  Option<String>,
+    // lowered into locals when the module is loaded, see statemachine.rs
+    pub statemachines:  Vec<StateMachine>,
+    // lowered like statemachines, see wire.rs
+    pub wires:          Vec<Wire>,
+    // unstable features this module's package opted in to, by package. see stability.rs
+    pub allow_unstable: HashMap<String, Vec<String>>,
+    // where interface module; is, and the module zz.toml selects in its place. see interface.rs
+    pub interface:      Option<Location>,
+    pub backend:        Option<Name>,
+}
+
+/// @deprecated("message", replace = "name") before a declaration
+#[derive(Clone, Debug, Serialize)]
+pub struct Deprecation {
+    pub message:    String,
+    // the name in the same module that takes its place, for zz fix
+    pub replace:    Option<String>,
+    pub loc:        Location,
 }
 
-#[derive(Clone, Debug)]
+/// @override(package::module::name) before a fn. it replaces that @weak fn at link time, see weak.rs
+#[derive(Clone, Debug, Serialize)]
+pub struct Override {
+    pub target:     Name,
+    pub loc:        Location,
+}
+
+/// @symbol("name") before an export fn, the c symbol it is emitted as. see symbols.rs
+#[derive(Clone, Debug, Serialize)]
+pub struct Symbol {
+    pub name:       String,
+    pub loc:        Location,
+}
+
+/// @derive(name, ...) before a declaration, with the plugins from zz.toml that generate code for it
+#[derive(Clone, Debug, Serialize)]
+pub struct Derive {
+    pub plugins:    Vec<String>,
+    pub loc:        Location,
+}
+
+/// statemachine Name { states { .. } events { .. } initial A; A -> B on e where guard; }
+#[derive(Clone, Debug, Serialize)]
+pub struct StateMachine {
+    pub name:           String,
+    pub vis:            Visibility,
+    pub loc:            Location,
+    pub states:         Vec<(String, Location)>,
+    pub events:         Vec<(String, Location)>,
+    // the first state if not given
+    pub initial:        Option<(String, Location)>,
+    pub transitions:    Vec<Transition>,
+    // more fields of the machine struct, as written
+    pub fields:         Vec<String>,
+    pub doc:            String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Transition {
+    pub from:       (String, Location),
+    pub to:         (String, Location),
+    pub event:      (String, Location),
+    pub guard:      Option<(String, Location)>,
+    pub loc:        Location,
+}
+
+/// wire Name { u8 version; u16 size; u8 body[size <= 64] if version == 2; }
+#[derive(Clone, Debug, Serialize)]
+pub struct Wire {
+    pub name:       String,
+    pub vis:        Visibility,
+    pub loc:        Location,
+    pub fields:     Vec<WireField>,
+    pub doc:        String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct WireField {
+    pub typed:      (String, Location),
+    pub name:       (String, Location),
+    // u8 data[16] or u8 data[size <= 64], with the field holding the length
+    pub array:      Option<(Option<(String, Location)>, u64)>,
+    // only on the wire if field op value, like if flags & 0x1
+    pub cond:       Option<((String, Location), String, String)>,
+    pub loc:        Location,
+}
+
+/// @unstable("feature") before a pub or export declaration
+#[derive(Clone, Debug, Serialize)]
+pub struct Unstable {
+    pub feature:    String,
+    pub loc:        Location,
+}
+
+#[derive(Clone, Debug, Serialize)]
 pub struct AnonArg {
     pub typed:    Typed,
+    // only comptime fn can return arrays, see comptime.rs
+    pub array:    Option<Expression>,
+    // only @unit, like -> u32 @unit("ms"). see units.rs
+    pub tags:     Tags,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct NamedArg {
     pub typed:      Typed,
     pub name:       String,
@@ -313,16 +529,22 @@ pub struct NamedArg {
 }
 
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct Field {
     pub typed:      Typed,
     pub name:       String,
     pub array:      Option<Option<Expression>>,
+    // width of a bitfield, like u32 flags : 3
+    pub bits:       Option<u64>,
+    // bounds of an integer field, like int n @range(0, 1023). see symbolic.rs
+    pub range:      Option<(Expression, Expression)>,
+    // int retries = 3, what struct initializers that leave it out set it to. see defaults.rs
+    pub default:    Option<Expression>,
     pub tags:       Tags,
     pub loc:        Location,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize)]
 pub enum InfixOperator {
     Equals,
     Nequals,
@@ -423,7 +645,7 @@ impl InfixOperator {
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize)]
 pub enum PrefixOperator {
     Boolnot,
     Bitnot,
@@ -433,13 +655,13 @@ pub enum PrefixOperator {
     Deref,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize)]
 pub enum PostfixOperator {
     Increment,
     Decrement,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize)]
 pub enum AssignOperator {
     Bitor,
     Bitand,
@@ -448,7 +670,7 @@ pub enum AssignOperator {
     Eq,
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize)]
 pub enum EmitBehaviour {
     Default,
     Skip,
@@ -458,7 +680,161 @@ pub enum EmitBehaviour {
     },
 }
 
-#[derive(Clone, Debug)]
+/// the value of a literal, parsed once when the literal is created
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub enum LiteralValue {
+    Bool(bool),
+    Integer{
+        value:  i128,
+        // 16 for 0xff, 2 for 0b11, 8 for 0o17 and 017
+        radix:  u32,
+        // the type written after the digits, like u8 in 0xff_u8
+        suffix: Option<Type>,
+    },
+    // floats, and anything emitted verbatim
+    Other,
+}
+
+const INT_SUFFIXES : &[(&str, Type)] = &[
+    ("u128",    Type::U128),
+    ("u16",     Type::U16),
+    ("u32",     Type::U32),
+    ("u64",     Type::U64),
+    ("u8",      Type::U8),
+    ("usize",   Type::USize),
+    ("i128",    Type::I128),
+    ("i16",     Type::I16),
+    ("i32",     Type::I32),
+    ("i64",     Type::I64),
+    ("i8",      Type::I8),
+    ("isize",   Type::ISize),
+];
+
+/// sign, radix, digits without separators, and suffix of an integer literal
+fn split_integer(v: &str) -> Option<(bool, u32, String, Option<Type>)> {
+    let (neg, s) = match v.strip_prefix('-') {
+        Some(s) => (true, s),
+        None    => (false, v.strip_prefix('+').unwrap_or(v)),
+    };
+    let mut suffix = None;
+    let mut s = s;
+    for (name, t) in INT_SUFFIXES {
+        if let Some(rest) = s.strip_suffix(name) {
+            suffix = Some(t.clone());
+            s = rest;
+            break;
+        }
+    }
+    let (radix, s) = if let Some(s) = s.strip_prefix("0x") {
+        (16, s)
+    } else if let Some(s) = s.strip_prefix("0b") {
+        (2, s)
+    } else if let Some(s) = s.strip_prefix("0o") {
+        (8, s)
+    } else if s.len() > 1 && s.starts_with('0') {
+        // like c does
+        (8, &s[1..])
+    } else {
+        (10, s)
+    };
+    let digits : String = s.chars().filter(|c| *c != '_').collect();
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+    Some((neg, radix, digits, suffix))
+}
+
+/// the digit of a literal like 09, which has a leading 0 and so is octal in c, that octal doesn't have
+pub fn bad_octal_digit(v: &str) -> Option<char> {
+    let s = v.trim_start_matches(|c| c == '-' || c == '+');
+    let mut digits = s.strip_prefix('0')?;
+    if let Some((name, _)) = INT_SUFFIXES.iter().find(|(name, _)| digits.ends_with(name)) {
+        digits = &digits[..digits.len() - name.len()];
+    }
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit() || c == '_') {
+        return None;
+    }
+    digits.chars().find(|c| *c == '8' || *c == '9')
+}
+
+impl LiteralValue {
+    pub fn parse(v: &str) -> LiteralValue {
+        match v {
+            "true"  => return LiteralValue::Bool(true),
+            "false" => return LiteralValue::Bool(false),
+            _       => (),
+        }
+        let (neg, radix, digits, suffix) = match split_integer(v) {
+            Some(v) => v,
+            None    => return LiteralValue::Other,
+        };
+        match i128::from_str_radix(&digits, radix) {
+            Ok(i)   => LiteralValue::Integer{
+                value: if neg { -i } else { i },
+                radix,
+                suffix,
+            },
+            Err(_)  => LiteralValue::Other,
+        }
+    }
+
+    /// the value as an unsigned 64 bit integer, if it is one
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            LiteralValue::Integer{value, ..} => std::convert::TryFrom::try_from(*value).ok(),
+            _ => None,
+        }
+    }
+
+    /// the type given by the suffix, if any
+    pub fn suffix(&self) -> Option<&Type> {
+        match self {
+            LiteralValue::Integer{suffix, ..} => suffix.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// false if the value doesn't fit into the type of its suffix
+    pub fn fits(&self) -> bool {
+        let (value, t) = match self {
+            LiteralValue::Integer{value, suffix: Some(t), ..} => (*value, t),
+            _ => return true,
+        };
+        let (min, max) : (i128, i128) = match t {
+            Type::U8    => (0, u8::MAX as i128),
+            Type::U16   => (0, u16::MAX as i128),
+            Type::U32   => (0, u32::MAX as i128),
+            Type::U64 | Type::USize => (0, u64::MAX as i128),
+            Type::U128  => (0, i128::MAX),
+            Type::I8    => (i8::MIN as i128, i8::MAX as i128),
+            Type::I16   => (i16::MIN as i128, i16::MAX as i128),
+            Type::I32   => (i32::MIN as i128, i32::MAX as i128),
+            Type::I64 | Type::ISize => (i64::MIN as i128, i64::MAX as i128),
+            _           => (i128::MIN, i128::MAX),
+        };
+        value >= min && value <= max
+    }
+}
+
+/// a number literal as c reads it: without digit separators and suffix, and octal with a leading 0.
+/// the suffix is emitted as a cast, see emitter
+pub fn c_number(v: &str) -> String {
+    match split_integer(v) {
+        Some((neg, radix, digits, _)) => {
+            let sign = if neg { "-" } else { "" };
+            match radix {
+                16  => format!("{}0x{}", sign, digits),
+                2   => format!("{}0b{}", sign, digits),
+                8   => format!("{}0{}", sign, digits),
+                _   => format!("{}{}", sign, digits),
+            }
+        }
+        // floats only lose their separators
+        None => v.replace('_', ""),
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
 pub enum Expression {
     Name(Typed),
     MemberAccess {
@@ -482,7 +858,9 @@ pub enum Expression {
     },
     Literal{
         loc:    Location,
+        // emitted as is. see c_number for how number literals are written in c
         v:      String,
+        value:  LiteralValue,
     },
     Call {
         loc:            Location,
@@ -501,6 +879,8 @@ pub enum Expression {
         loc:    Location,
         into:   Typed,
         expr:   Box<Expression>,
+        // written after the type, like provenance in (usize provenance)p
+        tags:   Tags,
     },
     UnaryPost {
         loc:    Location,
@@ -520,10 +900,17 @@ pub enum Expression {
     ArrayInit {
         loc:        Location,
         fields:     Vec<Box<Expression>>,
+        // where each field goes, from {[7] = 2}. empty if they are in order from 0
+        index:      Vec<u64>,
     },
 }
 
 impl Expression {
+    pub fn literal(loc: Location, v: String) -> Expression {
+        let value = LiteralValue::parse(&v);
+        Expression::Literal{loc, v, value}
+    }
+
     pub fn loc(&self) -> &Location {
         match self {
             Expression::Name(name)              => &name.loc,
@@ -544,7 +931,7 @@ impl Expression {
 }
 
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub enum Statement {
     Mark{
         lhs:        Expression,
@@ -611,7 +998,7 @@ pub enum Statement {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct Block {
     pub end:        Location,
     pub statements: Vec<Box<Statement>>,
@@ -0,0 +1,385 @@
+/// rust bindings of the export api of a project
+
+use super::ast;
+use super::comptime;
+use super::loader;
+use super::name::Name;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+const KEYWORDS : &[&str] = &[
+    "as", "async", "await", "box", "break", "const", "continue", "dyn", "else", "enum", "extern", "false", "fn",
+    "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "static",
+    "struct", "trait", "true", "try", "type", "union", "unsafe", "use", "where", "while", "yield",
+];
+
+fn ident(s: &str) -> String {
+    match s {
+        "self" | "super" | "crate" | "Self" => format!("{}_", s),
+        _ if KEYWORDS.contains(&s) => format!("r#{}", s),
+        _ => s.to_string(),
+    }
+}
+
+/// a c type named in an ext module, like char in <stdio.h>::char
+fn ext(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "void"      => "std::os::raw::c_void",
+        "char"      => "std::os::raw::c_char",
+        "short"     => "std::os::raw::c_short",
+        "int"       => "std::os::raw::c_int",
+        "long"      => "std::os::raw::c_long",
+        "float"     => "std::os::raw::c_float",
+        "double"    => "std::os::raw::c_double",
+        "size_t"    => "usize",
+        "ssize_t"   => "isize",
+        _           => return None,
+    })
+}
+
+fn byte_string(v: &[u8]) -> String {
+    let mut s = String::from("b\"");
+    for c in v {
+        match *c {
+            b'"'    => s.push_str("\\\""),
+            b'\\'   => s.push_str("\\\\"),
+            b'\n'   => s.push_str("\\n"),
+            b'\t'   => s.push_str("\\t"),
+            b'\r'   => s.push_str("\\r"),
+            c if c.is_ascii() && !c.is_ascii_control() => s.push(c as char),
+            c       => s.push_str(&format!("\\x{:02x}", c)),
+        }
+    }
+    s.push_str("\\0\"");
+    s
+}
+
+fn doc(doc: &str, indent: &str) -> String {
+    doc.trim().lines().map(|l| format!("{}/// {}\n", indent, l.trim()).replace("/// \n", "///\n")).collect()
+}
+
+pub struct Rust<'a> {
+    modules:    &'a HashMap<Name, loader::Module>,
+    project:    &'a str,
+    safe:       bool,
+    // pub mod nesting of what is written, for paths relative to the top of the file
+    depth:      usize,
+}
+
+impl<'a> Rust<'a> {
+    pub fn new(modules: &'a HashMap<Name, loader::Module>, project: &'a str, safe: bool) -> Self {
+        Self { modules, project, safe, depth: 0 }
+    }
+
+    fn local(&self, name: &Name) -> Option<&'a ast::Local> {
+        let mut module = name.clone();
+        let local = module.pop()?;
+        match self.modules.get(&module) {
+            Some(loader::Module::ZZ(md)) => md.locals.iter().find(|l| l.name == local),
+            _ => None,
+        }
+    }
+
+    /// the rust path of an export declaration of the project, from the current module
+    fn path(&self, name: &Name) -> String {
+        let rel : Vec<String> = name.0[2..].iter().map(|s| ident(s)).collect();
+        format!("{}{}", "super::".repeat(self.depth), rel.join("::"))
+    }
+
+    /// the rust type of t, or why there is none
+    fn typ(&self, t: &ast::Typed) -> Result<String, String> {
+        let mut ptrs = t.ptr.clone();
+        let base = match &t.t {
+            ast::Type::U8   => "u8".to_string(),
+            ast::Type::U16  => "u16".to_string(),
+            ast::Type::U32  => "u32".to_string(),
+            ast::Type::U64  => "u64".to_string(),
+            ast::Type::U128 => "u128".to_string(),
+            ast::Type::I8   => "i8".to_string(),
+            ast::Type::I16  => "i16".to_string(),
+            ast::Type::I32  => "i32".to_string(),
+            ast::Type::I64  => "i64".to_string(),
+            ast::Type::I128 => "i128".to_string(),
+            ast::Type::Int  => "std::os::raw::c_int".to_string(),
+            ast::Type::UInt => "std::os::raw::c_uint".to_string(),
+            ast::Type::ISize=> "isize".to_string(),
+            ast::Type::USize=> "usize".to_string(),
+            ast::Type::Bool => "bool".to_string(),
+            ast::Type::F32  => "f32".to_string(),
+            ast::Type::F64  => "f64".to_string(),
+            ast::Type::Other(n) if n.0.len() > 1 && n.0[1] == "ext" => {
+                match (ext(n.0.last().unwrap()), ptrs.is_empty()) {
+                    (Some(v), _) => v.to_string(),
+                    (None, false) => "std::os::raw::c_void".to_string(),
+                    (None, true) => return Err(format!("{} is a c type of unknown layout", n)),
+                }
+            }
+            ast::Type::Other(n) => {
+                let local = self.local(n).filter(|l| n.0.len() > 1 && n.0[1] == self.project);
+                match local.map(|l| (&l.def, &l.vis)) {
+                    Some((ast::Def::TypeAlias{typed}, _)) => {
+                        let mut aliased = typed.clone();
+                        aliased.ptr.extend(ptrs.drain(..));
+                        return self.typ(&aliased);
+                    }
+                    Some((ast::Def::Struct{..}, ast::Visibility::Export)) if !ptrs.is_empty() || t.tail == ast::Tail::None => {
+                        self.path(n)
+                    }
+                    Some((ast::Def::Enum{..}, ast::Visibility::Export)) | Some((ast::Def::Fntype{..}, ast::Visibility::Export)) => {
+                        self.path(n)
+                    }
+                    _ if !ptrs.is_empty() => "std::os::raw::c_void".to_string(),
+                    _ => return Err(format!("{} is not an export type of {}", n, self.project)),
+                }
+            }
+            ast::Type::ILiteral | ast::Type::ULiteral | ast::Type::Elided | ast::Type::New => {
+                return Err("the type is not known before expansion".to_string());
+            }
+        };
+        Ok(ptrs.iter().fold(base, |inner, ptr| {
+            format!("*{} {}", if ptr.tags.contains_key("mut") { "mut" } else { "const" }, inner)
+        }))
+    }
+
+    fn args(&self, args: &[ast::NamedArg]) -> Result<Vec<(String, String)>, String> {
+        args.iter().map(|arg| Ok((ident(&arg.name), self.typ(&arg.typed)?))).collect()
+    }
+
+    fn ret(&self, ret: &Option<ast::AnonArg>) -> Result<Option<String>, String> {
+        match ret {
+            Some(ret) => Ok(Some(self.typ(&ret.typed)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn signature(args: &[(String, String)], vararg: bool, ret: &Option<String>) -> String {
+        let mut s : Vec<String> = args.iter().map(|(n, t)| format!("{}: {}", n, t)).collect();
+        if vararg {
+            s.push("...".to_string());
+        }
+        match ret {
+            Some(ret) => format!("({}) -> {}", s.join(", "), ret),
+            None => format!("({})", s.join(", ")),
+        }
+    }
+
+    fn structure(&self, out: &mut String, local: &ast::Local, md: &ast::Module) -> Result<(), String> {
+        let (fields, packed, union) = match &local.def {
+            ast::Def::Struct{fields, packed, union, ..} => (fields, *packed, *union),
+            _ => unreachable!(),
+        };
+        let mut body = String::new();
+        for field in fields {
+            if field.bits.is_some() {
+                return Err(format!("field {} is a bitfield", field.name));
+            }
+            let typ = self.typ(&field.typed)?;
+            let typ = match &field.array {
+                None => typ,
+                Some(None) => format!("[{}; 0]", typ),
+                Some(Some(len)) => match comptime::Interpreter::any(md, self.modules).eval(len) {
+                    Ok(comptime::Value::Int(n)) => format!("[{}; {}]", typ, n),
+                    _ => return Err(format!("the length of field {} is not a constant", field.name)),
+                },
+            };
+            writeln!(body, "    pub {}: {},", ident(&field.name), typ).unwrap();
+        }
+        out.push_str(&doc(&local.doc, ""));
+        writeln!(out, "#[repr(C{})]", if packed { ", packed" } else { "" }).unwrap();
+        writeln!(out, "#[derive(Clone, Copy)]").unwrap();
+        writeln!(out, "pub {} {} {{\n{}}}\n", if union { "union" } else { "struct" }, ident(&local.name), body).unwrap();
+        Ok(())
+    }
+
+    fn enumeration(&self, out: &mut String, local: &ast::Local) {
+        let names = match &local.def {
+            ast::Def::Enum{names, ..} => names,
+            _ => unreachable!(),
+        };
+        let name = ident(&local.name);
+        out.push_str(&doc(&local.doc, ""));
+        writeln!(out, "#[repr(transparent)]").unwrap();
+        writeln!(out, "#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]").unwrap();
+        writeln!(out, "pub struct {}(pub std::os::raw::c_int);\n", name).unwrap();
+        writeln!(out, "impl {} {{", name).unwrap();
+        let mut next = 0;
+        for (variant, value) in names {
+            let value = value.unwrap_or(next);
+            next = value + 1;
+            writeln!(out, "    pub const {}: {} = {}({});", ident(variant), name, name, value).unwrap();
+        }
+        writeln!(out, "}}\n").unwrap();
+    }
+
+    fn constant(&self, out: &mut String, local: &ast::Local, md: &ast::Module) -> Result<(), String> {
+        let (typed, expr) = match &local.def {
+            ast::Def::Const{typed, expr, ..} => (typed, expr),
+            _ => unreachable!(),
+        };
+        let value = match expr {
+            ast::Expression::LiteralString{v, ..} => {
+                out.push_str(&doc(&local.doc, ""));
+                writeln!(out, "pub const {}: &[u8] = {};\n", ident(&local.name), byte_string(v)).unwrap();
+                return Ok(());
+            }
+            _ => match comptime::Interpreter::any(md, self.modules).eval(expr) {
+                Ok(comptime::Value::Int(n)) => n.to_string(),
+                Ok(comptime::Value::Bool(b)) => b.to_string(),
+                _ => return Err("the value is not a constant integer, bool or string".to_string()),
+            },
+        };
+        out.push_str(&doc(&local.doc, ""));
+        writeln!(out, "pub const {}: {} = {};\n", ident(&local.name), self.typ(typed)?, value).unwrap();
+        Ok(())
+    }
+
+    fn fntype(&self, out: &mut String, local: &ast::Local) -> Result<(), String> {
+        let (ret, args, vararg) = match &local.def {
+            ast::Def::Fntype{ret, args, vararg, ..} => (ret, args, *vararg),
+            _ => unreachable!(),
+        };
+        let sig = Self::signature(&self.args(args)?, vararg, &self.ret(ret)?);
+        out.push_str(&doc(&local.doc, ""));
+        writeln!(out, "pub type {} = Option<unsafe extern \"C\" fn{}>;\n", ident(&local.name), sig).unwrap();
+        Ok(())
+    }
+
+    /// declares the fn in extern "C". returns whether a safe wrapper can call it
+    fn function(&self, out: &mut String, local: &ast::Local, md: &ast::Module, name: &Name) -> Result<bool, String> {
+        let (ret, args, vararg) = match &local.def {
+            ast::Def::Function{ret, args, vararg, ..} => (ret, args, *vararg),
+            _ => unreachable!(),
+        };
+        let args = self.args(args)?;
+        let ret = self.ret(ret)?;
+        let symbol = md.symbols.get(&local.name).map(|s| s.name.clone()).unwrap_or_else(|| name.0[1..].join("_"));
+        out.push_str(&doc(&local.doc, "    "));
+        writeln!(out, "    #[link_name = \"{}\"]", symbol).unwrap();
+        writeln!(out, "    pub fn {}{};", ident(&local.name), Self::signature(&args, vararg, &ret)).unwrap();
+        Ok(!vararg)
+    }
+
+    /// a safe fn calling f. pointers to one value become references, pointers to char become &CStr.
+    /// any other pointer keeps the wrapper unsafe
+    fn wrapper(&self, out: &mut String, local: &ast::Local) {
+        let (ret, args) = match &local.def {
+            ast::Def::Function{ret, args, ..} => (ret, args),
+            _ => unreachable!(),
+        };
+        // the paths change inside mod safe, the types are known to work from function()
+        let (args, ret) = match (self.args(args), self.ret(ret)) {
+            (Ok(args), Ok(ret)) => (args, ret),
+            _ => return,
+        };
+        let mut unsafe_ = false;
+        let mut params = Vec::new();
+        let mut call = Vec::new();
+        for (name, typ) in &args {
+            let (param, pass) = if typ == "*const std::os::raw::c_char" {
+                ("&std::ffi::CStr".to_string(), format!("{}.as_ptr()", name))
+            } else if typ.starts_with("*const ") && !typ[7..].starts_with('*') && !typ.ends_with("c_void") {
+                (format!("&{}", &typ[7..]), name.clone())
+            } else if typ.starts_with("*mut ") && !typ[5..].starts_with('*') && !typ.ends_with("c_void") {
+                (format!("&mut {}", &typ[5..]), name.clone())
+            } else {
+                unsafe_ |= typ.starts_with('*');
+                (typ.clone(), name.clone())
+            };
+            params.push(format!("{}: {}", name, param));
+            call.push(pass);
+        }
+        let name = ident(&local.name);
+        let ret = ret.map(|r| format!(" -> {}", r)).unwrap_or_default();
+        writeln!(out, "    pub {}fn {}({}){} {{", if unsafe_ { "unsafe " } else { "" }, name, params.join(", "), ret).unwrap();
+        writeln!(out, "        unsafe {{ super::{}({}) }}", name, call.join(", ")).unwrap();
+        writeln!(out, "    }}\n").unwrap();
+    }
+
+    /// the bindings of one module, or nothing if it exports nothing
+    fn module(&mut self, name: &Name, md: &ast::Module) -> String {
+        self.depth = name.0.len() - 2;
+        let mut items = String::new();
+        let mut externs = String::new();
+        let mut fns = Vec::new();
+        for local in md.locals.iter().filter(|l| l.vis == ast::Visibility::Export) {
+            let mut full = name.clone();
+            full.push(local.name.clone());
+            let r = match &local.def {
+                ast::Def::Struct{..}    => self.structure(&mut items, local, md),
+                ast::Def::Enum{..}      => {
+                    self.enumeration(&mut items, local);
+                    Ok(())
+                }
+                ast::Def::Const{..}     => self.constant(&mut items, local, md),
+                ast::Def::Fntype{..}    => self.fntype(&mut items, local),
+                ast::Def::Function{attr, ..} if local.name != "main"
+                    && !attr.contains_key("inline") && !attr.contains_key("extern") =>
+                {
+                    self.function(&mut externs, local, md, &full).map(|safe| if safe { fns.push(local) })
+                }
+                _ => Ok(()),
+            };
+            if let Err(why) = r {
+                writeln!(items, "// {} is left out: {}\n", local.name, why).unwrap();
+            }
+        }
+        if !externs.is_empty() {
+            writeln!(items, "extern \"C\" {{\n{}}}\n", externs).unwrap();
+        }
+        if self.safe && !fns.is_empty() {
+            self.depth += 1;
+            writeln!(items, "/// safe wrappers, a skeleton to review and adjust").unwrap();
+            writeln!(items, "pub mod safe {{").unwrap();
+            for local in &fns {
+                self.wrapper(&mut items, local);
+            }
+            writeln!(items, "}}\n").unwrap();
+        }
+        items
+    }
+
+    /// the bindings of all modules of the project, as nested modules
+    pub fn emit(mut self, names: &[Name]) -> String {
+        let mut out = String::new();
+        writeln!(out, "// rust bindings of {}, written by zz bindgen", self.project).unwrap();
+        writeln!(out, "#![allow(non_camel_case_types, non_snake_case, non_upper_case_globals, dead_code, unused_unsafe)]\n").unwrap();
+
+        let mut open : Vec<String> = Vec::new();
+        for name in names {
+            if name.0.len() < 3 || name.0[1] != self.project {
+                continue;
+            }
+            let md = match self.modules.get(name) {
+                Some(loader::Module::ZZ(md)) => md,
+                _ => continue,
+            };
+            let items = self.module(name, md);
+            if items.is_empty() {
+                continue;
+            }
+            let path = &name.0[2..];
+            let common = open.iter().zip(path).take_while(|(a, b)| a == b).count();
+            while open.len() > common {
+                open.pop();
+                writeln!(out, "{}}}\n", "    ".repeat(open.len())).unwrap();
+            }
+            for m in &path[common..] {
+                writeln!(out, "{}pub mod {} {{", "    ".repeat(open.len()), ident(m)).unwrap();
+                open.push(m.to_string());
+            }
+            let indent = "    ".repeat(open.len());
+            for line in items.lines() {
+                if line.is_empty() {
+                    out.push('\n');
+                } else {
+                    writeln!(out, "{}{}", indent, line).unwrap();
+                }
+            }
+        }
+        while !open.is_empty() {
+            open.pop();
+            writeln!(out, "{}}}\n", "    ".repeat(open.len())).unwrap();
+        }
+        out
+    }
+}
@@ -0,0 +1,127 @@
+/// compile_commands.json and build.ninja, for tools and build systems around the c compiler
+
+use super::make::Make;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static NINJA: AtomicBool = AtomicBool::new(false);
+
+pub fn enable_ninja() {
+    NINJA.store(true, Ordering::Relaxed);
+}
+
+pub fn ninja() -> bool {
+    NINJA.load(Ordering::Relaxed)
+}
+
+/// an entry of compile_commands.json
+#[derive(Serialize)]
+struct Compile {
+    directory:  String,
+    file:       String,
+    arguments:  Vec<String>,
+    output:     String,
+}
+
+struct Link {
+    cmd:    String,
+    args:   Vec<String>,
+    out:    String,
+    objs:   Vec<String>,
+}
+
+/// the c side of a build, collected from the make of each artifact
+#[derive(Default)]
+pub struct BuildFiles {
+    compiles:   Vec<Compile>,
+    links:      Vec<Link>,
+    /// zz sources the c is emitted from
+    sources:    BTreeSet<PathBuf>,
+    emitted:    BTreeSet<String>,
+}
+
+impl BuildFiles {
+    /// the objects of make, and its link step if link and the build writes build.ninja
+    pub fn add(&mut self, make: &Make, link: bool) {
+        let directory = std::env::current_dir().expect("current dir").to_string_lossy().to_string();
+        for step in &make.steps {
+            if step.emitted {
+                self.sources.extend(step.deps.iter().cloned());
+                self.emitted.insert(step.source.to_string_lossy().to_string());
+            }
+            // artifacts share objects, each is compiled once
+            if self.compiles.iter().any(|c| c.output == step.outp) {
+                continue;
+            }
+            self.compiles.push(Compile{
+                directory:  directory.clone(),
+                file:       step.source.to_string_lossy().to_string(),
+                arguments:  std::iter::once(make.cc.clone()).chain(step.args.iter().cloned()).collect(),
+                output:     step.outp.clone(),
+            });
+        }
+        if link && ninja() {
+            if let Some((cmd, args, out)) = make.link_command() {
+                self.links.push(Link{cmd, args, out, objs: make.lobjs.clone()});
+            }
+        }
+    }
+
+    /// write target/<stage>/compile_commands.json
+    pub fn compile_commands(&self, dir: &str) {
+        let path = format!("{}/compile_commands.json", dir);
+        std::fs::write(&path, serde_json::to_string_pretty(&self.compiles).unwrap())
+            .expect(&format!("cannot write {}", path));
+    }
+
+    /// write target/<stage>/build.ninja, returns its path
+    pub fn ninja(&self, dir: &str) -> String {
+        let path = format!("{}/build.ninja", dir);
+        let zz = std::env::current_exe().expect("cannot find zz executable").to_string_lossy().to_string();
+        let again : Vec<String> = std::iter::once(zz).chain(std::env::args().skip(1)).collect();
+
+        let mut s = String::new();
+        s.push_str("# written by zz build --ninja, which writes it again when a zz source changes\n\n");
+        s.push_str(&format!("rule zz\n  command = {}\n  description = zz emit\n  generator = 1\n  restat = 1\n\n",
+            command(&again)));
+        s.push_str("rule cc\n  command = $cc -MMD -MF $out.d $args\n  description = cc $in\n  depfile = $out.d\n  deps = gcc\n\n");
+        s.push_str("rule link\n  command = $cmd $args\n  description = link $out\n\n");
+
+        let outputs : Vec<String> = std::iter::once(path.clone()).chain(self.emitted.iter().cloned()).collect();
+        let inputs : Vec<String> = self.sources.iter().map(|p| p.to_string_lossy().to_string())
+            .chain(std::iter::once("zz.toml".to_string())).collect();
+        s.push_str(&format!("build {}: zz {}\n\n", paths(&outputs), paths(&inputs)));
+
+        for c in &self.compiles {
+            s.push_str(&format!("build {}: cc {}\n  cc = {}\n  args = {}\n\n",
+                paths(&[c.output.clone()]), paths(&[c.file.clone()]), command(&c.arguments[..1]), command(&c.arguments[1..])));
+        }
+        for l in &self.links {
+            s.push_str(&format!("build {}: link {}\n  cmd = {}\n  args = {}\n\n",
+                paths(&[l.out.clone()]), paths(&l.objs), command(&[l.cmd.clone()]), command(&l.args)));
+        }
+        let defaults : Vec<String> = self.links.iter().map(|l| l.out.clone()).collect();
+        if !defaults.is_empty() {
+            s.push_str(&format!("default {}\n", paths(&defaults)));
+        }
+
+        std::fs::write(&path, s).expect(&format!("cannot write {}", path));
+        path
+    }
+}
+
+/// paths in a build line of ninja
+fn paths(paths: &[String]) -> String {
+    paths.iter().map(|p| p.replace('$', "$$").replace(' ', "$ ").replace(':', "$:")).collect::<Vec<_>>().join(" ")
+}
+
+/// a command line for the shell, in a variable of ninja
+fn command(args: &[String]) -> String {
+    args.iter().map(|a| {
+        let plain = !a.is_empty() && a.chars().all(|c| c.is_ascii_alphanumeric() || "-_./=+,:@%".contains(c));
+        let a = if plain { a.clone() } else { format!("'{}'", a.replace('\'', "'\\''")) };
+        a.replace('$', "$$")
+    }).collect::<Vec<_>>().join(" ")
+}
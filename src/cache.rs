@@ -0,0 +1,262 @@
+/// content hash based build cache
+
+use super::emitter;
+use super::loader;
+use super::make;
+use super::project;
+use super::resources;
+use super::warnings;
+use super::name::Name;
+use metrohash::MetroHash128;
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize, Deserialize, Default, PartialEq)]
+pub struct Inputs {
+    key:    String,
+    files:  BTreeMap<PathBuf, String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Entry {
+    pub inputs: Inputs,
+    pub cfile:  emitter::CFile,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    inputs:     Inputs,
+    // loader picks up every .zz and .h file in these directories
+    dirs:       BTreeMap<PathBuf, Vec<String>>,
+    // every file in the directories of [resources] and [i18n], see resources.rs and i18n.rs
+    #[serde(default)]
+    resources:  BTreeMap<PathBuf, Vec<String>>,
+    modules:    Vec<Name>,
+    project:    project::Project,
+    // warnings of the last build, printed again when it is skipped
+    #[serde(default)]
+    warnings:   Vec<String>,
+}
+
+pub fn hash_bytes(b: &[u8]) -> String {
+    let mut hasher = MetroHash128::default();
+    hasher.write(b);
+    let (a, b) = hasher.finish128();
+    format!("{:x}{:x}", a, b)
+}
+
+pub fn hash_file(path: &Path) -> Option<String> {
+    std::fs::read(path).ok().map(|b| hash_bytes(&b))
+}
+
+/// anything that changes the output, except the files
+pub fn key(variant: &str, stage: &make::Stage) -> String {
+    // a rebuilt compiler may emit something else
+    let exe = std::env::current_exe().ok()
+        .and_then(|p| std::fs::metadata(p).ok())
+        .and_then(|m| m.modified().ok())
+        .map(|t| format!("{:?}", t))
+        .unwrap_or_default();
+    format!("{} {} {} {} {}", env!("CARGO_PKG_VERSION"), exe, variant, stage, warnings::key())
+}
+
+impl Inputs {
+    pub fn new(key: String) -> Self {
+        Self {
+            key,
+            files: BTreeMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, path: &Path) {
+        if self.files.contains_key(path) {
+            return;
+        }
+        if let Some(hash) = hash_file(path) {
+            self.files.insert(path.into(), hash);
+        }
+    }
+
+    pub fn unchanged(&self, key: &str) -> bool {
+        self.key == key && self.files.iter().all(|(path, hash)| hash_file(path).as_ref() == Some(hash))
+    }
+
+    /// the sources of a module and all modules it imports, transitively
+    pub fn module(&mut self, name: &Name, modules: &HashMap<Name, loader::Module>) {
+        let mut seen = HashSet::new();
+        let mut need = vec![name.clone()];
+        while let Some(name) = need.pop() {
+            if !seen.insert(name.clone()) {
+                continue;
+            }
+            match modules.get(&name) {
+                Some(loader::Module::C(path)) => self.add(path),
+                Some(loader::Module::ZZ(md)) => {
+                    for source in &md.sources {
+                        self.add(source);
+                        // features of the module's project are evaluated by the preprocessor
+                        if let Some(root) = source.parent().and_then(|p| p.parent()) {
+                            self.add(&root.join("zz.toml"));
+                        }
+                    }
+                    for import in &md.imports {
+                        need.push(import.name.clone());
+                    }
+                }
+                None => (),
+            }
+        }
+    }
+}
+
+fn manifest_name(stage: &make::Stage, variant: &str) -> String {
+    format!("target/{}/zz/{}.buildcache", stage, variant)
+}
+
+fn list_dir(dir: &Path) -> Vec<String> {
+    let mut r = Vec::new();
+    if let Ok(entries) = std::fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.ends_with(".zz") || name.ends_with(".h") {
+                r.push(name);
+            }
+        }
+    }
+    r.sort();
+    r
+}
+
+pub fn load_entry(cachename: &str, key: &str) -> Option<emitter::CFile> {
+    let entry : Entry = serde_json::from_str(&std::fs::read_to_string(cachename).ok()?).ok()?;
+    if !entry.inputs.unchanged(key) || !Path::new(&entry.cfile.filepath).exists() {
+        return None;
+    }
+    Some(entry.cfile)
+}
+
+pub fn store_entry(cachename: &str, key: &str, name: &Name, modules: &HashMap<Name, loader::Module>, cf: &emitter::CFile) {
+    let mut inputs = Inputs::new(key.to_string());
+    inputs.module(name, modules);
+    for source in &cf.sources {
+        inputs.add(source);
+    }
+    let entry = Entry {
+        inputs,
+        cfile: emitter::CFile {
+            name:       cf.name.clone(),
+            filepath:   cf.filepath.clone(),
+            sources:    cf.sources.clone(),
+            deps:       cf.deps.clone(),
+            exports:    cf.exports.clone(),
+            tests:      cf.tests.clone(),
+        },
+    };
+    let f = std::fs::File::create(cachename).expect(&format!("cannot create {}", cachename));
+    serde_json::ser::to_writer(f, &entry).expect(&format!("cannot write {}", cachename));
+}
+
+/// if nothing changed since the last build, returns the c files of all modules without parsing anything
+pub fn load_project(
+    project:    &mut project::Config,
+    variant:    &str,
+    stage:      &make::Stage,
+) -> Option<HashMap<Name, emitter::CFile>> {
+    let key = key(variant, stage);
+    let manifest : Manifest = serde_json::from_str(&std::fs::read_to_string(manifest_name(stage, variant)).ok()?).ok()?;
+    if !manifest.inputs.unchanged(&key) {
+        return None;
+    }
+    for (dir, listing) in &manifest.dirs {
+        if &list_dir(dir) != listing {
+            return None;
+        }
+    }
+    for (dir, listing) in &manifest.resources {
+        if &resources::list(dir) != listing {
+            return None;
+        }
+    }
+
+    let mut cfiles = HashMap::new();
+    for name in manifest.modules {
+        let (_, outname) = emitter::outname(&project.project, stage, &name, false);
+        let cf = load_entry(&format!("{}.buildcache", outname), &key)?;
+        cfiles.insert(name, cf);
+    }
+
+    for warning in manifest.warnings {
+        warnings::hold(warning);
+    }
+
+    // load_project merges flags of dependencies into the project
+    project.project = manifest.project;
+    Some(cfiles)
+}
+
+/// the files the last build of the project read, and the directories it listed. see watch.rs
+pub fn watched(root: &Path, stage: &make::Stage, variant: &str) -> Option<(Vec<PathBuf>, Vec<PathBuf>)> {
+    let manifest : Manifest = serde_json::from_str(&std::fs::read_to_string(root.join(manifest_name(stage, variant))).ok()?).ok()?;
+    let files = manifest.inputs.files.keys().cloned().collect();
+    let dirs = manifest.dirs.keys().chain(manifest.resources.keys()).cloned().collect();
+    Some((files, dirs))
+}
+
+pub fn store_project(
+    root:       &Path,
+    project:    &project::Config,
+    variant:    &str,
+    stage:      &make::Stage,
+    modules:    &HashMap<Name, loader::Module>,
+) {
+    let mut inputs = Inputs::new(key(variant, stage));
+    let mut dirs = BTreeMap::new();
+    inputs.add(&root.join("zz.toml"));
+    inputs.add(&root.join("zz.lock"));
+    for dir in &[root.join("src"), root.join("tests")] {
+        if let Ok(dir) = dir.canonicalize() {
+            dirs.insert(dir.clone(), list_dir(&dir));
+        }
+    }
+    for name in modules.keys() {
+        inputs.module(name, modules);
+        let path = match modules.get(name) {
+            Some(loader::Module::C(path)) => Some(path.clone()),
+            Some(loader::Module::ZZ(md)) => Some(md.source.clone()),
+            None => None,
+        };
+        if let Some(dir) = path.and_then(|p| p.canonicalize().ok()).and_then(|p| p.parent().map(PathBuf::from)) {
+            let listing = list_dir(&dir);
+            dirs.insert(dir, listing);
+        }
+    }
+
+    let mut resource_dirs = BTreeMap::new();
+    for path in project.resources.values() {
+        let dir = root.join(path);
+        if dir.is_dir() {
+            resource_dirs.insert(dir.clone(), resources::list(&dir));
+        }
+    }
+    if let Some(i18n) = &project.i18n {
+        let dir = root.join(&i18n.dir);
+        resource_dirs.insert(dir.clone(), resources::list(&dir));
+    }
+
+    let mut names : Vec<Name> = modules.keys().cloned().collect();
+    names.sort_unstable();
+
+    let manifest = Manifest {
+        inputs,
+        dirs,
+        resources: resource_dirs,
+        modules: names,
+        project: project.project.clone(),
+        warnings: warnings::held(),
+    };
+    let p = manifest_name(stage, variant);
+    let f = std::fs::File::create(&p).expect(&format!("cannot create {}", p));
+    serde_json::ser::to_writer(f, &manifest).expect(&format!("cannot write {}", p));
+}
@@ -0,0 +1,809 @@
+/// signatures of functions declared in imported c headers
+
+use super::ast;
+use super::project;
+use super::make;
+use super::name::Name;
+use super::parser::{emit_error, abort};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// a c type, reduced to what calls are checked against
+#[derive(Clone, Debug)]
+pub enum CType {
+    /// like int, unsigned long or struct foo after typedefs, and how many pointers to it
+    Plain{base: String, ptr: usize},
+    /// a pointer to a function
+    Fn(Box<Signature>),
+    Unknown,
+}
+
+#[derive(Clone, Debug)]
+pub struct Signature {
+    pub args:   usize,
+    pub params: Vec<CType>,
+    pub ret:    CType,
+    pub vararg: bool,
+    /// declared with the noreturn attribute, like exit
+    pub noreturn: bool,
+    // the declaration as the preprocessor printed it, for diagnostics
+    pub decl:   String,
+}
+
+/// what a header declares
+#[derive(Default)]
+pub struct Header {
+    pub functions:  HashMap<String, Signature>,
+    pub typedefs:   HashMap<String, CType>,
+}
+
+pub struct Headers {
+    cc:         String,
+    cflags:     Vec<String>,
+    parsed:     HashMap<String, Header>,
+}
+
+impl Headers {
+    pub fn new(config: &project::Config, stage: &make::Stage) -> Self {
+        let project = &config.project;
+        let mut cc = std::env::var("TARGET_CC")
+            .or(std::env::var("CC"))
+            .unwrap_or("clang".to_string());
+        let mut cflags = Vec::new();
+        for i in &project.cincludes {
+            cflags.push(format!("-I{}", i));
+        }
+        cflags.extend(project.cflags.iter().cloned());
+        // a cross compiler sees different headers
+        if let Some(profile) = stage.profile(config) {
+            if let Some(v) = &profile.cc {
+                cc = v.clone();
+            }
+            if let Some(sysroot) = &profile.sysroot {
+                cflags.push(format!("--sysroot={}", sysroot));
+            }
+            cflags.extend(profile.cflags.iter().cloned());
+            for define in &profile.defines {
+                cflags.push(format!("-D{}", define));
+            }
+        }
+        Self {
+            cc,
+            cflags,
+            parsed: HashMap::new(),
+        }
+    }
+
+    /// the declarations in an include like <stdio.h> or "foo.h", relative to dir
+    pub fn get(&mut self, include: &str, dir: &Path) -> &Header {
+        let key = if include.starts_with('"') {
+            format!("{}{}", dir.display(), include)
+        } else {
+            include.to_string()
+        };
+        if !self.parsed.contains_key(&key) {
+            let decls = match self.preprocess(include, dir) {
+                Some(src) => parse(&src),
+                None => Header::default(),
+            };
+            self.parsed.insert(key.clone(), decls);
+        }
+        &self.parsed[&key]
+    }
+
+    /// the signature of an ext function like ::ext::<stdlib.h>::exit, called at loc
+    pub fn function(&mut self, name: &Name, loc: &ast::Location) -> Option<&Signature> {
+        self.header(name, loc)?.functions.get(name.0[3].as_str())
+    }
+
+    /// the callback the ext function name takes: its first function pointer argument with a void* argument
+    pub fn callback(&mut self, name: &Name, loc: &ast::Location) -> Option<Signature> {
+        self.function(name, loc)?.params.iter().find_map(|p| match p {
+            CType::Fn(sig) if sig.params.iter().any(|p| p.is_void_pointer()) => Some(sig.as_ref().clone()),
+            _ => None,
+        })
+    }
+
+    /// the c type zz emits for typed, as the header of the ext name sees it
+    pub fn emitted(&mut self, typed: &ast::Typed, name: &Name, loc: &ast::Location) -> CType {
+        match self.header(name, loc) {
+            Some(header) => emitted(typed, header),
+            None => CType::Unknown,
+        }
+    }
+
+    /// the header of an ext name like ::ext::<stdlib.h>::exit, used at loc
+    fn header(&mut self, name: &Name, loc: &ast::Location) -> Option<&Header> {
+        if !name.is_absolute() || name.len() != 4 || name.0[1] != "ext" {
+            return None;
+        }
+        let file = loc.file.name();
+        let dir = Path::new(&*file).parent().unwrap_or(Path::new("."));
+        Some(self.get(&name.0[2], dir))
+    }
+
+    fn preprocess(&self, include: &str, dir: &Path) -> Option<String> {
+        let mut child = Command::new(&self.cc)
+            .args(&self.cflags)
+            .arg(format!("-iquote{}", dir.display()))
+            .args(&["-E", "-P", "-x", "c", "-"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| debug!("cannot run {} to read {}: {}", self.cc, include, e))
+            .ok()?;
+        child.stdin.take()?.write_all(format!("#include {}\n", include).as_bytes()).ok()?;
+        let output = child.wait_with_output().ok()?;
+        if !output.status.success() {
+            debug!("{} failed to preprocess {}", self.cc, include);
+            return None;
+        }
+        Some(String::from_utf8_lossy(&output.stdout).into())
+    }
+}
+
+fn tokenize(src: &str) -> Vec<String> {
+    let mut r = Vec::new();
+    let mut it = src.chars().peekable();
+    while let Some(c) = it.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        if c.is_alphanumeric() || c == '_' {
+            let mut s = c.to_string();
+            while let Some(&c) = it.peek() {
+                if !(c.is_alphanumeric() || c == '_') {
+                    break;
+                }
+                s.push(c);
+                it.next();
+            }
+            r.push(s);
+        } else if c == '"' || c == '\'' {
+            let mut escape = false;
+            for n in &mut it {
+                if !escape && n == c {
+                    break;
+                }
+                escape = !escape && n == '\\';
+            }
+            r.push(c.to_string());
+        } else if c == '.' && it.peek() == Some(&'.') {
+            it.next();
+            it.next();
+            r.push("...".to_string());
+        } else {
+            r.push(c.to_string());
+        }
+    }
+    r
+}
+
+/// drop gcc extensions that can appear anywhere in a declaration
+fn strip_extensions(tokens: &[String]) -> Vec<String> {
+    let mut r = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        match tokens[i].as_str() {
+            "__attribute__" | "__attribute" | "__asm__" | "__asm" | "asm" | "__declspec" => {
+                i += 1;
+                if tokens.get(i).map(|t|t.as_str()) == Some("(") {
+                    let mut depth = 0;
+                    while i < tokens.len() {
+                        match tokens[i].as_str() {
+                            "(" => depth += 1,
+                            ")" => depth -= 1,
+                            _ => (),
+                        }
+                        i += 1;
+                        if depth == 0 {
+                            break;
+                        }
+                    }
+                }
+            }
+            "__extension__" | "_Noreturn" | "__inline" | "__inline__" | "__restrict" | "__restrict__" | "restrict" => i += 1,
+            _ => {
+                r.push(tokens[i].clone());
+                i += 1;
+            }
+        }
+    }
+    r
+}
+
+/// words that don't change what a type is passed as
+const QUALIFIERS : &[&str] = &["const", "volatile", "static", "extern", "register", "inline", "_Thread_local", "__thread"];
+
+/// words a base type like unsigned long int is made of
+const ARITHMETIC : &[&str] = &["void", "char", "short", "int", "long", "signed", "unsigned", "float", "double", "_Bool", "__int128"];
+
+impl std::fmt::Display for CType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CType::Plain{base, ptr: 0} => write!(f, "{}", base),
+            CType::Plain{base, ptr} => write!(f, "{} {}", base, "*".repeat(*ptr)),
+            CType::Fn(sig) => {
+                let mut params : Vec<String> = sig.params.iter().map(|p| p.to_string()).collect();
+                if sig.vararg {
+                    params.push("...".to_string());
+                }
+                write!(f, "{} (*)({})", sig.ret, params.join(", "))
+            }
+            CType::Unknown => write!(f, "?"),
+        }
+    }
+}
+
+impl CType {
+    /// a pointer to this, n times
+    fn pointer(self, n: usize) -> CType {
+        match self {
+            CType::Plain{base, ptr} => CType::Plain{base, ptr: ptr + n},
+            CType::Fn(sig) if n == 0 => CType::Fn(sig),
+            _ => CType::Unknown,
+        }
+    }
+
+    /// how many pointers deep this is, a function pointer is one
+    fn depth(&self) -> Option<usize> {
+        match self {
+            CType::Plain{ptr, ..} => Some(*ptr),
+            CType::Fn(_) => Some(1),
+            CType::Unknown => None,
+        }
+    }
+
+    /// whether both are known and differ
+    pub fn differs(&self, other: &CType) -> bool {
+        match (self, other) {
+            (CType::Plain{base: a, ptr: p}, CType::Plain{base: b, ptr: q}) => a != b || p != q,
+            _ => false,
+        }
+    }
+
+    pub fn is_void_pointer(&self) -> bool {
+        matches!(self, CType::Plain{base, ptr: 1} if base == "void")
+    }
+}
+
+fn is_ident(s: &str) -> bool {
+    s.chars().next().map(|c| c.is_alphabetic() || c == '_').unwrap_or(false)
+}
+
+/// a base type made of keywords, spelled one way, like long unsigned int as unsigned long
+fn arithmetic(words: &[&str]) -> String {
+    let has = |w: &str| words.contains(&w);
+    let unsigned = if has("unsigned") { "unsigned " } else { "" };
+    let longs = words.iter().filter(|w| **w == "long").count();
+    if has("void") {
+        "void".to_string()
+    } else if has("_Bool") {
+        "_Bool".to_string()
+    } else if has("float") {
+        "float".to_string()
+    } else if has("double") {
+        if longs > 0 { "long double" } else { "double" }.to_string()
+    } else if has("char") {
+        // char, signed char and unsigned char are three different types
+        if has("unsigned") { "unsigned char" } else if has("signed") { "signed char" } else { "char" }.to_string()
+    } else if has("short") {
+        format!("{}short", unsigned)
+    } else if has("__int128") {
+        format!("{}__int128", unsigned)
+    } else {
+        format!("{}{}", unsigned, match longs { 0 => "int", 1 => "long", _ => "long long" })
+    }
+}
+
+/// the type and name of one declarator, like const char *name or void (*cb)(void *)
+fn declarator(tokens: &[&str], typedefs: &HashMap<String, CType>) -> (CType, Option<String>) {
+    if let Some(open) = tokens.iter().position(|t| *t == "(") {
+        // only a pointer to a function, like void (*name)(int)
+        let close = match tokens.iter().skip(open).position(|t| *t == ")") {
+            Some(i) => open + i,
+            None => return (CType::Unknown, None),
+        };
+        let inner : Vec<&str> = tokens[open + 1..close].iter().filter(|t| !QUALIFIERS.contains(t)).cloned().collect();
+        let name = match inner.as_slice() {
+            [_, name] if is_ident(name) => Some(name.to_string()),
+            _ => None,
+        };
+        if inner.first() != Some(&"*") || inner.len() > 2 || tokens.get(close + 1) != Some(&"(") || tokens.last() != Some(&")") {
+            return (CType::Unknown, name);
+        }
+        let (ret, _) = declarator(&tokens[..open], typedefs);
+        return match params(&tokens[close + 2..tokens.len() - 1], typedefs) {
+            Some((params, vararg)) => (CType::Fn(Box::new(Signature{
+                args:       params.len(),
+                params,
+                ret,
+                vararg,
+                noreturn:   false,
+                decl:       tokens.join(" "),
+            })), name),
+            None => (CType::Unknown, name),
+        };
+    }
+
+    // an array argument is a pointer
+    let arrays = tokens.iter().filter(|t| **t == "[").count();
+    let end = tokens.iter().position(|t| *t == "[").unwrap_or(tokens.len());
+    let stars = tokens[..end].iter().filter(|t| **t == "*").count() + arrays;
+    let mut words : Vec<&str> = tokens[..end].iter().filter(|t| **t != "*" && !QUALIFIERS.contains(t)).cloned().collect();
+    let mut name = None;
+    if let [.., before, last] = words.as_slice() {
+        if is_ident(last) && !ARITHMETIC.contains(last) && !matches!(*before, "struct" | "union" | "enum") {
+            name = Some(last.to_string());
+            words.pop();
+        }
+    }
+    if arrays > 1 {
+        return (CType::Unknown, name);
+    }
+    let t = match words.as_slice() {
+        [kind, tag] if matches!(*kind, "struct" | "union" | "enum") => CType::Plain{base: format!("{} {}", kind, tag), ptr: 0},
+        [word] if !ARITHMETIC.contains(word) => typedefs.get(*word).cloned().unwrap_or(CType::Unknown),
+        [] => CType::Unknown,
+        words if words.iter().all(|w| ARITHMETIC.contains(w)) => CType::Plain{base: arithmetic(words), ptr: 0},
+        _ => CType::Unknown,
+    };
+    (t.pointer(stars), name)
+}
+
+/// the parameters between the parentheses of a declaration, and whether it ends in ...
+/// None for (), which declares a function with unspecified arguments
+fn params(tokens: &[&str], typedefs: &HashMap<String, CType>) -> Option<(Vec<CType>, bool)> {
+    let mut depth = 0;
+    let mut args  = Vec::new();
+    let mut arg   = Vec::new();
+    for t in tokens {
+        match *t {
+            "(" | "[" => depth += 1,
+            ")" | "]" => depth -= 1,
+            "," if depth == 0 => {
+                args.push(std::mem::replace(&mut arg, Vec::new()));
+                continue;
+            }
+            _ => (),
+        }
+        arg.push(*t);
+    }
+    if !arg.is_empty() {
+        args.push(arg);
+    }
+
+    let vararg = args.last().map(|a| a == &["..."]).unwrap_or(false);
+    if vararg {
+        args.pop();
+    }
+    match args.as_slice() {
+        [] if !vararg => None,
+        [a] if a == &["void"] => Some((Vec::new(), vararg)),
+        _ => Some((args.iter().map(|a| declarator(a, typedefs).0).collect(), vararg)),
+    }
+}
+
+/// the function declared by one top level declaration, if it is a plain one
+fn declaration(tokens: &[String], typedefs: &HashMap<String, CType>) -> Option<(String, Signature)> {
+    let noreturn = tokens.iter().any(|t| t == "noreturn" || t == "__noreturn__" || t == "_Noreturn");
+    let tokens = strip_extensions(tokens);
+    let tokens : Vec<&str> = tokens.iter().map(|t| t.as_str()).collect();
+    if tokens.first() == Some(&"typedef") || tokens.iter().any(|t| *t == "=" || *t == "{") {
+        return None;
+    }
+    let open = tokens.iter().position(|t| *t == "(")?;
+    let name = tokens.get(open.checked_sub(1)?)?;
+    if !is_ident(name) || open < 2 {
+        return None;
+    }
+
+    let mut depth = 0;
+    let mut close = None;
+    for (i, t) in tokens.iter().enumerate().skip(open + 1) {
+        match *t {
+            "(" | "[" => depth += 1,
+            ")" if depth == 0 => {
+                close = Some(i);
+                break;
+            }
+            ")" | "]" => depth -= 1,
+            _ => (),
+        }
+    }
+    // functions returning function pointers and the like
+    let close = close?;
+    if close + 1 != tokens.len() {
+        return None;
+    }
+
+    let (params, vararg) = params(&tokens[open + 1..close], typedefs)?;
+    let (ret, _) = declarator(&tokens[..open - 1], typedefs);
+    Some((name.to_string(), Signature{
+        args:   params.len(),
+        params,
+        ret,
+        vararg,
+        noreturn,
+        decl:   format!("{};", tokens.join(" ")),
+    }))
+}
+
+/// the names one typedef declares, like typedef struct foo foo_t;
+fn typedef(tokens: &[String], typedefs: &mut HashMap<String, CType>) {
+    let tokens = strip_extensions(tokens);
+    let tokens : Vec<&str> = tokens.iter().skip(1).map(|t| t.as_str()).collect();
+    // of a struct with a body, only its opening brace is left
+    if let Some(open) = tokens.iter().position(|t| *t == "{") {
+        for declarator in tokens[open + 1..].split(|t| *t == ",") {
+            let name = match declarator.last() {
+                Some(name) if is_ident(name) => name.to_string(),
+                _ => continue,
+            };
+            let base = match &tokens[..open] {
+                [kind, tag] => format!("{} {}", kind, tag),
+                // an anonymous struct is only ever named by its typedef
+                [kind] => format!("{} {}", kind, name),
+                _ => continue,
+            };
+            let stars = declarator.iter().filter(|t| **t == "*").count();
+            typedefs.insert(name, CType::Plain{base, ptr: stars});
+        }
+        return;
+    }
+    // further declarators after a comma are left unknown
+    let first = tokens.split(|t| *t == ",").next().unwrap_or(&[]);
+    if let (t, Some(name)) = declarator(first, typedefs) {
+        typedefs.insert(name, t);
+    }
+}
+
+/// scan preprocessed c for top level function declarations and typedefs
+pub fn parse(src: &str) -> Header {
+    let tokens = tokenize(&src.lines().filter(|l| !l.trim_start().starts_with('#')).collect::<Vec<_>>().join("\n"));
+    let mut r = Header::default();
+    let mut depth = 0;
+    let mut decl = Vec::new();
+    for t in tokens {
+        match t.as_str() {
+            "{" => {
+                depth += 1;
+                if depth == 1 {
+                    decl.push(t);
+                }
+                continue;
+            }
+            "}" => {
+                depth -= 1;
+                // a function definition, like static inline, ends with its body
+                if depth == 0 && decl.last().map(|t|t.as_str()) == Some("{") && decl.len() > 1 && decl[decl.len() - 2] == ")" {
+                    decl.pop();
+                    if let Some((name, sig)) = declaration(&decl, &r.typedefs) {
+                        r.functions.insert(name, sig);
+                    }
+                    decl.clear();
+                }
+                continue;
+            }
+            ";" if depth == 0 => {
+                if strip_extensions(&decl).first().map(|t|t.as_str()) == Some("typedef") {
+                    typedef(&decl, &mut r.typedefs);
+                } else if let Some((name, sig)) = declaration(&decl, &r.typedefs) {
+                    r.functions.insert(name, sig);
+                }
+                decl.clear();
+                continue;
+            }
+            _ => (),
+        }
+        if depth == 0 {
+            decl.push(t);
+        }
+    }
+    r
+}
+
+struct Call<'a> {
+    name:   &'a Name,
+    args:   &'a [Box<ast::Expression>],
+    loc:    &'a ast::Location,
+    /// where the returned value goes
+    into:   Option<Into<'a>>,
+}
+
+enum Into<'a> {
+    Var(&'a ast::Typed),
+    Assign(&'a ast::Expression),
+}
+
+/// whether expr is a call that calls_in_expr collects
+fn named_call(expr: &ast::Expression) -> bool {
+    match expr {
+        ast::Expression::Call{name, ..} => matches!(name.as_ref(), ast::Expression::Name(ast::Typed{t: ast::Type::Other(_), ..})),
+        _ => false,
+    }
+}
+
+/// the calls in a function, and the types of its arguments and locals
+#[derive(Default)]
+struct Body<'a> {
+    calls:  Vec<Call<'a>>,
+    /// the type and whether it is an array. None for a name declared twice with different types
+    vars:   HashMap<&'a str, Option<(&'a ast::Typed, bool)>>,
+}
+
+impl<'a> Body<'a> {
+    fn declare(&mut self, name: &'a str, typed: &'a ast::Typed, array: bool) {
+        let var = self.vars.entry(name).or_insert(Some((typed, array)));
+        if var.map(|(t, a)| t != typed || a != array).unwrap_or(false) {
+            *var = None;
+        }
+    }
+
+    /// the c type of an argument, if it is certain
+    fn given(&self, expr: &ast::Expression, header: &Header) -> Option<CType> {
+        let t = match expr {
+            ast::Expression::LiteralString{..} => CType::Plain{base: "char".to_string(), ptr: 1},
+            ast::Expression::LiteralChar{..} => CType::Plain{base: "int".to_string(), ptr: 0},
+            // 0 is also a null pointer
+            ast::Expression::Literal{value: ast::LiteralValue::Integer{value, ..}, ..} if *value != 0 => {
+                CType::Plain{base: "int".to_string(), ptr: 0}
+            }
+            ast::Expression::Name(ast::Typed{t: ast::Type::Other(name), ..}) if !name.is_absolute() && name.len() == 1 => {
+                let (typed, array) = (*self.vars.get(name.0[0].as_str())?)?;
+                emitted(typed, header).pointer(if array { 1 } else { 0 })
+            }
+            ast::Expression::UnaryPre{op: ast::PrefixOperator::AddressOf, expr, ..} => {
+                // the address of an array is a pointer to the array, not to its first element
+                if let ast::Expression::Name(ast::Typed{t: ast::Type::Other(name), ..}) = expr.as_ref() {
+                    if let Some(Some((_, true))) = self.vars.get(name.0[0].as_str()) {
+                        return None;
+                    }
+                }
+                self.given(expr, header)?.pointer(1)
+            }
+            ast::Expression::UnaryPre{op: ast::PrefixOperator::Deref, expr, ..}
+            | ast::Expression::ArrayAccess{lhs: expr, ..} => match self.given(expr, header)? {
+                CType::Plain{base, ptr} if ptr > 0 => CType::Plain{base, ptr: ptr - 1},
+                _ => return None,
+            },
+            ast::Expression::Cast{into, ..} => emitted(into, header),
+            _ => return None,
+        };
+        t.depth().map(|_| t)
+    }
+}
+
+/// the c type zz emits for typed, as far as the typedefs of header tell
+fn emitted(typed: &ast::Typed, header: &Header) -> CType {
+    let name = match &typed.t {
+        ast::Type::U8       => "uint8_t",
+        ast::Type::U16      => "uint16_t",
+        ast::Type::U32      => "uint32_t",
+        ast::Type::U64      => "uint64_t",
+        ast::Type::I8       => "int8_t",
+        ast::Type::I16      => "int16_t",
+        ast::Type::I32      => "int32_t",
+        ast::Type::I64      => "int64_t",
+        ast::Type::Int      => "int",
+        ast::Type::UInt     => "unsigned int",
+        ast::Type::ISize    => "intptr_t",
+        ast::Type::USize    => "uintptr_t",
+        ast::Type::Bool     => "_Bool",
+        ast::Type::F32      => "float",
+        ast::Type::F64      => "double",
+        ast::Type::Other(name) if name.is_absolute() && name.len() == 4 && name.0[1] == "ext" => &name.0[3],
+        _ => return CType::Unknown,
+    };
+    let words : Vec<&str> = name.split(' ').collect();
+    declarator(&words, &header.typedefs).0.pointer(typed.ptr.len())
+}
+
+/// whether passing given where param is declared is certainly wrong.
+/// numbers convert into each other, pointers only into void pointers
+fn mismatch(param: &CType, given: &CType) -> bool {
+    let (p, g) = match (param.depth(), given.depth()) {
+        (Some(p), Some(g)) => (p, g),
+        _ => return false,
+    };
+    if p == 0 && g == 0 {
+        return false;
+    }
+    if p != g {
+        return !(p > 0 && g > 0 && (param.is_void_pointer() || given.is_void_pointer()));
+    }
+    match (param, given) {
+        (CType::Plain{base: a, ..}, CType::Plain{base: b, ..}) => a != b && a != "void" && b != "void",
+        _ => false,
+    }
+}
+
+fn calls_in_block<'a>(block: &'a ast::Block, r: &mut Body<'a>) {
+    for stm in &block.statements {
+        calls_in_statement(stm, r);
+    }
+}
+
+fn calls_in_statement<'a>(stm: &'a ast::Statement, r: &mut Body<'a>) {
+    match stm {
+        ast::Statement::Mark{lhs, ..} => calls_in_expr(lhs, r),
+        ast::Statement::Label{..}
+        | ast::Statement::Break{..}
+        | ast::Statement::Continue{..}
+        | ast::Statement::CBlock{..} => {}
+        ast::Statement::Block(b) | ast::Statement::Unsafe(b) => calls_in_block(b, r),
+        ast::Statement::For{e1, e2, e3, body} => {
+            for s in e1.iter().chain(e3.iter()) {
+                calls_in_statement(s, r);
+            }
+            if let Some(e2) = e2 {
+                calls_in_expr(e2, r);
+            }
+            calls_in_block(body, r);
+        }
+        ast::Statement::While{expr, body} => {
+            calls_in_expr(expr, r);
+            calls_in_block(body, r);
+        }
+        ast::Statement::If{branches} => {
+            for (_, expr, body) in branches {
+                if let Some(expr) = expr {
+                    calls_in_expr(expr, r);
+                }
+                calls_in_block(body, r);
+            }
+        }
+        ast::Statement::Assign{lhs, rhs, op, ..} => {
+            calls_in_expr(lhs, r);
+            let at = r.calls.len();
+            calls_in_expr(rhs, r);
+            if *op == ast::AssignOperator::Eq && named_call(rhs) {
+                r.calls[at].into = Some(Into::Assign(lhs));
+            }
+        }
+        ast::Statement::Var{typed, name, array, assign, ..} => {
+            r.declare(name, typed, array.is_some());
+            if let Some(Some(array)) = array {
+                calls_in_expr(array, r);
+            }
+            if let Some(assign) = assign {
+                let at = r.calls.len();
+                calls_in_expr(assign, r);
+                if array.is_none() && named_call(assign) {
+                    r.calls[at].into = Some(Into::Var(typed));
+                }
+            }
+        }
+        ast::Statement::Expr{expr, ..} => calls_in_expr(expr, r),
+        ast::Statement::Return{expr, ..} => {
+            if let Some(expr) = expr {
+                calls_in_expr(expr, r);
+            }
+        }
+        ast::Statement::Switch{expr, cases, default, ..} => {
+            calls_in_expr(expr, r);
+            for (conds, block) in cases {
+                for cond in conds {
+                    calls_in_expr(cond, r);
+                }
+                calls_in_block(block, r);
+            }
+            if let Some(default) = default {
+                calls_in_block(default, r);
+            }
+        }
+    }
+}
+
+fn calls_in_expr<'a>(expr: &'a ast::Expression, r: &mut Body<'a>) {
+    match expr {
+        ast::Expression::Name(_)
+        | ast::Expression::LiteralString{..}
+        | ast::Expression::LiteralChar{..}
+        | ast::Expression::Literal{..} => {}
+        ast::Expression::MemberAccess{lhs, ..} => calls_in_expr(lhs, r),
+        ast::Expression::ArrayAccess{lhs, rhs, ..} | ast::Expression::Infix{lhs, rhs, ..} => {
+            calls_in_expr(lhs, r);
+            calls_in_expr(rhs, r);
+        }
+        ast::Expression::Call{name, args, loc, ..} => {
+            if let ast::Expression::Name(ast::Typed{t: ast::Type::Other(name), ..}) = name.as_ref() {
+                r.calls.push(Call{name, args, loc, into: None});
+            } else {
+                calls_in_expr(name, r);
+            }
+            for arg in args {
+                calls_in_expr(arg, r);
+            }
+        }
+        ast::Expression::Cast{expr, ..}
+        | ast::Expression::UnaryPost{expr, ..}
+        | ast::Expression::UnaryPre{expr, ..} => calls_in_expr(expr, r),
+        ast::Expression::StructInit{fields, ..} => {
+            for (_, expr) in fields {
+                calls_in_expr(expr, r);
+            }
+        }
+        ast::Expression::ArrayInit{fields, ..} => {
+            for expr in fields {
+                calls_in_expr(expr, r);
+            }
+        }
+    }
+}
+
+/// check the argument count, and the pointer types of arguments and returned values, of all calls into ext modules against the declarations in their headers
+pub fn check(md: &ast::Module, headers: &mut Headers) {
+    let mut bodies = Vec::new();
+    for local in &md.locals {
+        let mut r = Body::default();
+        match &local.def {
+            ast::Def::Function{args, body, ..} => {
+                for arg in args {
+                    r.declare(&arg.name, &arg.typed, false);
+                }
+                calls_in_block(body, &mut r);
+            }
+            ast::Def::Test{body, ..} => calls_in_block(body, &mut r),
+            _ => continue,
+        }
+        bodies.push(r);
+    }
+
+    let mut failed = false;
+    for body in &bodies {
+        for call in &body.calls {
+            let header = match headers.header(call.name, call.loc) {
+                Some(header) => header,
+                None => continue,
+            };
+            let sig = match header.functions.get(call.name.0[3].as_str()) {
+                Some(sig) => sig,
+                None => continue,
+            };
+            let given = call.args.len();
+            let ok = if sig.vararg { given >= sig.args } else { given == sig.args };
+            if !ok {
+                emit_error(msg!("ext_argument_count"), &[
+                    (call.loc.clone(), msg!(if sig.vararg { "ext_argument_count.vararg" } else { "ext_argument_count.note" },
+                        name = call.name.0[3], expected = sig.args, given = given)),
+                    (call.loc.clone(), msg!("ext_argument_count.declared", header = call.name.0[2], decl = sig.decl)),
+                ]);
+                failed = true;
+                continue;
+            }
+            for (i, (param, arg)) in sig.params.iter().zip(call.args).enumerate() {
+                let t = match body.given(arg, header) {
+                    Some(t) => t,
+                    None => continue,
+                };
+                if mismatch(param, &t) {
+                    emit_error(msg!("ext_argument_type"), &[
+                        (arg.loc().clone(), msg!("ext_argument_type.note",
+                            index = i + 1, name = call.name.0[3], expected = param, given = t)),
+                        (call.loc.clone(), msg!("ext_argument_count.declared", header = call.name.0[2], decl = sig.decl)),
+                    ]);
+                    failed = true;
+                }
+            }
+            let into = match &call.into {
+                Some(Into::Var(typed)) => emitted(typed, header),
+                Some(Into::Assign(lhs)) => body.given(lhs, header).unwrap_or(CType::Unknown),
+                None => continue,
+            };
+            if mismatch(&into, &sig.ret) {
+                emit_error(msg!("ext_return_type"), &[
+                    (call.loc.clone(), msg!("ext_return_type.note", name = call.name.0[3], ret = sig.ret, into = into)),
+                    (call.loc.clone(), msg!("ext_argument_count.declared", header = call.name.0[2], decl = sig.decl)),
+                ]);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        abort();
+    }
+}
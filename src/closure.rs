@@ -0,0 +1,960 @@
+/// expand closure declarations into a trampoline function
+
+use super::ast;
+use super::cheader;
+use super::loader;
+use super::macros::{Visit, walk_block, walk_statement, walk_expr};
+use super::name::Name;
+use super::parser::{emit_error, abort};
+use std::collections::{HashMap, HashSet};
+
+/// tag of the declaration heading a closure in a function body, until lower
+pub const INLINE : &str = ":closure";
+/// the same for a function declared in a function body
+pub const NESTED : &str = ":fn";
+
+/// a closure declared in a function body
+pub struct Inline {
+    pub name:       String,
+    pub loc:        ast::Location,
+    pub fntype:     ast::Typed,
+    // the captured fields and their values
+    pub captures:   Vec<(ast::Field, ast::Expression)>,
+    pub args:       Vec<ast::NamedArg>,
+    pub ret:        Option<ast::AnonArg>,
+    pub body:       ast::Block,
+    // a fn without captures or fntype
+    pub nested:     bool,
+}
+
+/// the block of declarations the parser leaves for a closure in a function body:
+/// the closure tagged INLINE, captures with their value, arguments, the return type without a name, and the body
+pub fn marker(inline: Inline) -> ast::Block {
+    let mut head = ast::Tags::new();
+    head.insert(if inline.nested { NESTED } else { INLINE }.to_string(), String::new(), inline.loc.clone());
+    let mut statements = vec![Box::new(ast::Statement::Var{
+        loc:    inline.loc,
+        typed:  inline.fntype,
+        tags:   head,
+        name:   inline.name,
+        array:  None,
+        assign: None,
+    })];
+    for (field, value) in inline.captures {
+        statements.push(Box::new(ast::Statement::Var{
+            loc:    field.loc,
+            typed:  field.typed,
+            tags:   field.tags,
+            name:   field.name,
+            array:  None,
+            assign: Some(value),
+        }));
+    }
+    for arg in inline.args {
+        statements.push(Box::new(ast::Statement::Var{
+            loc:    arg.loc,
+            typed:  arg.typed,
+            tags:   arg.tags,
+            name:   arg.name,
+            array:  None,
+            assign: None,
+        }));
+    }
+    if let Some(ret) = inline.ret {
+        statements.push(Box::new(ast::Statement::Var{
+            loc:    ret.typed.loc.clone(),
+            typed:  ret.typed,
+            tags:   ret.tags,
+            name:   String::new(),
+            array:  ret.array.map(Some),
+            assign: None,
+        }));
+    }
+    let end = inline.body.end.clone();
+    statements.push(Box::new(ast::Statement::Block(Box::new(inline.body))));
+    ast::Block{end, statements, expanded: false}
+}
+
+/// name and location of the closure or function declared by block, if it is one, and whether it's a function
+fn head(block: &ast::Block) -> Option<(&str, &ast::Location, bool)> {
+    match block.statements.first().map(|s| s.as_ref()) {
+        Some(ast::Statement::Var{tags, name, loc, ..}) if tags.contains(INLINE) || tags.contains(NESTED) => {
+            Some((name.as_str(), loc, tags.contains(NESTED)))
+        }
+        _ => None,
+    }
+}
+
+/// the closure declared by block, if it is one
+pub fn inline(block: &ast::Block) -> Option<Inline> {
+    let (name, loc, nested) = head(block)?;
+    let fntype = match block.statements[0].as_ref() {
+        ast::Statement::Var{typed, ..} => typed.clone(),
+        _ => unreachable!(),
+    };
+    let mut captures = Vec::new();
+    let mut args = Vec::new();
+    let mut ret = None;
+    let mut body = None;
+    for stm in &block.statements[1..] {
+        match stm.as_ref() {
+            ast::Statement::Var{typed, tags, name, array, ..} if name.is_empty() => {
+                ret = Some(ast::AnonArg{typed: typed.clone(), array: array.clone().flatten(), tags: tags.clone()});
+            }
+            ast::Statement::Var{loc, typed, tags, name, assign: Some(value), ..} => {
+                captures.push((ast::Field{
+                    typed:  typed.clone(),
+                    name:   name.clone(),
+                    array:  None,
+                    bits:   None,
+                    range:  None,
+                    default: None,
+                    tags:   tags.clone(),
+                    loc:    loc.clone(),
+                }, value.clone()));
+            }
+            ast::Statement::Var{loc, typed, tags, name, ..} => {
+                args.push(ast::NamedArg{
+                    typed:  typed.clone(),
+                    name:   name.clone(),
+                    tags:   tags.clone(),
+                    loc:    loc.clone(),
+                });
+            }
+            ast::Statement::Block(b) => body = Some(b.as_ref().clone()),
+            _ => return None,
+        }
+    }
+    Some(Inline{
+        name:   name.to_string(),
+        loc:    loc.clone(),
+        fntype,
+        captures,
+        args,
+        ret,
+        body:   body?,
+        nested,
+    })
+}
+
+pub fn find_def<'a>(
+    name:           &Name,
+    md:             &'a ast::Module,
+    all_modules:    &'a HashMap<Name, loader::Module>,
+) -> Option<&'a ast::Local> {
+    let mut module_name = name.clone();
+    let local_name = module_name.pop()?;
+    let locals = if module_name == md.name {
+        &md.locals
+    } else {
+        match all_modules.get(&module_name) {
+            Some(loader::Module::ZZ(m)) => &m.locals,
+            _ => return None,
+        }
+    };
+    locals.iter().find(|l| l.name == local_name)
+}
+
+fn is_void_ptr(typed: &ast::Typed) -> bool {
+    if typed.ptr.len() != 1 {
+        return false;
+    }
+    match &typed.t {
+        ast::Type::Other(n) => n.0.len() == 4 && n.0[1] == "ext" && n.0[3] == "void",
+        _ => false,
+    }
+}
+
+/// compare two signatures by base type and pointer depth.
+/// returns a description of the first mismatch
+pub fn signature_mismatch(
+    a_args: &[ast::NamedArg],
+    a_ret:  &Option<ast::AnonArg>,
+    b_args: &[ast::NamedArg],
+    b_ret:  &Option<ast::AnonArg>,
+    skip:   Option<usize>,
+) -> Option<(Option<usize>, String)> {
+    if a_args.len() != b_args.len() {
+        return Some((None, format!("expected {} arguments, but it takes {}", a_args.len(), b_args.len())));
+    }
+    for (i, (a, b)) in a_args.iter().zip(b_args.iter()).enumerate() {
+        if Some(i) == skip {
+            continue;
+        }
+        if a.typed.t != b.typed.t || a.typed.ptr.len() != b.typed.ptr.len() {
+            return Some((Some(i), format!("argument {} '{}' expected to be {} but is {}", i + 1, b.name, a.typed, b.typed)));
+        }
+    }
+    match (a_ret, b_ret) {
+        (None, None) => None,
+        (Some(a), Some(b)) if a.typed.t == b.typed.t && a.typed.ptr.len() == b.typed.ptr.len() => None,
+        (a, b) => {
+            let show = |r: &Option<ast::AnonArg>| r.as_ref().map(|r|format!("{}", r.typed)).unwrap_or("void".to_string());
+            Some((None, format!("expected return type {} but it returns {}", show(a), show(b))))
+        }
+    }
+}
+
+/// the arguments and return type of the callback the c function name takes, in the types of target.
+/// the context argument is the void* of the callback, everything else has to be the same c type in target
+fn from_c(
+    fntype:         &ast::Typed,
+    name:           &Name,
+    target:         &ast::Typed,
+    target_args:    &[ast::NamedArg],
+    target_ret:     &Option<ast::AnonArg>,
+    headers:        &mut cheader::Headers,
+) -> (Vec<ast::NamedArg>, Option<ast::AnonArg>) {
+    let cb = match headers.callback(name, &fntype.loc) {
+        Some(cb) if !cb.vararg => cb,
+        _ => {
+            emit_error("closure_for requires a c function taking a callback with a void* context argument", &[
+                (fntype.loc.clone(), format!("{} takes no such callback", name.human_name())),
+            ]);
+            std::process::exit(9);
+        }
+    };
+    let ctxarg = cb.params.iter().position(|p| p.is_void_pointer()).unwrap();
+    let target_name = match &target.t {
+        ast::Type::Other(n) => n.human_name(),
+        _ => unreachable!(),
+    };
+    let mismatch = |at: &ast::Location, msg: String| -> ! {
+        emit_error(format!("{} cannot be called through {}", target_name, name.human_name()), &[
+            (target.loc.clone(), "incompatible signature".to_string()),
+            (at.clone(), msg),
+            (fntype.loc.clone(), format!("callback declared as {}", cheader::CType::Fn(Box::new(cb.clone())))),
+        ]);
+        std::process::exit(9);
+    };
+
+    if target_args.len() != cb.params.len() {
+        mismatch(&target.loc, format!("expected {} arguments, but it takes {}", cb.params.len(), target_args.len()));
+    }
+    for (i, (param, arg)) in cb.params.iter().zip(target_args).enumerate() {
+        if i == ctxarg {
+            continue;
+        }
+        let t = headers.emitted(&arg.typed, name, &fntype.loc);
+        if t.differs(param) {
+            mismatch(&arg.loc, format!("argument {} '{}' expected to be {} but is {}", i + 1, arg.name, param, t));
+        }
+    }
+    let returns = match target_ret {
+        Some(r) => headers.emitted(&r.typed, name, &fntype.loc),
+        None => cheader::CType::Plain{base: "void".to_string(), ptr: 0},
+    };
+    if returns.differs(&cb.ret) {
+        mismatch(&target.loc, format!("expected return type {} but it returns {}", cb.ret, returns));
+    }
+
+    let mut args = target_args.to_vec();
+    let mut mutable = ast::Tags::new();
+    mutable.insert("mut".to_string(), String::new(), fntype.loc.clone());
+    let mut void = name.clone();
+    void.pop();
+    void.push("void".to_string());
+    args[ctxarg].typed = ast::Typed{
+        t:      ast::Type::Other(void),
+        loc:    fntype.loc.clone(),
+        ptr:    vec![ast::Pointer{loc: fntype.loc.clone(), tags: mutable}],
+        tail:   ast::Tail::None,
+    };
+    (args, target_ret.clone())
+}
+
+pub fn expand(md: &mut ast::Module, all_modules: &HashMap<Name, loader::Module>, headers: &mut cheader::Headers) {
+    for i in 0..md.locals.len() {
+        let (nameloc, fntype, target, ctx, inline) = match &md.locals[i].def {
+            ast::Def::Closure{nameloc, fntype, target, ctx, inline} => {
+                (nameloc.clone(), fntype.clone(), target.clone(), ctx.clone(), *inline)
+            }
+            _ => continue,
+        };
+
+        let fntype_name = match &fntype.t {
+            ast::Type::Other(n) => n.clone(),
+            _ => unreachable!(),
+        };
+        let target_name = match &target.t {
+            ast::Type::Other(n) => n.clone(),
+            _ => unreachable!(),
+        };
+
+        let (target_args, target_ret, target_loc) = match find_def(&target_name, md, all_modules) {
+            Some(ast::Local{def: ast::Def::Function{args, ret, ..}, loc, ..}) => (args.clone(), ret.clone(), loc.clone()),
+            _ => {
+                emit_error("closure_for requires a function to call", &[
+                    (target.loc.clone(), format!("{} is not a function", target_name.human_name())),
+                ]);
+                std::process::exit(9);
+            }
+        };
+
+        let (args, ret, fntype_loc) = match find_def(&fntype_name, md, all_modules) {
+            Some(ast::Local{def: ast::Def::Fntype{args, ret, vararg: false, ..}, loc, ..}) => (args.clone(), ret.clone(), loc.clone()),
+            Some(ast::Local{def: ast::Def::Fntype{..}, loc, ..}) => {
+                emit_error("closure_for cannot wrap a variadic fntype", &[
+                    (fntype.loc.clone(), format!("{} is variadic", fntype_name.human_name())),
+                    (loc.clone(), "declared here".to_string()),
+                ]);
+                std::process::exit(9);
+            }
+            None if headers.function(&fntype_name, &fntype.loc).is_some() => {
+                if inline {
+                    emit_error("a closure in a function body requires an fntype describing the callback", &[
+                        (fntype.loc.clone(), format!("{} is a c function, the closure has no type to store it as", fntype_name.human_name())),
+                    ]);
+                    std::process::exit(9);
+                }
+                let (args, ret) = from_c(&fntype, &fntype_name, &target, &target_args, &target_ret, headers);
+                (args, ret, fntype.loc.clone())
+            }
+            _ => {
+                emit_error("closure_for requires an fntype describing the callback, or a c function taking it", &[
+                    (fntype.loc.clone(), format!("{} is not an fntype", fntype_name.human_name())),
+                ]);
+                std::process::exit(9);
+            }
+        };
+
+        let ctxarg = match args.iter().position(|a| is_void_ptr(&a.typed)) {
+            Some(v) => v,
+            None => {
+                emit_error("closure_for requires a callback with a void* context argument", &[
+                    (fntype.loc.clone(), format!("{} has no void* argument", fntype_name.human_name())),
+                    (fntype_loc, "declared here".to_string()),
+                ]);
+                std::process::exit(9);
+            }
+        };
+
+        // the body of a closure declared in a function takes self first, wherever the callback has its void*
+        let order : Vec<usize> = if inline {
+            std::iter::once(ctxarg).chain((0..args.len()).filter(|i| *i != ctxarg)).collect()
+        } else {
+            (0..args.len()).collect()
+        };
+        let expected : Vec<ast::NamedArg> = order.iter().map(|i| args[*i].clone()).collect();
+        let target_ctx = if inline { 0 } else { ctxarg };
+
+        let mut ctx_name = md.name.clone();
+        ctx_name.push(ctx.clone());
+
+        if let Some((at, msg)) = signature_mismatch(&expected, &ret, &target_args, &target_ret, Some(target_ctx)) {
+            let loc = match at {
+                Some(at) => target_args[at].loc.clone(),
+                None => target_loc,
+            };
+            emit_error(format!("{} cannot be called through {}", target_name.human_name(), fntype_name.human_name()), &[
+                (target.loc.clone(), "incompatible signature".to_string()),
+                (loc, msg),
+                (fntype_loc, "callback declared here".to_string()),
+            ]);
+            std::process::exit(9);
+        }
+
+        let ctx_typed = &target_args[target_ctx].typed;
+        if ctx_typed.t != ast::Type::Other(ctx_name.clone()) || ctx_typed.ptr.len() != 1 {
+            emit_error(format!("{} cannot be called through {}", target_name.human_name(), fntype_name.human_name()), &[
+                (target.loc.clone(), "incompatible signature".to_string()),
+                (target_args[target_ctx].loc.clone(), format!("argument {} must be {}*", target_ctx + 1, ctx)),
+            ]);
+            std::process::exit(9);
+        }
+
+        let mut callargs = Vec::new();
+        for i in order {
+            let arg = &args[i];
+            // tail arguments are expanded again by the call
+            if arg.tags.get("tail").is_some() {
+                continue;
+            }
+            let name = ast::Expression::Name(ast::Typed{
+                t:      ast::Type::Other(Name::from(&arg.name)),
+                loc:    nameloc.clone(),
+                ptr:    Vec::new(),
+                tail:   ast::Tail::None,
+            });
+            if i == ctxarg {
+                callargs.push(Box::new(ast::Expression::Cast{
+                    loc:    nameloc.clone(),
+                    into:   ctx_typed.clone(),
+                    expr:   Box::new(name),
+                    tags:   ast::Tags::new(),
+                }));
+            } else {
+                callargs.push(Box::new(name));
+            }
+        }
+
+        let call = ast::Expression::Call {
+            loc:        nameloc.clone(),
+            name:       Box::new(ast::Expression::Name(target.clone())),
+            args:       callargs,
+            expanded:   false,
+            emit:       ast::EmitBehaviour::Default,
+        };
+
+        let stm = if ret.is_some() {
+            ast::Statement::Return{
+                loc:    nameloc.clone(),
+                expr:   Some(call),
+            }
+        } else {
+            ast::Statement::Expr{
+                loc:    nameloc.clone(),
+                expr:   call,
+            }
+        };
+
+        md.locals[i].def = ast::Def::Function {
+            nameloc:    nameloc.clone(),
+            ret,
+            args,
+            hints:      HashMap::new(),
+            attr:       HashMap::new(),
+            body:       ast::Block {
+                end:        nameloc.clone(),
+                statements: vec![Box::new(stm)],
+                expanded:   false,
+            },
+            vararg:     false,
+            callassert: Vec::new(),
+            calleffect: Vec::new(),
+            callattests: Vec::new(),
+        };
+    }
+}
+
+fn name_expr(n: &str, loc: &ast::Location) -> ast::Expression {
+    ast::Expression::Name(ast::Typed{
+        t:      ast::Type::Other(Name::from(n)),
+        loc:    loc.clone(),
+        ptr:    Vec::new(),
+        tail:   ast::Tail::None,
+    })
+}
+
+fn named(n: &str, loc: &ast::Location) -> ast::Typed {
+    ast::Typed{
+        t:      ast::Type::Other(Name::from(n)),
+        loc:    loc.clone(),
+        ptr:    Vec::new(),
+        tail:   ast::Tail::None,
+    }
+}
+
+fn single(name: &Name) -> Option<&str> {
+    if !name.is_absolute() && name.len() == 1 {
+        Some(&name.0[0])
+    } else {
+        None
+    }
+}
+
+/// variables declared in a body, where, and whether they hold a value rather than a pointer.
+/// of a closure declared in it, only its own name counts. functions declared in it are not variables
+#[derive(Default)]
+struct Declared(HashMap<String, (ast::Location, bool)>);
+
+impl Visit for Declared {
+    fn statement(&mut self, stm: &mut ast::Statement) {
+        if let ast::Statement::Block(b) = stm {
+            if let Some((name, loc, nested)) = head(b) {
+                if !nested {
+                    self.0.insert(name.to_string(), (loc.clone(), true));
+                }
+                return;
+            }
+        }
+        if let ast::Statement::Var{name, loc, typed, ..} = stm {
+            self.0.insert(name.clone(), (loc.clone(), typed.ptr.is_empty()));
+        }
+        walk_statement(self, stm);
+    }
+}
+
+/// names of the enclosing function used in the body of a closure
+struct Uncaptured<'a> {
+    outer:  &'a HashMap<String, (ast::Location, bool)>,
+    inner:  &'a HashSet<String>,
+    found:  Vec<(String, ast::Location)>,
+}
+
+impl<'a> Visit for Uncaptured<'a> {
+    fn expr(&mut self, expr: &mut ast::Expression) {
+        if let ast::Expression::Name(ast::Typed{t: ast::Type::Other(name), loc, ..}) = expr {
+            if let Some(n) = single(name) {
+                if self.outer.contains_key(n) && !self.inner.contains(n) && !self.found.iter().any(|(f, _)| f == n) {
+                    self.found.push((n.to_string(), loc.clone()));
+                }
+            }
+        }
+        walk_expr(self, expr);
+    }
+}
+
+/// replace the captures in the body of a closure with self->name
+struct Captured<'a>(&'a HashSet<String>);
+
+impl<'a> Visit for Captured<'a> {
+    fn expr(&mut self, expr: &mut ast::Expression) {
+        if let ast::Expression::Name(ast::Typed{t: ast::Type::Other(name), loc, ..}) = expr {
+            if let Some(n) = single(name).filter(|n| self.0.contains(*n)) {
+                let n = n.to_string();
+                let loc = loc.clone();
+                *expr = ast::Expression::MemberAccess{
+                    lhs:    Box::new(name_expr("self", &loc)),
+                    op:     "->".to_string(),
+                    rhs:    n,
+                    loc,
+                };
+                return;
+            }
+        }
+        walk_expr(self, expr);
+    }
+}
+
+/// the closure expr is, or points into. taking .call alone is a plain function pointer.
+/// a call is assumed to not return what it was passed
+fn mentions(expr: &ast::Expression, aliases: &HashMap<String, String>) -> Option<String> {
+    match expr {
+        ast::Expression::Name(ast::Typed{t: ast::Type::Other(name), ..}) => {
+            single(name).and_then(|n| aliases.get(n).cloned())
+        }
+        ast::Expression::MemberAccess{lhs, op, rhs, ..} => {
+            if op == "." && rhs == "call" {
+                if let ast::Expression::Name(_) = lhs.as_ref() {
+                    return None;
+                }
+            }
+            mentions(lhs, aliases)
+        }
+        ast::Expression::ArrayAccess{lhs, rhs, ..} | ast::Expression::Infix{lhs, rhs, ..} => {
+            mentions(lhs, aliases).or_else(|| mentions(rhs, aliases))
+        }
+        ast::Expression::Cast{expr, ..}
+        | ast::Expression::UnaryPre{expr, ..}
+        | ast::Expression::UnaryPost{expr, ..} => mentions(expr, aliases),
+        ast::Expression::StructInit{fields, ..} => fields.iter().find_map(|(_, e)| mentions(e, aliases)),
+        ast::Expression::ArrayInit{fields, ..} => fields.iter().find_map(|e| mentions(e, aliases)),
+        _ => None,
+    }
+}
+
+/// whether expr is a local variable holding a value, or a field or element of one
+fn value_local(expr: &ast::Expression, locals: &HashMap<String, (ast::Location, bool)>) -> bool {
+    match expr {
+        ast::Expression::Name(ast::Typed{t: ast::Type::Other(name), ..}) => {
+            single(name).and_then(|n| locals.get(n)).map(|(_, value)| *value).unwrap_or(false)
+        }
+        ast::Expression::MemberAccess{lhs, op, ..} if op == "." => value_local(lhs, locals),
+        ast::Expression::ArrayAccess{lhs, ..} => value_local(lhs, locals),
+        _ => false,
+    }
+}
+
+/// closures returned or stored outside of the locals of the function declaring them
+struct Escapes<'a> {
+    // locals holding a closure or pointing into one, and which
+    aliases:    HashMap<String, String>,
+    locals:     &'a HashMap<String, (ast::Location, bool)>,
+    found:      Vec<(String, ast::Location)>,
+}
+
+impl<'a> Visit for Escapes<'a> {
+    fn statement(&mut self, stm: &mut ast::Statement) {
+        match stm {
+            ast::Statement::Return{loc, expr: Some(expr)} => {
+                if let Some(closure) = mentions(expr, &self.aliases) {
+                    self.found.push((closure, loc.clone()));
+                }
+            }
+            ast::Statement::Var{name, assign: Some(value), ..} => {
+                if let Some(closure) = mentions(value, &self.aliases) {
+                    self.aliases.insert(name.clone(), closure);
+                }
+            }
+            ast::Statement::Assign{loc, lhs, rhs, ..} => {
+                if let Some(closure) = mentions(rhs, &self.aliases) {
+                    let local = match lhs {
+                        ast::Expression::Name(ast::Typed{t: ast::Type::Other(name), ..}) => {
+                            single(name).filter(|n| self.locals.contains_key(*n)).map(String::from)
+                        }
+                        _ => None,
+                    };
+                    match local {
+                        Some(n) => {
+                            self.aliases.insert(n, closure);
+                        }
+                        None if value_local(lhs, self.locals) => (),
+                        None => self.found.push((closure, loc.clone())),
+                    }
+                }
+            }
+            _ => (),
+        }
+        walk_statement(self, stm);
+    }
+}
+
+/// functions declared in a body, not counting those declared in closures or functions in it
+#[derive(Default)]
+struct Nested(Vec<(String, ast::Location)>);
+
+impl Visit for Nested {
+    fn statement(&mut self, stm: &mut ast::Statement) {
+        if let ast::Statement::Block(b) = stm {
+            if let Some((name, loc, nested)) = head(b) {
+                if nested {
+                    self.0.push((name.to_string(), loc.clone()));
+                }
+                return;
+            }
+        }
+        walk_statement(self, stm);
+    }
+}
+
+/// rename the uses of functions declared in a body to what they are lifted to
+struct Lifted<'a>(&'a HashMap<String, String>);
+
+impl<'a> Visit for Lifted<'a> {
+    fn expr(&mut self, expr: &mut ast::Expression) {
+        if let ast::Expression::Name(ast::Typed{t: ast::Type::Other(name), ..}) = expr {
+            if let Some(to) = single(name).and_then(|n| self.0.get(n)) {
+                *name = Name::from(to.as_str());
+            }
+        }
+        walk_expr(self, expr);
+    }
+}
+
+struct Lowering<'a> {
+    // the enclosing function, as a prefix for the names of what closures lower to
+    prefix:     &'a str,
+    outer:      &'a HashMap<String, (ast::Location, bool)>,
+    lifted:     Vec<ast::Local>,
+    closures:   HashMap<String, ast::Location>,
+    functions:  HashMap<String, ast::Location>,
+    ok:         bool,
+}
+
+impl<'a> Lowering<'a> {
+    fn conflict(&mut self, name: &str, closure: &str, at: &ast::Location, first: &ast::Location) {
+        emit_error(msg!("closure_conflict", name = name, closure = closure), &[
+            (at.clone(), msg!("here")),
+            (first.clone(), msg!("defined_here")),
+        ]);
+        self.ok = false;
+    }
+
+    fn clash(&mut self, name: &str, function: &str, at: &ast::Location, first: &ast::Location) {
+        emit_error(msg!("nested_fn_conflict", name = name, function = function), &[
+            (at.clone(), msg!("here")),
+            (first.clone(), msg!("defined_here")),
+        ]);
+        self.ok = false;
+    }
+
+    fn block(&mut self, block: &mut ast::Block) {
+        for stm in &mut block.statements {
+            self.statement(stm);
+        }
+    }
+
+    fn statement(&mut self, stm: &mut ast::Statement) {
+        if let ast::Statement::Block(b) = stm {
+            if let Some(closure) = inline(b) {
+                *stm = if closure.nested {
+                    self.lift(closure)
+                } else {
+                    self.lower(closure)
+                };
+                return;
+            }
+        }
+        match stm {
+            ast::Statement::Block(b) | ast::Statement::Unsafe(b) => self.block(b),
+            ast::Statement::While{body, ..} | ast::Statement::For{body, ..} => self.block(body),
+            ast::Statement::If{branches} => {
+                for (_, _, body) in branches {
+                    self.block(body);
+                }
+            }
+            ast::Statement::Switch{cases, default, ..} => {
+                for (_, body) in cases {
+                    self.block(body);
+                }
+                if let Some(body) = default {
+                    self.block(body);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// a function declared in the body becomes a function of the module. an empty block is left in its place
+    fn lift(&mut self, c: Inline) -> ast::Statement {
+        let loc = c.loc.clone();
+        let first = self.closures.get(&c.name).or_else(|| self.functions.get(&c.name)).or_else(|| self.outer.get(&c.name).map(|o| &o.0));
+        if let Some(first) = first.cloned() {
+            let prefix = self.prefix.to_string();
+            self.clash(&c.name, &prefix, &loc, &first);
+        }
+        self.functions.insert(c.name.clone(), loc.clone());
+
+        let mut seen : HashMap<String, ast::Location> = HashMap::new();
+        for arg in &c.args {
+            match seen.get(&arg.name).cloned() {
+                Some(first) => self.clash(&arg.name, &c.name, &arg.loc, &first),
+                None => {
+                    seen.insert(arg.name.clone(), arg.loc.clone());
+                }
+            }
+        }
+        let mut body = c.body;
+        let mut own = Declared::default();
+        walk_block(&mut own, &mut body);
+        let inner : HashSet<String> = seen.into_iter().map(|(n, _)| n).chain(own.0.into_iter().map(|(n, _)| n)).collect();
+        let mut uncaptured = Uncaptured{outer: self.outer, inner: &inner, found: Vec::new()};
+        walk_block(&mut uncaptured, &mut body);
+        for (name, at) in uncaptured.found {
+            emit_error(msg!("nested_fn_captures", name = name, function = c.name), &[
+                (at, msg!("nested_fn_captures.note", name = name)),
+                (self.outer[&name].0.clone(), msg!("defined_here")),
+            ]);
+            self.ok = false;
+        }
+
+        self.lifted.push(ast::Local{
+            name:   format!("{}_{}", self.prefix, c.name),
+            vis:    ast::Visibility::Object,
+            loc:    loc.clone(),
+            def:    ast::Def::Function{
+                nameloc:    loc.clone(),
+                ret:        c.ret,
+                args:       c.args,
+                hints:      HashMap::new(),
+                attr:       HashMap::new(),
+                body,
+                vararg:     false,
+                callassert: Vec::new(),
+                calleffect: Vec::new(),
+                callattests: Vec::new(),
+            },
+            doc:    String::new(),
+        });
+        ast::Statement::Block(Box::new(ast::Block{end: loc, statements: Vec::new(), expanded: false}))
+    }
+
+    /// the local holding the closure, and the declarations it needs
+    fn lower(&mut self, c: Inline) -> ast::Statement {
+        let base = format!("{}_{}", self.prefix, c.name);
+        let loc = c.loc.clone();
+        if let Some(first) = self.closures.get(&c.name).or_else(|| self.functions.get(&c.name)).cloned() {
+            self.conflict(&c.name, &c.name, &loc, &first);
+        }
+        self.closures.insert(c.name.clone(), loc.clone());
+        if c.captures.is_empty() {
+            emit_error(msg!("closure_no_captures", name = c.name), &[(loc.clone(), msg!("closure_no_captures.note"))]);
+            self.ok = false;
+        }
+
+        // self is the context, so it can't be declared again
+        let mut seen : HashMap<String, ast::Location> = HashMap::new();
+        seen.insert("self".to_string(), loc.clone());
+        let declared = c.captures.iter().map(|(f, _)| (&f.name, &f.loc)).chain(c.args.iter().map(|a| (&a.name, &a.loc)));
+        for (name, at) in declared {
+            match seen.get(name).cloned() {
+                Some(first) => self.conflict(name, &c.name, at, &first),
+                None => {
+                    seen.insert(name.clone(), at.clone());
+                }
+            }
+        }
+        let mut body = c.body;
+        let mut own = Declared::default();
+        walk_block(&mut own, &mut body);
+        let mut own : Vec<(String, ast::Location)> = own.0.into_iter().map(|(n, (at, _))| (n, at)).collect();
+        own.sort_by_key(|(_, at)| at.start);
+        for (name, at) in &own {
+            if let Some(first) = seen.get(name).cloned() {
+                self.conflict(name, &c.name, at, &first);
+            }
+        }
+
+        let inner : HashSet<String> = seen.keys().cloned().chain(own.into_iter().map(|(n, _)| n)).collect();
+        let mut uncaptured = Uncaptured{outer: self.outer, inner: &inner, found: Vec::new()};
+        walk_block(&mut uncaptured, &mut body);
+        for (name, at) in uncaptured.found {
+            emit_error(msg!("closure_not_captured", name = name, closure = c.name), &[
+                (at, msg!("closure_not_captured.note", name = name)),
+                (self.outer[&name].0.clone(), msg!("defined_here")),
+            ]);
+            self.ok = false;
+        }
+
+        let captured : HashSet<String> = c.captures.iter().map(|(f, _)| f.name.clone()).collect();
+        walk_block(&mut Captured(&captured), &mut body);
+
+        let ctx = format!("{}_ctx", base);
+        let mut mutable = ast::Tags::new();
+        mutable.insert("mut".to_string(), String::new(), loc.clone());
+        let mut args = vec![ast::NamedArg{
+            typed:  ast::Typed{
+                ptr:    vec![ast::Pointer{loc: loc.clone(), tags: mutable.clone()}],
+                ..named(&ctx, &loc)
+            },
+            name:   "self".to_string(),
+            tags:   ast::Tags::new(),
+            loc:    loc.clone(),
+        }];
+        args.extend(c.args);
+
+        let local = |name: &str, def: ast::Def| ast::Local{
+            name:   name.to_string(),
+            vis:    ast::Visibility::Object,
+            loc:    loc.clone(),
+            def,
+            doc:    String::new(),
+        };
+        let structure = |fields: Vec<ast::Field>| ast::Def::Struct{
+            fields,
+            packed: false,
+            tail:   ast::Tail::None,
+            union:  false,
+            impls:  HashMap::new(),
+            abi:    None,
+        };
+        let field = |name: &str, typed: ast::Typed| ast::Field{
+            typed,
+            name:   name.to_string(),
+            array:  None,
+            bits:   None,
+            range:  None,
+            default: None,
+            tags:   ast::Tags::new(),
+            loc:    loc.clone(),
+        };
+
+        let mut values = Vec::new();
+        let mut fields = Vec::new();
+        for (f, value) in c.captures {
+            values.push((f.name.clone(), Box::new(value)));
+            fields.push(f);
+        }
+        let target = format!("{}_body", base);
+        let trampoline = format!("{}_fn", base);
+        self.lifted.push(local(&ctx, structure(fields)));
+        self.lifted.push(local(&target, ast::Def::Function{
+            nameloc:    loc.clone(),
+            ret:        c.ret,
+            args,
+            hints:      HashMap::new(),
+            attr:       HashMap::new(),
+            body,
+            vararg:     false,
+            callassert: Vec::new(),
+            calleffect: Vec::new(),
+            callattests: Vec::new(),
+        }));
+        self.lifted.push(local(&trampoline, ast::Def::Closure{
+            nameloc:    loc.clone(),
+            fntype:     c.fntype.clone(),
+            target:     named(&target, &loc),
+            ctx:        ctx.clone(),
+            inline:     true,
+        }));
+        self.lifted.push(local(&base, structure(vec![
+            field("call", c.fntype),
+            field("ctx", named(&ctx, &loc)),
+        ])));
+
+        // base mut name = base{call: base_fn, ctx: base_ctx{captures}}
+        ast::Statement::Var{
+            loc:    loc.clone(),
+            typed:  named(&base, &loc),
+            tags:   mutable,
+            name:   c.name,
+            array:  None,
+            assign: Some(ast::Expression::StructInit{
+                loc:    loc.clone(),
+                typed:  named(&base, &loc),
+                fields: vec![
+                    ("call".to_string(), Box::new(name_expr(&trampoline, &loc))),
+                    ("ctx".to_string(), Box::new(ast::Expression::StructInit{
+                        loc:    loc.clone(),
+                        typed:  named(&ctx, &loc),
+                        fields: values,
+                    })),
+                ],
+            }),
+        }
+    }
+}
+
+/// lower the closures declared in function bodies of md. see the top of this file
+pub fn lower(md: &mut ast::Module) {
+    let mut ok = true;
+    // closures declared in a closure are lowered when the loop gets to its body
+    let mut i = 0;
+    while i < md.locals.len() {
+        let prefix : String = md.locals[i].name.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+        let (args, body) = match &mut md.locals[i].def {
+            ast::Def::Function{args, body, ..} => (&args[..], body),
+            ast::Def::Test{body, ..} => (&[][..], body),
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+        // functions declared in the body are renamed first, so closures and functions declared next to them see the new name
+        let mut nested = Nested::default();
+        walk_block(&mut nested, body);
+        let renamed : HashMap<String, String> = nested.0.into_iter().map(|(n, _)| (n.clone(), format!("{}_{}", prefix, n))).collect();
+        if !renamed.is_empty() {
+            walk_block(&mut Lifted(&renamed), body);
+        }
+
+        let mut outer = Declared::default();
+        for arg in args {
+            outer.0.insert(arg.name.clone(), (arg.loc.clone(), arg.typed.ptr.is_empty()));
+        }
+        walk_block(&mut outer, body);
+
+        let mut l = Lowering{
+            prefix:     &prefix,
+            outer:      &outer.0,
+            lifted:     Vec::new(),
+            closures:   HashMap::new(),
+            functions:  HashMap::new(),
+            ok:         true,
+        };
+        l.block(body);
+
+        let mut escapes = Escapes{
+            aliases:    l.closures.keys().map(|n| (n.clone(), n.clone())).collect(),
+            locals:     &outer.0,
+            found:      Vec::new(),
+        };
+        if !escapes.aliases.is_empty() {
+            walk_block(&mut escapes, body);
+        }
+        for (name, at) in escapes.found {
+            emit_error(msg!("closure_escapes", name = name), &[
+                (at, msg!("closure_escapes.note")),
+                (l.closures[&name].clone(), msg!("defined_here")),
+            ]);
+            ok = false;
+        }
+        ok &= l.ok;
+        md.locals.extend(l.lifted);
+        i += 1;
+    }
+    if !ok {
+        abort();
+    }
+}
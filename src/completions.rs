@@ -0,0 +1,255 @@
+/// shell completions and man pages, generated from the command line definition of zz
+
+use super::project::{self, ArtifactType};
+use super::warnings;
+use clap::{App, Shell};
+use std::io::Write;
+use std::path::Path;
+
+pub const LISTS : &[&str] = &["targets", "variants", "tests", "modules", "bins", "lints"];
+
+/// options completed from a list, by long name, short name
+const OPTIONS : &[(&str, Option<&str>, &str)] = &[
+    ("target",  None,       "targets"),
+    ("variant", Some("s"),  "variants"),
+    ("module",  Some("m"),  "modules"),
+    ("bin",     None,       "bins"),
+    ("allow",   Some("A"),  "lints"),
+    ("warn",    Some("W"),  "lints"),
+    ("deny",    Some("D"),  "lints"),
+];
+
+/// positional arguments completed from a list, by name
+const POSITIONALS : &[(&str, &str)] = &[
+    ("testname", "tests"),
+];
+
+/// modules below dir, as package::module names
+fn modules(dir: &Path, prefix: &str, into: &mut Vec<String>) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let stem = match path.file_stem() {
+            Some(stem) => stem.to_string_lossy().to_string(),
+            None => continue,
+        };
+        if path.is_dir() {
+            modules(&path, &format!("{}{}::", prefix, stem), into);
+        } else if path.extension().map(|e| e == "zz").unwrap_or(false) {
+            into.push(format!("{}{}", prefix, stem));
+        }
+    }
+}
+
+/// print the values of a list, one per line. prints nothing outside of a project
+pub fn list(what: &str) {
+    let mut values : Vec<String> = match what {
+        "lints" => warnings::LINTS.iter().chain(std::iter::once(&warnings::ALL)).map(|s| s.to_string()).collect(),
+        _ => {
+            let root = match project::find_root_cwd() {
+                Some(root) => root,
+                None => return,
+            };
+            let (root, config) = project::load(&root);
+            let artifacts = config.artifacts.clone().unwrap_or_default();
+            match what {
+                "targets" => std::iter::once("wasm32".to_string()).chain(config.target.keys().cloned()).collect(),
+                "variants" => std::iter::once("default".to_string()).chain(config.variants.keys().cloned()).collect(),
+                "tests" => artifacts.iter()
+                    .filter(|a| matches!(a.typ, ArtifactType::Test))
+                    .map(|a| a.name.trim_start_matches("tests_").to_string())
+                    .collect(),
+                "bins" => artifacts.iter()
+                    .filter(|a| matches!(a.typ, ArtifactType::Exe))
+                    .map(|a| a.name.clone())
+                    .collect(),
+                "modules" => {
+                    let mut v = Vec::new();
+                    modules(&root.join("src"), "", &mut v);
+                    v
+                }
+                _ => Vec::new(),
+            }
+        }
+    };
+    values.sort();
+    values.dedup();
+    for v in values {
+        println!("{}", v);
+    }
+}
+
+/// the long or short option a line of clap's zsh or fish completions is for, and its list
+fn option_list(line: &str, long: impl Fn(&str) -> String, short: impl Fn(&str) -> String) -> Option<&'static str> {
+    OPTIONS.iter().find(|(l, s, _)| {
+        line.contains(&long(*l)) || s.map(|s| line.contains(&short(s))).unwrap_or(false)
+    }).map(|(_, _, list)| *list)
+}
+
+fn bash(app: &mut App) -> String {
+    let mut out = Vec::new();
+    app.gen_completions_to("zz", Shell::Bash, &mut out);
+    let mut s = String::from_utf8_lossy(&out).to_string();
+
+    s.push_str("\n_zz_dynamic() {\n");
+    s.push_str("    local cur=\"${COMP_WORDS[COMP_CWORD]}\"\n");
+    s.push_str("    local prev=\"${COMP_WORDS[COMP_CWORD-1]}\"\n");
+    s.push_str("    local list=\"\"\n");
+    s.push_str("    case \"${prev}\" in\n");
+    for (long, short, list) in OPTIONS {
+        match short {
+            Some(short) => s.push_str(&format!("        --{}|-{}) list={} ;;\n", long, short, list)),
+            None        => s.push_str(&format!("        --{}) list={} ;;\n", long, list)),
+        }
+    }
+    s.push_str("    esac\n");
+    s.push_str("    if [[ -z \"${list}\" && ${COMP_CWORD} -eq 2 && \"${cur}\" != -* ]]; then\n");
+    s.push_str("        case \"${COMP_WORDS[1]}\" in\n");
+    s.push_str("            test|bench|fuzz) list=tests ;;\n");
+    s.push_str("        esac\n");
+    s.push_str("    fi\n");
+    s.push_str("    if [[ -n \"${list}\" ]]; then\n");
+    s.push_str("        COMPREPLY=( $(compgen -W \"$(zz completions --list ${list} 2>/dev/null)\" -- \"${cur}\") )\n");
+    s.push_str("        return 0\n");
+    s.push_str("    fi\n");
+    s.push_str("    _zz \"$@\"\n");
+    s.push_str("}\n\n");
+    s.push_str("complete -F _zz_dynamic -o bashdefault -o default zz\n");
+    s
+}
+
+fn zsh(app: &mut App) -> String {
+    let mut out = Vec::new();
+    app.gen_completions_to("zz", Shell::Zsh, &mut out);
+    let generated = String::from_utf8_lossy(&out).to_string();
+
+    let mut s = String::new();
+    for line in generated.lines() {
+        let trimmed = line.trim_end();
+        let list = if trimmed.ends_with("]' \\") {
+            option_list(trimmed, |l| format!("--{}=[", l), |s| format!("-{}+[", s))
+        } else {
+            None
+        };
+        let positional = POSITIONALS.iter().find(|(name, _)| trimmed.ends_with(&format!(":{}:_files' \\", name)));
+        match (list, positional) {
+            (Some(list), _) => {
+                let at = trimmed.len() - "' \\".len();
+                s.push_str(&format!("{}: :{{_zz_list {}}}' \\\n", &trimmed[..at], list));
+            }
+            (None, Some((_, list))) => {
+                let at = trimmed.len() - "_files' \\".len();
+                s.push_str(&format!("{}{{_zz_list {}}}' \\\n", &trimmed[..at], list));
+            }
+            (None, None) => {
+                s.push_str(line);
+                s.push('\n');
+            }
+        }
+    }
+
+    // clap ends the script with the call to _zz, the helper has to be defined before it
+    let helper = "_zz_list() {\n    local -a values\n    values=(${(f)\"$(zz completions --list $1 2>/dev/null)\"})\n    compadd -a values\n}\n\n";
+    match s.rfind("_zz \"$@\"") {
+        Some(at) => s.insert_str(at, helper),
+        None => s.push_str(helper),
+    }
+    s
+}
+
+fn fish(app: &mut App) -> String {
+    let mut out = Vec::new();
+    app.gen_completions_to("zz", Shell::Fish, &mut out);
+    let generated = String::from_utf8_lossy(&out).to_string();
+
+    let mut s = String::new();
+    for line in generated.lines() {
+        s.push_str(line);
+        let words = format!("{} ", line);
+        if let Some(list) = option_list(&words, |l| format!(" -l {} ", l), |s| format!(" -s {} ", s)) {
+            s.push_str(&format!(" -r -f -a '(zz completions --list {} 2>/dev/null)'", list));
+        }
+        s.push('\n');
+    }
+    s.push_str("complete -c zz -n \"__fish_seen_subcommand_from test bench fuzz\" -f -a '(zz completions --list tests 2>/dev/null)'\n");
+    s
+}
+
+/// print the completions of zz for a shell
+pub fn print(mut app: App, shell: &str) {
+    let s = match shell {
+        "bash"  => bash(&mut app),
+        "zsh"   => zsh(&mut app),
+        "fish"  => fish(&mut app),
+        _       => unreachable!(),
+    };
+    print!("{}", s);
+}
+
+/// text as roff, so nothing in it is read as a request or escape
+fn roff(text: &str) -> String {
+    let mut s = String::new();
+    for line in text.lines() {
+        let line = line.replace('\\', "\\e").replace('-', "\\-");
+        if line.starts_with('.') || line.starts_with('\'') {
+            s.push_str("\\&");
+        }
+        s.push_str(&line);
+        s.push('\n');
+    }
+    s
+}
+
+/// a man page, with the help of clap as its options
+fn page(name: &str, about: &str, help: &str, commands: &[(String, String)]) -> String {
+    let mut s = String::new();
+    s.push_str(&format!(".TH {} 1 \"\" \"zz {}\" \"zz manual\"\n", name.to_uppercase(), env!("CARGO_PKG_VERSION")));
+    s.push_str(&format!(".SH NAME\n{} \\- {}\n", roff(name).trim_end(), roff(about).trim_end()));
+    s.push_str(".SH DESCRIPTION\n.nf\n");
+    s.push_str(&roff(help));
+    s.push_str(".fi\n");
+    if !commands.is_empty() {
+        s.push_str(".SH COMMANDS\n");
+        for (command, about) in commands {
+            s.push_str(&format!(".TP\n\\fB{}\\fR\n{}", roff(command).trim_end(), roff(about)));
+        }
+        s.push_str(".SH SEE ALSO\n");
+        let refs : Vec<String> = commands.iter().map(|(c, _)| format!("\\fBzz\\-{}\\fR(1)", roff(c).trim_end())).collect();
+        s.push_str(&refs.join(",\n"));
+        s.push('\n');
+    }
+    s
+}
+
+fn help(app: &mut App) -> String {
+    let mut out = Vec::new();
+    app.write_long_help(&mut out).expect("cannot render help");
+    String::from_utf8_lossy(&out).to_string()
+}
+
+/// write zz.1 and zz-<command>.1 for every command into dir
+pub fn man(app: App, dir: &Path) {
+    std::fs::create_dir_all(dir).expect(&format!("cannot create {}", dir.display()));
+    let mut app = app.bin_name("zz");
+
+    // clap 2 has no public way to list the subcommands of an app
+    let mut commands = Vec::new();
+    for sub in &app.p.subcommands {
+        let name = sub.get_name().to_string();
+        let about = sub.p.meta.about.unwrap_or("").to_string();
+        let mut sub = sub.clone().bin_name(format!("zz {}", name));
+        let text = page(&format!("zz-{}", name), &about, &help(&mut sub), &[]);
+        let path = dir.join(format!("zz-{}.1", name));
+        std::fs::File::create(&path).and_then(|mut f| f.write_all(text.as_bytes()))
+            .expect(&format!("cannot write {}", path.display()));
+        commands.push((name, about));
+    }
+
+    let text = page("zz", "the zz language", &help(&mut app), &commands);
+    let path = dir.join("zz.1");
+    std::fs::write(&path, text).expect(&format!("cannot write {}", path.display()));
+    info!("wrote {} man pages to {}", commands.len() + 1, dir.display());
+}
@@ -0,0 +1,1000 @@
+/// interpreter for the pure subset of zz, folding comptime calls and array sizes at compile time
+
+use super::ast;
+use super::closure;
+use super::loader;
+use super::name::Name;
+use super::parser::{emit_error, abort};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+pub const MAX_STEPS : usize = 10_000_000;
+pub const MAX_DEPTH : usize = 256;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Int(i128),
+    Bool(bool),
+    Array(Vec<Value>),
+}
+
+pub struct Error {
+    pub loc:        ast::Location,
+    pub message:    String,
+}
+
+enum Flow {
+    Next,
+    Break,
+    Continue,
+    Return(Option<Value>),
+}
+
+pub struct Interpreter<'a> {
+    // the module being folded isn't in modules
+    md:         &'a ast::Module,
+    modules:    &'a HashMap<Name, loader::Module>,
+    // zz eval calls any zz function, not just comptime ones
+    comptime:   bool,
+    steps:      usize,
+    depth:      usize,
+    // scopes of the function being executed, innermost last
+    scopes:     Vec<HashMap<String, (ast::Type, Value)>>,
+}
+
+fn err<T>(loc: &ast::Location, message: impl Into<String>) -> Result<T, Error> {
+    Err(Error{loc: loc.clone(), message: message.into()})
+}
+
+/// truncate v to the range of t, like a c cast would
+fn wrap(t: &ast::Type, v: Value, loc: &ast::Location) -> Result<Value, Error> {
+    let i = match v {
+        Value::Int(i)   => i,
+        Value::Bool(b)  => b as i128,
+        Value::Array(_) => return Ok(v),
+    };
+    Ok(match t {
+        ast::Type::U8       => Value::Int(i as u8 as i128),
+        ast::Type::U16      => Value::Int(i as u16 as i128),
+        ast::Type::U32      => Value::Int(i as u32 as i128),
+        ast::Type::UInt     => Value::Int(i as u32 as i128),
+        ast::Type::U64      => Value::Int(i as u64 as i128),
+        ast::Type::USize    => Value::Int(i as u64 as i128),
+        ast::Type::I8       => Value::Int(i as i8 as i128),
+        ast::Type::I16      => Value::Int(i as i16 as i128),
+        ast::Type::I32      => Value::Int(i as i32 as i128),
+        ast::Type::Int      => Value::Int(i as i32 as i128),
+        ast::Type::I64      => Value::Int(i as i64 as i128),
+        ast::Type::ISize    => Value::Int(i as i64 as i128),
+        ast::Type::Bool     => Value::Bool(i != 0),
+        ast::Type::F32 | ast::Type::F64 => return err(loc, "floating point is not supported at compile time"),
+        _                   => Value::Int(i),
+    })
+}
+
+/// wrap each element of an array, or v itself
+fn wrap_each(t: &ast::Type, v: Value, loc: &ast::Location) -> Result<Value, Error> {
+    match v {
+        Value::Array(a) => {
+            let mut r = Vec::new();
+            for v in a {
+                r.push(wrap(t, v, loc)?);
+            }
+            Ok(Value::Array(r))
+        }
+        v => wrap(t, v, loc),
+    }
+}
+
+impl Value {
+    fn int(&self, loc: &ast::Location) -> Result<i128, Error> {
+        match self {
+            Value::Int(i)   => Ok(*i),
+            Value::Bool(b)  => Ok(*b as i128),
+            Value::Array(_) => err(loc, "expected a number, not an array"),
+        }
+    }
+
+    fn truth(&self, loc: &ast::Location) -> Result<bool, Error> {
+        Ok(self.int(loc)? != 0)
+    }
+
+    /// the literal expression to replace a call with
+    pub fn to_expr(&self, loc: &ast::Location) -> Result<ast::Expression, Error> {
+        match self {
+            Value::Int(i)   => Ok(ast::Expression::literal(loc.clone(), format!("{}", i))),
+            Value::Bool(b)  => Ok(ast::Expression::literal(loc.clone(), format!("{}", b))),
+            Value::Array(a) => {
+                let mut fields = Vec::new();
+                for v in a {
+                    fields.push(Box::new(v.to_expr(loc)?));
+                }
+                Ok(ast::Expression::ArrayInit{loc: loc.clone(), fields, index: Vec::new()})
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Value::Int(i)   => write!(f, "{}", i),
+            Value::Bool(b)  => write!(f, "{}", b),
+            Value::Array(a) => {
+                write!(f, "{{")?;
+                for (i, v) in a.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", v)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+impl<'a> Interpreter<'a> {
+    pub fn new(md: &'a ast::Module, modules: &'a HashMap<Name, loader::Module>) -> Self {
+        Self {
+            md,
+            modules,
+            comptime: true,
+            steps:  0,
+            depth:  0,
+            scopes: Vec::new(),
+        }
+    }
+
+    /// an interpreter that calls any zz function, for zz eval
+    pub fn any(md: &'a ast::Module, modules: &'a HashMap<Name, loader::Module>) -> Self {
+        Self {
+            comptime: false,
+            ..Self::new(md, modules)
+        }
+    }
+
+    fn step(&mut self, loc: &ast::Location) -> Result<(), Error> {
+        self.steps += 1;
+        if self.steps > MAX_STEPS {
+            return err(loc, format!("compile time evaluation exceeded {} steps", MAX_STEPS));
+        }
+        Ok(())
+    }
+
+    fn find(&self, name: &Name) -> Option<&'a ast::Local> {
+        let mut module = name.clone();
+        let local = module.pop()?;
+        if module == self.md.name {
+            return self.md.locals.iter().find(|l| l.name == local);
+        }
+        match self.modules.get(&module) {
+            Some(loader::Module::ZZ(md)) => md.locals.iter().find(|l| l.name == local),
+            _ => None,
+        }
+    }
+
+    /// the value of an enum member, numbered by abs
+    fn member(&self, name: &Name) -> Option<u64> {
+        let mut parent = name.clone();
+        let member = parent.pop()?;
+        match self.find(&parent).map(|l| &l.def) {
+            Some(ast::Def::Enum{names, ..}) => names.iter().find(|(n, _)| *n == member).and_then(|(_, v)| *v),
+            _ => None,
+        }
+    }
+
+    /// the comptime function called by expr, if it is a call to one. any function for zz eval
+    pub fn callee(&self, expr: &ast::Expression) -> Option<&'a ast::Local> {
+        if let ast::Expression::Call{name, ..} = expr {
+            if let ast::Expression::Name(ast::Typed{t: ast::Type::Other(name), ..}) = name.as_ref() {
+                // abs doesn't resolve array sizes of statics
+                let mut name = name.clone();
+                if !name.is_absolute() && name.len() == 1 {
+                    let short = name.0[0].clone();
+                    name = self.md.name.clone();
+                    name.push(short);
+                }
+                if let Some(local) = self.find(&name) {
+                    if let ast::Def::Function{attr, ..} = &local.def {
+                        if attr.contains_key("comptime") || !self.comptime {
+                            return Some(local);
+                        }
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// check that every entry of a const table with fntype elements is a function of that type,
+    /// so calls through the table need no null check. t is the type of expr, or of its elements if it is an array.
+    /// fntype fields of a struct can't be left out, they would be null
+    fn fn_entries(&self, t: &ast::Typed, expr: &ast::Expression) -> Result<(), Error> {
+        if let ast::Expression::ArrayInit{fields, ..} = expr {
+            for field in fields {
+                self.fn_entries(t, field)?;
+            }
+            return Ok(());
+        }
+        let name = match &t.t {
+            ast::Type::Other(name) if t.ptr.is_empty() => name,
+            _ => return Ok(()),
+        };
+        match self.find(name).map(|l| &l.def) {
+            Some(ast::Def::Fntype{args, ret, ..}) => {
+                let function = match expr {
+                    ast::Expression::Name(ast::Typed{t: ast::Type::Other(f), ..}) => self.find(f).map(|l| (f, &l.def)),
+                    _ => None,
+                };
+                match function {
+                    Some((f, ast::Def::Function{args: fargs, ret: fret, ..})) => {
+                        match closure::signature_mismatch(args, ret, fargs, fret, None) {
+                            Some((_, message)) => err(expr.loc(), format!("{} does not match {}: {}", f, name, message)),
+                            None => Ok(()),
+                        }
+                    }
+                    _ => err(expr.loc(), format!("entry of a const {} table must be a zz function", name)),
+                }
+            }
+            Some(ast::Def::Struct{fields, ..}) => {
+                let init = match expr {
+                    ast::Expression::StructInit{fields, ..} => fields,
+                    _ => return Ok(()),
+                };
+                for field in fields {
+                    match init.iter().find(|(n, _)| n == &field.name) {
+                        Some((_, v)) => self.fn_entries(&field.typed, v)?,
+                        None if field.array.is_none() && self.is_fntype(&field.typed) => {
+                            return err(expr.loc(), format!("missing function {} in const table", field.name));
+                        }
+                        None => (),
+                    }
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn is_fntype(&self, t: &ast::Typed) -> bool {
+        match &t.t {
+            ast::Type::Other(name) if t.ptr.is_empty() => {
+                matches!(self.find(name).map(|l| &l.def), Some(ast::Def::Fntype{..}))
+            }
+            _ => false,
+        }
+    }
+
+    /// whether expr depends on names from c headers, like sizeof, directly or through constants.
+    /// only the c compiler knows their value
+    fn opaque(&self, expr: &ast::Expression, locals: bool, depth: usize) -> bool {
+        if depth > MAX_DEPTH {
+            return false;
+        }
+        match expr {
+            ast::Expression::Name(ast::Typed{t: ast::Type::Other(name), ..}) => {
+                if name.len() > 1 && name.0[1] == "ext" {
+                    return true;
+                }
+                let mut name = name.clone();
+                if !name.is_absolute() {
+                    if locals || name.len() != 1 {
+                        return locals;
+                    }
+                    let short = name.0[0].clone();
+                    name = self.md.name.clone();
+                    name.push(short);
+                }
+                match self.find(&name).map(|l|&l.def) {
+                    Some(ast::Def::Const{expr, ..}) => self.opaque(expr, locals, depth + 1),
+                    _ => false,
+                }
+            }
+            ast::Expression::Call{name, args, ..} => {
+                self.opaque(name, locals, depth) || args.iter().any(|a| self.opaque(a, locals, depth))
+            }
+            ast::Expression::Infix{lhs, rhs, ..}
+            | ast::Expression::ArrayAccess{lhs, rhs, ..} => {
+                self.opaque(lhs, locals, depth) || self.opaque(rhs, locals, depth)
+            }
+            ast::Expression::Cast{expr, ..}
+            | ast::Expression::UnaryPre{expr, ..}
+            | ast::Expression::UnaryPost{expr, ..} => self.opaque(expr, locals, depth),
+            ast::Expression::MemberAccess{lhs, ..} => self.opaque(lhs, locals, depth),
+            _ => false,
+        }
+    }
+
+    fn var(&mut self, name: &str) -> Option<&mut (ast::Type, Value)> {
+        self.scopes.iter_mut().rev().find_map(|s| s.get_mut(name))
+    }
+
+    pub fn eval(&mut self, expr: &ast::Expression) -> Result<Value, Error> {
+        let loc = expr.loc().clone();
+        self.step(&loc)?;
+        match expr {
+            ast::Expression::Literal{v, value, ..} => {
+                match value {
+                    ast::LiteralValue::Bool(b)      => Ok(Value::Bool(*b)),
+                    ast::LiteralValue::Integer{value, ..} => Ok(Value::Int(*value)),
+                    ast::LiteralValue::Other        => err(&loc, format!("literal {} is not supported at compile time", v)),
+                }
+            }
+            ast::Expression::LiteralChar{v, ..} => Ok(Value::Int(*v as i128)),
+            ast::Expression::LiteralString{..} => err(&loc, "strings are not supported at compile time"),
+            ast::Expression::Name(typed) => {
+                let name = match &typed.t {
+                    ast::Type::Other(name) => name,
+                    _ => return err(&loc, "types are not values"),
+                };
+                let mut name = name.clone();
+                if !name.is_absolute() {
+                    if let Some((_, v)) = self.var(&name.to_string()) {
+                        return Ok(v.clone());
+                    }
+                    // abs doesn't resolve array sizes of statics
+                    let mut local = self.md.name.clone();
+                    local.push(name.0[0].clone());
+                    if name.len() != 1 || self.find(&local).is_none() {
+                        return err(&loc, format!("{} is not known at compile time", name));
+                    }
+                    name = local;
+                }
+                match self.find(&name).map(|l|&l.def) {
+                    Some(ast::Def::Const{typed, expr, ..}) => {
+                        self.depth += 1;
+                        if self.depth > MAX_DEPTH {
+                            return err(&loc, format!("compile time evaluation exceeded a depth of {}", MAX_DEPTH));
+                        }
+                        let scopes = std::mem::take(&mut self.scopes);
+                        let v = self.eval(expr);
+                        self.scopes = scopes;
+                        self.depth -= 1;
+                        wrap(&typed.t, v?, &loc)
+                    }
+                    None => match self.member(&name) {
+                        Some(v) => Ok(Value::Int(v as i128)),
+                        None => err(&loc, format!("{} is not a constant", name)),
+                    },
+                    _ => err(&loc, format!("{} is not a constant", name)),
+                }
+            }
+            ast::Expression::Cast{into, expr, ..} => {
+                if !into.ptr.is_empty() {
+                    return err(&loc, "pointers are not supported at compile time");
+                }
+                let v = self.eval(expr)?;
+                wrap(&into.t, v, &loc)
+            }
+            ast::Expression::Infix{lhs, rhs, op, ..} => {
+                // && and || short circuit, like in c
+                match op {
+                    ast::InfixOperator::Booland => {
+                        return Ok(Value::Bool(self.eval(lhs)?.truth(&loc)? && self.eval(rhs)?.truth(&loc)?));
+                    }
+                    ast::InfixOperator::Boolor => {
+                        return Ok(Value::Bool(self.eval(lhs)?.truth(&loc)? || self.eval(rhs)?.truth(&loc)?));
+                    }
+                    _ => (),
+                }
+                let a = self.eval(lhs)?.int(&loc)?;
+                let b = self.eval(rhs)?.int(&loc)?;
+                self.infix(op, a, b, &loc)
+            }
+            ast::Expression::UnaryPre{op, expr, ..} => {
+                match op {
+                    ast::PrefixOperator::Boolnot    => Ok(Value::Bool(!self.eval(expr)?.truth(&loc)?)),
+                    ast::PrefixOperator::Bitnot     => Ok(Value::Int(!self.eval(expr)?.int(&loc)?)),
+                    ast::PrefixOperator::Increment  => self.modify(expr, 1, false),
+                    ast::PrefixOperator::Decrement  => self.modify(expr, -1, false),
+                    ast::PrefixOperator::AddressOf | ast::PrefixOperator::Deref => {
+                        err(&loc, "pointers are not supported at compile time")
+                    }
+                }
+            }
+            ast::Expression::UnaryPost{op, expr, ..} => {
+                match op {
+                    ast::PostfixOperator::Increment => self.modify(expr, 1, true),
+                    ast::PostfixOperator::Decrement => self.modify(expr, -1, true),
+                }
+            }
+            ast::Expression::ArrayAccess{lhs, rhs, ..} => {
+                let index = self.eval(rhs)?.int(&loc)?;
+                match self.eval(lhs)? {
+                    Value::Array(a) => {
+                        match usize::try_from(index).ok().and_then(|i|a.get(i)) {
+                            Some(v) => Ok(v.clone()),
+                            None    => err(&loc, format!("index {} is out of bounds of an array of length {}", index, a.len())),
+                        }
+                    }
+                    _ => err(&loc, "only arrays can be indexed at compile time"),
+                }
+            }
+            ast::Expression::ArrayInit{fields, index, ..} => {
+                let mut a = Vec::new();
+                for (i, field) in fields.iter().enumerate() {
+                    let at = index.get(i).map(|i| *i as usize).unwrap_or(i);
+                    if a.len() <= at {
+                        a.resize(at + 1, Value::Int(0));
+                    }
+                    a[at] = self.eval(field)?;
+                }
+                Ok(Value::Array(a))
+            }
+            ast::Expression::Call{name, args, ..} => {
+                let local = match self.callee(expr) {
+                    Some(local) => local,
+                    None => {
+                        let name = match name.as_ref() {
+                            ast::Expression::Name(ast::Typed{t: ast::Type::Other(name), ..}) => name.to_string(),
+                            _ => String::from("this"),
+                        };
+                        if !self.comptime {
+                            return err(&loc, format!("{} is not a zz function", name));
+                        }
+                        return err(&loc, format!("{} is not a comptime fn and cannot be called at compile time", name));
+                    }
+                };
+                let mut values = Vec::new();
+                for arg in args {
+                    values.push(self.eval(arg)?);
+                }
+                self.call(local, values, &loc)
+            }
+            ast::Expression::MemberAccess{..} | ast::Expression::StructInit{..} => {
+                err(&loc, "structs are not supported at compile time")
+            }
+        }
+    }
+
+    fn infix(&self, op: &ast::InfixOperator, a: i128, b: i128, loc: &ast::Location) -> Result<Value, Error> {
+        let overflow = || Error{loc: loc.clone(), message: "arithmetic overflow at compile time".to_string()};
+        Ok(match op {
+            ast::InfixOperator::Equals      => Value::Bool(a == b),
+            ast::InfixOperator::Nequals     => Value::Bool(a != b),
+            ast::InfixOperator::Moreeq      => Value::Bool(a >= b),
+            ast::InfixOperator::Lesseq      => Value::Bool(a <= b),
+            ast::InfixOperator::Lessthan    => Value::Bool(a < b),
+            ast::InfixOperator::Morethan    => Value::Bool(a > b),
+            ast::InfixOperator::Add         => Value::Int(a.checked_add(b).ok_or_else(overflow)?),
+            ast::InfixOperator::Subtract    => Value::Int(a.checked_sub(b).ok_or_else(overflow)?),
+            ast::InfixOperator::Multiply    => Value::Int(a.checked_mul(b).ok_or_else(overflow)?),
+            ast::InfixOperator::Divide | ast::InfixOperator::Modulo if b == 0 => {
+                return err(loc, "division by zero at compile time");
+            }
+            ast::InfixOperator::Divide      => Value::Int(a / b),
+            ast::InfixOperator::Modulo      => Value::Int(a % b),
+            ast::InfixOperator::Bitxor      => Value::Int(a ^ b),
+            ast::InfixOperator::Bitand      => Value::Int(a & b),
+            ast::InfixOperator::Bitor       => Value::Int(a | b),
+            ast::InfixOperator::Shiftleft | ast::InfixOperator::Shiftright if !(0..128).contains(&b) => {
+                return err(loc, format!("shift by {} at compile time", b));
+            }
+            ast::InfixOperator::Shiftleft   => Value::Int(a << b),
+            ast::InfixOperator::Shiftright  => Value::Int(a >> b),
+            ast::InfixOperator::Booland | ast::InfixOperator::Boolor => unreachable!(),
+        })
+    }
+
+    /// the local variable or array element expr refers to
+    fn lvalue(&mut self, expr: &ast::Expression) -> Result<(ast::Type, &mut Value), Error> {
+        let loc = expr.loc().clone();
+        match expr {
+            ast::Expression::Name(ast::Typed{t: ast::Type::Other(name), ..}) if !name.is_absolute() => {
+                match self.var(&name.to_string()) {
+                    Some((t, v)) => Ok((t.clone(), v)),
+                    None => err(&loc, format!("{} is not a local variable", name)),
+                }
+            }
+            ast::Expression::ArrayAccess{lhs, rhs, ..} => {
+                let index = self.eval(rhs)?.int(&loc)?;
+                match self.lvalue(lhs)? {
+                    (t, Value::Array(a)) => {
+                        let len = a.len();
+                        match usize::try_from(index).ok().and_then(move |i|a.get_mut(i)) {
+                            Some(v) => Ok((t, v)),
+                            None    => err(&loc, format!("index {} is out of bounds of an array of length {}", index, len)),
+                        }
+                    }
+                    _ => err(&loc, "only arrays can be indexed at compile time"),
+                }
+            }
+            _ => err(&loc, "only local variables can be assigned at compile time"),
+        }
+    }
+
+    fn assign(&mut self, lhs: &ast::Expression, v: Value) -> Result<(), Error> {
+        let loc = lhs.loc().clone();
+        let (t, slot) = self.lvalue(lhs)?;
+        *slot = wrap(&t, v, &loc)?;
+        Ok(())
+    }
+
+    fn modify(&mut self, expr: &ast::Expression, by: i128, post: bool) -> Result<Value, Error> {
+        let loc = expr.loc().clone();
+        let old = self.eval(expr)?;
+        let new = Value::Int(old.int(&loc)? + by);
+        self.assign(expr, new)?;
+        if post {
+            Ok(old)
+        } else {
+            self.eval(expr)
+        }
+    }
+
+    pub fn call(&mut self, local: &ast::Local, values: Vec<Value>, loc: &ast::Location) -> Result<Value, Error> {
+        let (args, ret, body) = match &local.def {
+            ast::Def::Function{args, ret, body, ..} => (args, ret, body),
+            _ => return err(loc, format!("{} is not a function", local.name)),
+        };
+        if args.len() != values.len() {
+            return err(loc, format!("{} takes {} arguments, but {} were given", local.name, args.len(), values.len()));
+        }
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return err(loc, format!("compile time evaluation exceeded a depth of {}", MAX_DEPTH));
+        }
+
+        let mut scope = HashMap::new();
+        for (arg, v) in args.iter().zip(values) {
+            if !arg.typed.ptr.is_empty() {
+                return err(&arg.loc, "pointers are not supported at compile time");
+            }
+            scope.insert(arg.name.clone(), (arg.typed.t.clone(), wrap(&arg.typed.t, v, &arg.loc)?));
+        }
+        let scopes = std::mem::replace(&mut self.scopes, vec![scope]);
+        let flow = self.block(body);
+        self.scopes = scopes;
+        self.depth -= 1;
+
+        match (flow?, ret) {
+            (Flow::Return(Some(v)), Some(ret)) => {
+                let len = match &ret.array {
+                    None => return wrap(&ret.typed.t, v, loc),
+                    Some(len) => self.eval(len)?.int(len.loc())?,
+                };
+                match v {
+                    Value::Array(a) if a.len() as i128 == len => wrap_each(&ret.typed.t, Value::Array(a), loc),
+                    Value::Array(a) => err(&body.end, format!("{} returned an array of length {}, but is declared to return {}",
+                                                              local.name, a.len(), len)),
+                    _ => err(&body.end, format!("{} is declared to return an array", local.name)),
+                }
+            }
+            (_, None) => err(loc, format!("{} does not return a value", local.name)),
+            _ => err(&body.end, format!("{} ended without returning a value", local.name)),
+        }
+    }
+
+    fn block(&mut self, block: &ast::Block) -> Result<Flow, Error> {
+        self.scopes.push(HashMap::new());
+        let mut flow = Ok(Flow::Next);
+        for stm in &block.statements {
+            flow = self.statement(stm);
+            match flow {
+                Ok(Flow::Next) => (),
+                _ => break,
+            }
+        }
+        self.scopes.pop();
+        flow
+    }
+
+    fn statement(&mut self, stm: &ast::Statement) -> Result<Flow, Error> {
+        match stm {
+            ast::Statement::Mark{..} | ast::Statement::Label{..} => Ok(Flow::Next),
+            ast::Statement::Expr{expr, loc} => {
+                // proof only, like static_attest
+                if let ast::Expression::Call{name, ..} = expr {
+                    if let ast::Expression::Name(ast::Typed{t: ast::Type::Other(name), ..}) = name.as_ref() {
+                        if let Some(n) = name.0.last() {
+                            if n == "static_attest" || n == "static_assert" {
+                                return Ok(Flow::Next);
+                            }
+                        }
+                    }
+                }
+                self.step(loc)?;
+                self.eval(expr)?;
+                Ok(Flow::Next)
+            }
+            ast::Statement::Assign{lhs, op, rhs, loc} => {
+                self.step(loc)?;
+                let v = self.eval(rhs)?;
+                let op = match op {
+                    ast::AssignOperator::Eq     => None,
+                    ast::AssignOperator::Add    => Some(ast::InfixOperator::Add),
+                    ast::AssignOperator::Sub    => Some(ast::InfixOperator::Subtract),
+                    ast::AssignOperator::Bitor  => Some(ast::InfixOperator::Bitor),
+                    ast::AssignOperator::Bitand => Some(ast::InfixOperator::Bitand),
+                };
+                let v = match op {
+                    None => v,
+                    Some(op) => {
+                        let cur = self.eval(lhs)?.int(loc)?;
+                        self.infix(&op, cur, v.int(loc)?, loc)?
+                    }
+                };
+                self.assign(lhs, v)?;
+                Ok(Flow::Next)
+            }
+            ast::Statement::Var{loc, typed, name, array, assign, ..} => {
+                self.step(loc)?;
+                if !typed.ptr.is_empty() {
+                    return err(loc, "pointers are not supported at compile time");
+                }
+                let len = match array {
+                    Some(Some(len)) => {
+                        let len = self.eval(len)?.int(loc)?;
+                        match usize::try_from(len) {
+                            Ok(len) if len <= MAX_STEPS => Some(len),
+                            _ => return err(loc, format!("array of length {} at compile time", len)),
+                        }
+                    }
+                    _ => None,
+                };
+                let v = match (len, assign) {
+                    // like in c, members without an initializer are zero
+                    (Some(len), Some(assign)) => match self.eval(assign)? {
+                        Value::Array(mut members) if members.len() <= len => {
+                            members.resize(len, wrap(&typed.t, Value::Int(0), loc)?);
+                            Value::Array(members)
+                        }
+                        Value::Array(members) => {
+                            return err(loc, format!("{} initializers for an array of length {}", members.len(), len));
+                        }
+                        v => v,
+                    },
+                    (_, Some(assign)) => self.eval(assign)?,
+                    (Some(len), None) => Value::Array(vec![wrap(&typed.t, Value::Int(0), loc)?; len]),
+                    (None, None) if array.is_some() => return err(loc, "array without length"),
+                    (None, None) => wrap(&typed.t, Value::Int(0), loc)?,
+                };
+                let v = wrap_each(&typed.t, v, loc)?;
+                self.scopes.last_mut().unwrap().insert(name.clone(), (typed.t.clone(), v));
+                Ok(Flow::Next)
+            }
+            ast::Statement::Return{expr, loc} => {
+                self.step(loc)?;
+                match expr {
+                    Some(expr) => Ok(Flow::Return(Some(self.eval(expr)?))),
+                    None => Ok(Flow::Return(None)),
+                }
+            }
+            ast::Statement::Break{..}       => Ok(Flow::Break),
+            ast::Statement::Continue{..}    => Ok(Flow::Continue),
+            ast::Statement::If{branches} => {
+                for (loc, cond, body) in branches {
+                    let taken = match cond {
+                        Some(cond) => self.eval(cond)?.truth(loc)?,
+                        None => true,
+                    };
+                    if taken {
+                        return self.block(body);
+                    }
+                }
+                Ok(Flow::Next)
+            }
+            ast::Statement::While{expr, body} => {
+                let loc = expr.loc().clone();
+                while self.eval(expr)?.truth(&loc)? {
+                    match self.block(body)? {
+                        Flow::Break => break,
+                        Flow::Return(v) => return Ok(Flow::Return(v)),
+                        Flow::Next | Flow::Continue => (),
+                    }
+                }
+                Ok(Flow::Next)
+            }
+            ast::Statement::For{e1, e2, e3, body} => {
+                self.scopes.push(HashMap::new());
+                let r = self.for_loop(e1, e2, e3, body);
+                self.scopes.pop();
+                r
+            }
+            ast::Statement::Switch{loc, expr, cases, default} => {
+                let v = self.eval(expr)?.int(loc)?;
+                for (conds, body) in cases {
+                    for cond in conds {
+                        if self.eval(cond)?.int(loc)? == v {
+                            return match self.block(body)? {
+                                Flow::Break => Ok(Flow::Next),
+                                flow => Ok(flow),
+                            };
+                        }
+                    }
+                }
+                match default {
+                    Some(body) => match self.block(body)? {
+                        Flow::Break => Ok(Flow::Next),
+                        flow => Ok(flow),
+                    },
+                    None => Ok(Flow::Next),
+                }
+            }
+            ast::Statement::Block(body) | ast::Statement::Unsafe(body) => self.block(body),
+            ast::Statement::CBlock{loc, ..} => err(loc, "inline c is not supported at compile time"),
+        }
+    }
+
+    fn for_loop(
+        &mut self,
+        e1:     &[Box<ast::Statement>],
+        e2:     &Option<ast::Expression>,
+        e3:     &[Box<ast::Statement>],
+        body:   &ast::Block,
+    ) -> Result<Flow, Error> {
+        for stm in e1 {
+            self.statement(stm)?;
+        }
+        loop {
+            if let Some(e2) = e2 {
+                if !self.eval(e2)?.truth(e2.loc())? {
+                    break;
+                }
+            }
+            match self.block(body)? {
+                Flow::Break => break,
+                Flow::Return(v) => return Ok(Flow::Return(v)),
+                Flow::Next | Flow::Continue => (),
+            }
+            for stm in e3 {
+                self.statement(stm)?;
+            }
+        }
+        Ok(Flow::Next)
+    }
+}
+
+/// make the names in an expression given to zz eval absolute, like abs does in the body of md:
+/// locals of md first, then names imported into it
+pub fn resolve(expr: &mut ast::Expression, md: &ast::Module) {
+    match expr {
+        ast::Expression::Name(ast::Typed{t: ast::Type::Other(name), ..}) if !name.is_absolute() && name.len() == 1 => {
+            let short = &name.0[0];
+            let found = if md.locals.iter().any(|l| &l.name == short) {
+                Some((md.name.clone(), short.to_string()))
+            } else {
+                md.imports.iter().find_map(|i| i.local.iter().find_map(|(n, alias)| {
+                    (alias.as_ref().unwrap_or(n) == short).then(|| (i.name.clone(), n.clone()))
+                }))
+            };
+            if let Some((mut abs, local)) = found {
+                abs.push(local);
+                *name = abs;
+            }
+        }
+        ast::Expression::Call{name, args, ..} => {
+            resolve(name, md);
+            for arg in args {
+                resolve(arg, md);
+            }
+        }
+        ast::Expression::Infix{lhs, rhs, ..}
+        | ast::Expression::ArrayAccess{lhs, rhs, ..} => {
+            resolve(lhs, md);
+            resolve(rhs, md);
+        }
+        ast::Expression::Cast{expr, ..}
+        | ast::Expression::UnaryPre{expr, ..}
+        | ast::Expression::UnaryPost{expr, ..} => resolve(expr, md),
+        ast::Expression::ArrayInit{fields, ..} => {
+            for field in fields {
+                resolve(field, md);
+            }
+        }
+        _ => (),
+    }
+}
+
+/// replace calls to comptime functions in expr by their result
+fn fold_expr(expr: &mut ast::Expression, md: &ast::Module, modules: &HashMap<Name, loader::Module>) -> Result<(), Error> {
+    let mut it = Interpreter::new(md, modules);
+    if it.callee(expr).is_some() {
+        let loc = expr.loc().clone();
+        let v = it.eval(expr)?;
+        *expr = v.to_expr(&loc)?;
+        return Ok(());
+    }
+    match expr {
+        ast::Expression::Infix{lhs, rhs, ..} => {
+            fold_expr(lhs, md, modules)?;
+            fold_expr(rhs, md, modules)?;
+        }
+        ast::Expression::Cast{expr, ..}
+        | ast::Expression::UnaryPre{expr, ..}
+        | ast::Expression::UnaryPost{expr, ..} => fold_expr(expr, md, modules)?,
+        ast::Expression::ArrayInit{fields, ..} => {
+            for field in fields {
+                fold_expr(field, md, modules)?;
+            }
+        }
+        ast::Expression::StructInit{fields, ..} => {
+            for (_, field) in fields {
+                fold_expr(field, md, modules)?;
+            }
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+/// replace an array size by its value, so it doesn't depend on what the c compiler accepts as a constant.
+/// locals is true in function bodies, where sizes using local variables are left to symbolic execution
+fn fold_size(
+    len:        &mut ast::Expression,
+    locals:     bool,
+    md:         &ast::Module,
+    modules:    &HashMap<Name, loader::Module>,
+) -> Result<(), Error> {
+    let mut it = Interpreter::new(md, modules);
+    if it.opaque(len, locals, 0) {
+        return fold_expr(len, md, modules);
+    }
+    let loc = len.loc().clone();
+    let v = it.eval(len)?.int(&loc)?;
+    if v < 0 {
+        return err(&loc, format!("array size is {}", v));
+    }
+    *len = Value::Int(v).to_expr(&loc)?;
+    Ok(())
+}
+
+/// fold the array sizes of local variables in block
+fn fold_block_sizes(
+    block:      &mut ast::Block,
+    md:         &ast::Module,
+    modules:    &HashMap<Name, loader::Module>,
+) -> Result<(), (ast::Location, Error)> {
+    for stm in &mut block.statements {
+        fold_statement_sizes(stm, md, modules)?;
+    }
+    Ok(())
+}
+
+fn fold_statement_sizes(
+    stm:        &mut ast::Statement,
+    md:         &ast::Module,
+    modules:    &HashMap<Name, loader::Module>,
+) -> Result<(), (ast::Location, Error)> {
+    match stm {
+        ast::Statement::Var{array: Some(Some(len)), ..} => {
+            let at = len.loc().clone();
+            fold_size(len, true, md, modules).map_err(|e| (at, e))?;
+        }
+        ast::Statement::Block(block) | ast::Statement::Unsafe(block) => fold_block_sizes(block, md, modules)?,
+        ast::Statement::While{body, ..} => fold_block_sizes(body, md, modules)?,
+        ast::Statement::For{e1, e3, body, ..} => {
+            for stm in e1.iter_mut().chain(e3.iter_mut()) {
+                fold_statement_sizes(stm, md, modules)?;
+            }
+            fold_block_sizes(body, md, modules)?;
+        }
+        ast::Statement::If{branches} => {
+            for (_, _, body) in branches {
+                fold_block_sizes(body, md, modules)?;
+            }
+        }
+        ast::Statement::Switch{cases, default, ..} => {
+            for (_, body) in cases {
+                fold_block_sizes(body, md, modules)?;
+            }
+            if let Some(default) = default {
+                fold_block_sizes(default, md, modules)?;
+            }
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+/// replace an initializer marked comptime by its value
+fn fold_comptime(
+    expr:       &mut ast::Expression,
+    typed:      &ast::Typed,
+    array:      &Option<Option<ast::Expression>>,
+    md:         &ast::Module,
+    modules:    &HashMap<Name, loader::Module>,
+) -> Result<(), Error> {
+    let mut it = Interpreter::new(md, modules);
+    let loc = expr.loc().clone();
+    let v = wrap_each(&typed.t, it.eval(expr)?, &loc)?;
+    match (&v, array) {
+        (Value::Array(a), Some(Some(len))) => {
+            // array sizes of statics are not absolute, so the check is skipped if len can't be evaluated
+            if let Ok(Value::Int(len)) = it.eval(len) {
+                if a.len() as i128 != len {
+                    return err(&loc, format!("initializer has {} elements, but the array has {}", a.len(), len));
+                }
+            }
+        }
+        (Value::Array(_), Some(None)) => (),
+        (Value::Array(_), None) => return err(&loc, "array initializer for a variable that is not an array"),
+        (_, Some(_)) => return err(&loc, "expected an array"),
+        _ => (),
+    }
+    *expr = v.to_expr(&loc)?;
+    Ok(())
+}
+
+/// evaluate calls to comptime functions in the initializers of constants and statics,
+/// and array sizes of statics, struct fields and local variables
+pub fn fold(md: &mut ast::Module, modules: &HashMap<Name, loader::Module>) {
+    let lookup = md.clone();
+    let mut errors = Vec::new();
+    let mut sizes = Vec::new();
+    for local in &mut md.locals {
+        let r = match &mut local.def {
+            ast::Def::Const{expr, typed, comptime} => {
+                let r = if *comptime {
+                    fold_comptime(expr, typed, &None, &lookup, modules)
+                } else {
+                    fold_expr(expr, &lookup, modules)
+                };
+                r.and_then(|()| Interpreter::new(&lookup, modules).fn_entries(typed, expr))
+            }
+            ast::Def::Static{expr, array, typed, comptime, storage, ..} => {
+                let r = if *comptime {
+                    fold_comptime(expr, typed, array, &lookup, modules)
+                } else {
+                    fold_expr(expr, &lookup, modules)
+                };
+                let r = r.and_then(|()| match storage {
+                    ast::Storage::Const => Interpreter::new(&lookup, modules).fn_entries(typed, expr),
+                    _ => Ok(()),
+                });
+                if let (Some(Some(len)), Ok(())) = (array, &r) {
+                    let at = len.loc().clone();
+                    if let Err(e) = fold_size(len, false, &lookup, modules) {
+                        sizes.push((at, e));
+                    }
+                }
+                r
+            }
+            ast::Def::Struct{fields, ..} => {
+                for field in fields {
+                    if let Some(Some(len)) = &mut field.array {
+                        let at = len.loc().clone();
+                        if let Err(e) = fold_size(len, false, &lookup, modules) {
+                            sizes.push((at, e));
+                        }
+                    }
+                }
+                Ok(())
+            }
+            ast::Def::Function{body, ..} | ast::Def::Test{body, ..} => {
+                if let Err(e) = fold_block_sizes(body, &lookup, modules) {
+                    sizes.push(e);
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        };
+        if let Err(e) = r {
+            errors.push((local.loc.clone(), e));
+        }
+    }
+    for (at, e) in &errors {
+        emit_error("compile time evaluation failed", &[
+            (e.loc.clone(), e.message.clone()),
+            (at.clone(), "while evaluating this".to_string()),
+        ]);
+    }
+    for (at, e) in &sizes {
+        emit_error(msg!("array_size_not_constant"), &[
+            (e.loc.clone(), e.message.clone()),
+            (at.clone(), msg!("array_size_not_constant.note")),
+        ]);
+    }
+    if !errors.is_empty() || !sizes.is_empty() {
+        abort();
+    }
+}
+
+/// remove comptime functions returning arrays. they can't be emitted and are only called at compile time
+pub fn strip(md: &mut ast::Module) {
+    md.locals.retain(|local| match &local.def {
+        ast::Def::Function{attr, ret: Some(ret), ..} => !(attr.contains_key("comptime") && ret.array.is_some()),
+        _ => true,
+    });
+}
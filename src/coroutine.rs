@@ -0,0 +1,891 @@
+/// lowers async fn into a frame struct with a poll method, and gen fn into one with a next method
+
+use super::ast;
+use super::name::Name;
+use super::parser::{self, emit_error};
+use std::collections::{HashMap, HashSet};
+
+/// the state of a frame that has returned
+const DONE : &str = "0xffffffff";
+
+/// names in the frame that aren't locals
+const RESERVED : &[&str] = &["self", "state", "result", "value"];
+
+/// await e and yield e are parsed as calls to these, and for (T x : e) as a for declaring x = RANGE(e)
+pub const AWAIT : &str = "await";
+pub const YIELD : &str = "yield";
+pub const RANGE : &str = ":";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Async,
+    Generator,
+}
+
+pub(crate) fn marker<'a>(expr: &'a ast::Expression, markers: &[&'static str]) -> Option<(&'static str, &'a ast::Expression)> {
+    if let ast::Expression::Call{name, args, ..} = expr {
+        if let ast::Expression::Name(ast::Typed{t: ast::Type::Other(n), ..}) = name.as_ref() {
+            if n.0.len() == 1 && args.len() == 1 {
+                return markers.iter().find(|m| n.0[0] == **m).map(|m| (*m, args[0].as_ref()));
+            }
+        }
+    }
+    None
+}
+
+/// await or yield, and the expression after it, if expr is one
+pub fn suspends(expr: &ast::Expression) -> Option<(&'static str, &ast::Expression)> {
+    marker(expr, &[AWAIT, YIELD])
+}
+
+/// the variable and what it iterates over, if this is the first clause of a for (T x : e)
+pub fn ranged(e1: &[Box<ast::Statement>]) -> Option<(&ast::Statement, &ast::Expression)> {
+    match e1 {
+        [var] => match var.as_ref() {
+            ast::Statement::Var{assign: Some(assign), ..} => marker(assign, &[RANGE]).map(|(_, over)| (var.as_ref(), over)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// the first await or yield in stm
+fn find_suspend(stm: &ast::Statement) -> Option<(&ast::Location, &'static str)> {
+    match stm {
+        ast::Statement::Expr{expr, loc} => suspends(expr).map(|(m, _)| (loc, m)),
+        ast::Statement::While{body, ..} => find_suspend_block(body),
+        ast::Statement::For{e1, e3, body, ..} => {
+            e1.iter().chain(e3.iter()).find_map(|s| find_suspend(s)).or_else(|| find_suspend_block(body))
+        }
+        ast::Statement::If{branches} => branches.iter().find_map(|(_, _, b)| find_suspend_block(b)),
+        ast::Statement::Switch{cases, default, ..} => {
+            cases.iter().map(|(_, b)| b).chain(default.iter()).find_map(|b| find_suspend_block(b))
+        }
+        ast::Statement::Block(b) | ast::Statement::Unsafe(b) => find_suspend_block(b),
+        _ => None,
+    }
+}
+
+fn find_suspend_block(block: &ast::Block) -> Option<(&ast::Location, &'static str)> {
+    block.statements.iter().find_map(|s| find_suspend(s))
+}
+
+fn name_expr(n: &str, loc: &ast::Location) -> ast::Expression {
+    ast::Expression::Name(ast::Typed{
+        t:      ast::Type::Other(Name::from(n)),
+        loc:    loc.clone(),
+        ptr:    Vec::new(),
+        tail:   ast::Tail::None,
+    })
+}
+
+fn member(lhs: ast::Expression, op: &str, rhs: &str, loc: &ast::Location) -> ast::Expression {
+    ast::Expression::MemberAccess{
+        loc:    loc.clone(),
+        lhs:    Box::new(lhs),
+        op:     op.to_string(),
+        rhs:    rhs.to_string(),
+    }
+}
+
+/// self->f
+fn field(f: &str, loc: &ast::Location) -> ast::Expression {
+    member(name_expr("self", loc), "->", f, loc)
+}
+
+fn literal(v: &str, loc: &ast::Location) -> ast::Expression {
+    ast::Expression::literal(loc.clone(), v.to_string())
+}
+
+fn assign(lhs: ast::Expression, rhs: ast::Expression, loc: &ast::Location) -> Box<ast::Statement> {
+    Box::new(ast::Statement::Assign{
+        loc:    loc.clone(),
+        lhs,
+        op:     ast::AssignOperator::Eq,
+        rhs,
+    })
+}
+
+fn block(statements: Vec<Box<ast::Statement>>, loc: &ast::Location) -> ast::Block {
+    ast::Block{
+        end:        loc.clone(),
+        statements,
+        expanded:   false,
+    }
+}
+
+/// a branch body continuing in state to
+fn goto(to: usize, loc: &ast::Location) -> ast::Block {
+    block(vec![
+        assign(field("state", loc), literal(&to.to_string(), loc), loc),
+        Box::new(ast::Statement::Continue{loc: loc.clone()}),
+    ], loc)
+}
+
+/// replace the locals that moved into the frame with self->name
+fn rewrite(expr: &mut ast::Expression, hoisted: &HashSet<String>) {
+    match expr {
+        ast::Expression::Name(typed) => {
+            let moved = match &typed.t {
+                ast::Type::Other(n) if n.0.len() == 1 && hoisted.contains(n.0[0].as_str()) => Some(n.0[0].to_string()),
+                _ => None,
+            };
+            if let Some(n) = moved {
+                let loc = typed.loc.clone();
+                *expr = field(&n, &loc);
+            }
+        }
+        ast::Expression::MemberAccess{lhs, ..} => rewrite(lhs, hoisted),
+        ast::Expression::ArrayAccess{lhs, rhs, ..} | ast::Expression::Infix{lhs, rhs, ..} => {
+            rewrite(lhs, hoisted);
+            rewrite(rhs, hoisted);
+        }
+        ast::Expression::Call{name, args, ..} => {
+            rewrite(name, hoisted);
+            for arg in args {
+                rewrite(arg, hoisted);
+            }
+        }
+        ast::Expression::Cast{expr, ..}
+        | ast::Expression::UnaryPost{expr, ..}
+        | ast::Expression::UnaryPre{expr, ..} => rewrite(expr, hoisted),
+        ast::Expression::StructInit{fields, ..} => {
+            for (_, e) in fields {
+                rewrite(e, hoisted);
+            }
+        }
+        ast::Expression::ArrayInit{fields, ..} => {
+            for e in fields {
+                rewrite(e, hoisted);
+            }
+        }
+        ast::Expression::LiteralString{..}
+        | ast::Expression::LiteralChar{..}
+        | ast::Expression::Literal{..} => (),
+    }
+}
+
+struct Lowering<'a> {
+    kind:       Kind,
+    name:       &'a str,
+    asyncs:     &'a HashSet<String>,
+    args:       HashSet<String>,
+
+    // fields of the frame besides state and result
+    fields:     Vec<ast::Field>,
+    hoisted:    HashSet<String>,
+    frames:     usize,
+
+    // one block of statements per state
+    blocks:     Vec<Vec<Box<ast::Statement>>>,
+    current:    usize,
+    // the current block already jumped or returned
+    done:       bool,
+    // head and exit state of the lowered loops around the current block
+    loops:      Vec<(usize, usize)>,
+    // states that ended up inline in an if
+    inlined:    HashSet<usize>,
+
+    ok:         bool,
+}
+
+impl<'a> Lowering<'a> {
+    fn state(&mut self) -> usize {
+        self.blocks.push(Vec::new());
+        self.blocks.len() - 1
+    }
+
+    fn enter(&mut self, state: usize) {
+        self.current = state;
+        self.done = false;
+    }
+
+    fn push(&mut self, stm: Box<ast::Statement>) {
+        if !self.done {
+            self.blocks[self.current].push(stm);
+        }
+    }
+
+    fn jump(&mut self, to: usize, loc: &ast::Location) {
+        for stm in goto(to, loc).statements {
+            self.push(stm);
+        }
+        self.done = true;
+    }
+
+    /// store the result and finish the frame
+    fn ret(&mut self, expr: Option<ast::Expression>, loc: &ast::Location) -> Vec<Box<ast::Statement>> {
+        let mut v = Vec::new();
+        if let Some(mut expr) = expr {
+            if self.kind == Kind::Generator {
+                self.error(msg!("generator_return_value", name = self.name), loc, msg!("generator_return_value.note"));
+            }
+            rewrite(&mut expr, &self.hoisted);
+            v.push(assign(field("result", loc), expr, loc));
+        }
+        v.push(assign(field("state", loc), literal(DONE, loc), loc));
+        v.push(Box::new(ast::Statement::Return{
+            loc:    loc.clone(),
+            expr:   Some(literal(self.finished(), loc)),
+        }));
+        v
+    }
+
+    /// what poll or next return once the function returned
+    fn finished(&self) -> &'static str {
+        match self.kind {
+            Kind::Async     => "true",
+            Kind::Generator => "false",
+        }
+    }
+
+    fn error(&mut self, message: String, loc: &ast::Location, note: String) {
+        emit_error(message, &[(loc.clone(), note)]);
+        self.ok = false;
+    }
+
+    /// locals live in the frame next to each other, so their names have to be unique in it
+    fn unique(&mut self, name: &str, loc: &ast::Location) -> bool {
+        if RESERVED.contains(&name) || name.starts_with("_await") || self.args.contains(name) || self.hoisted.contains(name) {
+            let message = msg!("async_local_conflict", name = name, function = self.name);
+            self.error(message, loc, msg!("async_local_conflict.note"));
+            return false;
+        }
+        true
+    }
+
+    /// statements of a block that may await or yield
+    fn seq(&mut self, statements: Vec<Box<ast::Statement>>) {
+        for stm in statements {
+            match *stm {
+                ast::Statement::Expr{expr, loc} if suspends(&expr).is_some() => {
+                    let (m, operand) = suspends(&expr).unwrap();
+                    let operand = operand.clone();
+                    match (self.kind, m) {
+                        (Kind::Async, AWAIT) => self.await_(operand, &loc),
+                        (Kind::Generator, YIELD) => self.yield_(operand, &loc),
+                        (Kind::Async, _) => self.error(msg!("yield_outside_generator"), &loc, msg!("yield_outside_generator.note")),
+                        (Kind::Generator, _) => self.error(msg!("await_outside_async"), &loc, msg!("await_outside_async.note")),
+                    }
+                }
+                stm if find_suspend(&stm).is_none() => self.top(stm),
+                ast::Statement::If{branches} => self.lower_if(branches),
+                ast::Statement::While{expr, body} => self.lower_while(expr, body),
+                ast::Statement::Block(b) => self.seq(b.statements),
+                stm => {
+                    let loc = find_suspend(&stm).unwrap().0.clone();
+                    self.error(msg!("async_await_unsupported"), &loc, msg!("async_await_unsupported.note"));
+                }
+            }
+        }
+    }
+
+    /// a statement without await or yield in a block that may have them
+    fn top(&mut self, stm: ast::Statement) {
+        match stm {
+            ast::Statement::Var{loc, typed, name, array, assign: value, ..} => {
+                if !self.unique(&name, &loc) {
+                    return;
+                }
+                if let ast::Type::New | ast::Type::Elided = typed.t {
+                    self.error(msg!("async_local_type", name = name), &loc, msg!("async_local_type.note"));
+                    return;
+                }
+                let init = match &value {
+                    Some(ast::Expression::ArrayInit{..}) => true,
+                    Some(_) => array.is_some(),
+                    None => false,
+                };
+                if init {
+                    self.error(msg!("async_array_init", name = name), &loc, msg!("async_array_init.note"));
+                    return;
+                }
+                self.fields.push(ast::Field{
+                    typed,
+                    name:   name.clone(),
+                    array,
+                    bits:   None,
+                    range:  None,
+                    default: None,
+                    tags:   ast::Tags::new(),
+                    loc:    loc.clone(),
+                });
+                self.hoisted.insert(name.clone());
+                if let Some(mut value) = value {
+                    rewrite(&mut value, &self.hoisted);
+                    self.push(assign(field(&name, &loc), value, &loc));
+                }
+            }
+            ast::Statement::Return{loc, expr} => {
+                for stm in self.ret(expr, &loc) {
+                    self.push(stm);
+                }
+                self.done = true;
+            }
+            stm => {
+                let ends = match &stm {
+                    ast::Statement::Break{..} | ast::Statement::Continue{..} => !self.loops.is_empty(),
+                    _ => false,
+                };
+                let stm = self.plain(stm, false);
+                self.push(stm);
+                if ends {
+                    self.done = true;
+                }
+            }
+        }
+    }
+
+    /// check in every poll if awaited is ready, and stop until it is
+    fn await_(&mut self, mut awaited: ast::Expression, loc: &ast::Location) {
+        rewrite(&mut awaited, &self.hoisted);
+        let callee = match &awaited {
+            ast::Expression::Call{name, ..} => match name.as_ref() {
+                ast::Expression::Name(ast::Typed{t: ast::Type::Other(n), ..})
+                    if n.0.len() == 1 && self.asyncs.contains(n.0[0].as_str()) => Some(n.0[0].to_string()),
+                _ => None,
+            },
+            _ => None,
+        };
+        let poll = match (awaited, callee) {
+            (ast::Expression::Call{loc: callloc, args, ..}, Some(callee)) => {
+                if callee == self.name {
+                    self.error(msg!("async_recursive", name = callee), &callloc, msg!("async_recursive.note"));
+                }
+                // the callee runs in a frame inside this one, started over on every await
+                let frame = format!("_await{}", self.frames);
+                self.frames += 1;
+                self.fields.push(ast::Field{
+                    typed:  ast::Typed{
+                        t:      ast::Type::Other(Name::from(&callee)),
+                        loc:    callloc.clone(),
+                        ptr:    Vec::new(),
+                        tail:   ast::Tail::None,
+                    },
+                    name:   frame.clone(),
+                    array:  None,
+                    bits:   None,
+                    range:  None,
+                    default: None,
+                    tags:   ast::Tags::new(),
+                    loc:    callloc.clone(),
+                });
+                self.push(assign(member(field(&frame, loc), ".", "state", loc), literal("0", loc), loc));
+                ast::Expression::Call{
+                    loc:        callloc.clone(),
+                    name:       Box::new(member(field(&frame, loc), ".", "poll", &callloc)),
+                    args,
+                    expanded:   false,
+                    emit:       ast::EmitBehaviour::Default,
+                }
+            }
+            (call @ ast::Expression::Call{..}, None) => call,
+            (pollable, _) => {
+                let loc = pollable.loc().clone();
+                ast::Expression::Call{
+                    loc:        loc.clone(),
+                    name:       Box::new(member(pollable, ".", "poll", &loc)),
+                    args:       Vec::new(),
+                    expanded:   false,
+                    emit:       ast::EmitBehaviour::Default,
+                }
+            }
+        };
+
+        let next = self.state();
+        self.jump(next, loc);
+        self.enter(next);
+        let pending = ast::Expression::UnaryPre{
+            loc:    loc.clone(),
+            op:     ast::PrefixOperator::Boolnot,
+            expr:   Box::new(poll),
+        };
+        self.push(Box::new(ast::Statement::If{
+            branches: vec![(loc.clone(), Some(pending), block(vec![Box::new(ast::Statement::Return{
+                loc:    loc.clone(),
+                expr:   Some(literal("false", loc)),
+            })], loc))],
+        }));
+    }
+
+    /// hand out the value and continue from here on the next call
+    fn yield_(&mut self, mut value: ast::Expression, loc: &ast::Location) {
+        rewrite(&mut value, &self.hoisted);
+        self.push(assign(field("value", loc), value, loc));
+        let next = self.state();
+        self.push(assign(field("state", loc), literal(&next.to_string(), loc), loc));
+        self.push(Box::new(ast::Statement::Return{
+            loc:    loc.clone(),
+            expr:   Some(literal("true", loc)),
+        }));
+        self.done = true;
+        self.enter(next);
+    }
+
+    /// every branch of the if starts inline, so what its condition proves holds there,
+    /// and continues in the states its awaits or yields lead to
+    fn lower_if(&mut self, branches: Vec<(ast::Location, Option<ast::Expression>, ast::Block)>) {
+        let join = self.state();
+        let (outer, outer_done) = (self.current, self.done);
+        let mut lowered = Vec::new();
+        let mut otherwise = false;
+        for (loc, mut cond, body) in branches {
+            match &mut cond {
+                Some(cond) => rewrite(cond, &self.hoisted),
+                None => otherwise = true,
+            }
+            // lowered into a state of its own, which is then moved into the branch
+            let start = self.state();
+            self.enter(start);
+            self.seq(body.statements);
+            self.jump(join, &body.end);
+            let statements = std::mem::take(&mut self.blocks[start]);
+            self.inlined.insert(start);
+            lowered.push((loc, cond, block(statements, &body.end)));
+        }
+        if !otherwise {
+            let loc = lowered[lowered.len() - 1].0.clone();
+            lowered.push((loc.clone(), None, goto(join, &loc)));
+        }
+        self.current = outer;
+        self.done = outer_done;
+        self.push(Box::new(ast::Statement::If{branches: lowered}));
+        self.done = true;
+        self.enter(join);
+    }
+
+    /// the loop checks its condition at the start of the state the body starts in,
+    /// which the end of the body jumps back to
+    fn lower_while(&mut self, mut expr: ast::Expression, body: ast::Block) {
+        let head    = self.state();
+        let exit    = self.state();
+        let loc     = expr.loc().clone();
+
+        self.jump(head, &loc);
+        self.enter(head);
+        rewrite(&mut expr, &self.hoisted);
+        let over = ast::Expression::UnaryPre{
+            loc:    loc.clone(),
+            op:     ast::PrefixOperator::Boolnot,
+            expr:   Box::new(expr),
+        };
+        self.push(Box::new(ast::Statement::If{
+            branches: vec![(loc.clone(), Some(over), goto(exit, &loc))],
+        }));
+
+        self.loops.push((head, exit));
+        self.seq(body.statements);
+        self.jump(head, &body.end);
+        self.loops.pop();
+        self.enter(exit);
+    }
+
+    /// a statement without await, with locals of the frame replaced and jumps out of lowered loops
+    fn plain(&mut self, stm: ast::Statement, in_loop: bool) -> Box<ast::Statement> {
+        Box::new(match stm {
+            ast::Statement::Var{loc, typed, tags, name, mut array, mut assign} => {
+                if RESERVED.contains(&name.as_str()) || self.args.contains(&name) || self.hoisted.contains(&name) {
+                    let message = msg!("async_local_conflict", name = name, function = self.name);
+                    self.error(message, &loc, msg!("async_local_conflict.note"));
+                }
+                if let Some(Some(size)) = &mut array {
+                    rewrite(size, &self.hoisted);
+                }
+                if let Some(value) = &mut assign {
+                    rewrite(value, &self.hoisted);
+                }
+                ast::Statement::Var{loc, typed, tags, name, array, assign}
+            }
+            ast::Statement::Return{loc, expr} => {
+                ast::Statement::Block(Box::new(block(self.ret(expr, &loc), &loc)))
+            }
+            ast::Statement::Break{loc} if !in_loop && !self.loops.is_empty() => {
+                let (_, exit) = self.loops[self.loops.len() - 1];
+                ast::Statement::Block(Box::new(goto(exit, &loc)))
+            }
+            ast::Statement::Continue{loc} if !in_loop && !self.loops.is_empty() => {
+                let (head, _) = self.loops[self.loops.len() - 1];
+                ast::Statement::Block(Box::new(goto(head, &loc)))
+            }
+            ast::Statement::Assign{loc, mut lhs, op, mut rhs} => {
+                rewrite(&mut lhs, &self.hoisted);
+                rewrite(&mut rhs, &self.hoisted);
+                ast::Statement::Assign{loc, lhs, op, rhs}
+            }
+            ast::Statement::Expr{loc, mut expr} => {
+                rewrite(&mut expr, &self.hoisted);
+                ast::Statement::Expr{loc, expr}
+            }
+            ast::Statement::Mark{loc, mut lhs, key, value} => {
+                rewrite(&mut lhs, &self.hoisted);
+                ast::Statement::Mark{loc, lhs, key, value}
+            }
+            ast::Statement::Switch{loc, mut expr, cases, default} => {
+                rewrite(&mut expr, &self.hoisted);
+                let cases = cases.into_iter().map(|(mut conds, body)| {
+                    for cond in &mut conds {
+                        rewrite(cond, &self.hoisted);
+                    }
+                    (conds, self.plain_block(body, in_loop))
+                }).collect();
+                let default = default.map(|body| self.plain_block(body, in_loop));
+                ast::Statement::Switch{loc, expr, cases, default}
+            }
+            ast::Statement::While{mut expr, body} => {
+                rewrite(&mut expr, &self.hoisted);
+                ast::Statement::While{expr, body: self.plain_block(body, true)}
+            }
+            ast::Statement::For{e1, mut e2, e3, body} => {
+                let e1 = e1.into_iter().map(|s| self.plain(*s, in_loop)).collect();
+                if let Some(e2) = &mut e2 {
+                    rewrite(e2, &self.hoisted);
+                }
+                let e3 = e3.into_iter().map(|s| self.plain(*s, in_loop)).collect();
+                ast::Statement::For{e1, e2, e3, body: self.plain_block(body, true)}
+            }
+            ast::Statement::If{branches} => {
+                let branches = branches.into_iter().map(|(loc, mut cond, body)| {
+                    if let Some(cond) = &mut cond {
+                        rewrite(cond, &self.hoisted);
+                    }
+                    (loc, cond, self.plain_block(body, in_loop))
+                }).collect();
+                ast::Statement::If{branches}
+            }
+            ast::Statement::Block(b) => ast::Statement::Block(Box::new(self.plain_block(*b, in_loop))),
+            ast::Statement::Unsafe(b) => ast::Statement::Unsafe(Box::new(self.plain_block(*b, in_loop))),
+            stm => stm,
+        })
+    }
+
+    fn plain_block(&mut self, b: ast::Block, in_loop: bool) -> ast::Block {
+        ast::Block{
+            end:        b.end,
+            statements: b.statements.into_iter().map(|s| self.plain(*s, in_loop)).collect(),
+            expanded:   b.expanded,
+        }
+    }
+}
+
+/// the frame struct and its poll or next method for an async fn or generator
+fn lower_fn(local: ast::Local, kind: Kind, asyncs: &HashSet<String>) -> Option<(ast::Local, ast::Local)> {
+    let (nameloc, ret, args, hints, mut attr, body, vararg, callassert, calleffect, callattests) = match local.def {
+        ast::Def::Function{nameloc, ret, args, hints, attr, body, vararg, callassert, calleffect, callattests} => {
+            (nameloc, ret, args, hints, attr, body, vararg, callassert, calleffect, callattests)
+        }
+        _ => unreachable!(),
+    };
+    let loc = local.loc.clone();
+    let (keyword, method, slot) = match kind {
+        Kind::Async     => ("async", "poll", "result"),
+        Kind::Generator => ("gen", "next", "value"),
+    };
+
+    if local.name.contains("::") {
+        emit_error(msg!("async_method", name = local.name, what = keyword), &[(nameloc, msg!("async_method.note"))]);
+        return None;
+    }
+    let unsupported = if vararg {
+        Some("variable arguments")
+    } else if !calleffect.is_empty() {
+        Some("a model")
+    } else if ret.as_ref().map(|r| r.array.is_some()).unwrap_or(false) {
+        Some("an array return type")
+    } else if kind == Kind::Generator && ret.is_none() {
+        Some("no return type, the type it yields")
+    } else {
+        None
+    };
+    if let Some(what) = unsupported {
+        emit_error(msg!("async_signature", kind = keyword, name = local.name, what = what), &[(nameloc, msg!("here"))]);
+        return None;
+    }
+
+    let mut l = Lowering{
+        kind,
+        name:       &local.name,
+        asyncs,
+        args:       args.iter().map(|a| a.name.clone()).collect(),
+        fields:     Vec::new(),
+        hoisted:    HashSet::new(),
+        frames:     0,
+        blocks:     Vec::new(),
+        current:    0,
+        done:       false,
+        loops:      Vec::new(),
+        inlined:    HashSet::new(),
+        ok:         true,
+    };
+    if let Some(arg) = args.iter().find(|a| RESERVED.contains(&a.name.as_str())) {
+        let message = msg!("async_local_conflict", name = arg.name, function = local.name);
+        l.error(message, &arg.loc, msg!("async_local_conflict.note"));
+    }
+
+    let entry = l.state();
+    l.enter(entry);
+    l.seq(body.statements);
+    for stm in l.ret(None, &body.end) {
+        l.push(stm);
+    }
+    if !l.ok {
+        return None;
+    }
+    let done = l.finished();
+
+    let u32_t = ast::Typed{t: ast::Type::U32, loc: loc.clone(), ptr: Vec::new(), tail: ast::Tail::None};
+    let mut fields = vec![ast::Field{
+        typed:  u32_t,
+        name:   "state".to_string(),
+        array:  None,
+        bits:   None,
+        range:  None,
+        default: None,
+        tags:   ast::Tags::new(),
+        loc:    loc.clone(),
+    }];
+    if let Some(ret) = &ret {
+        fields.push(ast::Field{
+            typed:  ret.typed.clone(),
+            name:   slot.to_string(),
+            array:  None,
+            bits:   None,
+            range:  None,
+            default: None,
+            tags:   ast::Tags::new(),
+            loc:    loc.clone(),
+        });
+    }
+    fields.extend(l.fields);
+
+    // while true { switch self->state { 0 => { .. } 1 => { .. } default => { return true; } } }
+    let inlined = l.inlined;
+    let cases = l.blocks.into_iter().enumerate().filter(|(i, _)| !inlined.contains(i)).map(|(i, statements)| {
+        (vec![literal(&i.to_string(), &loc)], block(statements, &loc))
+    }).collect();
+    let finished = || Box::new(ast::Statement::Return{loc: loc.clone(), expr: Some(literal(done, &loc))});
+    let switch = ast::Statement::Switch{
+        loc:        loc.clone(),
+        expr:       field("state", &loc),
+        cases,
+        default:    Some(block(vec![finished()], &loc)),
+    };
+    let body = block(vec![
+        Box::new(ast::Statement::While{
+            expr:   literal("true", &loc),
+            body:   block(vec![Box::new(switch)], &loc),
+        }),
+        finished(),
+    ], &body.end);
+
+    let mut mutable = ast::Tags::new();
+    mutable.insert("mut".to_string(), String::new(), loc.clone());
+    let mut method_args = vec![ast::NamedArg{
+        typed:  ast::Typed{
+            t:      ast::Type::Other(Name::from(&local.name)),
+            loc:    loc.clone(),
+            ptr:    vec![ast::Pointer{loc: loc.clone(), tags: mutable}],
+            tail:   ast::Tail::None,
+        },
+        name:   "self".to_string(),
+        tags:   ast::Tags::new(),
+        loc:    loc.clone(),
+    }];
+    method_args.extend(args);
+    attr.remove(keyword);
+
+    let method = ast::Local{
+        name:   format!("{}::{}", local.name, method),
+        vis:    local.vis.clone(),
+        loc:    loc.clone(),
+        doc:    String::new(),
+        def:    ast::Def::Function{
+            nameloc,
+            ret:            Some(ast::AnonArg{
+                typed:  ast::Typed{t: ast::Type::Bool, loc: loc.clone(), ptr: Vec::new(), tail: ast::Tail::None},
+                array:  None,
+                tags:   ast::Tags::new(),
+            }),
+            args:           method_args,
+            hints,
+            attr,
+            body,
+            vararg:         false,
+            callassert,
+            calleffect:     Vec::new(),
+            callattests,
+        },
+    };
+    let frame = ast::Local{
+        name:   local.name,
+        vis:    local.vis,
+        loc,
+        doc:    local.doc,
+        def:    ast::Def::Struct{
+            fields,
+            packed: false,
+            tail:   ast::Tail::None,
+            union:  false,
+            impls:  HashMap::new(),
+            abi:    None,
+        },
+    };
+    Some((frame, method))
+}
+
+/// for (T x : g(args)) { .. } as
+///   { g mut _range0 = {0}; while _range0.next(args) { T x = _range0.value; .. } }
+fn lower_range(stm: ast::Statement, n: &mut usize) -> ast::Statement {
+    let (e1, body) = match stm {
+        ast::Statement::For{e1, body, ..} => (e1, body),
+        _ => unreachable!(),
+    };
+    let (loc, typed, tags, name, over) = match e1.into_iter().next().map(|s| *s) {
+        Some(ast::Statement::Var{loc, typed, tags, name, assign: Some(ast::Expression::Call{mut args, ..}), ..}) => {
+            (loc, typed, tags, name, *args.remove(0))
+        }
+        _ => unreachable!(),
+    };
+    let (generator, args) = match over {
+        ast::Expression::Call{name, args, ..} => match *name {
+            ast::Expression::Name(ast::Typed{t: ast::Type::Other(g), ..}) => (g, args),
+            other => {
+                emit_error(msg!("range_not_generator"), &[(other.loc().clone(), msg!("range_not_generator.note"))]);
+                parser::abort();
+            }
+        },
+        other => {
+            emit_error(msg!("range_not_generator"), &[(other.loc().clone(), msg!("range_not_generator.note"))]);
+            parser::abort();
+        }
+    };
+
+    let frame = format!("_range{}", n);
+    *n += 1;
+    let mut mutable = ast::Tags::new();
+    mutable.insert("mut".to_string(), String::new(), loc.clone());
+    let init = ast::Statement::Var{
+        loc:    loc.clone(),
+        typed:  ast::Typed{
+            t:      ast::Type::Other(generator),
+            loc:    loc.clone(),
+            ptr:    Vec::new(),
+            tail:   ast::Tail::None,
+        },
+        tags:   mutable,
+        name:   frame.clone(),
+        array:  None,
+        assign: Some(ast::Expression::ArrayInit{loc: loc.clone(), fields: vec![Box::new(literal("0", &loc))], index: Vec::new()}),
+    };
+    let next = ast::Expression::Call{
+        loc:        loc.clone(),
+        name:       Box::new(member(name_expr(&frame, &loc), ".", "next", &loc)),
+        args,
+        expanded:   false,
+        emit:       ast::EmitBehaviour::Default,
+    };
+    let mut statements = vec![Box::new(ast::Statement::Var{
+        loc:    loc.clone(),
+        typed,
+        tags,
+        name,
+        array:  None,
+        assign: Some(member(name_expr(&frame, &loc), ".", "value", &loc)),
+    })];
+    statements.extend(body.statements);
+    let body = ast::Block{
+        end:        body.end,
+        statements,
+        expanded:   body.expanded,
+    };
+    ast::Statement::Block(Box::new(block(vec![
+        Box::new(init),
+        Box::new(ast::Statement::While{expr: next, body}),
+    ], &loc)))
+}
+
+fn lower_ranges(body: &mut ast::Block, n: &mut usize) {
+    for stm in &mut body.statements {
+        match stm.as_mut() {
+            ast::Statement::While{body, ..} | ast::Statement::For{body, ..} => lower_ranges(body, n),
+            ast::Statement::If{branches} => {
+                for (_, _, body) in branches {
+                    lower_ranges(body, n);
+                }
+            }
+            ast::Statement::Switch{cases, default, ..} => {
+                for (_, body) in cases {
+                    lower_ranges(body, n);
+                }
+                if let Some(body) = default {
+                    lower_ranges(body, n);
+                }
+            }
+            ast::Statement::Block(b) | ast::Statement::Unsafe(b) => lower_ranges(b, n),
+            _ => (),
+        }
+        let range = match stm.as_ref() {
+            ast::Statement::For{e1, ..} => ranged(e1).is_some(),
+            _ => false,
+        };
+        if range {
+            let old = std::mem::replace(stm.as_mut(), ast::Statement::Block(Box::new(block(Vec::new(), &ast::Location::builtin()))));
+            **stm = lower_range(old, n);
+        }
+    }
+}
+
+/// lower every for (T x : g()), and replace every async fn and generator of md with its frame and method
+pub fn lower(md: &mut ast::Module) {
+    let mut n = 0;
+    for local in &mut md.locals {
+        match &mut local.def {
+            ast::Def::Function{body, ..} | ast::Def::Test{body, ..} => lower_ranges(body, &mut n),
+            _ => (),
+        }
+    }
+
+    let kind = |local: &ast::Local| match &local.def {
+        ast::Def::Function{attr, ..} if attr.contains_key("async") => Some(Kind::Async),
+        ast::Def::Function{attr, ..} if attr.contains_key("gen") => Some(Kind::Generator),
+        _ => None,
+    };
+    let asyncs : HashSet<String> = md.locals.iter()
+        .filter(|local| kind(local) == Some(Kind::Async))
+        .map(|local| local.name.clone())
+        .collect();
+
+    let mut ok = true;
+    for local in &md.locals {
+        let body = match &local.def {
+            ast::Def::Function{body, ..} if kind(local).is_none() => body,
+            ast::Def::Test{body, ..} => body,
+            _ => continue,
+        };
+        match find_suspend_block(body) {
+            Some((loc, AWAIT)) => emit_error(msg!("await_outside_async"), &[(loc.clone(), msg!("await_outside_async.note"))]),
+            Some((loc, _)) => emit_error(msg!("yield_outside_generator"), &[(loc.clone(), msg!("yield_outside_generator.note"))]),
+            None => continue,
+        }
+        ok = false;
+    }
+
+    for local in std::mem::take(&mut md.locals) {
+        let k = match kind(&local) {
+            Some(k) => k,
+            None => {
+                md.locals.push(local);
+                continue;
+            }
+        };
+        match lower_fn(local, k, &asyncs) {
+            Some((frame, method)) => {
+                md.locals.push(frame);
+                md.locals.push(method);
+            }
+            None => ok = false,
+        }
+    }
+    if !ok {
+        parser::abort();
+    }
+}
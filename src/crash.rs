@@ -0,0 +1,159 @@
+/// crash bundles for internal compiler errors
+
+use std::cell::RefCell;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+thread_local! {
+    // the innermost selfprofile pass and the last source file of this thread
+    static PASS:    RefCell<Option<&'static str>> = RefCell::new(None);
+    static SOURCE:  RefCell<Option<PathBuf>>      = RefCell::new(None);
+}
+
+static WRITTEN : AtomicBool = AtomicBool::new(false);
+
+/// restores the pass that was current before enter_pass on drop
+pub struct PassGuard(Option<&'static str>);
+
+impl Drop for PassGuard {
+    fn drop(&mut self) {
+        let prev = self.0.take();
+        PASS.with(|p| *p.borrow_mut() = prev);
+    }
+}
+
+pub fn enter_pass(name: &'static str) -> PassGuard {
+    PassGuard(PASS.with(|p| p.replace(Some(name))))
+}
+
+/// the source file this thread works on, it becomes repro.zz
+pub fn working_on(path: &Path) {
+    SOURCE.with(|s| *s.borrow_mut() = Some(path.to_path_buf()));
+}
+
+/// replace the default panic output with one that also writes a crash bundle
+pub fn install() {
+    let default = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        default(info);
+        if WRITTEN.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let message = match (info.payload().downcast_ref::<&str>(), info.payload().downcast_ref::<String>()) {
+            (Some(s), _) => s.to_string(),
+            (None, Some(s)) => s.clone(),
+            (None, None) => "unknown panic".to_string(),
+        };
+        let location = info.location().map(|l| format!("{}:{}", l.file(), l.line())).unwrap_or_default();
+        match write(&message, &location) {
+            Ok(dir) => {
+                error!("{}", msg!("crash_bundle", path = dir.display()));
+                error!("{}", msg!("crash_bundle.note", url = concat!(env!("CARGO_PKG_REPOSITORY"), "/issues")));
+            }
+            Err(e) => error!("{}", msg!("crash_bundle_failed", error = e)),
+        }
+    }));
+}
+
+fn write(message: &str, location: &str) -> std::io::Result<PathBuf> {
+    let root = super::project::find_root_cwd().unwrap_or_else(|| PathBuf::from("."));
+    let time = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let dir = root.join("target").join("crash").join(time.to_string());
+    std::fs::create_dir_all(&dir)?;
+
+    let pass = PASS.with(|p| *p.borrow());
+    let source = SOURCE.with(|s| s.borrow().clone());
+
+    let mut r = String::new();
+    writeln!(r, "zz {}", env!("CARGO_PKG_VERSION")).ok();
+    writeln!(r, "os: {} {}", std::env::consts::OS, std::env::consts::ARCH).ok();
+    writeln!(r, "command: {}", std::env::args().collect::<Vec<_>>().join(" ")).ok();
+    for var in &["CC", "CXX", "TARGET_CC", "CFLAGS", "TARGET_CFLAGS", "LDFLAGS", "ZZ_CACHE"] {
+        if let Ok(v) = std::env::var(var) {
+            writeln!(r, "{}={}", var, v).ok();
+        }
+    }
+    writeln!(r, "pass: {}", pass.unwrap_or("none")).ok();
+    if let Some(source) = &source {
+        writeln!(r, "source: {}", source.display()).ok();
+    }
+    writeln!(r, "thread: {}", std::thread::current().name().unwrap_or("unnamed")).ok();
+    writeln!(r, "panic: {}", message).ok();
+    writeln!(r, "at: {}", location).ok();
+    writeln!(r, "\n{}", std::backtrace::Backtrace::force_capture()).ok();
+    std::fs::write(dir.join("report.txt"), r)?;
+
+    if let Some(source) = source {
+        if let Ok(s) = std::fs::read_to_string(&source) {
+            std::fs::write(dir.join("repro.zz"), strip(&s))?;
+        }
+    }
+    if let Ok(config) = std::fs::read_to_string(root.join("zz.toml")) {
+        std::fs::write(dir.join("zz.toml"), config)?;
+    }
+    Ok(dir)
+}
+
+/// source without comments, trailing whitespace and blank lines.
+/// string and char literals are kept as they are
+fn strip(source: &str) -> String {
+    let mut out = String::new();
+    let mut chars = source.chars().peekable();
+    let mut in_string = None;
+    let mut in_block = false;
+    while let Some(c) = chars.next() {
+        if in_block {
+            if c == '*' && chars.peek() == Some(&'/') {
+                chars.next();
+                in_block = false;
+            } else if c == '\n' {
+                out.push('\n');
+            }
+            continue;
+        }
+        if let Some(quote) = in_string {
+            out.push(c);
+            if c == '\\' {
+                if let Some(n) = chars.next() {
+                    out.push(n);
+                }
+            } else if c == quote || c == '\n' {
+                in_string = None;
+            }
+            continue;
+        }
+        match (c, chars.peek()) {
+            ('"', _) | ('\'', _) => {
+                in_string = Some(c);
+                out.push(c);
+            }
+            ('/', Some('/')) => {
+                while let Some(n) = chars.peek() {
+                    if *n == '\n' {
+                        break;
+                    }
+                    chars.next();
+                }
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                in_block = true;
+            }
+            _ => out.push(c),
+        }
+    }
+
+    let mut r = String::new();
+    for line in out.lines() {
+        let line = line.trim_end();
+        if !line.is_empty() {
+            r.push_str(line);
+            r.push('\n');
+        }
+    }
+    r
+}
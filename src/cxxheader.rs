@@ -0,0 +1,111 @@
+/// export headers that c++ can include
+
+use super::ast;
+use super::loader;
+use super::make;
+use super::name::Name;
+use super::parser::emit_error;
+use std::collections::HashMap;
+use std::process::Command;
+
+/// reserved in c++ but not in c
+const KEYWORDS : &[&str] = &[
+    "alignas", "alignof", "and", "and_eq", "asm", "bitand", "bitor", "bool", "catch", "char8_t", "char16_t",
+    "char32_t", "class", "co_await", "co_return", "co_yield", "compl", "concept", "consteval", "constexpr",
+    "constinit", "const_cast", "decltype", "delete", "dynamic_cast", "explicit", "export", "false", "friend",
+    "mutable", "namespace", "new", "noexcept", "not", "not_eq", "nullptr", "operator", "or", "or_eq",
+    "private", "protected", "public", "reinterpret_cast", "requires", "static_assert", "static_cast",
+    "template", "this", "thread_local", "throw", "true", "try", "typeid", "typename", "using", "virtual",
+    "wchar_t", "xor", "xor_eq",
+];
+
+pub fn is_keyword(name: &str) -> bool {
+    KEYWORDS.contains(&name)
+}
+
+/// an argument name that a c++ compiler accepts
+pub fn arg_name(name: &str) -> String {
+    if is_keyword(name) {
+        format!("{}_", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// check the export structs of the project for what a c++ header can't declare. returns false on errors
+pub fn check(modules: &HashMap<Name, loader::Module>, names: &[Name], project: &str) -> bool {
+    let mut ok = true;
+    for name in names {
+        if name.0[1] != project {
+            continue;
+        }
+        let md = match modules.get(name) {
+            Some(loader::Module::ZZ(md)) => md,
+            _ => continue,
+        };
+        for local in &md.locals {
+            if local.vis != ast::Visibility::Export {
+                continue;
+            }
+            let fields = match &local.def {
+                ast::Def::Struct{fields, ..} => fields,
+                _ => continue,
+            };
+            if fields.is_empty() {
+                emit_error(msg!("cxx_empty_struct", name = local.name), &[
+                    (local.loc.clone(), msg!("cxx_empty_struct.note")),
+                ]);
+                ok = false;
+            }
+            for field in fields {
+                if is_keyword(&field.name) {
+                    emit_error(msg!("cxx_keyword_field", name = local.name, field = field.name), &[
+                        (field.loc.clone(), msg!("cxx_keyword_field.note")),
+                    ]);
+                    ok = false;
+                }
+            }
+        }
+    }
+    ok
+}
+
+/// the flags of the c compiler that decide what a header sees
+fn header_flags(cflags: &[String]) -> Vec<String> {
+    let mut r = Vec::new();
+    let mut it = cflags.iter();
+    while let Some(flag) = it.next() {
+        if flag == "-I" {
+            if let Some(dir) = it.next() {
+                r.push(flag.clone());
+                r.push(dir.clone());
+            }
+        } else if flag.starts_with("-I") || flag.starts_with("-D") || flag.starts_with("--sysroot=")
+            || flag.starts_with("--target=") || flag == "-m32" {
+            r.push(flag.clone());
+        }
+    }
+    r
+}
+
+/// compile a header as c++ without generating anything. exits if the compiler rejects it
+pub fn validate(make: &make::Make, header: &str) {
+    let cxx = make::env_cc(true);
+    let mut args = vec!["-x".to_string(), "c++".to_string(), "-fsyntax-only".to_string()];
+    args.extend(header_flags(&make.cflags));
+    args.push(header.to_string());
+
+    let output = match Command::new(&cxx).args(&args).output() {
+        Ok(output) => output,
+        Err(e) => {
+            error!("{}", msg!("cxx_header_compiler", cxx = cxx, error = e));
+            std::process::exit(9);
+        }
+    };
+    if !output.status.success() {
+        eprint!("{}", String::from_utf8_lossy(&output.stderr));
+        error!("{}", msg!("cxx_header_invalid", path = header));
+        error!("{} {}", cxx, args.join(" "));
+        std::process::exit(11);
+    }
+}
@@ -0,0 +1,76 @@
+/// default field values
+
+use super::ast;
+use super::loader;
+use super::macros::{Visit, walk_block, walk_expr};
+use super::name::Name;
+use std::collections::HashMap;
+
+/// the fields with a default of every struct, by absolute name
+#[derive(Default)]
+pub struct Defaults(HashMap<Name, Vec<(String, ast::Expression)>>);
+
+impl Defaults {
+    pub fn collect(modules: &HashMap<Name, loader::Module>) -> Defaults {
+        let mut defaults = Defaults::default();
+        for (_, md) in modules {
+            let md = match md {
+                loader::Module::ZZ(md) => md,
+                loader::Module::C(_) => continue,
+            };
+            for local in &md.locals {
+                if let ast::Def::Struct{fields, ..} = &local.def {
+                    let fields : Vec<(String, ast::Expression)> = fields.iter()
+                        .filter_map(|f| f.default.as_ref().map(|d| (f.name.clone(), d.clone())))
+                        .collect();
+                    if !fields.is_empty() {
+                        let mut name = md.name.clone();
+                        name.push(local.name.clone());
+                        defaults.0.insert(name, fields);
+                    }
+                }
+            }
+        }
+        defaults
+    }
+}
+
+impl<'a> Visit for &'a Defaults {
+    fn expr(&mut self, expr: &mut ast::Expression) {
+        walk_expr(self, expr);
+        if let ast::Expression::StructInit{typed: ast::Typed{t: ast::Type::Other(name), ptr, ..}, fields, ..} = expr {
+            if !ptr.is_empty() {
+                return;
+            }
+            for (field, default) in self.0.get(&*name).into_iter().flatten() {
+                if !fields.iter().any(|(f, _)| f == field) {
+                    fields.push((field.clone(), Box::new(default.clone())));
+                }
+            }
+        }
+    }
+}
+
+/// add the defaults of the fields every struct initializer in md leaves out
+pub fn expand(md: &mut ast::Module, defaults: &Defaults) {
+    if defaults.0.is_empty() {
+        return;
+    }
+    let mut v = defaults;
+    for local in &mut md.locals {
+        match &mut local.def {
+            ast::Def::Static{expr, ..} | ast::Def::Const{expr, ..} => {
+                v.expr(expr);
+            }
+            ast::Def::Function{body, ..} | ast::Def::Test{body, ..} => {
+                walk_block(&mut v, body);
+            }
+            ast::Def::Testcase{fields} => {
+                for (_, expr) in fields {
+                    v.expr(expr);
+                }
+            }
+            _ => (),
+        }
+    }
+}
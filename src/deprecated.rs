@@ -0,0 +1,136 @@
+/// deprecated declarations
+
+use super::ast;
+use super::loader;
+use super::name::Name;
+use super::parser::emit_warn;
+use super::uses::{self, Use};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// all deprecated declarations, by absolute name
+#[derive(Default)]
+pub struct Deprecated(HashMap<Name, (ast::Deprecation, ast::Location)>);
+
+impl Deprecated {
+    pub fn collect(modules: &HashMap<Name, loader::Module>) -> Deprecated {
+        let mut deprecated = Deprecated::default();
+        for (_, md) in modules {
+            let md = match md {
+                loader::Module::ZZ(md) => md,
+                loader::Module::C(_) => continue,
+            };
+            for local in &md.locals {
+                if let Some(d) = md.deprecated.get(&local.name) {
+                    let mut name = md.name.clone();
+                    name.push(local.name.clone());
+                    deprecated.0.insert(name, (d.clone(), local.loc.clone()));
+                }
+            }
+        }
+        deprecated
+    }
+
+    /// all uses of deprecated names in md, in source order. a declaration may use itself
+    pub fn uses(&self, md: &ast::Module) -> Vec<Use> {
+        if self.0.is_empty() {
+            return Vec::new();
+        }
+        uses::uses(md, &|name| self.0.contains_key(name))
+            .into_iter()
+            .filter(|u| u.name != u.by)
+            .collect()
+    }
+
+    /// warn at every use in md
+    pub fn check(&self, md: &ast::Module) {
+        for u in self.uses(md) {
+            let (d, declared) = &self.0[&u.name];
+            emit_warn(msg!("deprecated_use", name = u.name, message = d.message), &[
+                (u.loc.clone(), msg!("deprecated_use.note")),
+                (declared.clone(), msg!("deprecated_use.declared")),
+            ]);
+        }
+    }
+}
+
+/// the byte range of the last :: segment of a written name
+fn last_segment(written: &str) -> (usize, usize) {
+    let start = written.rfind("::").map(|i| i + 2).unwrap_or(0);
+    (start, written.len())
+}
+
+/// the first occurrence of word in s that is not part of a longer identifier
+fn find_word(s: &str, word: &str) -> Option<usize> {
+    let ident = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut from = 0;
+    while let Some(i) = s[from..].find(word).map(|i| i + from) {
+        let before = s[..i].chars().next_back().map(ident).unwrap_or(false);
+        let after  = s[i + word.len()..].chars().next().map(ident).unwrap_or(false);
+        if !before && !after {
+            return Some(i);
+        }
+        from = i + word.len();
+    }
+    None
+}
+
+/// zz fix: write the replacement over uses and imports of deprecated names in md.
+/// returns the edits as (file, start, end, text)
+pub fn fixes(deprecated: &Deprecated, md: &ast::Module) -> Vec<(PathBuf, usize, usize, String)> {
+    let mut r = Vec::new();
+    for u in deprecated.uses(md) {
+        let replace = match &deprecated.0[&u.name].0.replace {
+            Some(v) => v,
+            None => continue,
+        };
+        // an imported alias keeps working once the import is fixed
+        let written = u.loc.text();
+        let (start, end) = last_segment(&written);
+        if &written[start..end] != u.name.0.last().unwrap() {
+            continue;
+        }
+        let at = u.loc.start;
+        r.push((PathBuf::from(&*u.loc.file.name()), at + start, at + end, replace.clone()));
+    }
+
+    for import in &md.imports {
+        for (local, _) in &import.local {
+            let mut name = import.name.clone();
+            name.push(local.clone());
+            let replace = match deprecated.0.get(&name).and_then(|(d, _)| d.replace.as_ref()) {
+                Some(v) => v,
+                None => continue,
+            };
+            let written = import.loc.text();
+            let open = match written.find('{') {
+                Some(v) => v,
+                None => continue,
+            };
+            if let Some(i) = find_word(&written[open..], local) {
+                let at = import.loc.start + open + i;
+                r.push((PathBuf::from(&*import.loc.file.name()), at, at + local.len(), replace.clone()));
+            }
+        }
+    }
+    r
+}
+
+/// apply edits from fixes to the files. returns the number of changes
+pub fn apply(mut edits: Vec<(PathBuf, usize, usize, String)>) -> Result<usize, String> {
+    edits.sort();
+    edits.dedup();
+
+    let mut n = 0;
+    let mut files : Vec<PathBuf> = edits.iter().map(|e| e.0.clone()).collect();
+    files.dedup();
+    for file in files {
+        let mut src = std::fs::read_to_string(&file).map_err(|e|format!("{}: {}", file.display(), e))?;
+        for (_, start, end, text) in edits.iter().rev().filter(|e| e.0 == file) {
+            src.replace_range(*start..*end, text);
+            n += 1;
+        }
+        std::fs::write(&file, src).map_err(|e|format!("{}: {}", file.display(), e))?;
+    }
+    Ok(n)
+}
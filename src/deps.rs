@@ -0,0 +1,185 @@
+/// dependencies by path and git, and the zz.lock they are resolved with
+
+use serde::{Serialize, Deserialize};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+pub enum Source {
+    Search,
+    Path(PathBuf),
+    Git {
+        url:    String,
+        rev:    Option<String>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct Locked {
+    pub name:   String,
+    pub git:    String,
+    // as written in zz.toml, a change re-resolves the dependency
+    pub rev:    Option<String>,
+    pub commit: String,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Lock {
+    #[serde(default, rename = "package")]
+    pub packages:   Vec<Locked>,
+    #[serde(skip)]
+    changed:        bool,
+}
+
+impl Source {
+    pub fn parse(name: &str, v: &toml::Value) -> Source {
+        match v {
+            toml::Value::String(_) => Source::Search,
+            toml::Value::Table(t) => {
+                let s = |k: &str| t.get(k).and_then(|v|v.as_str()).map(|v|v.to_string());
+                if let Some(path) = s("path") {
+                    Source::Path(PathBuf::from(path))
+                } else if let Some(url) = s("git") {
+                    Source::Git{url, rev: s("rev")}
+                } else {
+                    error!("dependency \"{}\" needs either path or git", name);
+                    std::process::exit(9);
+                }
+            }
+            _ => {
+                error!("dependency \"{}\" must be a version string or a table", name);
+                std::process::exit(9);
+            }
+        }
+    }
+}
+
+impl Lock {
+    pub fn load(root: &Path) -> Lock {
+        let s = match std::fs::read_to_string(root.join("zz.lock")) {
+            Ok(s) => s,
+            Err(_) => return Lock::default(),
+        };
+        match toml::from_str(&s) {
+            Ok(lock) => lock,
+            Err(e) => {
+                error!("cannot read {:?}: {}", root.join("zz.lock"), e);
+                std::process::exit(9);
+            }
+        }
+    }
+
+    pub fn store(&mut self, root: &Path) {
+        if !self.changed {
+            return;
+        }
+        self.packages.sort_by(|a, b| a.name.cmp(&b.name));
+        let s = toml::to_string(self).unwrap();
+        std::fs::write(root.join("zz.lock"), s).expect("cannot write zz.lock");
+        self.changed = false;
+    }
+
+    /// forget all locked commits, so the next build resolves them again
+    pub fn clear(&mut self) {
+        self.packages.clear();
+        self.changed = true;
+    }
+
+    fn get(&self, name: &str, url: &str, rev: &Option<String>) -> Option<&Locked> {
+        self.packages.iter().find(|l| l.name == name && l.git == url && &l.rev == rev)
+    }
+
+    fn set(&mut self, locked: Locked) {
+        self.packages.retain(|l| l.name != locked.name);
+        self.packages.push(locked);
+        self.changed = true;
+    }
+}
+
+/// directory git dependencies are checked out into
+pub fn cache_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("ZZ_CACHE") {
+        return PathBuf::from(dir);
+    }
+    match std::env::var_os("HOME") {
+        Some(home) => PathBuf::from(home).join(".cache").join("zz"),
+        None => std::env::temp_dir().join("zz-cache"),
+    }
+}
+
+fn git(args: &[&str], dir: &Path) -> Result<String, String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .map_err(|e| format!("cannot execute git: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("git {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// resolve rev to a commit in a bare mirror of url
+fn resolve(mirror: &Path, url: &str, rev: &Option<String>) -> Result<String, String> {
+    if !mirror.exists() {
+        std::fs::create_dir_all(mirror.parent().unwrap()).map_err(|e| e.to_string())?;
+        // a url or rev starting with - would otherwise be read as an option
+        git(&["clone", "--quiet", "--bare", "--", url, &mirror.to_string_lossy()], mirror.parent().unwrap())?;
+    } else {
+        git(&["fetch", "--quiet", "--force", "--tags", "--", url, "+refs/heads/*:refs/heads/*"], mirror)?;
+    }
+    let rev = rev.as_ref().map(|v|v.as_str()).unwrap_or("HEAD");
+    git(&["rev-parse", "--verify", "--end-of-options", &format!("{}^{{commit}}", rev)], mirror)
+}
+
+/// the checkout of dependency name from url, at the locked commit or rev
+pub fn fetch(name: &str, url: &str, rev: &Option<String>, lock: &mut Lock) -> PathBuf {
+    let cache   = cache_dir().join("git");
+    let key     = super::cache::hash_bytes(url.as_bytes());
+    let mirror  = cache.join("db").join(format!("{}-{}", name, &key[..16]));
+
+    let commit = match lock.get(name, url, rev) {
+        Some(locked) => locked.commit.clone(),
+        None => {
+            info!("fetching {} from {}", name, url);
+            let commit = match resolve(&mirror, url, rev) {
+                Ok(v) => v,
+                Err(e) => {
+                    error!("dependency \"{}\": {}", name, e);
+                    std::process::exit(9);
+                }
+            };
+            lock.set(Locked{
+                name:   name.to_string(),
+                git:    url.to_string(),
+                rev:    rev.clone(),
+                commit: commit.clone(),
+            });
+            commit
+        }
+    };
+
+    let checkout = cache.join("checkouts").join(format!("{}-{}", name, &commit[..commit.len().min(12)]));
+    if checkout.join("zz.toml").exists() {
+        return checkout;
+    }
+
+    // a locked commit may not be in the mirror yet, on a new machine
+    // checking out what rev-parse resolved, never the text of the lock file, which could be an option
+    let r = resolve(&mirror, url, &Some(commit.clone())).and_then(|resolved| {
+        if checkout.exists() {
+            std::fs::remove_dir_all(&checkout).map_err(|e| e.to_string())?;
+        }
+        std::fs::create_dir_all(&checkout).map_err(|e| e.to_string())?;
+        git(&["clone", "--quiet", "--shared", "--no-checkout", &mirror.to_string_lossy(), "."], &checkout)?;
+        git(&["checkout", "--quiet", "--detach", &resolved], &checkout)
+    });
+    if let Err(e) = r {
+        error!("dependency \"{}\": {}", name, e);
+        std::process::exit(9);
+    }
+    if !checkout.join("zz.toml").exists() {
+        error!("dependency \"{}\": {} has no zz.toml at {}", name, url, commit);
+        std::process::exit(9);
+    }
+    checkout
+}
@@ -0,0 +1,124 @@
+/// code generation plugins
+
+use super::ast;
+use super::edition::Edition;
+use super::flags;
+use super::make::Stage;
+use super::parser::{self, emit_error};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+#[derive(Serialize)]
+struct Input<'a> {
+    module:         String,
+    declaration:    &'a ast::Local,
+}
+
+/// run a plugin on local. returns the generated source, or the error for the user
+fn run(command: &[String], root: &Path, input: &str) -> Result<String, String> {
+    let mut child = Command::new(&command[0])
+        .args(&command[1..])
+        .current_dir(root)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("{}: {}", command[0], e))?;
+    // written from a thread, a plugin that writes before it has read everything would block on a full pipe otherwise
+    let mut stdin = child.stdin.take().unwrap();
+    let input = input.to_string();
+    let writer = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let out = child.wait_with_output().map_err(|e| format!("{}: {}", command[0], e))?;
+    if !out.status.success() {
+        return Err(format!("{} exited with {}\n{}", command[0], out.status, String::from_utf8_lossy(&out.stderr)));
+    }
+    writer.join().unwrap().map_err(|e| format!("{}: {}", command[0], e))?;
+    String::from_utf8(out.stdout).map_err(|_| format!("{} wrote invalid utf8", command[0]))
+}
+
+/// derives that come with the compiler. a plugin of the same name in zz.toml replaces them
+fn builtin(name: &str) -> Option<fn(&ast::Local) -> Result<String, String>> {
+    match name {
+        "flags" => Some(flags::derive),
+        _ => None,
+    }
+}
+
+/// run the plugins of every @derive in md and add what they generate to it
+pub fn expand(
+    md:         &mut ast::Module,
+    plugins:    &HashMap<String, Vec<String>>,
+    root:       &Path,
+    features:   &HashMap<String, bool>,
+    stage:      &Stage,
+    edition:    Edition,
+) {
+    if md.derive.is_empty() {
+        return;
+    }
+    let dir = root.join("target").join(stage.to_string()).join("derive");
+    std::fs::create_dir_all(&dir).expect("create target dir");
+
+    let mut generated = Vec::new();
+    for local in &md.locals {
+        let derive = match md.derive.get(&local.name) {
+            Some(v) => v,
+            None => continue,
+        };
+        for plugin in &derive.plugins {
+            let output = match (plugins.get(plugin), builtin(plugin)) {
+                (Some(command), _) if !command.is_empty() => {
+                    let input = serde_json::to_string(&Input{
+                        module:         md.name.to_string(),
+                        declaration:    local,
+                    }).expect("serialize declaration");
+                    run(command, root, &input)
+                }
+                (_, Some(generate)) => generate(local),
+                _ => {
+                    emit_error(msg!("derive_unknown", name = plugin), &[
+                        (derive.loc.clone(), msg!("derive_unknown.note", name = plugin)),
+                    ]);
+                    parser::abort();
+                }
+            };
+            let src = match output {
+                Ok(v) => v,
+                Err(e) => {
+                    emit_error(msg!("derive_failed", name = plugin), &[
+                        (derive.loc.clone(), e),
+                    ]);
+                    parser::abort();
+                }
+            };
+
+            let stem = md.source.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+            let path = dir.join(format!("{}_{}_{}.zz", stem, local.name.replace("::", "_"), plugin.replace("::", "_")));
+            std::fs::write(&path, src).expect(&format!("cannot write {}", path.display()));
+            generated.push(path);
+        }
+    }
+
+    for path in generated {
+        splice(md, &path, features, stage, edition);
+    }
+}
+
+/// parse a generated file and add its declarations and imports to md
+pub fn splice(
+    md:         &mut ast::Module,
+    path:       &Path,
+    features:   &HashMap<String, bool>,
+    stage:      &Stage,
+    edition:    Edition,
+) {
+    let g = parser::parse(path, features, stage, edition);
+    md.imports.extend(g.imports);
+    md.locals.extend(g.locals);
+    md.deprecated.extend(g.deprecated);
+    md.unstable.extend(g.unstable);
+    md.sources.extend(g.sources);
+}
@@ -0,0 +1,180 @@
+/// rendering of errors and warnings for the terminal
+
+use super::ast::Location;
+use super::messages;
+use super::sourcemap;
+use std::io::IsTerminal;
+use std::path::Path;
+
+#[derive(Clone, Copy)]
+pub enum Level {
+    Error,
+    Warning,
+    Debug,
+}
+
+impl Level {
+    fn name(&self) -> &'static str {
+        match self {
+            Level::Error    => "error",
+            Level::Warning  => "warning",
+            Level::Debug    => "debug",
+        }
+    }
+
+    fn color(&self) -> &'static str {
+        match self {
+            Level::Error    => "\x1b[1;31m",
+            Level::Warning  => "\x1b[1;33m",
+            Level::Debug    => "\x1b[1;36m",
+        }
+    }
+}
+
+const BLUE  : &str = "\x1b[1;34m";
+const BOLD  : &str = "\x1b[1m";
+const RESET : &str = "\x1b[0m";
+
+pub fn colors() -> bool {
+    match std::env::var("ZZ_COLOR").as_ref().map(|s| s.as_str()) {
+        Ok("always")    => return true,
+        Ok("never")     => return false,
+        _               => (),
+    }
+    std::env::var_os("NO_COLOR").is_none() && std::io::stderr().is_terminal()
+}
+
+struct Painter {
+    on: bool,
+}
+
+impl Painter {
+    fn paint(&self, color: &str, s: &str) -> String {
+        if self.on {
+            format!("{}{}{}", color, s, RESET)
+        } else {
+            s.to_string()
+        }
+    }
+}
+
+/// the path of a source file as the user would type it, relative to the current directory if it's below it
+fn display_path(file: &str) -> String {
+    let path = Path::new(file);
+    std::env::current_dir().ok()
+        .and_then(|cwd| path.strip_prefix(cwd).ok().map(|p| p.to_string_lossy().to_string()))
+        .unwrap_or_else(|| file.to_string())
+}
+
+/// line number line of file, without the newline. files that weren't parsed are read from disk
+fn source_line(file: &str, line: usize) -> String {
+    if let Some(id) = sourcemap::find(file) {
+        return sourcemap::line(id, line);
+    }
+    std::fs::read_to_string(file).ok()
+        .and_then(|src| src.lines().nth(line.saturating_sub(1)).map(String::from))
+        .unwrap_or_default()
+}
+
+/// one source line with a label under the columns from col to col + width
+struct Frame {
+    file:       String,
+    line:       usize,
+    col:        usize,
+    text:       String,
+    width:      usize,
+    label:      String,
+    primary:    bool,
+}
+
+impl Frame {
+    fn new(loc: &Location, label: String, primary: bool) -> Self {
+        let (line, col)     = loc.line_col();
+        let (eline, ecol)   = loc.end_line_col();
+        let text = sourcemap::line(loc.file, line);
+        let width = if eline == line {
+            ecol.saturating_sub(col)
+        } else {
+            text.chars().count().saturating_sub(col - 1)
+        };
+        Frame {
+            file:   display_path(&loc.file.name()),
+            line,
+            col,
+            text,
+            width:  width.max(1),
+            label,
+            primary,
+        }
+    }
+
+    fn render(&self, p: &Painter, level: Level, gutter: usize, s: &mut String) {
+        let pad = " ".repeat(gutter);
+        s.push_str(&format!("{}{} {}:{}:{}\n", pad, p.paint(BLUE, "-->"), self.file, self.line, self.col));
+        s.push_str(&format!("{} {}\n", pad, p.paint(BLUE, "|")));
+        s.push_str(&format!("{} {} {}\n",
+            p.paint(BLUE, &format!("{:>w$}", self.line, w = gutter)),
+            p.paint(BLUE, "|"),
+            self.text,
+        ));
+
+        // keep tabs, so the marks line up under the code
+        let indent : String = self.text.chars().take(self.col - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let mark = if self.primary { "^" } else { "-" };
+        let color = if self.primary { level.color() } else { BLUE };
+        let marks = p.paint(color, &mark.repeat(self.width));
+        let label = if self.primary { p.paint(BOLD, &self.label) } else { self.label.clone() };
+        s.push_str(&format!("{} {} {}{} {}\n", pad, p.paint(BLUE, "|"), indent, marks, label));
+    }
+}
+
+fn header(p: &Painter, level: Level, message: &str) -> String {
+    let name = match messages::code_of(message) {
+        Some(code) => format!("{}[{}]", level.name(), code),
+        None => level.name().to_string(),
+    };
+    format!("{}{}\n", p.paint(level.color(), &name), p.paint(BOLD, &format!(": {}", message)))
+}
+
+/// a diagnostic with labels on the source it is about
+pub fn render(level: Level, message: &str, labels: &[(&Location, String)]) -> String {
+    let p = Painter{on: colors()};
+
+    let mut frames = Vec::new();
+    for (i, (loc, label)) in labels.iter().enumerate() {
+        frames.push(Frame::new(loc, label.clone(), i == 0));
+        for expansion in loc.expansions() {
+            frames.push(Frame::new(&expansion.call, messages::format("expanded_from_macro", &[
+                ("name", expansion.name.to_string()),
+            ]), false));
+        }
+    }
+    let gutter = frames.iter().map(|f| f.line.to_string().len()).max().unwrap_or(1);
+
+    let mut s = header(&p, level, message);
+    for frame in &frames {
+        frame.render(&p, level, gutter, &mut s);
+    }
+    s
+}
+
+/// a syntax error from the grammar, reported at line and col of file
+pub fn render_syntax(file: &Path, line: usize, col: usize, label: &str) -> String {
+    let p = Painter{on: colors()};
+    let message = messages::format("syntax_error", &[]);
+    let file = file.to_string_lossy();
+    let frame = Frame {
+        file:       display_path(&file),
+        line,
+        col:        col.max(1),
+        text:       source_line(&file, line),
+        width:      1,
+        label:      label.to_string(),
+        primary:    true,
+    };
+    let mut s = header(&p, Level::Error, &message);
+    frame.render(&p, Level::Error, line.to_string().len(), &mut s);
+    s
+}
@@ -0,0 +1,294 @@
+/// differences between two versions of a source file, by declaration instead of by line
+
+use super::ast;
+use super::edition::Edition;
+use super::fmt;
+use super::make;
+use super::parser;
+use super::tokens::{self, Kind};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Change {
+    /// added, removed, renamed, moved, signature or changed
+    pub change:     &'static str,
+    /// fn, struct, enum, const, and so on
+    pub kind:       &'static str,
+    pub name:       String,
+    /// the name before, if it was renamed
+    pub old_name:   Option<String>,
+    pub old_line:   Option<usize>,
+    pub new_line:   Option<usize>,
+    /// the signature before and after, for signature changes
+    pub old:        Option<String>,
+    pub new:        Option<String>,
+    /// lines of a changed declaration starting with "- ", "+ " or "  ", "..." where unchanged lines are left out
+    pub lines:      Vec<String>,
+}
+
+struct Decl {
+    kind:   &'static str,
+    name:   String,
+    line:   usize,
+    /// the name as printed, methods without their struct
+    base:   String,
+    sig:    String,
+    body:   String,
+}
+
+fn kind(def: &ast::Def) -> &'static str {
+    match def {
+        ast::Def::Function{..}                      => "fn",
+        ast::Def::Struct{union: true, ..}           => "union",
+        ast::Def::Struct{..}                        => "struct",
+        ast::Def::Enum{..}                          => "enum",
+        ast::Def::Const{..}                         => "const",
+        ast::Def::Static{..}                        => "static",
+        ast::Def::TypeAlias{..}                     => "type",
+        ast::Def::Fntype{..}                        => "fntype",
+        ast::Def::Theory{..}                        => "theory",
+        ast::Def::Closure{..}                       => "closure",
+        ast::Def::Macro{..}                         => "macro",
+        ast::Def::Test{..}                          => "test",
+        ast::Def::Testcase{..}                      => "testcase",
+        ast::Def::Include{..}                       => "include",
+    }
+}
+
+fn load(path: &Path) -> Vec<Decl> {
+    let src = match std::fs::read_to_string(path) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("{}: {}", path.display(), e);
+            std::process::exit(9);
+        }
+    };
+    // like zz fmt, the oldest edition accepts all syntax
+    let md = parser::parse(path, &HashMap::new(), &make::Stage::test(), Edition::E2020);
+    fmt::declarations(&md, &src).into_iter().map(|(local, text)| {
+        // the body of a fn starts at its first brace, everything else is all signature
+        let (sig, body) = match (&local.def, text.find('{')) {
+            (ast::Def::Function{..}, Some(at)) => (text[..at].trim_end().to_string(), text[at..].to_string()),
+            _ => (text.trim_end().to_string(), String::new()),
+        };
+        Decl {
+            kind:   kind(&local.def),
+            name:   local.name.clone(),
+            line:   local.loc.line(),
+            base:   local.name.rsplit("::").next().unwrap_or(&local.name).to_string(),
+            sig,
+            body,
+        }
+    }).collect()
+}
+
+/// text with every identifier renamed
+fn renamed(text: &str, renames: &[(String, String)]) -> String {
+    tokens::tokenize(text).into_iter().map(|t| {
+        match renames.iter().find(|(from, _)| t.kind == Kind::Ident && from == t.text) {
+            Some((_, to)) => to.as_str(),
+            None => t.text,
+        }
+    }).collect()
+}
+
+/// the lines of a and b marked as removed, added or kept, with one kept line around changes
+fn lines(a: &str, b: &str) -> Vec<String> {
+    let a : Vec<&str> = a.lines().collect();
+    let b : Vec<&str> = b.lines().collect();
+
+    // longest common subsequence, from the back
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+        }
+    }
+    let mut marked = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            marked.push(("  ", a[i]));
+            i += 1;
+            j += 1;
+        } else if j < b.len() && (i == a.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            marked.push(("+ ", b[j]));
+            j += 1;
+        } else {
+            marked.push(("- ", a[i]));
+            i += 1;
+        }
+    }
+
+    let changed = |k: usize| marked.get(k).map(|m| m.0 != "  ").unwrap_or(false);
+    let mut r = Vec::new();
+    let mut skipped = false;
+    for (k, (mark, line)) in marked.iter().enumerate() {
+        if *mark != "  " || changed(k + 1) || (k > 0 && changed(k - 1)) {
+            if skipped && !r.is_empty() {
+                r.push("...".to_string());
+            }
+            skipped = false;
+            r.push(format!("{}{}", mark, line));
+        } else {
+            skipped = true;
+        }
+    }
+    r
+}
+
+/// the positions in seq that are not part of its longest increasing subsequence
+fn out_of_order(seq: &[usize]) -> Vec<usize> {
+    let mut best : Vec<usize> = vec![1; seq.len()];
+    let mut prev : Vec<Option<usize>> = vec![None; seq.len()];
+    for i in 0..seq.len() {
+        for j in 0..i {
+            if seq[j] < seq[i] && best[j] + 1 > best[i] {
+                best[i] = best[j] + 1;
+                prev[i] = Some(j);
+            }
+        }
+    }
+    let mut keep = vec![false; seq.len()];
+    let mut at = (0..seq.len()).max_by_key(|i| (best[*i], std::cmp::Reverse(*i)));
+    while let Some(i) = at {
+        keep[i] = true;
+        at = prev[i];
+    }
+    (0..seq.len()).filter(|i| !keep[*i]).collect()
+}
+
+fn change(change: &'static str, decl: &Decl) -> Change {
+    Change {
+        change,
+        kind:       decl.kind,
+        name:       decl.name.clone(),
+        old_name:   None,
+        old_line:   None,
+        new_line:   None,
+        old:        None,
+        new:        None,
+        lines:      Vec::new(),
+    }
+}
+
+pub fn diff(a: &Path, b: &Path) -> Vec<Change> {
+    let old = load(a);
+    let new = load(b);
+
+    // same kind and name first
+    let mut pairs : Vec<(usize, usize)> = Vec::new();
+    let mut gone : Vec<usize> = Vec::new();
+    for (i, o) in old.iter().enumerate() {
+        match new.iter().position(|n| n.kind == o.kind && n.name == o.name) {
+            Some(j) => pairs.push((i, j)),
+            None => gone.push(i),
+        }
+    }
+    let mut fresh : Vec<usize> = (0..new.len()).filter(|j| !pairs.iter().any(|p| p.1 == *j)).collect();
+
+    // then renames, a declaration that is gone and a new one that are the same under the new name
+    let mut renames : Vec<(String, String)> = Vec::new();
+    let mut changes = Vec::new();
+    for i in std::mem::take(&mut gone) {
+        let o = &old[i];
+        let found = fresh.iter().position(|j| {
+            let n = &new[*j];
+            let mut with = renames.clone();
+            with.push((o.base.clone(), n.base.clone()));
+            n.kind == o.kind && renamed(&o.sig, &with) == n.sig && renamed(&o.body, &with) == n.body
+        });
+        match found {
+            Some(at) => {
+                let j = fresh.remove(at);
+                renames.push((old[i].base.clone(), new[j].base.clone()));
+                let mut c = change("renamed", &new[j]);
+                c.old_name = Some(o.name.clone());
+                c.old_line = Some(o.line);
+                c.new_line = Some(new[j].line);
+                changes.push(c);
+                pairs.push((i, j));
+            }
+            None => gone.push(i),
+        }
+    }
+
+    pairs.sort();
+    let moved = out_of_order(&pairs.iter().map(|p| p.1).collect::<Vec<_>>());
+    for (k, (i, j)) in pairs.iter().enumerate() {
+        let (o, n) = (&old[*i], &new[*j]);
+        if moved.contains(&k) {
+            let mut c = change("moved", n);
+            c.old_line = Some(o.line);
+            c.new_line = Some(n.line);
+            changes.push(c);
+        }
+        let sig = renamed(&o.sig, &renames);
+        let body = renamed(&o.body, &renames);
+        if o.kind == "fn" && sig != n.sig {
+            let mut c = change("signature", n);
+            c.old_line = Some(o.line);
+            c.new_line = Some(n.line);
+            c.old = Some(sig.clone());
+            c.new = Some(n.sig.clone());
+            changes.push(c);
+        }
+        let (before, after) = if o.kind == "fn" { (body, &n.body) } else { (sig, &n.sig) };
+        if &before != after {
+            let mut c = change("changed", n);
+            c.old_line = Some(o.line);
+            c.new_line = Some(n.line);
+            c.lines = lines(&before, after);
+            changes.push(c);
+        }
+    }
+    for i in gone {
+        let mut c = change("removed", &old[i]);
+        c.old_line = Some(old[i].line);
+        changes.push(c);
+    }
+    for j in fresh {
+        let mut c = change("added", &new[j]);
+        c.new_line = Some(new[j].line);
+        changes.push(c);
+    }
+    changes.sort_by_key(|c| (c.new_line.or(c.old_line), c.old_line));
+    changes
+}
+
+pub fn main(a: &Path, b: &Path, json: bool) {
+    let changes = diff(a, b);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&changes).unwrap());
+    } else {
+        for c in &changes {
+            let name = match &c.old_name {
+                Some(old) => format!("{} {} -> {}", c.kind, old, c.name),
+                None => format!("{} {}", c.kind, c.name),
+            };
+            let at = match (c.old_line, c.new_line) {
+                (Some(o), Some(n)) => format!("{} -> {}", o, n),
+                (Some(o), None) => o.to_string(),
+                (None, Some(n)) => n.to_string(),
+                (None, None) => String::new(),
+            };
+            println!("{:<9} {:<36} {}", c.change, name, at);
+            if let (Some(old), Some(new)) = (&c.old, &c.new) {
+                for line in old.lines() {
+                    println!("    - {}", line);
+                }
+                for line in new.lines() {
+                    println!("    + {}", line);
+                }
+            }
+            for line in &c.lines {
+                println!("    {}", line);
+            }
+        }
+    }
+    if !changes.is_empty() {
+        std::process::exit(1);
+    }
+}
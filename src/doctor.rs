@@ -0,0 +1,237 @@
+/// zz doctor. checks what a build needs from the system, and says how to fix what is missing
+
+use super::deps;
+use super::diagnostics;
+use super::make;
+use super::project::{self, Config};
+use std::path::Path;
+use std::process::Command;
+
+const GREEN     : &str = "\x1b[1;32m";
+const YELLOW    : &str = "\x1b[1;33m";
+const RED       : &str = "\x1b[1;31m";
+const RESET     : &str = "\x1b[0m";
+
+struct Report {
+    color:  bool,
+    errors: usize,
+}
+
+impl Report {
+    fn line(&self, color: &str, level: &str, text: &str) {
+        let level = format!("{:<8}", level);
+        if self.color {
+            println!("{}{}{} {}", color, level, RESET, text);
+        } else {
+            println!("{} {}", level, text);
+        }
+    }
+
+    fn ok(&mut self, what: String, detail: String) {
+        if detail.is_empty() {
+            self.line(GREEN, &msg!("doctor.ok"), &what);
+        } else {
+            self.line(GREEN, &msg!("doctor.ok"), &format!("{}: {}", what, detail));
+        }
+    }
+
+    fn warn(&mut self, what: String, why: String, fix: String) {
+        self.line(YELLOW, &msg!("doctor.warning"), &format!("{}: {}", what, why));
+        println!("{:<8} {}: {}", "", msg!("doctor.fix"), fix);
+    }
+
+    fn error(&mut self, what: String, why: String, fix: String) {
+        self.errors += 1;
+        self.line(RED, &msg!("doctor.error"), &format!("{}: {}", what, why));
+        println!("{:<8} {}: {}", "", msg!("doctor.fix"), fix);
+    }
+}
+
+/// the first line bin prints for --version
+fn version(bin: &str) -> Result<String, String> {
+    let out = Command::new(bin).arg("--version").output().map_err(|e| e.to_string())?;
+    let text = if out.stdout.is_empty() { out.stderr } else { out.stdout };
+    Ok(String::from_utf8_lossy(&text).lines().next().unwrap_or("").trim().to_string())
+}
+
+/// run a command, with its error output if it fails
+fn run(cmd: &mut Command) -> Result<(), String> {
+    let out = cmd.output().map_err(|e| e.to_string())?;
+    if out.status.success() {
+        Ok(())
+    } else {
+        Err(String::from_utf8_lossy(&out.stderr).lines().next().unwrap_or("").trim().to_string())
+    }
+}
+
+/// whether dir can be created and written to
+fn writable(dir: &Path) -> Result<(), String> {
+    std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    let probe = dir.join(".zz-doctor");
+    std::fs::write(&probe, b"").map_err(|e| e.to_string())?;
+    std::fs::remove_file(&probe).map_err(|e| e.to_string())
+}
+
+/// compile and link an empty program with cc
+fn compiler(r: &mut Report, cc: &str, sysroot: Option<&str>) {
+    let what = msg!("doctor_cc", cc = cc);
+    let found = match which::which(cc) {
+        Ok(_)   => version(cc),
+        Err(e)  => Err(e.to_string()),
+    };
+    match found {
+        Ok(v) => r.ok(what, v),
+        Err(_) => {
+            r.error(what, msg!("doctor_cc_missing", cc = cc), msg!("doctor_cc_missing.fix"));
+            return;
+        }
+    }
+
+    let dir = match tempdir::TempDir::new("zzdoctor") {
+        Ok(dir) => dir,
+        Err(e) => {
+            r.error(msg!("doctor_linker", cc = cc), e.to_string(), msg!("doctor_tmp.fix"));
+            return;
+        }
+    };
+    let src = dir.path().join("doctor.c");
+    if let Err(e) = std::fs::write(&src, "int main() { return 0; }\n") {
+        r.error(msg!("doctor_linker", cc = cc), e.to_string(), msg!("doctor_tmp.fix"));
+        return;
+    }
+    let obj = dir.path().join("doctor.o");
+    let sysroot : Vec<String> = sysroot.iter().map(|s| format!("--sysroot={}", s)).collect();
+
+    let mut cmd = Command::new(cc);
+    cmd.args(&sysroot).arg("-c").arg(&src).arg("-o").arg(&obj);
+    if let Err(e) = run(&mut cmd) {
+        r.error(msg!("doctor_cc", cc = cc), msg!("doctor_compile", cc = cc, error = e), msg!("doctor_compile.fix", cc = cc));
+        return;
+    }
+    let mut cmd = Command::new(cc);
+    cmd.args(&sysroot).arg(&obj).arg("-o").arg(dir.path().join("doctor"));
+    match run(&mut cmd) {
+        Ok(())  => r.ok(msg!("doctor_linker", cc = cc), String::new()),
+        Err(e)  => r.error(msg!("doctor_linker", cc = cc), msg!("doctor_link", cc = cc, error = e), msg!("doctor_link.fix")),
+    }
+}
+
+fn tool(r: &mut Report, what: String, bin: &str, missing: String, fix: String) -> bool {
+    match which::which(bin) {
+        Ok(path) => {
+            r.ok(what, path.display().to_string());
+            true
+        }
+        Err(_) => {
+            r.error(what, missing, fix);
+            false
+        }
+    }
+}
+
+fn sysroot(r: &mut Report, target: &str, path: &str) {
+    let what = msg!("doctor_sysroot", target = target, path = path);
+    let dir = Path::new(path);
+    if !dir.is_dir() {
+        r.error(what, msg!("doctor_sysroot_missing", path = path), msg!("doctor_sysroot_missing.fix", target = target));
+    } else if !dir.join("usr").join("include").is_dir() && !dir.join("include").is_dir() {
+        r.warn(what, msg!("doctor_sysroot_headers", path = path), msg!("doctor_sysroot_headers.fix"));
+    } else {
+        r.ok(what, String::new());
+    }
+}
+
+fn pkg_config(r: &mut Report, config: &Config) {
+    let bin = std::env::var("PKG_CONFIG").unwrap_or("pkg-config".to_string());
+    let mut packages = config.project.pkgconfig.clone();
+    for feature in config.features.iter().flat_map(|f| f.values()) {
+        packages.extend(feature.pkgconfig.iter().cloned());
+    }
+    packages.sort();
+    packages.dedup();
+
+    let what = msg!("doctor_pkg_config", bin = bin);
+    if which::which(&bin).is_err() {
+        if packages.is_empty() {
+            r.warn(what, msg!("doctor_pkg_config_missing", bin = bin), msg!("doctor_pkg_config_missing.fix"));
+        } else {
+            r.error(what, msg!("doctor_pkg_config_missing", bin = bin), msg!("doctor_pkg_config_missing.fix"));
+        }
+        return;
+    }
+    r.ok(what, version(&bin).unwrap_or_default());
+
+    for pkg in &packages {
+        let what = msg!("doctor_pkg", pkg = pkg);
+        match run(Command::new(&bin).arg("--exists").arg(pkg)) {
+            Ok(())  => r.ok(what, String::new()),
+            Err(_)  => r.error(what, msg!("doctor_pkg_missing", bin = bin, pkg = pkg), msg!("doctor_pkg_missing.fix", pkg = pkg)),
+        }
+    }
+}
+
+fn git_dependencies(config: &Config) -> bool {
+    config.dependencies.iter().flat_map(|d| d.values()).any(|v| v.get("git").is_some())
+}
+
+fn directory(r: &mut Report, what: String, dir: &Path, fix: String) {
+    match writable(dir) {
+        Ok(())  => r.ok(what, String::new()),
+        Err(e)  => r.error(what, msg!("doctor_unwritable", path = dir.display(), error = e), fix),
+    }
+}
+
+pub fn main() {
+    let mut r = Report {
+        color:  diagnostics::colors(),
+        errors: 0,
+    };
+
+    let project = project::find_root_cwd().map(|root| project::load(&root));
+    let cxx = project.as_ref()
+        .and_then(|(_, config)| config.project.std.as_ref())
+        .map(|std| std.contains("c++"))
+        .unwrap_or(false);
+    if project.is_none() {
+        println!("{}", msg!("doctor_no_project"));
+    }
+
+    compiler(&mut r, &make::env_cc(cxx), None);
+    let ar = make::env_ar();
+    tool(&mut r, msg!("doctor_ar", ar = ar), &ar, msg!("doctor_ar_missing", ar = ar), msg!("doctor_ar_missing.fix"));
+    tool(&mut r, msg!("doctor_smt"), "z3", msg!("doctor_smt_missing"), msg!("doctor_smt_missing.fix"));
+
+    if let Some((root, config)) = &project {
+        let mut targets : Vec<_> = config.target.iter().collect();
+        targets.sort_by(|a, b| a.0.cmp(b.0));
+        for (name, target) in targets {
+            if let Some(path) = &target.sysroot {
+                sysroot(&mut r, name, path);
+            }
+            let cc = if cxx { &target.cxx } else { &target.cc };
+            if cc.is_some() || target.sysroot.is_some() {
+                let cc = cc.clone().unwrap_or_else(|| make::env_cc(cxx));
+                compiler(&mut r, &cc, target.sysroot.as_deref());
+            }
+            if let Some(ar) = &target.ar {
+                tool(&mut r, msg!("doctor_ar", ar = ar), ar, msg!("doctor_ar_missing", ar = ar), msg!("doctor_ar_missing.fix"));
+            }
+        }
+
+        pkg_config(&mut r, config);
+        if git_dependencies(config) {
+            tool(&mut r, msg!("doctor_git"), "git", msg!("doctor_git_missing"), msg!("doctor_git_missing.fix"));
+        }
+        let target = root.join("target");
+        directory(&mut r, msg!("doctor_target", path = target.display()), &target, msg!("doctor_target.fix", path = target.display()));
+    }
+    let cache = deps::cache_dir();
+    directory(&mut r, msg!("doctor_cache", path = cache.display()), &cache, msg!("doctor_cache.fix", path = cache.display()));
+
+    println!();
+    if r.errors > 0 {
+        println!("{}", msg!("doctor_summary", errors = r.errors));
+        std::process::exit(9);
+    }
+    println!("{}", msg!("doctor_summary.ok"));
+}
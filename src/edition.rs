@@ -0,0 +1,136 @@
+/// language editions
+
+use super::parser::{Rule, ZZParser};
+use pest::Parser;
+use serde::{Serialize, Deserialize};
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Edition {
+    #[serde(rename = "2020")]
+    E2020,
+    #[serde(rename = "2021")]
+    E2021,
+}
+
+impl Default for Edition {
+    fn default() -> Self {
+        Edition::E2020
+    }
+}
+
+impl Edition {
+    /// what zz init writes
+    pub const LATEST: Edition = Edition::E2021;
+
+    pub fn parse(s: &str) -> Option<Edition> {
+        match s {
+            "2020" => Some(Edition::E2020),
+            "2021" => Some(Edition::E2021),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Edition::E2020 => "2020",
+            Edition::E2021 => "2021",
+        }
+    }
+}
+
+impl std::fmt::Display for Edition {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// a piece of syntax that edition no longer accepts
+pub struct Migration<'a> {
+    pub span:       pest::Span<'a>,
+    pub replace:    &'static str,
+    pub since:      Edition,
+}
+
+/// everything in a parsed file that must be rewritten to be valid in edition.
+/// this also looks into #if branches that are not taken, so fix migrates all of them
+pub fn migrations<'a>(file: pest::iterators::Pairs<'a, Rule>, edition: Edition) -> Vec<Migration<'a>> {
+    let mut r = Vec::new();
+    for pair in file.flatten() {
+        match pair.as_rule() {
+            Rule::key_mut if edition >= Edition::E2021 && pair.as_str() == "mutable" => {
+                r.push(Migration{
+                    span:       pair.as_span(),
+                    replace:    "mut",
+                    since:      Edition::E2021,
+                });
+            }
+            _ => {}
+        }
+    }
+    r
+}
+
+/// rewrite one source file for edition. returns the number of changes
+pub fn fix_file(path: &Path, edition: Edition) -> Result<usize, String> {
+    let src = std::fs::read_to_string(path).map_err(|e|format!("{}: {}", path.display(), e))?;
+    let file = ZZParser::parse(Rule::file, &src).map_err(|e|format!("{}", e.with_path(&path.to_string_lossy())))?;
+
+    let mut out = String::new();
+    let mut at  = 0;
+    let mut n   = 0;
+    for m in migrations(file, edition) {
+        out.push_str(&src[at..m.span.start()]);
+        out.push_str(m.replace);
+        at = m.span.end();
+        n += 1;
+    }
+    if n == 0 {
+        return Ok(0);
+    }
+    out.push_str(&src[at..]);
+    std::fs::write(path, out).map_err(|e|format!("{}: {}", path.display(), e))?;
+    Ok(n)
+}
+
+/// zz fix --edition. migrate src/ and tests/ of the current project and bump zz.toml
+pub fn fix(edition: Edition) {
+    let (root, config) = super::project::load_cwd();
+    if config.project.edition > edition {
+        error!("project is on edition {}, cannot go back to {}", config.project.edition, edition);
+        std::process::exit(9);
+    }
+
+    let mut files = Vec::new();
+    for dir in &["src", "tests"] {
+        let entries = match std::fs::read_dir(root.join(dir)) {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+        for entry in entries {
+            let path = entry.unwrap().path();
+            if path.is_file() && path.extension().map(|e| e == "zz").unwrap_or(false) {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+
+    let mut failed = false;
+    for path in files {
+        match fix_file(&path, edition) {
+            Ok(0) => {}
+            Ok(n) => info!("{}: {} changes", path.display(), n),
+            Err(e) => {
+                error!("{}", e);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        std::process::exit(9);
+    }
+
+    super::project::set_edition(&root, edition);
+    info!("project is now on edition {}", edition);
+}
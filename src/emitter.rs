@@ -3,11 +3,13 @@ use std::fs;
 use super::flatten;
 use super::ast;
 use super::make;
-use std::io::{Write, Read};
+use std::io::{self, Write};
 use std::collections::HashSet;
 use std::path::PathBuf;
 use super::name::Name;
 use super::parser::{self, emit_error};
+use super::unittest;
+use super::cxxheader;
 use serde::{Serialize,Deserialize};
 
 #[derive(Serialize, Deserialize)]
@@ -16,18 +18,34 @@ pub struct CFile {
     pub filepath:   String,
     pub sources:    HashSet<PathBuf>,
     pub deps:       HashSet<Name>,
+    // symbols visible in the shared library
+    #[serde(default)]
+    pub exports:    Vec<String>,
+    #[serde(default)]
+    pub tests:      Vec<unittest::Test>,
 }
 
 pub struct Emitter{
     cxx:            bool,
     p:              String,
-    f:              fs::File,
+    // written as the module is walked, never held in memory as a whole
+    f:              io::BufWriter<Box<dyn io::Write + Send>>,
     module:         flatten::Module,
     header:         bool,
     inside_macro:   bool,
     cur_loc:        Option<ast::Location>,
     casedir:        String,
     emit_as_extern: HashSet<Name>,
+    exports:        Vec<String>,
+    tests:          Vec<unittest::Test>,
+    // exported functions become wasm exports
+    wasm:           bool,
+    // module holds all modules of the build, see amalgamate.rs
+    amalgamation:   bool,
+    // #line markers pointing back at the zz sources
+    lines:          bool,
+    // the header is wrapped in extern "C" for c++, see cxxheader.rs
+    cxx_headers:    bool,
 }
 
 pub fn outname(project: &Project, stage: &make::Stage, module: &Name , header: bool) -> (bool, String) {
@@ -53,16 +71,38 @@ impl Emitter {
     pub fn new(project: &Project, stage: make::Stage , module: flatten::Module, header: bool) -> Self {
 
         let (cxx, p) = outname(project, &stage, &module.name, header);
-        let mut f = fs::File::create(&p).expect(&format!("cannot create {}", p));
 
         let casedir = format!("target/{}/testcases/{}", stage, module.name.0[1..].join("_"));
         std::fs::remove_dir_all(&casedir).ok();
         std::fs::create_dir_all(&casedir).unwrap();
 
+        let f = fs::File::create(&p).expect(&format!("cannot create {}", p));
+        Self::create(project, stage, module, header, cxx, p, casedir, Box::new(f))
+    }
+
+    /// the whole build as one file. module is the result of amalgamate::merge, p names out in messages
+    pub fn amalgamation(project: &Project, stage: make::Stage , module: flatten::Module, header: bool, cxx: bool, p: String,
+        out: Box<dyn io::Write + Send>) -> Self
+    {
+        // testcases are left out of the amalgamation, so nothing is written here
+        let casedir = format!("target/{}/testcases/{}", stage, project.name);
+        let mut em = Self::create(project, stage, module, header, cxx, p, casedir, out);
+        em.amalgamation = true;
+        em
+    }
+
+    fn create(project: &Project, stage: make::Stage , module: flatten::Module, header: bool, cxx: bool, p: String, casedir: String,
+        out: Box<dyn io::Write + Send>) -> Self
+    {
+        let mut f = io::BufWriter::new(out);
 
         write!(f, "#include <stdint.h>\n").unwrap();
         write!(f, "#include <stddef.h>\n").unwrap();
-        write!(f, "#include <stdbool.h>\n").unwrap();
+        if project.stdbool() {
+            write!(f, "#include <stdbool.h>\n").unwrap();
+        } else {
+            write!(f, "#ifndef __cplusplus\n#ifndef bool\n#define bool int\n#define true 1\n#define false 0\n#endif\n#endif\n").unwrap();
+        }
 
         Emitter{
             cxx,
@@ -74,6 +114,12 @@ impl Emitter {
             inside_macro: false,
             cur_loc: None,
             emit_as_extern: HashSet::new(),
+            exports:        Vec::new(),
+            tests:          Vec::new(),
+            wasm:           stage.wasm,
+            amalgamation:   false,
+            lines:          project.line_directives(),
+            cxx_headers:    project.cxx_headers(),
         }
     }
 
@@ -90,10 +136,10 @@ impl Emitter {
         if self.header {
             return
         }
-        if self.inside_macro {
+        if self.inside_macro || !self.lines {
             return;
         }
-        write!(self.f, "\n#line {} \"{}\"\n", loc.line(), loc.file.replace("\\", "\\\\")).unwrap();
+        write!(self.f, "\n#line {} \"{}\"\n", loc.line(), loc.file.name().replace("\\", "\\\\")).unwrap();
     }
 
     fn to_local_typed_name(&self, name: &ast::Typed) -> String {
@@ -125,12 +171,16 @@ impl Emitter {
                 }
                 s
             }
+            // let in the #define of a macro, for c using it. zz has its uses expanded, see macros.rs
+            ast::Type::Elided if self.inside_macro => {
+                if self.cxx { "auto".to_string() } else { "__auto_type".to_string() }
+            }
             ast::Type::ILiteral | ast::Type::ULiteral | ast::Type::Elided | ast::Type::New => {
                 parser::emit_error(
                     "ICE: untyped literal ended up in emitter",
                     &[(name.loc.clone(), format!("this should have been resolved earlier"))]
                     );
-                std::process::exit(9);
+                parser::abort();
             }
         }
     }
@@ -141,16 +191,19 @@ impl Emitter {
         }
 
         if self.emit_as_extern.contains(s) {
-            return s.0.last().unwrap().clone();
+            return s.0.last().unwrap().to_string();
         }
 
         assert!(s.is_absolute(), "ICE not abs: '{}'", s);
         if let Some(an) = self.module.aliases.get(&s) {
             return an.clone();
         }
+        if let Some(symbol) = self.module.symbols.get(&s) {
+            return symbol.clone();
+        }
 
         if s.0[1] == "ext" {
-            return s.0.last().unwrap().clone();
+            return s.0.last().unwrap().to_string();
         }
 
         let mut s = s.clone();
@@ -158,6 +211,14 @@ impl Emitter {
         return s.0.join("_");
     }
 
+    /// the c symbol of a function, see symbols.rs
+    fn symbol(&self, name: &Name) -> String {
+        match self.module.symbols.get(name) {
+            Some(symbol) => symbol.clone(),
+            None => name.0[1..].join("_"),
+        }
+    }
+
     fn to_local_name_mangle(&self, s: &Name) -> String {
         self.to_local_name(s).chars().map(|x| match x {
             'A'..='Z'   => x,
@@ -175,6 +236,9 @@ impl Emitter {
         if self.header {
             let headername = module.name.0.join("_");
             write!(self.f, "#ifndef ZZ_EXPORT_HEADER_{}\n#define ZZ_EXPORT_HEADER_{}\n", headername, headername).unwrap();
+            if self.cxx_headers {
+                write!(self.f, "#ifdef __cplusplus\nextern \"C\" {{\n#endif\n").unwrap();
+            }
         }
 
         let mut dup = HashSet::new();
@@ -209,6 +273,9 @@ impl Emitter {
                     self.emit_macro(&d)
                 }
                 ast::Def::Const{..} => {
+                    if self.header && d.vis == ast::Visibility::Package {
+                        continue
+                    }
                     if self.header {
                         write!(self.f, r#"
 #ifndef ZZ_EXPORT_{tn}
@@ -249,6 +316,21 @@ impl Emitter {
                 ast::Def::Fntype{..} => {
                     self.emit_fntype(&d);
                 }
+                ast::Def::TypeAlias{..} => {
+                    if self.header {
+                        write!(self.f, r#"
+#ifndef ZZ_EXPORT_{tn}
+#define ZZ_EXPORT_{tn}
+"#,
+                            tn = self.to_local_name_mangle(&Name::from(&d.name))).unwrap();
+                    }
+
+                    self.emit_type_alias(&d);
+
+                    if self.header {
+                        write!(self.f, "\n#endif\n").unwrap();
+                    }
+                }
                 ast::Def::Theory{..} => {
                 }
                 ast::Def::Testcase {..} => {
@@ -258,6 +340,10 @@ impl Emitter {
                     self.emit_testcase(&d);
                 }
                 ast::Def::Function{..} => {
+                    // pub(package) is for the modules of the project, not for c code including the header
+                    if self.header && d.vis == ast::Visibility::Package {
+                        continue
+                    }
                     self.emit_decl(&d);
                 }
                 ast::Def::Include {inline,..} => {
@@ -277,7 +363,7 @@ impl Emitter {
 
                         let mut name = Name::from(&d.name);
                         name.pop();
-                        let isimpl = name == module.name;
+                        let isimpl = self.amalgamation || name == module.name;
 
                         self.emit_struct(&d, isimpl, None);
 
@@ -311,6 +397,9 @@ impl Emitter {
 
 
         if self.header {
+            if self.cxx_headers {
+                write!(self.f, "#ifdef __cplusplus\n}}\n#endif\n").unwrap();
+            }
             write!(self.f, "#endif\n").unwrap();
         } else {
             // function impls are always last.
@@ -321,7 +410,12 @@ impl Emitter {
                     ast::Def::Function{attr, ..} => {
                         let mut mname = Name::from(&d.name);
                         mname.pop();
-                        if complete == &flatten::TypeComplete::Complete && (mname == module.name || attr.contains_key("inline")) {
+                        // methods declared in a struct are named module::struct::method
+                        let method = mname.0.len() == module.name.0.len() + 1
+                            && mname.0.starts_with(&module.name.0)
+                            && module.d.iter().any(|(s, _)| matches!(s.def, ast::Def::Struct{..}) && Name::from(&s.name) == mname);
+                        let own = self.amalgamation || mname == module.name || method;
+                        if complete == &flatten::TypeComplete::Complete && (own || attr.contains_key("inline")) {
                             self.emit_def(&d);
                         }
                     }
@@ -330,11 +424,15 @@ impl Emitter {
             }
         }
 
+        self.f.flush().expect(&format!("cannot write {}", self.p));
+
         CFile {
             name:       module.name,
             filepath:   self.p,
             sources:    module.sources,
             deps:       module.deps,
+            exports:    self.exports,
+            tests:      self.tests,
         }
     }
 
@@ -362,20 +460,17 @@ impl Emitter {
                         format!("cannot inline {:?}", expr),
                         &[(loc.clone(), format!("{}", e))]
                         );
-                    std::process::exit(9);
+                    parser::abort();
 
                 },
                 Ok(f) => f,
             };
 
 
-            let mut v = Vec::new();
-            f.read_to_end(&mut v).expect(&format!("read {:?}", expr));
-
-            if !self.inside_macro {
+            if !self.inside_macro && self.lines {
                 write!(self.f, "\n#line 1 \"{}\"\n", expr.replace("\\", "\\\\")).unwrap();
             }
-            self.f.write_all(&v).unwrap();
+            io::copy(&mut f, &mut self.f).expect(&format!("read {:?}", expr));
 
 
             return;
@@ -420,11 +515,25 @@ impl Emitter {
         self.emit_loc(&ast.loc);
 
         let (typed, expr, tags, storage, array) = match &ast.def {
-            ast::Def::Static{typed, expr, tags, storage, array} => (typed, expr, tags, storage, array),
+            ast::Def::Static{typed, expr, tags, storage, array, ..} => (typed, expr, tags, storage, array),
             _ => unreachable!(),
         };
 
-        if tags.contains_key("mut") {
+        // a const array shared with other modules, like the tables of resources.rs, is defined once by the module declaring it
+        // and declared extern in the modules using it
+        let name = Name::from(&ast.name);
+        let shared = matches!(storage, ast::Storage::Const) && ast.vis != ast::Visibility::Object;
+        let mut owner = name.clone();
+        owner.pop();
+        let extern_ = shared && !self.amalgamation && owner != self.module.name;
+
+        if shared {
+            if extern_ {
+                write!(self.f, "extern const ").unwrap();
+            } else {
+                write!(self.f, "const ").unwrap();
+            }
+        } else if tags.contains_key("mut") {
             write!(self.f, "static ").unwrap();
         } else {
             write!(self.f, "static const ").unwrap();
@@ -439,13 +548,18 @@ impl Emitter {
             ast::Storage::ThreadLocal => {
                 write!(self.f, "_Thread_local ").unwrap();
             },
-            ast::Storage::Static  => (),
+            ast::Storage::Static | ast::Storage::Const => (),
         }
 
         write!(self.f, "{} ", self.to_local_typed_name(&typed)).unwrap();
         self.emit_pointer(&typed.ptr);
 
-        write!(self.f, "{} ", self.to_local_name(&Name::from(&ast.name))).unwrap();
+        let local = self.to_local_name(&name);
+        if shared {
+            write!(self.f, "{} ", self.symbol(&name)).unwrap();
+        } else {
+            write!(self.f, "{} ", local).unwrap();
+        }
 
         if let Some(array) = &array {
             write!(self.f, " [ ").unwrap();
@@ -455,14 +569,24 @@ impl Emitter {
             write!(self.f, " ] ").unwrap();
         }
 
-        write!(self.f, "=").unwrap();
-        self.emit_expr(&expr);
+        if shared {
+            write!(self.f, "__attribute__ ((visibility (\"hidden\"))) ").unwrap();
+        }
+        if !extern_ {
+            write!(self.f, "=").unwrap();
+            self.emit_expr(&expr);
+        }
         write!(self.f, ";\n").unwrap();
+
+        // imported under another name
+        if shared && local != self.symbol(&name) {
+            write!(self.f, "#define {} {}\n", local, self.symbol(&name)).unwrap();
+        }
     }
 
     pub fn emit_const(&mut self, ast: &ast::Local) {
         let (typed, expr) = match &ast.def {
-            ast::Def::Const{typed, expr} => (typed, expr),
+            ast::Def::Const{typed, expr, ..} => (typed, expr),
             _ => unreachable!(),
         };
 
@@ -480,9 +604,21 @@ impl Emitter {
         write!(self.f, ")\n").unwrap();
     }
 
+    pub fn emit_type_alias(&mut self, ast: &ast::Local) {
+        let typed = match &ast.def {
+            ast::Def::TypeAlias{typed} => typed,
+            _ => unreachable!(),
+        };
+
+        self.emit_loc(&ast.loc);
+        write!(self.f, "typedef {} ", self.to_local_typed_name(&typed)).unwrap();
+        self.emit_pointer(&typed.ptr);
+        write!(self.f, "{};\n", self.to_local_name(&Name::from(&ast.name))).unwrap();
+    }
+
     pub fn emit_enum(&mut self, ast: &ast::Local) {
         let names = match &ast.def {
-            ast::Def::Enum{names} => (names),
+            ast::Def::Enum{names, ..} => (names),
             _ => unreachable!(),
         };
         self.emit_loc(&ast.loc);
@@ -523,8 +659,8 @@ impl Emitter {
                             ast::Expression::LiteralChar{v,..} => {
                                 f.write(&[*v as u8]).unwrap();
                             },
-                            ast::Expression::Literal{v,loc} => {
-                                match parser::parse_u64(v) {
+                            ast::Expression::Literal{value,loc, ..} => {
+                                match value.as_u64() {
                                     Some(v) if v <= 255 => {
                                         f.write(&[v as u8]).unwrap();
                                     }
@@ -533,7 +669,7 @@ impl Emitter {
                                             "testcase field must be literal string or byte array",
                                             &[(loc.clone(), format!("this expression cannot be emitted as testcase file"))]
                                             );
-                                        std::process::exit(9);
+                                        parser::abort();
                                     }
                                 }
                             },
@@ -542,7 +678,7 @@ impl Emitter {
                                     "testcase field must be literal string or byte array, not",
                                     &[(field.loc().clone(), format!("this expression cannot be emitted as testcase file"))]
                                     );
-                                std::process::exit(9);
+                                parser::abort();
                             }
                         }
                     }
@@ -555,7 +691,7 @@ impl Emitter {
                         "testcase field must be literal string or byte array",
                         &[(expr.loc().clone(), format!("this expression cannot be emitted as testcase file"))]
                         );
-                    std::process::exit(9);
+                    parser::abort();
                 }
             }
         }
@@ -627,7 +763,7 @@ impl Emitter {
                             "tail field has no be the last field in a struct",
                             &[(field.loc.clone(), format!("tail field would displace next field"))]
                             );
-                        std::process::exit(9);
+                        parser::abort();
                     }
                     if let Some(tt) = tail_variant {
                         emitted_tail = true;
@@ -642,6 +778,9 @@ impl Emitter {
             } else {
                 write!(self.f, " {}", field.name).unwrap();
             }
+            if let Some(bits) = field.bits {
+                write!(self.f, " : {}", bits).unwrap();
+            }
             write!(self.f, " ;\n").unwrap();
         }
         if let Some(tt) = tail_variant {
@@ -662,12 +801,56 @@ impl Emitter {
             self.to_local_name(&Name::from(&ast.name)),
             self.to_local_name(&Name::from(&ast.name)),
             ).unwrap();
+            self.exports.push(format!("sizeof_{}", self.to_local_name(&Name::from(&ast.name))));
         }
     }
 
 
 
 
+    /// the path to the flexible array member of a struct, through nested tails
+    fn tail_member(&self, sname: &Name) -> Option<String> {
+        for (local, _) in &self.module.d {
+            if let ast::Def::Struct{fields, ..} = &local.def {
+                if &Name::from(&local.name) != sname {
+                    continue;
+                }
+                let field = fields.last()?;
+                return match (&field.array, &field.typed.t) {
+                    (Some(None), _) => Some(field.name.clone()),
+                    (None, ast::Type::Other(nested)) if field.typed.tail == ast::Tail::Dynamic => {
+                        self.tail_member(nested).map(|path| format!("{}.{}", field.name, path))
+                    }
+                    _ => None,
+                };
+            }
+        }
+        None
+    }
+
+    /// sizeof(T+n) is the struct plus n elements of its flexible array member.
+    /// returns the type and the path to that member
+    fn tail_sizeof(&self, name: &ast::Expression, args: &[Box<ast::Expression>]) -> Option<(ast::Typed, String)> {
+        match name {
+            ast::Expression::Name(ast::Typed{t: ast::Type::Other(n), ..})
+                if n.len() == 4 && n.0[1] == "ext" && n.0[3] == "sizeof" => (),
+            _ => return None,
+        }
+        let typed = match args {
+            [arg] => match arg.as_ref() {
+                ast::Expression::Name(typed) => typed,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        match (&typed.t, &typed.tail) {
+            (ast::Type::Other(sname), ast::Tail::Static(..)) | (ast::Type::Other(sname), ast::Tail::Bind(..)) => {
+                self.tail_member(sname).map(|path| (typed.clone(), path))
+            }
+            _ => None,
+        }
+    }
+
     fn function_args(&mut self, args: &Vec<ast::NamedArg>) {
         let mut first = true ;
         for arg in args {
@@ -685,7 +868,11 @@ impl Emitter {
                 write!(self.f, " const ").unwrap();
             }
 
-            write!(self.f, " {}", arg.name).unwrap();
+            if self.header && self.cxx_headers {
+                write!(self.f, " {}", cxxheader::arg_name(&arg.name)).unwrap();
+            } else {
+                write!(self.f, " {}", arg.name).unwrap();
+            }
         }
     }
 
@@ -713,7 +900,7 @@ impl Emitter {
                         "ICE: unsupported attr",
                         &[(loc.clone(), format!("'{}' is not a valid c attribute", o))]
                         );
-                    std::process::exit(9);
+                    parser::abort();
 
                 }
             }
@@ -752,10 +939,11 @@ impl Emitter {
         self.emit_loc(&ast.loc);
 
         match &ast.vis {
-            ast::Visibility::Object  => {
+            // inline already writes static inline
+            ast::Visibility::Object if !inline => {
                 write!(self.f, "static ").unwrap();
             },
-            ast::Visibility::Shared if !inline => {
+            ast::Visibility::Shared | ast::Visibility::Package if !inline => {
                 write!(self.f, "extern ").unwrap();
             },
             _ => (),
@@ -771,33 +959,49 @@ impl Emitter {
 
         match &ast.vis {
             ast::Visibility::Object => (),
-            ast::Visibility::Shared => write!(self.f, "__attribute__ ((visibility (\"hidden\"))) ").unwrap(),
+            ast::Visibility::Shared | ast::Visibility::Package => write!(self.f, "__attribute__ ((visibility (\"hidden\"))) ").unwrap(),
             ast::Visibility::Export => write!(self.f, "__attribute__ ((visibility (\"default\"))) ").unwrap(),
         }
 
+        // c code using the export header gets the warning zz gives
+        if self.header {
+            if let Some(message) = self.module.deprecated.get(&Name::from(&ast.name)) {
+                write!(self.f, "__attribute__ ((deprecated ({:?}))) ", message).unwrap();
+            }
+        }
+
         let mut name = Name::from(&ast.name);
         for (attr, loc) in attr {
             match attr.as_str() {
                 "extern" => {
                     self.emit_as_extern.insert(name.clone());
                     name = Name::from(name.0.last().unwrap());
-                    name.0.insert(0, String::new());
+                    name.0.insert(0, "".into());
                 },
                 "inline" => {
                     write!(self.f, " static inline ").unwrap();
                 },
+                "noreturn" => {
+                    write!(self.f, "__attribute__ ((noreturn)) ").unwrap();
+                },
+                "cold" => {
+                    write!(self.f, "__attribute__ ((cold)) ").unwrap();
+                },
+                // only the definition is weak. a weak declaration would make the overriding definition weak too
+                "weak" => (),
+                "comptime" => (),
                 o => {
                     parser::emit_error(
                         "ICE: unsupported attr",
                         &[(loc.clone(), format!("'{}' is not a valid c attribute", o))]
                         );
-                    std::process::exit(9);
+                    parser::abort();
 
                 }
             }
         }
 
-        write!(self.f, "{} (", name.0[1..].join("_")).unwrap();
+        write!(self.f, "{} (", self.symbol(&name)).unwrap();
 
         self.function_args(args);
         if vararg {
@@ -813,7 +1017,7 @@ impl Emitter {
             return;
         }
 
-        if self.to_local_name(&Name::from(&ast.name)) == Name::from(&ast.name).0[1..].join("_") {
+        if self.to_local_name(&Name::from(&ast.name)) == self.symbol(&Name::from(&ast.name)) {
             return;
         }
         if self.header {
@@ -846,7 +1050,7 @@ impl Emitter {
             write!(self.f, "return ").unwrap();
         }
 
-        write!(self.f, "{}(", Name::from(&ast.name).0[1..].join("_")).unwrap();
+        write!(self.f, "{}(", self.symbol(&Name::from(&ast.name))).unwrap();
 
         let mut first = true;
         for arg in args {
@@ -862,8 +1066,8 @@ impl Emitter {
     }
 
     pub fn emit_def(&mut self, ast: &ast::Local) {
-        let (ret, args, body, vararg, attr) = match &ast.def {
-            ast::Def::Function{ret, args, body, vararg, attr, ..} => (ret, args, body, *vararg, attr),
+        let (ret, args, body, vararg, attr, hints) = match &ast.def {
+            ast::Def::Function{ret, args, body, vararg, attr, hints, ..} => (ret, args, body, *vararg, attr, hints),
             _ => unreachable!(),
         };
 
@@ -871,7 +1075,7 @@ impl Emitter {
 
         if !ast.name.ends_with("::main") {
             match &ast.vis {
-                ast::Visibility::Object  => {
+                ast::Visibility::Object if !attr.contains_key("inline") => {
                     write!(self.f, "static ").unwrap();
                 },
                 _ => (),
@@ -884,17 +1088,27 @@ impl Emitter {
                 "extern" => {
                     self.emit_as_extern.insert(name.clone());
                     name = Name::from(name.0.last().unwrap());
-                    name.0.insert(0, String::new());
+                    name.0.insert(0, "".into());
                 },
                 "inline" => {
                     write!(self.f, " static inline ").unwrap();
                 },
+                "noreturn" => {
+                    write!(self.f, "__attribute__ ((noreturn)) ").unwrap();
+                },
+                "cold" => {
+                    write!(self.f, "__attribute__ ((cold)) ").unwrap();
+                },
+                "weak" => {
+                    write!(self.f, "__attribute__ ((weak)) ").unwrap();
+                },
+                "comptime" => (),
                 o => {
                     parser::emit_error(
                         "ICE: unsupported attr",
                         &[(loc.clone(), format!("'{}' is not a valid c attribute", o))]
                         );
-                    std::process::exit(9);
+                    parser::abort();
 
                 }
             }
@@ -915,10 +1129,24 @@ impl Emitter {
         } else  {
             match &ast.vis {
                 ast::Visibility::Object => (),
-                ast::Visibility::Shared => write!(self.f, "__attribute__ ((visibility (\"hidden\"))) ").unwrap(),
+                ast::Visibility::Shared | ast::Visibility::Package => write!(self.f, "__attribute__ ((visibility (\"hidden\"))) ").unwrap(),
                 ast::Visibility::Export => write!(self.f, "__attribute__ ((visibility (\"default\"))) ").unwrap(),
             }
-            write!(self.f, "{} (", name.0[1..].join("_")).unwrap();
+            if ast.vis == ast::Visibility::Export && !attr.contains_key("inline") {
+                self.exports.push(self.symbol(&name));
+                if self.wasm {
+                    write!(self.f, "__attribute__ ((export_name (\"{}\"))) ", self.symbol(&name)).unwrap();
+                }
+            }
+            if let Some(test) = hints.get("test") {
+                self.tests.push(unittest::Test{
+                    symbol: self.symbol(&name),
+                    name:   test.clone(),
+                    file:   ast.loc.file.to_string(),
+                    line:   ast.loc.line(),
+                });
+            }
+            write!(self.f, "{} (", self.symbol(&name)).unwrap();
         }
 
 
@@ -930,6 +1158,29 @@ impl Emitter {
         write!(self.f, ")\n").unwrap();
         self.emit_zblock(&body, true);
         write!(self.f, "\n").unwrap();
+
+        if let Some(target) = self.module.overrides.get(&Name::from(&ast.name)).cloned() {
+            self.emit_override(&target, &name, ret, args);
+        }
+    }
+
+    /// a strong definition of the @weak function target, calling the function overriding it. see weak.rs
+    fn emit_override(&mut self, target: &Name, name: &Name, ret: &Option<ast::AnonArg>, args: &Vec<ast::NamedArg>) {
+        match &ret {
+            None       => write!(self.f, "void ").unwrap(),
+            Some(a)    => {
+                write!(self.f, "{} ", self.to_local_typed_name(&a.typed)).unwrap();
+                self.emit_pointer(&a.typed.ptr);
+            }
+        };
+        write!(self.f, "__attribute__ ((visibility (\"default\"))) {} (", self.to_local_name(target)).unwrap();
+        self.function_args(args);
+        write!(self.f, ")\n{{\n    ").unwrap();
+        if ret.is_some() {
+            write!(self.f, "return ").unwrap();
+        }
+        let forward : Vec<&str> = args.iter().map(|a| a.name.as_str()).collect();
+        write!(self.f, "{}({});\n}}\n", self.symbol(&name), forward.join(", ")).unwrap();
     }
 
     fn emit_statement(&mut self, stm: &ast::Statement) -> bool /* ends with semicolon */ {
@@ -1160,10 +1411,13 @@ impl Emitter {
 
     fn emit_expr(&mut self, v: &ast::Expression) {
         match v {
-            ast::Expression::ArrayInit{fields,loc} => {
+            ast::Expression::ArrayInit{fields,loc,index} => {
                 self.emit_loc(&loc);
                 write!(self.f, "{{").unwrap();
-                for field in fields {
+                for (i, field) in fields.iter().enumerate() {
+                    if let Some(at) = index.get(i) {
+                        write!(self.f, "[{}] = ", at).unwrap();
+                    }
                     self.emit_expr(field);
                     write!(self.f, ",").unwrap();
                 }
@@ -1173,6 +1427,10 @@ impl Emitter {
                 self.emit_loc(&loc);
                 write!(self.f, "    ({}", self.to_local_typed_name(&typed)).unwrap();
                 write!(self.f, "){{").unwrap();
+                // Foo{} of a struct without defaults, {} is not c before c23
+                if fields.is_empty() {
+                    write!(self.f, "0").unwrap();
+                }
                 for (name, field) in fields {
                     write!(self.f, ".{} = ", name).unwrap();
                     self.emit_expr(field);
@@ -1230,9 +1488,22 @@ impl Emitter {
                 self.write_escaped_literal(*v, false);
                 write!(self.f, "'").unwrap();
             }
-            ast::Expression::Literal {loc, v} => {
+            ast::Expression::Literal {loc, v, value} => {
                 self.emit_loc(&loc);
-                write!(self.f, "    {}", v).unwrap();
+                match value.suffix() {
+                    Some(t) => {
+                        let t = self.to_local_typed_name(&ast::Typed{
+                            t:      t.clone(),
+                            loc:    loc.clone(),
+                            ptr:    Vec::new(),
+                            tail:   ast::Tail::None,
+                        });
+                        write!(self.f, "    (({}){})", t, v).unwrap();
+                    }
+                    None => {
+                        write!(self.f, "    {}", v).unwrap();
+                    }
+                }
             }
             ast::Expression::Call { loc, name, args, emit , ..} => {
                 match emit {
@@ -1242,11 +1513,21 @@ impl Emitter {
                         emit_error(format!("{}", message), &[
                             (loc.clone(), "here")
                         ]);
-                        std::process::exit(9);
+                        parser::abort();
                     }
                 };
 
                 self.emit_loc(&loc);
+                if let Some((typed, field)) = self.tail_sizeof(name, args) {
+                    let t = self.to_local_typed_name(&ast::Typed{tail: ast::Tail::None, ..typed.clone()});
+                    let len = match &typed.tail {
+                        ast::Tail::Static(v, _) => format!("{}", v),
+                        ast::Tail::Bind(v, _)   => v.clone(),
+                        _ => unreachable!(),
+                    };
+                    write!(self.f, "(sizeof({}) + ({}) * sizeof((({}*)0)->{}[0]))", t, len, t, field).unwrap();
+                    return;
+                }
                 self.emit_expr(&name);
                 write!(self.f, "(").unwrap();
 
@@ -1338,24 +1619,3 @@ impl Emitter {
         }
     }
 }
-
-impl CFile {
-    pub fn is_newer_than(&self, target: &str) -> bool {
-        let itarget = match std::fs::metadata(&target) {
-            Ok(v)  => v,
-            Err(_) => return true,
-        };
-        let itarget = itarget.modified().expect(&format!("cannot stat {}", target));
-
-        for source in &self.sources {
-            let isource = std::fs::metadata(source).expect(&format!("cannot stat {:?}", source));
-
-            let isource = isource.modified().expect(&format!("cannot stat {:?}", source));
-
-            if isource > itarget {
-                return true;
-            }
-        }
-        return false;
-    }
-}
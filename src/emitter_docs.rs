@@ -1,203 +1,344 @@
 use super::project::{Project};
-use std::fs;
-use super::flatten;
 use super::ast;
-use super::make;
-use std::io::{Write};
-use std::collections::HashSet;
-use std::path::PathBuf;
+use super::loader;
+use super::fmt::tags;
 use super::name::Name;
-use super::parser::{self, emit_error};
-use serde::Serialize;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use askama::Template;
 
-pub struct CFile {
-    pub name:       Name,
-    pub filepath:   String,
-    pub sources:    HashSet<PathBuf>,
-    pub deps:       HashSet<Name>,
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    Html,
+    Markdown,
 }
 
-pub struct Emitter{
-    p:              String,
-    project_name:   String,
-    f:              fs::File,
-    module:         flatten::Module,
-    cur_loc:        Option<ast::Location>,
-    register_structs:   Vec<String>,
-    register_fns:   Vec<(String, String)>,
+impl Format {
+    fn ext(&self) -> &'static str {
+        match self {
+            Format::Html        => "html",
+            Format::Markdown    => "md",
+        }
+    }
 }
 
-pub fn outname(_project: &Project, stage: &make::Stage, module: &flatten::Module) -> String {
-    format!("target/{}/docs/{}.html", stage, module.name.0[1..].join("_"))
+pub struct Item {
+    kind:       &'static str,
+    name:       String,
+    vis:        &'static str,
+    signature:  String,
+    // links to the types used in the signature. markdown can't link inside code
+    refs:       Vec<String>,
+    doc:        String,
+    // the message of @deprecated, if it is
+    deprecated: String,
+    // the feature of @unstable
+    unstable:   String,
 }
 
-impl Emitter {
-    pub fn new(project: &Project, stage: make::Stage , module: flatten::Module) -> Self {
+struct Emitter<'a> {
+    format:     Format,
+    // modules that have a page to link to
+    pages:      &'a HashSet<Name>,
+    refs:       Vec<String>,
+}
 
-        std::fs::create_dir_all(format!("target/{}/docs/", stage)).unwrap();
-        let p = outname(project, &stage, &module);
-        let f = fs::File::create(&p).expect(&format!("cannot create {}", p));
+fn page(module: &Name) -> String {
+    module.0[1..].join("_")
+}
 
-        Emitter{
-            p,
-            project_name: project.name.clone(),
-            f,
-            module,
-            cur_loc: None,
-            register_structs:   Vec::new(),
-            register_fns:       Vec::new(),
+fn title(module: &Name) -> String {
+    module.0[1..].join("::")
+}
 
-        }
-    }
+pub fn outname(format: Format, module: &Name) -> String {
+    format!("target/doc/{}.{}", page(module), format.ext())
+}
 
-    pub fn emit(mut self) {
-        let module = self.module.clone();
-        write!(self.f, r#"
-<h1> module {} </h1>
-"#,
-self.module.name.0[1..].join("::")).unwrap();
+/// render every zz module and an index into target/doc/. returns the path of the index
+pub fn emit(project: &Project, format: Format, modules: &HashMap<Name, loader::Module>) -> String {
+    std::fs::create_dir_all("target/doc/").unwrap();
 
+    let mut names : Vec<&Name> = modules.iter().filter_map(|(name, m)| match m {
+        loader::Module::ZZ(_) => Some(name),
+        loader::Module::C(_)  => None,
+    }).collect();
+    names.sort_unstable();
+    let pages : HashSet<Name> = names.iter().map(|n|(*n).clone()).collect();
 
-        for (d,complete) in &module.d {
-            if d.vis == ast::Visibility::Object {
-                continue;
-            }
-            if complete != &flatten::TypeComplete::Complete {
-                continue
-            }
-            match d.def {
-                ast::Def::Function{..} => {
-                    self.emit_fndecl(&d);
-                },
-                _ => (),
-            }
-        }
-        for (d,complete) in &module.d {
-            if d.vis == ast::Visibility::Object {
-                continue;
+    let mut index = Vec::new();
+    for name in names {
+        let md = match &modules[name] {
+            loader::Module::ZZ(md) => md,
+            loader::Module::C(_) => unreachable!(),
+        };
+        let mut em = Emitter{
+            format,
+            pages: &pages,
+            refs: Vec::new(),
+        };
+        let imports = md.imports.iter().map(|i|em.import(i)).collect();
+        let items : Vec<Item> = md.locals.iter().filter_map(|l|{
+            let mut item = em.item(l)?;
+            if let Some(d) = md.deprecated.get(&l.name) {
+                item.deprecated = d.message.clone();
             }
-            if complete != &flatten::TypeComplete::Complete {
-                continue
+            if let Some(u) = md.unstable.get(&l.name) {
+                item.unstable = u.feature.clone();
             }
+            Some(item)
+        }).collect();
+        index.push((title(name), format!("{}.{}", page(name), format.ext()), items.len()));
 
-            let mut dmodname = Name::from(&d.name);
-            dmodname.pop();
-            if dmodname != module.name {
-                continue;
-            }
+        let rendered = match format {
+            Format::Html        => ModuleHtml{name: title(name), imports, items}.render(),
+            Format::Markdown    => ModuleMd{name: title(name), imports, items}.render(),
+        };
+        let p = outname(format, name);
+        std::fs::write(&p, rendered.unwrap() + "\n").expect(&format!("cannot write {}", p));
+    }
 
+    let rendered = match format {
+        Format::Html        => IndexHtml{project: project.name.clone(), modules: index}.render(),
+        Format::Markdown    => IndexMd{project: project.name.clone(), modules: index}.render(),
+    };
+    let p = format!("target/doc/index.{}", format.ext());
+    std::fs::write(&p, rendered.unwrap() + "\n").expect(&format!("cannot write {}", p));
+    p
+}
 
-            match d.def {
-                ast::Def::Macro{..} => {}
-                ast::Def::Const{..} => {
-                    self.emit_const(&d)
-                }
-                ast::Def::Static{..} => {
-                    self.emit_static(&d)
-                }
-                ast::Def::Struct{..} => {
-                    self.emit_struct(&d, None);
-                    if let Some(vs) = module.typevariants.get(&Name::from(&d.name)) {
-                        for v in vs {
-                            let mut d = d.clone();
-                            d.name = format!("{}_{}", d.name, v);
-                            self.emit_struct(&d, Some(*v));
-                        }
-                    }
-                }
-                ast::Def::Enum{..} => {
-                    self.emit_enum(&d)
-                }
-                ast::Def::Function{..} => {
-                }
-                ast::Def::Fntype{..} => {
-                    self.emit_fntype(&d);
-                }
-                ast::Def::Theory{..} => {}
-                ast::Def::Testcase {..} => {}
-                ast::Def::Include{..} => {}
-            }
-            write!(self.f, "\n").unwrap();
+impl<'a> Emitter<'a> {
+    fn text(&self, s: &str) -> String {
+        match self.format {
+            Format::Html        => s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;"),
+            Format::Markdown    => s.to_string(),
         }
     }
 
-    pub fn emit_static(&mut self, ast: &ast::Local) {
-        let (_typed, _expr, _tags, storage, _array) = match &ast.def {
-            ast::Def::Static{typed, expr, tags, storage, array} => (typed, expr, tags, storage, array),
-            _ => unreachable!(),
-        };
+    fn link(&self, module: &Name, anchor: Option<&str>, text: &str) -> String {
+        let mut target = format!("{}.{}", page(module), self.format.ext());
+        if let Some(anchor) = anchor {
+            target = format!("{}#{}", target, anchor);
+        }
+        match self.format {
+            Format::Html        => format!("<a href=\"{}\">{}</a>", target, self.text(text)),
+            Format::Markdown    => format!("[{}]({})", text, target),
+        }
+    }
 
-        match storage {
-            ast::Storage::Atomic => {
-                return;
-            },
-            ast::Storage::ThreadLocal => {
-                return;
-            },
-            ast::Storage::Static  => (),
+    fn type_name(&mut self, name: &Name) -> String {
+        let short = name.0.last().map(|n| n.to_string()).unwrap_or_default();
+        let mut module = name.clone();
+        module.pop();
+        if !self.pages.contains(&module) {
+            return self.text(&short);
+        }
+        let link = self.link(&module, Some(&short), &short);
+        match self.format {
+            Format::Html => link,
+            Format::Markdown => {
+                if !self.refs.contains(&link) {
+                    self.refs.push(link);
+                }
+                short
+            }
         }
     }
 
-    pub fn emit_const(&mut self, ast: &ast::Local) {
-        let (_typed, _expr) = match &ast.def {
-            ast::Def::Const{typed, expr} => (typed, expr),
-            _ => unreachable!(),
+    fn typed(&mut self, t: &ast::Typed) -> String {
+        let mut s = match &t.t {
+            ast::Type::Other(n) => self.type_name(n),
+            _ => format!("{}", ast::Typed{ptr: Vec::new(), tail: ast::Tail::None, ..t.clone()}),
         };
-
+        match &t.tail {
+            ast::Tail::None         => (),
+            ast::Tail::Dynamic      => s.push('+'),
+            ast::Tail::Static(v, _) => s.push_str(&format!("+{}", v)),
+            ast::Tail::Bind(v, _)   => s.push_str(&format!("+{}", v)),
+        }
+        for ptr in &t.ptr {
+            for tag in tags(&ptr.tags) {
+                s.push(' ');
+                s.push_str(&self.text(&tag));
+            }
+            s.push('*');
+        }
+        s
     }
 
-    pub fn emit_enum(&mut self, ast: &ast::Local) {
-        let names = match &ast.def {
-            ast::Def::Enum{names} => (names),
-            _ => unreachable!(),
-        };
+    fn named(&mut self, t: &ast::Typed, tg: &ast::Tags, name: &str) -> String {
+        let mut s = self.typed(t);
+        for tag in tags(tg) {
+            s.push(' ');
+            s.push_str(&self.text(&tag));
+        }
+        s.push(' ');
+        s.push_str(name);
+        s
     }
 
-    pub fn emit_struct(&mut self, ast: &ast::Local, _tail_variant: Option<u64>) {
-        let (fields, _packed, _tail, _union, impls) = match &ast.def {
-            ast::Def::Struct{fields, packed, tail, union, impls, ..} => (fields, packed, tail, union, impls),
-            _ => unreachable!(),
-        };
+    fn signature(&mut self, head: String, args: &[ast::NamedArg], vararg: bool, ret: &Option<ast::AnonArg>) -> String {
+        let mut r : Vec<String> = args.iter().map(|a| self.named(&a.typed, &a.tags, &a.name)).collect();
+        if vararg {
+            r.push("...".to_string());
+        }
+        let mut s = format!("{}({})", head, r.join(", "));
+        if let Some(ret) = ret {
+            s.push_str(&self.text(" -> "));
+            s.push_str(&self.typed(&ret.typed));
+            match &ret.array {
+                None => (),
+                Some(ast::Expression::Literal{v, ..}) => s.push_str(&format!("[{}]", v)),
+                Some(ast::Expression::Name(n)) => {
+                    let n = self.typed(n);
+                    s.push_str(&format!("[{}]", n));
+                }
+                Some(_) => s.push_str("[]"),
+            }
+        }
+        s
     }
 
-    pub fn emit_fntype(&mut self, ast: &ast::Local) {
-        let (_ret, _args, _vararg, _attr) = match &ast.def {
-            ast::Def::Fntype{ret, args, vararg, attr, ..} => (ret, args, *vararg, attr),
-            _ => unreachable!(),
-        };
+    fn import(&mut self, import: &ast::Import) -> String {
+        let mut s = String::from("using ");
+        if import.name.0.len() > 2 && import.name.0[1] == "ext" {
+            match self.format {
+                Format::Html        => s.push_str(&self.text(&import.name.0[2..].join("::"))),
+                Format::Markdown    => s.push_str(&format!("`{}`", import.name.0[2..].join("::"))),
+            }
+        } else if self.pages.contains(&import.name) {
+            s.push_str(&self.link(&import.name, None, &title(&import.name)));
+        } else {
+            s.push_str(&self.text(&title(&import.name)));
+        }
+        if !import.local.is_empty() {
+            let local : Vec<String> = import.local.iter().map(|(name, alias)| match alias {
+                Some(alias) => format!("{} as {}", name, alias),
+                None        => name.clone(),
+            }).collect();
+            s.push_str(&format!("::{{{}}}", local.join(", ")));
+        }
+        if let Some(alias) = &import.alias {
+            s.push_str(&format!(" as {}", alias));
+        }
+        s
     }
 
-    pub fn emit_fndecl(&mut self, ast: &ast::Local) {
-        let (ret, args, _body, _vararg, _attr) = match &ast.def {
-            ast::Def::Function{ret, args, body, vararg, attr, ..} => (ret, args, body, *vararg, attr),
-            _ => unreachable!(),
+    fn item(&mut self, local: &ast::Local) -> Option<Item> {
+        let vis = match local.vis {
+            ast::Visibility::Object => return None,
+            ast::Visibility::Shared => "pub",
+            ast::Visibility::Package => "pub(package)",
+            ast::Visibility::Export => "export",
         };
+        let name = Name::from(&local.name).0.last().map(|n| n.to_string()).unwrap_or_default();
+        self.refs.clear();
 
-        let mut sargs = HashMap::new();
-        for arg in args {
-            sargs.insert(arg.name.clone(), format!("{}", arg.typed));
-        }
-        let mut tpl = FunctionHtml {
-            doc:  ast.doc.clone().replace("\n", "<br>"),
-            name: Name::from(&ast.name).0.last().unwrap().clone(),
-            ret:  ret.as_ref().map(|r|{
-                format!("{}", r.typed)
-            }),
-            args: sargs,
+        let (kind, signature) = match &local.def {
+            ast::Def::Function{ret, args, vararg, ..} => {
+                ("fn", self.signature(format!("fn {}", name), args, *vararg, ret))
+            }
+            ast::Def::Fntype{ret, args, vararg, ..} => {
+                ("fntype", self.signature(format!("fntype {}", name), args, *vararg, ret))
+            }
+            ast::Def::Theory{ret, args, ..} => {
+                ("theory", self.signature(format!("theory {}", name), args, false, ret))
+            }
+            ast::Def::Macro{args, ..} => {
+                ("macro", format!("macro {}({})", name, args.join(", ")))
+            }
+            ast::Def::Closure{fntype, target, ..} => {
+                let s = format!("closure {} = closure_for({}, {})", name, self.typed(fntype), self.typed(target));
+                ("closure", s)
+            }
+            ast::Def::TypeAlias{typed} => {
+                let s = format!("type {} = {}", name, self.typed(typed));
+                ("type", s)
+            }
+            ast::Def::Const{..} | ast::Def::Static{..} => {
+                let kind = if let ast::Def::Const{..} = local.def { "const" } else { "static" };
+                let src = local.loc.text().trim().trim_end_matches(';').to_string();
+                let src = src.trim_start_matches("export").trim_start_matches("pub").trim().to_string();
+                (kind, self.text(&src))
+            }
+            ast::Def::Enum{names, ..} => {
+                let mut s = format!("enum {} {{\n", name);
+                for (name, literal) in names {
+                    match literal {
+                        Some(v) => s.push_str(&format!("    {} = {},\n", name, v)),
+                        None    => s.push_str(&format!("    {},\n", name)),
+                    }
+                }
+                s.push('}');
+                ("enum", s)
+            }
+            ast::Def::Struct{fields, packed, tail, union, ..} => {
+                let kind = if *union { "union" } else { "struct" };
+                let mut s = format!("{} {}", kind, name);
+                if let ast::Tail::Dynamic = tail {
+                    s.push('+');
+                }
+                s.push_str(" {\n");
+                for field in fields {
+                    s.push_str(&format!("    {}", self.named(&field.typed, &field.tags, &field.name)));
+                    match &field.array {
+                        None => (),
+                        Some(Some(ast::Expression::Literal{v, ..})) => s.push_str(&format!("[{}]", v)),
+                        Some(Some(ast::Expression::Name(n))) => {
+                            let n = self.typed(n);
+                            s.push_str(&format!("[{}]", n));
+                        }
+                        Some(_) => s.push_str("[]"),
+                    }
+                    s.push_str(";\n");
+                }
+                s.push('}');
+                if *packed {
+                    s.push_str(" packed");
+                }
+                (kind, s)
+            }
+            ast::Def::Testcase{..} | ast::Def::Test{..} | ast::Def::Include{..} => return None,
         };
-        write!(self.f, "{}", tpl.render().unwrap()).unwrap();
+
+        Some(Item{
+            kind,
+            name,
+            vis,
+            signature: format!("{} {}", vis, signature),
+            refs: std::mem::take(&mut self.refs),
+            doc: local.doc.lines().map(|l|l.trim()).collect::<Vec<&str>>().join("\n").trim().to_string(),
+            deprecated: String::new(),
+            unstable:   String::new(),
+        })
     }
 }
 
+#[derive(Template)]
+#[template(path = "module.html")]
+struct ModuleHtml {
+    name:       String,
+    imports:    Vec<String>,
+    items:      Vec<Item>,
+}
 
 #[derive(Template)]
-#[template(path = "function.html")]
-struct FunctionHtml {
-    name:   String,
-    doc:    String,
-    ret:    Option<String>,
-    args:   HashMap<String, String>,
+#[template(path = "module.md")]
+struct ModuleMd {
+    name:       String,
+    imports:    Vec<String>,
+    items:      Vec<Item>,
 }
 
+#[derive(Template)]
+#[template(path = "index.html")]
+struct IndexHtml {
+    project:    String,
+    modules:    Vec<(String, String, usize)>,
+}
+
+#[derive(Template)]
+#[template(path = "index.md")]
+struct IndexMd {
+    project:    String,
+    modules:    Vec<(String, String, usize)>,
+}
@@ -3,7 +3,7 @@ use std::fs;
 use super::flatten;
 use super::ast;
 use super::make;
-use std::io::{Write};
+use std::io::{self, Write};
 use std::collections::HashSet;
 use std::path::PathBuf;
 use super::name::Name;
@@ -20,7 +20,7 @@ pub struct CFile {
 pub struct Emitter{
     p:              String,
     project_name:   String,
-    f:              fs::File,
+    f:              io::BufWriter<fs::File>,
     module:         flatten::Module,
     cur_loc:        Option<ast::Location>,
     register_structs:   Vec<String>,
@@ -177,7 +177,7 @@ impl Emitter {
 
         std::fs::create_dir_all(format!("target/{}/js/", stage)).unwrap();
         let p = outname(project, &stage, &module);
-        let f = fs::File::create(&p).expect(&format!("cannot create {}", p));
+        let f = io::BufWriter::new(fs::File::create(&p).expect(&format!("cannot create {}", p)));
 
         Emitter{
             p,
@@ -236,7 +236,7 @@ impl Emitter {
                     "ICE: untyped literal ended up in emitter",
                     &[(name.loc.clone(), format!("this should have been resolved earlier"))]
                     );
-                std::process::exit(9);
+                parser::abort();
             }
         }
     }
@@ -283,7 +283,7 @@ impl Emitter {
                     "ICE: untyped literal ended up in emitter",
                     &[(typed.loc.clone(), format!("this should have been resolved earlier"))]
                     );
-                std::process::exit(9);
+                parser::abort();
             }
         }
     }
@@ -346,7 +346,7 @@ impl Emitter {
                     "ICE: untyped literal ended up in emitter",
                     &[(typed.loc.clone(), format!("this should have been resolved earlier"))]
                     );
-                std::process::exit(9);
+                parser::abort();
             }
         }
     }
@@ -361,9 +361,12 @@ impl Emitter {
         if let Some(an) = self.module.aliases.get(&s) {
             return an.clone();
         }
+        if let Some(symbol) = self.module.symbols.get(&s) {
+            return symbol.clone();
+        }
 
         if s.0[1] == "ext" {
-            return s.0.last().unwrap().clone();
+            return s.0.last().unwrap().to_string();
         }
 
         let mut s = s.clone();
@@ -442,6 +445,9 @@ self.module.name.0[1..].join("_")).unwrap();
                 ast::Def::Theory{..} => {}
                 ast::Def::Testcase {..} => {}
                 ast::Def::Include{..} => {}
+                ast::Def::Closure{..} => {}
+                ast::Def::Test{..} => {}
+                ast::Def::TypeAlias{..} => {}
             }
             write!(self.f, "\n").unwrap();
         }
@@ -461,12 +467,13 @@ self.module.name.0[1..].join("_")).unwrap();
         }
         write!(self.f, "    return exports;\n").unwrap();
         write!(self.f, "}}\n").unwrap();
+        self.f.flush().unwrap();
     }
 
     pub fn emit_static(&mut self, ast: &ast::Local) {
         self.emit_loc(&ast.loc);
         let (_typed, _expr, _tags, storage, _array) = match &ast.def {
-            ast::Def::Static{typed, expr, tags, storage, array} => (typed, expr, tags, storage, array),
+            ast::Def::Static{typed, expr, tags, storage, array, ..} => (typed, expr, tags, storage, array),
             _ => unreachable!(),
         };
 
@@ -477,7 +484,7 @@ self.module.name.0[1..].join("_")).unwrap();
             ast::Storage::ThreadLocal => {
                 return;
             },
-            ast::Storage::Static  => (),
+            ast::Storage::Static | ast::Storage::Const => (),
         }
 
     }
@@ -485,7 +492,7 @@ self.module.name.0[1..].join("_")).unwrap();
     pub fn emit_const(&mut self, ast: &ast::Local) {
         self.emit_loc(&ast.loc);
         let (_typed, _expr) = match &ast.def {
-            ast::Def::Const{typed, expr} => (typed, expr),
+            ast::Def::Const{typed, expr, ..} => (typed, expr),
             _ => unreachable!(),
         };
 
@@ -494,7 +501,7 @@ self.module.name.0[1..].join("_")).unwrap();
     pub fn emit_enum(&mut self, ast: &ast::Local) {
         self.emit_loc(&ast.loc);
         let names = match &ast.def {
-            ast::Def::Enum{names} => (names),
+            ast::Def::Enum{names, ..} => (names),
             _ => unreachable!(),
         };
     }
@@ -506,7 +513,7 @@ self.module.name.0[1..].join("_")).unwrap();
             _ => unreachable!(),
         };
 
-        let shortname   = Name::from(&ast.name).0.last().unwrap().clone();
+        let shortname   = Name::from(&ast.name).0.last().unwrap().to_string();
         let longname    = self.to_local_name(&Name::from(&ast.name));
 
         // getters and setters
@@ -704,7 +711,7 @@ napi_value js_new_{}(napi_env env, napi_callback_info info) {{
             _ => unreachable!(),
         };
 
-        let shortname   = Name::from(&ast.name).0.last().unwrap().clone();
+        let shortname   = Name::from(&ast.name).0.last().unwrap().to_string();
         let longname    = self.to_local_name(&Name::from(&ast.name));
         self.register_fns.push((shortname.clone(), format!("js_{}", longname)));
 
@@ -852,9 +859,22 @@ napi_value js_{}(napi_env env, napi_callback_info info) {{
                 self.write_escaped_literal(*v, false);
                 write!(self.f, "'").unwrap();
             }
-            ast::Expression::Literal {loc, v} => {
+            ast::Expression::Literal {loc, v, value} => {
                 self.emit_loc(&loc);
-                write!(self.f, "    {}", v).unwrap();
+                match value.suffix() {
+                    Some(t) => {
+                        let t = self.to_local_typed_name(&ast::Typed{
+                            t:      t.clone(),
+                            loc:    loc.clone(),
+                            ptr:    Vec::new(),
+                            tail:   ast::Tail::None,
+                        });
+                        write!(self.f, "    (({}){})", t, v).unwrap();
+                    }
+                    None => {
+                        write!(self.f, "    {}", v).unwrap();
+                    }
+                }
             }
             ast::Expression::Call { loc, name, args, emit , ..} => {
                 match emit {
@@ -864,7 +884,7 @@ napi_value js_{}(napi_env env, napi_callback_info info) {{
                         emit_error(format!("{}", message), &[
                             (loc.clone(), "here")
                         ]);
-                        std::process::exit(9);
+                        parser::abort();
                     }
                 };
 
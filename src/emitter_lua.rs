@@ -0,0 +1,279 @@
+use super::project::{Project};
+use std::fs;
+use super::flatten;
+use super::ast;
+use super::make;
+use std::io::{self, Write};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use super::name::Name;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// zz build --emit lua
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+pub struct Emitter{
+    f:              io::BufWriter<fs::File>,
+    // the shared library artifact the bindings load
+    lib_name:       String,
+    module:         flatten::Module,
+    // types declared in the cdef so far. anything else can only be passed as void*
+    declared:       HashSet<Name>,
+    // declared, but without a layout
+    opaque:         HashSet<Name>,
+}
+
+const KEYWORDS : &[&str] = &[
+    "and", "break", "do", "else", "elseif", "end", "false", "for", "function", "goto", "if", "in",
+    "local", "nil", "not", "or", "repeat", "return", "then", "true", "until", "while",
+];
+
+pub fn outname(_project: &Project, stage: &make::Stage, module: &flatten::Module) -> String {
+    format!("target/{}/lua/{}.lua", stage, module.name.0[1..].join("_"))
+}
+
+// M.name, or M["name"] if name is a lua keyword
+fn field(table: &str, s: &str) -> String {
+    if KEYWORDS.contains(&s) {
+        format!("{}[\"{}\"]", table, s)
+    } else {
+        format!("{}.{}", table, s)
+    }
+}
+
+fn comment(doc: &str, indent: &str) -> String {
+    let mut s = String::new();
+    for line in doc.trim().lines() {
+        s.push_str(&format!("{}-- {}\n", indent, line.trim()));
+    }
+    s
+}
+
+impl Emitter {
+    pub fn new(project: &Project, lib_name: &str, stage: make::Stage , module: flatten::Module) -> Self {
+
+        std::fs::create_dir_all(format!("target/{}/lua/", stage)).unwrap();
+        let p = outname(project, &stage, &module);
+        let f = io::BufWriter::new(fs::File::create(&p).expect(&format!("cannot create {}", p)));
+
+        Emitter{
+            f,
+            lib_name: lib_name.to_string(),
+            module,
+            declared: HashSet::new(),
+            opaque: HashSet::new(),
+        }
+    }
+
+    fn is_local(&self, name: &str) -> bool {
+        let mut dmodname = Name::from(name);
+        dmodname.pop();
+        dmodname == self.module.name
+    }
+
+    fn to_local_name(&self, s: &Name) -> String {
+        if let Some(an) = self.module.aliases.get(s) {
+            return an.clone();
+        }
+        if let Some(symbol) = self.module.symbols.get(s) {
+            return symbol.clone();
+        }
+        if s.0[1] == "ext" {
+            return s.0.last().unwrap().to_string();
+        }
+        let mut s = s.clone();
+        s.0.remove(0);
+        s.0.join("_")
+    }
+
+    /// the c declaration of a zz type, or None if luajit can't express it
+    fn to_ctype(&self, typed: &ast::Typed, name: &str) -> Option<String> {
+        let base = match &typed.t {
+            ast::Type::U8   => "uint8_t".to_string(),
+            ast::Type::U16  => "uint16_t".to_string(),
+            ast::Type::U32  => "uint32_t".to_string(),
+            ast::Type::U64  => "uint64_t".to_string(),
+            ast::Type::I8   => "int8_t".to_string(),
+            ast::Type::I16  => "int16_t".to_string(),
+            ast::Type::I32  => "int32_t".to_string(),
+            ast::Type::I64  => "int64_t".to_string(),
+            ast::Type::Int  => "int".to_string(),
+            ast::Type::UInt => "unsigned int".to_string(),
+            ast::Type::ISize=> "intptr_t".to_string(),
+            ast::Type::USize=> "size_t".to_string(),
+            ast::Type::Bool => "bool".to_string(),
+            ast::Type::F32  => "float".to_string(),
+            ast::Type::F64  => "double".to_string(),
+            ast::Type::Other(n) if n.0.len() > 1 && n.0[1] == "ext" => {
+                match n.0.last().unwrap().as_str() {
+                    v @ "char" | v @ "void" | v @ "int" | v @ "long" | v @ "size_t" | v @ "float" | v @ "double" => v.to_string(),
+                    _ if !typed.ptr.is_empty() => "void".to_string(),
+                    _ => return None,
+                }
+            }
+            ast::Type::Other(n) if self.declared.contains(n) => {
+                if typed.ptr.is_empty() && self.opaque.contains(n) {
+                    return None;
+                }
+                self.to_local_name(n)
+            }
+            ast::Type::Other(_) if !typed.ptr.is_empty() => "void".to_string(),
+            _ => return None,
+        };
+
+        let mut s = base;
+        for ptr in &typed.ptr {
+            if ptr.tags.contains_key("mut") {
+                s.push('*');
+            } else {
+                s = format!("const {}*", s);
+            }
+        }
+        if !name.is_empty() {
+            s.push(' ');
+            s.push_str(name);
+        }
+        Some(s)
+    }
+
+    pub fn emit(mut self) {
+        let module = self.module.clone();
+        debug!("emitting lua {}", module.name);
+
+        let mut exports = Vec::new();
+        let mut cdef = String::new();
+
+        for (d, complete) in &module.d {
+            if !self.is_local(&d.name) || d.vis != ast::Visibility::Export {
+                continue;
+            }
+            match &d.def {
+                ast::Def::Enum{names, ..} => {
+                    let cname = self.to_local_name(&Name::from(&d.name));
+                    cdef.push_str("typedef enum {\n");
+                    for (name, literal) in names {
+                        match literal {
+                            Some(v) => cdef.push_str(&format!("    {}_{} = {},\n", cname, name, v)),
+                            None    => cdef.push_str(&format!("    {}_{},\n", cname, name)),
+                        }
+                    }
+                    cdef.push_str(&format!("}} {};\n", cname));
+                    self.declared.insert(Name::from(&d.name));
+
+                    let mut e = comment(&d.doc, "");
+                    e.push_str(&format!("{} = {{\n", field("M", Name::from(&d.name).0.last().unwrap())));
+                    for (name, _) in names {
+                        if KEYWORDS.contains(&name.as_str()) {
+                            e.push_str(&format!("    [\"{}\"] = ffi.C.{}_{},\n", name, cname, name));
+                        } else {
+                            e.push_str(&format!("    {} = ffi.C.{}_{},\n", name, cname, name));
+                        }
+                    }
+                    e.push_str("}\n");
+                    exports.push(e);
+                }
+                ast::Def::Struct{fields, tail, union, packed, ..} => {
+                    let cname = self.to_local_name(&Name::from(&d.name));
+                    let kw = if *union { "union" } else { "struct" };
+                    cdef.push_str(&format!("typedef {} {}_t {};\n", kw, cname, cname));
+                    self.declared.insert(Name::from(&d.name));
+
+                    let mut body = Vec::new();
+                    if complete == &flatten::TypeComplete::Complete && tail == &ast::Tail::None {
+                        for field in fields {
+                            let decl = match (&field.array, self.to_ctype(&field.typed, &field.name)) {
+                                (None, Some(decl)) => decl,
+                                (Some(Some(ast::Expression::Literal{v, ..})), Some(decl)) => format!("{}[{}]", decl, v),
+                                _ => break,
+                            };
+                            body.push(decl);
+                        }
+                    }
+                    if body.len() == fields.len() && tail == &ast::Tail::None {
+                        if *packed {
+                            cdef.push_str(&format!("{} __attribute__((packed)) {}_t {{\n", kw, cname));
+                        } else {
+                            cdef.push_str(&format!("{} {}_t {{\n", kw, cname));
+                        }
+                        for decl in body {
+                            cdef.push_str(&format!("    {};\n", decl));
+                        }
+                        cdef.push_str("};\n");
+                    } else {
+                        self.opaque.insert(Name::from(&d.name));
+                    }
+
+                    let mut e = comment(&d.doc, "");
+                    e.push_str(&format!("{} = ffi.typeof(\"{}\")\n", field("M", Name::from(&d.name).0.last().unwrap()), cname));
+                    exports.push(e);
+                }
+                _ => (),
+            }
+        }
+
+        for (d, complete) in &module.d {
+            if !self.is_local(&d.name) || d.vis != ast::Visibility::Export || complete != &flatten::TypeComplete::Complete {
+                continue;
+            }
+            let (ret, args, vararg) = match &d.def {
+                ast::Def::Function{ret, args, vararg, ..} if !d.name.ends_with("::main") => (ret, args, *vararg),
+                _ => continue,
+            };
+            let cname = self.to_local_name(&Name::from(&d.name));
+            let shortname = Name::from(&d.name).0.last().unwrap().clone();
+
+            let mut cargs = Vec::new();
+            for arg in args {
+                match self.to_ctype(&arg.typed, &arg.name) {
+                    Some(v) => cargs.push(v),
+                    None => break,
+                }
+            }
+            let cret = match ret {
+                None => Some("void".to_string()),
+                Some(ret) => self.to_ctype(&ret.typed, ""),
+            };
+            let cret = match cret {
+                Some(v) if cargs.len() == args.len() => v,
+                _ => {
+                    cdef.push_str(&format!("// {}: cannot be expressed in luajit ffi\n", shortname));
+                    continue;
+                }
+            };
+            if vararg {
+                cargs.push("...".to_string());
+            }
+            if cargs.is_empty() {
+                cargs.push("void".to_string());
+            }
+            cdef.push_str(&format!("{} {}({});\n", cret, cname, cargs.join(", ")));
+
+            let mut e = comment(&d.doc, "");
+            e.push_str(&format!("{} = lib.{}\n", field("M", &shortname), cname));
+            exports.push(e);
+        }
+
+        write!(self.f, "-- generated by zz from {}. do not edit\n", module.name).unwrap();
+        write!(self.f, "local ffi = require(\"ffi\")\n\n").unwrap();
+        write!(self.f, "ffi.cdef[[\n{}]]\n\n", cdef).unwrap();
+        write!(self.f, "local here = debug.getinfo(1, \"S\").source:match(\"^@(.*/)\") or \"./\"\n").unwrap();
+        write!(self.f, "local name = ({{ OSX = \"{}\", Windows = \"{}\" }})[ffi.os] or \"{}\"\n",
+            make::shared_lib(&self.lib_name, "apple-darwin"),
+            make::shared_lib(&self.lib_name, "windows"),
+            make::shared_lib(&self.lib_name, "linux")).unwrap();
+        write!(self.f, "local lib = ffi.load(os.getenv(\"ZZ_LIB\") or (here .. \"../lib/\" .. name))\n\n").unwrap();
+        write!(self.f, "local M = {{ lib = lib }}\n\n").unwrap();
+        for e in exports {
+            write!(self.f, "{}\n", e).unwrap();
+        }
+        write!(self.f, "return M\n").unwrap();
+        self.f.flush().unwrap();
+    }
+}
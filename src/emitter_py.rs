@@ -0,0 +1,318 @@
+use super::project::{Project};
+use std::fs;
+use super::flatten;
+use super::ast;
+use super::make;
+use std::io::{self, Write};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use super::name::Name;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// zz build --emit python
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+pub struct Emitter{
+    f:              io::BufWriter<fs::File>,
+    // the shared library artifact the bindings load
+    lib_name:       String,
+    module:         flatten::Module,
+    // structs with a ctypes layout we can express
+    structs:        HashSet<String>,
+    enums:          HashSet<Name>,
+}
+
+const KEYWORDS : &[&str] = &[
+    "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del", "elif", "else", "except",
+    "finally", "for", "from", "global", "if", "import", "in", "is", "lambda", "nonlocal", "not", "or", "pass",
+    "raise", "return", "try", "while", "with", "yield", "None", "True", "False",
+];
+
+pub fn outname(_project: &Project, stage: &make::Stage, module: &flatten::Module) -> String {
+    format!("target/{}/py/{}.py", stage, module.name.0[1..].join("_"))
+}
+
+fn pyname(s: &str) -> String {
+    if KEYWORDS.contains(&s) {
+        format!("{}_", s)
+    } else {
+        s.to_string()
+    }
+}
+
+fn docstring(doc: &str, indent: &str) -> String {
+    let doc = doc.trim();
+    if doc.is_empty() {
+        return String::new();
+    }
+    let doc = doc.replace('\\', "\\\\").replace("\"\"\"", "\\\"\\\"\\\"");
+    let mut s = format!("{}\"\"\"", indent);
+    for (i, line) in doc.lines().enumerate() {
+        if i > 0 {
+            s.push('\n');
+            if !line.trim().is_empty() {
+                s.push_str(indent);
+            }
+        }
+        s.push_str(line.trim());
+    }
+    s.push_str("\"\"\"\n");
+    s
+}
+
+impl Emitter {
+    pub fn new(project: &Project, lib_name: &str, stage: make::Stage , module: flatten::Module) -> Self {
+
+        std::fs::create_dir_all(format!("target/{}/py/", stage)).unwrap();
+        let p = outname(project, &stage, &module);
+        let f = io::BufWriter::new(fs::File::create(&p).expect(&format!("cannot create {}", p)));
+
+        Emitter{
+            f,
+            lib_name: lib_name.to_string(),
+            module,
+            structs: HashSet::new(),
+            enums: HashSet::new(),
+        }
+    }
+
+    fn is_local(&self, name: &str) -> bool {
+        let mut dmodname = Name::from(name);
+        dmodname.pop();
+        dmodname == self.module.name
+    }
+
+    fn to_local_name(&self, s: &Name) -> String {
+        if let Some(an) = self.module.aliases.get(s) {
+            return an.clone();
+        }
+        if let Some(symbol) = self.module.symbols.get(s) {
+            return symbol.clone();
+        }
+        if s.0[1] == "ext" {
+            return s.0.last().unwrap().to_string();
+        }
+        let mut s = s.clone();
+        s.0.remove(0);
+        s.0.join("_")
+    }
+
+    /// the ctypes type for a zz type, or None if it can't be passed through ctypes
+    fn to_ctype(&self, typed: &ast::Typed) -> Option<String> {
+        if typed.ptr.len() > 1 {
+            return Some("ctypes.c_void_p".to_string());
+        }
+        let base = match &typed.t {
+            ast::Type::U8   => "ctypes.c_uint8",
+            ast::Type::U16  => "ctypes.c_uint16",
+            ast::Type::U32  => "ctypes.c_uint32",
+            ast::Type::U64  => "ctypes.c_uint64",
+            ast::Type::I8   => "ctypes.c_int8",
+            ast::Type::I16  => "ctypes.c_int16",
+            ast::Type::I32  => "ctypes.c_int32",
+            ast::Type::I64  => "ctypes.c_int64",
+            ast::Type::Int  => "ctypes.c_int",
+            ast::Type::UInt => "ctypes.c_uint",
+            ast::Type::ISize=> "ctypes.c_ssize_t",
+            ast::Type::USize=> "ctypes.c_size_t",
+            ast::Type::Bool => "ctypes.c_bool",
+            ast::Type::F32  => "ctypes.c_float",
+            ast::Type::F64  => "ctypes.c_double",
+            ast::Type::Other(n) if n.0.len() > 1 && n.0[1] == "ext" => {
+                match n.0.last().unwrap().as_str() {
+                    "char" if typed.ptr.len() == 1 => return Some("ctypes.c_char_p".to_string()),
+                    "char"  => "ctypes.c_char",
+                    "void" if typed.ptr.len() == 1 => return Some("ctypes.c_void_p".to_string()),
+                    "int"   => "ctypes.c_int",
+                    "long"  => "ctypes.c_long",
+                    "size_t"=> "ctypes.c_size_t",
+                    _ if typed.ptr.len() == 1 => return Some("ctypes.c_void_p".to_string()),
+                    _ => return None,
+                }
+            }
+            ast::Type::Other(n) if self.enums.contains(n) => "ctypes.c_int",
+            ast::Type::Other(n) => {
+                let local = n.0.last().unwrap().to_string();
+                if self.is_local(&n.to_string()) && self.structs.contains(&local) {
+                    if typed.ptr.is_empty() {
+                        return Some(local);
+                    }
+                    return Some(format!("ctypes.POINTER({})", local));
+                }
+                if typed.ptr.len() == 1 {
+                    return Some("ctypes.c_void_p".to_string());
+                }
+                return None;
+            }
+            _ => return None,
+        };
+        if typed.ptr.len() == 1 {
+            Some(format!("ctypes.POINTER({})", base))
+        } else {
+            Some(base.to_string())
+        }
+    }
+
+    pub fn emit(mut self) {
+        let module = self.module.clone();
+        debug!("emitting py {}", module.name);
+
+        for (d, _) in &module.d {
+            if let ast::Def::Enum{..} = d.def {
+                self.enums.insert(Name::from(&d.name));
+            }
+        }
+
+        write!(self.f, "# generated by zz from {}. do not edit\n", module.name).unwrap();
+        write!(self.f, "import ctypes\nimport os\nimport sys\n\n").unwrap();
+        write!(self.f,
+            "_name = {{\"darwin\": \"{}\", \"win32\": \"{}\"}}.get(sys.platform, \"{}\")\n",
+            make::shared_lib(&self.lib_name, "apple-darwin"),
+            make::shared_lib(&self.lib_name, "windows"),
+            make::shared_lib(&self.lib_name, "linux")).unwrap();
+        write!(self.f,
+            "_lib = ctypes.CDLL(os.environ.get(\"ZZ_LIB\") or os.path.join(os.path.dirname(os.path.abspath(__file__)), \"..\", \"lib\", _name))\n\n").unwrap();
+
+        for (d, complete) in &module.d {
+            if !self.is_local(&d.name) || d.vis != ast::Visibility::Export {
+                continue;
+            }
+            match d.def {
+                ast::Def::Enum{..} => self.emit_enum(d),
+                ast::Def::Struct{..} if complete == &flatten::TypeComplete::Complete => self.emit_struct(d),
+                _ => (),
+            }
+        }
+
+        for (d, complete) in &module.d {
+            if !self.is_local(&d.name) || d.vis != ast::Visibility::Export {
+                continue;
+            }
+            if complete != &flatten::TypeComplete::Complete {
+                continue;
+            }
+            if let ast::Def::Function{..} = d.def {
+                if !d.name.ends_with("::main") {
+                    self.emit_fn(d);
+                }
+            }
+        }
+        self.f.flush().unwrap();
+    }
+
+    fn emit_enum(&mut self, ast: &ast::Local) {
+        let names = match &ast.def {
+            ast::Def::Enum{names, ..} => names,
+            _ => unreachable!(),
+        };
+        let shortname = Name::from(&ast.name).0.last().unwrap().to_string();
+        write!(self.f, "class {}:\n", pyname(&shortname)).unwrap();
+        write!(self.f, "{}", docstring(&ast.doc, "    ")).unwrap();
+        // c enums without an explicit value count up from the previous one
+        let mut next = 0;
+        for (name, literal) in names {
+            let v = literal.unwrap_or(next);
+            write!(self.f, "    {} = {}\n", pyname(name), v).unwrap();
+            next = v + 1;
+        }
+        write!(self.f, "\n").unwrap();
+    }
+
+    fn emit_struct(&mut self, ast: &ast::Local) {
+        let (fields, packed, tail, union) = match &ast.def {
+            ast::Def::Struct{fields, packed, tail, union, ..} => (fields, *packed, tail, *union),
+            _ => unreachable!(),
+        };
+        let shortname = Name::from(&ast.name).0.last().unwrap().to_string();
+        let base = if union { "ctypes.Union" } else { "ctypes.Structure" };
+
+        let mut pyfields = Vec::new();
+        for field in fields {
+            let t = match self.to_ctype(&field.typed) {
+                Some(t) => t,
+                None => break,
+            };
+            match &field.array {
+                None => match field.bits {
+                    Some(bits) => pyfields.push(format!("(\"{}\", {}, {})", field.name, t, bits)),
+                    None => pyfields.push(format!("(\"{}\", {})", field.name, t)),
+                },
+                Some(Some(ast::Expression::Literal{v, ..})) => pyfields.push(format!("(\"{}\", {} * {})", field.name, t, v)),
+                Some(_) => break,
+            }
+        }
+
+        write!(self.f, "class {}({}):\n", shortname, base).unwrap();
+        write!(self.f, "{}", docstring(&ast.doc, "    ")).unwrap();
+
+        // anything we can't lay out is opaque, and has to be allocated with the size from the c side
+        if pyfields.len() != fields.len() || tail != &ast::Tail::None {
+            write!(self.f, "    @staticmethod\n").unwrap();
+            write!(self.f, "    def alloc(tail=0):\n").unwrap();
+            write!(self.f, "        size = ctypes.c_size_t.in_dll(_lib, \"sizeof_{}\").value + tail\n",
+                self.to_local_name(&Name::from(&ast.name))).unwrap();
+            write!(self.f, "        return ctypes.create_string_buffer(size)\n\n").unwrap();
+            return;
+        }
+
+        if packed {
+            write!(self.f, "    _pack_ = 1\n").unwrap();
+        }
+        write!(self.f, "    _fields_ = [\n").unwrap();
+        for f in pyfields {
+            write!(self.f, "        {},\n", f).unwrap();
+        }
+        write!(self.f, "    ]\n\n").unwrap();
+        self.structs.insert(shortname);
+    }
+
+    fn emit_fn(&mut self, ast: &ast::Local) {
+        let (ret, args, vararg) = match &ast.def {
+            ast::Def::Function{ret, args, vararg, ..} => (ret, args, *vararg),
+            _ => unreachable!(),
+        };
+        let shortname = Name::from(&ast.name).0.last().unwrap().to_string();
+        let cname = self.to_local_name(&Name::from(&ast.name));
+
+        let mut argtypes = Vec::new();
+        for arg in args {
+            match self.to_ctype(&arg.typed) {
+                Some(t) => argtypes.push(t),
+                None => {
+                    write!(self.f, "# {}: argument {} of type {} has no ctypes equivalent\n\n", shortname, arg.name, arg.typed).unwrap();
+                    return;
+                }
+            }
+        }
+        let restype = match ret {
+            None => "None".to_string(),
+            Some(ret) => match self.to_ctype(&ret.typed) {
+                Some(t) => t,
+                None => {
+                    write!(self.f, "# {}: return type {} has no ctypes equivalent\n\n", shortname, ret.typed).unwrap();
+                    return;
+                }
+            },
+        };
+
+        if !vararg {
+            write!(self.f, "_lib.{}.argtypes = [{}]\n", cname, argtypes.join(", ")).unwrap();
+        }
+        write!(self.f, "_lib.{}.restype = {}\n", cname, restype).unwrap();
+
+        let mut names : Vec<String> = args.iter().map(|a| pyname(&a.name)).collect();
+        if vararg {
+            names.push("*args".to_string());
+        }
+        write!(self.f, "def {}({}):\n", pyname(&shortname), names.join(", ")).unwrap();
+        write!(self.f, "{}", docstring(&ast.doc, "    ")).unwrap();
+        write!(self.f, "    return _lib.{}({})\n\n", cname, names.join(", ")).unwrap();
+    }
+}
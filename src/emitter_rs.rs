@@ -3,7 +3,7 @@ use std::fs;
 use super::flatten;
 use super::ast;
 use super::make;
-use std::io::{Write};
+use std::io::{self, Write};
 use std::collections::HashSet;
 use std::path::PathBuf;
 use super::name::Name;
@@ -19,7 +19,7 @@ pub struct CFile {
 
 pub struct Emitter{
     p:              String,
-    f:              fs::File,
+    f:              io::BufWriter<fs::File>,
     module:         flatten::Module,
     inside_macro:   bool,
     cur_loc:        Option<ast::Location>,
@@ -34,7 +34,7 @@ impl Emitter {
 
         std::fs::create_dir_all(format!("target/{}/rs/", stage)).unwrap();
         let p = outname(project, &stage, &module);
-        let f = fs::File::create(&p).expect(&format!("cannot create {}", p));
+        let f = io::BufWriter::new(fs::File::create(&p).expect(&format!("cannot create {}", p)));
 
         Emitter{
             p,
@@ -97,7 +97,7 @@ impl Emitter {
                     "ICE: untyped ended up in emitter",
                     &[(name.loc.clone(), format!("this should have been resolved earlier"))]
                     );
-                std::process::exit(9);
+                parser::abort();
             }
         })
     }
@@ -110,9 +110,12 @@ impl Emitter {
         if let Some(an) = self.module.aliases.get(&s) {
             return an.clone();
         }
+        if let Some(symbol) = self.module.symbols.get(&s) {
+            return symbol.clone();
+        }
 
         if s.0[1] == "ext" {
-            return s.0.last().unwrap().clone();
+            return s.0.last().unwrap().to_string();
         }
 
 
@@ -207,17 +210,21 @@ impl Emitter {
                 ast::Def::Theory{..} => {}
                 ast::Def::Testcase {..} => {}
                 ast::Def::Include{..} => {}
+                ast::Def::Closure{..} => {}
+                ast::Def::Test{..} => {}
+                ast::Def::TypeAlias{..} => {}
             }
             write!(self.f, "\n").unwrap();
         }
 
         write!(self.f, "}}\n").unwrap();
+        self.f.flush().unwrap();
     }
 
     pub fn emit_static(&mut self, ast: &ast::Local) {
         self.emit_loc(&ast.loc);
         let (_typed, _expr, _tags, storage, _array) = match &ast.def {
-            ast::Def::Static{typed, expr, tags, storage, array} => (typed, expr, tags, storage, array),
+            ast::Def::Static{typed, expr, tags, storage, array, ..} => (typed, expr, tags, storage, array),
             _ => unreachable!(),
         };
 
@@ -228,7 +235,7 @@ impl Emitter {
             ast::Storage::ThreadLocal => {
                 return;
             },
-            ast::Storage::Static  => (),
+            ast::Storage::Static | ast::Storage::Const => (),
         }
 
     }
@@ -236,7 +243,7 @@ impl Emitter {
     pub fn emit_const(&mut self, ast: &ast::Local) {
         self.emit_loc(&ast.loc);
         let (_typed, _expr) = match &ast.def {
-            ast::Def::Const{typed, expr} => (typed, expr),
+            ast::Def::Const{typed, expr, ..} => (typed, expr),
             _ => unreachable!(),
         };
 
@@ -245,7 +252,7 @@ impl Emitter {
     pub fn emit_enum(&mut self, ast: &ast::Local) {
         self.emit_loc(&ast.loc);
         let names = match &ast.def {
-            ast::Def::Enum{names} => (names),
+            ast::Def::Enum{names, ..} => (names),
             _ => unreachable!(),
         };
         write!(self.f, "enum {} {{\n", self.to_local_name(&Name::from(&ast.name))).unwrap();
@@ -348,7 +355,7 @@ impl {name} {{
                             "tail field has no be the last field in a struct",
                             &[(field.loc.clone(), format!("tail field would displace next field"))]
                             );
-                        std::process::exit(9);
+                        parser::abort();
                     }
                     if let Some(tt) = tail_variant {
                         write!(self.f, "    pub {} : [", field.name).unwrap();
@@ -516,9 +523,30 @@ impl {name} {{
                 self.write_escaped_literal(*v, false);
                 write!(self.f, "'").unwrap();
             }
-            ast::Expression::Literal {loc, v} => {
+            ast::Expression::Literal {loc, v, value} => {
                 self.emit_loc(&loc);
-                write!(self.f, "    {}", v).unwrap();
+                match value {
+                    // rust has no c octal, and takes the suffix as is
+                    ast::LiteralValue::Integer{value, radix, suffix} => {
+                        let sign = if *value < 0 { "-" } else { "" };
+                        let mut s = match radix {
+                            8   => format!("{}0o{:o}", sign, value.abs()),
+                            _   => v.clone(),
+                        };
+                        if let Some(t) = suffix {
+                            s += &self.to_local_typed_name(&ast::Typed{
+                                t:      t.clone(),
+                                loc:    loc.clone(),
+                                ptr:    Vec::new(),
+                                tail:   ast::Tail::None,
+                            }).unwrap();
+                        }
+                        write!(self.f, "    {}", s).unwrap();
+                    }
+                    _ => {
+                        write!(self.f, "    {}", v).unwrap();
+                    }
+                }
             }
             ast::Expression::Call { loc, name, args, emit , ..} => {
                 match emit {
@@ -528,7 +556,7 @@ impl {name} {{
                         emit_error(format!("{}", message), &[
                             (loc.clone(), "here")
                         ]);
-                        std::process::exit(9);
+                        parser::abort();
                     }
                 };
 
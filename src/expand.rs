@@ -6,6 +6,7 @@ use crate::ast;
 use ast::Tags;
 use super::parser::{emit_debug};
 use std::collections::HashSet;
+use super::nullable;
 
 
 
@@ -158,7 +159,7 @@ pub fn expand(module: &mut flatten::Module) -> Result<(), Error> {
                     complete,
                 )?;
             },
-            ast::Def::Fntype {..} => {
+            ast::Def::Fntype {..} | ast::Def::TypeAlias {..} => {
                 stack.alloc(
                     Name::from(&d.name),
                     ast::Typed{
@@ -243,6 +244,8 @@ pub fn expand(module: &mut flatten::Module) -> Result<(), Error> {
             },
             ast::Def::Testcase {..} => {},
             ast::Def::Include {..} => {},
+            ast::Def::Closure {..} => {},
+            ast::Def::Test {..} => {},
         }
     }
 
@@ -256,7 +259,7 @@ pub fn expand(module: &mut flatten::Module) -> Result<(), Error> {
                 for farg in args.iter_mut() {
                     if farg.typed.ptr.len() > 0 {
 
-                        if Name::from(&d.name).0.last() != Some(&"borrow".to_string()) {
+                        if Name::from(&d.name).0.last().map(|n| n != "borrow").unwrap_or(true) {
                             if let ast::Type::Other(name) = &farg.typed.t {
                                 if let Some(ast::Def::Struct{impls,..}) = stack.defs.get(name) {
                                     if let Some((fnname,_)) = impls.get("borrow") {
@@ -307,8 +310,8 @@ pub fn expand(module: &mut flatten::Module) -> Result<(), Error> {
                         }
 
 
-                        // safe is implicit unless the arg is marked unsafe
-                        if !farg.tags.contains("unsafe") {
+                        // safe is implicit unless the arg is marked unsafe or nullable
+                        if !farg.tags.contains("unsafe") && !nullable::nullable(&farg.typed) {
                             let loc = farg.typed.ptr[0].loc.clone();
                             let ast_safe = ast::Expression::Name(ast::Typed{
                                 t:      ast::Type::Other(Name::from("safe")),
@@ -523,11 +526,9 @@ impl Stack {
                         *assign = ast::Expression::ArrayInit {
                             loc: loc.clone(),
                             fields: vec![
-                                Box::new(ast::Expression::Literal{
-                                    loc: loc.clone(),
-                                    v:  "0".to_string(),
-                                }),
-                            ]
+                                Box::new(ast::Expression::literal(loc.clone(), "0".to_string())),
+                            ],
+                            index: Vec::new(),
                         };
                         body.insert(i + 1, stm);
                         i   += 1;
@@ -0,0 +1,391 @@
+/// flags enums
+
+use super::ast;
+use super::closure::find_def;
+use super::loader;
+use super::name::Name;
+use super::parser::emit_error;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// whether name is a flags enum
+fn is_flags(name: &Name, md: &ast::Module, modules: &HashMap<Name, loader::Module>) -> bool {
+    matches!(find_def(name, md, modules), Some(ast::Local{def: ast::Def::Enum{flags: true, ..}, ..}))
+}
+
+/// what an operand is, for mixing rules
+#[derive(PartialEq)]
+enum Kind {
+    Flags(Name),
+    Zero,
+    Other(String),
+    Unknown,
+}
+
+impl Kind {
+    fn describe(&self) -> String {
+        match self {
+            Kind::Flags(name)   => name.0.last().map(|n| n.to_string()).unwrap_or_default(),
+            Kind::Zero          => "0".to_string(),
+            Kind::Other(s)      => s.clone(),
+            Kind::Unknown       => "a value".to_string(),
+        }
+    }
+}
+
+struct Checker<'a> {
+    md:             &'a ast::Module,
+    modules:        &'a HashMap<Name, loader::Module>,
+    locals:         HashMap<String, ast::Typed>,
+    findings:       Vec<(ast::Location, String, String)>,
+}
+
+impl<'a> Checker<'a> {
+    fn report(&mut self, loc: &ast::Location, message: String, note: String) {
+        let seen = self.findings.iter().any(|(l, m, _)| l.file == loc.file && (l.start, l.end) == (loc.start, loc.end) && *m == message);
+        if !seen {
+            self.findings.push((loc.clone(), message, note));
+        }
+    }
+
+    fn of_type(&self, typed: &ast::Typed) -> Kind {
+        match &typed.t {
+            ast::Type::Other(name) if typed.ptr.is_empty() && is_flags(name, self.md, self.modules) => {
+                Kind::Flags(name.clone())
+            }
+            _ => Kind::Other(format!("{}", typed)),
+        }
+    }
+
+    fn field(&self, typed: &ast::Typed, name: &str) -> Option<&'a ast::Field> {
+        let def = match &typed.t {
+            ast::Type::Other(n) => find_def(n, self.md, self.modules)?,
+            _ => return None,
+        };
+        match &def.def {
+            ast::Def::Struct{fields, ..} => fields.iter().find(|f| f.name == name),
+            _ => None,
+        }
+    }
+
+    /// the type of expr, where it is known without the symbolic executor
+    fn typed(&self, expr: &ast::Expression) -> Option<ast::Typed> {
+        match expr {
+            ast::Expression::Name(ast::Typed{t: ast::Type::Other(name), ..}) if !name.is_absolute() => {
+                self.locals.get(&name.to_string()).cloned()
+            }
+            ast::Expression::Name(ast::Typed{t: ast::Type::Other(name), ..}) => match find_def(name, self.md, self.modules) {
+                Some(ast::Local{def: ast::Def::Static{typed, ..}, ..}) => Some(typed.clone()),
+                _ => None,
+            },
+            ast::Expression::MemberAccess{lhs, rhs, ..} => {
+                let typed = self.typed(lhs)?;
+                self.field(&typed, rhs).map(|f| f.typed.clone())
+            }
+            ast::Expression::Cast{into, ..} => Some(into.clone()),
+            ast::Expression::Call{name, ..} => match name.as_ref() {
+                ast::Expression::Name(ast::Typed{t: ast::Type::Other(name), ..}) => match find_def(name, self.md, self.modules) {
+                    Some(ast::Local{def: ast::Def::Function{ret: Some(ret), ..}, ..}) => Some(ret.typed.clone()),
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn kind(&self, expr: &ast::Expression) -> Kind {
+        match expr {
+            ast::Expression::Literal{value: ast::LiteralValue::Integer{value: 0, ..}, ..} => Kind::Zero,
+            ast::Expression::Literal{v, ..} => Kind::Other(v.clone()),
+            // Perm::Read
+            ast::Expression::Name(ast::Typed{t: ast::Type::Other(name), ..}) if name.is_absolute() => {
+                let mut parent = name.clone();
+                parent.pop();
+                match find_def(&parent, self.md, self.modules) {
+                    Some(ast::Local{def: ast::Def::Enum{flags: true, ..}, ..}) => Kind::Flags(parent),
+                    Some(ast::Local{def: ast::Def::Enum{..}, name, ..}) => Kind::Other(name.clone()),
+                    _ => self.typed(expr).map(|t| self.of_type(&t)).unwrap_or(Kind::Unknown),
+                }
+            }
+            ast::Expression::Infix{lhs, rhs, op: ast::InfixOperator::Bitor | ast::InfixOperator::Bitand | ast::InfixOperator::Bitxor, ..} => {
+                match self.kind(lhs) {
+                    Kind::Flags(name) => Kind::Flags(name),
+                    _ => self.kind(rhs),
+                }
+            }
+            ast::Expression::UnaryPre{op: ast::PrefixOperator::Bitnot, expr, ..} => self.kind(expr),
+            _ => self.typed(expr).map(|t| self.of_type(&t)).unwrap_or(Kind::Unknown),
+        }
+    }
+
+    /// values combined with op must be of the same flags enum, if one is
+    fn mixed(&mut self, op: &str, lhs: &ast::Expression, rhs: &ast::Expression, loc: &ast::Location) {
+        let l = self.kind(lhs);
+        let r = self.kind(rhs);
+        let bad = match (&l, &r) {
+            (Kind::Flags(a), Kind::Flags(b)) => a != b,
+            (Kind::Flags(_), Kind::Unknown) | (Kind::Unknown, Kind::Flags(_)) => false,
+            (Kind::Flags(_), Kind::Zero) | (Kind::Zero, Kind::Flags(_)) => false,
+            (Kind::Flags(_), _) | (_, Kind::Flags(_)) => true,
+            _ => false,
+        };
+        if bad {
+            self.report(loc, msg!("flags_mixed", op = op, lhs = l.describe(), rhs = r.describe()), msg!("flags_mixed.note"));
+        }
+    }
+
+    fn arithmetic(&mut self, op: &str, expr: &ast::Expression, loc: &ast::Location) {
+        if let Kind::Flags(name) = self.kind(expr) {
+            self.report(loc, msg!("flags_arithmetic", op = op, name = name.0.last().cloned().unwrap_or_default()), msg!("flags_arithmetic.note"));
+        }
+    }
+
+    fn expr(&mut self, expr: &ast::Expression) {
+        match expr {
+            ast::Expression::Name(_)
+            | ast::Expression::LiteralString{..}
+            | ast::Expression::LiteralChar{..}
+            | ast::Expression::Literal{..} => {}
+            ast::Expression::MemberAccess{lhs, ..} => self.expr(lhs),
+            ast::Expression::ArrayAccess{lhs, rhs, ..} => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            ast::Expression::Infix{lhs, rhs, op, loc} => {
+                self.expr(lhs);
+                self.expr(rhs);
+                let sym = match op {
+                    ast::InfixOperator::Bitor   => Some("|"),
+                    ast::InfixOperator::Bitand  => Some("&"),
+                    ast::InfixOperator::Bitxor  => Some("^"),
+                    ast::InfixOperator::Equals  => Some("=="),
+                    ast::InfixOperator::Nequals => Some("!="),
+                    _ => None,
+                };
+                match sym {
+                    Some(sym) => self.mixed(sym, lhs, rhs, loc),
+                    None if op.returns_boolean() => {}
+                    None => {
+                        let sym = match op {
+                            ast::InfixOperator::Add         => "+",
+                            ast::InfixOperator::Subtract    => "-",
+                            ast::InfixOperator::Multiply    => "*",
+                            ast::InfixOperator::Divide      => "/",
+                            ast::InfixOperator::Modulo      => "%",
+                            ast::InfixOperator::Shiftleft   => "<<",
+                            _                               => ">>",
+                        };
+                        self.arithmetic(sym, lhs, loc);
+                        self.arithmetic(sym, rhs, loc);
+                    }
+                }
+            }
+            ast::Expression::UnaryPre{op: ast::PrefixOperator::Increment, expr: inner, loc}
+            | ast::Expression::UnaryPost{op: ast::PostfixOperator::Increment, expr: inner, loc} => {
+                self.expr(inner);
+                self.arithmetic("++", inner, loc);
+            }
+            ast::Expression::UnaryPre{op: ast::PrefixOperator::Decrement, expr: inner, loc}
+            | ast::Expression::UnaryPost{op: ast::PostfixOperator::Decrement, expr: inner, loc} => {
+                self.expr(inner);
+                self.arithmetic("--", inner, loc);
+            }
+            ast::Expression::Call{name, args, ..} => {
+                self.expr(name);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            ast::Expression::Cast{expr, ..}
+            | ast::Expression::UnaryPre{expr, ..} => self.expr(expr),
+            ast::Expression::StructInit{fields, ..} => {
+                for (_, e) in fields {
+                    self.expr(e);
+                }
+            }
+            ast::Expression::ArrayInit{fields, ..} => {
+                for e in fields {
+                    self.expr(e);
+                }
+            }
+        }
+    }
+
+    /// a value stored into something of type typed
+    fn store(&mut self, typed: &ast::Typed, value: &ast::Expression, loc: &ast::Location) {
+        if let Kind::Flags(name) = self.of_type(typed) {
+            let k = self.kind(value);
+            if k != Kind::Flags(name.clone()) && k != Kind::Zero && k != Kind::Unknown {
+                let name = name.0.last().cloned().unwrap_or_default();
+                self.report(loc, msg!("flags_mixed", op = "=", lhs = name, rhs = k.describe()), msg!("flags_mixed.note"));
+            }
+        }
+    }
+
+    fn block(&mut self, block: &ast::Block) {
+        for stm in &block.statements {
+            self.statement(stm);
+        }
+    }
+
+    fn statement(&mut self, stm: &ast::Statement) {
+        match stm {
+            ast::Statement::Mark{lhs, ..} => self.expr(lhs),
+            ast::Statement::Label{..}
+            | ast::Statement::CBlock{..}
+            | ast::Statement::Break{..}
+            | ast::Statement::Continue{..} => {}
+            ast::Statement::Return{expr, ..} => {
+                if let Some(expr) = expr {
+                    self.expr(expr);
+                }
+            }
+            ast::Statement::Block(b) | ast::Statement::Unsafe(b) => self.block(b),
+            ast::Statement::Expr{expr, ..} => self.expr(expr),
+            ast::Statement::Var{name, typed, array, assign, loc, ..} => {
+                if let Some(Some(size)) = array {
+                    self.expr(size);
+                }
+                if let Some(assign) = assign {
+                    self.expr(assign);
+                    if array.is_none() {
+                        self.store(typed, assign, loc);
+                    }
+                }
+                self.locals.insert(name.clone(), typed.clone());
+            }
+            ast::Statement::Assign{lhs, rhs, op, loc} => {
+                self.expr(lhs);
+                self.expr(rhs);
+                match op {
+                    ast::AssignOperator::Eq => {
+                        if let Some(typed) = self.typed(lhs) {
+                            self.store(&typed, rhs, loc);
+                        }
+                    }
+                    ast::AssignOperator::Bitor => self.mixed("|=", lhs, rhs, loc),
+                    ast::AssignOperator::Bitand => self.mixed("&=", lhs, rhs, loc),
+                    ast::AssignOperator::Add => self.arithmetic("+=", lhs, loc),
+                    ast::AssignOperator::Sub => self.arithmetic("-=", lhs, loc),
+                }
+            }
+            ast::Statement::If{branches} => {
+                for (_, cond, body) in branches {
+                    if let Some(cond) = cond {
+                        self.expr(cond);
+                    }
+                    self.block(body);
+                }
+            }
+            ast::Statement::While{expr, body} => {
+                self.expr(expr);
+                self.block(body);
+            }
+            ast::Statement::For{e1, e2, e3, body} => {
+                for s in e1 {
+                    self.statement(s);
+                }
+                if let Some(e2) = e2 {
+                    self.expr(e2);
+                }
+                for s in e3 {
+                    self.statement(s);
+                }
+                self.block(body);
+            }
+            ast::Statement::Switch{expr, cases, default, ..} => {
+                self.expr(expr);
+                for (_, body) in cases {
+                    self.block(body);
+                }
+                if let Some(default) = default {
+                    self.block(default);
+                }
+            }
+        }
+    }
+}
+
+/// check every function of md. returns false if flags are mixed with other values or used in arithmetic
+pub fn check(md: &ast::Module, modules: &HashMap<Name, loader::Module>) -> bool {
+    let mut findings = Vec::new();
+    for local in &md.locals {
+        let (args, body) = match &local.def {
+            ast::Def::Function{args, body, ..} => (&args[..], body),
+            ast::Def::Test{body, ..} => (&[][..], body),
+            _ => continue,
+        };
+        let mut checker = Checker{
+            md,
+            modules,
+            locals:     HashMap::new(),
+            findings:   Vec::new(),
+        };
+        for arg in args {
+            checker.locals.insert(arg.name.clone(), arg.typed.clone());
+        }
+        checker.block(body);
+        findings.extend(checker.findings);
+    }
+
+    for (loc, message, note) in &findings {
+        emit_error(message, &[(loc.clone(), note)]);
+    }
+    findings.is_empty()
+}
+
+/// the source @derive(flags) generates for local, or why it can't
+pub fn derive(local: &ast::Local) -> Result<String, String> {
+    let names = match &local.def {
+        ast::Def::Enum{names, flags: true} => names,
+        _ => return Err(format!("{} is not a flags enum", local.name)),
+    };
+    let vis = match local.vis {
+        ast::Visibility::Export => "export ",
+        ast::Visibility::Shared => "pub ",
+        ast::Visibility::Package => "pub(package) ",
+        ast::Visibility::Object => "",
+    };
+    let name = &local.name;
+    let all = names.iter().fold(0u64, |all, (_, v)| all | v.unwrap_or(0));
+
+    let mut s = String::new();
+    writeln!(s, "// generated by @derive(flags) from {} in {}", name, local.loc.file).unwrap();
+    writeln!(s).unwrap();
+    writeln!(s, "/// whether v has no bits set that aren't a member of {}", name).unwrap();
+    writeln!(s, "{}fn {}_valid({} v) -> bool {{", vis, name, name).unwrap();
+    writeln!(s, "    return (as<u64>(v) | {}) == {};", all, all).unwrap();
+    writeln!(s, "}}").unwrap();
+    writeln!(s).unwrap();
+    writeln!(s, "/// the names of the members set in v, like {}, written 0 terminated to buf.",
+        names.iter().take(2).map(|(n, _)| n.as_str()).collect::<Vec<_>>().join("|")).unwrap();
+    writeln!(s, "/// returns the length of all names, which is size or more if they didn't fit").unwrap();
+    writeln!(s, "{}fn {}_to_str({} v, char mut* buf, usize size) -> usize", vis, name, name).unwrap();
+    writeln!(s, "    where len(buf) >= size").unwrap();
+    writeln!(s, "{{").unwrap();
+    writeln!(s, "    @{{{{").unwrap();
+    writeln!(s, "        static const char * const names[] = {{ {} }};",
+        names.iter().map(|(n, _)| format!("\"{}\"", n)).collect::<Vec<_>>().join(", ")).unwrap();
+    writeln!(s, "        static const unsigned long long values[] = {{ {} }};",
+        names.iter().map(|(_, v)| format!("{}ULL", v.unwrap_or(0))).collect::<Vec<_>>().join(", ")).unwrap();
+    writeln!(s, "        size_t at = 0;").unwrap();
+    writeln!(s, "        for (size_t i = 0; i < {}; i++) {{", names.len()).unwrap();
+    writeln!(s, "            if (((unsigned long long)v & values[i]) == 0) {{").unwrap();
+    writeln!(s, "                continue;").unwrap();
+    writeln!(s, "            }}").unwrap();
+    writeln!(s, "            for (const char * c = at == 0 ? \"\" : \"|\"; *c; c++, at++) {{").unwrap();
+    writeln!(s, "                if (at + 1 < size) buf[at] = *c;").unwrap();
+    writeln!(s, "            }}").unwrap();
+    writeln!(s, "            for (const char * c = names[i]; *c; c++, at++) {{").unwrap();
+    writeln!(s, "                if (at + 1 < size) buf[at] = *c;").unwrap();
+    writeln!(s, "            }}").unwrap();
+    writeln!(s, "        }}").unwrap();
+    writeln!(s, "        if (size > 0) {{").unwrap();
+    writeln!(s, "            buf[at < size ? at : size - 1] = 0;").unwrap();
+    writeln!(s, "        }}").unwrap();
+    writeln!(s, "        return at;").unwrap();
+    writeln!(s, "    }}}}@").unwrap();
+    writeln!(s, "}}").unwrap();
+    Ok(s)
+}
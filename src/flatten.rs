@@ -1,11 +1,12 @@
 use super::ast;
 use super::name::Name;
 use super::loader;
-use super::parser::emit_error;
+use super::parser::{self, emit_error};
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::path::PathBuf;
 use super::abs::Ext;
+use super::edition::Edition;
 
 
 #[derive(Clone, Debug, PartialEq, PartialOrd)]
@@ -41,6 +42,15 @@ pub struct Module {
     pub deps:           HashSet<Name>,
 
     pub typevariants:   HashMap<Name, HashSet<u64>>,
+
+    pub edition:        Edition,
+
+    // messages of the deprecated declarations of this module, for the export header
+    pub deprecated:     HashMap<Name, String>,
+    // the @weak function each function of this module overrides, see weak.rs
+    pub overrides:      HashMap<Name, Name>,
+    // c symbols of export functions other than their full name, see symbols.rs
+    pub symbols:        HashMap<Name, String>,
 }
 
 #[derive(Clone)]
@@ -286,6 +296,23 @@ pub fn flatten(md: &ast::Module, all_modules: &HashMap<Name, loader::Module>, ex
     let mut flat    = Module::default();
     flat.name       = md.name.clone();
     flat.sources    = md.sources.clone();
+    flat.edition    = md.edition;
+    for (local, d) in &md.deprecated {
+        flat.deprecated.insert(Name::from(&format!("{}::{}", md.name, local)), d.message.clone());
+    }
+    for (local, o) in &md.overrides {
+        flat.overrides.insert(Name::from(&format!("{}::{}", md.name, local)), o.target.clone());
+    }
+    // any module may call them, or override them. all_modules has every module but md
+    let others = all_modules.values().filter_map(|module| match module {
+        loader::Module::ZZ(m) => Some(m),
+        _ => None,
+    });
+    for m in std::iter::once(md).chain(others) {
+        for (local, s) in &m.symbols {
+            flat.symbols.insert(Name::from(&format!("{}::{}", m.name, local)), s.name.clone());
+        }
+    }
 
     let mut collected   = Locals::default();
 
@@ -328,7 +355,7 @@ pub fn flatten(md: &ast::Module, all_modules: &HashMap<Name, loader::Module>, ex
                 emit_error(format!("undefined type '{}' during flatten of '{}'", name, md.name), &[
                        (loc.clone(), &format!("type '{}' unavailable in this scope", name)),
                 ]);
-                std::process::exit(9);
+                parser::abort();
             }
 
 
@@ -343,7 +370,7 @@ pub fn flatten(md: &ast::Module, all_modules: &HashMap<Name, loader::Module>, ex
                     emit_error(format!("ICE ext module {} unavable or somehow we're missing local {}", module_name, local_name ), &[
                         (loc.clone(), &format!("type '{}' unavailable in this scope", name)),
                     ]);
-                    std::process::exit(9);
+                    parser::abort();
                 }
             } else {
                 let module = loop {
@@ -363,7 +390,7 @@ pub fn flatten(md: &ast::Module, all_modules: &HashMap<Name, loader::Module>, ex
                             emit_error(format!("ice: unknown module {}", module_name), &[
                                        (loc.clone(), &format!("type '{}' unavailable in this scope", name)),
                             ]);
-                            std::process::exit(9);
+                            parser::abort();
                         },
                         Some(loader::Module::C(_)) => panic!("not implemented"),
                         Some(loader::Module::ZZ(ast)) => ast,
@@ -371,6 +398,15 @@ pub fn flatten(md: &ast::Module, all_modules: &HashMap<Name, loader::Module>, ex
                     break module;
                 };
 
+                // methods declared in a struct are locals named struct::method
+                if expecting_sub_type {
+                    let method = format!("{}::{}", local_name, name.0.last().unwrap());
+                    if module.locals.iter().any(|l| l.name == method && matches!(l.def, ast::Def::Function{..})) {
+                        local_name = method;
+                        expecting_sub_type = false;
+                    }
+                }
+
                 // find the local we're looking for
                 for local2 in &module.locals {
                     if local2.name == local_name {
@@ -400,7 +436,7 @@ pub fn flatten(md: &ast::Module, all_modules: &HashMap<Name, loader::Module>, ex
                     emit_error(format!("module {} does not contain {}", module_name, local_name ), &[
                                (loc.clone(), &format!("type '{}' unavailable in this scope", name)),
                     ]);
-                    std::process::exit(9);
+                    parser::abort();
                 }
             };
 
@@ -455,7 +491,7 @@ pub fn flatten(md: &ast::Module, all_modules: &HashMap<Name, loader::Module>, ex
                                 emit_error(format!("self arg must be first"), &[
                                     (arg.loc.clone(), "self argument in wrong position"),
                                 ]);
-                                std::process::exit(9);
+                                parser::abort();
 
                             }
                             if let ast::Type::Other(name) = &arg.typed.t {
@@ -510,11 +546,18 @@ pub fn flatten(md: &ast::Module, all_modules: &HashMap<Name, loader::Module>, ex
                                 impl_deps.extend(expr_deps(cr, expr));
                             }
                         }
+                        if let Some((lo, hi)) = &field.range {
+                            impl_deps.extend(expr_deps(cr, lo));
+                            impl_deps.extend(expr_deps(cr, hi));
+                        }
                     }
 
                     //all structs eventually need to be emitted complete before function body
                     thisobject.insert(name.clone(), TypeComplete::Complete);
                 }
+                ast::Def::TypeAlias{typed} => {
+                    decl_deps.extend(type_deps(cr, &typed));
+                }
                 ast::Def::Macro{body, ..} => {
                     decl_deps.extend(block_deps(cr, body));
                     forceinline.insert(name.clone());
@@ -535,6 +578,12 @@ pub fn flatten(md: &ast::Module, all_modules: &HashMap<Name, loader::Module>, ex
                     }
                     forceinline.insert(name.clone());
                 }
+                ast::Def::Closure{..} => {
+                    unreachable!("closure {} must be expanded before flatten", name);
+                }
+                ast::Def::Test{..} => {
+                    unreachable!("test {} must be lowered before flatten", name);
+                }
             }
 
             if expecting_sub_type {
@@ -692,7 +741,7 @@ pub fn flatten(md: &ast::Module, all_modules: &HashMap<Name, loader::Module>, ex
             if let Some(ast) = &mut local.ast {
                 if let ast::Def::Struct{impls,..} = &mut ast.def {
                     for dep in &deps {
-                        impls.insert((dep.0).0.last().unwrap().clone(), dep.clone());
+                        impls.insert((dep.0).0.last().unwrap().to_string(), dep.clone());
                     }
                 }
             }
@@ -727,7 +776,7 @@ pub fn flatten(md: &ast::Module, all_modules: &HashMap<Name, loader::Module>, ex
             name.clone(),
             complete.clone(),
             None,
-            0,
+            &mut Vec::new(),
             &forceinline,
             &mut more,
         );
@@ -740,7 +789,7 @@ pub fn flatten(md: &ast::Module, all_modules: &HashMap<Name, loader::Module>, ex
             name.clone(),
             TypeComplete::Incomplete,
             None,
-            0,
+            &mut Vec::new(),
             &forceinline,
             &mut more,
         );
@@ -791,7 +840,7 @@ fn sort_visit(
         mut name:           Name,
         mut complete:       TypeComplete,
         here:               Option<&ast::Location>,
-        depth:              usize,
+        stack:              &mut Vec<(Name, TypeComplete, Option<ast::Location>)>, // the visits this one is nested in
         forceinline:        &HashSet<Name>,
         more:               &mut HashSet<Name>, // discover more dependencies
 )
@@ -802,6 +851,7 @@ fn sort_visit(
         }
     }
 
+    let depth = stack.len();
     debug!("  {} sort_visit: {} {:?}", " ".repeat(depth), name, complete);
 
     if forceinline.contains(&name) {
@@ -817,6 +867,11 @@ fn sort_visit(
     }
 
 
+    // declaring it needs its own declaration first, like two fntypes taking each other
+    if complete == TypeComplete::Incomplete && stack.iter().any(|(n, c, _)| n == &name && c == &complete) {
+        cycle(stack, &name, &complete, here);
+    }
+
     let n = match complete {
         TypeComplete::Incomplete => {
             match unsorted.get(&name) {
@@ -831,6 +886,9 @@ fn sort_visit(
             match unsorted.remove(&name) {
                 Some(v) => v,
                 None => {
+                    if stack.iter().any(|(n, _, _)| n == &name) {
+                        cycle(stack, &name, &complete, here);
+                    }
                     let mut estack = Vec::new();
                     if let Some(here) = here {
                         estack.push((here.clone(), format!("type incomplete in this scope")));
@@ -845,25 +903,27 @@ fn sort_visit(
                     for (name, _) in unsorted {
                         debug!("  {}", name);
                     }
-                    std::process::exit(10);
+                    parser::abort();
                 }
             }
         }
     };
 
+    stack.push((name.clone(), complete.clone(), here.cloned()));
     if complete == TypeComplete::Complete {
         for (dep, complete, loc) in &n.impl_deps {
             if dep == &name {
                 continue;
             }
-            sort_visit(sorted, sorted_mark, unsorted, dep.clone(), complete.clone(), Some(loc), depth+1, forceinline, more);
+            sort_visit(sorted, sorted_mark, unsorted, dep.clone(), complete.clone(), Some(loc), stack, forceinline, more);
         }
     }
 
 
     for (dep, complete, loc) in &n.decl_deps {
-        sort_visit(sorted, sorted_mark, unsorted, dep.clone(), complete.clone(), Some(loc), depth+1, forceinline, more);
+        sort_visit(sorted, sorted_mark, unsorted, dep.clone(), complete.clone(), Some(loc), stack, forceinline, more);
     }
+    stack.pop();
 
     debug!("  {} < marked : {} {:?}", " ".repeat(depth), name, complete);
     match sorted_mark.get(&name) {
@@ -880,6 +940,34 @@ fn sort_visit(
     }
 }
 
+/// name needs itself to be emitted first. reports the chain from the outermost visit of name to here.
+/// complete visits need the complete type, incomplete ones only need it declared
+fn cycle(
+    stack:      &[(Name, TypeComplete, Option<ast::Location>)],
+    name:       &Name,
+    complete:   &TypeComplete,
+    here:       Option<&ast::Location>,
+) -> ! {
+    let start = stack.iter().position(|(n, _, _)| n == name).unwrap_or(0);
+    let mut chain : Vec<String> = stack[start..].iter().map(|(n, _, _)| n.to_string()).collect();
+    chain.push(name.to_string());
+
+    let mut estack = Vec::new();
+    let steps = stack[start + 1..].iter().map(|(n, c, l)| (n, c, l.as_ref()))
+        .chain(std::iter::once((name, complete, here)));
+    for (i, (dep, complete, loc)) in steps.enumerate() {
+        if let Some(loc) = loc {
+            let key = match complete {
+                TypeComplete::Complete   => "type_cycle.complete",
+                TypeComplete::Incomplete => "type_cycle.declared",
+            };
+            estack.push((loc.clone(), msg!(key, name = chain[i], dep = dep)));
+        }
+    }
+    emit_error(msg!("type_cycle", name = name, chain = chain.join(" -> ")), &estack);
+    parser::abort();
+}
+
 fn dependency_visit(
         visited:    &mut HashSet<Name>,
         unsorted:   &HashMap<Name, Local>,
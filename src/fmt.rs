@@ -0,0 +1,1150 @@
+/// pretty print modules back from the ast
+
+use super::ast;
+use super::coroutine;
+use super::propagate;
+use super::closure;
+use super::parser;
+use super::make;
+use super::edition::Edition;
+use std::collections::HashMap;
+use std::path::Path;
+
+pub struct Printer<'a> {
+    src:        &'a str,
+    comments:   Vec<(usize, usize)>,
+    next:       usize,
+    last:       usize,
+    out:        String,
+    indent:     usize,
+    // put every compound expression in parens. used to compare the structure of two asts
+    explicit:   bool,
+}
+
+fn infix_op(op: &ast::InfixOperator) -> (&'static str, u8) {
+    match op {
+        ast::InfixOperator::Multiply    => ("*",  3),
+        ast::InfixOperator::Divide      => ("/",  3),
+        ast::InfixOperator::Modulo      => ("%",  3),
+        ast::InfixOperator::Add         => ("+",  4),
+        ast::InfixOperator::Subtract    => ("-",  4),
+        ast::InfixOperator::Shiftleft   => ("<<", 5),
+        ast::InfixOperator::Shiftright  => (">>", 5),
+        ast::InfixOperator::Lessthan    => ("<",  6),
+        ast::InfixOperator::Morethan    => (">",  6),
+        ast::InfixOperator::Lesseq      => ("<=", 6),
+        ast::InfixOperator::Moreeq      => (">=", 6),
+        ast::InfixOperator::Equals      => ("==", 7),
+        ast::InfixOperator::Nequals     => ("!=", 7),
+        ast::InfixOperator::Bitand      => ("&",  8),
+        ast::InfixOperator::Bitxor      => ("^",  9),
+        ast::InfixOperator::Bitor       => ("|",  10),
+        ast::InfixOperator::Booland     => ("&&", 11),
+        ast::InfixOperator::Boolor      => ("||", 12),
+    }
+}
+
+// same levels as the precedence climber in the parser
+fn precedence(expr: &ast::Expression) -> u8 {
+    match expr {
+        ast::Expression::Infix{op, ..} => infix_op(op).1,
+        ast::Expression::UnaryPre{..} | ast::Expression::UnaryPost{..} | ast::Expression::Cast{..} => 2,
+        _ => 0,
+    }
+}
+
+fn expr_start(expr: &ast::Expression) -> usize {
+    match expr {
+        ast::Expression::Infix{lhs, ..}
+        | ast::Expression::MemberAccess{lhs, ..}
+        | ast::Expression::ArrayAccess{lhs, ..} => expr_start(lhs),
+        ast::Expression::Call{name, ..} => expr_start(name),
+        _ => expr.loc().start,
+    }
+}
+
+fn stm_start(stm: &ast::Statement) -> Option<usize> {
+    match stm {
+        ast::Statement::Mark{lhs, ..} => Some(expr_start(lhs)),
+        ast::Statement::Assign{lhs, ..} => Some(expr_start(lhs)),
+        ast::Statement::Expr{expr, ..} => Some(expr_start(expr)),
+        ast::Statement::Label{loc, ..}
+        | ast::Statement::Switch{loc, ..}
+        | ast::Statement::Continue{loc}
+        | ast::Statement::Break{loc}
+        | ast::Statement::Return{loc, ..}
+        | ast::Statement::Var{loc, ..}
+        | ast::Statement::CBlock{loc, ..} => Some(loc.start),
+        ast::Statement::If{branches} => branches.first().map(|b|b.0.start),
+        ast::Statement::While{expr, ..} => Some(expr_start(expr)),
+        ast::Statement::For{e1, e2, ..} => match (e1.first(), e2) {
+            (Some(e1), _)       => stm_start(e1),
+            (None, Some(e2))    => Some(expr_start(e2)),
+            (None, None)        => None,
+        },
+        ast::Statement::Block(b) => closure::inline(b).map(|c| c.loc.start),
+        ast::Statement::Unsafe(_) => None,
+    }
+}
+
+fn stm_end(stm: &ast::Statement) -> usize {
+    match stm {
+        ast::Statement::Mark{loc, ..}
+        | ast::Statement::Label{loc, ..}
+        | ast::Statement::Assign{loc, ..}
+        | ast::Statement::Expr{loc, ..}
+        | ast::Statement::Switch{loc, ..}
+        | ast::Statement::Continue{loc}
+        | ast::Statement::Break{loc}
+        | ast::Statement::Return{loc, ..}
+        | ast::Statement::Var{loc, ..}
+        | ast::Statement::CBlock{loc, ..} => loc.end,
+        ast::Statement::If{branches} => branches.last().map(|b|b.2.end.end).unwrap_or(0),
+        ast::Statement::While{body, ..} | ast::Statement::For{body, ..} => body.end.end,
+        ast::Statement::Block(b) | ast::Statement::Unsafe(b) => b.end.end,
+    }
+}
+
+pub fn tags(tags: &ast::Tags) -> Vec<String> {
+    let mut r = Vec::new();
+    for (k, v) in &tags.0 {
+        for v in v.keys() {
+            if k == super::units::TAG {
+                r.push(format!("@unit(\"{}\")", v));
+            } else if v.is_empty() {
+                r.push(k.clone());
+            } else {
+                r.push(format!("{}<{}>", k, v));
+            }
+        }
+    }
+    r.sort();
+    r
+}
+
+pub fn typed(t: &ast::Typed) -> String {
+    let mut s = match &t.t {
+        ast::Type::Other(n) => n.to_string(),
+        _ => format!("{}", ast::Typed{ptr: Vec::new(), tail: ast::Tail::None, ..t.clone()}),
+    };
+    match &t.tail {
+        ast::Tail::None         => (),
+        ast::Tail::Dynamic      => s.push('+'),
+        ast::Tail::Static(v, _) => s.push_str(&format!("+{}", v)),
+        ast::Tail::Bind(v, _)   => s.push_str(&format!("+{}", v)),
+    }
+    for ptr in &t.ptr {
+        for tag in tags(&ptr.tags) {
+            s.push(' ');
+            s.push_str(&tag);
+        }
+        s.push('*');
+    }
+    s
+}
+
+/// a return type, with its unit
+fn returned(t: &ast::Typed, tg: &ast::Tags) -> String {
+    let mut s = typed(t);
+    for tag in tags(tg) {
+        s.push(' ');
+        s.push_str(&tag);
+    }
+    s
+}
+
+pub fn named(t: &ast::Typed, tg: &ast::Tags, name: &str) -> String {
+    let mut s = returned(t, tg);
+    s.push(' ');
+    s.push_str(name);
+    s
+}
+
+/// a struct or union declared in place of a field type, see parse_anon_struct. it is kept as written
+fn inline_struct(src: &str, loc: &ast::Location) -> bool {
+    let s = match src.get(loc.start..loc.end) {
+        Some(s) => s,
+        None => return false,
+    };
+    let body = s.trim_start_matches("struct").trim_start_matches("union");
+    body.len() < s.len() && body.trim_start().starts_with('{')
+}
+
+fn is_ident(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+impl<'a> Printer<'a> {
+    pub fn new(md: &ast::Module, src: &'a str, explicit: bool) -> Self {
+        Printer {
+            src,
+            comments:   md.comments.iter().map(|c|(c.start, c.end)).collect(),
+            next:       0,
+            last:       0,
+            out:        String::new(),
+            indent:     0,
+            explicit,
+        }
+    }
+
+    fn write_indent(&mut self) {
+        for _ in 0..self.indent {
+            self.out.push_str("    ");
+        }
+    }
+
+    fn blank(&mut self) {
+        if self.out.is_empty() || self.out.ends_with("{\n") || self.out.ends_with("\n\n") {
+            return;
+        }
+        self.out.push('\n');
+    }
+
+    // keep one empty line if there was at least one in the source
+    fn gap(&mut self, pos: usize) {
+        if self.last >= pos || pos > self.src.len() {
+            return;
+        }
+        let lines : Vec<&str> = self.src[self.last..pos].split('\n').collect();
+        if lines.len() > 2 && lines[1..lines.len() - 1].iter().any(|l| l.trim().is_empty()) {
+            self.blank();
+        }
+    }
+
+    // all comments that start before pos go on their own lines
+    fn before(&mut self, pos: usize, force_blank: bool) {
+        if self.explicit {
+            return;
+        }
+        let first = match self.comments.get(self.next) {
+            Some((s, _)) if *s < pos => *s,
+            _ => pos,
+        };
+        if force_blank {
+            self.blank();
+        } else {
+            self.gap(first);
+        }
+        while let Some((s, e)) = self.comments.get(self.next).cloned() {
+            if s >= pos {
+                break;
+            }
+            self.gap(s);
+            self.write_indent();
+            self.out.push_str(&self.src[s..e]);
+            self.out.push('\n');
+            self.last = e;
+            self.next += 1;
+        }
+        self.gap(pos);
+    }
+
+    // a comment on the same line as the end of a node stays there
+    fn trailing(&mut self, end: usize) {
+        if self.explicit {
+            return;
+        }
+        if let Some((s, e)) = self.comments.get(self.next).cloned() {
+            if s >= end && !self.src[end..s].contains('\n') {
+                self.out.push(' ');
+                self.out.push_str(&self.src[s..e]);
+                self.last = e;
+                self.next += 1;
+                return;
+            }
+        }
+        if end > self.last {
+            self.last = end;
+        }
+    }
+
+    // position of the closing brace of a declaration body
+    fn find_close(&self, from: usize) -> usize {
+        self.find(from, b'}')
+    }
+
+    fn find(&self, from: usize, c: u8) -> usize {
+        let mut i = from;
+        'outer: while i < self.src.len() {
+            for (s, e) in &self.comments {
+                if i >= *s && i < *e {
+                    i = *e;
+                    continue 'outer;
+                }
+            }
+            if self.src.as_bytes()[i] == c {
+                return i;
+            }
+            i += 1;
+        }
+        self.src.len()
+    }
+
+    // true if there are comments we'd have to move out of a node
+    fn commented(&self, start: usize, end: usize) -> bool {
+        !self.explicit && self.comments[self.next..].iter().any(|c| c.0 >= start && c.0 < end)
+    }
+
+    // print the source of a node as it is
+    fn verbatim(&mut self, start: usize, end: usize) {
+        self.out.push_str(self.src[start..end].trim_end());
+        while self.comments.get(self.next).map(|c| c.0 < end).unwrap_or(false) {
+            self.next += 1;
+        }
+        self.last = end;
+    }
+
+    fn child(&self, expr: &ast::Expression, parens: bool) -> String {
+        let s = self.expr(expr);
+        let compound = !matches!(expr,
+            ast::Expression::Name(_)
+            | ast::Expression::Literal{..}
+            | ast::Expression::LiteralString{..}
+            | ast::Expression::LiteralChar{..}
+        );
+        if parens || (self.explicit && compound) {
+            format!("({})", s)
+        } else {
+            s
+        }
+    }
+
+    pub fn expr(&self, expr: &ast::Expression) -> String {
+        match expr {
+            ast::Expression::Name(t) => typed(t),
+            ast::Expression::LiteralString{loc, ..}
+            | ast::Expression::LiteralChar{loc, ..}
+            | ast::Expression::Literal{loc, ..} => loc.text(),
+            ast::Expression::MemberAccess{lhs, op, rhs, ..} => {
+                format!("{}{}{}", self.child(lhs, precedence(lhs) > 0), op, rhs)
+            }
+            ast::Expression::ArrayAccess{lhs, rhs, ..} => {
+                format!("{}[{}]", self.child(lhs, precedence(lhs) > 0), self.expr(rhs))
+            }
+            ast::Expression::Call{..} if coroutine::suspends(expr).is_some() => {
+                let (keyword, operand) = coroutine::suspends(expr).unwrap();
+                format!("{} {}", keyword, self.expr(operand))
+            }
+            ast::Expression::Call{..} if propagate::tried(expr).is_some() => {
+                format!("try {}", self.expr(propagate::tried(expr).unwrap()))
+            }
+            ast::Expression::Call{name, args, ..} => {
+                let args : Vec<String> = args.iter().map(|a|self.expr(a)).collect();
+                format!("{}({})", self.child(name, precedence(name) > 0), args.join(", "))
+            }
+            ast::Expression::Infix{lhs, rhs, op, ..} => {
+                let (op, p) = infix_op(op);
+                // a && b || c is correct, but nobody wants to read it
+                let mixed = |e: &ast::Expression| op == "||" && precedence(e) == 11;
+                format!("{} {} {}",
+                    self.child(lhs, precedence(lhs) > p || mixed(lhs)),
+                    op,
+                    self.child(rhs, precedence(rhs) >= p || mixed(rhs)),
+                )
+            }
+            ast::Expression::Cast{into, expr, tags: tg, ..} => {
+                let mut into = typed(into);
+                for tag in tags(tg) {
+                    into.push(' ');
+                    into.push_str(&tag);
+                }
+                format!("({}){}", into, self.child(expr, precedence(expr) > 2))
+            }
+            ast::Expression::UnaryPost{op, expr, ..} => {
+                let op = match op {
+                    ast::PostfixOperator::Increment => "++",
+                    ast::PostfixOperator::Decrement => "--",
+                };
+                let parens = !matches!(**expr, ast::Expression::Name(_));
+                format!("{}{}", self.child(expr, parens), op)
+            }
+            ast::Expression::UnaryPre{op, expr, ..} => {
+                let op = match op {
+                    ast::PrefixOperator::Boolnot    => "!",
+                    ast::PrefixOperator::Bitnot     => "~",
+                    ast::PrefixOperator::Increment  => "++",
+                    ast::PrefixOperator::Decrement  => "--",
+                    ast::PrefixOperator::AddressOf  => "&",
+                    ast::PrefixOperator::Deref      => "*",
+                };
+                format!("{}{}", op, self.child(expr, precedence(expr) > 2))
+            }
+            ast::Expression::StructInit{typed: t, fields, ..} => {
+                let fields : Vec<String> = fields.iter().map(|(n, e)| format!("{}: {}", n, self.expr(e))).collect();
+                format!("{}{{{}}}", typed(t), fields.join(", "))
+            }
+            ast::Expression::ArrayInit{fields, index, ..} => {
+                // {value; count} was parsed into copies at the same location
+                if fields.len() > 1 && index.is_empty() && fields.iter().all(|e| e.loc().start == fields[0].loc().start) {
+                    return format!("{{{}; {}}}", self.expr(&fields[0]), fields.len());
+                }
+                let fields : Vec<String> = fields.iter().enumerate().map(|(i, e)| match index.get(i) {
+                    Some(at) => format!("[{}] = {}", at, self.expr(e)),
+                    None => self.expr(e),
+                }).collect();
+                format!("{{{}}}", fields.join(", "))
+            }
+        }
+    }
+
+    fn inline_statement(&self, stm: &ast::Statement) -> String {
+        match stm {
+            ast::Statement::Mark{lhs, key, value, ..} => {
+                if value.is_empty() {
+                    format!("{} is {}", self.expr(lhs), key)
+                } else {
+                    format!("{} is {}<{}>", self.expr(lhs), key, value)
+                }
+            }
+            ast::Statement::Assign{lhs, op, rhs, ..} => {
+                let op = match op {
+                    ast::AssignOperator::Bitor  => "|=",
+                    ast::AssignOperator::Bitand => "&=",
+                    ast::AssignOperator::Add    => "+=",
+                    ast::AssignOperator::Sub    => "-=",
+                    ast::AssignOperator::Eq     => "=",
+                };
+                format!("{} {} {}", self.expr(lhs), op, self.expr(rhs))
+            }
+            ast::Statement::Expr{expr, ..} => self.expr(expr),
+            ast::Statement::Continue{..} => "continue".to_string(),
+            ast::Statement::Break{..} => "break".to_string(),
+            ast::Statement::Return{expr: Some(expr), ..} => format!("return {}", self.expr(expr)),
+            ast::Statement::Return{expr: None, ..} => "return".to_string(),
+            ast::Statement::Var{typed: t, tags: tg, name, array, assign, ..} => {
+                let mut s = named(t, tg, name);
+                match array {
+                    Some(Some(array))   => s.push_str(&format!("[{}]", self.expr(array))),
+                    Some(None)          => s.push_str("[]"),
+                    None                => (),
+                }
+                if let Some(assign) = assign {
+                    s.push_str(&format!(" = {}", self.expr(assign)));
+                }
+                s
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn block(&mut self, block: &ast::Block) {
+        let end = block.end.start.saturating_sub(1);
+        let commented = !self.explicit && self.comments.get(self.next).map(|c| c.0 < end).unwrap_or(false);
+        if block.statements.is_empty() && !commented {
+            self.out.push_str("{}");
+            self.last = block.end.end;
+            return;
+        }
+        self.out.push_str("{\n");
+        self.indent += 1;
+        for stm in &block.statements {
+            self.statement(stm);
+        }
+        self.before(block.end.start.saturating_sub(1), false);
+        self.indent -= 1;
+        self.write_indent();
+        self.out.push('}');
+        self.last = block.end.end;
+    }
+
+    fn statement(&mut self, stm: &ast::Statement) {
+        if let Some(start) = stm_start(stm) {
+            self.before(start, false);
+        }
+        self.write_indent();
+        match stm {
+            ast::Statement::Label{label, ..} => {
+                self.out.push_str(&format!("{}:", label));
+            }
+            ast::Statement::CBlock{loc, ..} => {
+                // the literal is trimmed by the parser, so keep the original layout
+                let open  = self.src[..loc.start].rfind("@{{").unwrap_or(loc.start);
+                let close = self.src[loc.end..].find("}}@").map(|i| loc.end + i + 3).unwrap_or(loc.end);
+                self.out.push_str(&self.src[open..close]);
+            }
+            ast::Statement::Block(b) if closure::inline(b).is_some() => {
+                let c = closure::inline(b).unwrap();
+                let captures : Vec<String> = c.captures.iter().map(|(f, value)| {
+                    let s = named(&f.typed, &f.tags, &f.name);
+                    match value {
+                        ast::Expression::Name(ast::Typed{t: ast::Type::Other(n), ..}) if n.to_string() == f.name => s,
+                        _ => format!("{} = {}", s, self.expr(value)),
+                    }
+                }).collect();
+                let args : Vec<String> = c.args.iter().map(|a| named(&a.typed, &a.tags, &a.name)).collect();
+                let ret = match &c.ret {
+                    Some(ast::AnonArg{typed: t, array: Some(len), tags: tg}) => format!(" -> {}[{}]", returned(t, tg), self.expr(len)),
+                    Some(ast::AnonArg{typed: t, array: None, tags: tg}) => format!(" -> {}", returned(t, tg)),
+                    None => String::new(),
+                };
+                if c.nested {
+                    self.out.push_str(&format!("fn {}({}){} ", c.name, args.join(", "), ret));
+                } else {
+                    self.out.push_str(&format!("closure {} = closure_for({}) [{}] ({}){} ",
+                        c.name, typed(&c.fntype), captures.join(", "), args.join(", "), ret));
+                }
+                self.block(&c.body);
+            }
+            ast::Statement::Block(b) => {
+                self.block(b);
+            }
+            ast::Statement::Unsafe(b) => {
+                self.out.push_str("unsafe ");
+                self.block(b);
+            }
+            ast::Statement::While{expr, body} => {
+                self.out.push_str(&format!("while {} ", self.expr(expr)));
+                self.block(body);
+            }
+            ast::Statement::For{e1, body, ..} if coroutine::ranged(e1).is_some() => {
+                let (var, over) = coroutine::ranged(e1).unwrap();
+                if let ast::Statement::Var{typed: t, tags: tg, name, ..} = var {
+                    self.out.push_str(&format!("for ({} : {}) ", named(t, tg, name), self.expr(over)));
+                }
+                self.block(body);
+            }
+            ast::Statement::For{e1, e2, e3, body} => {
+                let e1 : Vec<String> = e1.iter().map(|s|self.inline_statement(s)).collect();
+                let e3 : Vec<String> = e3.iter().map(|s|self.inline_statement(s)).collect();
+                let e2 = e2.as_ref().map(|e|self.expr(e)).unwrap_or_default();
+                let e3 = e3.join(", ");
+                let space = |s: &str| if s.is_empty() { String::new() } else { format!(" {}", s) };
+                self.out.push_str(&format!("for ({};{};{}) ", e1.join(", "), space(&e2), space(&e3)));
+                self.block(body);
+            }
+            ast::Statement::If{branches} => {
+                for (i, (_, expr, body)) in branches.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push_str(" else ");
+                    }
+                    if let Some(expr) = expr {
+                        self.out.push_str(&format!("if {} ", self.expr(expr)));
+                    }
+                    self.block(body);
+                }
+            }
+            ast::Statement::Switch{expr, cases, default, ..} => {
+                self.out.push_str(&format!("switch {} {{\n", self.expr(expr)));
+                self.indent += 1;
+                for (conds, body) in cases {
+                    let conds : Vec<String> = conds.iter().map(|e|self.expr(e)).collect();
+                    self.write_indent();
+                    self.out.push_str(&format!("{} => ", conds.join(", ")));
+                    self.block(body);
+                    self.out.push('\n');
+                }
+                if let Some(body) = default {
+                    self.write_indent();
+                    self.out.push_str("default => ");
+                    self.block(body);
+                    self.out.push('\n');
+                }
+                self.indent -= 1;
+                self.write_indent();
+                self.out.push('}');
+            }
+            _ => {
+                let start = stm_start(stm).unwrap_or(0);
+                if self.commented(start, stm_end(stm)) {
+                    self.verbatim(start, stm_end(stm));
+                } else {
+                    let s = self.inline_statement(stm);
+                    self.out.push_str(&s);
+                }
+                self.out.push(';');
+            }
+        }
+        self.trailing(stm_end(stm));
+        self.out.push('\n');
+    }
+
+    // long argument lists get one argument per line
+    /// the argument a where len(a) >= n was written as @len(n) for
+    fn len_tag(&self, e: &ast::Expression) -> Option<String> {
+        if !self.src.get(e.loc().start..).map(|s| s.starts_with("@len")).unwrap_or(false) {
+            return None;
+        }
+        match e {
+            ast::Expression::Infix{lhs, op: ast::InfixOperator::Moreeq, ..} => match lhs.as_ref() {
+                ast::Expression::Call{args, ..} => args.first().map(|a| self.expr(a)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// the argument n where n >= lo or n <= hi was written as @range(lo, hi) for
+    fn range_tag(&self, e: &ast::Expression) -> Option<String> {
+        if !self.src.get(e.loc().start..).map(|s| s.starts_with("@range")).unwrap_or(false) {
+            return None;
+        }
+        match e {
+            ast::Expression::Infix{lhs, ..} => Some(self.expr(lhs)),
+            _ => None,
+        }
+    }
+
+    fn signature(&self, head: String, args: &[ast::NamedArg], vararg: bool, tagged: &[&ast::Expression]) -> String {
+        let mut r : Vec<String> = args.iter().map(|a| {
+            let mut s = named(&a.typed, &a.tags, &a.name);
+            let mut range = (None, None);
+            for e in tagged {
+                if let (Some(of), ast::Expression::Infix{rhs, ..}) = (self.len_tag(e), e) {
+                    if of == a.name {
+                        s.push_str(&format!(" @len({})", self.expr(rhs)));
+                    }
+                }
+                if let (Some(of), ast::Expression::Infix{rhs, op, ..}) = (self.range_tag(e), e) {
+                    if of == a.name {
+                        match op {
+                            ast::InfixOperator::Moreeq  => range.0 = Some(self.expr(rhs)),
+                            _                           => range.1 = Some(self.expr(rhs)),
+                        }
+                    }
+                }
+            }
+            if let (Some(lo), Some(hi)) = range {
+                s.push_str(&format!(" @range({}, {})", lo, hi));
+            }
+            s
+        }).collect();
+        if vararg {
+            r.push("...".to_string());
+        }
+        let line = format!("{}({})", head, r.join(", "));
+        if line.len() <= 100 {
+            return line;
+        }
+        let mut s = format!("{}(\n", head);
+        for arg in r {
+            s.push_str("    ");
+            s.push_str(&arg);
+            if arg != "..." {
+                s.push(',');
+            }
+            s.push('\n');
+        }
+        s.push(')');
+        s
+    }
+
+    fn vis(vis: &ast::Visibility) -> &'static str {
+        match vis {
+            ast::Visibility::Shared => "pub ",
+            ast::Visibility::Package => "pub(package) ",
+            ast::Visibility::Export => "export ",
+            ast::Visibility::Object => "",
+        }
+    }
+
+    fn doc(&mut self, doc: &str) {
+        for line in doc.lines() {
+            self.write_indent();
+            self.out.push_str(&format!("/!{}\n", line));
+        }
+    }
+
+    fn fields(&mut self, fields: &[ast::Field], start: usize, methods: &[&ast::Local]) {
+        self.out.push_str("{\n");
+        self.indent += 1;
+        let mut methods = methods.iter().peekable();
+        for field in fields {
+            while let Some(method) = methods.next_if(|m| m.loc.start < field.loc.start) {
+                self.method(method);
+            }
+            self.before(field.loc.start, false);
+            self.write_indent();
+            if inline_struct(self.src, &field.loc) {
+                self.verbatim(field.loc.start, field.loc.end);
+                self.out.push('\n');
+                continue;
+            }
+            self.out.push_str(&named(&field.typed, &field.tags, &field.name));
+            match &field.array {
+                Some(Some(array))   => self.out.push_str(&format!("[{}]", self.expr(array))),
+                Some(None)          => self.out.push_str("[]"),
+                None                => (),
+            }
+            if let Some(bits) = field.bits {
+                self.out.push_str(&format!(" : {}", bits));
+            }
+            if let Some((lo, hi)) = &field.range {
+                self.out.push_str(&format!(" @range({}, {})", self.expr(lo), self.expr(hi)));
+            }
+            if let Some(default) = &field.default {
+                self.out.push_str(&format!(" = {}", self.expr(default)));
+            }
+            self.out.push(';');
+            self.trailing(field.loc.end);
+            self.out.push('\n');
+        }
+        for method in methods {
+            self.method(method);
+        }
+        let end = fields.iter().map(|f|f.loc.end).chain(std::iter::once(self.last)).max().unwrap_or(start);
+        let close = self.find_close(end.max(start));
+        self.before(close, false);
+        self.indent -= 1;
+        self.write_indent();
+        self.out.push('}');
+        self.last = close + 1;
+    }
+
+    // a function declared in a struct body
+    fn method(&mut self, method: &ast::Local) {
+        self.before(method.loc.start, false);
+        self.write_indent();
+        self.local(method, None, &[]);
+        let last = self.last;
+        self.trailing(last);
+        self.out.push('\n');
+    }
+
+    fn import(&mut self, import: &ast::Import) {
+        self.out.push_str(Self::vis(&import.vis));
+        if import.inline {
+            self.out.push_str("inline ");
+        }
+        self.out.push_str("using ");
+        if !import.needs.is_empty() {
+            let needs : Vec<String> = import.needs.iter().map(|(t,_)|typed(t)).collect();
+            self.out.push_str(&format!("(needs {}) ", needs.join(", ")));
+        }
+        let name = if import.name.0.len() > 2 && import.name.0[0].is_empty() && import.name.0[1] == "ext" {
+            import.name.0[2..].join("::")
+        } else {
+            import.name.to_string()
+        };
+        self.out.push_str(&name);
+        if !import.local.is_empty() {
+            let locals : Vec<String> = import.local.iter().map(|(n, alias)| {
+                let n = if is_ident(n) { n.clone() } else { format!("({})", n) };
+                match alias {
+                    Some(alias) => format!("{} as {}", n, alias),
+                    None => n,
+                }
+            }).collect();
+            self.out.push_str(&format!("::{{{}}}", locals.join(", ")));
+        }
+        if let Some(alias) = &import.alias {
+            self.out.push_str(&format!(" as {}", alias));
+        }
+        self.out.push(';');
+    }
+
+    fn local(&mut self, local: &ast::Local, ctx: Option<&ast::Local>, methods: &[&ast::Local]) {
+        match &local.def {
+            ast::Def::Function{ret, args, hints, attr, body, vararg, callassert, calleffect, ..} => {
+                let start = local.loc.start;
+                let brace = self.find(args.iter().map(|a|a.loc.end)
+                    .chain(ret.iter().map(|r|r.array.as_ref().map(|a|a.loc()).unwrap_or(&r.typed.loc).end))
+                    .chain(callassert.iter().chain(calleffect.iter()).map(|e|e.loc().end))
+                    .max().unwrap_or(start), b'{');
+                if self.commented(start, brace) {
+                    self.verbatim(start, brace);
+                    self.out.push(if self.src[start..brace].trim_end().contains('\n') { '\n' } else { ' ' });
+                    self.block(body);
+                    return;
+                }
+                let mut head = Self::vis(&local.vis).to_string();
+                // @weak is written before the declaration
+                let mut attr : Vec<&String> = attr.keys().filter(|a| *a != "weak").collect();
+                attr.sort();
+                for a in attr {
+                    head.push_str(&format!("{} ", a));
+                }
+                // methods are named struct::method
+                head.push_str(&format!("fn {}", local.name.rsplit("::").next().unwrap()));
+                let (tagged, callassert) : (Vec<&ast::Expression>, Vec<&ast::Expression>) =
+                    callassert.iter().partition(|e| self.len_tag(e).is_some() || self.range_tag(e).is_some());
+                let sig = self.signature(head, args, *vararg, &tagged);
+                self.out.push_str(&sig);
+                if let Some(ret) = ret {
+                    self.out.push_str(&format!(" -> {}", returned(&ret.typed, &ret.tags)));
+                    if let Some(array) = &ret.array {
+                        self.out.push_str(&format!("[{}]", self.expr(array)));
+                    }
+                }
+                let mut hints : Vec<(&String, &String)> = hints.iter().collect();
+                hints.sort();
+                if callassert.is_empty() && calleffect.is_empty() && hints.is_empty() {
+                    self.out.push(' ');
+                } else {
+                    self.out.push('\n');
+                    for (k, v) in hints {
+                        self.out.push_str(&format!("    @{}={}\n", k, v));
+                    }
+                    for e in callassert {
+                        self.out.push_str(&format!("    where {}\n", self.expr(e)));
+                    }
+                    for e in calleffect {
+                        self.out.push_str(&format!("    model {}\n", self.expr(e)));
+                    }
+                }
+                self.block(body);
+            }
+            ast::Def::Fntype{ret, args, attr, ..} | ast::Def::Theory{ret, args, attr, ..} => {
+                let (kw, vararg) = match &local.def {
+                    ast::Def::Fntype{vararg, ..} => ("fntype", *vararg),
+                    _ => ("theory", false),
+                };
+                let mut head = Self::vis(&local.vis).to_string();
+                let mut attr : Vec<&String> = attr.keys().collect();
+                attr.sort();
+                for a in attr {
+                    head.push_str(&format!("{} ", a));
+                }
+                head.push_str(&format!("{} {}", kw, local.name));
+                let sig = self.signature(head, args, vararg, &[]);
+                self.out.push_str(&sig);
+                if let Some(ret) = ret {
+                    self.out.push_str(&format!(" -> {}", returned(&ret.typed, &ret.tags)));
+                }
+                self.out.push(';');
+                self.last = local.loc.end;
+            }
+            ast::Def::Closure{fntype, target, ..} => {
+                self.out.push_str(Self::vis(&local.vis));
+                self.out.push_str(&format!("closure {} = closure_for({}, {}) ", local.name, typed(fntype), typed(target)));
+                let fields = match ctx.map(|c|&c.def) {
+                    Some(ast::Def::Struct{fields, ..}) => fields.clone(),
+                    _ => Vec::new(),
+                };
+                self.fields(&fields, target.loc.end, &[]);
+                self.out.push(';');
+            }
+            ast::Def::Struct{fields, packed, tail, union, abi, ..} => {
+                self.out.push_str(Self::vis(&local.vis));
+                self.out.push_str(if *union { "union " } else { "struct " });
+                self.out.push_str(&local.name);
+                if *tail != ast::Tail::None {
+                    self.out.push('+');
+                }
+                if *packed {
+                    self.out.push_str(" packed");
+                }
+                match abi {
+                    Some(0)         => self.out.push_str(" @abi"),
+                    Some(version)   => self.out.push_str(&format!(" @abi = {}", version)),
+                    None            => (),
+                }
+                self.out.push(' ');
+                self.fields(fields, local.loc.end, methods);
+            }
+            ast::Def::Enum{names, flags} => {
+                self.out.push_str(Self::vis(&local.vis));
+                if *flags {
+                    self.out.push_str("flags ");
+                }
+                self.out.push_str(&format!("enum {} {{\n", local.name));
+                self.indent += 1;
+                // the parser numbers flags, only values that aren't the next bit were written
+                let mut next = Some(1);
+                for (name, value) in names {
+                    self.write_indent();
+                    match value {
+                        Some(v) if !*flags || Some(*v) != next => self.out.push_str(&format!("{} = {},\n", name, v)),
+                        _       => self.out.push_str(&format!("{},\n", name)),
+                    }
+                    next = value.and_then(|v| v.checked_mul(2));
+                }
+                let close = self.find_close(local.loc.end);
+                self.before(close, false);
+                self.indent -= 1;
+                self.write_indent();
+                self.out.push('}');
+                self.last = close + 1;
+            }
+            ast::Def::Const{typed: t, expr, comptime} => {
+                self.out.push_str(Self::vis(&local.vis));
+                let comptime = if *comptime { "comptime " } else { "" };
+                self.out.push_str(&format!("const {} = {}{};", named(t, &ast::Tags::new(), &local.name), comptime, self.expr(expr)));
+                self.last = local.loc.end;
+            }
+            ast::Def::TypeAlias{typed: t} => {
+                self.out.push_str(Self::vis(&local.vis));
+                self.out.push_str(&format!("type {} = {};", local.name, typed(t)));
+                self.last = local.loc.end;
+            }
+            ast::Def::Static{tags: tg, typed: t, expr, storage, array, comptime} => {
+                let storage = match storage {
+                    ast::Storage::Static        => "static",
+                    ast::Storage::ThreadLocal   => "thread_local",
+                    ast::Storage::Atomic        => "atomic",
+                    ast::Storage::Const         => "const",
+                };
+                self.out.push_str(&format!("{} {}", storage, named(t, tg, &local.name)));
+                match array {
+                    Some(Some(array))   => self.out.push_str(&format!("[{}]", self.expr(array))),
+                    Some(None)          => self.out.push_str("[]"),
+                    None                => (),
+                }
+                let comptime = if *comptime { "comptime " } else { "" };
+                self.out.push_str(&format!(" = {}{};", comptime, self.expr(expr)));
+                self.last = local.loc.end;
+            }
+            ast::Def::Test{name, body} => {
+                let text = local.loc.text();
+                let src = text["test".len()..].trim_start();
+                // raw names as written, up to their closing quote and hashes
+                let hashes = src.strip_prefix('r').map(|s| s.chars().take_while(|c| *c == '#').count());
+                let end = hashes.and_then(|h| {
+                    let close = format!("\"{}", "#".repeat(h));
+                    src[h + 2..].find(&close).map(|p| h + 2 + p + close.len())
+                });
+                match end {
+                    Some(end) => self.out.push_str(&format!("test {} ", &src[..end])),
+                    None => self.out.push_str(&format!("test \"{}\" ", name)),
+                }
+                self.block(body);
+            }
+            ast::Def::Macro{args, body} => {
+                self.out.push_str(Self::vis(&local.vis));
+                self.out.push_str(&format!("macro {}({}) ", local.name, args.join(", ")));
+                self.block(body);
+            }
+            ast::Def::Testcase{fields} => {
+                if local.name.starts_with("anonymous_test_case_") {
+                    self.out.push_str("test {\n");
+                } else {
+                    self.out.push_str(&format!("test {} {{\n", local.name));
+                }
+                self.indent += 1;
+                for (name, expr) in fields {
+                    self.before(expr_start(expr), false);
+                    self.write_indent();
+                    self.out.push_str(&format!("{} = {}", name, self.expr(expr)));
+                    self.trailing(expr.loc().end);
+                    self.out.push('\n');
+                }
+                self.indent -= 1;
+                self.out.push('}');
+            }
+            ast::Def::Include{..} => unreachable!(),
+        }
+    }
+
+    pub fn module(mut self, md: &ast::Module) -> String {
+        enum Item<'a> {
+            Import(&'a ast::Import),
+            Local(&'a ast::Local, Option<&'a ast::Local>, Vec<&'a ast::Local>),
+            // statemachines and wires are kept as written, after their doc
+            Verbatim(&'a ast::Location, &'a str),
+        }
+
+        let mut items : Vec<(usize, Item)> = Vec::new();
+        for import in &md.imports {
+            items.push((import.loc.start, Item::Import(import)));
+        }
+        let mut ctxs = HashMap::new();
+        for local in &md.locals {
+            if let ast::Def::Closure{ctx, ..} = &local.def {
+                ctxs.insert(ctx.clone(), local.name.clone());
+            }
+        }
+        // methods are written in the body of their struct
+        let is_method = |l: &ast::Local| matches!(l.def, ast::Def::Function{..}) && l.name.contains("::");
+        for local in &md.locals {
+            if ctxs.contains_key(&local.name) || is_method(local) {
+                continue;
+            }
+            if matches!(local.def, ast::Def::Struct{..}) && inline_struct(self.src, &local.loc) {
+                continue;
+            }
+            let ctx = match &local.def {
+                ast::Def::Closure{ctx, ..} => md.locals.iter().find(|l|&l.name == ctx),
+                _ => None,
+            };
+            let prefix = format!("{}::", local.name);
+            let methods = match &local.def {
+                ast::Def::Struct{..} => md.locals.iter().filter(|l| is_method(*l) && l.name.starts_with(&prefix)).collect(),
+                _ => Vec::new(),
+            };
+            items.push((local.loc.start, Item::Local(local, ctx, methods)));
+        }
+        for sm in &md.statemachines {
+            items.push((sm.loc.start, Item::Verbatim(&sm.loc, &sm.doc)));
+        }
+        for wire in &md.wires {
+            items.push((wire.loc.start, Item::Verbatim(&wire.loc, &wire.doc)));
+        }
+        if let Some(loc) = &md.interface {
+            items.push((loc.start, Item::Verbatim(loc, "")));
+        }
+        items.sort_by_key(|i|i.0);
+
+        let mut previous_import = false;
+        for (start, item) in items {
+            match item {
+                Item::Import(import) => {
+                    self.before(start, !previous_import);
+                    if self.commented(start, import.loc.end) {
+                        self.verbatim(start, import.loc.end);
+                    } else {
+                        self.import(import);
+                    }
+                    self.trailing(import.loc.end);
+                    previous_import = true;
+                }
+                Item::Local(local, ctx, methods) => {
+                    self.before(start, true);
+                    // /// lines are kept as comments by before()
+                    let line_doc = parser::line_doc(self.src, &self.comments, local.loc.start);
+                    self.doc(local.doc.strip_prefix(line_doc.as_str()).unwrap_or(&local.doc));
+                    let deprecated = md.deprecated.get(&local.name).map(|d| &d.loc);
+                    let unstable   = md.unstable.get(&local.name).map(|u| &u.loc);
+                    let derive     = md.derive.get(&local.name).map(|d| &d.loc);
+                    let weak       = match &local.def {
+                        ast::Def::Function{attr, ..} => attr.get("weak"),
+                        _ => None,
+                    };
+                    let overrides  = md.overrides.get(&local.name).map(|o| &o.loc);
+                    for loc in deprecated.into_iter().chain(unstable).chain(derive).chain(weak).chain(overrides) {
+                        self.write_indent();
+                        self.out.push_str(&loc.text());
+                        self.out.push('\n');
+                    }
+                    let simple = matches!(local.def,
+                        ast::Def::Const{..} | ast::Def::Static{..} | ast::Def::Fntype{..} | ast::Def::Theory{..}
+                        | ast::Def::TypeAlias{..});
+                    if simple && self.commented(start, local.loc.end) {
+                        self.verbatim(start, local.loc.end);
+                    } else {
+                        self.local(local, ctx, &methods);
+                    }
+                    let last = self.last;
+                    self.trailing(last);
+                    previous_import = false;
+                }
+                Item::Verbatim(loc, doc) => {
+                    self.before(start, true);
+                    self.doc(doc);
+                    self.verbatim(start, loc.end);
+                    self.trailing(loc.end);
+                    previous_import = false;
+                }
+            }
+            self.out.push('\n');
+        }
+        self.before(self.src.len(), false);
+        self.out
+    }
+}
+
+/// format a single file. returns the formatted source, or an error message
+pub fn format(path: &Path) -> Result<String, String> {
+    let src = std::fs::read_to_string(path).map_err(|e|format!("{}: {}", path.display(), e))?;
+    for line in src.lines() {
+        let line = line.trim_start();
+        if line.starts_with("#if") || line.starts_with("#elif") || line.starts_with("#else") || line.starts_with("#endif") {
+            return Err(format!("{}: cannot format files with preprocessor conditions yet", path.display()));
+        }
+    }
+
+    let md = parse(path)?;
+    let out = print(&md, &src, false);
+
+    // the formatted file must parse into the same ast, or we'd silently break code
+    let dir = tempdir::TempDir::new("zzfmt").map_err(|e|format!("{}", e))?;
+    let check = dir.path().join(path.file_name().unwrap());
+    std::fs::write(&check, &out).map_err(|e|format!("{}", e))?;
+    let md2 = parse(&check).map_err(|e| format!("{}: ICE: formatting would break this file\n{}", path.display(), e))?;
+    if print(&md, &src, true) != print(&md2, &out, true) || print(&md2, &out, false) != out {
+        return Err(format!("{}: ICE: formatting would change the meaning of this file", path.display()));
+    }
+    Ok(out)
+}
+
+/// parse a file without exiting on syntax errors, which are returned as they would be printed
+fn parse(path: &Path) -> Result<ast::Module, String> {
+    let features = HashMap::new();
+    let stage    = make::Stage::test();
+    // formatting is the same in every edition, and the oldest one accepts all syntax
+    let (md, diagnostics) = parser::capture(|| parser::parse(path, &features, &stage, Edition::E2020));
+    match md {
+        Some(md) => Ok(md),
+        None => Err(diagnostics.into_iter().map(|d| match d {
+            parser::Diagnostic::Json(s)
+            | parser::Diagnostic::Log(_, s)
+            | parser::Diagnostic::Text(s)
+            | parser::Diagnostic::Warning(s) => s,
+        }).collect::<Vec<_>>().join("\n")),
+    }
+}
+
+fn print(md: &ast::Module, src: &str, explicit: bool) -> String {
+    Printer::new(md, src, explicit).module(md)
+}
+
+/// every declaration of md printed on its own, without comments and with every compound expression in parens,
+/// so two of them are the same text when they mean the same. methods are separate from their struct. see diff.rs
+pub fn declarations<'m>(md: &'m ast::Module, src: &str) -> Vec<(&'m ast::Local, String)> {
+    let ctxs : Vec<&String> = md.locals.iter().filter_map(|l| match &l.def {
+        ast::Def::Closure{ctx, ..} => Some(ctx),
+        _ => None,
+    }).collect();
+    md.locals.iter()
+        .filter(|l| !ctxs.contains(&&l.name))
+        .filter(|l| !(matches!(l.def, ast::Def::Struct{..}) && inline_struct(src, &l.loc)))
+        .map(|l| {
+            let ctx = match &l.def {
+                ast::Def::Closure{ctx, ..} => md.locals.iter().find(|c| &c.name == ctx),
+                _ => None,
+            };
+            let mut p = Printer::new(md, src, true);
+            p.local(l, ctx, &[]);
+            (l, p.out)
+        })
+        .collect()
+}
+
+fn collect(dir: &Path, files: &mut Vec<std::path::PathBuf>) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(v) => v,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect(&path, files);
+        } else if path.extension().map(|e| e == "zz").unwrap_or(false) {
+            files.push(path);
+        }
+    }
+}
+
+pub fn main(check: bool, files: Vec<String>) {
+    let mut files : Vec<std::path::PathBuf> = files.into_iter().map(std::path::PathBuf::from).collect();
+    if files.is_empty() {
+        let (root, _) = super::project::load_cwd();
+        collect(&root.join("src"), &mut files);
+        collect(&root.join("tests"), &mut files);
+        files.sort();
+    }
+
+    let mut failed = false;
+    for path in files {
+        let out = match format(&path) {
+            Ok(v) => v,
+            Err(e) => {
+                error!("{}", e);
+                failed = true;
+                continue;
+            }
+        };
+        let src = std::fs::read_to_string(&path).unwrap_or_default();
+        if src == out {
+            continue;
+        }
+        if check {
+            println!("{}", path.display());
+            failed = true;
+        } else if let Err(e) = std::fs::write(&path, out) {
+            error!("{}: {}", path.display(), e);
+            failed = true;
+        }
+    }
+    if failed {
+        std::process::exit(9);
+    }
+}
@@ -0,0 +1,279 @@
+/// constant folding and dead branches, on the flat module after it is proven and before it is emitted
+
+use super::ast;
+use super::flatten;
+use super::macros::{Visit, walk_expr, walk_statement};
+use std::collections::HashMap;
+
+const MAX_DEPTH : usize = 64;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Value {
+    Int(i128),
+    Bool(bool),
+}
+
+impl Value {
+    fn to_expr(self, loc: &ast::Location) -> ast::Expression {
+        match self {
+            Value::Int(i)   => ast::Expression::literal(loc.clone(), format!("{}", i)),
+            Value::Bool(b)  => ast::Expression::literal(loc.clone(), format!("{}", b)),
+        }
+    }
+}
+
+/// v, if c computes it as an int
+fn int(v: i128) -> Option<Value> {
+    if v >= i32::MIN as i128 && v <= i32::MAX as i128 {
+        Some(Value::Int(v))
+    } else {
+        None
+    }
+}
+
+/// whether a constant of type t has the value v in c, and is promoted to int
+fn fits(t: &ast::Type, v: Value) -> bool {
+    match (t, v) {
+        (ast::Type::Bool, Value::Bool(_)) => true,
+        (ast::Type::Int, Value::Int(_)) | (ast::Type::I32, Value::Int(_)) => true,
+        (ast::Type::I16, Value::Int(i)) => i >= i16::MIN as i128 && i <= i16::MAX as i128,
+        (ast::Type::I8, Value::Int(i))  => i >= i8::MIN as i128 && i <= i8::MAX as i128,
+        (ast::Type::U16, Value::Int(i)) => i >= 0 && i <= u16::MAX as i128,
+        (ast::Type::U8, Value::Int(i))  => i >= 0 && i <= u8::MAX as i128,
+        _ => false,
+    }
+}
+
+/// whether goto may jump into stm
+fn labeled(stm: &ast::Statement) -> bool {
+    match stm {
+        ast::Statement::Label{..} | ast::Statement::CBlock{..} => true,
+        ast::Statement::Block(b) | ast::Statement::Unsafe(b) => labeled_block(b),
+        ast::Statement::While{body, ..} => labeled_block(body),
+        ast::Statement::For{e1, e3, body, ..} => {
+            e1.iter().chain(e3.iter()).any(|s| labeled(s)) || labeled_block(body)
+        }
+        ast::Statement::If{branches} => branches.iter().any(|(_, _, b)| labeled_block(b)),
+        ast::Statement::Switch{cases, default, ..} => {
+            cases.iter().any(|(_, b)| labeled_block(b)) || default.iter().any(labeled_block)
+        }
+        _ => false,
+    }
+}
+
+fn labeled_block(block: &ast::Block) -> bool {
+    block.statements.iter().any(|s| labeled(s))
+}
+
+struct Fold {
+    // every constant of the module and those it uses, by absolute name
+    consts: HashMap<String, (ast::Type, ast::Expression)>,
+}
+
+impl Fold {
+    fn eval(&self, expr: &ast::Expression, depth: usize) -> Option<Value> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        match expr {
+            ast::Expression::Literal{value, ..} => match value {
+                ast::LiteralValue::Bool(b) => Some(Value::Bool(*b)),
+                ast::LiteralValue::Integer{value, suffix: None, ..} => int(*value),
+                _ => None,
+            },
+            ast::Expression::LiteralChar{v, ..} => int(*v as i128),
+            ast::Expression::Name(ast::Typed{t: ast::Type::Other(name), ptr, ..}) if ptr.is_empty() => {
+                let (t, expr) = self.consts.get(&name.to_string())?;
+                let v = self.eval(expr, depth + 1)?;
+                if fits(t, v) {
+                    Some(v)
+                } else {
+                    None
+                }
+            }
+            ast::Expression::UnaryPre{op, expr, ..} => {
+                match (op, self.eval(expr, depth + 1)?) {
+                    (ast::PrefixOperator::Boolnot, Value::Bool(b)) => Some(Value::Bool(!b)),
+                    (ast::PrefixOperator::Bitnot, Value::Int(i)) => int(!i),
+                    _ => None,
+                }
+            }
+            ast::Expression::Infix{lhs, rhs, op, ..} => {
+                let a = self.eval(lhs, depth + 1)?;
+                let b = self.eval(rhs, depth + 1)?;
+                match (a, b) {
+                    (Value::Bool(a), Value::Bool(b)) => self.bools(op, a, b),
+                    (Value::Int(a), Value::Int(b)) => self.ints(op, a, b),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn bools(&self, op: &ast::InfixOperator, a: bool, b: bool) -> Option<Value> {
+        match op {
+            ast::InfixOperator::Booland => Some(Value::Bool(a && b)),
+            ast::InfixOperator::Boolor  => Some(Value::Bool(a || b)),
+            ast::InfixOperator::Equals  => Some(Value::Bool(a == b)),
+            ast::InfixOperator::Nequals => Some(Value::Bool(a != b)),
+            _ => None,
+        }
+    }
+
+    fn ints(&self, op: &ast::InfixOperator, a: i128, b: i128) -> Option<Value> {
+        match op {
+            ast::InfixOperator::Equals      => Some(Value::Bool(a == b)),
+            ast::InfixOperator::Nequals     => Some(Value::Bool(a != b)),
+            ast::InfixOperator::Moreeq      => Some(Value::Bool(a >= b)),
+            ast::InfixOperator::Lesseq      => Some(Value::Bool(a <= b)),
+            ast::InfixOperator::Lessthan    => Some(Value::Bool(a < b)),
+            ast::InfixOperator::Morethan    => Some(Value::Bool(a > b)),
+            ast::InfixOperator::Add         => int(a + b),
+            ast::InfixOperator::Subtract    => int(a - b),
+            ast::InfixOperator::Multiply    => int(a * b),
+            ast::InfixOperator::Divide      if b != 0 => int(a / b),
+            ast::InfixOperator::Modulo      if b != 0 => int(a % b),
+            ast::InfixOperator::Bitxor      => int(a ^ b),
+            ast::InfixOperator::Bitand      => int(a & b),
+            ast::InfixOperator::Bitor       => int(a | b),
+            // shifting negative numbers, or by the width of int or more, is up to the c compiler
+            ast::InfixOperator::Shiftleft   if a >= 0 && (0..31).contains(&b) => int(a << b),
+            ast::InfixOperator::Shiftright  if a >= 0 && (0..31).contains(&b) => int(a >> b),
+            _ => None,
+        }
+    }
+
+    /// the value of a condition, if it is known
+    fn truth(&mut self, cond: &mut ast::Expression) -> Option<bool> {
+        self.expr(cond);
+        match self.eval(cond, 0) {
+            Some(Value::Bool(b)) => Some(b),
+            _ => None,
+        }
+    }
+
+    fn block(&mut self, block: &mut ast::Block) {
+        let mut out = Vec::new();
+        let mut reachable = true;
+        for stm in std::mem::take(&mut block.statements) {
+            if !reachable {
+                if !labeled(&stm) {
+                    continue;
+                }
+                reachable = true;
+            }
+            if let Some(stm) = self.fold_statement(stm) {
+                if let ast::Statement::Return{..} | ast::Statement::Break{..} | ast::Statement::Continue{..} = *stm {
+                    reachable = false;
+                }
+                out.push(stm);
+            }
+        }
+        block.statements = out;
+    }
+
+    /// stm with constants folded, or none if it does nothing
+    fn fold_statement(&mut self, mut stm: Box<ast::Statement>) -> Option<Box<ast::Statement>> {
+        match &mut *stm {
+            ast::Statement::If{branches} => {
+                let fixed = branches.iter().any(|(_, _, b)| labeled_block(b));
+                let mut kept = Vec::new();
+                for (loc, mut cond, mut body) in std::mem::take(branches) {
+                    let taken = match &mut cond {
+                        Some(cond) => self.truth(cond),
+                        None => None,
+                    };
+                    self.block(&mut body);
+                    match taken {
+                        Some(false) if !fixed => continue,
+                        Some(true) if !fixed => {
+                            kept.push((loc, None, body));
+                            break;
+                        }
+                        _ => kept.push((loc, cond, body)),
+                    }
+                }
+                if kept.is_empty() {
+                    return None;
+                }
+                if kept[0].1.is_none() {
+                    let (_, _, body) = kept.remove(0);
+                    return Some(Box::new(ast::Statement::Block(Box::new(body))));
+                }
+                *branches = kept;
+            }
+            ast::Statement::While{expr, body} => {
+                if self.truth(expr) == Some(false) && !labeled_block(body) {
+                    return None;
+                }
+                self.block(body);
+            }
+            ast::Statement::For{e1, e2, e3, body} => {
+                for s in e1.iter_mut().chain(e3.iter_mut()) {
+                    walk_statement(self, s);
+                }
+                if let Some(e2) = e2 {
+                    self.expr(e2);
+                }
+                self.block(body);
+            }
+            ast::Statement::Switch{expr, cases, default, ..} => {
+                self.expr(expr);
+                for (conds, body) in cases {
+                    for cond in conds {
+                        self.expr(cond);
+                    }
+                    self.block(body);
+                }
+                if let Some(default) = default {
+                    self.block(default);
+                }
+            }
+            ast::Statement::Block(body) | ast::Statement::Unsafe(body) => {
+                self.block(body);
+            }
+            other => walk_statement(self, other),
+        }
+        Some(stm)
+    }
+}
+
+impl Visit for Fold {
+    fn expr(&mut self, expr: &mut ast::Expression) {
+        walk_expr(self, expr);
+        let foldable = match expr {
+            ast::Expression::Infix{..} => true,
+            ast::Expression::UnaryPre{op, ..} => matches!(op, ast::PrefixOperator::Boolnot | ast::PrefixOperator::Bitnot),
+            _ => false,
+        };
+        if foldable {
+            if let Some(v) = self.eval(expr, 0) {
+                let folded = v.to_expr(expr.loc());
+                *expr = folded;
+            }
+        }
+    }
+}
+
+/// fold the constants of every function and initializer of md
+pub fn fold(md: &mut flatten::Module) {
+    let mut f = Fold {
+        consts: HashMap::new(),
+    };
+    for (local, _) in &md.d {
+        if let ast::Def::Const{typed, expr, ..} = &local.def {
+            if typed.ptr.is_empty() {
+                f.consts.insert(local.name.clone(), (typed.t.clone(), expr.clone()));
+            }
+        }
+    }
+
+    for (local, _) in &mut md.d {
+        match &mut local.def {
+            ast::Def::Function{body, ..} | ast::Def::Test{body, ..} => f.block(body),
+            ast::Def::Const{expr, ..} | ast::Def::Static{expr, ..} => f.expr(expr),
+            _ => (),
+        }
+    }
+}
@@ -0,0 +1,181 @@
+/// printf style format strings
+
+use super::ast;
+use super::name::Name;
+
+pub const TAG : &str = "format";
+
+/// the index of the format argument of c functions known to take one
+pub fn known(name: &Name) -> Option<usize> {
+    if name.0.get(1).map(|s| s.as_str()) != Some("ext") {
+        return None;
+    }
+    match name.0.last()?.as_str() {
+        "printf"                                => Some(0),
+        "fprintf" | "sprintf" | "dprintf"       => Some(1),
+        "snprintf"                              => Some(2),
+        _ => None,
+    }
+}
+
+/// the index of the argument tagged format of a vararg function
+pub fn tagged(args: &[ast::NamedArg]) -> Option<usize> {
+    args.iter().position(|arg| arg.typed.ptr.iter().any(|p| p.tags.contains_key(TAG)) || arg.tags.contains_key(TAG))
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Conversion {
+    // d i u o x X c, with the length modifier
+    Int(Length),
+    Float,
+    Str,
+    Ptr,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Length {
+    Int,
+    Long,
+    Size,
+}
+
+/// the conversions of a format string, in the order they take arguments.
+/// * as width or precision takes an int
+fn conversions(format: &[u8]) -> Result<Vec<(String, Conversion)>, String> {
+    let mut r = Vec::new();
+    let mut i = 0;
+    while i < format.len() {
+        if format[i] != b'%' {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        i += 1;
+        while i < format.len() && b"-+ #0'".contains(&format[i]) {
+            i += 1;
+        }
+        // width, then precision
+        for precision in &[false, true] {
+            if *precision {
+                if i >= format.len() || format[i] != b'.' {
+                    break;
+                }
+                i += 1;
+            }
+            if i < format.len() && format[i] == b'*' {
+                r.push(("*".to_string(), Conversion::Int(Length::Int)));
+                i += 1;
+            }
+            while i < format.len() && format[i].is_ascii_digit() {
+                i += 1;
+            }
+        }
+        let mut length = Length::Int;
+        while i < format.len() && b"hlLjzt".contains(&format[i]) {
+            length = match format[i] {
+                b'h'                => length,
+                b'l' | b'j' | b'L'  => Length::Long,
+                _                   => Length::Size,
+            };
+            i += 1;
+        }
+        let c = match format.get(i) {
+            Some(c) => *c,
+            None => return Err("format string ends inside a conversion".to_string()),
+        };
+        i += 1;
+        let spec = String::from_utf8_lossy(&format[start..i]).to_string();
+        let conversion = match c {
+            b'%' => continue,
+            b'd' | b'i' | b'u' | b'o' | b'x' | b'X' | b'c'              => Conversion::Int(length),
+            b'f' | b'F' | b'e' | b'E' | b'g' | b'G' | b'a' | b'A'       => Conversion::Float,
+            b's'                                                        => Conversion::Str,
+            b'p' | b'n'                                                 => Conversion::Ptr,
+            _ => return Err(format!("unknown conversion {}", spec)),
+        };
+        r.push((spec, conversion));
+    }
+    Ok(r)
+}
+
+/// why format string b takes other arguments than a, if it does
+pub fn differs(a: &[u8], b: &[u8]) -> Option<String> {
+    let a = match conversions(a) {
+        Ok(a) => a,
+        Err(e) => return Some(e),
+    };
+    let b = match conversions(b) {
+        Ok(b) => b,
+        Err(e) => return Some(e),
+    };
+    for (i, (spec, conversion)) in a.iter().enumerate() {
+        match b.get(i) {
+            None => return Some(format!("{} conversions instead of {}. {} is missing", b.len(), a.len(), spec)),
+            Some((other, c)) if c != conversion => return Some(format!("{} takes another argument than {}", other, spec)),
+            _ => (),
+        }
+    }
+    if b.len() > a.len() {
+        return Some(format!("{} conversions instead of {}. {} has no argument", b.len(), a.len(), b[a.len()].0));
+    }
+    None
+}
+
+/// why t can't be printed with conversion, if it can't
+fn mismatch(spec: &str, conversion: Conversion, t: &ast::Typed) -> Option<String> {
+    let scalar = t.ptr.is_empty();
+    let float = scalar && matches!(t.t, ast::Type::F32 | ast::Type::F64);
+    let length = if scalar {
+        match t.t {
+            ast::Type::U64 | ast::Type::I64 | ast::Type::U128 | ast::Type::I128 => Some(Length::Long),
+            ast::Type::USize | ast::Type::ISize => Some(Length::Size),
+            ast::Type::U8 | ast::Type::U16 | ast::Type::U32
+            | ast::Type::I8 | ast::Type::I16 | ast::Type::I32
+            | ast::Type::Int | ast::Type::UInt | ast::Type::Bool => Some(Length::Int),
+            _ => None,
+        }
+    } else {
+        None
+    };
+    match conversion {
+        Conversion::Int(want) => {
+            if !scalar || float {
+                return Some(format!("{} needs an integer, but this is {}", spec, t));
+            }
+            match length {
+                Some(have) if have != want => {
+                    let fix = match have {
+                        Length::Int     => "%d or %u",
+                        Length::Long    => "%lld or %llu with a cast to long long, or the PRI macros of inttypes.h",
+                        Length::Size    => "%zu or %zd",
+                    };
+                    Some(format!("{} does not fit {}, which needs {}", spec, t, fix))
+                }
+                _ => None,
+            }
+        }
+        Conversion::Float if !scalar || length.is_some() => Some(format!("{} needs a floating point number, but this is {}", spec, t)),
+        Conversion::Str if t.ptr.len() > 1 || float || length.is_some() => Some(format!("{} needs a string, but this is {}", spec, t)),
+        Conversion::Ptr if float || length.is_some() => Some(format!("{} needs a pointer, but this is {}", spec, t)),
+        _ => None,
+    }
+}
+
+/// check the arguments following a format string. returns the index into args of the argument that is wrong,
+/// or none if there are too few, and why
+pub fn check(format: &[u8], args: &[ast::Typed]) -> Result<(), (Option<usize>, String)> {
+    let conversions = conversions(format).map_err(|e| (None, e))?;
+    for (i, (spec, conversion)) in conversions.iter().enumerate() {
+        let t = match args.get(i) {
+            Some(t) => t,
+            None => return Err((None, format!("{} conversions, but {} arguments. {} has no argument", conversions.len(), args.len(), spec))),
+        };
+        if let Some(e) = mismatch(spec, *conversion, t) {
+            return Err((Some(i), e));
+        }
+    }
+    if args.len() > conversions.len() {
+        return Err((Some(conversions.len()), format!("{} conversions, but {} arguments", conversions.len(), args.len())));
+    }
+    Ok(())
+}
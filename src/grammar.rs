@@ -0,0 +1,630 @@
+/// the grammar of zz as ebnf and railroad diagrams, generated from zz.pest
+
+use std::fmt::Write;
+
+const GRAMMAR : &str = include_str!("zz.pest");
+
+#[derive(Clone, Debug)]
+enum Node {
+    Str(String),
+    Insensitive(String),
+    Range(char, char),
+    Rule(String),
+    Seq(Vec<Node>),
+    Choice(Vec<Node>),
+    Opt(Box<Node>),
+    Star(Box<Node>),
+    Plus(Box<Node>),
+    Not(Box<Node>),
+    And(Box<Node>),
+}
+
+struct Rule {
+    name:   String,
+    atomic: bool,
+    doc:    Vec<String>,
+    node:   Node,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Insensitive(String),
+    Char(char),
+    Int(usize),
+    Punct(char),
+    Range,
+    Doc(String),
+}
+
+fn unescape(chars: &mut std::iter::Peekable<std::str::Chars>, quote: char) -> String {
+    let mut s = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c == quote => break,
+            '\\' => match chars.next() {
+                Some('n')   => s.push('\n'),
+                Some('r')   => s.push('\r'),
+                Some('t')   => s.push('\t'),
+                Some('0')   => s.push('\0'),
+                Some(c)     => s.push(c),
+                None        => break,
+            },
+            c => s.push(c),
+        }
+    }
+    s
+}
+
+fn tokens(src: &str) -> Vec<Token> {
+    let mut r = Vec::new();
+    let mut chars = src.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => (),
+            '/' if chars.peek() == Some(&'/') => {
+                let mut line = String::new();
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                    line.push(c);
+                }
+                // comments directly above a rule document it
+                r.push(Token::Doc(line.trim_start_matches('/').trim().to_string()));
+            }
+            '"' => r.push(Token::Str(unescape(&mut chars, '"'))),
+            '\'' => {
+                let s = unescape(&mut chars, '\'');
+                r.push(Token::Char(s.chars().next().unwrap_or('\0')));
+            }
+            '^' if chars.peek() == Some(&'"') => {
+                chars.next();
+                r.push(Token::Insensitive(unescape(&mut chars, '"')));
+            }
+            '.' if chars.peek() == Some(&'.') => {
+                chars.next();
+                r.push(Token::Range);
+            }
+            c if c.is_ascii_digit() => {
+                let mut n = c.to_digit(10).unwrap() as usize;
+                while let Some(d) = chars.peek().and_then(|c| c.to_digit(10)) {
+                    n = n * 10 + d as usize;
+                    chars.next();
+                }
+                r.push(Token::Int(n));
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let mut s = c.to_string();
+                while let Some(c) = chars.peek().filter(|c| c.is_ascii_alphanumeric() || **c == '_') {
+                    s.push(*c);
+                    chars.next();
+                }
+                r.push(Token::Ident(s));
+            }
+            c => r.push(Token::Punct(c)),
+        }
+    }
+    r
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    at:     usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.at)
+    }
+
+    fn bump(&mut self) -> Option<Token> {
+        let t = self.tokens.get(self.at).cloned();
+        self.at += 1;
+        t
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(&Token::Punct(c)) {
+            self.at += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn rules(&mut self) -> Result<Vec<Rule>, String> {
+        let mut rules = Vec::new();
+        let mut doc = Vec::new();
+        while let Some(t) = self.bump() {
+            let name = match t {
+                Token::Doc(line) => {
+                    doc.push(line);
+                    continue;
+                }
+                Token::Ident(name) => name,
+                other => return Err(format!("expected a rule, found {:?}", other)),
+            };
+            if !self.eat('=') {
+                return Err(format!("expected = after {}", name));
+            }
+            let mut atomic = false;
+            if let Some(Token::Punct(m)) = self.peek().cloned() {
+                if m != '{' {
+                    atomic = m == '@' || m == '$';
+                    self.at += 1;
+                }
+            } else if self.peek() == Some(&Token::Ident("_".to_string())) {
+                self.at += 1;
+            }
+            if !self.eat('{') {
+                return Err(format!("expected {{ in {}", name));
+            }
+            let node = self.choice()?;
+            if !self.eat('}') {
+                return Err(format!("expected }} at the end of {}", name));
+            }
+            rules.push(Rule { name, atomic, doc: std::mem::take(&mut doc), node });
+        }
+        Ok(rules)
+    }
+
+    fn skip_doc(&mut self) {
+        while let Some(Token::Doc(_)) = self.peek() {
+            self.at += 1;
+        }
+    }
+
+    fn choice(&mut self) -> Result<Node, String> {
+        self.skip_doc();
+        self.eat('|');
+        let mut v = vec![self.seq()?];
+        loop {
+            self.skip_doc();
+            if !self.eat('|') {
+                break;
+            }
+            v.push(self.seq()?);
+        }
+        Ok(if v.len() == 1 { v.pop().unwrap() } else { Node::Choice(v) })
+    }
+
+    fn seq(&mut self) -> Result<Node, String> {
+        let mut v = vec![self.term()?];
+        loop {
+            self.skip_doc();
+            if !self.eat('~') {
+                break;
+            }
+            v.push(self.term()?);
+        }
+        Ok(if v.len() == 1 { v.pop().unwrap() } else { Node::Seq(v) })
+    }
+
+    fn term(&mut self) -> Result<Node, String> {
+        self.skip_doc();
+        if self.eat('!') {
+            return Ok(Node::Not(Box::new(self.term()?)));
+        }
+        if self.eat('&') {
+            return Ok(Node::And(Box::new(self.term()?)));
+        }
+        let mut node = self.atom()?;
+        loop {
+            if self.eat('?') {
+                node = Node::Opt(Box::new(node));
+            } else if self.eat('*') {
+                node = Node::Star(Box::new(node));
+            } else if self.eat('+') {
+                node = Node::Plus(Box::new(node));
+            } else if self.peek() == Some(&Token::Punct('{')) && matches!(self.tokens.get(self.at + 1), Some(Token::Int(_)) | Some(Token::Punct(','))) {
+                self.at += 1;
+                node = self.repeat(node)?;
+            } else {
+                return Ok(node);
+            }
+        }
+    }
+
+    /// a{n}, a{n,}, a{,m} and a{n,m} as sequences of a and a?
+    fn repeat(&mut self, node: Node) -> Result<Node, String> {
+        let min = match self.peek() {
+            Some(Token::Int(n)) => { let n = *n; self.at += 1; n }
+            _ => 0,
+        };
+        let max = if self.eat(',') {
+            match self.peek() {
+                Some(Token::Int(n)) => { let n = *n; self.at += 1; Some(n) }
+                _ => None,
+            }
+        } else {
+            Some(min)
+        };
+        if !self.eat('}') {
+            return Err("expected } after a repetition".to_string());
+        }
+        let mut v = vec![node.clone(); min];
+        match max {
+            None => v.push(Node::Star(Box::new(node))),
+            Some(max) => v.extend((min..max).map(|_| Node::Opt(Box::new(node.clone())))),
+        }
+        Ok(Node::Seq(v))
+    }
+
+    fn atom(&mut self) -> Result<Node, String> {
+        match self.bump() {
+            Some(Token::Punct('(')) => {
+                let node = self.choice()?;
+                if !self.eat(')') {
+                    return Err("expected )".to_string());
+                }
+                Ok(node)
+            }
+            Some(Token::Str(s)) => Ok(Node::Str(s)),
+            Some(Token::Insensitive(s)) => Ok(Node::Insensitive(s)),
+            Some(Token::Char(a)) => {
+                if self.peek() == Some(&Token::Range) {
+                    self.at += 1;
+                    match self.bump() {
+                        Some(Token::Char(b)) => Ok(Node::Range(a, b)),
+                        _ => Err("expected a char after ..".to_string()),
+                    }
+                } else {
+                    Ok(Node::Str(a.to_string()))
+                }
+            }
+            Some(Token::Ident(name)) if name == "PUSH" => {
+                if !self.eat('(') {
+                    return Err("expected ( after PUSH".to_string());
+                }
+                let node = self.choice()?;
+                if !self.eat(')') {
+                    return Err("expected ) after PUSH(..".to_string());
+                }
+                Ok(node)
+            }
+            Some(Token::Ident(name)) => Ok(Node::Rule(name)),
+            other => Err(format!("unexpected {:?}", other)),
+        }
+    }
+}
+
+fn parse() -> Vec<Rule> {
+    let mut p = Parser { tokens: tokens(GRAMMAR), at: 0 };
+    match p.rules() {
+        Ok(rules) => rules,
+        Err(e) => {
+            error!("{}", msg!("grammar_unreadable", error = e));
+            std::process::exit(9);
+        }
+    }
+}
+
+/// rules of pest, written out in ebnf where they are used
+const BUILTINS : &[(&str, &str)] = &[
+    ("ANY",         "[#x0-#x10FFFF]"),
+    ("NEWLINE",     "#xA | #xD #xA | #xD"),
+    ("PEEK",        "/* the last PUSH */"),
+    ("POP",         "/* the last PUSH, removing it */"),
+    ("ASCII_DIGIT", "[0-9]"),
+    ("ASCII_ALPHA", "[a-zA-Z]"),
+    ("ASCII_ALPHANUMERIC", "[a-zA-Z0-9]"),
+];
+
+/// leaves out SOI and EOI, which match no input
+fn strip(node: Node) -> Option<Node> {
+    Some(match node {
+        Node::Rule(name) if name == "SOI" || name == "EOI" => return None,
+        Node::Seq(v) => {
+            let mut v : Vec<Node> = v.into_iter().filter_map(strip).collect();
+            match v.len() {
+                0 => return None,
+                1 => v.pop().unwrap(),
+                _ => Node::Seq(v),
+            }
+        }
+        Node::Choice(v) => Node::Choice(v.into_iter().filter_map(strip).collect()),
+        Node::Opt(n) => Node::Opt(Box::new(strip(*n)?)),
+        Node::Star(n) => Node::Star(Box::new(strip(*n)?)),
+        Node::Plus(n) => Node::Plus(Box::new(strip(*n)?)),
+        Node::Not(n) => Node::Not(Box::new(strip(*n)?)),
+        Node::And(n) => Node::And(Box::new(strip(*n)?)),
+        other => other,
+    })
+}
+
+fn ebnf_char(c: char) -> String {
+    if c.is_ascii_graphic() && c != '"' && c != '\'' && c != '[' && c != ']' && c != '-' && c != '^' && c != '\\' {
+        c.to_string()
+    } else {
+        format!("#x{:X}", c as u32)
+    }
+}
+
+fn ebnf_str(s: &str) -> String {
+    if s.chars().all(|c| c.is_ascii_graphic() || c == ' ') && !s.is_empty() {
+        if s.contains('"') {
+            if s.contains('\'') {
+                return s.chars().map(|c| format!("#x{:X}", c as u32)).collect::<Vec<_>>().join(" ");
+            }
+            return format!("'{}'", s);
+        }
+        return format!("\"{}\"", s);
+    }
+    s.chars().map(|c| {
+        if c.is_ascii_graphic() && c != '"' {
+            format!("\"{}\"", c)
+        } else {
+            format!("#x{:X}", c as u32)
+        }
+    }).collect::<Vec<_>>().join(" ")
+}
+
+fn ebnf(node: &Node, nested: bool) -> String {
+    let s = match node {
+        Node::Str(s) => return ebnf_str(s),
+        Node::Insensitive(s) => {
+            let v : Vec<String> = s.chars().map(|c| {
+                let (l, u) = (c.to_ascii_lowercase(), c.to_ascii_uppercase());
+                if l == u { ebnf_str(&c.to_string()) } else { format!("[{}{}]", ebnf_char(l), ebnf_char(u)) }
+            }).collect();
+            v.join(" ")
+        }
+        Node::Range(a, b) => return format!("[{}-{}]", ebnf_char(*a), ebnf_char(*b)),
+        Node::Rule(name) => return name.clone(),
+        Node::Seq(v) => {
+            let mut parts = Vec::new();
+            let mut i = 0;
+            while i < v.len() {
+                match (&v[i], v.get(i + 1)) {
+                    (Node::Not(not), Some(next)) => {
+                        parts.push(format!("({} - {})", ebnf(next, true), ebnf(not, true)));
+                        i += 2;
+                    }
+                    (n, _) => {
+                        parts.push(ebnf(n, true));
+                        i += 1;
+                    }
+                }
+            }
+            parts.join(" ")
+        }
+        Node::Choice(v) => v.iter().map(|n| ebnf(n, true)).collect::<Vec<_>>().join(" | "),
+        Node::Opt(n) => return format!("{}?", ebnf(n, true)),
+        Node::Star(n) => return format!("{}*", ebnf(n, true)),
+        Node::Plus(n) => return format!("{}+", ebnf(n, true)),
+        Node::Not(n) => return format!("/* not followed by {} */", ebnf(n, false)),
+        Node::And(n) => return format!("/* followed by {} */", ebnf(n, false)),
+    };
+    if nested { format!("({})", s) } else { s }
+}
+
+fn uses(node: &Node, into: &mut Vec<String>) {
+    match node {
+        Node::Rule(name) => into.push(name.clone()),
+        Node::Seq(v) | Node::Choice(v) => v.iter().for_each(|n| uses(n, into)),
+        Node::Opt(n) | Node::Star(n) | Node::Plus(n) | Node::Not(n) | Node::And(n) => uses(n, into),
+        _ => (),
+    }
+}
+
+fn rules() -> Vec<Rule> {
+    parse().into_iter().filter_map(|mut r| {
+        r.node = strip(r.node)?;
+        Some(r)
+    }).collect()
+}
+
+/// the grammar as w3c ebnf
+pub fn ebnf_text() -> String {
+    let rules = rules();
+    let mut s = String::new();
+    writeln!(s, "/* the grammar of zz {}, generated from zz.pest */", env!("CARGO_PKG_VERSION")).unwrap();
+    writeln!(s, "/* WHITESPACE and COMMENT may appear between the items of rules not marked ws: explicit */\n").unwrap();
+    let mut used = Vec::new();
+    for rule in &rules {
+        for line in &rule.doc {
+            writeln!(s, "/* {} */", line.replace("*/", "* /")).unwrap();
+        }
+        writeln!(s, "{} ::= {}{}", rule.name, ebnf(&rule.node, false),
+            if rule.atomic { " /* ws: explicit */" } else { "" }).unwrap();
+        uses(&rule.node, &mut used);
+    }
+    for (name, def) in BUILTINS {
+        if used.iter().any(|u| u == name) && !rules.iter().any(|r| r.name == *name) {
+            writeln!(s, "{} ::= {}", name, def).unwrap();
+        }
+    }
+    s
+}
+
+// railroad diagrams, laid out the way https://github.com/tabatkins/railroad-diagrams does
+
+const CHAR_WIDTH : f64 = 8.0;
+const GAP : f64 = 10.0;
+const ARC : f64 = 10.0;
+
+enum Item {
+    Terminal(String),
+    NonTerminal(String),
+    Comment(String),
+    Skip,
+    Seq(Vec<Item>),
+    Choice(Vec<Item>),
+    Loop(Box<Item>),
+}
+
+impl Item {
+    fn from(node: &Node) -> Item {
+        match node {
+            Node::Str(s) => Item::Terminal(format!("{:?}", s)),
+            Node::Insensitive(s) => Item::Terminal(format!("^{:?}", s)),
+            Node::Range(a, b) => Item::Terminal(format!("{:?}..{:?}", a, b)),
+            Node::Rule(name) => Item::NonTerminal(name.clone()),
+            Node::Seq(v) => Item::Seq(v.iter().map(Item::from).collect()),
+            Node::Choice(v) => Item::Choice(v.iter().map(Item::from).collect()),
+            Node::Opt(n) => Item::Choice(vec![Item::Skip, Item::from(n)]),
+            Node::Plus(n) => Item::Loop(Box::new(Item::from(n))),
+            Node::Star(n) => Item::Choice(vec![Item::Skip, Item::Loop(Box::new(Item::from(n)))]),
+            Node::Not(n) => Item::Comment(format!("not {}", ebnf(n, false))),
+            Node::And(n) => Item::Comment(format!("followed by {}", ebnf(n, false))),
+        }
+    }
+
+    /// width, height above and below the line through it
+    fn size(&self) -> (f64, f64, f64) {
+        match self {
+            Item::Terminal(s) | Item::NonTerminal(s) => (s.chars().count() as f64 * CHAR_WIDTH + 20.0, 11.0, 11.0),
+            Item::Comment(s) => (s.chars().count() as f64 * CHAR_WIDTH * 0.85 + 10.0, 8.0, 8.0),
+            Item::Skip => (0.0, 0.0, 0.0),
+            Item::Seq(v) => v.iter().map(|i| i.size()).fold((0.0, 0.0, 0.0), |(w, u, d), (iw, iu, id)| {
+                (w + iw + if w > 0.0 { GAP } else { 0.0 }, u.max(iu), d.max(id))
+            }),
+            Item::Choice(v) => {
+                let sizes : Vec<(f64, f64, f64)> = v.iter().map(|i| i.size()).collect();
+                let width = sizes.iter().map(|s| s.0).fold(0.0, f64::max) + 4.0 * ARC;
+                let mut down = sizes[0].2;
+                for s in &sizes[1..] {
+                    down += GAP + s.1.max(ARC) + s.2;
+                }
+                (width, sizes[0].1, down)
+            }
+            Item::Loop(item) => {
+                let (w, u, d) = item.size();
+                (w + 2.0 * ARC, u, d + GAP + ARC)
+            }
+        }
+    }
+
+    /// svg of the item with the line through it starting at x, y
+    fn svg(&self, out: &mut String, x: f64, y: f64) {
+        let (width, _, _) = self.size();
+        match self {
+            Item::Terminal(s) => {
+                writeln!(out, "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"22\" rx=\"10\"/>", x, y - 11.0, width).unwrap();
+                writeln!(out, "<text x=\"{}\" y=\"{}\">{}</text>", x + width / 2.0, y + 4.0, html(s)).unwrap();
+            }
+            Item::NonTerminal(s) => {
+                writeln!(out, "<a href=\"#{}\"><rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"22\"/>", s, x, y - 11.0, width).unwrap();
+                writeln!(out, "<text x=\"{}\" y=\"{}\">{}</text></a>", x + width / 2.0, y + 4.0, html(s)).unwrap();
+            }
+            Item::Comment(s) => {
+                writeln!(out, "<text class=\"comment\" x=\"{}\" y=\"{}\">{}</text>", x + width / 2.0, y - 4.0, html(s)).unwrap();
+                line(out, x, y, x + width, y);
+            }
+            Item::Skip => (),
+            Item::Seq(v) => {
+                let mut at = x;
+                for (i, item) in v.iter().enumerate() {
+                    if i > 0 {
+                        line(out, at, y, at + GAP, y);
+                        at += GAP;
+                    }
+                    item.svg(out, at, y);
+                    at += item.size().0;
+                }
+            }
+            Item::Choice(v) => {
+                let end = x + width;
+                let mut offset = 0.0;
+                for (i, item) in v.iter().enumerate() {
+                    let (w, u, d) = item.size();
+                    if i > 0 {
+                        offset += GAP + u.max(ARC);
+                        let iy = y + offset;
+                        writeln!(out, "<path d=\"M{} {} a{a} {a} 0 0 1 {a} {a} V{} a{a} {a} 0 0 0 {a} {a}\"/>",
+                            x, y, iy - ARC, a = ARC).unwrap();
+                        writeln!(out, "<path d=\"M{} {} a{a} {a} 0 0 0 {a} -{a} V{} a{a} {a} 0 0 1 {a} -{a}\"/>",
+                            end - 2.0 * ARC, iy, y + ARC, a = ARC).unwrap();
+                    } else {
+                        line(out, x, y, x + 2.0 * ARC, y);
+                        line(out, end - 2.0 * ARC, y, end, y);
+                    }
+                    let iy = y + offset;
+                    item.svg(out, x + 2.0 * ARC, iy);
+                    line(out, x + 2.0 * ARC + w, iy, end - 2.0 * ARC, iy);
+                    offset += d;
+                }
+            }
+            Item::Loop(item) => {
+                let (w, _, d) = item.size();
+                line(out, x, y, x + ARC, y);
+                item.svg(out, x + ARC, y);
+                line(out, x + ARC + w, y, x + width, y);
+                let below = y + d + GAP;
+                writeln!(out, "<path d=\"M{} {} a{a} {a} 0 0 1 {a} {a} V{} a{a} {a} 0 0 1 -{a} {a} H{} a{a} {a} 0 0 1 -{a} -{a} V{} a{a} {a} 0 0 1 {a} -{a}\"/>",
+                    x + ARC + w, y, below - ARC, x + ARC, y + ARC, a = ARC).unwrap();
+            }
+        }
+    }
+}
+
+fn line(out: &mut String, x1: f64, y1: f64, x2: f64, y2: f64) {
+    if x1 != x2 || y1 != y2 {
+        writeln!(out, "<path d=\"M{} {} L{} {}\"/>", x1, y1, x2, y2).unwrap();
+    }
+}
+
+fn html(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn diagram(node: &Node) -> String {
+    let item = Item::from(node);
+    let (w, u, d) = item.size();
+    let mut s = String::new();
+    writeln!(s, "<svg class=\"railroad\" width=\"{}\" height=\"{}\">", w + 40.0, u + d + 20.0).unwrap();
+    let y = u + 10.0;
+    writeln!(s, "<path d=\"M10 {} v20 m10 -20 v20 m-10 -10 h10\"/>", y - 10.0).unwrap();
+    item.svg(&mut s, 20.0, y);
+    writeln!(s, "<path d=\"M{} {} h10 m0 -10 v20\"/>", w + 20.0, y).unwrap();
+    writeln!(s, "</svg>").unwrap();
+    s
+}
+
+/// every rule as railroad diagram, with its ebnf, in one html page
+pub fn railroad_html() -> String {
+    let mut s = String::new();
+    writeln!(s, "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>zz grammar</title>\n<style>").unwrap();
+    writeln!(s, "body {{ font-family: sans-serif; margin: 2em; }}").unwrap();
+    writeln!(s, "svg.railroad path {{ stroke: #333; stroke-width: 2; fill: none; }}").unwrap();
+    writeln!(s, "svg.railroad rect {{ stroke: #333; stroke-width: 2; fill: #eef; }}").unwrap();
+    writeln!(s, "svg.railroad text {{ font: 13px monospace; text-anchor: middle; }}").unwrap();
+    writeln!(s, "svg.railroad text.comment {{ font: italic 11px monospace; fill: #666; }}").unwrap();
+    writeln!(s, "pre {{ color: #555; white-space: pre-wrap; }}").unwrap();
+    writeln!(s, "</style>\n</head>\n<body>\n<h1>zz {} grammar</h1>", env!("CARGO_PKG_VERSION")).unwrap();
+    for rule in rules() {
+        writeln!(s, "<h3 id=\"{}\">{}</h3>", rule.name, rule.name).unwrap();
+        for line in &rule.doc {
+            writeln!(s, "<p>{}</p>", html(line)).unwrap();
+        }
+        s.push_str(&diagram(&rule.node));
+        writeln!(s, "<pre>{} ::= {}</pre>", rule.name, html(&ebnf(&rule.node, false))).unwrap();
+    }
+    writeln!(s, "</body>\n</html>").unwrap();
+    s
+}
+
+pub fn main(format: &str, output: Option<&std::path::Path>) {
+    match format {
+        "railroad" => {
+            let path = output.map(|p| p.to_path_buf()).unwrap_or_else(|| {
+                let (root, _) = super::project::load_cwd();
+                root.join("target").join("doc").join("grammar.html")
+            });
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir).expect(&format!("cannot create {}", dir.display()));
+            }
+            std::fs::write(&path, railroad_html()).expect(&format!("cannot write {}", path.display()));
+            info!("{}", msg!("grammar_written", path = path.display()));
+        }
+        _ => {
+            let s = ebnf_text();
+            match output {
+                Some(path) => std::fs::write(path, s).expect(&format!("cannot write {}", path.display())),
+                None => print!("{}", s),
+            }
+        }
+    }
+}
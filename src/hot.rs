@@ -0,0 +1,229 @@
+use super::project;
+use super::make;
+use super::name::Name;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime};
+
+/// the lib artifact that is reloaded by zz run --hot
+fn core(artifacts: &[project::Artifact]) -> project::Artifact {
+    let libs : Vec<&project::Artifact> = artifacts.iter()
+        .filter(|a| matches!(a.typ, project::ArtifactType::Lib)).collect();
+    if libs.len() != 1 {
+        error!("zz run --hot needs exactly one lib artifact as the reloadable core, found {}", libs.len());
+        std::process::exit(9);
+    }
+    libs[0].clone()
+}
+
+/// modification times of everything a rebuild depends on
+fn snapshot(root: &Path) -> Vec<(PathBuf, SystemTime)> {
+    let mut r = Vec::new();
+    let mut dirs = vec![root.join("src")];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if let Ok(m) = std::fs::metadata(&path).and_then(|m|m.modified()) {
+                r.push((path, m));
+            }
+        }
+    }
+    if let Ok(m) = std::fs::metadata(root.join("zz.toml")).and_then(|m|m.modified()) {
+        r.push((root.join("zz.toml"), m));
+    }
+    r.sort();
+    r
+}
+
+/// prefix is what the symbols of export functions start with, module the c name of the main module
+fn shim(f: &mut std::fs::File, module: &str, prefix: &str) {
+    write!(f, "#include <dlfcn.h>\n").unwrap();
+    write!(f, "#include <stdbool.h>\n").unwrap();
+    write!(f, "#include <stdio.h>\n").unwrap();
+    write!(f, "#include <stdlib.h>\n").unwrap();
+    write!(f, "#include <string.h>\n").unwrap();
+    write!(f, "#include <time.h>\n").unwrap();
+    write!(f, "#include <unistd.h>\n\n").unwrap();
+
+    write!(f, "typedef void (*hot_fn)(void *);\n").unwrap();
+    write!(f, "typedef bool (*hot_update_fn)(void *);\n\n").unwrap();
+
+    write!(f, "static void          *lib;\n").unwrap();
+    write!(f, "static hot_update_fn  update;\n").unwrap();
+    write!(f, "static hot_fn         reload;\n").unwrap();
+    write!(f, "static void          *state;\n").unwrap();
+    write!(f, "static size_t         size;\n\n").unwrap();
+
+    write!(f, "static long generation(const char *stamp) {{\n").unwrap();
+    write!(f, "    long g = -1;\n").unwrap();
+    write!(f, "    FILE *fp = fopen(stamp, \"r\");\n").unwrap();
+    write!(f, "    if (fp) {{\n").unwrap();
+    write!(f, "        if (fscanf(fp, \"%ld\", &g) != 1) {{ g = -1; }}\n").unwrap();
+    write!(f, "        fclose(fp);\n").unwrap();
+    write!(f, "    }}\n").unwrap();
+    write!(f, "    return g;\n").unwrap();
+    write!(f, "}}\n\n").unwrap();
+
+    // dlopen returns the already loaded handle for a known path, so every generation is loaded from its own copy
+    write!(f, "static bool load(const char *so, long gen) {{\n").unwrap();
+    write!(f, "    char path[4096];\n").unwrap();
+    write!(f, "    snprintf(path, sizeof(path), \"%s.%ld\", so, gen);\n").unwrap();
+    write!(f, "    FILE *in  = fopen(so, \"rb\");\n").unwrap();
+    write!(f, "    FILE *out = fopen(path, \"wb\");\n").unwrap();
+    write!(f, "    if (!in || !out) {{\n").unwrap();
+    write!(f, "        fprintf(stderr, \"hot: cannot copy %s to %s\\n\", so, path);\n").unwrap();
+    write!(f, "        if (in) {{ fclose(in); }}\n").unwrap();
+    write!(f, "        if (out) {{ fclose(out); }}\n").unwrap();
+    write!(f, "        return false;\n").unwrap();
+    write!(f, "    }}\n").unwrap();
+    write!(f, "    char buf[65536];\n").unwrap();
+    write!(f, "    size_t n;\n").unwrap();
+    write!(f, "    while ((n = fread(buf, 1, sizeof(buf), in)) > 0) {{ fwrite(buf, 1, n, out); }}\n").unwrap();
+    write!(f, "    fclose(in);\n").unwrap();
+    write!(f, "    fclose(out);\n").unwrap();
+    write!(f, "    void *l = dlopen(path, RTLD_NOW | RTLD_LOCAL);\n").unwrap();
+    write!(f, "    unlink(path);\n").unwrap();
+    write!(f, "    if (!l) {{\n").unwrap();
+    write!(f, "        fprintf(stderr, \"hot: %s\\n\", dlerror());\n").unwrap();
+    write!(f, "        return false;\n").unwrap();
+    write!(f, "    }}\n").unwrap();
+    write!(f, "    hot_update_fn u = (hot_update_fn)dlsym(l, \"{}hot_update\");\n", prefix).unwrap();
+    write!(f, "    if (!u) {{\n").unwrap();
+    write!(f, "        fprintf(stderr, \"hot: %s does not export {}hot_update\\n\", so);\n", prefix).unwrap();
+    write!(f, "        dlclose(l);\n").unwrap();
+    write!(f, "        return false;\n").unwrap();
+    write!(f, "    }}\n").unwrap();
+    write!(f, "    const size_t *sz = (const size_t *)dlsym(l, \"sizeof_{}_State\");\n", module).unwrap();
+    write!(f, "    size_t nsize = sz ? *sz : 0;\n").unwrap();
+    write!(f, "    if (!state || nsize > size) {{\n").unwrap();
+    write!(f, "        state = realloc(state, nsize ? nsize : 1);\n").unwrap();
+    write!(f, "        memset((char *)state + size, 0, (nsize ? nsize : 1) - size);\n").unwrap();
+    write!(f, "        size = nsize ? nsize : 1;\n").unwrap();
+    write!(f, "    }}\n").unwrap();
+    write!(f, "    if (lib) {{ dlclose(lib); }}\n").unwrap();
+    write!(f, "    lib    = l;\n").unwrap();
+    write!(f, "    update = u;\n").unwrap();
+    write!(f, "    reload = (hot_fn)dlsym(l, \"{}hot_reload\");\n", prefix).unwrap();
+    write!(f, "    return true;\n").unwrap();
+    write!(f, "}}\n\n").unwrap();
+
+    write!(f, "static double now() {{\n").unwrap();
+    write!(f, "    struct timespec ts;\n").unwrap();
+    write!(f, "    clock_gettime(CLOCK_MONOTONIC, &ts);\n").unwrap();
+    write!(f, "    return (double)ts.tv_sec + (double)ts.tv_nsec / 1e9;\n").unwrap();
+    write!(f, "}}\n\n").unwrap();
+
+    write!(f, "int main(int argc, char **argv) {{\n").unwrap();
+    write!(f, "    if (argc < 3) {{\n").unwrap();
+    write!(f, "        fprintf(stderr, \"usage: %s <lib> <stamp>\\n\", argv[0]);\n").unwrap();
+    write!(f, "        return 2;\n").unwrap();
+    write!(f, "    }}\n").unwrap();
+    write!(f, "    setvbuf(stdout, NULL, _IOLBF, 0);\n").unwrap();
+    write!(f, "    long gen = generation(argv[2]);\n").unwrap();
+    write!(f, "    if (!load(argv[1], gen)) {{ return 1; }}\n").unwrap();
+    write!(f, "    hot_fn init = (hot_fn)dlsym(lib, \"{}hot_init\");\n", prefix).unwrap();
+    write!(f, "    if (init) {{ init(state); }}\n").unwrap();
+    write!(f, "    double checked = now();\n").unwrap();
+    write!(f, "    while (update(state)) {{\n").unwrap();
+    write!(f, "        if (now() - checked < 0.1) {{ continue; }}\n").unwrap();
+    write!(f, "        checked = now();\n").unwrap();
+    write!(f, "        long g = generation(argv[2]);\n").unwrap();
+    write!(f, "        if (g == gen) {{ continue; }}\n").unwrap();
+    write!(f, "        gen = g;\n").unwrap();
+    write!(f, "        if (load(argv[1], gen)) {{\n").unwrap();
+    write!(f, "            fprintf(stderr, \"hot: reloaded generation %ld\\n\", gen);\n").unwrap();
+    write!(f, "            if (reload) {{ reload(state); }}\n").unwrap();
+    write!(f, "        }}\n").unwrap();
+    write!(f, "    }}\n").unwrap();
+    write!(f, "    return 0;\n").unwrap();
+    write!(f, "}}\n").unwrap();
+}
+
+/// build the lib artifact, run it in a generated shim and reload it whenever the sources change
+pub fn run(variant: &str, stage: make::Stage) {
+    if cfg!(windows) {
+        error!("zz run --hot is not supported on windows");
+        std::process::exit(9);
+    }
+    super::build(super::BuildSet::All, variant, stage.clone(), false);
+    let (root, project) = project::load_cwd();
+    let artifact = core(project.artifacts.as_ref().expect("no artifacts"));
+
+    let mut main = Name::from(&artifact.main);
+    if !main.is_absolute() {
+        main.0.insert(0, "".into());
+    }
+    let module = main.0[1..].join("_");
+    let prefix = project.project.symbol_prefix.clone().unwrap_or(format!("{}_", module));
+
+    let td = root.join("target").join(stage.to_string()).join("hot");
+    std::fs::create_dir_all(&td).expect("create target dir");
+    let so    = root.join("target").join(stage.to_string()).join("lib").join(make::shared_lib(&artifact.name, make::host()));
+    let stamp = td.join("generation");
+    let src   = td.join("shim.c");
+    let exe   = td.join(&artifact.name);
+
+    let mut f = std::fs::File::create(&src).expect(&format!("cannot create {:?}", src));
+    shim(&mut f, &module, &prefix);
+    drop(f);
+
+    // the shim links with the same flags as the lib, so sanitizer runtimes are loaded before the lib is
+    let make = make::Make::new(project, variant, stage.clone(), artifact);
+    let cc = make.cc.clone();
+    let status = Command::new(&cc)
+        .arg(&src)
+        .args(make.lflags.iter().filter(|f|!f.is_empty()))
+        .arg("-o")
+        .arg(&exe)
+        .arg("-ldl")
+        .status()
+        .unwrap_or_else(|e| make::cannot_run(&cc, &e));
+    if !status.success() {
+        error!("{} failed to build the hot reload shim", cc);
+        std::process::exit(11);
+    }
+
+    let mut generation : u64 = 0;
+    std::fs::write(&stamp, format!("{}\n", generation)).expect(&format!("cannot write {:?}", stamp));
+
+    println!("running \"{}\" with hot reload of \"{}\"\n", exe.to_string_lossy(), so.to_string_lossy());
+    let mut child = Command::new(&exe)
+        .arg(&so)
+        .arg(&stamp)
+        .spawn()
+        .expect("failed to execute process");
+
+    let mut last = snapshot(&root);
+    loop {
+        if let Some(status) = child.try_wait().expect("failed to wait for process") {
+            std::process::exit(status.code().unwrap_or(1));
+        }
+        std::thread::sleep(Duration::from_millis(300));
+        let now = snapshot(&root);
+        if now == last {
+            continue;
+        }
+        last = now;
+
+        // the build may abort the process, so it runs in a separate zz
+        let mut cmd = Command::new(std::env::current_exe().expect("cannot find zz executable"));
+        cmd.current_dir(&root).arg("build").arg("--variant").arg(variant);
+        if stage.to_string() == make::Stage::release().to_string() {
+            cmd.arg("--release");
+        } else if stage.to_string() == make::Stage::debug().to_string() {
+            cmd.arg("--debug");
+        }
+        match cmd.status() {
+            Ok(status) if status.success() => {
+                generation += 1;
+                std::fs::write(&stamp, format!("{}\n", generation)).expect(&format!("cannot write {:?}", stamp));
+            }
+            _ => {
+                warn!("build failed, keeping the previous code loaded");
+            }
+        }
+    }
+}
@@ -0,0 +1,275 @@
+/// user facing strings in catalogs, one per language
+
+use super::ast;
+use super::format;
+use super::loader;
+use super::make::Stage;
+use super::name::Name;
+use super::parser::{self, emit_error, emit_warn};
+use super::project::{Config, I18n};
+use super::resources;
+use super::sourcemap;
+use super::uses;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+pub const MODULE : &str = "i18n";
+
+/// a string of a catalog
+struct Entry {
+    key:    String,
+    text:   String,
+    loc:    ast::Location,
+}
+
+struct Catalog {
+    lang:       String,
+    path:       PathBuf,
+    // in the order they are written
+    entries:    Vec<Entry>,
+}
+
+fn is_ident(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => chars.all(|c| c.is_ascii_alphanumeric() || c == '_'),
+        _ => false,
+    }
+}
+
+/// the key in the line of text that sets it, or the start of text
+fn key_span(text: &str, key: &str) -> (usize, usize) {
+    let quoted = format!("\"{}\"", key);
+    let mut start = 0;
+    for line in text.split('\n') {
+        let trimmed = line.trim_start();
+        let at = start + line.len() - trimmed.len();
+        for written in &[key, quoted.as_str()] {
+            if let Some(rest) = trimmed.strip_prefix(written) {
+                if rest.trim_start().starts_with('=') {
+                    return (at, at + written.len());
+                }
+            }
+        }
+        start += line.len() + 1;
+    }
+    (0, 0)
+}
+
+/// read a catalog file. there is nothing to check without it, so errors abort
+fn catalog(path: &Path) -> Catalog {
+    let text = std::fs::read_to_string(path).expect(&format!("cannot read {}", path.display()));
+    let strings : BTreeMap<String, String> = match toml::from_str(&text) {
+        Ok(strings) => strings,
+        Err(e) => {
+            error!("{}", msg!("i18n_catalog_invalid", path = path.display(), error = e));
+            parser::abort();
+        }
+    };
+    let file = sourcemap::add(&path.to_string_lossy(), text);
+    let text = file.source();
+    let mut entries : Vec<Entry> = strings.into_iter().map(|(key, s)| {
+        let (start, end) = key_span(&text, &key);
+        Entry {
+            key,
+            text:   s,
+            loc:    ast::Location::at(file, start, end),
+        }
+    }).collect();
+    entries.sort_by_key(|e| e.loc.start);
+    Catalog {
+        lang:   path.file_stem().unwrap().to_string_lossy().to_string(),
+        path:   path.to_path_buf(),
+        entries,
+    }
+}
+
+/// the catalog of the default language and those of the others
+fn catalogs(root: &Path, i18n: &I18n) -> (Catalog, Vec<Catalog>) {
+    let dir = root.join(&i18n.dir);
+    let mut translations : Vec<Catalog> = resources::list(&dir).into_iter()
+        .filter(|file| !file.contains('/') && file.ends_with(".toml"))
+        .map(|file| catalog(&dir.join(file)))
+        .collect();
+    match translations.iter().position(|c| c.lang == i18n.default) {
+        Some(at) => {
+            let default = translations.remove(at);
+            (default, translations)
+        }
+        None => {
+            let path = dir.join(format!("{}.toml", i18n.default));
+            error!("{}", msg!("i18n_default_missing", lang = i18n.default, path = path.display()));
+            parser::abort();
+        }
+    }
+}
+
+/// check the translations against the default catalog. returns false on errors
+fn check(default: &Catalog, translations: &[Catalog]) -> bool {
+    let mut ok = true;
+    for c in std::iter::once(default).chain(translations) {
+        if !is_ident(&c.lang) {
+            error!("{}", msg!("i18n_lang_invalid", lang = c.lang, path = c.path.display()));
+            ok = false;
+        }
+    }
+    if default.entries.is_empty() {
+        error!("{}", msg!("i18n_catalog_empty", path = default.path.display()));
+        ok = false;
+    }
+    for d in &default.entries {
+        if !is_ident(&d.key) {
+            emit_error(msg!("i18n_key_invalid", key = d.key), &[(d.loc.clone(), msg!("i18n_key_invalid.note"))]);
+            ok = false;
+        }
+    }
+
+    for c in translations {
+        for e in &c.entries {
+            let d = match default.entries.iter().find(|d| d.key == e.key) {
+                Some(d) => d,
+                None => {
+                    emit_warn(msg!("i18n_unknown_key", key = e.key, lang = c.lang, default = default.lang), &[
+                        (e.loc.clone(), msg!("i18n_unknown_key.note", default = default.lang)),
+                    ]);
+                    continue;
+                }
+            };
+            if let Some(why) = format::differs(d.text.as_bytes(), e.text.as_bytes()) {
+                emit_error(msg!("i18n_format", key = e.key, lang = c.lang, default = default.lang), &[
+                    (e.loc.clone(), why),
+                    (d.loc.clone(), msg!("i18n_format.default")),
+                ]);
+                ok = false;
+            }
+        }
+        for d in &default.entries {
+            if !c.entries.iter().any(|e| e.key == d.key) {
+                emit_warn(msg!("i18n_missing_key", key = d.key, lang = c.lang), &[
+                    (d.loc.clone(), msg!("i18n_missing_key.note", lang = c.lang, default = default.lang)),
+                ]);
+            }
+        }
+    }
+    ok
+}
+
+/// a zz string literal of s
+fn literal(s: &str) -> String {
+    let mut r = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"'     => r.push_str("\\\""),
+            '\\'    => r.push_str("\\\\"),
+            '\n'    => r.push_str("\\n"),
+            '\t'    => r.push_str("\\t"),
+            c if (c as u32) < 0x20 || c == '\x7f' => write!(r, "\\{:03o}", c as u32).unwrap(),
+            c => r.push(c),
+        }
+    }
+    r.push('"');
+    r
+}
+
+/// the generated module
+fn source(default: &Catalog, translations: &[Catalog]) -> String {
+    let mut s = String::new();
+    s.push_str("/// generated from [i18n] in zz.toml\n\n");
+
+    s.push_str("pub enum Lang {\n");
+    for c in std::iter::once(default).chain(translations) {
+        write!(s, "    {},\n", c.lang).unwrap();
+    }
+    s.push_str("}\n\npub enum Key {\n");
+    for d in &default.entries {
+        write!(s, "    {},\n", d.key).unwrap();
+    }
+    s.push_str("}\n\n");
+    write!(s, "pub const usize count = {};\n", default.entries.len()).unwrap();
+
+    for c in std::iter::once(default).chain(translations) {
+        write!(s, "\nconst char* strings_{}[{}] = {{\n", c.lang, default.entries.len()).unwrap();
+        for d in &default.entries {
+            let text = c.entries.iter().find(|e| e.key == d.key).unwrap_or(d);
+            write!(s, "    {},\n", literal(&text.text)).unwrap();
+        }
+        s.push_str("};\n");
+    }
+
+    s.push_str("\npub fn get(Lang lang, Key key) -> char* {\n");
+    s.push_str("    usize k = (usize)key;\n");
+    s.push_str("    if k >= count {\n        return \"\";\n    }\n");
+    for c in translations {
+        write!(s, "    if lang == Lang::{} {{\n        return strings_{}[k];\n    }}\n", c.lang, c.lang).unwrap();
+    }
+    write!(s, "    return strings_{}[k];\n}}\n", default.lang).unwrap();
+    s
+}
+
+/// generate and parse the i18n module of the project, if zz.toml has [i18n]
+pub fn load(
+    modules:        &mut HashMap<Name, loader::Module>,
+    project_name:   &Name,
+    root:           &Path,
+    config:         &Config,
+    features:       &HashMap<String, bool>,
+    stage:          &Stage,
+) {
+    let i18n = match &config.i18n {
+        Some(i18n) => i18n,
+        None => return,
+    };
+    let mut name = project_name.clone();
+    name.push(MODULE.to_string());
+    if modules.contains_key(&name) {
+        error!("{}", msg!("i18n_module_taken", name = name));
+        parser::abort();
+    }
+
+    let (default, translations) = catalogs(root, i18n);
+    if !check(&default, &translations) {
+        parser::abort();
+    }
+
+    let dir = root.join("target").join(stage.to_string()).join(MODULE);
+    std::fs::create_dir_all(&dir).expect(&format!("cannot create {}", dir.display()));
+    let path = dir.join(format!("{}.zz", MODULE));
+    std::fs::write(&path, source(&default, &translations)).expect(&format!("cannot write {}", path.display()));
+
+    let mut md = parser::parse(&path, features, stage, config.project.edition);
+    md.name = name.clone();
+    md.sources.insert(default.path);
+    md.sources.extend(translations.into_iter().map(|c| c.path));
+    modules.insert(name, loader::Module::ZZ(md));
+}
+
+/// warn about keys that no module of the project names
+pub fn unused(modules: &HashMap<Name, loader::Module>, names: &[Name], root: &Path, config: &Config) {
+    let i18n = match &config.i18n {
+        Some(i18n) => i18n,
+        None => return,
+    };
+    let module = Name(vec!["".into(), config.project.name.as_str().into(), MODULE.into()]);
+    let mut key = module.clone();
+    key.push("Key".to_string());
+
+    let mut used = HashSet::new();
+    for name in names {
+        if name.0[1] != config.project.name || *name == module {
+            continue;
+        }
+        if let Some(loader::Module::ZZ(md)) = modules.get(name) {
+            for u in uses::uses(md, &|n: &Name| n.0.len() == key.0.len() + 1 && n.0.starts_with(&key.0)) {
+                used.insert(u.name.0.last().unwrap().to_string());
+            }
+        }
+    }
+
+    let default = catalog(&root.join(&i18n.dir).join(format!("{}.toml", i18n.default)));
+    for d in &default.entries {
+        if !used.contains(&d.key) {
+            emit_warn(msg!("i18n_unused", key = d.key), &[(d.loc.clone(), msg!("i18n_unused.note", key = d.key))]);
+        }
+    }
+}
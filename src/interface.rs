@@ -0,0 +1,128 @@
+/// interface modules
+
+use super::ast;
+use super::closure;
+use super::loader;
+use super::make::Stage;
+use super::name::Name;
+use super::parser::emit_error;
+use super::project::Config;
+use std::collections::HashMap;
+
+/// the module a name in zz.toml is, in the project or else in a dependency
+fn resolve(path: &str, project: &str, modules: &HashMap<Name, loader::Module>) -> Option<Name> {
+    let own = Name::from(&format!("::{}::{}", project, path));
+    if modules.contains_key(&own) {
+        return Some(own);
+    }
+    let dep = Name::from(&format!("::{}", path));
+    if modules.contains_key(&dep) {
+        return Some(dep);
+    }
+    None
+}
+
+/// set the backend of every interface module zz.toml selects one for. returns false on errors
+pub fn select(modules: &mut HashMap<Name, loader::Module>, config: &Config, stage: &Stage) -> bool {
+    let mut selected = config.interfaces.clone();
+    if let Some(profile) = stage.profile(config) {
+        selected.extend(profile.interfaces.clone());
+    }
+
+    let mut ok = true;
+    for (interface, backend) in &selected {
+        let name = match resolve(interface, &config.project.name, modules) {
+            Some(name) => name,
+            None => {
+                error!("{}", msg!("interface_unknown", name = interface));
+                ok = false;
+                continue;
+            }
+        };
+        let target = match resolve(backend, &config.project.name, modules) {
+            Some(target) if matches!(modules.get(&target), Some(loader::Module::ZZ(_))) => target,
+            _ => {
+                error!("{}", msg!("interface_backend_unknown", name = interface, backend = backend));
+                ok = false;
+                continue;
+            }
+        };
+        match modules.get_mut(&name) {
+            Some(loader::Module::ZZ(md)) if md.interface.is_some() => {
+                md.backend = Some(target);
+            }
+            _ => {
+                error!("{}", msg!("interface_not_interface", name = interface));
+                ok = false;
+            }
+        }
+    }
+    ok
+}
+
+/// check that the backend of every interface module has its functions. returns false on errors
+pub fn check(modules: &HashMap<Name, loader::Module>, names: &[Name]) -> bool {
+    let mut ok = true;
+    for name in names {
+        let md = match modules.get(name) {
+            Some(loader::Module::ZZ(md)) if md.interface.is_some() => md,
+            _ => continue,
+        };
+        let backend = md.backend.as_ref().and_then(|b| match modules.get(b) {
+            Some(loader::Module::ZZ(backend)) => Some(backend),
+            _ => None,
+        });
+        for local in &md.locals {
+            let (args, ret, vararg) = match &local.def {
+                ast::Def::Fntype{args, ret, vararg, ..} => (args, ret, *vararg),
+                _ => {
+                    emit_error(msg!("interface_local", name = local.name), &[(local.loc.clone(), msg!("interface_local.note"))]);
+                    ok = false;
+                    continue;
+                }
+            };
+            let backend = match backend {
+                Some(backend) => backend,
+                None => continue,
+            };
+            let found = backend.locals.iter().find(|l| l.name == local.name);
+            match found.map(|l| (&l.def, &l.loc, &l.vis)) {
+                Some((ast::Def::Function{args: bargs, ret: bret, vararg: bvararg, ..}, loc, vis)) => {
+                    if *vis == ast::Visibility::Object {
+                        emit_error(msg!("interface_private", local = local.name, backend = backend.name, name = name), &[
+                            (loc.clone(), msg!("interface_private.note", name = name)),
+                        ]);
+                        ok = false;
+                    }
+                    let mismatch = if vararg != *bvararg {
+                        Some((None, "only one of them takes ...".to_string()))
+                    } else {
+                        closure::signature_mismatch(args, ret, bargs, bret, None)
+                    };
+                    if let Some((at, message)) = mismatch {
+                        let here = at.map(|i| bargs[i].loc.clone()).unwrap_or(loc.clone());
+                        emit_error(msg!("interface_signature", local = local.name, backend = backend.name, name = name), &[
+                            (here, message),
+                            (local.loc.clone(), msg!("defined_here")),
+                        ]);
+                        ok = false;
+                    }
+                }
+                Some((_, loc, _)) => {
+                    emit_error(msg!("interface_not_function", local = local.name, backend = backend.name), &[
+                        (loc.clone(), msg!("here")),
+                        (local.loc.clone(), msg!("interface_missing.note")),
+                    ]);
+                    ok = false;
+                }
+                None => {
+                    emit_error(msg!("interface_missing", local = local.name, backend = backend.name, name = name), &[
+                        (local.loc.clone(), msg!("interface_missing.note")),
+                    ]);
+                    ok = false;
+                }
+            }
+        }
+    }
+    ok
+}
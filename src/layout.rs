@@ -0,0 +1,148 @@
+/// layout fingerprints of wire format structs
+
+use super::ast;
+use super::loader;
+use super::name::Name;
+use super::parser::emit_error;
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+pub const FILE : &str = "layout.toml";
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Recorded {
+    pub version:        u64,
+    pub fingerprint:    String,
+}
+
+/// fnv-1a, because it must not change between builds of zz
+fn fnv(s: &str) -> String {
+    let mut h : u64 = 0xcbf29ce484222325;
+    for b in s.bytes() {
+        h ^= b as u64;
+        h = h.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", h)
+}
+
+struct Layouts<'a> {
+    modules: &'a HashMap<Name, loader::Module>,
+}
+
+impl<'a> Layouts<'a> {
+    fn local(&self, name: &Name) -> Option<&'a ast::Local> {
+        let mut module = name.clone();
+        let local = module.pop()?;
+        match self.modules.get(&module) {
+            Some(loader::Module::ZZ(md)) => md.locals.iter().find(|l| l.name == local),
+            _ => None,
+        }
+    }
+
+    /// everything about a struct that decides its layout, as text
+    fn describe(&self, def: &ast::Def) -> String {
+        let (fields, packed, union) = match def {
+            ast::Def::Struct{fields, packed, union, ..} => (fields, *packed, *union),
+            _ => return String::new(),
+        };
+        let mut s = String::from(if union { "union" } else { "struct" });
+        if packed {
+            s.push_str(" packed");
+        }
+        s.push('{');
+        for field in fields {
+            s.push_str(&format!("{} {}", field.typed, field.name));
+            match &field.array {
+                Some(Some(ast::Expression::Literal{v, ..})) => s.push_str(&format!("[{}]", v)),
+                Some(Some(expr))    => s.push_str(&format!("[{}]", expr.loc().text())),
+                Some(None)          => s.push_str("[]"),
+                None                => (),
+            }
+            if let Some(bits) = field.bits {
+                s.push_str(&format!(":{}", bits));
+            }
+            // nested structs are part of the layout, pointed to ones are not
+            if let ast::Type::Other(name) = &field.typed.t {
+                if field.typed.ptr.is_empty() {
+                    if let Some(local) = self.local(name) {
+                        s.push_str(&self.describe(&local.def));
+                    }
+                }
+            }
+            s.push(';');
+        }
+        s.push('}');
+        s
+    }
+}
+
+fn load(path: &Path) -> BTreeMap<String, Recorded> {
+    match std::fs::read_to_string(path) {
+        Ok(s) => match toml::from_str(&s) {
+            Ok(v) => v,
+            Err(e) => {
+                error!("{}: {}", path.display(), e);
+                std::process::exit(9);
+            }
+        },
+        Err(_) => BTreeMap::new(),
+    }
+}
+
+/// compare the wire format structs of md to layout.toml. returns false if one changed without a version bump
+pub fn check(md: &ast::Module, modules: &HashMap<Name, loader::Module>, recorded: &mut BTreeMap<String, Recorded>) -> bool {
+    let layouts = Layouts{modules};
+    let mut ok = true;
+    for local in &md.locals {
+        let (packed, abi) = match &local.def {
+            ast::Def::Struct{packed, abi, ..} => (*packed, *abi),
+            _ => continue,
+        };
+        if !packed && abi.is_none() {
+            continue;
+        }
+        let mut name = md.name.clone();
+        name.push(local.name.clone());
+        let now = Recorded {
+            version:        abi.unwrap_or(0),
+            fingerprint:    fnv(&layouts.describe(&local.def)),
+        };
+        match recorded.get(&name.to_string()).cloned() {
+            Some(was) if was.version == now.version && was.fingerprint != now.fingerprint => {
+                emit_error(msg!("layout_changed", name = name), &[
+                    (local.loc.clone(), msg!("layout_changed.note", version = now.version + 1, file = FILE)),
+                ]);
+                ok = false;
+            }
+            Some(was) if was == now => (),
+            _ => {
+                recorded.insert(name.to_string(), now);
+            }
+        }
+    }
+    ok
+}
+
+/// check all modules of the project, and record new layouts
+pub fn check_all(names: &[Name], modules: &HashMap<Name, loader::Module>, project: &str) -> bool {
+    let path = Path::new(FILE);
+    let mut recorded = load(path);
+    let before = recorded.clone();
+    let mut ok = true;
+    for name in names {
+        if name.0[1] != project {
+            continue;
+        }
+        if let Some(loader::Module::ZZ(ast)) = modules.get(name) {
+            ok &= check(ast, modules, &mut recorded);
+        }
+    }
+    if ok && recorded != before {
+        let s = toml::to_string(&recorded).expect("serialize layouts");
+        if let Err(e) = std::fs::write(path, s) {
+            warn!("cannot write {}: {}", path.display(), e);
+        }
+    }
+    ok
+}
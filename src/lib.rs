@@ -7,7 +7,9 @@ extern crate pbr;
 extern crate rayon;
 extern crate askama;
 
+#[macro_use] pub mod messages;
 pub mod ast;
+pub mod sourcemap;
 pub mod parser;
 pub mod project;
 pub mod make;
@@ -16,16 +18,81 @@ pub mod flatten;
 pub mod emitter;
 pub mod emitter_rs;
 pub mod emitter_js;
+pub mod emitter_py;
+pub mod emitter_lua;
 pub mod abs;
 pub mod name;
 pub mod pp;
 pub mod symbolic;
+pub mod fold;
 pub mod expand;
 pub mod smt;
 pub mod emitter_docs;
+pub mod closure;
+pub mod cache;
+pub mod lsp;
+pub mod fmt;
+pub mod hot;
+pub mod unittest;
+pub mod comptime;
+pub mod deps;
+pub mod cheader;
+pub mod selfprofile;
+pub mod macros;
+pub mod metadata;
+pub mod provenance;
+pub mod vla;
+pub mod pitfalls;
+pub mod struct_init;
+pub mod layout;
+pub mod alias;
+pub mod amalgamate;
+pub mod archive;
+pub mod buildfiles;
+pub mod edition;
+pub mod deprecated;
+pub mod uses;
+pub mod stability;
+pub mod diagnostics;
+pub mod warnings;
+pub mod api;
+pub mod analyze;
+pub mod unused;
+pub mod derive;
+pub mod statemachine;
+pub mod wire;
+pub mod nullable;
+pub mod flags;
+pub mod units;
+pub mod noreturn;
+pub mod format;
+pub mod weak;
+pub mod interface;
+pub mod defaults;
+pub mod resources;
+pub mod i18n;
+pub mod schema;
+pub mod symbols;
+pub mod cxxheader;
+pub mod crash;
+pub mod bindgen;
+pub mod reduce;
+pub mod grammar;
+pub mod tokens;
+pub mod diff;
+pub mod watch;
+pub mod migrate;
+pub mod postprocess;
+pub mod lintpass;
+pub mod package;
+pub mod doctor;
+pub mod completions;
+pub mod coroutine;
+pub mod propagate;
+pub mod ownership;
 
 use std::path::Path;
-use name::Name;
+use name::{Ident, Name};
 use std::collections::HashSet;
 use std::collections::HashMap;
 use std::sync::atomic::{AtomicBool, Ordering};
@@ -56,78 +123,543 @@ pub enum BuildSet {
 }
 
 pub fn build(buildset: BuildSet, variant: &str, stage: make::Stage, slow: bool) {
-    use rayon::prelude::*;
-    use std::sync::{Arc, Mutex};
-
     let (root, mut project) = project::load_cwd();
     //std::env::set_current_dir(root).unwrap();
+    let stage = stage.resolve(&project);
 
     std::fs::create_dir_all(root.join("target").join(stage.to_string()).join("c")).expect("create target dir");
     std::fs::create_dir_all(root.join("target").join(stage.to_string()).join("zz")).expect("create target dir");
     std::fs::create_dir_all(root.join("target").join(stage.to_string()).join("include")
                             .join("zz").join(&project.project.name)).expect("create target dir");
 
-    let project_name        = Name(vec![String::new(), project.project.name.clone()]);
-    let project_tests_name  = Name(vec![String::new(), project.project.name.clone(), "tests".to_string()]);
+    // the amalgamation and bindings are written from all modules, so none can come from the cache
+    let cached = if amalgamate::enabled() || emitter_py::enabled() || emitter_lua::enabled() {
+        None
+    } else {
+        cache::load_project(&mut project, variant, &stage)
+    };
+    let cfiles = match cached {
+        Some(cfiles) => cfiles,
+        None => emit_modules(&root, &mut project, variant, &stage, slow),
+    };
+
+    let mut buildfiles = buildfiles::BuildFiles::default();
+    for artifact in std::mem::replace(&mut project.artifacts, None).expect("no artifacts") {
+        match (&artifact.typ, &buildset) {
+            (project::ArtifactType::Test, BuildSet::Tests)  => (),
+            (project::ArtifactType::Test, _)                => continue,
+            (project::ArtifactType::Exe, _)                 => (),
+            (_, BuildSet::Run)                              => continue,
+            (_,_)                                           => (),
+        };
+        let mut make = make::Make::new(project.clone(), variant, stage.clone(), artifact.clone());
+
+        let mut main = Name::from(&artifact.main);
+        if !main.is_absolute() {
+            main.0.insert(0,"".into());
+        }
+        let main = cfiles.get(&main).expect(&format!(
+                "cannot build artifact '{}', main module '{}' does not exist", artifact.name, main));
+
+        let linked = selfprofile::pass("cc", || make_objects(&mut make, &cfiles, vec![main.name.clone()]));
 
+        match artifact.typ {
+            project::ArtifactType::Lib | project::ArtifactType::Staticlib | project::ArtifactType::Header => {
+                let header = public_header(&project, &stage, &artifact, &linked);
+                if project.project.cxx_headers() {
+                    selfprofile::pass("cxxheader", || cxxheader::validate(&make, &header));
+                }
+            }
+            _ => (),
+        }
 
+        let link = buildset != BuildSet::Check && artifact.typ != project::ArtifactType::Header;
+        buildfiles.add(&make, link && artifact.typ != project::ArtifactType::NodeModule);
+        if link && !buildfiles::ninja() {
+            selfprofile::pass("link", || make.link());
+        }
 
-    let mut modules = HashMap::new();
-    let features = project.features(variant).into_iter().map(|(n,(e,_))|(n,e)).collect();
-    if root.join("src").exists() {
-        loader::load(&mut modules, &project_name, &root.join("src"), &features, &stage);
+    };
+
+    if buildset == BuildSet::Tests {
+        link_unittests(&project, &cfiles, variant, &stage, &mut buildfiles);
     }
-    if root.join("tests").exists() {
-        loader::load(&mut modules, &project_tests_name, &root.join("tests").canonicalize().unwrap(), &features, &stage);
+
+    let dir = format!("./target/{}", stage);
+    buildfiles.compile_commands(&dir);
+    if buildfiles::ninja() {
+        let path = buildfiles.ninja(&dir);
+        info!("{}", msg!("buildfiles_ninja_written", path = path));
     }
 
+    if selfprofile::enabled() {
+        selfprofile::finish(&root.join("target").join(stage.to_string()));
+    }
 
+    let held = warnings::print();
+    if held > 0 && warnings::denied() {
+        error!("{}", msg!("warnings_denied", count = held));
+        std::process::exit(9);
+    }
+}
 
-    let mut searchpaths = HashSet::new();
-    searchpaths.insert(std::env::current_exe().expect("self path")
-        .canonicalize().expect("self path")
-        .parent().expect("self path")
-        .parent().expect("self path")
-        .parent().expect("self path")
-        .join("modules"));
-    searchpaths.insert(
-        std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("modules")
-    );
+/// add the modules in need, everything they depend on, and the c files in src/ to make.
+/// returns the modules, in the order they were added
+fn make_objects(make: &mut make::Make, cfiles: &HashMap<Name, emitter::CFile>, mut need: Vec<Name>) -> Vec<Name> {
+    let mut order = Vec::new();
+    let mut used = HashSet::new();
 
+    while need.len() > 0 {
+        for n in std::mem::replace(&mut need, Vec::new()) {
+            if !used.insert(n.clone()) {
+                continue
+            }
+            order.push(n.clone());
+            let n = cfiles.get(&n).expect(&format!("ICE: dependency {} module doesnt exist", n));
+            let mut deps : Vec<&Name> = n.deps.iter().collect();
+            deps.sort_unstable();
+            for d in deps {
+                need.push(d.clone());
+            }
+            make.build(n);
+        }
+    }
 
-    if let Some(deps) = &project.dependencies {
-        for (name, dep) in deps {
-            match dep {
-                toml::Value::String(_) => {
-                    getdep(name, &mut modules, &mut project.project, &mut searchpaths, &stage);
-                },
-                _ => (),
+    for entry in std::fs::read_dir("./src").unwrap() {
+        let entry = entry.unwrap();
+        let path  = entry.path();
+        if path.is_file() {
+            if let Some("c") = path.extension().map(|v|v.to_str().expect("invalid file name")) {
+                make.cobject(&path);
             }
         }
     }
+    order
+}
 
+/// link the test "name" { } declarations of the project's own modules into target/<stage>/bin/unittests
+fn link_unittests(
+    project:    &project::Config,
+    cfiles:     &HashMap<Name, emitter::CFile>,
+    variant:    &str,
+    stage:      &make::Stage,
+    buildfiles: &mut buildfiles::BuildFiles,
+) {
+    let mut names : Vec<&Name> = cfiles.iter().filter(|(name, cf)| {
+        !cf.tests.is_empty() && name.0.get(1).map(|n| n == &project.project.name).unwrap_or(false)
+    }).map(|(name, _)|name).collect();
+    if names.is_empty() {
+        return;
+    }
+    names.sort_unstable();
+    let mut tests : Vec<unittest::Test> = names.iter().flat_map(|n|cfiles[*n].tests.clone()).collect();
+    tests.sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+
+    let dir = format!("./target/{}/unittests", stage);
+    std::fs::create_dir_all(&dir).expect("create target dir");
+    let main = format!("{}/main.c", dir);
+    unittest::harness(&main, &tests);
+
+    let artifact = project::Artifact{
+        name:   "unittests".to_string(),
+        typ:    project::ArtifactType::Test,
+        ..Default::default()
+    };
+    let mut make = make::Make::new(project.clone(), variant, stage.clone(), artifact);
+    make.cflags.push("-Dmain=zz_unittests_replaced_main".to_string());
+    make_objects(&mut make, cfiles, names.into_iter().cloned().collect());
+    make.cobject(Path::new(&main));
+    buildfiles.add(&make, true);
+    if !buildfiles::ninja() {
+        make.link();
+    }
+}
+
+/// target/<stage>/include/<artifact>.h includes the export headers of every module in a library,
+/// so a c program only needs this one header next to the library
+fn public_header(project: &project::Config, stage: &make::Stage, artifact: &project::Artifact, modules: &[Name]) -> String {
+    let include = format!("target/{}/include/", stage);
+    let guard = format!("ZZ_PUBLIC_HEADER_{}", artifact.name.replace(|c: char| !c.is_ascii_alphanumeric(), "_"));
+
+    let mut s = format!("#ifndef {}\n#define {}\n\n", guard, guard);
+    for name in modules {
+        let (_, path) = emitter::outname(&project.project, stage, name, true);
+        s.push_str(&format!("#include \"{}\"\n", path.strip_prefix(include.as_str()).unwrap_or(&path)));
+    }
+    s.push_str("\n#endif\n");
+
+    let path = format!("{}{}.h", include, artifact.name);
+    std::fs::write(&path, s).expect(&format!("cannot write {}", path));
+    path
+}
+
+/// build for release and copy the libraries with their public headers into prefix/lib and prefix/include.
+/// every library also gets a pkg-config file in prefix/lib/pkgconfig
+pub fn install(prefix: &Path, variant: &str) {
+    build(BuildSet::All, variant, make::Stage::release(), false);
+
+    let (root, project) = project::load_cwd();
+    let stage = make::Stage::release().resolve(&project);
+    let target = root.join("target").join(stage.to_string());
+    let libdir = prefix.join("lib");
+    let includedir = prefix.join("include");
+    std::fs::create_dir_all(libdir.join("pkgconfig")).expect(&format!("cannot create {:?}", libdir));
+    std::fs::create_dir_all(&includedir).expect(&format!("cannot create {:?}", includedir));
+
+    let copy = |from: &Path, to: &Path| {
+        std::fs::copy(from, to).expect(&format!("cannot copy {:?} to {:?}", from, to));
+        println!("installed {}", to.display());
+    };
 
+    copy_dir(&target.join("include").join("zz"), &includedir.join("zz"));
 
+    for artifact in project.artifacts.as_ref().expect("no artifacts") {
+        let file = match artifact.typ {
+            project::ArtifactType::Lib          => make::shared_lib(&artifact.name, make::host()),
+            project::ArtifactType::Staticlib    => format!("lib{}.a", artifact.name),
+            project::ArtifactType::Header       => String::new(),
+            _ => continue,
+        };
+        let header = format!("{}.h", artifact.name);
+        copy(&target.join("include").join(&header), &includedir.join(&header));
+        if file.is_empty() {
+            continue;
+        }
+        copy(&target.join("lib").join(&file), &libdir.join(&file));
+
+        let mut libs : Vec<String> = vec![format!("-l{}", artifact.name)];
+        libs.extend(project.project.libs.iter().map(|l| format!("-l{}", l)));
+        let pc = format!("\
+prefix={}
+libdir=${{prefix}}/lib
+includedir=${{prefix}}/include
+
+Name: {}
+Description: {}
+Version: {}
+Requires: {}
+Cflags: -I${{includedir}}
+Libs: -L${{libdir}} {}
+",
+            prefix.display(),
+            artifact.name,
+            project.project.name,
+            project.project.version,
+            project.project.pkgconfig.join(" "),
+            libs.join(" "),
+        );
+        let path = libdir.join("pkgconfig").join(format!("{}.pc", artifact.name));
+        std::fs::write(&path, pc).expect(&format!("cannot write {:?}", path));
+        println!("installed {}", path.display());
+    }
+}
+
+fn copy_dir(from: &Path, to: &Path) {
+    std::fs::create_dir_all(to).expect(&format!("cannot create {:?}", to));
+    for entry in std::fs::read_dir(from).expect(&format!("cannot read {:?}", from)) {
+        let path = entry.expect(&format!("cannot read {:?}", from)).path();
+        let dest = to.join(path.file_name().unwrap());
+        if path.is_dir() {
+            copy_dir(&path, &dest);
+        } else {
+            std::fs::copy(&path, &dest).expect(&format!("cannot copy {:?} to {:?}", path, dest));
+        }
+    }
+}
+
+/// resolve all names in modules to absolute names
+fn abs_modules(modules: &mut HashMap<Name, loader::Module>, names: &[Name]) -> abs::Ext {
     let mut ext = abs::Ext::new();
-    let mut names : Vec<Name> = modules.keys().cloned().collect();
-    names.sort_unstable();
 
+    let silent = parser::ERRORS_AS_JSON.load(Ordering::SeqCst);
     let mut pb = pbr::ProgressBar::new(names.len() as u64);
     pb.show_speed = false;
 
-    for name in &names {
+    for name in names {
         let mut md = modules.remove(name).unwrap();
         match &mut md {
             loader::Module::C(_) => (),
             loader::Module::ZZ(ast) => {
-                abs::abs(ast, &modules, &mut ext);
+                abs::abs(ast, modules, &mut ext);
             }
         }
         modules.insert(name.clone(), md);
-        pb.message(&format!("abs {}", name));
-        pb.inc();
+        if !silent {
+            pb.message(&format!("abs {}", name));
+            pb.inc();
+        }
+    }
+    if !silent {
+        pb.finish_print("done abs");
+    }
+    ext
+}
+
+/// parse, check and emit all modules of the project
+/// system headers that neither emscripten nor wasi provide
+const WASM_UNAVAILABLE : &[&str] = &[
+    "<pthread.h>",
+    "<dlfcn.h>",
+    "<signal.h>",
+    "<sys/socket.h>",
+    "<sys/mman.h>",
+    "<sys/wait.h>",
+    "<sys/ioctl.h>",
+    "<termios.h>",
+    "<netinet/in.h>",
+    "<arpa/inet.h>",
+    "<execinfo.h>",
+];
+
+fn check_wasm_imports(modules: &HashMap<Name, loader::Module>, names: &[Name]) {
+    let mut bad = false;
+    for name in names {
+        if let Some(loader::Module::ZZ(ast)) = modules.get(name) {
+            for import in &ast.imports {
+                if import.name.0.len() > 2 && import.name.0[1] == "ext" && WASM_UNAVAILABLE.contains(&import.name.0[2].as_str()) {
+                    parser::emit_error(msg!("wasm_unavailable_header", name = import.name.0[2]), &[
+                        (import.loc.clone(), msg!("imported_here")),
+                        (import.loc.clone(), msg!("wasm_unavailable_header.note")),
+                    ]);
+                    bad = true;
+                }
+            }
+        }
+    }
+    if bad {
+        parser::abort();
+    }
+}
+
+fn emit_modules(
+    root:       &Path,
+    project:    &mut project::Config,
+    variant:    &str,
+    stage:      &make::Stage,
+    slow:       bool,
+) -> HashMap<Name, emitter::CFile> {
+    use rayon::prelude::*;
+    use std::sync::{Arc, Mutex};
+
+    let mut modules = selfprofile::pass("parse", || load_project(root, project, variant, stage));
+
+    let mut names : Vec<Name> = modules.keys().cloned().collect();
+    names.sort_unstable();
+
+    if stage.wasm {
+        check_wasm_imports(&modules, &names);
+    }
+
+    let ext = selfprofile::pass("abs", || abs_modules(&mut modules, &names));
+
+    // api.toml describes the default variant
+    if variant == "default" {
+        selfprofile::pass("api", || {
+            if let Some(recorded) = api::load(&root.join(api::FILE)) {
+                let surface = api::surface(&names, &modules, &project.project.name);
+                if !api::check(&names, &modules, &surface, &recorded) {
+                    parser::abort();
+                }
+            }
+        });
+    }
+
+    selfprofile::pass("stability", || {
+        if !stability::check(&modules, &names) {
+            parser::abort();
+        }
+    });
+
+    selfprofile::pass("interface", || {
+        if !interface::check(&modules, &names) {
+            parser::abort();
+        }
+    });
+
+    selfprofile::pass("weak", || {
+        if !weak::check(&modules) {
+            parser::abort();
+        }
+    });
+
+    selfprofile::pass("symbols", || {
+        if !symbols::resolve(&mut modules) {
+            parser::abort();
+        }
+    });
+
+    if project.project.cxx_headers() {
+        selfprofile::pass("cxxheader", || {
+            if !cxxheader::check(&modules, &names, &project.project.name) {
+                parser::abort();
+            }
+        });
+    }
+
+    selfprofile::pass("deprecated", || {
+        let deprecated = deprecated::Deprecated::collect(&modules);
+        for name in &names {
+            if name.0[1] != project.project.name {
+                continue;
+            }
+            if let Some(loader::Module::ZZ(ast)) = modules.get(name) {
+                deprecated.check(ast);
+            }
+        }
+    });
+
+    selfprofile::pass("i18n", || i18n::unused(&modules, &names, root, project));
+
+    let lints = match stage.profile(project) {
+        Some(target) => project.lints.stricter(&target.lints),
+        None => project.lints.clone(),
+    };
+    let lints = warnings::apply(lints);
+
+    // before macros are expanded, so imported macros are still used
+    selfprofile::pass("unused", || {
+        let mut ok = true;
+        for name in &names {
+            if name.0[1] != project.project.name {
+                continue;
+            }
+            if let Some(loader::Module::ZZ(ast)) = modules.get(name) {
+                ok &= unused::check(ast, lints.unused);
+            }
+        }
+        if !ok {
+            parser::abort();
+        }
+    });
+
+    selfprofile::pass("macros", || {
+        let macros = macros::Macros::collect(&modules);
+        for name in &names {
+            if let Some(loader::Module::ZZ(ast)) = modules.get_mut(name) {
+                macros::expand(ast, &macros);
+            }
+        }
+    });
+
+    selfprofile::pass("alias", || {
+        let aliases = alias::Aliases::collect(&modules);
+        for name in &names {
+            if let Some(loader::Module::ZZ(ast)) = modules.get_mut(name) {
+                alias::expand(ast, &aliases);
+            }
+        }
+    });
+
+    selfprofile::pass("defaults", || {
+        let defaults = defaults::Defaults::collect(&modules);
+        for name in &names {
+            if let Some(loader::Module::ZZ(ast)) = modules.get_mut(name) {
+                defaults::expand(ast, &defaults);
+            }
+        }
+    });
+
+    let mut headers = cheader::Headers::new(project, stage);
+    selfprofile::pass("closure", || {
+        for name in &names {
+            let mut md = modules.remove(name).unwrap();
+            if let loader::Module::ZZ(ast) = &mut md {
+                closure::expand(ast, &modules, &mut headers);
+            }
+            modules.insert(name.clone(), md);
+        }
+    });
+
+    selfprofile::pass("comptime", || {
+        for name in &names {
+            let mut md = modules.remove(name).unwrap();
+            if let loader::Module::ZZ(ast) = &mut md {
+                comptime::fold(ast, &modules);
+            }
+            modules.insert(name.clone(), md);
+        }
+
+        for name in &names {
+            if let Some(loader::Module::ZZ(ast)) = modules.get_mut(name) {
+                comptime::strip(ast);
+            }
+        }
+    });
+
+    selfprofile::pass("cheader", || {
+        for name in &names {
+            if let Some(loader::Module::ZZ(ast)) = modules.get(name) {
+                cheader::check(ast, &mut headers);
+            }
+        }
+    });
+
+    selfprofile::pass("lints", || {
+        let rules = lintpass::load(root);
+        let mut ok = true;
+        for name in &names {
+            if name.0[1] != project.project.name {
+                continue;
+            }
+            if let Some(loader::Module::ZZ(ast)) = modules.get(name) {
+                ok &= provenance::check(ast, &modules, lints.provenance);
+                ok &= vla::check(ast, lints.vla);
+                ok &= pitfalls::check(ast, lints.pitfalls);
+                ok &= struct_init::check(ast, &modules, lints.struct_init);
+                ok &= lintpass::check(ast, &modules, &rules);
+            }
+        }
+        if !ok {
+            parser::abort();
+        }
+    });
+
+    selfprofile::pass("nullable", || {
+        let mut ok = true;
+        for name in &names {
+            if name.0[1] != project.project.name {
+                continue;
+            }
+            if let Some(loader::Module::ZZ(ast)) = modules.get(name) {
+                ok &= nullable::check(ast, &modules);
+                ok &= units::check(ast, &modules);
+                ok &= flags::check(ast, &modules);
+                ok &= noreturn::check(ast, &modules, &mut headers);
+            }
+        }
+        if !ok {
+            parser::abort();
+        }
+    });
+
+    selfprofile::pass("ownership", || {
+        let mut ok = true;
+        for name in &names {
+            if name.0[1] != project.project.name {
+                continue;
+            }
+            if let Some(loader::Module::ZZ(ast)) = modules.get(name) {
+                ok &= ownership::check(ast, &modules);
+            }
+        }
+        if !ok {
+            parser::abort();
+        }
+    });
+
+    if !project.policy.is_empty() {
+        selfprofile::pass("policy", || {
+            if !analyze::check(&names, &modules, &project.project.name, root, &project.policy) {
+                parser::abort();
+            }
+        });
     }
-    pb.finish_print("done abs");
+
+    selfprofile::pass("layout", || {
+        if !layout::check_all(&names, &modules, &project.project.name) {
+            parser::abort();
+        }
+    });
 
     let pb = Arc::new(Mutex::new(pbr::ProgressBar::new(names.len() as u64)));
     pb.lock().unwrap().show_speed = false;
@@ -161,34 +693,25 @@ pub fn build(buildset: BuildSet, variant: &str, stage: make::Stage, slow: bool)
 
     let silent = parser::ERRORS_AS_JSON.load(Ordering::SeqCst);
     let working_on_these = Arc::new(Mutex::new(HashSet::new()));
+    let amalgamated = Mutex::new(Vec::new());
+    let lib_name = project.artifacts.iter().flatten()
+        .find(|a| a.typ == project::ArtifactType::Lib)
+        .map(|a| a.name.clone())
+        .unwrap_or_else(|| project.project.name.clone());
 
-    let iterf =  |name: Name| {
+    let iterf =  |name: Name| -> Result<Option<(Name, emitter::CFile)>, Error> {
         let (_, outname) = emitter::outname(&project.project, &stage, &name, false);
 
         let cachename = format!("{}.buildcache", outname);
-        let cached : Option<emitter::CFile> = match std::fs::read_to_string(&cachename) {
-            Ok(f) => {
-                match serde_json::from_str(&f) {
-                    Ok(cf) => Some(cf),
-                    Err(_) => {
-                        std::fs::remove_file(&cachename).expect(&format!("cannot remove {}", cachename));
-                        None
-                    }
-                }
-
-            },
-            Err(_) => None,
-        };
+        let key = cache::key(variant, stage);
+        let all_modules = &modules;
 
-        //only emit if any source file is newer than the cache or output
-        if let Some(cached) = cached {
-            if !cached.is_newer_than(&outname) && !cached.is_newer_than(&cachename) {
-                if !silent {
-                    //pb.lock().unwrap().message(&format!("cached {} ", module.name));
-                    pb.lock().unwrap().inc();
-                }
-                return Ok(Some((cached.name.clone(), cached)))
+        //only emit if any input changed since the last build
+        if let Some(cached) = cache::load_entry(&cachename, &key).filter(|_| !amalgamate::enabled() && !emitter_py::enabled() && !emitter_lua::enabled()) {
+            if !silent {
+                pb.lock().unwrap().inc();
             }
+            return Ok(Some((cached.name.clone(), cached)))
         }
 
 
@@ -202,11 +725,15 @@ pub fn build(buildset: BuildSet, variant: &str, stage: make::Stage, slow: bool)
                     filepath:   c.to_string_lossy().into(),
                     sources:    HashSet::new(),
                     deps:       HashSet::new(),
+                    exports:    Vec::new(),
+                    tests:      Vec::new(),
                 };
+                cache::store_entry(&cachename, &key, &cf.name, all_modules, &cf);
                 return Ok(Some((cf.name.clone(), cf)));
             }
             loader::Module::ZZ(ast) => {
-                flatten::flatten(ast, &modules, &ext)
+                crash::working_on(&ast.source);
+                selfprofile::pass("flatten", || flatten::flatten(ast, &modules, &ext))
             }
         };
 
@@ -229,26 +756,40 @@ pub fn build(buildset: BuildSet, variant: &str, stage: make::Stage, slow: bool)
             pb.lock().unwrap().tick();
         }
 
-        expand::expand(&mut module)?;
-        if !symbolic::execute(&mut module) {
+        selfprofile::pass("expand", || expand::expand(&mut module))?;
+        if !selfprofile::pass("symbolic", || symbolic::execute(&mut module)) {
             ABORT.store(true, Ordering::Relaxed);
             return Ok(None);
         }
+        selfprofile::pass("fold", || fold::fold(&mut module));
+
+        let cf = selfprofile::pass("emit", || {
+            let header  = emitter::Emitter::new(&project.project, stage.clone(), module.clone(), true);
+            header.emit();
 
-        let header  = emitter::Emitter::new(&project.project, stage.clone(), module.clone(), true);
-        header.emit();
+            let rsbridge = emitter_rs::Emitter::new(&project.project, stage.clone(), module.clone());
+            rsbridge.emit();
 
-        let rsbridge = emitter_rs::Emitter::new(&project.project, stage.clone(), module.clone());
-        rsbridge.emit();
+            let jsbridge = emitter_js::Emitter::new(&project.project, stage.clone(), module.clone());
+            jsbridge.emit();
 
-        let jsbridge = emitter_js::Emitter::new(&project.project, stage.clone(), module.clone());
-        jsbridge.emit();
+            if emitter_py::enabled() {
+                let pybridge = emitter_py::Emitter::new(&project.project, &lib_name, stage.clone(), module.clone());
+                pybridge.emit();
+            }
 
-        let docs = emitter_docs::Emitter::new(&project.project, stage.clone(), module.clone());
-        docs.emit();
+            if emitter_lua::enabled() {
+                let luabridge = emitter_lua::Emitter::new(&project.project, &lib_name, stage.clone(), module.clone());
+                luabridge.emit();
+            }
 
-        let em = emitter::Emitter::new(&project.project, stage.clone(), module, false);
-        let cf = em.emit();
+            if amalgamate::enabled() {
+                amalgamated.lock().unwrap().push(module.clone());
+            }
+
+            let em = emitter::Emitter::new(&project.project, stage.clone(), module, false);
+            em.emit()
+        });
 
 
         if !silent {
@@ -269,28 +810,37 @@ pub fn build(buildset: BuildSet, variant: &str, stage: make::Stage, slow: bool)
             pb.lock().unwrap().inc();
         }
 
-        let cachefile = std::fs::File::create(&cachename).expect(&format!("cannot create {}", cachename));
-        serde_json::ser::to_writer(cachefile, &cf).expect(&format!("cannot write {}", cachename));
+        selfprofile::pass("postprocess", || {
+            postprocess::run(&project.postprocess, &project.project, root, variant, stage, &cf)
+        })?;
+
+        cache::store_entry(&cachename, &key, &name, all_modules, &cf);
 
         Ok(Some((cf.name.clone(), cf)))
     };
-    let cfiles_r : Vec<Result<Option<(Name, emitter::CFile)>, Error>> = if slow {
+    // modules are worked on in parallel, but their diagnostics are reported in order of their names
+    let iterf = |name: Name| parser::capture(|| iterf(name));
+    let cfiles_r : Vec<_> = if slow {
         names.into_iter().map(iterf).collect()
     } else {
         names.into_par_iter().map(iterf).collect()
     };
 
     let mut cfiles = HashMap::new();
-    for r in cfiles_r {
+    for (r, diagnostics) in cfiles_r {
+        parser::replay(diagnostics);
         match r {
-            Ok(None) => {},
-            Ok(Some(v)) => {
+            Some(Ok(None)) => {},
+            Some(Ok(Some(v))) => {
                 cfiles.insert(v.0, v.1);
             }
-            Err(e) => {
+            Some(Err(e)) => {
                 parser::emit_error(e.message.clone(), &e.details);
                 ABORT.store(true, Ordering::Relaxed);
             }
+            None => {
+                ABORT.store(true, Ordering::Relaxed);
+            }
         }
     };
 
@@ -304,95 +854,298 @@ pub fn build(buildset: BuildSet, variant: &str, stage: make::Stage, slow: bool)
         pb.lock().unwrap().finish_print("done emitting");
     }
 
-    for artifact in std::mem::replace(&mut project.artifacts, None).expect("no artifacts") {
-        match (&artifact.typ, &buildset) {
-            (project::ArtifactType::Test, BuildSet::Tests)  => (),
-            (project::ArtifactType::Test, _)                => continue,
-            (project::ArtifactType::Exe, _)                 => (),
-            (_, BuildSet::Run)                              => continue,
-            (_,_)                                           => (),
-        };
-        let mut make = make::Make::new(project.clone(), variant, stage.clone(), artifact.clone());
+    if amalgamate::enabled() {
+        let modules = amalgamated.into_inner().unwrap();
+        selfprofile::pass("amalgamate", || amalgamate::write(&project.project, stage, modules));
+    }
 
-        let mut main = Name::from(&artifact.main);
-        if !main.is_absolute() {
-            main.0.insert(0,String::new());
+    cache::store_project(root, project, variant, stage, &modules);
+    cfiles
+}
+
+/// render the documentation of all modules to target/doc/
+pub fn doc(format: emitter_docs::Format) {
+    let (root, mut project) = project::load_cwd();
+    let stage = make::Stage::release();
+
+    let mut modules = load_project(&root, &mut project, "default", &stage);
+    let mut names : Vec<Name> = modules.keys().cloned().collect();
+    names.sort_unstable();
+    abs_modules(&mut modules, &names);
+
+    let index = emitter_docs::emit(&project.project, format, &modules);
+    println!("{}", index);
+}
+
+/// replace uses of deprecated declarations in the project with what they name as replacement
+pub fn fix() {
+    let (root, mut project) = project::load_cwd();
+    let stage = make::Stage::test();
+
+    let mut modules = load_project(&root, &mut project, "default", &stage);
+    let mut names : Vec<Name> = modules.keys().cloned().collect();
+    names.sort_unstable();
+    abs_modules(&mut modules, &names);
+
+    let deprecated = deprecated::Deprecated::collect(&modules);
+    let mut edits = Vec::new();
+    for name in &names {
+        if name.0[1] != project.project.name {
+            continue;
         }
-        let main = cfiles.get(&main).expect(&format!(
-                "cannot build artifact '{}', main module '{}' does not exist", artifact.name, main));
+        if let Some(loader::Module::ZZ(ast)) = modules.get(name) {
+            edits.extend(deprecated::fixes(&deprecated, ast));
+        }
+    }
+    match deprecated::apply(edits) {
+        Ok(0) => (),
+        Ok(n) => info!("replaced {} uses of deprecated names", n),
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(9);
+        }
+    }
+}
 
-        let mut need = Vec::new();
-        need.push(main.name.clone());
-        let mut used = HashSet::new();
+/// zz api. record the api of the project in api.toml, or compare it
+pub fn api(record: bool) {
+    let (root, mut project) = project::load_cwd();
+    let stage = make::Stage::test();
 
-        while need.len() > 0 {
-            for n in std::mem::replace(&mut need, Vec::new()) {
-                if !used.insert(n.clone()) {
-                    continue
-                }
-                let n = cfiles.get(&n).expect(&format!("ICE: dependency {} module doesnt exist", n));
-                for d in &n.deps {
-                    need.push(d.clone());
-                }
-                make.build(n);
-            }
+    let mut modules = load_project(&root, &mut project, "default", &stage);
+    let mut names : Vec<Name> = modules.keys().cloned().collect();
+    names.sort_unstable();
+    abs_modules(&mut modules, &names);
+
+    let path = root.join(api::FILE);
+    let surface = api::surface(&names, &modules, &project.project.name);
+    if record {
+        api::record(&path, &surface);
+        info!("recorded {} declarations in {}", surface.values().map(|m| m.len()).sum::<usize>(), api::FILE);
+        return;
+    }
+    let recorded = match api::load(&path) {
+        Some(v) => v,
+        None => {
+            error!("no {}, record it with zz api --record", api::FILE);
+            std::process::exit(9);
         }
+    };
+    if !api::check(&names, &modules, &surface, &recorded) {
+        parser::abort();
+    }
+}
+
+/// zz bindgen. write bindings of the export api of the project for another language
+pub fn bindgen(lang: &str, safe: bool, output: Option<&Path>) {
+    let (root, mut project) = project::load_cwd();
+    let stage = make::Stage::release();
+
+    let mut modules = load_project(&root, &mut project, "default", &stage);
+    let mut names : Vec<Name> = modules.keys().cloned().collect();
+    names.sort_unstable();
+    abs_modules(&mut modules, &names);
+    if !symbols::resolve(&mut modules) {
+        parser::abort();
+    }
+
+    let (s, ext) = match lang {
+        "rust"  => (bindgen::Rust::new(&modules, &project.project.name, safe).emit(&names), "rs"),
+        _       => unreachable!(),
+    };
+    let path = match output {
+        Some(path) => path.to_path_buf(),
+        None => root.join("target").join("bindgen").join(format!("{}.{}", project.project.name, ext)),
+    };
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).expect(&format!("cannot create {}", dir.display()));
+    }
+    std::fs::write(&path, s).expect(&format!("cannot write {}", path.display()));
+    info!("wrote {} bindings to {}", lang, path.display());
+}
+
+/// zz analyze --features-used. list the language features each module uses, and check the policies
+pub fn analyze() {
+    let (root, mut project) = project::load_cwd();
+    let stage = make::Stage::test();
+
+    let mut modules = load_project(&root, &mut project, "default", &stage);
+    let mut names : Vec<Name> = modules.keys().cloned().collect();
+    names.sort_unstable();
+    abs_modules(&mut modules, &names);
+
+    analyze::print(&names, &modules, &project.project.name);
+    weak::print(&names, &modules);
+    if !analyze::check(&names, &modules, &project.project.name, &root, &project.policy) {
+        parser::abort();
+    }
+}
 
-        if let project::ArtifactType::Lib = artifact.typ {
+/// zz eval. interpret expr in a module of the project, without a c compiler, and print its value
+pub fn eval(expr: &str, module: &str) {
+    let (root, mut project) = project::load_cwd();
+    let stage = make::Stage::test();
+
+    let mut modules = load_project(&root, &mut project, "default", &stage);
+    let mut names : Vec<Name> = modules.keys().cloned().collect();
+    names.sort_unstable();
+    abs_modules(&mut modules, &names);
+
+    let mut name = Name(vec!["".into(), project.project.name.as_str().into()]);
+    name.0.extend(module.split("::").map(Ident::from));
+    let md = match modules.get(&name) {
+        Some(loader::Module::ZZ(md)) => md,
+        _ => {
+            error!("{}", msg!("eval_module", name = name));
+            std::process::exit(9);
         }
+    };
 
-        for entry in std::fs::read_dir("./src").unwrap() {
-            let entry = entry.unwrap();
-            let path  = entry.path();
-            if path.is_file() {
-                if let Some("c") = path.extension().map(|v|v.to_str().expect("invalid file name")) {
-                    make.cobject(&path);
-                }
-            }
+    let dir = root.join("target").join(stage.to_string()).join("eval");
+    std::fs::create_dir_all(&dir).expect("create target dir");
+    let path = dir.join("eval.zz");
+    std::fs::write(&path, expr).expect(&format!("cannot write {}", path.display()));
+    let mut expr = parser::parse_expression(&path);
+    comptime::resolve(&mut expr, md);
+
+    match comptime::Interpreter::any(md, &modules).eval(&expr) {
+        Ok(v) => println!("{}", v),
+        Err(e) => {
+            parser::emit_error(msg!("eval_failed"), &[(e.loc.clone(), e.message.clone())]);
+            parser::abort();
         }
+    }
+}
 
-        if buildset != BuildSet::Check {
-            make.link();
+/// resolve git dependencies again, ignoring zz.lock
+pub fn update() {
+    let (root, mut project) = project::load_cwd();
+    let mut lock = deps::Lock::load(&root);
+    lock.clear();
+    lock.store(&root);
+    load_project(&root, &mut project, "default", &make::Stage::release());
+    for locked in deps::Lock::load(&root).packages {
+        println!("{} {} {}", locked.name, locked.git, locked.commit);
+    }
+}
+
+/// load all modules of a project and its dependencies
+pub fn load_project(
+    root:       &Path,
+    project:    &mut project::Config,
+    variant:    &str,
+    stage:      &make::Stage,
+) -> HashMap<Name, loader::Module> {
+
+    let project_name        = Name(vec!["".into(), project.project.name.as_str().into()]);
+    let project_tests_name  = Name(vec!["".into(), project.project.name.as_str().into(), "tests".into()]);
+
+    let mut modules = HashMap::new();
+    let features = project.features(variant).into_iter().map(|(n,(e,_))|(n,e)).collect();
+    if root.join("src").exists() {
+        loader::load(&mut modules, &project_name, &root.join("src"), &features, &stage, project);
+    }
+    if root.join("tests").exists() {
+        loader::load(&mut modules, &project_tests_name, &root.join("tests").canonicalize().unwrap(), &features, &stage, project);
+    }
+    resources::load(&mut modules, &project_name, root, project, &features, stage);
+    i18n::load(&mut modules, &project_name, root, project, &features, stage);
+
+
+
+    let mut searchpaths = searchpaths();
+    let mut lock = deps::Lock::load(root);
+    if let Some(deps) = &project.dependencies {
+        for (name, dep) in deps {
+            getdep(name, dep, root, &mut modules, &mut project.project, &mut searchpaths, &stage, &mut lock);
         }
+    }
+    lock.store(root);
 
-    };
+    if !interface::select(&mut modules, project, stage) {
+        parser::abort();
+    }
+
+    modules
 }
 
-fn getdep(
+/// directories that dependencies given by version are searched in
+fn searchpaths() -> HashSet<std::path::PathBuf> {
+    let mut searchpaths = HashSet::new();
+    searchpaths.insert(std::env::current_exe().expect("self path")
+        .canonicalize().expect("self path")
+        .parent().expect("self path")
+        .parent().expect("self path")
+        .parent().expect("self path")
+        .join("modules"));
+    searchpaths.insert(
+        std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("modules")
+    );
+    searchpaths
+}
+
+/// the directory of dependency name, as declared in the project at from
+fn locate(
         name: &str,
-        modules: &mut HashMap<Name, loader::Module>,
-        rootproj: &mut project::Project,
+        dep:  &toml::Value,
+        from: &Path,
         searchpaths: &mut HashSet<std::path::PathBuf>,
-        stage:  &make::Stage,
-) {
+        lock:   &mut deps::Lock,
+) -> std::path::PathBuf {
 
     searchpaths.insert(
         std::env::current_dir().unwrap().join("modules")
     );
 
-    let mut found = None;
-    for searchpath in searchpaths.iter() {
-        let modpath = searchpath.join(name).join("zz.toml");
-        if modpath.exists() {
-            found = Some(searchpath.join(name));
+    match deps::Source::parse(name, dep) {
+        deps::Source::Search => {
+            let mut found = None;
+            for searchpath in searchpaths.iter() {
+                let modpath = searchpath.join(name).join("zz.toml");
+                if modpath.exists() {
+                    found = Some(searchpath.join(name));
+                }
+            }
+            match found {
+                Some(v) => v,
+                None => {
+                    eprintln!("dependency \"{}\" not found in any of {:#?}", name, searchpaths);
+                    std::process::exit(9);
+                }
+            }
         }
+        deps::Source::Path(path) => {
+            let path = from.join(path);
+            if !path.join("zz.toml").exists() {
+                eprintln!("dependency \"{}\" not found at {:?}", name, path);
+                std::process::exit(9);
+            }
+            path.canonicalize().expect(&format!("{}: cannot resolve path {:?}", name, path))
+        }
+        deps::Source::Git{url, rev} => deps::fetch(name, &url, &rev, lock),
     }
+}
 
-    let found = match found {
-        Some(v) => v,
-        None => {
-            eprintln!("dependency \"{}\" not found in any of {:#?}", name, searchpaths);
-            std::process::exit(9);
-        }
-    };
+fn getdep(
+        name: &str,
+        dep:  &toml::Value,
+        from: &Path,
+        modules: &mut HashMap<Name, loader::Module>,
+        rootproj: &mut project::Project,
+        searchpaths: &mut HashSet<std::path::PathBuf>,
+        stage:  &make::Stage,
+        lock:   &mut deps::Lock,
+) {
+
+    let found = locate(name, dep, from, searchpaths, lock);
 
     //let pp = std::env::current_dir().unwrap();
     //std::env::set_current_dir(&found).unwrap();
     let (root, project)  = project::load(&found);
-    let project_name     = Name(vec![String::new(), project.project.name.clone()]);        
+    let project_name     = Name(vec!["".into(), project.project.name.as_str().into()]);        
     if found.join("src").exists() {
         let features = project.features("default").into_iter().map(|(n,(e,_))|(n,e)).collect();
-        loader::load(modules, &project_name, &found.join("src"), &features, &stage);
+        loader::load(modules, &project_name, &found.join("src"), &features, &stage, &project);
     }
     //std::env::set_current_dir(pp).unwrap();
 
@@ -412,18 +1165,14 @@ fn getdep(
         rootproj.cobjects.push(i.to_string_lossy().into());
     }
     rootproj.pkgconfig.extend(project.project.pkgconfig);
+    rootproj.libs.extend(project.project.libs);
     rootproj.cflags.extend(project.project.cflags);
     rootproj.lflags.extend(project.project.lflags);
 
 
     if let Some(deps) = &project.dependencies {
         for (name, dep) in deps {
-            match dep {
-                toml::Value::String(_) => {
-                    getdep(name, modules, rootproj, searchpaths, stage);
-                },
-                _ => (),
-            }
+            getdep(name, dep, &root, modules, rootproj, searchpaths, stage, lock);
         }
     }
 }
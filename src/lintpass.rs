@@ -0,0 +1,329 @@
+/// lints of a project or team, next to the built-in ones
+
+use super::ast;
+use super::loader;
+use super::name::Name;
+use super::parser::{emit_error, emit_warn};
+use super::project::LintLevel;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::Mutex;
+
+pub type Modules = HashMap<Name, loader::Module>;
+
+pub trait LintPass: Send + Sync {
+    /// the name for -W, -D and -A, which also shows in its diagnostics
+    fn name(&self) -> &str;
+
+    /// the level unless the command line sets another
+    fn level(&self) -> LintLevel {
+        LintLevel::Warn
+    }
+
+    /// look at one module of the project, with all modules it can see
+    fn check(&self, md: &ast::Module, modules: &Modules, report: &mut Report);
+}
+
+struct Finding {
+    loc:        ast::Location,
+    message:    String,
+    note:       String,
+}
+
+/// what a pass found in a module
+#[derive(Default)]
+pub struct Report {
+    findings:   Vec<Finding>,
+}
+
+impl Report {
+    pub fn add(&mut self, loc: &ast::Location, message: String, note: String) {
+        self.findings.push(Finding{loc: loc.clone(), message, note});
+    }
+}
+
+static PASSES: Mutex<Vec<Box<dyn LintPass>>> = Mutex::new(Vec::new());
+
+/// add a compiled-in pass, before the build starts
+pub fn register(pass: Box<dyn LintPass>) {
+    PASSES.lock().unwrap().push(pass);
+}
+
+/// whether name is a registered pass or a lint file of the project in the current directory
+pub fn known(name: &str) -> bool {
+    PASSES.lock().unwrap().iter().any(|p| p.name() == name)
+        || super::project::find_root_cwd().map(|root| root.join(DIR).join(format!("{}.toml", name)).exists()).unwrap_or(false)
+}
+
+/// the directory of lint files, relative to zz.toml
+pub const DIR : &str = "lints";
+
+/// a lint file in lints/
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Rule {
+    #[serde(skip)]
+    name:       String,
+    #[serde(default = "warn")]
+    level:      LintLevel,
+    #[serde(default)]
+    note:       String,
+    /// functions that must not be called, by their last names, like malloc or mem::copy
+    #[serde(default)]
+    banned:     Vec<String>,
+    /// the style of names of declarations, by kind: fn, struct, enum, const, static, type, macro
+    #[serde(default)]
+    naming:     BTreeMap<String, String>,
+}
+
+fn warn() -> LintLevel {
+    LintLevel::Warn
+}
+
+const STYLES : &[&str] = &["snake_case", "CamelCase", "SCREAMING_CASE"];
+
+fn styled(name: &str, style: &str) -> bool {
+    let name = name.trim_start_matches('_');
+    let lower = name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    let upper = name.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_');
+    match style {
+        "snake_case"        => lower,
+        "SCREAMING_CASE"    => upper,
+        "CamelCase"         => name.chars().next().map(|c| c.is_ascii_uppercase()).unwrap_or(true) && !name.contains('_'),
+        _                   => true,
+    }
+}
+
+fn kind(def: &ast::Def) -> Option<&'static str> {
+    Some(match def {
+        ast::Def::Function{..}  => "fn",
+        ast::Def::Struct{..}    => "struct",
+        ast::Def::Enum{..}      => "enum",
+        ast::Def::Const{..}     => "const",
+        ast::Def::Static{..}    => "static",
+        ast::Def::TypeAlias{..} => "type",
+        ast::Def::Macro{..}     => "macro",
+        _ => return None,
+    })
+}
+
+/// whether name is one of banned, which may leave out leading parts of it
+fn banned<'a>(name: &Name, banned: &'a [String]) -> Option<&'a String> {
+    banned.iter().find(|b| {
+        let b : Vec<&str> = b.split("::").collect();
+        name.0.len() >= b.len() && name.0[name.0.len() - b.len()..].iter().zip(b).all(|(a, b)| a == b)
+    })
+}
+
+impl LintPass for Rule {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn level(&self) -> LintLevel {
+        self.level
+    }
+
+    fn check(&self, md: &ast::Module, _modules: &Modules, report: &mut Report) {
+        for local in &md.locals {
+            let kind = match kind(&local.def) {
+                Some(v) => v,
+                None => continue,
+            };
+            let style = match self.naming.get(kind) {
+                Some(v) => v,
+                None => continue,
+            };
+            let name = local.name.rsplit("::").next().unwrap_or(&local.name);
+            if !styled(name, style) {
+                report.add(&local.loc, msg!("lintpass_naming", lint = self.name, kind = kind, name = name, style = style),
+                    self.note.clone());
+            }
+        }
+        if self.banned.is_empty() {
+            return;
+        }
+        expressions(md, &mut |e| {
+            if let ast::Expression::Call{name, loc, ..} = e {
+                if let ast::Expression::Name(ast::Typed{t: ast::Type::Other(n), ..}) = name.as_ref() {
+                    if let Some(b) = banned(n, &self.banned) {
+                        report.add(loc, msg!("lintpass_banned", lint = self.name, name = b), self.note.clone());
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// the lint files of the project at root
+pub fn load(root: &Path) -> Vec<Box<dyn LintPass>> {
+    let mut paths : Vec<_> = std::fs::read_dir(root.join(DIR)).into_iter().flatten().flatten()
+        .map(|e| e.path())
+        .filter(|p| p.extension().map(|e| e == "toml").unwrap_or(false))
+        .collect();
+    paths.sort();
+    let mut r : Vec<Box<dyn LintPass>> = Vec::new();
+    for path in paths {
+        let s = std::fs::read_to_string(&path).expect(&format!("cannot read {}", path.display()));
+        let mut rule : Rule = match toml::from_str(&s) {
+            Ok(v) => v,
+            Err(e) => {
+                error!("{}", msg!("lintpass_invalid", path = path.display(), error = e));
+                std::process::exit(9);
+            }
+        };
+        if let Some(style) = rule.naming.values().find(|s| !STYLES.contains(&s.as_str())) {
+            error!("{}", msg!("lintpass_invalid", path = path.display(),
+                error = msg!("lintpass_unknown_style", style = style, styles = STYLES.join(", "))));
+            std::process::exit(9);
+        }
+        rule.name = path.file_stem().unwrap().to_string_lossy().to_string();
+        r.push(Box::new(rule));
+    }
+    r
+}
+
+fn emit(pass: &dyn LintPass, md: &ast::Module, modules: &Modules) -> bool {
+    let level = super::warnings::level(pass.name(), pass.level());
+    if level == LintLevel::Allow {
+        return true;
+    }
+    let mut report = Report::default();
+    pass.check(md, modules, &mut report);
+    for finding in &report.findings {
+        let labels = [(finding.loc.clone(), &finding.note)];
+        let labels : &[(ast::Location, &String)] = if finding.note.is_empty() { &[] } else { &labels };
+        if level == LintLevel::Deny {
+            emit_error(&finding.message, labels);
+        } else {
+            emit_warn(&finding.message, labels);
+        }
+    }
+    level != LintLevel::Deny || report.findings.is_empty()
+}
+
+/// run the lint files from load() and the registered passes on md.
+/// returns false if any that is at deny found something
+pub fn check(md: &ast::Module, modules: &Modules, rules: &[Box<dyn LintPass>]) -> bool {
+    let mut ok = true;
+    for rule in rules {
+        ok &= emit(rule.as_ref(), md, modules);
+    }
+    for pass in PASSES.lock().unwrap().iter() {
+        ok &= emit(pass.as_ref(), md, modules);
+    }
+    ok
+}
+
+/// call f on every expression in the function bodies of md, outer ones first
+pub fn expressions<'a>(md: &'a ast::Module, f: &mut dyn FnMut(&'a ast::Expression)) {
+    for local in &md.locals {
+        match &local.def {
+            ast::Def::Function{body, ..} | ast::Def::Test{body, ..} => block(body, f),
+            _ => (),
+        }
+    }
+}
+
+fn block<'a>(block: &'a ast::Block, f: &mut dyn FnMut(&'a ast::Expression)) {
+    for stm in &block.statements {
+        statement(stm, f);
+    }
+}
+
+fn statement<'a>(stm: &'a ast::Statement, f: &mut dyn FnMut(&'a ast::Expression)) {
+    match stm {
+        ast::Statement::Mark{lhs, ..} => expr(lhs, f),
+        ast::Statement::Label{..}
+        | ast::Statement::Break{..}
+        | ast::Statement::Continue{..}
+        | ast::Statement::CBlock{..} => {}
+        ast::Statement::Block(b) | ast::Statement::Unsafe(b) => block(b, f),
+        ast::Statement::For{e1, e2, e3, body} => {
+            for s in e1.iter().chain(e3.iter()) {
+                statement(s, f);
+            }
+            if let Some(e2) = e2 {
+                expr(e2, f);
+            }
+            block(body, f);
+        }
+        ast::Statement::While{expr: e, body} => {
+            expr(e, f);
+            block(body, f);
+        }
+        ast::Statement::If{branches} => {
+            for (_, e, body) in branches {
+                if let Some(e) = e {
+                    expr(e, f);
+                }
+                block(body, f);
+            }
+        }
+        ast::Statement::Assign{lhs, rhs, ..} => {
+            expr(lhs, f);
+            expr(rhs, f);
+        }
+        ast::Statement::Var{array, assign, ..} => {
+            if let Some(Some(size)) = array {
+                expr(size, f);
+            }
+            if let Some(assign) = assign {
+                expr(assign, f);
+            }
+        }
+        ast::Statement::Expr{expr: e, ..} => expr(e, f),
+        ast::Statement::Return{expr: e, ..} => {
+            if let Some(e) = e {
+                expr(e, f);
+            }
+        }
+        ast::Statement::Switch{expr: e, cases, default, ..} => {
+            expr(e, f);
+            for (conds, body) in cases {
+                for cond in conds {
+                    expr(cond, f);
+                }
+                block(body, f);
+            }
+            if let Some(default) = default {
+                block(default, f);
+            }
+        }
+    }
+}
+
+fn expr<'a>(e: &'a ast::Expression, f: &mut dyn FnMut(&'a ast::Expression)) {
+    f(e);
+    match e {
+        ast::Expression::Name(_)
+        | ast::Expression::LiteralString{..}
+        | ast::Expression::LiteralChar{..}
+        | ast::Expression::Literal{..} => {}
+        ast::Expression::MemberAccess{lhs, ..} => expr(lhs, f),
+        ast::Expression::ArrayAccess{lhs, rhs, ..} | ast::Expression::Infix{lhs, rhs, ..} => {
+            expr(lhs, f);
+            expr(rhs, f);
+        }
+        ast::Expression::Call{name, args, ..} => {
+            expr(name, f);
+            for arg in args {
+                expr(arg, f);
+            }
+        }
+        ast::Expression::Cast{expr: inner, ..}
+        | ast::Expression::UnaryPost{expr: inner, ..}
+        | ast::Expression::UnaryPre{expr: inner, ..} => expr(inner, f),
+        ast::Expression::StructInit{fields, ..} => {
+            for (_, e) in fields {
+                expr(e, f);
+            }
+        }
+        ast::Expression::ArrayInit{fields, ..} => {
+            for e in fields {
+                expr(e, f);
+            }
+        }
+    }
+}
@@ -9,6 +9,14 @@ use rayon::prelude::*;
 use std::sync::{Arc, Mutex};
 use std::sync::atomic::{Ordering};
 use super::make::Stage;
+use super::unittest;
+use super::derive;
+use super::statemachine;
+use super::wire;
+use super::coroutine;
+use super::propagate;
+use super::closure;
+use super::project::Config;
 
 #[derive(Clone)]
 pub enum Module {
@@ -22,6 +30,7 @@ pub fn load(
     src:            &Path,
     features:       &HashMap<String, bool>,
     stage:          &Stage,
+    project:        &Config,
 ) {
 
 
@@ -55,23 +64,51 @@ pub fn load(
     if !silent{
         pb.lock().unwrap().finish_print(&format!("parsing {}", artifact_name));
     }
-    let om : HashMap<Name, Module> = files.into_par_iter().map(|path| {
-        if !silent{
-            pb.lock().unwrap().message(&format!("parsing {:?} ", path));
-        }
-        let mut m = parser::parse(&path, features, stage);
-        m.name = artifact_name.clone();
-        let stem = path.file_stem().unwrap().to_string_lossy().to_string();
-        if stem != "lib" {
-            m.name.push(stem);
-        }
-        debug!("loaded {:?} as {}", path, m.name);
-        if !silent{
-            pb.lock().unwrap().inc();
-        }
-        (m.name.clone(), Module::ZZ(m))
+    // parse in parallel, but report diagnostics in file order
+    files.sort();
+    let parsed : Vec<_> = files.into_par_iter().map(|path| {
+        parser::capture(||{
+            if !silent{
+                pb.lock().unwrap().message(&format!("parsing {:?} ", path));
+            }
+            let mut m = parser::parse(&path, features, stage, project.project.edition);
+            m.allow_unstable = project.unstable.clone();
+            m.symbol_prefix = project.project.symbol_prefix.clone();
+            statemachine::lower(&mut m, src.parent().unwrap_or(src), features, stage, project.project.edition);
+            wire::lower(&mut m, src.parent().unwrap_or(src), features, stage, project.project.edition);
+            derive::expand(&mut m, &project.derive, src.parent().unwrap_or(src), features, stage, project.project.edition);
+            closure::lower(&mut m);
+            propagate::lower(&mut m);
+            coroutine::lower(&mut m);
+            unittest::lower(&mut m, stage);
+            m.name = artifact_name.clone();
+            let stem = path.file_stem().unwrap().to_string_lossy().to_string();
+            if stem != "lib" {
+                m.name.push(stem);
+            }
+            debug!("loaded {:?} as {}", path, m.name);
+            if !silent{
+                pb.lock().unwrap().inc();
+            }
+            (m.name.clone(), Module::ZZ(m))
+        })
     }).collect();
-    modules.extend(om);
+
+    let mut failed = false;
+    for (m, diagnostics) in parsed {
+        parser::replay(diagnostics);
+        match m {
+            Some((name, m)) => {
+                modules.insert(name, m);
+            }
+            None => {
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        parser::abort();
+    }
     if !silent{
         pb.lock().unwrap().finish_print(&format!("finished parsing {}", artifact_name));
     }
@@ -0,0 +1,672 @@
+/// language server over stdio
+
+use super::ast;
+use super::abs;
+use super::loader;
+use super::project;
+use super::make;
+use super::parser::{self, JsonError};
+use super::name::Name;
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::Ordering;
+use std::sync::{mpsc, Arc, Mutex};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Range {
+    pub file:       String,
+    pub line_start: usize,
+    pub col_start:  usize,
+    pub line_end:   usize,
+    pub col_end:    usize,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Symbol {
+    pub id:     ast::NodeId,
+    pub name:   String,
+    pub kind:   u8,
+    pub range:  Range,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Reference {
+    pub name:   String,
+    pub range:  Range,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Index {
+    pub defs:       HashMap<String, Range>,
+    pub refs:       Vec<Reference>,
+    pub symbols:    Vec<Symbol>,
+}
+
+impl Range {
+    pub fn from_loc(loc: &ast::Location) -> Self {
+        let (line_start, col_start) = loc.line_col();
+        let (line_end, col_end)     = loc.end_line_col();
+        Range {
+            file: loc.file.to_string(),
+            line_start,
+            col_start,
+            line_end,
+            col_end,
+        }
+    }
+
+    fn contains(&self, line: usize, col: usize) -> bool {
+        if line < self.line_start || line > self.line_end {
+            return false;
+        }
+        if line == self.line_start && col < self.col_start {
+            return false;
+        }
+        if line == self.line_end && col >= self.col_end {
+            return false;
+        }
+        true
+    }
+
+    fn size(&self) -> (usize, usize) {
+        (self.line_end - self.line_start, self.col_end.wrapping_sub(self.col_start))
+    }
+
+    fn to_lsp(&self) -> Value {
+        json!({
+            "uri":   path_to_uri(&self.file),
+            "range": {
+                "start": { "line": self.line_start.saturating_sub(1), "character": self.col_start.saturating_sub(1) },
+                "end":   { "line": self.line_end.saturating_sub(1),   "character": self.col_end.saturating_sub(1) },
+            }
+        })
+    }
+}
+
+fn symbol_kind(def: &ast::Def) -> Option<u8> {
+    // https://microsoft.github.io/language-server-protocol/specification#symbolKind
+    match def {
+        ast::Def::Function{..} | ast::Def::Macro{..} | ast::Def::Theory{..} | ast::Def::Closure{..} => Some(12),
+        ast::Def::Fntype{..} | ast::Def::TypeAlias{..} => Some(26),
+        ast::Def::Struct{..}    => Some(23),
+        ast::Def::Enum{..}      => Some(10),
+        ast::Def::Const{..}     => Some(14),
+        ast::Def::Static{..}    => Some(13),
+        ast::Def::Testcase{..} | ast::Def::Test{..} | ast::Def::Include{..} => None,
+    }
+}
+
+
+/// build the index for the project in the current directory.
+/// all modules are resolved to absolute names, so this only works on a project whose names resolve
+pub fn index(variant: &str) -> Index {
+    parser::ERRORS_AS_JSON.store(true, Ordering::SeqCst);
+
+    let stage = make::Stage::test();
+    let (root, mut project) = project::load_cwd();
+    let mut modules = super::load_project(&root, &mut project, variant, &stage);
+
+    let mut ext = abs::Ext::new();
+    let mut names : Vec<Name> = modules.keys().cloned().collect();
+    names.sort_unstable();
+    for name in &names {
+        let mut md = modules.remove(name).unwrap();
+        if let loader::Module::ZZ(ast) = &mut md {
+            abs::abs(ast, &modules, &mut ext);
+        }
+        modules.insert(name.clone(), md);
+    }
+
+    let mut index = Index::default();
+    for name in &names {
+        let md = match modules.get(name) {
+            Some(loader::Module::ZZ(md)) => md,
+            _ => continue,
+        };
+
+        index.defs.insert(md.name.to_string(), Range{
+            file:       md.source.to_string_lossy().into(),
+            line_start: 1,
+            col_start:  1,
+            line_end:   1,
+            col_end:    1,
+        });
+
+        for import in &md.imports {
+            index.refs.push(Reference{
+                name:   import.name.to_string(),
+                range:  Range::from_loc(&import.loc),
+            });
+        }
+
+        for local in &md.locals {
+            let mut fqn = md.name.clone();
+            fqn.push(local.name.clone());
+
+            let range = match &local.def {
+                ast::Def::Function{nameloc, ..}
+                | ast::Def::Fntype{nameloc, ..}
+                | ast::Def::Closure{nameloc, ..} => Range::from_loc(nameloc),
+                _ => Range::from_loc(&local.loc),
+            };
+
+            if let Some(kind) = symbol_kind(&local.def) {
+                index.symbols.push(Symbol{
+                    id:   ast::NodeId::of(&fqn),
+                    name: local.name.clone(),
+                    kind,
+                    range: range.clone(),
+                });
+            }
+            if let ast::Def::Enum{names, ..} = &local.def {
+                for (n, _) in names {
+                    let mut fqn = fqn.clone();
+                    fqn.push(n.clone());
+                    index.defs.insert(fqn.to_string(), range.clone());
+                }
+            }
+            index.defs.insert(fqn.to_string(), range);
+
+            index_def(&mut index, &local.def);
+        }
+    }
+    index
+}
+
+fn index_typed(index: &mut Index, typed: &ast::Typed) {
+    if let ast::Type::Other(name) = &typed.t {
+        if name.is_absolute() {
+            index.refs.push(Reference{
+                name:   name.to_string(),
+                range:  Range::from_loc(&typed.loc),
+            });
+        }
+    }
+}
+
+fn index_def(index: &mut Index, def: &ast::Def) {
+    match def {
+        ast::Def::Static{typed, expr, ..} | ast::Def::Const{typed, expr, ..} => {
+            index_typed(index, typed);
+            index_expr(index, expr);
+        }
+        ast::Def::Function{ret, args, body, callassert, calleffect, ..} => {
+            if let Some(ret) = ret {
+                index_typed(index, &ret.typed);
+            }
+            for arg in args {
+                index_typed(index, &arg.typed);
+            }
+            for expr in callassert.iter().chain(calleffect.iter()) {
+                index_expr(index, expr);
+            }
+            index_block(index, body);
+        }
+        ast::Def::Theory{ret, args, ..} | ast::Def::Fntype{ret, args, ..} => {
+            if let Some(ret) = ret {
+                index_typed(index, &ret.typed);
+            }
+            for arg in args {
+                index_typed(index, &arg.typed);
+            }
+        }
+        ast::Def::Struct{fields, ..} => {
+            for field in fields {
+                index_typed(index, &field.typed);
+            }
+        }
+        ast::Def::Macro{body, ..} => {
+            index_block(index, body);
+        }
+        ast::Def::Testcase{fields} => {
+            for (_, expr) in fields {
+                index_expr(index, expr);
+            }
+        }
+        ast::Def::Test{body, ..} => {
+            index_block(index, body);
+        }
+        ast::Def::Closure{fntype, target, ..} => {
+            index_typed(index, fntype);
+            index_typed(index, target);
+        }
+        ast::Def::TypeAlias{typed} => {
+            index_typed(index, typed);
+        }
+        ast::Def::Enum{..} | ast::Def::Include{..} => (),
+    }
+}
+
+fn index_block(index: &mut Index, block: &ast::Block) {
+    for stm in &block.statements {
+        index_statement(index, stm);
+    }
+}
+
+fn index_statement(index: &mut Index, stm: &ast::Statement) {
+    match stm {
+        ast::Statement::Mark{lhs, ..} => {
+            index_expr(index, lhs);
+        }
+        ast::Statement::Label{..}
+        | ast::Statement::Break{..}
+        | ast::Statement::Continue{..}
+        | ast::Statement::CBlock{..} => {}
+        ast::Statement::Block(b) | ast::Statement::Unsafe(b) => {
+            index_block(index, b);
+        }
+        ast::Statement::For{e1, e2, e3, body} => {
+            for s in e1.iter().chain(e3.iter()) {
+                index_statement(index, s);
+            }
+            if let Some(e2) = e2 {
+                index_expr(index, e2);
+            }
+            index_block(index, body);
+        }
+        ast::Statement::While{expr, body} => {
+            index_expr(index, expr);
+            index_block(index, body);
+        }
+        ast::Statement::If{branches} => {
+            for (_, expr, body) in branches {
+                if let Some(expr) = expr {
+                    index_expr(index, expr);
+                }
+                index_block(index, body);
+            }
+        }
+        ast::Statement::Assign{lhs, rhs, ..} => {
+            index_expr(index, lhs);
+            index_expr(index, rhs);
+        }
+        ast::Statement::Var{typed, array, assign, ..} => {
+            index_typed(index, typed);
+            if let Some(Some(array)) = array {
+                index_expr(index, array);
+            }
+            if let Some(assign) = assign {
+                index_expr(index, assign);
+            }
+        }
+        ast::Statement::Expr{expr, ..} => {
+            index_expr(index, expr);
+        }
+        ast::Statement::Return{expr, ..} => {
+            if let Some(expr) = expr {
+                index_expr(index, expr);
+            }
+        }
+        ast::Statement::Switch{expr, cases, default, ..} => {
+            index_expr(index, expr);
+            for (conds, block) in cases {
+                for cond in conds {
+                    index_expr(index, cond);
+                }
+                index_block(index, block);
+            }
+            if let Some(default) = default {
+                index_block(index, default);
+            }
+        }
+    }
+}
+
+fn index_expr(index: &mut Index, expr: &ast::Expression) {
+    match expr {
+        ast::Expression::Name(typed) => {
+            index_typed(index, typed);
+        }
+        ast::Expression::MemberAccess{lhs, ..} => {
+            index_expr(index, lhs);
+        }
+        ast::Expression::ArrayAccess{lhs, rhs, ..} | ast::Expression::Infix{lhs, rhs, ..} => {
+            index_expr(index, lhs);
+            index_expr(index, rhs);
+        }
+        ast::Expression::LiteralString{..}
+        | ast::Expression::LiteralChar{..}
+        | ast::Expression::Literal{..} => {}
+        ast::Expression::Call{name, args, ..} => {
+            index_expr(index, name);
+            for arg in args {
+                index_expr(index, arg);
+            }
+        }
+        ast::Expression::Cast{into, expr, ..} => {
+            index_typed(index, into);
+            index_expr(index, expr);
+        }
+        ast::Expression::UnaryPost{expr, ..} | ast::Expression::UnaryPre{expr, ..} => {
+            index_expr(index, expr);
+        }
+        ast::Expression::StructInit{typed, fields, ..} => {
+            index_typed(index, typed);
+            for (_, expr) in fields {
+                index_expr(index, expr);
+            }
+        }
+        ast::Expression::ArrayInit{fields, ..} => {
+            for expr in fields {
+                index_expr(index, expr);
+            }
+        }
+    }
+}
+
+impl Index {
+    /// find the definition of the innermost name at a 1-based position
+    pub fn definition(&self, file: &str, line: usize, col: usize) -> Option<&Range> {
+        let mut best : Option<&Reference> = None;
+        for r in &self.refs {
+            if r.range.file != file || !r.range.contains(line, col) {
+                continue;
+            }
+            match best {
+                Some(b) if b.range.size() <= r.range.size() => (),
+                _ => best = Some(r),
+            }
+        }
+        let mut name = Name::from(&best?.name);
+        while name.len() > 1 {
+            if let Some(def) = self.defs.get(&name.to_string()) {
+                return Some(def);
+            }
+            name.pop();
+        }
+        None
+    }
+}
+
+
+fn path_to_uri(path: &str) -> String {
+    let path = Path::new(path).canonicalize().unwrap_or(PathBuf::from(path));
+    let mut s = path.to_string_lossy().replace("\\", "/");
+    if !s.starts_with('/') {
+        s.insert(0, '/');
+    }
+    format!("file://{}", s.replace(" ", "%20"))
+}
+
+fn uri_to_path(uri: &str) -> PathBuf {
+    let s = uri.trim_start_matches("file://");
+    let mut out = Vec::new();
+    let b = s.as_bytes();
+    let mut i = 0;
+    while i < b.len() {
+        // the two bytes after % may be the start of a multibyte char, so they are checked as bytes first
+        if b[i] == b'%' && i + 2 < b.len() && b[i + 1].is_ascii_hexdigit() && b[i + 2].is_ascii_hexdigit() {
+            let hex = std::str::from_utf8(&b[i + 1..i + 3]).unwrap();
+            out.push(u8::from_str_radix(hex, 16).unwrap());
+            i += 3;
+            continue;
+        }
+        out.push(b[i]);
+        i += 1;
+    }
+    let s = String::from_utf8_lossy(&out).to_string();
+    #[cfg(windows)]
+    let s = s.trim_start_matches('/').to_string();
+    PathBuf::from(s)
+}
+
+fn canonical(path: &Path) -> String {
+    path.canonicalize().unwrap_or(path.to_path_buf()).to_string_lossy().into()
+}
+
+/// the next message, or an error if its body is not json. None at the end of the input
+fn read_message<R: BufRead>(r: &mut R) -> Option<Result<Value, serde_json::Error>> {
+    let mut len = None;
+    loop {
+        let mut line = String::new();
+        if r.read_line(&mut line).ok()? == 0 {
+            return None;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(v) = line.strip_prefix("Content-Length:") {
+            len = v.trim().parse::<usize>().ok();
+        }
+    }
+    let mut buf = vec![0; len?];
+    r.read_exact(&mut buf).ok()?;
+    Some(serde_json::from_slice(&buf))
+}
+
+fn write_message(v: &Value) {
+    let body = serde_json::to_string(v).unwrap();
+    let stdout = std::io::stdout();
+    let mut stdout = stdout.lock();
+    write!(stdout, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+    stdout.flush().unwrap();
+}
+
+fn notify(method: &str, params: Value) {
+    write_message(&json!({"jsonrpc": "2.0", "method": method, "params": params}));
+}
+
+
+struct Server {
+    index:      Arc<Mutex<Index>>,
+    // wakes the thread running the checks, see checker
+    checks:     Option<mpsc::Sender<()>>,
+    shutdown:   bool,
+}
+
+impl Server {
+    fn respond(&self, id: Value, result: Value) {
+        write_message(&json!({"jsonrpc": "2.0", "id": id, "result": result}));
+    }
+
+    fn initialize(&mut self, params: &Value) -> Value {
+        let start = params["rootUri"].as_str().map(uri_to_path)
+            .or(params["rootPath"].as_str().map(PathBuf::from))
+            .unwrap_or(std::env::current_dir().unwrap());
+
+        let mut search = Some(start.as_path());
+        while let Some(p) = search {
+            if p.join("zz.toml").exists() {
+                let (tx, rx) = mpsc::channel();
+                let (root, index) = (p.to_path_buf(), self.index.clone());
+                std::thread::spawn(move || checker(root, index, rx));
+                self.checks = Some(tx);
+                break;
+            }
+            search = p.parent();
+        }
+
+        json!({
+            "capabilities": {
+                "textDocumentSync": { "openClose": true, "change": 0, "save": { "includeText": false } },
+                "definitionProvider":       true,
+                "documentSymbolProvider":   true,
+                "workspaceSymbolProvider":  true,
+            },
+            "serverInfo": { "name": "zz", "version": clap::crate_version!() },
+        })
+    }
+
+    fn symbol_information(&self, s: &Symbol) -> Value {
+        json!({
+            "name":     s.name,
+            "kind":     s.kind,
+            "location": s.range.to_lsp(),
+        })
+    }
+
+    fn handle(&mut self, msg: Value) {
+        let method = msg["method"].as_str().unwrap_or("").to_string();
+        let params = &msg["params"];
+        let id = msg.get("id").cloned();
+
+        match method.as_str() {
+            "initialize" => {
+                let r = self.initialize(params);
+                self.respond(id.unwrap_or(Value::Null), r);
+            }
+            "initialized" | "textDocument/didOpen" | "textDocument/didSave" => {
+                if let Some(checks) = &self.checks {
+                    checks.send(()).ok();
+                }
+            }
+            "textDocument/definition" => {
+                let file = canonical(&uri_to_path(params["textDocument"]["uri"].as_str().unwrap_or("")));
+                let line = params["position"]["line"].as_u64().unwrap_or(0) as usize + 1;
+                let col  = params["position"]["character"].as_u64().unwrap_or(0) as usize + 1;
+                let r = match self.index.lock().unwrap().definition(&file, line, col) {
+                    Some(range) => range.to_lsp(),
+                    None => Value::Null,
+                };
+                self.respond(id.unwrap_or(Value::Null), r);
+            }
+            "textDocument/documentSymbol" => {
+                let file = canonical(&uri_to_path(params["textDocument"]["uri"].as_str().unwrap_or("")));
+                let r : Vec<Value> = self.index.lock().unwrap().symbols.iter()
+                    .filter(|s| canonical(Path::new(&s.range.file)) == file)
+                    .map(|s| self.symbol_information(s))
+                    .collect();
+                self.respond(id.unwrap_or(Value::Null), Value::Array(r));
+            }
+            "workspace/symbol" => {
+                let query = params["query"].as_str().unwrap_or("").to_lowercase();
+                let r : Vec<Value> = self.index.lock().unwrap().symbols.iter()
+                    .filter(|s| s.name.to_lowercase().contains(&query))
+                    .map(|s| self.symbol_information(s))
+                    .collect();
+                self.respond(id.unwrap_or(Value::Null), Value::Array(r));
+            }
+            "shutdown" => {
+                self.shutdown = true;
+                self.respond(id.unwrap_or(Value::Null), Value::Null);
+            }
+            "exit" => {
+                std::process::exit(if self.shutdown { 0 } else { 1 });
+            }
+            _ => {
+                // requests must be answered, notifications can be ignored
+                if let Some(id) = id {
+                    write_message(&json!({
+                        "jsonrpc": "2.0",
+                        "id": id,
+                        "error": { "code": -32601, "message": format!("method not found: {}", method) },
+                    }));
+                }
+            }
+        }
+    }
+}
+
+/// runs the checks on its own thread, so requests are answered while the prover works.
+/// saves during a check are all covered by one more check after it
+fn checker(root: PathBuf, index: Arc<Mutex<Index>>, rx: mpsc::Receiver<()>) {
+    let mut diagnosed = HashSet::new();
+    while rx.recv().is_ok() {
+        while rx.try_recv().is_ok() {}
+        reindex(&root, &index);
+        check(&root, &mut diagnosed);
+    }
+}
+
+/// refresh the index, unless the names of the project don't resolve. it only needs parsing, so it comes before the check
+fn reindex(root: &Path, index: &Mutex<Index>) {
+    let exe = std::env::current_exe().expect("self path");
+    let output = match Command::new(&exe).arg("lsp").arg("--index").current_dir(root).output() {
+        Ok(v) => v,
+        Err(_) => return,
+    };
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if let Some(Ok(new)) = stdout.lines().last().map(serde_json::from_str::<Index>) {
+        *index.lock().unwrap() = new;
+    }
+}
+
+/// run zz check and publish its diagnostics. files diagnosed before that are clean now get an empty list
+fn check(root: &Path, diagnosed: &mut HashSet<String>) {
+    let exe = std::env::current_exe().expect("self path");
+    let output = match Command::new(&exe).arg("check").current_dir(root).output() {
+        Ok(v) => v,
+        Err(e) => {
+            notify("window/logMessage", json!({"type": 1, "message": format!("cannot run zz check: {}", e)}));
+            return;
+        }
+    };
+
+    let mut diags : HashMap<String, Vec<Value>> = HashMap::new();
+    // file and position of the error that detail lines belong to
+    let mut last : Option<(String, usize)> = None;
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let j : JsonError = match serde_json::from_str(line) {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+        let range = json!({
+            "start": { "line": j.line_start.saturating_sub(1), "character": j.column_start.saturating_sub(1) },
+            "end":   { "line": j.line_end.saturating_sub(1),   "character": j.column_end.saturating_sub(1) },
+        });
+        let file = canonical(Path::new(&j.file_name));
+
+        if j.level == "W" {
+            let last = last.as_ref().and_then(|(f, i)| diags.get_mut(f).and_then(|v| v.get_mut(*i)));
+            if let Some(last) = last {
+                if last["range"] == range {
+                    let msg = format!("{}\n{}", last["message"].as_str().unwrap_or(""), j.message);
+                    last["message"] = Value::String(msg);
+                } else {
+                    last["relatedInformation"].as_array_mut().unwrap().push(json!({
+                        "location": { "uri": path_to_uri(&file), "range": range },
+                        "message":  j.message,
+                    }));
+                }
+                continue;
+            }
+        }
+
+        let d = diags.entry(file.clone()).or_default();
+        d.push(json!({
+            "range":    range,
+            "severity": if j.level == "error" { 1 } else { 2 },
+            "source":   "zz",
+            "message":  j.message,
+            "relatedInformation": [],
+        }));
+        last = Some((file, d.len() - 1));
+    }
+
+    for file in std::mem::take(diagnosed) {
+        if !diags.contains_key(&file) {
+            notify("textDocument/publishDiagnostics", json!({"uri": path_to_uri(&file), "diagnostics": []}));
+        }
+    }
+    for (file, d) in &diags {
+        notify("textDocument/publishDiagnostics", json!({"uri": path_to_uri(file), "diagnostics": d}));
+        diagnosed.insert(file.clone());
+    }
+}
+
+pub fn main() {
+    let mut server = Server {
+        index:      Arc::new(Mutex::new(Index::default())),
+        checks:     None,
+        shutdown:   false,
+    };
+
+    let stdin = std::io::stdin();
+    let mut stdin = stdin.lock();
+    while let Some(msg) = read_message(&mut stdin) {
+        match msg {
+            Ok(msg) => server.handle(msg),
+            // the length was read, so the next message can still be found
+            Err(e) => write_message(&json!({
+                "jsonrpc": "2.0",
+                "id": Value::Null,
+                "error": { "code": -32700, "message": format!("parse error: {}", e) },
+            })),
+        }
+    }
+}
@@ -0,0 +1,387 @@
+/// hygienic expansion of macros on the ast
+
+use super::ast;
+use super::loader;
+use super::name::Name;
+use super::parser::{emit_error, abort};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+pub const MAX_DEPTH : usize = 64;
+
+static EXPANSIONS: AtomicUsize = AtomicUsize::new(0);
+
+pub struct Macro {
+    args:   Vec<String>,
+    body:   ast::Block,
+    loc:    ast::Location,
+}
+
+/// all macros that can be expanded, by absolute name
+#[derive(Default)]
+pub struct Macros(HashMap<Name, Macro>);
+
+impl Macros {
+    pub fn collect(modules: &HashMap<Name, loader::Module>) -> Macros {
+        let mut macros = Macros::default();
+        for (_, md) in modules {
+            let md = match md {
+                loader::Module::ZZ(md) => md,
+                loader::Module::C(_) => continue,
+            };
+            for local in &md.locals {
+                if let ast::Def::Macro{args, body} = &local.def {
+                    if args.iter().any(|a| a == "...") {
+                        continue;
+                    }
+                    let mut name = md.name.clone();
+                    name.push(local.name.clone());
+                    macros.0.insert(name, Macro{
+                        args:   args.clone(),
+                        body:   body.clone(),
+                        loc:    local.loc.clone(),
+                    });
+                }
+            }
+        }
+        macros
+    }
+}
+
+// mutable walk over everything in a body. visitors override what they need and call walk_* for the rest
+
+pub(crate) trait Visit : Sized {
+    fn statement(&mut self, stm: &mut ast::Statement) {
+        walk_statement(self, stm);
+    }
+    fn expr(&mut self, expr: &mut ast::Expression) {
+        walk_expr(self, expr);
+    }
+    fn loc(&mut self, _loc: &mut ast::Location) {
+    }
+}
+
+pub(crate) fn walk_block<V: Visit>(v: &mut V, block: &mut ast::Block) {
+    v.loc(&mut block.end);
+    for stm in &mut block.statements {
+        v.statement(stm);
+    }
+}
+
+pub(crate) fn walk_statement<V: Visit>(v: &mut V, stm: &mut ast::Statement) {
+    match stm {
+        ast::Statement::Mark{lhs, loc, ..} => {
+            v.loc(loc);
+            v.expr(lhs);
+        }
+        ast::Statement::Label{loc, ..} => {
+            v.loc(loc);
+        }
+        ast::Statement::Assign{loc, lhs, rhs, ..} => {
+            v.loc(loc);
+            v.expr(lhs);
+            v.expr(rhs);
+        }
+        ast::Statement::Expr{loc, expr} => {
+            v.loc(loc);
+            v.expr(expr);
+        }
+        ast::Statement::Switch{loc, expr, cases, default} => {
+            v.loc(loc);
+            v.expr(expr);
+            for (conds, block) in cases {
+                for cond in conds {
+                    v.expr(cond);
+                }
+                walk_block(v, block);
+            }
+            if let Some(default) = default {
+                walk_block(v, default);
+            }
+        }
+        ast::Statement::Continue{loc} | ast::Statement::Break{loc} => {
+            v.loc(loc);
+        }
+        ast::Statement::Return{loc, expr} => {
+            v.loc(loc);
+            if let Some(expr) = expr {
+                v.expr(expr);
+            }
+        }
+        ast::Statement::Var{loc, typed, array, assign, ..} => {
+            v.loc(loc);
+            v.loc(&mut typed.loc);
+            if let Some(Some(array)) = array {
+                v.expr(array);
+            }
+            if let Some(assign) = assign {
+                v.expr(assign);
+            }
+        }
+        ast::Statement::While{expr, body} => {
+            v.expr(expr);
+            walk_block(v, body);
+        }
+        ast::Statement::For{e1, e2, e3, body} => {
+            for stm in e1 {
+                v.statement(stm);
+            }
+            if let Some(e2) = e2 {
+                v.expr(e2);
+            }
+            for stm in e3 {
+                v.statement(stm);
+            }
+            walk_block(v, body);
+        }
+        ast::Statement::If{branches} => {
+            for (loc, cond, block) in branches {
+                v.loc(loc);
+                if let Some(cond) = cond {
+                    v.expr(cond);
+                }
+                walk_block(v, block);
+            }
+        }
+        ast::Statement::Block(block) | ast::Statement::Unsafe(block) => {
+            walk_block(v, block);
+        }
+        ast::Statement::CBlock{loc, ..} => {
+            v.loc(loc);
+        }
+    }
+}
+
+pub(crate) fn walk_expr<V: Visit>(v: &mut V, expr: &mut ast::Expression) {
+    match expr {
+        ast::Expression::Name(typed) => {
+            v.loc(&mut typed.loc);
+        }
+        ast::Expression::MemberAccess{loc, lhs, ..} => {
+            v.loc(loc);
+            v.expr(lhs);
+        }
+        ast::Expression::ArrayAccess{loc, lhs, rhs} => {
+            v.loc(loc);
+            v.expr(lhs);
+            v.expr(rhs);
+        }
+        ast::Expression::LiteralString{loc, ..}
+        | ast::Expression::LiteralChar{loc, ..}
+        | ast::Expression::Literal{loc, ..} => {
+            v.loc(loc);
+        }
+        ast::Expression::Call{loc, name, args, ..} => {
+            v.loc(loc);
+            v.expr(name);
+            for arg in args {
+                v.expr(arg);
+            }
+        }
+        ast::Expression::Infix{loc, lhs, rhs, ..} => {
+            v.loc(loc);
+            v.expr(lhs);
+            v.expr(rhs);
+        }
+        ast::Expression::Cast{loc, into, expr, ..} => {
+            v.loc(loc);
+            v.loc(&mut into.loc);
+            v.expr(expr);
+        }
+        ast::Expression::UnaryPost{loc, expr, ..} | ast::Expression::UnaryPre{loc, expr, ..} => {
+            v.loc(loc);
+            v.expr(expr);
+        }
+        ast::Expression::StructInit{loc, typed, fields} => {
+            v.loc(loc);
+            v.loc(&mut typed.loc);
+            for (_, expr) in fields {
+                v.expr(expr);
+            }
+        }
+        ast::Expression::ArrayInit{loc, fields, ..} => {
+            v.loc(loc);
+            for expr in fields {
+                v.expr(expr);
+            }
+        }
+    }
+}
+
+/// names of the variables declared in a macro body
+#[derive(Default)]
+struct Declared(HashSet<String>);
+
+impl Visit for Declared {
+    fn statement(&mut self, stm: &mut ast::Statement) {
+        if let ast::Statement::Var{name, ..} = stm {
+            self.0.insert(name.clone());
+        }
+        walk_statement(self, stm);
+    }
+}
+
+/// turns a copy of a macro body into one expansion of it
+struct Substitute<'a> {
+    expansion:  Arc<ast::Expansion>,
+    args:       HashMap<&'a str, &'a ast::Expression>,
+    rename:     HashMap<String, String>,
+}
+
+impl<'a> Visit for Substitute<'a> {
+    fn statement(&mut self, stm: &mut ast::Statement) {
+        if let ast::Statement::Var{name, ..} = stm {
+            if let Some(renamed) = self.rename.get(name.as_str()) {
+                *name = renamed.clone();
+            }
+        }
+        walk_statement(self, stm);
+    }
+
+    fn expr(&mut self, expr: &mut ast::Expression) {
+        if let ast::Expression::Name(ast::Typed{t: ast::Type::Other(name), ..}) = expr {
+            if !name.is_absolute() && name.len() == 1 {
+                // the argument is code of the caller, it keeps its own names and locations
+                if let Some(arg) = self.args.get(name.0[0].as_str()) {
+                    *expr = (*arg).clone();
+                    return;
+                }
+                if let Some(renamed) = self.rename.get(name.0[0].as_str()) {
+                    name.0[0] = renamed.into();
+                }
+            }
+        }
+        walk_expr(self, expr);
+    }
+
+    fn loc(&mut self, loc: &mut ast::Location) {
+        loc.expansion = Some(self.expansion.clone());
+    }
+}
+
+struct Expander<'a> {
+    macros: &'a Macros,
+    depth:  usize,
+}
+
+impl<'a> Expander<'a> {
+    /// the macro called by expr, if it is a call to one
+    fn callee(&self, expr: &ast::Expression) -> Option<(Name, ast::Location, Vec<Box<ast::Expression>>)> {
+        if let ast::Expression::Call{name, args, loc, ..} = expr {
+            if let ast::Expression::Name(ast::Typed{t: ast::Type::Other(name), ..}) = name.as_ref() {
+                if self.macros.0.contains_key(name) {
+                    return Some((name.clone(), loc.clone(), args.clone()));
+                }
+            }
+        }
+        None
+    }
+
+    /// the body of macro name, expanded for the call at loc with args
+    fn instantiate(&mut self, name: &Name, loc: &ast::Location, mut args: Vec<Box<ast::Expression>>) -> ast::Block {
+        let macros = self.macros;
+        let m = &macros.0[name];
+        if args.len() != m.args.len() {
+            emit_error(msg!("macro_argument_count", name = name, expected = m.args.len(), given = args.len()), &[
+                (loc.clone(), msg!("called_here")),
+                (m.loc.clone(), msg!("defined_here")),
+            ]);
+            abort();
+        }
+
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            emit_error(msg!("macro_recursive", name = name), &[
+                (loc.clone(), msg!("macro_recursive.note", depth = MAX_DEPTH)),
+            ]);
+            abort();
+        }
+
+        // macro calls in the arguments are expanded where they are, in the caller
+        for arg in &mut args {
+            self.expr(arg);
+        }
+
+        let expansion = Arc::new(ast::Expansion{
+            name:   name.clone(),
+            call:   loc.clone(),
+        });
+        let n = EXPANSIONS.fetch_add(1, Ordering::Relaxed);
+
+        let mut body = m.body.clone();
+        let mut declared = Declared::default();
+        walk_block(&mut declared, &mut body);
+
+        let mut substitute = Substitute {
+            expansion,
+            args:   m.args.iter().map(|a| a.as_str()).zip(args.iter().map(|a| a.as_ref())).collect(),
+            rename: declared.0.into_iter().map(|v| {
+                let renamed = format!("_{}_{}_{}", name.0.last().unwrap(), n, v);
+                (v, renamed)
+            }).collect(),
+        };
+        walk_block(&mut substitute, &mut body);
+
+        // macros calling macros
+        walk_block(self, &mut body);
+        self.depth -= 1;
+        body
+    }
+}
+
+impl<'a> Visit for Expander<'a> {
+    fn statement(&mut self, stm: &mut ast::Statement) {
+        if let ast::Statement::Expr{expr, ..} = stm {
+            if let Some((name, loc, args)) = self.callee(expr) {
+                let mut body = self.instantiate(&name, &loc, args);
+                // a single expression stays one, so a macro whose body calls another macro is still an expression
+                *stm = match body.statements.pop() {
+                    Some(last) if body.statements.is_empty() && matches!(*last, ast::Statement::Expr{..}) => *last,
+                    last => {
+                        body.statements.extend(last);
+                        ast::Statement::Block(Box::new(body))
+                    }
+                };
+                return;
+            }
+        }
+        walk_statement(self, stm);
+    }
+
+    fn expr(&mut self, expr: &mut ast::Expression) {
+        if let Some((name, loc, args)) = self.callee(expr) {
+            let mut body = self.instantiate(&name, &loc, args);
+            match body.statements.pop() {
+                Some(stm) if body.statements.is_empty() => {
+                    if let ast::Statement::Expr{expr: e, ..} = *stm {
+                        *expr = e;
+                        return;
+                    }
+                }
+                _ => (),
+            }
+            emit_error(msg!("macro_not_expression", name = name), &[
+                (loc, msg!("macro_not_expression.here")),
+                (self.macros.0[&name].loc.clone(), msg!("macro_not_expression.note")),
+            ]);
+            abort();
+        }
+        walk_expr(self, expr);
+    }
+}
+
+pub fn expand(md: &mut ast::Module, macros: &Macros) {
+    let mut expander = Expander {
+        macros,
+        depth: 0,
+    };
+    for local in &mut md.locals {
+        match &mut local.def {
+            ast::Def::Function{body, ..} | ast::Def::Test{body, ..} => {
+                walk_block(&mut expander, body);
+            }
+            _ => (),
+        }
+    }
+}
@@ -11,6 +11,10 @@ use std::sync::atomic::{Ordering};
 use zz;
 use std::io::{Read, Write};
 use std::time::{Duration, Instant};
+use std::collections::HashMap;
+
+#[global_allocator]
+static ALLOCATOR: zz::selfprofile::Counting = zz::selfprofile::Counting;
 
 fn main() {
     if let Err(_) = std::env::var("RUST_LOG") {
@@ -21,50 +25,146 @@ fn main() {
         .default_format_timestamp(false)
         .default_format_module_path(false)
         .init();
+    zz::crash::install();
 
-    let matches = App::new("Drunk Octopus")
-        .version(clap::crate_version!())
-        .setting(clap::AppSettings::UnifiedHelpMessage)
-        .arg(Arg::with_name("smt-timeout").takes_value(true).required(false).long("smt-timeout"))
-        .subcommand(SubCommand::with_name("check").about("check the current project"))
-            .arg(Arg::with_name("slow").takes_value(false).required(false).long("slow").short("0"))
-            .arg(Arg::with_name("variant").takes_value(true).required(false).long("variant").short("s"))
-            .arg(Arg::with_name("release").takes_value(false).required(false).long("release"))
-            .arg(Arg::with_name("debug").takes_value(false).required(false).long("debug"))
-        .subcommand(SubCommand::with_name("build").about("build the current project")
-            .arg(Arg::with_name("slow").takes_value(false).required(false).long("slow").short("0"))
-            .arg(Arg::with_name("variant").takes_value(true).required(false).long("variant").short("s"))
-            .arg(Arg::with_name("release").takes_value(false).required(false).long("release"))
-            .arg(Arg::with_name("debug").takes_value(false).required(false).long("debug"))
-        )
-        .subcommand(SubCommand::with_name("clean").about("remove the target directory"))
-        .subcommand(SubCommand::with_name("bench").about("benchmark tests/*.zz")
-                    .arg(Arg::with_name("testname").takes_value(true).required(false).index(1)),
-        )
-        .subcommand(SubCommand::with_name("test").about("execute tests/*.zz")
-                    .arg(Arg::with_name("testname").takes_value(true).required(false).index(1)),
-        )
-        .subcommand(SubCommand::with_name("init").about("init zz project in current directory"))
-        .subcommand(
-            SubCommand::with_name("run").about("build and run")
-            .arg(Arg::with_name("release").takes_value(false).required(false).long("release"))
-            .arg(Arg::with_name("debug").takes_value(false).required(false).long("debug"))
-            .arg(Arg::with_name("variant").takes_value(true).required(false).long("variant").short("s"))
-            .arg(Arg::with_name("args").takes_value(true).multiple(true).required(false).index(1))
-        )
-        .subcommand(SubCommand::with_name("fuzz").about("execute tests/*.zz with afl fuzzer")
-            .arg(Arg::with_name("testname").takes_value(true).required(false).index(1)),
-        )
-        .get_matches();
+    let matches = app().get_matches();
 
     if let Some(t) = matches.value_of("smt-timeout") {
         zz::smt::TIMEOUT.store(t.parse().unwrap(), Ordering::Relaxed);
     }
 
+    if let (_, Some(submatches)) = matches.subcommand() {
+        set_lints(submatches);
+    }
+
     match matches.subcommand() {
         ("init", Some(_submatches)) => {
             zz::project::init();
         },
+        ("lsp", Some(submatches)) => {
+            if submatches.is_present("index") {
+                let variant = submatches.value_of("variant").unwrap_or("default");
+                let index = zz::lsp::index(variant);
+                println!("{}", serde_json::to_string(&index).unwrap());
+            } else {
+                zz::lsp::main();
+            }
+        },
+        ("ast", Some(submatches)) => {
+            let path = std::path::Path::new(submatches.value_of("file").unwrap());
+            if !path.exists() {
+                error!("{}: no such file", path.display());
+                std::process::exit(9);
+            }
+            let md = zz::parser::parse(path, &HashMap::new(), &zz::make::Stage::release(), zz::edition::Edition::E2020);
+            println!("{}", serde_json::to_string_pretty(&md).unwrap());
+        },
+        ("watch", Some(submatches)) => {
+            zz::watch::main(
+                submatches.value_of("command").unwrap_or("build"),
+                submatches.value_of("variant").unwrap_or("default"),
+            );
+        },
+        ("diff", Some(submatches)) => {
+            zz::diff::main(
+                std::path::Path::new(submatches.value_of("old").unwrap()),
+                std::path::Path::new(submatches.value_of("new").unwrap()),
+                submatches.value_of("format") == Some("json"),
+            );
+        },
+        ("tokens", Some(submatches)) => {
+            let path = std::path::Path::new(submatches.value_of("file").unwrap());
+            let src = match std::fs::read_to_string(path) {
+                Ok(src) => src,
+                Err(e) => {
+                    error!("{}: {}", path.display(), e);
+                    std::process::exit(9);
+                }
+            };
+            println!("{}", serde_json::to_string_pretty(&zz::tokens::tokenize(&src)).unwrap());
+        },
+        ("metadata", Some(submatches)) => {
+            let metadata = zz::metadata::metadata(submatches.value_of("variant").unwrap_or("default"));
+            println!("{}", serde_json::to_string_pretty(&metadata).unwrap());
+        },
+        ("fix", Some(submatches)) => {
+            if submatches.is_present("edition") {
+                let edition = submatches.value_of("edition")
+                    .map(|v| zz::edition::Edition::parse(v).unwrap())
+                    .unwrap_or(zz::edition::Edition::LATEST);
+                zz::edition::fix(edition);
+            }
+            zz::fix();
+        },
+        ("migrate-config", Some(_submatches)) => {
+            zz::schema::migrate(&zz::project::root_cwd());
+        },
+        ("completions", Some(submatches)) => {
+            match submatches.value_of("list") {
+                Some(list) => zz::completions::list(list),
+                None => zz::completions::print(app(), submatches.value_of("shell").unwrap()),
+            }
+        },
+        ("man", Some(submatches)) => {
+            zz::completions::man(app(), std::path::Path::new(submatches.value_of("dir").unwrap_or(".")));
+        },
+        ("doctor", Some(_submatches)) => {
+            zz::doctor::main();
+        },
+        ("analyze", Some(_submatches)) => {
+            zz::analyze();
+        },
+        ("reduce", Some(submatches)) => {
+            zz::reduce::main(
+                std::path::Path::new(submatches.value_of("file").unwrap()),
+                submatches.value_of("check").unwrap(),
+            );
+        },
+        ("migrate", Some(submatches)) => {
+            zz::migrate::main(std::path::Path::new(submatches.value_of("dir").unwrap_or(".")));
+        },
+        ("package", Some(_submatches)) => {
+            zz::package::main();
+        },
+        ("grammar", Some(submatches)) => {
+            zz::grammar::main(
+                submatches.value_of("format").unwrap_or("ebnf"),
+                submatches.value_of("output").map(std::path::Path::new),
+            );
+        },
+        ("bindgen", Some(submatches)) => {
+            zz::bindgen(
+                submatches.value_of("lang").unwrap(),
+                submatches.is_present("safe"),
+                submatches.value_of("output").map(std::path::Path::new),
+            );
+        },
+        ("api", Some(submatches)) => {
+            zz::api(submatches.is_present("record"));
+        },
+        ("fmt", Some(submatches)) => {
+            let files = submatches.values_of("files").map(|v|v.map(String::from).collect()).unwrap_or_default();
+            zz::fmt::main(submatches.is_present("check"), files);
+        },
+        ("doc", Some(submatches)) => {
+            let format = match submatches.value_of("format") {
+                Some("markdown")    => zz::emitter_docs::Format::Markdown,
+                _                   => zz::emitter_docs::Format::Html,
+            };
+            zz::doc(format);
+        },
+        ("eval", Some(submatches)) => {
+            zz::eval(submatches.value_of("expr").unwrap(), submatches.value_of("module").unwrap_or("main"));
+        },
+        ("update", Some(_submatches)) => {
+            zz::update();
+        },
+        ("install", Some(submatches)) => {
+            let prefix = submatches.value_of("prefix").map(String::from)
+                .or_else(|| std::env::var("PREFIX").ok())
+                .unwrap_or("/usr/local".to_string());
+            zz::install(std::path::Path::new(&prefix), submatches.value_of("variant").unwrap_or("default"));
+        },
         ("clean", Some(_submatches)) => {
             let (root, _) = zz::project::load_cwd();
             if root.join("target").exists() {
@@ -217,6 +317,9 @@ fn main() {
                 }
             }
 
+            if !bench && !zz::unittest::run(&root, &stage, submatches.value_of("testname")) {
+                std::process::exit(10);
+            }
         }
         ("run", Some(submatches)) => {
             let stage = if submatches.is_present("release") {
@@ -227,6 +330,10 @@ fn main() {
                 zz::make::Stage::test()
             };
             let variant = submatches.value_of("variant").unwrap_or("default");
+            if submatches.is_present("hot") {
+                zz::hot::run(variant, stage);
+                return;
+            }
             zz::build(zz::BuildSet::Run, variant, stage.clone(), false);
             let (root, mut project) = zz::project::load_cwd();
 
@@ -240,18 +347,47 @@ fn main() {
                 error!("no exe artifact to run");
                 std::process::exit(9);
             }
-            if exes.len() > 1 {
-                error!("multiple exe artifacts");
-                std::process::exit(9);
-            }
+            let names : Vec<&str> = exes.iter().map(|a| a.name.as_str()).collect();
+            let exe = match submatches.value_of("bin") {
+                Some(bin) => match exes.iter().find(|a| a.name == bin) {
+                    Some(exe) => exe,
+                    None => {
+                        error!("no exe artifact \"{}\", there is {}", bin, names.join(", "));
+                        std::process::exit(9);
+                    }
+                },
+                None if exes.len() > 1 => {
+                    error!("multiple exe artifacts, pick one with --bin: {}", names.join(", "));
+                    std::process::exit(9);
+                }
+                None => &exes[0],
+            };
 
-            let running = root.join("target").join(stage.to_string()).join("bin").join(&exes[0].name);
-            println!("running \"{}\"\n", running.to_string_lossy());
+            // stdout belongs to the program
+            let running = root.join("target").join(stage.to_string()).join("bin").join(&exe.name);
+            eprintln!("running \"{}\"\n", running.to_string_lossy());
             let status = Command::new(running)
                 .args(submatches.values_of("args").unwrap_or_default())
                 .status()
                 .expect("failed to execute process");
-            std::process::exit(status.code().expect("failed to execute process"));
+            match status.code() {
+                Some(code) => std::process::exit(code),
+                None => {
+                    // like a shell does, 128 + the signal
+                    #[cfg(unix)]
+                    {
+                        use std::os::unix::process::ExitStatusExt;
+                        let signal = status.signal().unwrap_or(0);
+                        error!("{} died by signal {}", exe.name, signal);
+                        std::process::exit(128 + signal);
+                    }
+                    #[cfg(not(unix))]
+                    {
+                        error!("{} died by signal", exe.name);
+                        std::process::exit(9);
+                    }
+                }
+            }
         },
         ("fuzz", Some(submatches)) => {
             let variant = submatches.value_of("variant").unwrap_or("default");
@@ -352,6 +488,32 @@ fn main() {
             } else {
                 zz::make::Stage::test()
             };
+            let stage = match submatches.value_of("target") {
+                Some("wasm32")  => stage.wasm32(),
+                Some(name)      => stage.target(name),
+                None            => stage,
+            };
+            if submatches.is_present("self-profile") {
+                zz::selfprofile::enable();
+            }
+            if submatches.is_present("amalgamate") {
+                zz::amalgamate::enable();
+            }
+            match submatches.value_of("archive") {
+                Some("tar") => zz::amalgamate::archive(zz::archive::Format::Tar),
+                Some("zip") => zz::amalgamate::archive(zz::archive::Format::Zip),
+                _ => (),
+            }
+            if submatches.is_present("ninja") {
+                zz::buildfiles::enable_ninja();
+            }
+            for lang in submatches.values_of("emit").into_iter().flatten() {
+                match lang {
+                    "python" => zz::emitter_py::enable(),
+                    "lua" => zz::emitter_lua::enable(),
+                    _ => (),
+                }
+            }
 
             zz::build(zz::BuildSet::All, submatches.value_of("variant").unwrap_or("default"), stage, submatches.is_present("slow"))
         },
@@ -362,3 +524,183 @@ fn main() {
     }
 }
 
+/// the command line of zz, also what completions and man pages are generated from
+fn app() -> App<'static, 'static> {
+    App::new("Drunk Octopus")
+        .version(clap::crate_version!())
+        .setting(clap::AppSettings::UnifiedHelpMessage)
+        .arg(Arg::with_name("smt-timeout").takes_value(true).required(false).long("smt-timeout"))
+        .subcommand(lint_args(SubCommand::with_name("check").about("check the current project")))
+            .arg(Arg::with_name("slow").takes_value(false).required(false).long("slow").short("0"))
+            .arg(Arg::with_name("variant").takes_value(true).required(false).long("variant").short("s"))
+            .arg(Arg::with_name("release").takes_value(false).required(false).long("release"))
+            .arg(Arg::with_name("debug").takes_value(false).required(false).long("debug"))
+        .subcommand(lint_args(SubCommand::with_name("build").about("build the current project"))
+            .arg(Arg::with_name("slow").takes_value(false).required(false).long("slow").short("0"))
+            .arg(Arg::with_name("variant").takes_value(true).required(false).long("variant").short("s"))
+            .arg(Arg::with_name("release").takes_value(false).required(false).long("release"))
+            .arg(Arg::with_name("debug").takes_value(false).required(false).long("debug"))
+            .arg(Arg::with_name("target").takes_value(true).required(false).long("target")
+                 .help("wasm32, or a [target.name] profile from zz.toml"))
+            .arg(Arg::with_name("self-profile").takes_value(false).required(false).long("self-profile")
+                 .help("report time and memory the compiler spends in each pass"))
+            .arg(Arg::with_name("amalgamate").takes_value(false).required(false).long("amalgamate")
+                 .help("also write the whole build as one .c and .h file to target/<stage>/amalgamation/"))
+            .arg(Arg::with_name("archive").takes_value(true).required(false).long("archive")
+                 .possible_values(&["tar", "zip"]).requires("amalgamate")
+                 .help("write the amalgamation into target/<stage>/amalgamation/<project>.tar or .zip instead"))
+            .arg(Arg::with_name("ninja").takes_value(false).required(false).long("ninja")
+                 .help("write target/<stage>/build.ninja instead of compiling and linking"))
+            .arg(Arg::with_name("emit").takes_value(true).multiple(true).required(false).long("emit")
+                 .possible_values(&["python", "lua"])
+                 .help("also write bindings of the exported api for these languages"))
+        )
+        .subcommand(SubCommand::with_name("watch").about("build or test again whenever a source of the project changes")
+            .arg(Arg::with_name("command").takes_value(true).required(false).index(1).possible_values(&["build", "test"]))
+            .arg(Arg::with_name("variant").takes_value(true).required(false).long("variant").short("s")),
+        )
+        .subcommand(SubCommand::with_name("clean").about("remove the target directory"))
+        .subcommand(lint_args(SubCommand::with_name("bench").about("benchmark tests/*.zz"))
+                    .arg(Arg::with_name("testname").takes_value(true).required(false).index(1)),
+        )
+        .subcommand(lint_args(SubCommand::with_name("test").about("execute tests/*.zz and test \"name\" { } blocks"))
+                    .arg(Arg::with_name("testname").takes_value(true).required(false).index(1)),
+        )
+        .subcommand(SubCommand::with_name("init").about("init zz project in current directory"))
+        .subcommand(SubCommand::with_name("update").about("resolve git dependencies again and rewrite zz.lock"))
+        .subcommand(SubCommand::with_name("install").about("build the libraries for release and install them with their headers")
+            .arg(Arg::with_name("prefix").takes_value(true).required(false).long("prefix")
+                 .help("defaults to $PREFIX or /usr/local"))
+            .arg(Arg::with_name("variant").takes_value(true).required(false).long("variant").short("s"))
+        )
+        .subcommand(
+            lint_args(SubCommand::with_name("run").about("build and run"))
+            .arg(Arg::with_name("release").takes_value(false).required(false).long("release"))
+            .arg(Arg::with_name("debug").takes_value(false).required(false).long("debug"))
+            .arg(Arg::with_name("variant").takes_value(true).required(false).long("variant").short("s"))
+            .arg(Arg::with_name("hot").takes_value(false).required(false).long("hot")
+                 .help("run the lib artifact in a shim that reloads it when the sources change"))
+            .arg(Arg::with_name("bin").takes_value(true).required(false).long("bin")
+                 .help("the exe artifact to run, if the project has more than one"))
+            .arg(Arg::with_name("args").takes_value(true).multiple(true).required(false).index(1)
+                 .help("passed on to the program, after --"))
+        )
+        .subcommand(SubCommand::with_name("fuzz").about("execute tests/*.zz with afl fuzzer")
+            .arg(Arg::with_name("testname").takes_value(true).required(false).index(1)),
+        )
+        .subcommand(SubCommand::with_name("lsp").about("language server over stdio")
+            .arg(Arg::with_name("index").takes_value(false).required(false).long("index").hidden(true))
+            .arg(Arg::with_name("variant").takes_value(true).required(false).long("variant").short("s")),
+        )
+        .subcommand(SubCommand::with_name("ast").about("dump the syntax tree of a source file")
+            .arg(Arg::with_name("file").takes_value(true).required(true).index(1))
+            .arg(Arg::with_name("format").takes_value(true).required(false).long("format").possible_values(&["json"])),
+        )
+        .subcommand(SubCommand::with_name("diff").about("compare two versions of a source file by declaration")
+            .arg(Arg::with_name("old").takes_value(true).required(true).index(1))
+            .arg(Arg::with_name("new").takes_value(true).required(true).index(2))
+            .arg(Arg::with_name("format").takes_value(true).required(false).long("format").possible_values(&["text", "json"])),
+        )
+        .subcommand(SubCommand::with_name("tokens").about("dump the tokens of a source file, including whitespace and comments")
+            .arg(Arg::with_name("file").takes_value(true).required(true).index(1))
+            .arg(Arg::with_name("format").takes_value(true).required(false).long("format").possible_values(&["json"])),
+        )
+        .subcommand(SubCommand::with_name("metadata").about("describe the project and its dependencies for other tools")
+            .arg(Arg::with_name("format").takes_value(true).required(false).long("format").possible_values(&["json"]))
+            .arg(Arg::with_name("variant").takes_value(true).required(false).long("variant").short("s")),
+        )
+        .subcommand(SubCommand::with_name("fmt").about("format source files")
+            .arg(Arg::with_name("check").takes_value(false).required(false).long("check"))
+            .arg(Arg::with_name("files").takes_value(true).multiple(true).required(false).index(1)),
+        )
+        .subcommand(SubCommand::with_name("fix").about("replace uses of deprecated declarations, and migrate to a new edition")
+            .arg(Arg::with_name("edition").takes_value(true).min_values(0).required(false).long("edition")
+                 .possible_values(&["2020", "2021"])
+                 .help("rewrite the sources for this edition, the newest one if not given, and set it in zz.toml")),
+        )
+        .subcommand(SubCommand::with_name("migrate").about("start a zz project around an existing c project, translating its headers")
+            .arg(Arg::with_name("dir").takes_value(true).required(false).index(1)),
+        )
+        .subcommand(SubCommand::with_name("package").about("write a reproducible source archive of the project with checksums to target/package"))
+        .subcommand(SubCommand::with_name("migrate-config").about("rewrite zz.toml in the layout of the current schema"))
+        .subcommand(SubCommand::with_name("completions").about("print shell completions for bash, zsh or fish")
+            .arg(Arg::with_name("shell").takes_value(true).required_unless("list").index(1).possible_values(&["bash", "zsh", "fish"]))
+            .arg(Arg::with_name("list").takes_value(true).required(false).long("list").hidden(true)
+                 .possible_values(zz::completions::LISTS)),
+        )
+        .subcommand(SubCommand::with_name("man").about("write man pages of zz and its commands")
+            .arg(Arg::with_name("dir").takes_value(true).required(false).index(1)
+                 .help("the current directory if not given")),
+        )
+        .subcommand(SubCommand::with_name("doctor").about("check the c compiler, sysroots and other tools a build needs"))
+        .subcommand(SubCommand::with_name("api").about("record the public api in api.toml, or compare it")
+            .arg(Arg::with_name("record").takes_value(false).required(false).long("record").conflicts_with("check")
+                 .help("write api.toml from the current sources"))
+            .arg(Arg::with_name("check").takes_value(false).required(false).long("check")
+                 .help("fail if the api differs from api.toml. this is the default")),
+        )
+        .subcommand(SubCommand::with_name("reduce").about("shrink a source file while a check on it still holds, for bug reports")
+            .arg(Arg::with_name("file").takes_value(true).required(true).index(1))
+            .arg(Arg::with_name("check").takes_value(true).required(true).long("check")
+                 .help("a shell command, exit code 0 means the file still shows the bug")),
+        )
+        .subcommand(SubCommand::with_name("bindgen").about("write bindings of the export api for another language")
+            .arg(Arg::with_name("lang").takes_value(true).required(true).long("lang").possible_values(&["rust"]))
+            .arg(Arg::with_name("safe").takes_value(false).required(false).long("safe")
+                 .help("also write a skeleton of safe wrappers for every fn"))
+            .arg(Arg::with_name("output").takes_value(true).required(false).long("output").short("o")
+                 .help("where to write the bindings, target/bindgen/<project>.rs if not given")),
+        )
+        .subcommand(SubCommand::with_name("grammar").about("export the grammar of zz as ebnf or railroad diagrams")
+            .arg(Arg::with_name("format").takes_value(true).required(false).long("format").possible_values(&["ebnf", "railroad"])
+                 .help("ebnf is printed, railroad is written to target/doc/grammar.html if not given -o"))
+            .arg(Arg::with_name("output").takes_value(true).required(false).long("output").short("o")
+                 .help("where to write the grammar")),
+        )
+        .subcommand(SubCommand::with_name("analyze").about("report what the modules of the project use")
+            .arg(Arg::with_name("features-used").takes_value(false).required(true).long("features-used")
+                 .help("language features like vla, varargs and unsafe, by module")),
+        )
+        .subcommand(SubCommand::with_name("eval").about("interpret an expression without a c compiler and print its value")
+            .arg(Arg::with_name("expr").takes_value(true).required(true).index(1))
+            .arg(Arg::with_name("module").takes_value(true).required(false).long("module").short("m")
+                 .help("the module of the project names are looked up in, main if not given")),
+        )
+        .subcommand(SubCommand::with_name("doc").about("render documentation of all modules to target/doc/")
+            .arg(Arg::with_name("format").takes_value(true).required(false).long("format").possible_values(&["html", "markdown"])),
+        )
+}
+
+/// -A, -W and -D, see warnings.rs
+fn lint_args<'a, 'b>(cmd: App<'a, 'b>) -> App<'a, 'b> {
+    let level = |name: &'static str, short: &'static str, help: &'static str| {
+        Arg::with_name(name).takes_value(true).multiple(true).number_of_values(1).required(false)
+            .long(name).short(short).value_name("LINT").help(help)
+    };
+    cmd
+        .arg(level("allow", "A", "allow a lint, or all of them with \"warnings\""))
+        .arg(level("warn",  "W", "make a lint a warning"))
+        .arg(level("deny",  "D", "make a lint an error, or all warnings with \"warnings\""))
+}
+
+fn set_lints(submatches: &clap::ArgMatches) {
+    let mut levels = Vec::new();
+    for (arg, level) in &[
+        ("allow",   zz::project::LintLevel::Allow),
+        ("warn",    zz::project::LintLevel::Warn),
+        ("deny",    zz::project::LintLevel::Deny),
+    ] {
+        if let (Some(names), Some(indices)) = (submatches.values_of(arg), submatches.indices_of(arg)) {
+            for (index, name) in indices.zip(names) {
+                levels.push((index, name, *level));
+            }
+        }
+    }
+    levels.sort_by_key(|(index, _, _)| *index);
+    for (_, name, level) in levels {
+        if let Err(e) = zz::warnings::set(name, level) {
+            error!("{}", e);
+            std::process::exit(9);
+        }
+    }
+}
@@ -1,10 +1,11 @@
-use super::project::{Config, Artifact};
+use super::project::{Config, Artifact, Target};
 use std::hash::{Hasher};
 use metrohash::{MetroHash128};
 use std::path::Path;
 use std::path::PathBuf;
 use std::collections::HashSet;
 use std::process::Command;
+use std::io::Write;
 use pbr;
 use std::sync::atomic::{AtomicBool, Ordering};
 use super::emitter_js;
@@ -22,6 +23,12 @@ pub struct Stage {
     pub asan:       bool,
     pub fuzz:       bool,
     pub pic:        bool,
+    // compile test "name" { } declarations, see unittest.rs
+    pub tests:      bool,
+    pub wasm:       bool,
+    // name of a [target.name] profile in zz.toml
+    pub target:     Option<String>,
+    pub defines:    Vec<String>,
 }
 
 impl Stage {
@@ -34,6 +41,10 @@ impl Stage {
             asan:       false,
             fuzz:       false,
             pic:        !cfg!(windows),
+            tests:      false,
+            wasm:       false,
+            target:     None,
+            defines:    Vec::new(),
         }
     }
     pub fn test() -> Self {
@@ -45,6 +56,10 @@ impl Stage {
             asan:       true,
             fuzz:       false,
             pic:        !cfg!(windows),
+            tests:      true,
+            wasm:       false,
+            target:     None,
+            defines:    Vec::new(),
         }
     }
     pub fn debug() -> Self {
@@ -56,6 +71,10 @@ impl Stage {
             asan:       false,
             fuzz:       false,
             pic:        !cfg!(windows),
+            tests:      false,
+            wasm:       false,
+            target:     None,
+            defines:    Vec::new(),
         }
     }
     pub fn fuzz() -> Self {
@@ -67,10 +86,55 @@ impl Stage {
             asan:       true,
             fuzz:       true,
             pic:        !cfg!(windows),
+            tests:      false,
+            wasm:       false,
+            target:     None,
+            defines:    Vec::new(),
         }
     }
 }
 
+impl Stage {
+    /// the same stage, compiled to webassembly into its own target directory
+    pub fn wasm32(mut self) -> Self {
+        self.name   = format!("{}-wasm32", self.name);
+        self.wasm   = true;
+        // there are no sanitizers, and nothing to load position independent code
+        self.asan   = false;
+        self.pic    = false;
+        self
+    }
+}
+
+impl Stage {
+    /// the same stage, cross compiled with a target profile into its own target directory
+    pub fn target(mut self, name: &str) -> Self {
+        self.name   = format!("{}-{}", self.name, name);
+        self.target = Some(name.to_string());
+        self
+    }
+
+    /// the target profile of this stage, if any
+    pub fn profile<'a>(&self, config: &'a Config) -> Option<&'a Target> {
+        let name = self.target.as_ref()?;
+        match config.target.get(name) {
+            Some(t) => Some(t),
+            None => {
+                error!("target {} not defined in zz.toml", name);
+                std::process::exit(9);
+            }
+        }
+    }
+
+    /// take over the defines of the target profile, so #if def() can see them
+    pub fn resolve(mut self, config: &Config) -> Self {
+        if let Some(t) = self.profile(config) {
+            self.defines = t.defines.clone();
+        }
+        self
+    }
+}
+
 impl std::fmt::Display for  Stage {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.name)
@@ -81,6 +145,8 @@ impl std::fmt::Display for  Stage {
 pub struct Step {
     pub source: PathBuf,
     pub args:   Vec<String>,
+    /// whether source is c emitted from zz, rather than a c file of the project
+    pub emitted: bool,
 
     pub deps:   HashSet<PathBuf>,
     pub outp:   String,
@@ -96,6 +162,7 @@ pub struct Make {
     pub lobjs:      Vec<String>,
     pub variant:    String,
     pub stage:      Stage,
+    pub exports:    Vec<String>,
 }
 
 impl Make {
@@ -118,23 +185,54 @@ impl Make {
                 .unwrap_or("".to_string()).split(" ")
                 .map(|s|s.to_string()).collect();
 
-        let mut cc = std::env::var("TARGET_CC")
-            .or(std::env::var("CC"))
-            .unwrap_or("clang".to_string());
-
         let mut cxx = false;
-        if let Some(std) = config.project.std {
+        if let Some(std) = &config.project.std {
             cflags.push(format!("-std={}", std));
-            if std.contains("c++") {
-                cxx = true;
-                cc = std::env::var("TARGET_CXX")
-                    .or(std::env::var("CXX"))
-                    .unwrap_or("clang++".to_string());
+            cxx = std.contains("c++");
+        }
+        let mut cc = env_cc(cxx);
+        let mut ar = env_ar();
+
+        let profile = stage.profile(&config).cloned();
+        if let Some(profile) = &profile {
+            if let Some(v) = if cxx { &profile.cxx } else { &profile.cc } {
+                cc = v.clone();
+            }
+            if let Some(v) = &profile.ar {
+                ar = v.clone();
+            }
+            if let Some(sysroot) = &profile.sysroot {
+                cflags.push(format!("--sysroot={}", sysroot));
+                lflags.push(format!("--sysroot={}", sysroot));
+            }
+            for define in &profile.defines {
+                cflags.push(format!("-D{}", define));
+            }
+        }
+
+        // emscripten brings a libc for the browser. without it, plain clang can only build freestanding code,
+        // or against a wasi sysroot
+        if stage.wasm {
+            if let Ok(emcc) = std::env::var("EMCC").or(which::which("emcc").map(|p|p.to_string_lossy().to_string())) {
+                cc = emcc;
+                ar = "emar".to_string();
+            } else {
+                match std::env::var("WASI_SYSROOT") {
+                    Ok(sysroot) => {
+                        cflags.push("--target=wasm32-wasi".into());
+                        lflags.push("--target=wasm32-wasi".into());
+                        cflags.push(format!("--sysroot={}", sysroot));
+                        lflags.push(format!("--sysroot={}", sysroot));
+                    }
+                    Err(_) => {
+                        cflags.push("--target=wasm32".into());
+                        lflags.push("--target=wasm32".into());
+                        cflags.push("-ffreestanding".into());
+                        lflags.push("-nostdlib".into());
+                    }
+                }
             }
         }
-        let ar = std::env::var("TARGET_AR")
-            .or(std::env::var("AR"))
-            .unwrap_or("ar".to_string());
 
 
         if stage.fuzz {
@@ -144,6 +242,7 @@ impl Make {
 
         let mut cincludes   = config.project.cincludes.clone();
         let mut pkgconfig   = config.project.pkgconfig.clone();
+        let mut libs        = config.project.libs.clone();
         let mut cobjects    = std::mem::replace(&mut config.project.cobjects, Vec::new());
         let mut user_cflags = config.project.cflags.clone();
         let mut user_lflags = config.project.lflags.clone();
@@ -155,40 +254,31 @@ impl Make {
             }
             cincludes.extend(feature.cincludes.clone());
             pkgconfig.extend(feature.pkgconfig.clone());
+            libs.extend(feature.libs.clone());
             cobjects.extend(feature.cobjects.clone());
             user_cflags.extend(feature.cflags.clone());
             user_lflags.extend(feature.lflags.clone());
         }
+        let sysroot = profile.as_ref().and_then(|p| p.sysroot.clone());
+        if let Some(profile) = profile {
+            user_cflags.extend(profile.cflags);
+            user_lflags.extend(profile.lflags);
+        }
 
         for cinc in cincludes{
             cflags.push("-I".into());
             cflags.push(cinc);
         }
 
-        for pkg in &pkgconfig {
-            let flags = Command::new("pkg-config")
-                .arg("--cflags")
-                .arg(pkg)
-                .output()
-                .expect(&format!("failed to execute pkg-config --cflags {}", pkg));
-
-            let flags = String::from_utf8_lossy(&flags.stdout);
-            let flags = flags.split_whitespace();
-            for flag in flags {
-                cflags.push(flag.to_string());
-            }
-
-            let flags = Command::new("pkg-config")
-                .arg("--libs")
-                .arg(pkg)
-                .output()
-                .expect(&format!("failed to execute pkg-config --lflags {}", pkg));
+        // the same package or library may come in through several dependencies
+        let mut seen = HashSet::new();
+        pkgconfig.retain(|p| seen.insert(p.clone()));
+        let mut seen = HashSet::new();
+        libs.retain(|l| seen.insert(l.clone()));
 
-            let flags = String::from_utf8_lossy(&flags.stdout);
-            let flags = flags.split_whitespace();
-            for flag in flags {
-                lflags.push(flag.to_string());
-            }
+        for pkg in &pkgconfig {
+            cflags.extend(pkg_config(pkg, "--cflags", sysroot.as_deref()));
+            lflags.extend(pkg_config(pkg, "--libs", sysroot.as_deref()));
         }
         if stage.pic {
             cflags.push("-fPIC".into());
@@ -221,7 +311,9 @@ impl Make {
         if stage.debug {
             cflags.push("-g".into());
             lflags.push("-g".into());
-            cflags.push("-fstack-protector-strong".into());
+            if !stage.wasm {
+                cflags.push("-fstack-protector-strong".into());
+            }
         }
 
         if stage.asan {
@@ -249,6 +341,9 @@ impl Make {
 
         cflags.extend(user_cflags);
         lflags.extend(user_lflags);
+        for lib in libs {
+            lflags.push(format!("-l{}", lib));
+        }
 
         let mut m = Make {
             variant: variant.to_string(),
@@ -261,6 +356,7 @@ impl Make {
             lobjs: Vec::new(),
             cflags,
             steps: Vec::new(),
+            exports: Vec::new(),
         };
 
         for c in cobjects {
@@ -293,6 +389,7 @@ impl Make {
         self.steps.push(Step{
             source: inp.into(),
             args,
+            emitted: false,
             deps: sources,
             outp: outp.clone(),
         });
@@ -331,9 +428,12 @@ impl Make {
         let outp = format!("./target/{}/zz/{}_{:x}{:x}.o", self.stage, cf.name.0.join("_"), hash.0, hash.1);
         args.push(outp.clone());
 
+        self.exports.extend(cf.exports.iter().cloned());
+
         self.steps.push(Step{
             source: Path::new(&cf.filepath).into(),
             args,
+            emitted: true,
             deps: cf.sources.clone(),
             outp: outp.clone(),
         });
@@ -352,80 +452,55 @@ impl Make {
 
         let pb = Arc::new(Mutex::new(pbr::ProgressBar::new(self.steps.len() as u64)));
         pb.lock().unwrap().show_speed = false;
-        self.steps.par_iter().for_each(|step|{
+        // compilers run concurrently, their output is printed in order of the steps afterwards
+        let outputs : Vec<Option<std::process::Output>> = self.steps.par_iter().map(|step|{
             if ABORT.load(Ordering::Relaxed) {
-                return;
+                return None;
             };
             pb.lock().unwrap().message(&format!("{} {:?} ", self.cc, step.source));
 
+            let mut output = None;
             if step.is_dirty() {
                 debug!("{} {:?}", self.cc, step.args);
-                let status = Command::new(&self.cc)
+                let out = Command::new(&self.cc)
                     .env("AFL_USE_ASAN", "1")
                     .args(&step.args)
-                    .status()
-                    .expect("failed to execute cc");
-                if !status.success() {
-                    error!("{} {}", self.cc, step.args.join(" "));
+                    .output()
+                    .unwrap_or_else(|e| cannot_run(&self.cc, &e));
+                if out.status.success() {
+                    step.built();
+                } else {
                     ABORT.store(true, Ordering::Relaxed);
                 }
+                output = Some(out);
             }
             pb.lock().unwrap().inc();
-        });
+            output
+        }).collect();
+
+        for (step, output) in self.steps.iter().zip(outputs) {
+            if let Some(output) = output {
+                std::io::stdout().write_all(&output.stdout).unwrap();
+                std::io::stderr().write_all(&output.stderr).unwrap();
+                if !output.status.success() {
+                    error!("{} {}", self.cc, step.args.join(" "));
+                    ABORT.store(true, Ordering::Relaxed);
+                }
+            }
+        }
 
         if ABORT.load(Ordering::Relaxed) {
             pb.lock().unwrap().finish_print(&format!("failed [{:?}] {}", self.artifact.typ, self.artifact.name));
             std::process::exit(11);
         }
 
-        let mut cmd     = self.cc.clone();
-        let mut args    = Vec::new();
-
-        match self.artifact.typ {
-            super::project::ArtifactType::Staticlib => {
-                std::fs::create_dir_all(format!("./target/{}/lib/", self.stage)).expect("create target dir");
-                cmd = self.ar.clone();
-                args = vec![
-                    "rcs".to_string(),
-                    format!("./target/{}/lib/lib{}.a", self.stage, self.artifact.name)
-                ];
-                args.extend_from_slice(&self.lobjs);
-
-                if BUILD_RS.load(Ordering::Relaxed) {
-                    println!("\n\ncargo:rustc-link-lib=static={}\n\n", self.artifact.name);
-                    println!("\n\ncargo:rustc-link-search=native={}/target/{}\n\n", std::env::current_dir().unwrap().display(), self.stage);
-                }
-
-            },
-            super::project::ArtifactType::Lib => {
-                std::fs::create_dir_all(format!("./target/{}/lib/", self.stage)).expect("create target dir");
-                args.extend_from_slice(&self.lobjs);
-                args.extend_from_slice(&self.lflags);
-                args.push("-shared".into());
-                args.push("-o".into());
-                args.push(format!("./target/{}/lib/lib{}.so", self.stage, self.artifact.name));
-            },
-            super::project::ArtifactType::Exe => {
-                std::fs::create_dir_all(format!("./target/{}/bin/", self.stage)).expect("create target dir");
-                args.extend_from_slice(&self.lobjs);
-                args.extend_from_slice(&self.lflags);
-                args.push("-o".into());
-                args.push(format!("./target/{}/bin/{}", self.stage, self.artifact.name));
-            }
-            super::project::ArtifactType::Test  => {
-                std::fs::create_dir_all(format!("./target/{}/bin/", self.stage)).expect("create target dir");
-                args.extend_from_slice(&self.lobjs);
-                args.extend_from_slice(&self.lflags);
-                args.push("-o".into());
-                args.push(format!("./target/{}/bin/{}", self.stage, self.artifact.name));
-            }
-            super::project::ArtifactType::Header  => {
-                panic!("cannot link header yet");
-            }
-            super::project::ArtifactType::NodeModule => {
-                unreachable!();
+        let (cmd, args, _) = match self.link_command() {
+            Some(v) => v,
+            None => {
+                pb.lock().unwrap().finish_print(&format!("skipped [{:?}] {}", self.artifact.typ, self.artifact.name));
+                return;
             }
-        }
+        };
         self.lflags.push("-fvisibility=hidden".into());
 
         pb.lock().unwrap().message(&format!("ld [{:?}] {} ", self.artifact.typ, self.artifact.name));
@@ -437,7 +512,7 @@ impl Make {
             .env("AFL_USE_ASAN", "1")
             .args(&args)
             .status()
-            .expect("failed to execute linker");
+            .unwrap_or_else(|e| cannot_run(&cmd, &e));
         if !status.success() {
             std::process::exit(status.code().unwrap_or(3));
         }
@@ -445,25 +520,253 @@ impl Make {
         pb.lock().unwrap().finish_print(&format!("finished [{:?}] {}", self.artifact.typ, self.artifact.name));
         println!("");
     }
+
+    /// the linker or archiver, its arguments and the file it writes. none if the artifact can't be linked for the target
+    pub fn link_command(&self) -> Option<(String, Vec<String>, String)> {
+        let mut cmd     = self.cc.clone();
+        let mut args    = Vec::new();
+
+        if self.stage.wasm && self.artifact.typ != super::project::ArtifactType::Staticlib {
+            if !self.link_wasm(&mut args) {
+                return None;
+            }
+        } else {
+            match self.artifact.typ {
+                super::project::ArtifactType::Staticlib => {
+                    std::fs::create_dir_all(format!("./target/{}/lib/", self.stage)).expect("create target dir");
+                    cmd = self.ar.clone();
+                    args = vec![
+                        "rcs".to_string(),
+                        format!("./target/{}/lib/lib{}.a", self.stage, self.artifact.name)
+                    ];
+                    args.extend_from_slice(&self.lobjs);
+
+                    if BUILD_RS.load(Ordering::Relaxed) {
+                        println!("\n\ncargo:rustc-link-lib=static={}\n\n", self.artifact.name);
+                        println!("\n\ncargo:rustc-link-search=native={}/target/{}\n\n", std::env::current_dir().unwrap().display(), self.stage);
+                    }
+
+                },
+                super::project::ArtifactType::Lib => {
+                    std::fs::create_dir_all(format!("./target/{}/lib/", self.stage)).expect("create target dir");
+                    args.extend_from_slice(&self.lobjs);
+                    args.extend_from_slice(&self.lflags);
+                    args.push(self.version_script());
+                    args.push("-shared".into());
+                    args.push("-o".into());
+                    args.push(format!("./target/{}/lib/{}", self.stage, shared_lib(&self.artifact.name, &self.machine())));
+                },
+                super::project::ArtifactType::Exe => {
+                    std::fs::create_dir_all(format!("./target/{}/bin/", self.stage)).expect("create target dir");
+                    args.extend_from_slice(&self.lobjs);
+                    args.extend_from_slice(&self.lflags);
+                    args.push("-o".into());
+                    args.push(format!("./target/{}/bin/{}", self.stage, self.artifact.name));
+                }
+                super::project::ArtifactType::Test  => {
+                    std::fs::create_dir_all(format!("./target/{}/bin/", self.stage)).expect("create target dir");
+                    args.extend_from_slice(&self.lobjs);
+                    args.extend_from_slice(&self.lflags);
+                    args.push("-o".into());
+                    args.push(format!("./target/{}/bin/{}", self.stage, self.artifact.name));
+                }
+                super::project::ArtifactType::Header  => {
+                    panic!("cannot link header yet");
+                }
+                super::project::ArtifactType::NodeModule => {
+                    unreachable!();
+                }
+            }
+        }
+        let out = if self.artifact.typ == super::project::ArtifactType::Staticlib {
+            args[1].clone()
+        } else {
+            args.last().unwrap().clone()
+        };
+        Some((cmd, args, out))
+    }
+
+    /// link arguments for webassembly. exported functions carry export_name attributes,
+    /// so they end up as wasm exports without a list. returns false if the artifact can't be built for wasm
+    fn link_wasm(&self, args: &mut Vec<String>) -> bool {
+        let emcc = self.cc.ends_with("emcc");
+        let out = match self.artifact.typ {
+            super::project::ArtifactType::Lib => {
+                std::fs::create_dir_all(format!("./target/{}/lib/", self.stage)).expect("create target dir");
+                args.push(if emcc { "--no-entry".into() } else { "-Wl,--no-entry".into() });
+                format!("./target/{}/lib/{}.wasm", self.stage, self.artifact.name)
+            }
+            super::project::ArtifactType::Exe => {
+                std::fs::create_dir_all(format!("./target/{}/bin/", self.stage)).expect("create target dir");
+                // emscripten generates the js loader next to the wasm module
+                if emcc {
+                    format!("./target/{}/bin/{}.js", self.stage, self.artifact.name)
+                } else {
+                    format!("./target/{}/bin/{}.wasm", self.stage, self.artifact.name)
+                }
+            }
+            _ => {
+                warn!("{:?} artifact {} cannot be built for wasm32", self.artifact.typ, self.artifact.name);
+                return false;
+            }
+        };
+        args.extend_from_slice(&self.lobjs);
+        args.extend_from_slice(&self.lflags);
+        args.push("-o".into());
+        args.push(out);
+        true
+    }
+
+    /// the triple the compiler builds for. not the host zz runs on, when cross compiling
+    fn machine(&self) -> String {
+        let target = self.cflags.iter().filter(|f| f.starts_with("--target="));
+        match Command::new(&self.cc).args(target).arg("-dumpmachine").output() {
+            Ok(out) if out.status.success() => String::from_utf8_lossy(&out.stdout).trim().to_string(),
+            _ => host().to_string(),
+        }
+    }
+
+    fn darwin(&self) -> bool {
+        let machine = self.machine();
+        machine.contains("apple") || machine.contains("darwin")
+    }
+
+    /// write the export set of a shared library as linker version script, and as exported symbols list for macos.
+    /// returns the linker flag that restricts the library to those symbols
+    fn version_script(&self) -> String {
+        let mut exports = self.exports.clone();
+        exports.sort();
+        exports.dedup();
+
+        let base = format!("./target/{}/lib/lib{}", self.stage, self.artifact.name);
+
+        let mut map = match &self.artifact.symbol_version {
+            Some(v) => format!("{} {{\n", v),
+            None    => "{\n".to_string(),
+        };
+        map.push_str("    global:\n");
+        for export in &exports {
+            map.push_str(&format!("        {};\n", export));
+        }
+        map.push_str("    local:\n        *;\n};\n");
+        std::fs::write(format!("{}.map", base), map).expect(&format!("cannot write {}.map", base));
+
+        // the darwin linker has no version nodes, just a list of mangled names
+        let mut list = String::new();
+        for export in &exports {
+            list.push_str(&format!("_{}\n", export));
+        }
+        std::fs::write(format!("{}.exports", base), list).expect(&format!("cannot write {}.exports", base));
+
+        if self.darwin() {
+            format!("-Wl,-exported_symbols_list,{}.exports", base)
+        } else {
+            format!("-Wl,--version-script={}.map", base)
+        }
+    }
 }
 
 impl Step {
+    /// hash of everything the object file is built from
+    fn hash(&self) -> String {
+        let mut deps : Vec<&PathBuf> = self.deps.iter().collect();
+        deps.sort();
+        let mut b = self.args.join(" ").into_bytes();
+        for source in std::iter::once(&self.source).chain(deps) {
+            b.extend(super::cache::hash_file(source).unwrap_or_default().as_bytes());
+        }
+        super::cache::hash_bytes(&b)
+    }
+
     fn is_dirty(&self) -> bool {
-        let itarget = match std::fs::metadata(&self.outp) {
-            Ok(v)  => v,
-            Err(_) => return true,
-        };
-        let itarget = itarget.modified().expect(&format!("cannot stat {}", self.outp));
+        if !Path::new(&self.outp).exists() {
+            return true;
+        }
+        match std::fs::read_to_string(format!("{}.hash", self.outp)) {
+            Ok(v) => v != self.hash(),
+            Err(_) => true,
+        }
+    }
 
-        for source in &self.deps {
-            let isource = std::fs::metadata(source).expect(&format!("cannot stat {:?}", source));
+    fn built(&self) {
+        let p = format!("{}.hash", self.outp);
+        std::fs::write(&p, self.hash()).expect(&format!("cannot write {}", p));
+    }
+}
 
-            let isource = isource.modified().expect(&format!("cannot stat {:?}", source));
+/// file name of the shared library artifact name for a machine triple: lib<name>.dylib on darwin, <name>.dll on windows, else lib<name>.so
+pub fn shared_lib(name: &str, machine: &str) -> String {
+    if machine.contains("apple") || machine.contains("darwin") {
+        format!("lib{}.dylib", name)
+    } else if machine.contains("windows") || machine.contains("mingw") || machine.contains("cygwin") {
+        format!("{}.dll", name)
+    } else {
+        format!("lib{}.so", name)
+    }
+}
 
-            if isource > itarget {
-                return true;
-            }
+/// enough of the triple of the machine zz runs on for shared_lib
+pub fn host() -> &'static str {
+    if cfg!(target_os = "macos") {
+        "apple-darwin"
+    } else if cfg!(windows) {
+        "windows"
+    } else {
+        "linux"
+    }
+}
+
+/// the c compiler from the environment, or the c++ compiler if cxx
+pub fn env_cc(cxx: bool) -> String {
+    if cxx {
+        std::env::var("TARGET_CXX").or(std::env::var("CXX")).unwrap_or("clang++".to_string())
+    } else {
+        std::env::var("TARGET_CC").or(std::env::var("CC")).unwrap_or("clang".to_string())
+    }
+}
+
+/// a tool of the build that isn't there is a mistake of the setup, not of zz
+pub fn cannot_run(tool: &str, e: &std::io::Error) -> ! {
+    let (var, target, profile) = if tool == env_ar() {
+        ("AR", "TARGET_AR", "ar")
+    } else if tool == env_cc(true) {
+        ("CXX", "TARGET_CXX", "cxx")
+    } else {
+        ("CC", "TARGET_CC", "cc")
+    };
+    error!("cannot run {}: {}", tool, e);
+    error!("install it, or set {} ({} when cross compiling) or {} of the target profile in zz.toml to the one to use", var, target, profile);
+    std::process::exit(9);
+}
+
+/// the archiver from the environment
+pub fn env_ar() -> String {
+    std::env::var("TARGET_AR").or(std::env::var("AR")).unwrap_or("ar".to_string())
+}
+
+/// flags for a package from pkg-config, or $PKG_CONFIG if set.
+/// a sysroot from the target profile is where pkg-config looks for the package, unless PKG_CONFIG_SYSROOT_DIR says otherwise
+fn pkg_config(pkg: &str, what: &str, sysroot: Option<&str>) -> Vec<String> {
+    let bin = std::env::var("PKG_CONFIG").unwrap_or("pkg-config".to_string());
+    let mut cmd = Command::new(&bin);
+    cmd.arg(what).arg(pkg);
+    if let Some(sysroot) = sysroot {
+        if std::env::var("PKG_CONFIG_SYSROOT_DIR").is_err() {
+            cmd.env("PKG_CONFIG_SYSROOT_DIR", sysroot);
         }
-        return false;
     }
+
+    let out = match cmd.output() {
+        Ok(v) => v,
+        Err(e) => {
+            error!("pkgconfig = [\"{}\"] in zz.toml needs {}: {}", pkg, bin, e);
+            std::process::exit(9);
+        }
+    };
+    if !out.status.success() {
+        error!("{} {} {} failed\n{}", bin, what, pkg, String::from_utf8_lossy(&out.stderr).trim());
+        std::process::exit(9);
+    }
+
+    String::from_utf8_lossy(&out.stdout).split_whitespace().map(|s|s.to_string()).collect()
 }
@@ -1,6 +1,6 @@
 use super::ast;
 use super::flatten;
-use super::parser::{emit_error, emit_warn, emit_debug};
+use super::parser::{self, emit_error, emit_warn, emit_debug};
 use std::collections::HashMap;
 use super::name::Name;
 use super::ast::Tags;
@@ -814,7 +814,7 @@ impl Stack {
                 }
             }
 
-            ast::Expression::Literal { loc, v } => {
+            ast::Expression::Literal { loc, v, .. } => {
                 let temp_ptr = self.local(None, Name::from(
                         &format!("literal {}", self.storage.len())),
                         loc.clone(),
@@ -842,10 +842,7 @@ impl Stack {
                                         Tags::new());
 
                                 let lit = format!("{}", s.len());
-                                *expr = ast::Expression::Literal{
-                                    loc: callloc.clone(),
-                                    v:   lit.clone(),
-                                };
+                                *expr = ast::Expression::literal(callloc.clone(), lit.clone());
                                 self.write(temp_ptr, Value::Literal(lit.clone()), &expr.loc());
                                 return Ok(temp_ptr);
                             },
@@ -884,10 +881,7 @@ impl Stack {
                 for i in 0..args.len() {
                     if let Some(cs) = args[i].tags.get("callsite_macro") {
                         let (v, loc) = cs.iter().next().unwrap();
-                        let genarg = Box::new(ast::Expression::Literal{
-                            loc: loc.clone(),
-                            v:   v.clone(),
-                        });
+                        let genarg = Box::new(ast::Expression::literal(loc.clone(), v.clone()));
                         callargs.push(genarg);
                     } else if let Some(_) = args[i].tags.get("tail") {
                         let mut prev = callargs.get_mut(i-1).expect("ICE: tail tag without previous arg");
@@ -896,6 +890,7 @@ impl Stack {
                             into: args[i-1].typed.clone(),
                             expr: prev.clone(),
                             loc:  args[i].loc.clone(),
+                            tags: ast::Tags::new(),
                         });
 
                         match &self.storage[callptr].typ {
@@ -913,10 +908,7 @@ impl Stack {
                                         ]));
                                     },
                                     ast::Tail::Static(v,loc) => {
-                                        let genarg = Box::new(ast::Expression::Literal{
-                                            loc: loc.clone(),
-                                            v:   format!("{}", v),
-                                        });
+                                        let genarg = Box::new(ast::Expression::literal(loc.clone(), format!("{}", v)));
                                         callargs.push(genarg);
                                     }
                                 }
@@ -984,7 +976,7 @@ impl Stack {
                     }
                     None => {
                         let temp_ptr = self.local(None, Name::from(
-                                &format!("void function call return {} at {:?}", fname, exprloc.span.start_pos().line_col())),
+                                &format!("void function call return {} at {:?}", fname, exprloc.line_col())),
                                 exprloc.clone(),
                                 Tags::new());
                         self.write(temp_ptr, Value::Void, &expr.loc());
@@ -1381,10 +1373,7 @@ pub fn check(md: &mut flatten::Module) {
                         Ok(v) => {
                             match &stack.storage[v].value {
                                 Value::Literal(s) => {
-                                    *expr = ast::Expression::Literal{
-                                        loc: expr.loc().clone(),
-                                        v: s.clone(),
-                                    };
+                                    *expr = ast::Expression::literal(expr.loc().clone(), s.clone());
                                 },
                                 _ => {
                                     emit_error(format!("unable to evaluate testcase field {} at compile time", name), &[
@@ -1418,7 +1407,7 @@ pub fn check(md: &mut flatten::Module) {
                 let ptr = stack.local(None, localname.clone(), local.loc.clone(), Tags::new());
                 stack.write(ptr, Value::Untrackable("macro".to_string()), &local.loc);
             },
-            ast::Def::Const{typed, expr} => {
+            ast::Def::Const{typed, expr, ..} => {
                 let localname = Name::from(&local.name);
                 let ptr = stack.local(Some(typed.clone()), localname.clone(), local.loc.clone(), Tags::new());
                 match stack.check_expr(expr) {
@@ -1431,7 +1420,7 @@ pub fn check(md: &mut flatten::Module) {
                 }
 
             },
-            ast::Def::Static{typed, expr, storage, tags, array} => {
+            ast::Def::Static{typed, expr, storage, tags, array, ..} => {
                 let localname = Name::from(&local.name);
                 let ptr = stack.local(Some(typed.clone()), localname.clone(), local.loc.clone(), tags.clone());
                 stack.write(ptr, Value::Untrackable("static decl".to_string()), &local.loc);
@@ -1535,7 +1524,7 @@ pub fn check(md: &mut flatten::Module) {
         }
     }
     if ABORT.load(Ordering::Relaxed) {
-        std::process::exit(9);
+        parser::abort();
     }
 }
 
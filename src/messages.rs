@@ -0,0 +1,672 @@
+/// diagnostic texts by code, so they can be shipped translated
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+#[macro_export]
+macro_rules! msg {
+    ($code:expr) => {
+        $crate::messages::format($code, &[])
+    };
+    ($code:expr, $($name:ident = $value:expr),+ $(,)?) => {
+        $crate::messages::format($code, &[$((stringify!($name), $value.to_string())),+])
+    };
+}
+
+pub const ENGLISH : &[(&str, &str)] = &[
+    // abs.rs
+    ("conflicting_local_name",          "conflicting local name '{name}'"),
+    ("conflicting_local_name.here",     "declared here"),
+    ("conflicting_local_name.previous", "also declared here"),
+    ("possibly_undefined_name",         "possibly undefined name '{name}'"),
+    ("possibly_undefined_name.note",    "cannot use :: notation to reference names not tracked by zz"),
+    ("undefined_name",                  "undefined name '{name}'"),
+    ("undefined_name.note",             "used in this scope"),
+    ("not_a_module_member",             "resolving '{name}' as member is not possible"),
+    ("not_a_module_member.note",        "'{name}' is not a module"),
+    ("module_as_type",                  "cannot use module '{name}' as a type"),
+    ("module_as_type.suggestion",       "if you wanted to import '{name}' as a type, use ::{{{name}}} here"),
+    ("module_not_found",                "cannot find module '{name}'"),
+    ("imported_here",                   "imported here"),
+    ("unresolved_relative_name",        "relative name {name} not resolved. likely due to previous error"),
+    ("unresolved_relative_name.note",   "this type is unresolved"),
+    ("module_not_found_in_abs",         "cannot find module '{name}' during abs of module '{module}'"),
+    ("module_not_found_in_abs.note",    "expected to be in scope here"),
+    ("interface_no_backend",            "interface module {name} has no backend"),
+    ("interface_no_backend.note",       "select one in zz.toml, like [interfaces] {local} = \"module\""),
+    ("private_type",                    "the type '{name}' in '{module}' is private"),
+    ("private_type.note",               "cannot use private type"),
+    ("private_type.suggestion",         "add 'pub' to share this type"),
+    ("package_private",                 "'{name}' in '{module}' is pub(package), only the modules of {package} can use it"),
+    ("package_private.note",            "used from {package}"),
+    ("package_private.suggestion",      "make it pub to share it with other packages"),
+    ("unexported_type",                 "the type '{name}' in '{module}' is not exported"),
+    ("unexported_type.note",            "cannot use an unexported type here"),
+    ("unexported_type.suggestion",      "suggestion: export this type"),
+    ("module_does_not_contain",         "module '{module}' does not contain '{name}'"),
+    ("import_cycle",                    "'{name}' is imported in a cycle: {chain}"),
+    ("import_cycle.note",               "{module} imports it from {from}"),
+    ("path_resolve_error",              "path resolve error"),
+    ("nested_tail_not_last",            "nested tail must be last field"),
+    ("nested_tail_not_last.note",       "field {name} is non static tail, but not the last field"),
+    ("conflicting_import_modes",        "conflicting import modes"),
+    ("conflicting_import_modes.inlined",        "inlined here"),
+    ("conflicting_import_modes.included",       "included here"),
+    ("conflicting_import_modes.prev_inlined",   "previously inlined here"),
+    ("conflicting_import_modes.prev_included",  "previously included here"),
+    ("inline_not_relative",             "cannot inline non-relative include"),
+    ("inline_not_relative.note",        "'{name}' is not a relative include"),
+    ("bitfield_type",                   "bitfield {name} of type {t}"),
+    ("bitfield_type.note",              "bitfields must be an integer type or bool"),
+    ("bitfield_width",                  "bitfield {name} is {bits} bits wide"),
+    ("bitfield_width.note",             "{t} only has {width} bits"),
+    ("bitfield_width.zero",             "the width must be at least 1"),
+    ("missing_tail_binding",            "missing tail binding"),
+    ("missing_tail_binding.dynamic",    "+ without a name makes no sense in this context"),
+    ("missing_tail_binding.static",     "+ with static size makes no sense in this context"),
+
+    // pp.rs
+    ("pp_argument_count",               "wrong number of arguments to {name}. expected {expected}"),
+    ("pp_feature_not_string",           "argument to feature must be a string"),
+    ("pp_undefined_feature",            "undefined feature defaults to false"),
+    ("pp_undefined_feature.note",       "avoid this warning by defining it explicitly in zz.toml"),
+    ("pp_undefined_def",                "undefined def defaults to false"),
+    ("pp_undefined_def.note",           "avoid this warning by defining it explicitly"),
+    ("pp_invalid_target_value",         "invalid attribute value defaults to false"),
+    ("pp_target_not_string",            "target attribute needs to be a string"),
+    ("pp_undefined_target",             "undefined target attribute defaults to false"),
+    ("pp_undefined_target.note",        "{name} is not a known target attribute"),
+    ("pp_invalid_target",               "invalid target attribute defaults to false"),
+    ("pp_unknown_function",             "function '{name}' not available in preprocessor directive"),
+    ("pp_unsupported_expression",       "{name} expression cannot (yet) be used in preprocessor directive"),
+    ("pp_not_boolean",                  "preprocessor directive must evaluate to boolean"),
+    ("pp_not_boolean.note",             "this expression = '{value}'"),
+    ("pp_missing_if",                   "missing preceeding #if directive"),
+
+    // parser.rs
+    ("literal_unknown_escape",          "unknown escape sequence {escape}"),
+    ("literal_unknown_escape.note",     "valid escapes are \\n \\r \\t \\a \\b \\f \\v \\? \\\\ \\\" \\' \\xHH and octal \\OOO"),
+    ("literal_hex_escape",              "\\x must be followed by one or two hex digits"),
+    ("literal_octal_escape",            "octal escape {escape} does not fit into a byte"),
+    ("literal_escape.here",             "in this literal"),
+    ("literal_char_length",             "character literal must be exactly one byte, but this is {len}"),
+    ("literal_char_length.note",        "use a string literal for more than one byte"),
+    ("literal_out_of_range",            "literal {value} does not fit into {t}"),
+    ("literal_out_of_range.note",       "out of range for its suffix"),
+    ("literal_octal_digit",             "literal {value} is octal because of its leading 0, and octal has no digit {digit}"),
+    ("literal_octal_digit.note",        "leave out the leading 0 for a decimal number"),
+    ("assign_in_condition",             "assignment used as condition"),
+    ("assign_in_condition.note",        "use == to compare, or assign before the condition"),
+    ("stray_semicolon",                 "; after {kind}"),
+    ("array_index_invalid",             "array index must be a number or a character"),
+    ("array_index_invalid.note",        "like [7] = or ['a'] ="),
+    ("array_index_twice",               "array index {index} is set twice"),
+    ("array_index_twice.first",         "set first here"),
+    ("array_repeat_count",              "the count of a repeated array value must be a number above 0"),
+    ("array_repeat_count.note",         "like {0; 64}"),
+    ("flexible_array_not_last",         "array {name} without size must be the last field"),
+    ("flexible_array_not_last.note",    "its elements are the tail of the struct, they would overlap the next field"),
+    ("anon_struct_unnamed",             "struct or union declared inside {name} needs a field name"),
+    ("anon_struct_unnamed.note",        "name the field after the closing }, like } header;"),
+    ("field_default_union",             "union field {name} cannot have a default"),
+    ("field_default_union.note",        "a union is initialized through one field, which the initializer names"),
+    ("anon_struct_flexible",            "a struct declared inside another struct cannot have a tail"),
+    ("sizeof_tail_unbound",             "sizeof a tail type needs the tail length"),
+    ("sizeof_tail_unbound.note",        "write the number of tail elements after +, like sizeof(String+n)"),
+    ("stray_semicolon.note",            "remove the ;. in c it would be the whole body"),
+    ("edition_syntax",                  "{found} is not valid since edition {since}"),
+    ("edition_syntax.note",             "write {replace}, or run zz fix --edition to migrate"),
+    ("attribute_dangling",              "attribute must be followed by a declaration"),
+    ("attribute_dangling.note",         "nothing is declared after this"),
+    ("unstable_private",                "{name} is not pub or export, it cannot be unstable"),
+    ("unstable_private.note",           "only what other packages can use has a stability"),
+    ("weak_not_function",               "@weak on {name}, which is not a pub or export fn"),
+    ("weak_not_function.note",          "only a fn with a symbol outside its module can be replaced at link time"),
+    ("override_not_function",           "@override on {name}, which is not a fn"),
+    ("symbol_not_export",               "@symbol on {name}, which is not an export fn"),
+    ("symbol_not_export.note",          "only export functions that are not inline or extern have a symbol of their own"),
+    ("syntax_error",                    "syntax error"),
+    ("layout_version_invalid",          "layout version must be an unsigned integer"),
+    ("layout_version_invalid.note",     "in this @abi"),
+    ("static_visibility",               "cannot change visibility of static variable"),
+    ("static_visibility.note",          "statics are private to their module"),
+    ("const_array_visibility",          "const arrays cannot be pub or export (yet)"),
+    ("const_array_visibility.note",     "declared here"),
+    ("const_array_mut",                 "const arrays cannot be mut"),
+    ("const_array_mut.note",            "this tag"),
+    ("anonymous_type_tags",             "anonymous type cannot have storage tags (yet)"),
+    ("anonymous_type_tags.note",        "this tag"),
+    ("else_without_if",                 "else without if"),
+    ("else_without_if.note",            "this else branch does not follow an if condition"),
+    ("multiple_default_cases",          "multiple default cases"),
+    ("multiple_default_cases.note",     "in this switch"),
+    ("returned_array_length",           "returned array must have a length"),
+    ("returned_array_length.note",      "in this return type"),
+    ("returned_array_not_comptime",     "only comptime functions can return arrays"),
+    ("returned_array_not_comptime.note", "in this return type"),
+    ("len_tag_declaration",             "@len is only allowed on arguments of functions"),
+    ("len_tag_declaration.note",        "write the length as where len(name) >= n on the functions implementing it"),
+    ("len_tag_not_pointer",             "@len on {name}, which is not a pointer"),
+    ("range_tag_declaration",           "@range is only allowed on arguments of functions and struct fields"),
+    ("range_tag_declaration.note",      "write the range as where n >= lo && n <= hi on the functions implementing it"),
+    ("range_tag_not_integer",           "@range on {name}, which is not an integer"),
+    ("flags_value",                     "{name} is not a single bit"),
+    ("flags_value.note",                "values of a flags enum are powers of two, this is {value}"),
+    ("keyword_as_name",                 "illegal use of keyword '{name}'"),
+    ("expected_name",                   "expected a name"),
+
+    // macros.rs
+    ("macro_argument_count",            "macro {name} takes {expected} arguments, but {given} were given"),
+    ("macro_recursive",                 "macro {name} expands itself"),
+    ("macro_recursive.note",            "expansion is more than {depth} macros deep here"),
+    ("macro_not_expression",            "macro {name} cannot be used as an expression"),
+    ("macro_not_expression.here",       "used as expression here"),
+    ("macro_not_expression.note",       "body must be a single expression to be used as one"),
+    ("expanded_from_macro",             "expanded from macro {name}"),
+
+    // provenance.rs
+    ("provenance_pointer_to_integer",   "cast from pointer {from} to integer {into}"),
+    ("provenance_integer_to_pointer",   "cast from integer {from} to pointer {into}"),
+    ("provenance_cast.note",            "pointers are not just addresses on every target. if this is intended, write ({into} provenance)"),
+    ("provenance_void_arithmetic",      "arithmetic on void pointer"),
+    ("provenance_void_arithmetic.note", "void has no size. cast to u8* first"),
+    ("provenance_unrelated_compare",    "comparing unrelated pointers {lhs} and {rhs}"),
+    ("provenance_unrelated_compare.note", "cast one side if both point into the same object"),
+
+    // vla.rs
+    ("vla_local",                       "variable length array {name}"),
+    ("vla_local.note",                  "the size is only known at runtime, so the stack can overflow. use a constant size"),
+    ("vla_alloca",                      "call to {name}"),
+    ("vla_alloca.note",                 "allocates an unbounded amount of stack"),
+
+    // pitfalls.rs
+    ("pitfall_bitwise_bool",            "bitwise {op} on bool"),
+    ("pitfall_bitwise_bool.note",       "did you mean {instead}?"),
+    ("pitfall_precedence",              "{inner} binds tighter than {outer}"),
+    ("pitfall_precedence.note",         "this is a {outer} (b {inner} c). write (a {outer} b) {inner} c if that was meant"),
+    ("pitfall_mixed_bool",              "&& inside || without parentheses"),
+    ("pitfall_mixed_bool.note",         "&& binds tighter. write (a && b) || c to make that visible"),
+
+    // struct_init.rs
+    ("struct_init_missing",             "initialization of {name} leaves out {fields}"),
+    ("struct_init_missing.note",        "these are set to zero. set them here, or start from {{0}} if zero is intended"),
+
+    // layout.rs
+    ("layout_changed",                  "layout of {name} changed"),
+    ("layout_changed.note",             "this is a wire format struct. if the change is intended, set @abi = {version}, or remove it from {file}"),
+
+    // alias.rs
+    ("alias_cycle",                     "type alias {name} names itself"),
+    ("alias_cycle.note",                "through {through}"),
+
+    // deprecated.rs
+    ("deprecated_use",                  "{name} is deprecated: {message}"),
+    ("deprecated_use.note",             "used here"),
+    ("deprecated_use.declared",         "deprecated here"),
+
+    // stability.rs
+    ("unstable_use",                    "{name} is unstable, behind feature {feature} of {package}"),
+    ("unstable_use.note",               "used here"),
+    ("unstable_use.declared",           "declared unstable here"),
+    ("unstable_use.suggestion",         "to opt in, add {package} = [\"{feature}\"] to [unstable] in zz.toml"),
+
+    // weak.rs
+    ("override_unknown",                "cannot find {target} to override"),
+    ("override_not_weak",               "{target} is not @weak"),
+    ("override_not_weak.note",          "only a @weak fn can be overridden"),
+    ("override_signature",              "{name} does not have the signature of {target}, which it overrides"),
+    ("override_twice",                  "{target} is overridden more than once"),
+    ("override_twice.first",            "also overridden here"),
+
+    // symbols.rs
+    ("symbol_invalid",                  "{symbol} is not a c identifier"),
+    ("symbol_invalid.note",             "a symbol has letters, digits and _, and does not start with a digit"),
+    ("symbol_prefix_invalid",           "symbol_prefix {prefix} in zz.toml makes {symbol}, which is not a c identifier"),
+    ("symbol_prefix_invalid.note",      "for this fn"),
+    ("symbol_collision",                "{name} and {other} are both the symbol {symbol}"),
+    ("symbol_collision.note",           "give one of them another with @symbol(\"...\")"),
+    ("symbol_collision.other",          "{other} is declared here"),
+
+    // resources.rs
+    ("resource_missing",                "resource {name} in zz.toml is {path}, which does not exist"),
+    ("resource_module_taken",           "{name} is generated from [resources] in zz.toml, rename src/resources.zz"),
+    ("resource_clash",                  "resources {name} and {other} are both named {ident}"),
+
+    // i18n.rs
+    ("i18n_module_taken",               "{name} is generated from [i18n] in zz.toml, rename src/i18n.zz"),
+    ("i18n_default_missing",            "the default language in [i18n] of zz.toml is {lang}, but there is no {path}"),
+    ("i18n_catalog_invalid",            "{path} is not a catalog of strings: {error}"),
+    ("i18n_catalog_empty",              "{path} has no strings"),
+    ("i18n_lang_invalid",               "{path} is not named after a language. {lang} must be an identifier, like en or pt_BR"),
+    ("i18n_key_invalid",                "{key} is not an identifier"),
+    ("i18n_key_invalid.note",           "keys become members of enum Key"),
+    ("i18n_format",                     "the {lang} string of {key} takes other arguments than the {default} one"),
+    ("i18n_format.default",             "the default string"),
+    ("i18n_missing_key",                "{lang} has no string for {key}"),
+    ("i18n_missing_key.note",           "the {default} string is used in {lang}"),
+    ("i18n_unknown_key",                "{key} of {lang} is not in the {default} catalog"),
+    ("i18n_unknown_key.note",           "left out, the keys are those of {default}"),
+    ("i18n_unused",                     "string {key} is never used"),
+    ("i18n_unused.note",                "no module of the project names i18n::Key::{key}"),
+
+    // interface.rs
+    ("interface_unknown",               "zz.toml selects a backend for {name}, but there is no such module"),
+    ("interface_not_interface",         "zz.toml selects a backend for {name}, which is not an interface module. start it with interface module;"),
+    ("interface_backend_unknown",       "backend {backend} of interface {name} is not a zz module"),
+    ("interface_local",                 "{name} is not a fntype"),
+    ("interface_local.note",            "an interface module only declares the functions of its backends"),
+    ("interface_missing",               "backend {backend} of interface {name} has no fn {local}"),
+    ("interface_missing.note",          "required here"),
+    ("interface_not_function",          "{local} in backend {backend} is not a fn"),
+    ("interface_private",               "fn {local} in backend {backend} is private"),
+    ("interface_private.note",          "make it pub, the interface {name} is used from other modules"),
+    ("interface_signature",             "fn {local} in backend {backend} does not have the signature interface {name} declares"),
+
+    // comptime.rs
+    ("array_size_not_constant",         "array size is not a compile time constant"),
+    ("array_size_not_constant.note",    "in this array size"),
+
+    // symbolic.rs
+    ("integer_as_bool",                 "integer {name} used as bool"),
+    ("integer_as_bool.note",            "write true or false, or compare with =="),
+    ("edition_int_to_ptr",              "integer {from} is not implicitly a pointer {into} since edition 2021"),
+    ("edition_int_to_ptr.note",         "write the cast, like ({into})"),
+
+    // cheader.rs
+    ("ext_argument_count",              "call argument count mismatch"),
+    ("ext_argument_count.note",         "{name} takes {expected} arguments, but {given} were given"),
+    ("ext_argument_count.vararg",       "{name} takes at least {expected} arguments, but {given} were given"),
+    ("ext_argument_count.declared",     "declared in {header} as {decl}"),
+    ("ext_argument_type",               "call argument type mismatch"),
+    ("ext_argument_type.note",          "argument {index} of {name} is {expected}, but {given} was given"),
+    ("ext_return_type",                 "call return type mismatch"),
+    ("ext_return_type.note",            "{name} returns {ret}, but it is stored as {into}"),
+
+    // unused.rs
+    ("unused_import",                   "unused import {name}"),
+    ("unused_import.note",              "nothing in this module uses it"),
+    ("unused_argument",                 "argument {name} is never read"),
+    ("unused_argument.note",            "name it _{name} if it has to be there"),
+    ("unused_local",                    "local {name} is never read"),
+    ("unused_local.note",               "declared here"),
+
+    // api.rs
+    ("api_changed",                     "the api of {name} changed, but not {file}"),
+    ("api_changed.note",                "it was {was}"),
+    ("api_added",                       "{name} is new in the api, but not in {file}"),
+    ("api_added.note",                  "run zz api --record to add it, or make it private"),
+    ("api_removed",                     "{name} is gone from the api, but still in {file}: {was}"),
+
+    // analyze.rs
+    ("policy_denied",                   "{feature} is denied in {dir} by the policy in zz.toml"),
+
+    // nullable.rs
+    ("nullable_deref",                  "{name} may be null here"),
+    ("nullable_deref.note",             "compare it with 0 before dereferencing it"),
+    ("nullable_argument",               "passing a pointer that may be null as {name}"),
+    ("nullable_argument.note",          "{name} is not tagged nullable"),
+
+    // flags.rs
+    ("flags_mixed",                     "{lhs} {op} {rhs} mixes a flags enum with something else"),
+    ("flags_mixed.note",                "cast explicitly if this is intended"),
+    ("flags_arithmetic",                "{op} on {name}, which is a flags enum"),
+    ("flags_arithmetic.note",           "flags only combine with | & ^ and ~"),
+
+    // units.rs
+    ("unit_mismatch",                   "mixing {lhs} and {rhs}"),
+    ("unit_mismatch.note",              "convert one side with a cast to a type with @unit(\"{unit}\")"),
+    ("unit_assign",                     "{have} value where {want} is expected"),
+    ("unit_assign.note",                "{name} is declared with @unit(\"{unit}\")"),
+
+    // noreturn.rs
+    ("noreturn_returns",                "{name} is noreturn but can return"),
+    ("noreturn_returns.note",           "a noreturn function may not return"),
+    ("noreturn_falls_through.note",     "the end of the body can be reached. end it with a call to a noreturn function, an endless loop or c code"),
+    ("noreturn_unreachable",            "unreachable, {name} does not return"),
+    ("noreturn_unreachable.note",       "the call to {name} ends the block"),
+
+    // ownership.rs
+    ("owned_use_after_move",            "{name} is used after it was moved"),
+    ("owned_maybe_moved",               "{name} may have been moved on the way here"),
+    ("owned_moved_here",                "moved here"),
+    ("owned_leaked",                    "{name} still owns its value here"),
+    ("owned_leaked.note",               "move it into an argument tagged owned, return it, or give it up with {name} is dropped"),
+    ("owned_overwritten",               "{name} is overwritten while it still owns its value"),
+
+    // statemachine.rs
+    ("statemachine_empty",              "statemachine {name} has no states or no events"),
+    ("statemachine_empty.note",         "a statemachine needs at least one state and one event"),
+    ("statemachine_duplicate",          "{name} is declared twice in statemachine {machine}"),
+    ("statemachine_duplicate.note",     "declared again here"),
+    ("statemachine_undefined_state",    "{name} is not a state of statemachine {machine}"),
+    ("statemachine_undefined_event",    "{name} is not an event of statemachine {machine}"),
+    ("statemachine_undefined.note",     "not declared"),
+    ("statemachine_shadowed",           "this transition never happens"),
+    ("statemachine_shadowed.note",      "{from} already goes to {to} on {event} without a guard"),
+    ("statemachine_unreachable",        "state {name} of statemachine {machine} can never be entered"),
+    ("statemachine_unreachable.note",   "no transitions lead here from {initial}"),
+
+    // wire.rs
+    ("wire_empty",                      "wire {name} has no fields"),
+    ("wire_duplicate",                  "{name} is declared twice"),
+    ("wire_duplicate.note",             "declared again here"),
+    ("wire_type",                       "{typed} cannot be sent over the wire"),
+    ("wire_type.note",                  "fields are u8, u16, u32 or u64, and arrays of u8"),
+    ("wire_array_type",                 "{name} is an array of something other than u8"),
+    ("wire_array_type.note",            "only u8 arrays can be on the wire"),
+    ("wire_size_invalid",               "the size of a wire array must be a number above 0"),
+    ("wire_field_before",               "{name} is not an integer field before this one"),
+    ("wire_field_before.note",          "lengths and conditions can only use integer fields before them, which are always on the wire"),
+
+    // closure.rs
+    ("closure_conflict",                "{name} is declared more than once in closure {closure}"),
+    ("closure_no_captures",             "closure {name} captures nothing"),
+    ("closure_no_captures.note",        "declare a fn instead"),
+    ("closure_not_captured",            "closure {closure} uses {name} without capturing it"),
+    ("closure_not_captured.note",       "add {name} to the capture list"),
+    ("nested_fn_conflict",              "{name} is declared more than once in {function}"),
+    ("nested_fn_captures",              "fn {function} uses {name} of the function it is declared in"),
+    ("nested_fn_captures.note",         "a fn in a function body captures nothing. pass {name} as an argument, or use a closure"),
+    ("closure_escapes",                 "closure {name} escapes the function declaring it"),
+    ("closure_escapes.note",            "its captures may point into this stack frame, which ends on return"),
+
+    // coroutine.rs
+    ("await_outside_async",             "await outside of an async fn"),
+    ("await_outside_async.note",        "only an async fn can wait, make this one async or poll instead"),
+    ("async_method",                    "method {name} cannot be {what}"),
+    ("async_method.note",               "it becomes a struct of its own, declare it outside the struct"),
+    ("async_signature",                 "{kind} fn {name} cannot have {what}"),
+    ("async_await_unsupported",         "await and yield cannot be inside for, switch or unsafe"),
+    ("async_await_unsupported.note",    "here, write the loop with while or the switch with if"),
+    ("async_local_conflict",            "{name} clashes with another name in the frame of {function}"),
+    ("async_local_conflict.note",       "locals of an async fn or generator are fields of its frame next to its arguments, state, result and value, and need unique names"),
+    ("async_local_type",                "the type of {name} has to be written out"),
+    ("async_local_type.note",           "it becomes a field of the frame of the async fn or generator"),
+    ("async_array_init",                "{name} cannot be initialized with {{..}} in an async fn or generator"),
+    ("async_array_init.note",           "it becomes a field of the frame, assign its elements or fields instead"),
+    ("async_recursive",                 "async fn {name} awaits itself"),
+    ("async_recursive.note",            "its frame would have to contain itself"),
+    ("yield_outside_generator",         "yield outside of a generator"),
+    ("yield_outside_generator.note",    "only a gen fn can yield"),
+    ("generator_return_value",          "generator {name} returns a value"),
+    ("generator_return_value.note",     "yield it, return only ends the generator"),
+    ("range_not_generator",             "for (x : ..) needs a call to a generator"),
+    ("range_not_generator.note",        "like for (u8 d : digits(n))"),
+
+    // derive.rs
+    ("derive_unknown",                  "no derive plugin named {name}"),
+    ("derive_unknown.note",             "add it to [derive] in zz.toml: {name} = [\"command\", \"args\"]"),
+    ("derive_failed",                   "derive plugin {name} failed"),
+
+    // lib.rs
+    ("wasm_unavailable_header",         "{name} is not available on wasm32"),
+    ("wasm_unavailable_header.note",    "wrap the import in #if target(\"wasm32\", false)"),
+    ("eval_module",                     "no module {name} in this project"),
+    ("eval_failed",                     "evaluation failed"),
+
+    // schema.rs
+    ("config_invalid",                  "cannot read {path}: {error}"),
+    ("config_unknown_key",              "unknown key {key} in zz.toml"),
+    ("config_unknown_key.suggest",      "did you mean {name}?"),
+    ("config_unknown_key.known",        "known keys are {known}"),
+    ("config_unknown_key.known_in",     "known keys in {table} are {known}"),
+    ("config_type",                     "{key} in zz.toml must be {want}, not a {found}"),
+    ("config_type.note",                "expected {want}"),
+    ("config_value",                    "{key} in zz.toml cannot be {value}"),
+    ("config_value.note",               "one of {values}"),
+    ("config_conflict",                 "{key} in zz.toml conflicts with {other}"),
+    ("config_conflict.dependency",      "a dependency comes either from a path, or from git at a rev"),
+    ("config_conflict.artifact",        "only an artifact of type {typ} has {key}"),
+    ("config_schema",                   "zz.toml has schema {schema}, but this zz knows schemas 1 to {current}"),
+    ("config_schema.note",              "a newer zz.toml needs a newer zz"),
+    ("config_migrate_current",          "zz.toml is schema {schema}, there is nothing to migrate"),
+    ("config_migrated",                 "migrated zz.toml from schema {from} to {to}"),
+
+    // cxxheader.rs
+    ("cxx_empty_struct",                "export struct {name} has no fields, which c++ can't declare with the same size"),
+    ("cxx_empty_struct.note",           "cxx_headers = true in zz.toml needs a field here, like u8 reserved"),
+    ("cxx_keyword_field",               "field {field} of export struct {name} is a c++ keyword"),
+    ("cxx_keyword_field.note",          "cxx_headers = true in zz.toml needs another name here"),
+    ("cxx_header_compiler",             "cannot run {cxx} to check the header as c++: {error}"),
+    ("cxx_header_invalid",              "{path} is not valid c++"),
+
+    // crash.rs
+    ("crash_bundle",                    "internal compiler error, wrote a crash bundle to {path}"),
+    ("crash_bundle.note",               "please attach it to a bug report at {url}"),
+    ("crash_bundle_failed",             "internal compiler error, and the crash bundle could not be written: {error}"),
+
+    // reduce.rs
+    ("reduce_not_interesting",          "the check does not hold on {path} as it is: {check}"),
+    ("reduce_progress",                 "{bytes} bytes left after {tests} tests"),
+    ("reduce_done",                     "reduced {path} from {from} to {to} bytes in {tests} tests, the original is in {orig}"),
+
+    // grammar.rs
+    ("grammar_unreadable",              "cannot read the zz.pest zz was built from: {error}"),
+    ("grammar_written",                 "wrote railroad diagrams of the grammar to {path}"),
+
+    // flatten.rs
+    ("type_cycle",                      "{name} can never be emitted, it depends on itself: {chain}"),
+    ("type_cycle.complete",             "{name} needs {dep} complete here. a pointer to it only needs a declaration"),
+    ("type_cycle.declared",             "{name} needs {dep} declared here"),
+
+    // watch.rs
+    ("watch_pass",                      "zz {command} passed in {time}"),
+    ("watch_fail",                      "zz {command} failed in {time}"),
+    ("watch_waiting",                   "watching {files} files for changes"),
+
+    // propagate.rs
+    ("try_without_err",                 "try in {name}, which has no err::Err+ mut* argument to return the error in"),
+    ("try_without_err.note",            "add one, like err::Err+et mut* e, and pass it to the call"),
+    ("try_return_type",                 "try in {name}, which returns neither nothing, bool nor an integer"),
+    ("try_return_type.note",            "there is no value to return on failure, check the error by hand with err::check"),
+    ("try_err_not_passed",              "try needs a call that is passed {err}"),
+    ("try_err_not_passed.note",         "a failure can only be seen in {err}"),
+
+    // migrate.rs
+    ("migrate_not_a_directory",         "{dir} is not a directory"),
+    ("migrate_config",                  "wrote {path} with {cobjects} c files to build"),
+    ("migrate_header",                  "translated {header} into {module}, {manual} places need manual attention"),
+    ("migrate_done",                    "{headers} headers translated, {manual} places need manual attention"),
+    ("migrate.exists",                  "exists already, left as it is"),
+    ("migrate.module_taken",            "another header already became module {module}, translate this one by hand"),
+    ("migrate.main",                    "defines main, so it is not built as a cobject. port it to src/main.zz"),
+    ("migrate.keyword",                 "{name} is a keyword in zz"),
+    ("migrate.macro",                   "macro {name} is not translated"),
+    ("migrate.conditional",             "conditional compilation, the declarations of all branches were translated"),
+    ("migrate.body",                    "{name} has a body in the header, only declarations are translated"),
+    ("migrate.field",                   "{name} has nested structs, function pointers or fields that could not be read"),
+    ("migrate.type",                    "{name} uses {typ}, which has no zz equivalent"),
+    ("migrate.enum",                    "enum {name} has a value that is not a constant: {item}"),
+    ("migrate.vararg",                  "{name} takes variable arguments"),
+    ("migrate.function_pointer",        "function pointers are not translated: {decl}"),
+    ("migrate.global",                  "global variables are not translated: {decl}"),
+    ("migrate.unknown",                 "not understood: {decl}"),
+
+    // postprocess.rs
+    ("postprocess_failed",              "postprocess command {name} failed on {file}: {error}"),
+    ("postprocess_empty",               "the command is empty"),
+
+    // lintpass.rs
+    ("lintpass_naming",                 "{kind} {name} is not {style} [{lint}]"),
+    ("lintpass_banned",                 "call to {name}, which is banned [{lint}]"),
+    ("lintpass_invalid",                "invalid lint {path}: {error}"),
+    ("lintpass_unknown_style",          "unknown naming style {style}, known styles are {styles}"),
+
+    // package.rs
+    ("package_written",                 "wrote {path} with {files} files, sha256 {sha256}"),
+    ("package_skipped",                 "{path} is not a file or directory, leaving it out of the package"),
+    ("package_path_too_long",           "{path} is too long for a tar archive"),
+
+    // buildfiles.rs
+    ("buildfiles_ninja_written",        "wrote {path}, build with ninja -f {path}"),
+
+    // doctor.rs
+    ("doctor.ok",                       "ok"),
+    ("doctor.warning",                  "warning"),
+    ("doctor.error",                    "error"),
+    ("doctor.fix",                      "fix"),
+    ("doctor_no_project",               "no zz.toml in the current directory or a parent, only checking the tools of every build"),
+    ("doctor_cc",                       "c compiler {cc}"),
+    ("doctor_cc_missing",               "cannot find {cc} in PATH"),
+    ("doctor_cc_missing.fix",           "install clang or gcc, or set $CC to the c compiler to use"),
+    ("doctor_compile",                  "{cc} cannot compile an empty program: {error}"),
+    ("doctor_compile.fix",              "check that {cc} is a c compiler and that the c headers of your system are installed"),
+    ("doctor_linker",                   "linker of {cc}"),
+    ("doctor_link",                     "{cc} cannot link an empty program: {error}"),
+    ("doctor_link.fix",                 "install the linker and the c library of your system, like binutils and libc6-dev on debian"),
+    ("doctor_tmp.fix",                  "set $TMPDIR to a writable directory"),
+    ("doctor_ar",                       "archiver {ar}"),
+    ("doctor_ar_missing",               "cannot find {ar} in PATH"),
+    ("doctor_ar_missing.fix",           "install binutils, or set $AR to the archiver to use"),
+    ("doctor_smt",                      "smt solver z3"),
+    ("doctor_smt_missing",              "cannot find z3 in PATH, symbolic execution needs it"),
+    ("doctor_smt_missing.fix",          "install z3, like apt install z3 or brew install z3"),
+    ("doctor_sysroot",                  "sysroot of target {target} {path}"),
+    ("doctor_sysroot_missing",          "{path} is not a directory"),
+    ("doctor_sysroot_missing.fix",      "install the sysroot, or correct sysroot in [target.{target}] of zz.toml"),
+    ("doctor_sysroot_headers",          "{path} has no include directory"),
+    ("doctor_sysroot_headers.fix",      "install the c library headers of the target into the sysroot"),
+    ("doctor_pkg_config",               "pkg-config {bin}"),
+    ("doctor_pkg_config_missing",       "cannot find {bin} in PATH"),
+    ("doctor_pkg_config_missing.fix",   "install pkg-config, or set $PKG_CONFIG. only pkgconfig = [..] in zz.toml needs it"),
+    ("doctor_pkg",                      "package {pkg}"),
+    ("doctor_pkg_missing",              "{bin} cannot find {pkg}"),
+    ("doctor_pkg_missing.fix",          "install the development package of {pkg}, or add the directory of {pkg}.pc to $PKG_CONFIG_PATH"),
+    ("doctor_git",                      "git"),
+    ("doctor_git_missing",              "cannot find git in PATH, git dependencies need it"),
+    ("doctor_git_missing.fix",          "install git"),
+    ("doctor_unwritable",               "cannot write to {path}: {error}"),
+    ("doctor_target",                   "build directory {path}"),
+    ("doctor_target.fix",               "make {path} writable, everything zz builds goes there"),
+    ("doctor_cache",                    "cache directory {path}"),
+    ("doctor_cache.fix",                "make {path} writable, or set $ZZ_CACHE to a writable directory"),
+    ("doctor_summary",                  "{errors} problems found"),
+    ("doctor_summary.ok",               "found everything a build needs"),
+
+    // warnings.rs
+    ("warnings_emitted",                "{count} warnings"),
+    ("warnings_denied",                 "{count} warnings, denied by -D warnings"),
+
+    // shared notes
+    ("called_here",                     "called here"),
+    ("used_here",                       "used here"),
+    ("defined_here",                    "defined here"),
+    ("here",                            "here"),
+];
+
+static LOCALE: Mutex<Option<HashMap<String, String>>> = Mutex::new(None);
+
+// diagnostics are handed around as text. this remembers the code each message was formatted from,
+// so diagnostics.rs can show it next to the message
+static CODES: Mutex<Option<HashMap<String, &'static str>>> = Mutex::new(None);
+
+/// the code a message was formatted from with msg!. notes have no code of their own
+pub fn code_of(text: &str) -> Option<&'static str> {
+    CODES.lock().unwrap().as_ref().and_then(|codes| codes.get(text).cloned())
+}
+
+/// the language asked for in the environment, if it isn't english
+fn language() -> Option<String> {
+    let lang = ["ZZ_LANG", "LC_ALL", "LC_MESSAGES", "LANG"].iter()
+        .filter_map(|v| std::env::var(v).ok())
+        .find(|v| !v.is_empty())?;
+    let lang = lang.split(|c| c == '.' || c == '@').next().unwrap_or("").to_string();
+    if lang.is_empty() || lang == "C" || lang == "POSIX" || lang.starts_with("en") {
+        return None;
+    }
+    Some(lang)
+}
+
+fn locale_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(dir) = std::env::var_os("ZZ_LOCALE_DIR") {
+        dirs.push(PathBuf::from(dir));
+    }
+    // next to modules/, found the same way
+    if let Some(root) = std::env::current_exe().ok()
+        .and_then(|p| p.canonicalize().ok())
+        .and_then(|p| p.parent()?.parent()?.parent().map(|p| p.to_path_buf()))
+    {
+        dirs.push(root.join("locale"));
+    }
+    dirs.push(std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("locale"));
+    dirs
+}
+
+fn load() -> HashMap<String, String> {
+    let lang = match language() {
+        Some(v) => v,
+        None => return HashMap::new(),
+    };
+    let mut names = vec![lang.clone()];
+    if let Some(short) = lang.split('_').next() {
+        if short != lang {
+            names.push(short.to_string());
+        }
+    }
+    for dir in locale_dirs() {
+        for name in &names {
+            let path = dir.join(format!("{}.toml", name));
+            let s = match std::fs::read_to_string(&path) {
+                Ok(s) => s,
+                Err(_) => continue,
+            };
+            match toml::from_str(&s) {
+                Ok(v) => return v,
+                Err(e) => {
+                    warn!("ignoring locale {}: {}", path.display(), e);
+                }
+            }
+        }
+    }
+    HashMap::new()
+}
+
+/// the text of code in the current locale, with the arguments filled in
+pub fn format(code: &str, args: &[(&str, String)]) -> String {
+    let mut locale = LOCALE.lock().unwrap();
+    let locale = locale.get_or_insert_with(load);
+
+    let template = match locale.get(code) {
+        Some(v) => v.as_str(),
+        None => match ENGLISH.iter().find(|(c, _)| *c == code) {
+            Some((_, v)) => v,
+            None => {
+                debug!("no message for code {}", code);
+                code
+            }
+        }
+    };
+
+    let mut s = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        s.push_str(&rest[..start].replace("}}", "}"));
+        rest = &rest[start..];
+        // {{ is a literal brace
+        if rest.starts_with("{{") {
+            s.push('{');
+            rest = &rest[2..];
+            continue;
+        }
+        match rest.find('}').and_then(|end| args.iter().find(|(n, _)| *n == &rest[1..end]).map(|(_, v)| (end, v))) {
+            Some((end, value)) => {
+                s.push_str(value);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                s.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    s.push_str(&rest.replace("}}", "}"));
+
+    if !code.contains('.') {
+        if let Some((code, _)) = ENGLISH.iter().find(|(c, _)| *c == code) {
+            CODES.lock().unwrap().get_or_insert_with(HashMap::new).insert(s.clone(), code);
+        }
+    }
+    s
+}
@@ -0,0 +1,248 @@
+/// zz metadata: the project as json, for editors and build tools that don't want to read zz.toml themselves
+
+use super::deps;
+use super::make::{self, Stage};
+use super::project::{self, ArtifactType, Config};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+// bumped when fields change meaning or go away. new fields may appear at any time
+pub const VERSION : u32 = 1;
+
+#[derive(Serialize)]
+pub struct Metadata {
+    pub version:            u32,
+    pub root:               String,
+    pub target_directory:   String,
+    pub variant:            String,
+    pub packages:           Vec<Package>,
+}
+
+#[derive(Serialize)]
+pub struct Package {
+    pub name:           String,
+    pub version:        String,
+    pub edition:        String,
+    pub root:           String,
+    pub manifest:       String,
+    // "root", "search", "path" or "git"
+    pub source:         String,
+    pub git:            Option<String>,
+    pub commit:         Option<String>,
+    pub dependencies:   Vec<String>,
+    pub artifacts:      Vec<Artifact>,
+    pub modules:        Vec<Module>,
+    pub targets:        Vec<String>,
+    pub features:       Vec<String>,
+    // features enabled by each variant
+    pub variants:       BTreeMap<String, Vec<String>>,
+    // features this package is built with. dependencies are always built with their default variant
+    pub enabled:        Vec<String>,
+    pub cflags:         Vec<String>,
+    pub lflags:         Vec<String>,
+    pub cincludes:      Vec<String>,
+    pub cobjects:       Vec<String>,
+    pub pkgconfig:      Vec<String>,
+    pub libs:           Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct Artifact {
+    pub name:       String,
+    #[serde(rename = "type")]
+    pub typ:        ArtifactType,
+    pub main:       String,
+    // the linked file, by stage
+    pub outputs:    BTreeMap<String, String>,
+}
+
+#[derive(Serialize)]
+pub struct Module {
+    pub name:       String,
+    // "zz", or "c" for headers in src/
+    pub kind:       String,
+    pub source:     String,
+}
+
+/// where zz build links artifact to in stage, relative to the package root
+pub fn output(stage: &Stage, artifact: &project::Artifact) -> Option<String> {
+    let dir = format!("target/{}", stage);
+    if stage.wasm {
+        return match artifact.typ {
+            ArtifactType::Lib       => Some(format!("{}/lib/{}.wasm", dir, artifact.name)),
+            // emscripten also writes a .js loader next to it
+            ArtifactType::Exe       => Some(format!("{}/bin/{}.wasm", dir, artifact.name)),
+            ArtifactType::Staticlib => Some(format!("{}/lib/lib{}.a", dir, artifact.name)),
+            _                       => None,
+        };
+    }
+    match artifact.typ {
+        ArtifactType::Lib       => Some(format!("{}/lib/{}", dir, make::shared_lib(&artifact.name, make::host()))),
+        ArtifactType::Staticlib => Some(format!("{}/lib/lib{}.a", dir, artifact.name)),
+        ArtifactType::Exe | ArtifactType::Test => Some(format!("{}/bin/{}", dir, artifact.name)),
+        ArtifactType::Header | ArtifactType::NodeModule => None,
+    }
+}
+
+/// the modules loader::load would make of the files in dir
+fn modules(prefix: &str, dir: &Path) -> Vec<Module> {
+    let mut r = Vec::new();
+    let entries = match std::fs::read_dir(dir) {
+        Ok(v) => v,
+        Err(_) => return r,
+    };
+    for entry in entries {
+        let path = entry.unwrap().path();
+        if !path.is_file() {
+            continue;
+        }
+        let stem = path.file_stem().unwrap().to_string_lossy().to_string();
+        let (name, kind) = match path.extension().and_then(|v|v.to_str()) {
+            Some("zz") if stem == "lib" => (prefix.to_string(), "zz"),
+            Some("zz")  => (format!("{}::{}", prefix, stem), "zz"),
+            Some("h")   => (format!("{}::{}", prefix, stem), "c"),
+            _ => continue,
+        };
+        r.push(Module{
+            name,
+            kind:   kind.to_string(),
+            source: path.to_string_lossy().into(),
+        });
+    }
+    r.sort_by(|a, b| a.name.cmp(&b.name));
+    r
+}
+
+fn package(root: &Path, config: &Config, variant: &str, source: &str) -> Package {
+    let stages = [Stage::test(), Stage::debug(), Stage::release()];
+
+    let mut artifacts = Vec::new();
+    for artifact in config.artifacts.as_ref().map(|v|v.as_slice()).unwrap_or_default() {
+        let mut outputs = BTreeMap::new();
+        for stage in &stages {
+            let targets = std::iter::once(stage.clone())
+                .chain(std::iter::once(stage.clone().wasm32()))
+                .chain(config.target.keys().map(|t| stage.clone().target(t)));
+            for stage in targets {
+                if let Some(out) = output(&stage, artifact) {
+                    outputs.insert(stage.to_string(), root.join(out).to_string_lossy().into());
+                }
+            }
+        }
+        artifacts.push(Artifact{
+            name:       artifact.name.clone(),
+            typ:        artifact.typ.clone(),
+            main:       artifact.main.clone(),
+            outputs,
+        });
+    }
+
+    let mut modules = modules(&config.project.name, &root.join("src"));
+    if source == "root" {
+        modules.extend(self::modules(&format!("{}::tests", config.project.name), &root.join("tests")));
+    }
+
+    let mut features : Vec<String> = config.features.as_ref().map(|f| f.keys().cloned().collect()).unwrap_or_default();
+    features.sort();
+
+    let mut variants = BTreeMap::new();
+    for (name, enabled) in &config.variants {
+        let mut enabled = enabled.clone();
+        enabled.sort();
+        variants.insert(name.clone(), enabled);
+    }
+
+    let mut enabled : Vec<String> = config.features(variant).into_iter().filter(|(_,(e,_))| *e).map(|(n,_)| n).collect();
+    enabled.sort();
+
+    let mut dependencies : Vec<String> = config.dependencies.as_ref().map(|d| d.keys().cloned().collect()).unwrap_or_default();
+    dependencies.sort();
+
+    let mut targets : Vec<String> = std::iter::once("wasm32".to_string()).chain(config.target.keys().cloned()).collect();
+    targets.sort();
+
+    Package {
+        name:           config.project.name.clone(),
+        version:        config.project.version.clone(),
+        edition:        config.project.edition.to_string(),
+        root:           root.to_string_lossy().into(),
+        manifest:       root.join("zz.toml").to_string_lossy().into(),
+        source:         source.to_string(),
+        git:            None,
+        commit:         None,
+        dependencies,
+        artifacts,
+        modules,
+        targets,
+        features,
+        variants,
+        enabled,
+        cflags:         config.project.cflags.clone(),
+        lflags:         config.project.lflags.clone(),
+        cincludes:      config.project.cincludes.clone(),
+        cobjects:       config.project.cobjects.clone(),
+        pkgconfig:      config.project.pkgconfig.clone(),
+        libs:           config.project.libs.clone(),
+    }
+}
+
+/// add the dependencies of the package at from to packages, the same way load_project walks them
+fn dependencies(
+    config:         &Config,
+    from:           &Path,
+    packages:       &mut Vec<Package>,
+    seen:           &mut HashSet<PathBuf>,
+    searchpaths:    &mut HashSet<PathBuf>,
+    lock:           &mut deps::Lock,
+) {
+    let mut names : Vec<(&String, &toml::Value)> = match &config.dependencies {
+        Some(d) => d.iter().collect(),
+        None    => return,
+    };
+    names.sort_by(|a, b| a.0.cmp(b.0));
+
+    for (name, dep) in names {
+        let found = super::locate(name, dep, from, searchpaths, lock);
+        if !seen.insert(found.clone()) {
+            continue;
+        }
+        let (root, config) = project::load(&found);
+
+        let mut package = match deps::Source::parse(name, dep) {
+            deps::Source::Search    => package(&root, &config, "default", "search"),
+            deps::Source::Path(_)   => package(&root, &config, "default", "path"),
+            deps::Source::Git{url, ..} => {
+                let mut package = package(&root, &config, "default", "git");
+                package.commit = lock.packages.iter().find(|l| l.name == *name && l.git == url).map(|l| l.commit.clone());
+                package.git = Some(url);
+                package
+            }
+        };
+        packages.push(package);
+
+        searchpaths.insert(root.join("modules"));
+        dependencies(&config, &root, packages, seen, searchpaths, lock);
+    }
+}
+
+pub fn metadata(variant: &str) -> Metadata {
+    let (root, config) = project::load_cwd();
+
+    let mut packages = vec![package(&root, &config, variant, "root")];
+
+    let mut seen = HashSet::new();
+    seen.insert(root.clone());
+    let mut searchpaths = super::searchpaths();
+    let mut lock = deps::Lock::load(&root);
+    dependencies(&config, &root, &mut packages, &mut seen, &mut searchpaths, &mut lock);
+    lock.store(&root);
+
+    Metadata {
+        version:            VERSION,
+        root:               root.to_string_lossy().into(),
+        target_directory:   root.join("target").to_string_lossy().into(),
+        variant:            variant.to_string(),
+        packages,
+    }
+}
@@ -0,0 +1,668 @@
+/// zz migrate, start a zz project around an existing c project
+
+use super::project;
+use super::tokens;
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// something the translation left out, for a human to look at
+struct Manual {
+    file:   PathBuf,
+    line:   usize,
+    what:   String,
+}
+
+#[derive(Clone)]
+struct Tok {
+    text:   String,
+    line:   usize,
+}
+
+/// the zz type of c base types, without pointers
+fn primitive(base: &[&str]) -> Option<&'static str> {
+    Some(match base.join(" ").as_str() {
+        "void"                                                          => "void",
+        "char"                                                          => "char",
+        "signed char" | "int8_t"                                        => "i8",
+        "unsigned char" | "uint8_t"                                     => "u8",
+        "short" | "short int" | "signed short" | "signed short int"
+        | "int16_t"                                                     => "i16",
+        "unsigned short" | "unsigned short int" | "uint16_t"            => "u16",
+        "int" | "signed" | "signed int"                                 => "int",
+        "unsigned" | "unsigned int"                                     => "uint",
+        "int32_t"                                                       => "i32",
+        "uint32_t"                                                      => "u32",
+        "long long" | "long long int" | "signed long long"
+        | "signed long long int" | "int64_t"                            => "i64",
+        "unsigned long long" | "unsigned long long int" | "uint64_t"    => "u64",
+        "size_t" | "uintptr_t"                                          => "usize",
+        "ssize_t" | "ptrdiff_t" | "intptr_t"                            => "isize",
+        "float"                                                         => "f32",
+        "double"                                                        => "f64",
+        "bool" | "_Bool"                                                => "bool",
+        _ => return None,
+    })
+}
+
+const RESTRICT : &[&str] = &["restrict", "__restrict", "__restrict__"];
+
+const C_TYPE_WORDS : &[&str] = &[
+    "void", "char", "short", "int", "long", "signed", "unsigned", "float", "double", "_Bool", "bool",
+    "const", "volatile", "struct", "union", "enum",
+];
+
+fn is_ident(t: &str) -> bool {
+    t.chars().next().map(|c| c.is_ascii_alphabetic() || c == '_').unwrap_or(false)
+        && t.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// the zz type of a c type, or the c type as written if there is none.
+/// named resolves names that aren't c base types, like typedefs of the header
+fn ty(tokens: &[&str], named: &dyn Fn(&str) -> Option<String>) -> Result<String, String> {
+    let text = tokens.join(" ");
+    let star = tokens.iter().position(|t| *t == "*").unwrap_or(tokens.len());
+    let mut base : Vec<&str> = tokens[..star].iter().cloned().filter(|t| !RESTRICT.contains(t)).collect();
+    let konst = base.contains(&"const");
+    base.retain(|t| *t != "const");
+
+    let mut r = match primitive(&base) {
+        Some(p) => p.to_string(),
+        None => match base.as_slice() {
+            [name] if is_ident(name) && !C_TYPE_WORDS.contains(name) => named(name).ok_or_else(|| text.clone())?,
+            _ => return Err(text),
+        },
+    };
+    // const before a * is about what it points to, after it about the pointer, which is what the next * points to
+    let mut pointee_const = konst;
+    for t in tokens[star..].iter().filter(|t| !RESTRICT.contains(*t)) {
+        match *t {
+            "*" => {
+                r.push_str(if pointee_const { "*" } else { " mut*" });
+                pointee_const = false;
+            }
+            "const" => pointee_const = true,
+            _ => return Err(text),
+        }
+    }
+    Ok(r)
+}
+
+/// a declarator like `const char *name[32]` split into its type, name and array or bitfield suffix
+fn declarator<'a>(tokens: &[&'a str]) -> Option<(Vec<&'a str>, Option<&'a str>, String)> {
+    let end = tokens.iter().position(|t| *t == "[" || *t == ":").unwrap_or(tokens.len());
+    let suffix = tokens[end..].join(" ").replace("[ ", "[").replace(" ]", "]");
+    let (typ, name) = match tokens[..end].split_last() {
+        Some((last, rest)) if !rest.is_empty() && is_ident(last) && !C_TYPE_WORDS.contains(last)
+            && primitive(&[*last]).is_none()
+            && !matches!(rest.last(), Some(&"struct") | Some(&"union") | Some(&"enum")) => (rest.to_vec(), Some(*last)),
+        _ => (tokens[..end].to_vec(), None),
+    };
+    if typ.is_empty() {
+        return None;
+    }
+    Some((typ, name, suffix))
+}
+
+/// split tokens at sep outside of all brackets
+fn split<'a>(tokens: &[&'a str], sep: &str) -> Vec<Vec<&'a str>> {
+    let mut r = vec![Vec::new()];
+    let mut depth = 0i32;
+    for t in tokens {
+        match *t {
+            "(" | "[" | "{" => depth += 1,
+            ")" | "]" | "}" => depth -= 1,
+            t if t == sep && depth == 0 => {
+                r.push(Vec::new());
+                continue;
+            }
+            _ => (),
+        }
+        r.last_mut().unwrap().push(*t);
+    }
+    r.retain(|v| !v.is_empty());
+    r
+}
+
+/// the value of a c integer literal, like 0x10u or 0755
+fn integer(t: &str) -> Option<i128> {
+    let t = t.trim_end_matches(|c| matches!(c, 'u' | 'U' | 'l' | 'L'));
+    if let Some(hex) = t.strip_prefix("0x").or_else(|| t.strip_prefix("0X")) {
+        i128::from_str_radix(hex, 16).ok()
+    } else if t.len() > 1 && t.starts_with('0') {
+        i128::from_str_radix(&t[1..], 8).ok()
+    } else {
+        t.parse().ok()
+    }
+}
+
+/// the value of a constant integer expression, as far as headers usually write them
+fn value(tokens: &[&str]) -> Option<i128> {
+    match tokens {
+        ["(", inner @ .., ")"] => value(inner),
+        ["-", rest @ ..] => value(rest).map(|v| -v),
+        [v] => integer(v),
+        [a, "<<", b] => integer(a)?.checked_shl(u32::try_from(integer(b)?).ok()?),
+        _ => None,
+    }
+}
+
+struct Header<'a> {
+    path:       &'a Path,
+    manual:     Vec<Manual>,
+    /// typedefs of the header, which the wrappers name through c::
+    typedefs:   HashSet<String>,
+    /// the names of zz declarations so far
+    names:      HashSet<String>,
+    out:        String,
+}
+
+impl<'a> Header<'a> {
+    fn manual(&mut self, line: usize, what: String) {
+        self.manual.push(Manual{file: self.path.to_path_buf(), line, what});
+    }
+
+    /// a name zz can use, or a report of it
+    fn name(&mut self, name: &str, line: usize) -> bool {
+        if tokens::KEYWORDS.contains(&name) {
+            self.manual(line, msg!("migrate.keyword", name = name));
+            return false;
+        }
+        true
+    }
+
+    /// whether the declaration is new, #if branches may declare it more than once
+    fn fresh(&mut self, name: &str) -> bool {
+        self.names.insert(name.to_string())
+    }
+
+    /// zz types of fields and typedefs, which may name what was translated before
+    fn zz_type(&self, tokens: &[&str]) -> Result<String, String> {
+        let names = &self.names;
+        ty(tokens, &|n: &str| if names.contains(n) { Some(n.to_string()) } else { None })
+    }
+
+    /// c types in wrappers, which are those of the header
+    fn c_type(&self, tokens: &[&str]) -> Result<String, String> {
+        let typedefs = &self.typedefs;
+        ty(tokens, &|n: &str| if typedefs.contains(n) { Some(format!("c::{}", n)) } else { None })
+    }
+
+    fn define(&mut self, line: usize, text: &str) {
+        let toks : Vec<String> = tokens::significant(text).into_iter().map(|t| t.text.to_string()).collect();
+        let toks : Vec<&str> = toks.iter().map(|s| s.as_str()).collect();
+        match toks.as_slice() {
+            // include guards and flags for #ifdef
+            [_] => (),
+            // NAME(x) is a function-like macro, NAME (x) is just in parentheses
+            [name, "(", ..] if text.starts_with(&format!("{}(", name)) => {
+                self.manual(line, msg!("migrate.macro", name = name));
+            }
+            [name, v @ ..] => match value(v) {
+                Some(v) if self.name(name, line) && self.fresh(name) => {
+                    let typ = if i32::try_from(v).is_ok() {
+                        "int"
+                    } else if i64::try_from(v).is_ok() {
+                        "i64"
+                    } else {
+                        "u64"
+                    };
+                    self.out.push_str(&format!("pub const {} {} = {};\n", typ, name, v));
+                }
+                Some(_) => (),
+                None => self.manual(line, msg!("migrate.macro", name = name)),
+            },
+            [] => (),
+        }
+    }
+
+    fn fields(&mut self, name: &str, line: usize, body: &[&str]) -> Option<Vec<String>> {
+        let mut r = Vec::new();
+        for field in split(body, ";") {
+            if field.contains(&"{") || field.contains(&"(") {
+                self.manual(line, msg!("migrate.field", name = name));
+                return None;
+            }
+            let decls = split(&field, ",");
+            let (base, first, suffix) = match declarator(&decls[0]) {
+                Some(v) => v,
+                None => {
+                    self.manual(line, msg!("migrate.field", name = name));
+                    return None;
+                }
+            };
+            // int x, *y; shares the type before the first declarator
+            let base_len = base.iter().position(|t| *t == "*").unwrap_or(base.len());
+            let mut all = vec![(base.clone(), first, suffix)];
+            for d in &decls[1..] {
+                let mut t = base[..base_len].to_vec();
+                t.extend(d.iter());
+                match declarator(&t) {
+                    Some(v) => all.push(v),
+                    None => {
+                        self.manual(line, msg!("migrate.field", name = name));
+                        return None;
+                    }
+                }
+            }
+            for (typ, field_name, suffix) in all {
+                let field_name = match field_name {
+                    Some(n) if self.name(n, line) => n,
+                    _ => {
+                        self.manual(line, msg!("migrate.field", name = name));
+                        return None;
+                    }
+                };
+                match self.zz_type(&typ) {
+                    Ok(t) => r.push(format!("    {} {}{}{};", t, field_name,
+                        if suffix.starts_with(':') { " " } else { "" }, suffix)),
+                    Err(c) => {
+                        self.manual(line, msg!("migrate.type", name = name, typ = c));
+                        return None;
+                    }
+                }
+            }
+        }
+        Some(r)
+    }
+
+    fn structure(&mut self, keyword: &str, name: &str, line: usize, body: &[&str]) {
+        if !self.name(name, line) || self.names.contains(name) {
+            return;
+        }
+        if let Some(fields) = self.fields(name, line, body) {
+            self.fresh(name);
+            self.out.push_str(&format!("\npub {} {} {{\n{}\n}}\n", keyword, name, fields.join("\n")));
+        }
+    }
+
+    fn enumeration(&mut self, name: &str, line: usize, body: &[&str]) {
+        if !self.name(name, line) || self.names.contains(name) {
+            return;
+        }
+        let mut items = Vec::new();
+        for item in split(body, ",") {
+            match item.as_slice() {
+                [n] if is_ident(n) => items.push(format!("    {},", n)),
+                [n, "=", v @ ..] if is_ident(n) && value(v).is_some() => {
+                    items.push(format!("    {} = {},", n, value(v).unwrap()));
+                }
+                _ => {
+                    self.manual(line, msg!("migrate.enum", name = name, item = item.join(" ")));
+                    return;
+                }
+            }
+        }
+        self.fresh(name);
+        self.out.push_str(&format!("\npub enum {} {{\n{}\n}}\n", name, items.join("\n")));
+    }
+
+    fn prototype(&mut self, line: usize, toks: &[&str]) {
+        let open = toks.iter().position(|t| *t == "(").unwrap();
+        let close = toks.iter().rposition(|t| *t == ")").unwrap_or(0);
+        let name = match open.checked_sub(1).map(|i| toks[i]) {
+            Some(n) if is_ident(n) && !C_TYPE_WORDS.contains(&n) && primitive(&[n]).is_none() && close + 1 == toks.len() => n,
+            _ => {
+                self.manual(line, msg!("migrate.function_pointer", decl = toks.join(" ")));
+                return;
+            }
+        };
+        let ret : Vec<&str> = toks[..open - 1].iter().cloned()
+            .filter(|t| !matches!(*t, "extern" | "inline" | "static")).collect();
+        if !self.name(name, line) || !self.fresh(name) {
+            return;
+        }
+
+        let mut args = Vec::new();
+        let mut call = Vec::new();
+        let params = split(&toks[open + 1..close], ",");
+        let void = params.len() == 1 && params[0] == ["void"];
+        for (i, param) in params.iter().enumerate().filter(|_| !void) {
+            if param.as_slice() == ["..."] {
+                self.manual(line, msg!("migrate.vararg", name = name));
+                return;
+            }
+            if param.contains(&"(") {
+                self.manual(line, msg!("migrate.function_pointer", decl = toks.join(" ")));
+                return;
+            }
+            let (mut typ, arg, suffix) = match declarator(param) {
+                Some(v) => v,
+                None => {
+                    self.manual(line, msg!("migrate.type", name = name, typ = param.join(" ")));
+                    return;
+                }
+            };
+            // int a[] is int *a
+            if suffix.starts_with('[') {
+                typ.push("*");
+            }
+            let arg = match arg {
+                Some(a) if tokens::KEYWORDS.contains(&a) => format!("{}_", a),
+                Some(a) => a.to_string(),
+                None => format!("a{}", i),
+            };
+            match self.c_type(&typ) {
+                Ok(t) => args.push(format!("{} {}", t, arg)),
+                Err(c) => {
+                    self.manual(line, msg!("migrate.type", name = name, typ = c));
+                    return;
+                }
+            }
+            call.push(arg);
+        }
+        let ret = match self.c_type(&ret) {
+            Ok(t) => t,
+            Err(c) => {
+                self.manual(line, msg!("migrate.type", name = name, typ = c));
+                return;
+            }
+        };
+        let (arrow, body) = if ret == "void" {
+            (String::new(), format!("c::{}({});", name, call.join(", ")))
+        } else {
+            (format!(" -> {}", ret), format!("return c::{}({});", name, call.join(", ")))
+        };
+        self.out.push_str(&format!("\npub fn {}({}){} {{\n    {}\n}}\n", name, args.join(", "), arrow, body));
+    }
+
+    /// one top level declaration, without its ;
+    fn declaration(&mut self, decl: &[Tok]) {
+        let line = decl[0].line;
+        let mut toks : Vec<&str> = decl.iter().map(|t| t.text.as_str()).collect();
+        if toks[0] == "extern" {
+            toks.remove(0);
+        }
+        let brace = toks.iter().position(|t| *t == "{");
+        let close = toks.iter().rposition(|t| *t == "}");
+
+        match (toks.as_slice(), brace, close) {
+            // typedef struct tag { .. } name
+            (["typedef", kw @ "struct", ..], Some(b), Some(c)) | (["typedef", kw @ "union", ..], Some(b), Some(c))
+                if c + 2 == toks.len() =>
+            {
+                let name = toks[c + 1];
+                self.typedefs.insert(name.to_string());
+                self.structure(kw, name, line, &toks[b + 1..c]);
+            }
+            (["typedef", "enum", ..], Some(b), Some(c)) if c + 2 == toks.len() => {
+                let name = toks[c + 1];
+                self.typedefs.insert(name.to_string());
+                self.enumeration(name, line, &toks[b + 1..c]);
+            }
+            // struct tag { .. }
+            ([kw @ "struct", name, "{", ..], Some(_), Some(c)) | ([kw @ "union", name, "{", ..], Some(_), Some(c))
+                if c + 1 == toks.len() =>
+            {
+                self.structure(kw, name, line, &toks[3..c]);
+            }
+            (["enum", name, "{", ..], Some(_), Some(c)) if c + 1 == toks.len() => {
+                self.enumeration(name, line, &toks[3..c]);
+            }
+            // forward declarations
+            (["struct", _], None, None) | (["union", _], None, None) => (),
+            (["typedef", ..], None, None) if toks.len() > 2 && !toks.contains(&"(") => {
+                let name = toks[toks.len() - 1];
+                self.typedefs.insert(name.to_string());
+                // an opaque struct only exists in c
+                if matches!(toks[1], "struct" | "union" | "enum") {
+                    return;
+                }
+                match self.zz_type(&toks[1..toks.len() - 1]) {
+                    Ok(t) if self.name(name, line) && self.fresh(name) => {
+                        self.out.push_str(&format!("pub type {} = {};\n", name, t));
+                    }
+                    Ok(_) => (),
+                    Err(c) => self.manual(line, msg!("migrate.type", name = name, typ = c)),
+                }
+            }
+            (_, None, None) if toks.contains(&"(") && toks[0] != "typedef" => self.prototype(line, &toks),
+            (_, None, None) if toks[0] != "typedef" => {
+                self.manual(line, msg!("migrate.global", decl = toks.join(" ")));
+            }
+            _ => self.manual(line, msg!("migrate.unknown", decl = toks.join(" "))),
+        }
+    }
+}
+
+/// the text of a header with directives as empty lines, and the directives with their line
+fn directives(src: &str) -> (String, Vec<(usize, String)>) {
+    let mut text = String::new();
+    let mut found = Vec::new();
+    let mut continued : Option<(usize, String)> = None;
+    for (i, l) in src.lines().enumerate() {
+        let (line, mut d) = match continued.take() {
+            Some(v) => v,
+            None if l.trim_start().starts_with('#') => (i + 1, String::new()),
+            None => {
+                text.push_str(l);
+                text.push('\n');
+                continue;
+            }
+        };
+        text.push('\n');
+        match l.strip_suffix('\\') {
+            Some(part) => {
+                d.push_str(part);
+                d.push(' ');
+                continued = Some((line, d));
+            }
+            None => {
+                d.push_str(l);
+                found.push((line, d.trim().trim_start_matches('#').trim_start().to_string()));
+            }
+        }
+    }
+    (text, found)
+}
+
+/// the top level declarations of a header, function definitions marked with true
+fn declarations(text: &str) -> Vec<(Vec<Tok>, bool)> {
+    let toks : Vec<Tok> = tokens::significant(text).into_iter()
+        .map(|t| Tok{text: t.text.to_string(), line: t.line}).collect();
+    let mut r = Vec::new();
+    let mut cur : Vec<Tok> = Vec::new();
+    let mut depth = 0;
+    let mut extern_c = 0;
+    for t in toks {
+        match t.text.as_str() {
+            // extern "C" { .. } around the whole header
+            "{" if depth == 0 && cur.len() == 2 && cur[0].text == "extern" => {
+                extern_c += 1;
+                cur.clear();
+                continue;
+            }
+            "}" if depth == 0 && extern_c > 0 => {
+                extern_c -= 1;
+                continue;
+            }
+            ";" if depth == 0 => {
+                if !cur.is_empty() {
+                    r.push((std::mem::take(&mut cur), false));
+                }
+                continue;
+            }
+            "{" => depth += 1,
+            "}" => {
+                depth -= 1;
+                let head = cur.iter().position(|t| t.text == "{").unwrap_or(0);
+                if depth == 0 && cur[..head].iter().any(|t| t.text == "(") && !cur[..head].iter().any(|t| t.text == "=") {
+                    cur.push(t);
+                    r.push((std::mem::take(&mut cur), true));
+                    continue;
+                }
+            }
+            _ => (),
+        }
+        cur.push(t);
+    }
+    r
+}
+
+/// the zz module of a header
+fn translate(path: &Path, include: &str) -> (String, Vec<Manual>) {
+    let src = std::fs::read_to_string(path).expect(&format!("cannot read {}", path.display()));
+    let (text, found) = directives(&src);
+
+    let mut h = Header {
+        path,
+        manual:     Vec::new(),
+        typedefs:   HashSet::new(),
+        names:      HashSet::new(),
+        out:        String::new(),
+    };
+
+    let mut guard = None;
+    let mut conditional = false;
+    for (i, (line, d)) in found.iter().enumerate() {
+        let (keyword, rest) = d.split_at(d.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(d.len()));
+        let rest = rest.trim();
+        match keyword {
+            "define" if guard == Some(rest) => (),
+            "define" => h.define(*line, rest),
+            // #ifndef FOO_H followed by #define FOO_H
+            "ifndef" if guard.is_none() && found.get(i + 1).map(|n| n.1 == format!("define {}", rest)).unwrap_or(false) => {
+                guard = Some(rest);
+            }
+            "if" | "ifdef" | "ifndef" if !rest.contains("__cplusplus") && !conditional => {
+                conditional = true;
+                h.manual(*line, msg!("migrate.conditional"));
+            }
+            _ => (),
+        }
+    }
+    for (decl, body) in declarations(&text) {
+        if body {
+            let open = decl.iter().position(|t| t.text == "(").unwrap_or(1);
+            h.manual(decl[0].line, msg!("migrate.body", name = decl[open.max(1) - 1].text));
+        } else {
+            h.declaration(&decl);
+        }
+    }
+
+    let out = format!("\
+/// translated from {} by zz migrate
+///
+/// the fns call the c implementation, with the c types of the header.
+/// the structs and enums are the same in zz, for when the implementation moves here.
+
+using <{}> as c;
+{}", path.display(), include, h.out);
+    (out, h.manual)
+}
+
+/// files below dir with the extension, without hidden directories and build output
+fn find(dir: &Path, extension: &str, into: &mut Vec<PathBuf>) {
+    let mut entries : Vec<PathBuf> = std::fs::read_dir(dir).into_iter().flatten().flatten().map(|e| e.path()).collect();
+    entries.sort();
+    for path in entries {
+        let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        if name.starts_with('.') || name == "target" {
+            continue;
+        }
+        if path.is_dir() {
+            find(&path, extension, into);
+        } else if path.extension().map(|e| e == extension).unwrap_or(false) {
+            into.push(path);
+        }
+    }
+}
+
+fn relative(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root).unwrap_or(path).to_string_lossy().to_string()
+}
+
+/// a module name for a header file name
+fn module_name(path: &Path) -> String {
+    let stem = path.file_stem().unwrap().to_string_lossy();
+    let mut r : String = stem.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+    if !is_ident(&r) || tokens::KEYWORDS.contains(&r.as_str()) || r == "main" {
+        r = format!("{}_h", r);
+    }
+    r
+}
+
+fn write_new(path: &Path, text: &str, manual: &mut Vec<Manual>) -> bool {
+    if path.exists() {
+        manual.push(Manual{file: path.to_path_buf(), line: 0, what: msg!("migrate.exists")});
+        return false;
+    }
+    let mut f = std::fs::File::create(path).expect(&format!("cannot write {}", path.display()));
+    f.write_all(text.as_bytes()).expect(&format!("cannot write {}", path.display()));
+    true
+}
+
+pub fn main(dir: &Path) {
+    if !dir.is_dir() {
+        error!("{}", msg!("migrate_not_a_directory", dir = dir.display()));
+        std::process::exit(9);
+    }
+    let mut manual = Vec::new();
+
+    let mut headers = Vec::new();
+    find(dir, "h", &mut headers);
+    let mut sources = Vec::new();
+    find(dir, "c", &mut sources);
+
+    let mut cincludes : Vec<String> = Vec::new();
+    for h in &headers {
+        let d = relative(dir, h.parent().unwrap());
+        let d = if d.is_empty() { ".".to_string() } else { d };
+        if !cincludes.contains(&d) {
+            cincludes.push(d);
+        }
+    }
+    // a program's main would end up in the library, it is the one file to port by hand
+    let mut cobjects = Vec::new();
+    for c in &sources {
+        let src = std::fs::read_to_string(c).unwrap_or_default();
+        let main = src.lines().position(|l| {
+            let l = l.trim_start();
+            l.starts_with("int main(") || l.starts_with("int main (") || l.starts_with("int main(void")
+        });
+        match main {
+            Some(line) => manual.push(Manual{file: c.clone(), line: line + 1, what: msg!("migrate.main")}),
+            None => cobjects.push(relative(dir, c)),
+        }
+    }
+
+    let name = dir.canonicalize().ok()
+        .and_then(|d| d.file_name().map(|n| n.to_string_lossy().to_string()))
+        .unwrap_or_else(|| "migrated".to_string());
+    let mut config = project::new_config(name);
+    config.project.cincludes = cincludes;
+    config.project.cobjects  = cobjects;
+    if write_new(&dir.join("zz.toml"), &toml::to_string(&config).unwrap(), &mut manual) {
+        info!("{}", msg!("migrate_config", path = dir.join("zz.toml").display(),
+            cobjects = config.project.cobjects.len()));
+    }
+
+    std::fs::create_dir_all(dir.join("src")).expect("create src dir");
+    let mut modules : Vec<String> = Vec::new();
+    for h in &headers {
+        let module = module_name(h);
+        if modules.contains(&module) {
+            manual.push(Manual{file: h.clone(), line: 0, what: msg!("migrate.module_taken", module = module)});
+            continue;
+        }
+        modules.push(module.clone());
+        let include = h.file_name().unwrap().to_string_lossy().to_string();
+        let (text, m) = translate(h, &include);
+        let path = dir.join("src").join(format!("{}.zz", module));
+        if write_new(&path, &text, &mut manual) {
+            info!("{}", msg!("migrate_header", header = relative(dir, h), module = relative(dir, &path),
+                manual = m.len()));
+        }
+        manual.extend(m);
+    }
+
+    for m in &manual {
+        if m.line > 0 {
+            warn!("{}:{}: {}", relative(dir, &m.file), m.line, m.what);
+        } else {
+            warn!("{}: {}", relative(dir, &m.file), m.what);
+        }
+    }
+    info!("{}", msg!("migrate_done", headers = modules.len(), manual = manual.len()));
+}
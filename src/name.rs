@@ -1,19 +1,180 @@
 use std::hash::{Hash, Hasher};
 use serde::{Serialize, Deserialize};
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::sync::{Arc, RwLock};
+
+/// one part of a name. identifiers are interned, equal ones share one allocation
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Ident(Arc<str>);
+
+static IDENTS: RwLock<Option<HashSet<Arc<str>>>> = RwLock::new(None);
+
+thread_local! {
+    // what this thread interned before, so the shared table is only locked for identifiers new to the thread
+    static SEEN: RefCell<HashSet<Arc<str>>> = RefCell::new(HashSet::new());
+}
+
+impl Ident {
+    pub fn new(s: &str) -> Ident {
+        SEEN.with(|seen| {
+            if let Some(s) = seen.borrow().get(s) {
+                return Ident(s.clone());
+            }
+            let shared = IDENTS.read().unwrap().as_ref().and_then(|idents| idents.get(s).cloned());
+            let shared = match shared {
+                Some(s) => s,
+                None => {
+                    let mut idents = IDENTS.write().unwrap();
+                    let idents = idents.get_or_insert_with(HashSet::new);
+                    match idents.get(s) {
+                        Some(s) => s.clone(),
+                        None => {
+                            let s : Arc<str> = s.into();
+                            idents.insert(s.clone());
+                            s
+                        }
+                    }
+                }
+            };
+            seen.borrow_mut().insert(shared.clone());
+            Ident(shared)
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Default for Ident {
+    fn default() -> Self {
+        Ident::new("")
+    }
+}
+
+impl std::ops::Deref for Ident {
+    type Target = str;
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::borrow::Borrow<str> for Ident {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for Ident {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for Ident {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", &*self.0)
+    }
+}
+
+impl std::fmt::Display for Ident {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl PartialEq<str> for Ident {
+    fn eq(&self, other: &str) -> bool {
+        &*self.0 == other
+    }
+}
+
+impl PartialEq<&str> for Ident {
+    fn eq(&self, other: &&str) -> bool {
+        &*self.0 == *other
+    }
+}
+
+impl PartialEq<String> for Ident {
+    fn eq(&self, other: &String) -> bool {
+        &*self.0 == other.as_str()
+    }
+}
+
+impl PartialEq<Ident> for str {
+    fn eq(&self, other: &Ident) -> bool {
+        self == &*other.0
+    }
+}
+
+impl PartialEq<Ident> for &str {
+    fn eq(&self, other: &Ident) -> bool {
+        *self == &*other.0
+    }
+}
+
+impl PartialEq<Ident> for String {
+    fn eq(&self, other: &Ident) -> bool {
+        self.as_str() == &*other.0
+    }
+}
+
+impl From<&str> for Ident {
+    fn from(s: &str) -> Self {
+        Ident::new(s)
+    }
+}
+
+impl From<String> for Ident {
+    fn from(s: String) -> Self {
+        Ident::new(&s)
+    }
+}
+
+impl From<std::borrow::Cow<'_, str>> for Ident {
+    fn from(s: std::borrow::Cow<'_, str>) -> Self {
+        Ident::new(&s)
+    }
+}
+
+impl From<&String> for Ident {
+    fn from(s: &String) -> Self {
+        Ident::new(s)
+    }
+}
+
+impl From<Ident> for String {
+    fn from(s: Ident) -> Self {
+        s.0.to_string()
+    }
+}
+
+impl Serialize for Ident {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Ident {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Ident::from)
+    }
+}
 
 #[derive(Debug, Default, Clone, PartialEq, PartialOrd, Eq, Ord, Serialize, Deserialize)]
-pub struct Name (pub Vec<String>);
+pub struct Name (pub Vec<Ident>);
 
 
 impl Name {
-    pub fn push(&mut self, v: String) {
-        for v in v.split("::") {
-            self.0.push(v.to_string());
+    pub fn push<S: AsRef<str>>(&mut self, v: S) {
+        for v in v.as_ref().split("::") {
+            self.0.push(Ident::new(v));
         }
     }
 
     pub fn pop(&mut self) -> Option<String> {
-        self.0.pop()
+        self.0.pop().map(String::from)
     }
 
     pub fn len(&self) -> usize {
@@ -52,13 +213,19 @@ impl std::fmt::Display for Name {
 
 impl From<&str> for Name {
     fn from(s: &str) -> Self {
-        Name(s.split("::").map(|s|s.to_string()).collect())
+        Name(s.split("::").map(Ident::new).collect())
+    }
+}
+
+impl From<&Ident> for Name {
+    fn from(s: &Ident) -> Self {
+        Name::from(s.as_str())
     }
 }
 
 impl From<&String> for Name {
     fn from(s: &String) -> Self {
-        Name(s.split("::").map(|s|s.to_string()).collect())
+        Name(s.split("::").map(Ident::new).collect())
     }
 }
 
@@ -0,0 +1,165 @@
+/// noreturn functions
+
+use super::ast;
+use super::cheader;
+use super::closure::find_def;
+use super::loader;
+use super::name::Name;
+use super::parser::emit_error;
+use std::collections::HashMap;
+
+struct Checker<'a> {
+    md:             &'a ast::Module,
+    modules:        &'a HashMap<Name, loader::Module>,
+    headers:        &'a mut cheader::Headers,
+    findings:       Vec<(ast::Location, String, String)>,
+}
+
+/// whether block contains a break that leaves the loop it is the body of
+fn breaks(block: &ast::Block) -> bool {
+    block.statements.iter().any(|stm| match stm.as_ref() {
+        ast::Statement::Break{..} => true,
+        ast::Statement::Block(b) | ast::Statement::Unsafe(b) => breaks(b),
+        ast::Statement::If{branches} => branches.iter().any(|(_, _, b)| breaks(b)),
+        ast::Statement::Switch{cases, default, ..} => {
+            cases.iter().any(|(_, b)| breaks(b)) || default.as_ref().map(breaks).unwrap_or(false)
+        }
+        _ => false,
+    })
+}
+
+fn is_true(expr: &ast::Expression) -> bool {
+    matches!(expr, ast::Expression::Literal{value: ast::LiteralValue::Bool(true), ..})
+}
+
+impl<'a> Checker<'a> {
+    /// the name of the noreturn function stm calls, if it is such a call
+    fn call(&mut self, stm: &ast::Statement) -> Option<String> {
+        let (name, loc) = match stm {
+            ast::Statement::Expr{expr: ast::Expression::Call{name, loc, ..}, ..} => (name, loc),
+            _ => return None,
+        };
+        match name.as_ref() {
+            ast::Expression::Name(ast::Typed{t: ast::Type::Other(name), ..}) if name.is_absolute() => {
+                if let Some(sig) = self.headers.function(name, loc) {
+                    return if sig.noreturn { name.0.last().map(|n| n.to_string()) } else { None };
+                }
+                match find_def(name, self.md, self.modules) {
+                    Some(ast::Local{def: ast::Def::Function{attr, ..}, ..}) if attr.contains_key("noreturn") => {
+                        name.0.last().map(|n| n.to_string())
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// whether execution can continue after stm
+    fn falls_through(&mut self, stm: &ast::Statement) -> bool {
+        if self.call(stm).is_some() {
+            return false;
+        }
+        match stm {
+            ast::Statement::Return{..} | ast::Statement::CBlock{..} => false,
+            ast::Statement::Block(b) | ast::Statement::Unsafe(b) => self.block_falls_through(b),
+            ast::Statement::If{branches} => {
+                branches.last().map(|(_, cond, _)| cond.is_some()).unwrap_or(true)
+                || branches.iter().any(|(_, _, b)| self.block_falls_through(b))
+            }
+            ast::Statement::While{expr, body} => !is_true(expr) || breaks(body),
+            ast::Statement::For{e2, body, ..} => e2.as_ref().map(|e| !is_true(e)).unwrap_or(false) || breaks(body),
+            _ => true,
+        }
+    }
+
+    fn block_falls_through(&mut self, block: &ast::Block) -> bool {
+        block.statements.last().map(|stm| self.falls_through(stm)).unwrap_or(true)
+    }
+
+    /// report statements behind a noreturn call, and return statements if inside is a noreturn function
+    fn block(&mut self, block: &ast::Block, inside: Option<&str>) {
+        let mut after : Option<String> = None;
+        for stm in &block.statements {
+            if let Some(callee) = &after {
+                if !matches!(stm.as_ref(), ast::Statement::Label{..}) {
+                    self.findings.push((stm_loc(stm, &block.end), msg!("noreturn_unreachable", name = callee), msg!("noreturn_unreachable.note", name = callee)));
+                    after = None;
+                }
+            }
+            if let Some(callee) = self.call(stm) {
+                after = Some(callee);
+            }
+            match stm.as_ref() {
+                ast::Statement::Return{loc, ..} => {
+                    if let Some(name) = inside {
+                        self.findings.push((loc.clone(), msg!("noreturn_returns", name = name), msg!("noreturn_returns.note")));
+                    }
+                }
+                ast::Statement::Block(b) | ast::Statement::Unsafe(b) => self.block(b, inside),
+                ast::Statement::If{branches} => {
+                    for (_, _, b) in branches {
+                        self.block(b, inside);
+                    }
+                }
+                ast::Statement::While{body, ..} | ast::Statement::For{body, ..} => self.block(body, inside),
+                ast::Statement::Switch{cases, default, ..} => {
+                    for (_, b) in cases {
+                        self.block(b, inside);
+                    }
+                    if let Some(b) = default {
+                        self.block(b, inside);
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+/// where a statement is, for statements that have no location of their own
+fn stm_loc(stm: &ast::Statement, end: &ast::Location) -> ast::Location {
+    match stm {
+        ast::Statement::Mark{loc, ..}
+        | ast::Statement::Label{loc, ..}
+        | ast::Statement::Assign{loc, ..}
+        | ast::Statement::Expr{loc, ..}
+        | ast::Statement::Switch{loc, ..}
+        | ast::Statement::Continue{loc}
+        | ast::Statement::Break{loc}
+        | ast::Statement::Return{loc, ..}
+        | ast::Statement::Var{loc, ..}
+        | ast::Statement::CBlock{loc, ..} => loc.clone(),
+        ast::Statement::While{expr, ..} => expr.loc().clone(),
+        ast::Statement::If{branches} => branches.first().map(|b| b.0.clone()).unwrap_or(end.clone()),
+        _ => end.clone(),
+    }
+}
+
+/// check every function of md. returns false if code follows a noreturn call, or a noreturn function returns
+pub fn check(md: &ast::Module, modules: &HashMap<Name, loader::Module>, headers: &mut cheader::Headers) -> bool {
+    let mut checker = Checker{
+        md,
+        modules,
+        headers,
+        findings:   Vec::new(),
+    };
+    for local in &md.locals {
+        let (attr, body, loc) = match &local.def {
+            ast::Def::Function{attr, body, nameloc, ..} => (Some(attr), body, nameloc),
+            ast::Def::Test{body, ..} => (None, body, &local.loc),
+            _ => continue,
+        };
+        let name = local.name.rsplit("::").next().unwrap_or(&local.name);
+        let noreturn = attr.map(|a| a.contains_key("noreturn")).unwrap_or(false);
+        checker.block(body, if noreturn { Some(name) } else { None });
+        if noreturn && checker.block_falls_through(body) {
+            checker.findings.push((loc.clone(), msg!("noreturn_returns", name = name), msg!("noreturn_falls_through.note")));
+        }
+    }
+
+    for (loc, message, note) in &checker.findings {
+        emit_error(message, &[(loc.clone(), note)]);
+    }
+    checker.findings.is_empty()
+}
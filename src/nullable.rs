@@ -0,0 +1,419 @@
+/// null pointer checks on pointers tagged nullable
+
+use super::ast;
+use super::closure::find_def;
+use super::loader;
+use super::name::Name;
+use super::parser::emit_error;
+use std::collections::{HashMap, HashSet};
+
+pub const TAG : &str = "nullable";
+
+/// whether the value of this type may be null, by the tags on its outermost pointer
+pub fn nullable(typed: &ast::Typed) -> bool {
+    typed.ptr.last().map(|p| p.tags.contains(TAG)).unwrap_or(false)
+}
+
+/// names of pointers that may be null at a point in the function
+type State = HashSet<String>;
+
+struct Loop {
+    breaks:     Vec<State>,
+    continues:  Vec<State>,
+}
+
+struct Checker<'a> {
+    md:             &'a ast::Module,
+    modules:        &'a HashMap<Name, loader::Module>,
+    pointers:       HashSet<String>,
+    loops:          Vec<Loop>,
+    findings:       Vec<(ast::Location, String, String)>,
+}
+
+fn merge(mut a: State, b: &State) -> State {
+    a.extend(b.iter().cloned());
+    a
+}
+
+fn is_zero(expr: &ast::Expression) -> bool {
+    match expr {
+        ast::Expression::Literal{value: ast::LiteralValue::Integer{value: 0, ..}, ..} => true,
+        ast::Expression::Cast{expr, ..} => is_zero(expr),
+        _ => false,
+    }
+}
+
+fn local_name(expr: &ast::Expression) -> Option<String> {
+    match expr {
+        ast::Expression::Name(ast::Typed{t: ast::Type::Other(name), ..}) if !name.is_absolute() => Some(name.to_string()),
+        _ => None,
+    }
+}
+
+impl<'a> Checker<'a> {
+    fn report(&mut self, loc: &ast::Location, message: String, note: String) {
+        let seen = self.findings.iter().any(|(l, m, _)| l.file == loc.file && (l.start, l.end) == (loc.start, loc.end) && *m == message);
+        if !seen {
+            self.findings.push((loc.clone(), message, note));
+        }
+    }
+
+    fn callee(&self, name: &ast::Expression) -> Option<&'a ast::Local> {
+        match name {
+            ast::Expression::Name(ast::Typed{t: ast::Type::Other(name), ..}) if name.is_absolute() => {
+                find_def(name, self.md, self.modules)
+            }
+            _ => None,
+        }
+    }
+
+    /// whether expr may evaluate to null
+    fn maybe_null(&self, expr: &ast::Expression, state: &State) -> bool {
+        match expr {
+            ast::Expression::Literal{..} => is_zero(expr),
+            ast::Expression::Cast{expr, ..} => self.maybe_null(expr, state),
+            ast::Expression::Call{name, ..} => match self.callee(name) {
+                Some(ast::Local{def: ast::Def::Function{ret: Some(ret), ..}, ..}) => nullable(&ret.typed),
+                _ => false,
+            },
+            _ => local_name(expr).map(|n| state.contains(&n)).unwrap_or(false),
+        }
+    }
+
+    /// the state after cond evaluated to truth
+    fn refine(&self, cond: &ast::Expression, truth: bool, state: &State) -> State {
+        match cond {
+            ast::Expression::Infix{lhs, rhs, op: op @ (ast::InfixOperator::Equals | ast::InfixOperator::Nequals), ..} => {
+                let name = if is_zero(rhs) { local_name(lhs) } else if is_zero(lhs) { local_name(rhs) } else { None };
+                let mut state = state.clone();
+                if let Some(name) = name {
+                    // p != 0 is true or p == 0 is false
+                    if truth == (*op == ast::InfixOperator::Nequals) {
+                        state.remove(&name);
+                    }
+                }
+                state
+            }
+            ast::Expression::Infix{lhs, rhs, op: ast::InfixOperator::Booland, ..} => {
+                let left = self.refine(lhs, true, state);
+                if truth {
+                    self.refine(rhs, true, &left)
+                } else {
+                    merge(self.refine(lhs, false, state), &self.refine(rhs, false, &left))
+                }
+            }
+            ast::Expression::Infix{lhs, rhs, op: ast::InfixOperator::Boolor, ..} => {
+                let left = self.refine(lhs, false, state);
+                if truth {
+                    merge(self.refine(lhs, true, state), &self.refine(rhs, true, &left))
+                } else {
+                    self.refine(rhs, false, &left)
+                }
+            }
+            ast::Expression::UnaryPre{op: ast::PrefixOperator::Boolnot, expr, ..} => self.refine(expr, !truth, state),
+            // safe(p) in static_attest
+            ast::Expression::Call{name, args, ..} if truth && args.len() == 1 => {
+                let mut state = state.clone();
+                if let ast::Expression::Name(ast::Typed{t: ast::Type::Other(n), ..}) = name.as_ref() {
+                    if n.0.last().map(|s| s == "safe").unwrap_or(false) {
+                        if let Some(name) = local_name(&args[0]) {
+                            state.remove(&name);
+                        }
+                    }
+                }
+                state
+            }
+            // if p
+            _ => {
+                let mut state = state.clone();
+                if truth {
+                    if let Some(name) = local_name(cond) {
+                        if self.pointers.contains(&name) {
+                            state.remove(&name);
+                        }
+                    }
+                }
+                state
+            }
+        }
+    }
+
+    fn deref(&mut self, expr: &ast::Expression, state: &State, loc: &ast::Location) {
+        if let Some(name) = local_name(expr) {
+            if state.contains(&name) {
+                self.report(loc, msg!("nullable_deref", name = name), msg!("nullable_deref.note"));
+            }
+        }
+    }
+
+    /// report dereferences and arguments of expr that may be null
+    fn expr(&mut self, expr: &ast::Expression, state: &State) {
+        match expr {
+            ast::Expression::Name(_)
+            | ast::Expression::LiteralString{..}
+            | ast::Expression::LiteralChar{..}
+            | ast::Expression::Literal{..} => {}
+            ast::Expression::MemberAccess{lhs, op, loc, ..} => {
+                if op == "->" {
+                    self.deref(lhs, state, loc);
+                }
+                self.expr(lhs, state);
+            }
+            ast::Expression::ArrayAccess{lhs, rhs, loc} => {
+                self.deref(lhs, state, loc);
+                self.expr(lhs, state);
+                self.expr(rhs, state);
+            }
+            ast::Expression::UnaryPre{op: ast::PrefixOperator::Deref, expr, loc} => {
+                self.deref(expr, state, loc);
+                self.expr(expr, state);
+            }
+            ast::Expression::Infix{lhs, rhs, op: ast::InfixOperator::Booland, ..} => {
+                self.expr(lhs, state);
+                let right = self.refine(lhs, true, state);
+                self.expr(rhs, &right);
+            }
+            ast::Expression::Infix{lhs, rhs, op: ast::InfixOperator::Boolor, ..} => {
+                self.expr(lhs, state);
+                let right = self.refine(lhs, false, state);
+                self.expr(rhs, &right);
+            }
+            ast::Expression::Infix{lhs, rhs, ..} => {
+                self.expr(lhs, state);
+                self.expr(rhs, state);
+            }
+            ast::Expression::Call{name, args, ..} => {
+                self.expr(name, state);
+                for arg in args {
+                    self.expr(arg, state);
+                }
+                let fargs = match self.callee(name) {
+                    Some(ast::Local{def: ast::Def::Function{args, ..}, ..}) => args,
+                    _ => return,
+                };
+                for (farg, arg) in fargs.iter().zip(args.iter()) {
+                    if farg.typed.ptr.is_empty() || nullable(&farg.typed) || farg.tags.contains("unsafe") {
+                        continue;
+                    }
+                    if self.maybe_null(arg, state) {
+                        self.report(arg.loc(), msg!("nullable_argument", name = farg.name), msg!("nullable_argument.note", name = farg.name));
+                    }
+                }
+            }
+            ast::Expression::Cast{expr, ..}
+            | ast::Expression::UnaryPost{expr, ..}
+            | ast::Expression::UnaryPre{expr, ..} => self.expr(expr, state),
+            ast::Expression::StructInit{fields, ..} => {
+                for (_, e) in fields {
+                    self.expr(e, state);
+                }
+            }
+            ast::Expression::ArrayInit{fields, ..} => {
+                for e in fields {
+                    self.expr(e, state);
+                }
+            }
+        }
+    }
+
+    /// the state after block, or None if it never falls through
+    fn block(&mut self, block: &ast::Block, state: State) -> Option<State> {
+        let mut state = state;
+        for stm in &block.statements {
+            state = self.statement(stm, state)?;
+        }
+        Some(state)
+    }
+
+    /// a loop with cond checked before every iteration and next run after it
+    fn looped(&mut self, cond: Option<&ast::Expression>, body: &ast::Block, next: &[Box<ast::Statement>], state: State) -> Option<State> {
+        let mut head = state;
+        loop {
+            if let Some(cond) = cond {
+                self.expr(cond, &head);
+            }
+            let inside = match cond {
+                Some(cond) => self.refine(cond, true, &head),
+                None => head.clone(),
+            };
+            self.loops.push(Loop{breaks: Vec::new(), continues: Vec::new()});
+            let end = self.block(body, inside);
+            let lp = self.loops.pop().unwrap();
+
+            let mut again = end.into_iter().chain(lp.continues).reduce(|a, b| merge(a, &b));
+            for stm in next {
+                again = match again {
+                    Some(s) => self.statement(stm, s),
+                    None => None,
+                };
+            }
+            let again = merge(head.clone(), &again.unwrap_or_default());
+            if again == head {
+                let exit = cond.map(|cond| self.refine(cond, false, &head));
+                return exit.into_iter().chain(lp.breaks).reduce(|a, b| merge(a, &b));
+            }
+            head = again;
+        }
+    }
+
+    fn statement(&mut self, stm: &ast::Statement, mut state: State) -> Option<State> {
+        match stm {
+            ast::Statement::Mark{lhs, ..} => self.expr(lhs, &state),
+            ast::Statement::Label{..} | ast::Statement::CBlock{..} => {}
+            ast::Statement::Break{..} => {
+                if let Some(lp) = self.loops.last_mut() {
+                    lp.breaks.push(state);
+                }
+                return None;
+            }
+            ast::Statement::Continue{..} => {
+                if let Some(lp) = self.loops.last_mut() {
+                    lp.continues.push(state);
+                }
+                return None;
+            }
+            ast::Statement::Return{expr, ..} => {
+                if let Some(expr) = expr {
+                    self.expr(expr, &state);
+                }
+                return None;
+            }
+            ast::Statement::Block(b) | ast::Statement::Unsafe(b) => return self.block(b, state),
+            ast::Statement::Expr{expr, ..} => {
+                self.expr(expr, &state);
+                // static_attest(p != 0), assert(p != 0)
+                if let ast::Expression::Call{name, args, ..} = expr {
+                    if let ast::Expression::Name(ast::Typed{t: ast::Type::Other(n), ..}) = name.as_ref() {
+                        let attest = n.0.last().map(|s| s == "static_attest" || s == "assert").unwrap_or(false);
+                        if attest && args.len() == 1 {
+                            state = self.refine(&args[0], true, &state);
+                        }
+                    }
+                }
+            }
+            ast::Statement::Var{name, typed, array, assign, ..} => {
+                if let Some(Some(size)) = array {
+                    self.expr(size, &state);
+                }
+                if let Some(assign) = assign {
+                    self.expr(assign, &state);
+                }
+                if array.is_none() && !typed.ptr.is_empty() {
+                    self.pointers.insert(name.clone());
+                    match assign {
+                        Some(assign) if self.maybe_null(assign, &state) => state.insert(name.clone()),
+                        _ => state.remove(name),
+                    };
+                }
+            }
+            ast::Statement::Assign{lhs, rhs, op, ..} => {
+                self.expr(lhs, &state);
+                self.expr(rhs, &state);
+                if let (Some(name), ast::AssignOperator::Eq) = (local_name(lhs), op) {
+                    if self.pointers.contains(&name) {
+                        if self.maybe_null(rhs, &state) {
+                            state.insert(name);
+                        } else {
+                            state.remove(&name);
+                        }
+                    }
+                }
+            }
+            ast::Statement::If{branches} => {
+                let mut after : Option<State> = None;
+                let mut rest = state;
+                let mut has_else = false;
+                for (_, cond, body) in branches {
+                    let inside = match cond {
+                        Some(cond) => {
+                            self.expr(cond, &rest);
+                            let inside = self.refine(cond, true, &rest);
+                            rest = self.refine(cond, false, &rest);
+                            inside
+                        }
+                        None => {
+                            has_else = true;
+                            rest.clone()
+                        }
+                    };
+                    if let Some(end) = self.block(body, inside) {
+                        after = Some(match after {
+                            Some(a) => merge(a, &end),
+                            None => end,
+                        });
+                    }
+                }
+                if !has_else {
+                    after = Some(match after {
+                        Some(a) => merge(a, &rest),
+                        None => rest,
+                    });
+                }
+                return after;
+            }
+            ast::Statement::While{expr, body} => return self.looped(Some(expr), body, &[], state),
+            ast::Statement::For{e1, e2, e3, body} => {
+                for s in e1 {
+                    state = self.statement(s, state)?;
+                }
+                return self.looped(e2.as_ref(), body, e3, state);
+            }
+            ast::Statement::Switch{expr, cases, default, ..} => {
+                self.expr(expr, &state);
+                let mut after : Option<State> = None;
+                let bodies = cases.iter().map(|(_, b)| b).chain(default.iter());
+                for body in bodies {
+                    if let Some(end) = self.block(body, state.clone()) {
+                        after = Some(match after {
+                            Some(a) => merge(a, &end),
+                            None => end,
+                        });
+                    }
+                }
+                if default.is_none() {
+                    after = Some(match after {
+                        Some(a) => merge(a, &state),
+                        None => state,
+                    });
+                }
+                return after;
+            }
+        }
+        Some(state)
+    }
+}
+
+/// check every function of md. returns false if something possibly null is dereferenced or passed on
+pub fn check(md: &ast::Module, modules: &HashMap<Name, loader::Module>) -> bool {
+    let mut findings = Vec::new();
+    for local in &md.locals {
+        let (args, body) = match &local.def {
+            ast::Def::Function{args, body, ..} => (&args[..], body),
+            ast::Def::Test{body, ..} => (&[][..], body),
+            _ => continue,
+        };
+        let mut checker = Checker{
+            md,
+            modules,
+            pointers:   HashSet::new(),
+            loops:      Vec::new(),
+            findings:   Vec::new(),
+        };
+        let mut state = State::new();
+        for arg in args {
+            if !arg.typed.ptr.is_empty() {
+                checker.pointers.insert(arg.name.clone());
+                if nullable(&arg.typed) {
+                    state.insert(arg.name.clone());
+                }
+            }
+        }
+        checker.block(body, state);
+        findings.extend(checker.findings);
+    }
+
+    for (loc, message, note) in &findings {
+        emit_error(message, &[(loc.clone(), note)]);
+    }
+    findings.is_empty()
+}
@@ -0,0 +1,371 @@
+/// ownership of values tagged owned
+
+use super::ast;
+use super::closure::find_def;
+use super::loader;
+use super::name::Name;
+use super::parser::emit_error;
+use std::collections::HashMap;
+
+pub const TAG : &str = "owned";
+
+/// the mark that gives up ownership, as in h is dropped
+pub const DROPPED : &str = "dropped";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Status {
+    Owned,
+    Moved,
+    // moved on some paths, but not all
+    Maybe,
+}
+
+/// status of the owned names at a point in the function
+type State = HashMap<String, Status>;
+
+struct Loop {
+    breaks:     Vec<State>,
+    continues:  Vec<State>,
+}
+
+struct Checker<'a> {
+    md:             &'a ast::Module,
+    modules:        &'a HashMap<Name, loader::Module>,
+    declared:       HashMap<String, ast::Location>,
+    moved:          HashMap<String, ast::Location>,
+    loops:          Vec<Loop>,
+    findings:       Vec<(String, Vec<(ast::Location, String)>)>,
+}
+
+fn merge(mut a: State, b: &State) -> State {
+    for (name, status) in b {
+        let merged = match a.get(name) {
+            Some(s) if s != status => Status::Maybe,
+            _ => *status,
+        };
+        a.insert(name.clone(), merged);
+    }
+    a
+}
+
+fn local_name(expr: &ast::Expression) -> Option<String> {
+    match expr {
+        ast::Expression::Name(ast::Typed{t: ast::Type::Other(name), ..}) if !name.is_absolute() => Some(name.to_string()),
+        _ => None,
+    }
+}
+
+impl<'a> Checker<'a> {
+    fn report(&mut self, message: String, notes: Vec<(ast::Location, String)>) {
+        let loc = &notes[0].0;
+        let seen = self.findings.iter().any(|(m, n)| n[0].0.file == loc.file && (n[0].0.start, n[0].0.end) == (loc.start, loc.end) && *m == message);
+        if !seen {
+            self.findings.push((message, notes));
+        }
+    }
+
+    fn callee(&self, name: &ast::Expression) -> Option<&'a ast::Local> {
+        match name {
+            ast::Expression::Name(ast::Typed{t: ast::Type::Other(name), ..}) if name.is_absolute() => {
+                find_def(name, self.md, self.modules)
+            }
+            _ => None,
+        }
+    }
+
+    /// the owned name expr is, if any
+    fn owned(&self, expr: &ast::Expression, state: &State) -> Option<String> {
+        local_name(expr).filter(|n| state.contains_key(n))
+    }
+
+    fn used(&mut self, name: &str, loc: &ast::Location, state: &State) {
+        let message = match state.get(name) {
+            Some(Status::Moved) => msg!("owned_use_after_move", name = name),
+            Some(Status::Maybe) => msg!("owned_maybe_moved", name = name),
+            _ => return,
+        };
+        let mut notes = vec![(loc.clone(), msg!("used_here"))];
+        if let Some(moved) = self.moved.get(name) {
+            notes.push((moved.clone(), msg!("owned_moved_here")));
+        }
+        self.report(message, notes);
+    }
+
+    fn consume(&mut self, name: &str, loc: &ast::Location, state: &mut State) {
+        self.used(name, loc, state);
+        state.insert(name.to_string(), Status::Moved);
+        self.moved.insert(name.to_string(), loc.clone());
+    }
+
+    /// report every name that still owns something at loc
+    fn leaks(&mut self, state: &State, loc: &ast::Location) {
+        let mut names : Vec<&String> = state.iter().filter(|(_, s)| **s != Status::Moved).map(|(n, _)| n).collect();
+        names.sort();
+        for name in names {
+            let mut notes = vec![(loc.clone(), msg!("owned_leaked.note", name = name))];
+            if let Some(declared) = self.declared.get(name) {
+                notes.push((declared.clone(), msg!("defined_here")));
+            }
+            self.report(msg!("owned_leaked", name = name), notes);
+        }
+    }
+
+    /// moves and uses in expr, in the order they happen
+    fn expr(&mut self, expr: &ast::Expression, state: &mut State) {
+        match expr {
+            ast::Expression::Name(_) => {
+                if let Some(name) = self.owned(expr, state) {
+                    self.used(&name, expr.loc(), state);
+                }
+            }
+            ast::Expression::LiteralString{..}
+            | ast::Expression::LiteralChar{..}
+            | ast::Expression::Literal{..} => {}
+            ast::Expression::MemberAccess{lhs, ..} => self.expr(lhs, state),
+            ast::Expression::ArrayAccess{lhs, rhs, ..} | ast::Expression::Infix{lhs, rhs, ..} => {
+                self.expr(lhs, state);
+                self.expr(rhs, state);
+            }
+            ast::Expression::Call{name, args, ..} => {
+                self.expr(name, state);
+                let fargs = match self.callee(name) {
+                    Some(ast::Local{def: ast::Def::Function{args, ..}, ..}) => &args[..],
+                    _ => &[][..],
+                };
+                for (i, arg) in args.iter().enumerate() {
+                    let takes = fargs.get(i).map(|a| a.tags.contains(TAG)).unwrap_or(false);
+                    match self.owned(arg, state) {
+                        Some(name) if takes => self.consume(&name, arg.loc(), state),
+                        _ => self.expr(arg, state),
+                    }
+                }
+            }
+            ast::Expression::Cast{expr, ..}
+            | ast::Expression::UnaryPost{expr, ..}
+            | ast::Expression::UnaryPre{expr, ..} => self.expr(expr, state),
+            ast::Expression::StructInit{fields, ..} => {
+                for (_, e) in fields {
+                    self.expr(e, state);
+                }
+            }
+            ast::Expression::ArrayInit{fields, ..} => {
+                for e in fields {
+                    self.expr(e, state);
+                }
+            }
+        }
+    }
+
+    /// the state after block, or None if it never falls through
+    fn block(&mut self, block: &ast::Block, state: State) -> Option<State> {
+        let mut state = state;
+        for stm in &block.statements {
+            state = self.statement(stm, state)?;
+        }
+        Some(state)
+    }
+
+    /// a loop with cond checked before every iteration and next run after it.
+    /// a move in the body is a move on the next iteration too
+    fn looped(&mut self, cond: Option<&ast::Expression>, body: &ast::Block, next: &[Box<ast::Statement>], state: State) -> Option<State> {
+        let mut head = state;
+        loop {
+            let mut inside = head.clone();
+            if let Some(cond) = cond {
+                self.expr(cond, &mut inside);
+            }
+            self.loops.push(Loop{breaks: Vec::new(), continues: Vec::new()});
+            let end = self.block(body, inside.clone());
+            let lp = self.loops.pop().unwrap();
+
+            let mut again = end.into_iter().chain(lp.continues).reduce(|a, b| merge(a, &b));
+            for stm in next {
+                again = match again {
+                    Some(s) => self.statement(stm, s),
+                    None => None,
+                };
+            }
+            let again = merge(head.clone(), &again.unwrap_or_default());
+            if again == head {
+                let exit = cond.map(|_| inside);
+                return exit.into_iter().chain(lp.breaks).reduce(|a, b| merge(a, &b));
+            }
+            head = again;
+        }
+    }
+
+    fn statement(&mut self, stm: &ast::Statement, mut state: State) -> Option<State> {
+        match stm {
+            ast::Statement::Mark{lhs, key, loc, ..} => match self.owned(lhs, &state) {
+                Some(name) if key == DROPPED => self.consume(&name, loc, &mut state),
+                _ => self.expr(lhs, &mut state),
+            },
+            ast::Statement::Label{..} | ast::Statement::CBlock{..} => {}
+            ast::Statement::Break{..} => {
+                if let Some(lp) = self.loops.last_mut() {
+                    lp.breaks.push(state);
+                }
+                return None;
+            }
+            ast::Statement::Continue{..} => {
+                if let Some(lp) = self.loops.last_mut() {
+                    lp.continues.push(state);
+                }
+                return None;
+            }
+            ast::Statement::Return{expr, loc} => {
+                if let Some(expr) = expr {
+                    match self.owned(expr, &state) {
+                        Some(name) => self.consume(&name, expr.loc(), &mut state),
+                        None => self.expr(expr, &mut state),
+                    }
+                }
+                self.leaks(&state, loc);
+                return None;
+            }
+            ast::Statement::Block(b) | ast::Statement::Unsafe(b) => return self.block(b, state),
+            ast::Statement::Expr{expr, ..} => self.expr(expr, &mut state),
+            ast::Statement::Var{loc, name, tags, array, assign, ..} => {
+                if let Some(Some(size)) = array {
+                    self.expr(size, &mut state);
+                }
+                let owned = tags.contains(TAG);
+                if let Some(assign) = assign {
+                    match self.owned(assign, &state) {
+                        Some(from) if owned => self.consume(&from, assign.loc(), &mut state),
+                        _ => self.expr(assign, &mut state),
+                    }
+                }
+                if owned {
+                    // declared again in a loop while the last one still owns something
+                    if let Some(Status::Owned) | Some(Status::Maybe) = state.get(name) {
+                        let mut last = State::new();
+                        last.insert(name.clone(), Status::Owned);
+                        self.leaks(&last, loc);
+                    }
+                    let status = if assign.is_some() { Status::Owned } else { Status::Moved };
+                    state.insert(name.clone(), status);
+                    self.declared.insert(name.clone(), loc.clone());
+                } else {
+                    // shadows an owned name
+                    state.remove(name);
+                }
+            }
+            ast::Statement::Assign{lhs, rhs, op, loc} => {
+                let is_eq = matches!(op, ast::AssignOperator::Eq);
+                match self.owned(lhs, &state) {
+                    Some(name) if is_eq => {
+                        match self.owned(rhs, &state) {
+                            Some(from) => self.consume(&from, rhs.loc(), &mut state),
+                            None => self.expr(rhs, &mut state),
+                        }
+                        if state.get(&name) != Some(&Status::Moved) {
+                            let mut notes = vec![(loc.clone(), msg!("here"))];
+                            if let Some(declared) = self.declared.get(&name) {
+                                notes.push((declared.clone(), msg!("defined_here")));
+                            }
+                            self.report(msg!("owned_overwritten", name = name), notes);
+                        }
+                        state.insert(name, Status::Owned);
+                    }
+                    _ => {
+                        self.expr(lhs, &mut state);
+                        // stored in a field or behind a pointer, which owns it now
+                        match self.owned(rhs, &state) {
+                            Some(from) if is_eq && local_name(lhs).is_none() => self.consume(&from, rhs.loc(), &mut state),
+                            _ => self.expr(rhs, &mut state),
+                        }
+                    }
+                }
+            }
+            ast::Statement::If{branches} => {
+                let mut after : Option<State> = None;
+                let mut rest = state;
+                let mut has_else = false;
+                for (_, cond, body) in branches {
+                    match cond {
+                        Some(cond) => self.expr(cond, &mut rest),
+                        None => has_else = true,
+                    }
+                    if let Some(end) = self.block(body, rest.clone()) {
+                        after = Some(match after {
+                            Some(a) => merge(a, &end),
+                            None => end,
+                        });
+                    }
+                }
+                if !has_else {
+                    after = Some(match after {
+                        Some(a) => merge(a, &rest),
+                        None => rest,
+                    });
+                }
+                return after;
+            }
+            ast::Statement::While{expr, body} => return self.looped(Some(expr), body, &[], state),
+            ast::Statement::For{e1, e2, e3, body} => {
+                for s in e1 {
+                    state = self.statement(s, state)?;
+                }
+                return self.looped(e2.as_ref(), body, e3, state);
+            }
+            ast::Statement::Switch{expr, cases, default, ..} => {
+                self.expr(expr, &mut state);
+                let mut after : Option<State> = None;
+                let bodies = cases.iter().map(|(_, b)| b).chain(default.iter());
+                for body in bodies {
+                    if let Some(end) = self.block(body, state.clone()) {
+                        after = Some(match after {
+                            Some(a) => merge(a, &end),
+                            None => end,
+                        });
+                    }
+                }
+                if default.is_none() {
+                    after = Some(match after {
+                        Some(a) => merge(a, &state),
+                        None => state,
+                    });
+                }
+                return after;
+            }
+        }
+        Some(state)
+    }
+}
+
+/// check every function of md. returns false if an owned value is used after a move or never consumed
+pub fn check(md: &ast::Module, modules: &HashMap<Name, loader::Module>) -> bool {
+    let mut findings = Vec::new();
+    for local in &md.locals {
+        let (args, body) = match &local.def {
+            ast::Def::Function{args, body, ..} => (&args[..], body),
+            ast::Def::Test{body, ..} => (&[][..], body),
+            _ => continue,
+        };
+        let mut checker = Checker{
+            md,
+            modules,
+            declared:   HashMap::new(),
+            moved:      HashMap::new(),
+            loops:      Vec::new(),
+            findings:   Vec::new(),
+        };
+        let mut state = State::new();
+        for arg in args {
+            if arg.tags.contains(TAG) {
+                state.insert(arg.name.clone(), Status::Owned);
+                checker.declared.insert(arg.name.clone(), arg.loc.clone());
+            }
+        }
+        if let Some(end) = checker.block(body, state) {
+            checker.leaks(&end, &body.end);
+        }
+        findings.extend(checker.findings);
+    }
+
+    for (message, notes) in &findings {
+        emit_error(message, notes);
+    }
+    findings.is_empty()
+}
@@ -0,0 +1,250 @@
+/// zz package, a source archive of the project that is the same byte for byte wherever it is made
+
+use super::deps;
+use super::make;
+use super::project;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// a line of .gitignore or of exclude
+struct Pattern {
+    glob:       String,
+    /// relative to the root if the pattern has a /, otherwise it matches a name at any depth
+    anchored:   bool,
+    dir:        bool,
+}
+
+impl Pattern {
+    fn parse(line: &str) -> Option<Pattern> {
+        let line = line.trim();
+        // negations are rare enough in projects to leave out
+        if line.is_empty() || line.starts_with('#') || line.starts_with('!') {
+            return None;
+        }
+        let dir = line.ends_with('/');
+        let line = line.trim_end_matches('/');
+        Some(Pattern {
+            anchored:   line.contains('/'),
+            glob:       line.trim_start_matches('/').to_string(),
+            dir,
+        })
+    }
+
+    fn matches(&self, rel: &str, is_dir: bool) -> bool {
+        if self.dir && !is_dir {
+            return false;
+        }
+        if self.anchored {
+            glob(self.glob.as_bytes(), rel.as_bytes())
+        } else {
+            glob(self.glob.as_bytes(), rel.rsplit('/').next().unwrap_or(rel).as_bytes())
+        }
+    }
+}
+
+/// * is any part of a name, ? any one character of it
+fn glob(p: &[u8], s: &[u8]) -> bool {
+    match p.split_first() {
+        None => s.is_empty(),
+        Some((b'*', rest)) => {
+            (0..=s.len()).take_while(|i| *i == 0 || s[i - 1] != b'/').any(|i| glob(rest, &s[i..]))
+        }
+        Some((b'?', rest)) => !s.is_empty() && s[0] != b'/' && glob(rest, &s[1..]),
+        Some((c, rest)) => s.first() == Some(c) && glob(rest, &s[1..]),
+    }
+}
+
+const ALWAYS_OUT : &[&str] = &["target", ".git", ".hg", ".svn"];
+const ALWAYS_IN : &[&str] = &["zz.toml", "zz.lock"];
+
+/// the files to package below dir, by their path relative to root with / between names
+fn files(root: &Path, dir: &Path, excludes: &[Pattern], into: &mut Vec<(String, PathBuf)>) {
+    let mut entries : Vec<PathBuf> = std::fs::read_dir(dir)
+        .expect(&format!("cannot read {}", dir.display()))
+        .flatten().map(|e| e.path()).collect();
+    entries.sort();
+    for path in entries {
+        let rel : Vec<String> = path.strip_prefix(root).unwrap().components()
+            .map(|c| c.as_os_str().to_string_lossy().to_string()).collect();
+        let rel = rel.join("/");
+        let meta = match std::fs::symlink_metadata(&path) {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+        if ALWAYS_IN.contains(&rel.as_str()) {
+            into.push((rel, path));
+            continue;
+        }
+        if dir == root && ALWAYS_OUT.contains(&rel.as_str()) {
+            continue;
+        }
+        if excludes.iter().any(|p| p.matches(&rel, meta.is_dir())) {
+            continue;
+        }
+        if meta.is_dir() {
+            files(root, &path, excludes, into);
+        } else if meta.is_file() {
+            into.push((rel, path));
+        } else {
+            warn!("{}", msg!("package_skipped", path = rel));
+        }
+    }
+}
+
+#[cfg(unix)]
+fn executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::metadata(path).map(|m| m.permissions().mode() & 0o111 != 0).unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn executable(_path: &Path) -> bool {
+    false
+}
+
+/// an octal number of a tar header, in width bytes with a nul at the end
+fn octal(field: &mut [u8], v: u64) {
+    let s = format!("{:0width$o}\0", v, width = field.len() - 1);
+    field.copy_from_slice(s.as_bytes());
+}
+
+/// the ustar header of a file
+fn header(path: &str, size: u64, mode: u64, mtime: u64) -> Result<[u8; 512], String> {
+    let mut h = [0u8; 512];
+    // names longer than 100 bytes are split into a prefix and a name at a /
+    let (prefix, name) = if path.len() <= 100 {
+        ("", path)
+    } else {
+        let at = path.char_indices()
+            .filter(|(i, c)| *c == '/' && *i <= 155 && path.len() - i - 1 <= 100)
+            .map(|(i, _)| i).next()
+            .ok_or_else(|| msg!("package_path_too_long", path = path))?;
+        (&path[..at], &path[at + 1..])
+    };
+    h[..name.len()].copy_from_slice(name.as_bytes());
+    octal(&mut h[100..108], mode);
+    octal(&mut h[108..116], 0);
+    octal(&mut h[116..124], 0);
+    octal(&mut h[124..136], size);
+    octal(&mut h[136..148], mtime);
+    h[156] = b'0';
+    h[257..263].copy_from_slice(b"ustar\0");
+    h[263..265].copy_from_slice(b"00");
+    h[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+
+    // the checksum is of the header with the checksum field as spaces
+    h[148..156].copy_from_slice(b"        ");
+    let sum : u64 = h.iter().map(|b| *b as u64).sum();
+    let s = format!("{:06o}\0 ", sum);
+    h[148..156].copy_from_slice(s.as_bytes());
+    Ok(h)
+}
+
+const K : [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// sha256 of b, in hex. distributions check archives with it, a hash of our own wouldn't do
+pub fn sha256(b: &[u8]) -> String {
+    let mut h : [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+    ];
+    let mut data = b.to_vec();
+    data.push(0x80);
+    while data.len() % 64 != 56 {
+        data.push(0);
+    }
+    data.extend_from_slice(&((b.len() as u64) * 8).to_be_bytes());
+
+    for chunk in data.chunks(64) {
+        let mut w = [0u32; 64];
+        for i in 0..16 {
+            w[i] = u32::from_be_bytes([chunk[i * 4], chunk[i * 4 + 1], chunk[i * 4 + 2], chunk[i * 4 + 3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut hh] = h;
+        for (k, w) in K.iter().zip(&w) {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = hh.wrapping_add(s1).wrapping_add(ch).wrapping_add(*k).wrapping_add(*w);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            hh = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (v, n) in h.iter_mut().zip(&[a, b, c, d, e, f, g, hh]) {
+            *v = v.wrapping_add(*n);
+        }
+    }
+    h.iter().map(|v| format!("{:08x}", v)).collect()
+}
+
+pub fn main() {
+    let (root, mut config) = project::load_cwd();
+    let base = format!("{}-{}", config.project.name, config.project.version);
+
+    // git dependencies are resolved by loading the project, which writes zz.lock
+    let git = config.dependencies.iter().flatten()
+        .any(|(name, v)| matches!(deps::Source::parse(name, v), deps::Source::Git{..}));
+    if git && !root.join("zz.lock").exists() {
+        super::load_project(&root, &mut config, "default", &make::Stage::release());
+    }
+
+    let mut excludes : Vec<Pattern> = std::fs::read_to_string(root.join(".gitignore")).unwrap_or_default()
+        .lines().filter_map(Pattern::parse).collect();
+    excludes.extend(config.project.exclude.iter().filter_map(|l| Pattern::parse(l)));
+
+    let mut found = Vec::new();
+    files(&root, &root, &excludes, &mut found);
+    found.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mtime = std::env::var("SOURCE_DATE_EPOCH").ok().and_then(|v| v.parse().ok()).unwrap_or(0);
+    let mut tar = Vec::new();
+    let mut sums = String::new();
+    for (rel, path) in &found {
+        let content = std::fs::read(path).expect(&format!("cannot read {}", path.display()));
+        let name = format!("{}/{}", base, rel);
+        let mode = if executable(path) { 0o755 } else { 0o644 };
+        match header(&name, content.len() as u64, mode, mtime) {
+            Ok(h) => tar.extend_from_slice(&h),
+            Err(e) => {
+                error!("{}", e);
+                std::process::exit(9);
+            }
+        }
+        tar.extend_from_slice(&content);
+        tar.resize((tar.len() + 511) / 512 * 512, 0);
+        sums.push_str(&format!("{}  {}\n", sha256(&content), name));
+    }
+    tar.resize(tar.len() + 1024, 0);
+
+    let out = root.join("target").join("package");
+    std::fs::create_dir_all(&out).expect("create target dir");
+    let archive = out.join(format!("{}.tar", base));
+    std::fs::write(&archive, &tar).expect(&format!("cannot write {}", archive.display()));
+    let hash = sha256(&tar);
+    sums.push_str(&format!("{}  {}.tar\n", hash, base));
+    let manifest = out.join(format!("{}.sha256", base));
+    let mut f = std::fs::File::create(&manifest).expect(&format!("cannot write {}", manifest.display()));
+    f.write_all(sums.as_bytes()).expect(&format!("cannot write {}", manifest.display()));
+
+    info!("{}", msg!("package_written", path = archive.display(), files = found.len(), sha256 = hash));
+}
@@ -1,13 +1,22 @@
 use pest::Parser;
 use super::ast::*;
+use super::sourcemap::FileId;
 use super::name::Name;
 use std::path::Path;
 use std::io::{Read};
 use super::pp::PP;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::collections::HashMap;
+use std::cell::RefCell;
 use pest::prec_climber::{Operator, PrecClimber, Assoc};
 use super::make::Stage;
+use super::edition::{self, Edition};
+use super::diagnostics;
+use super::messages;
+use super::warnings;
+use super::coroutine;
+use super::closure;
+use super::units;
 
 #[derive(Parser)]
 #[grammar = "zz.pest"]
@@ -15,63 +24,248 @@ pub struct ZZParser;
 
 pub static ERRORS_AS_JSON : AtomicBool = AtomicBool::new(false);
 
+/// a diagnostic held back by capture()
+pub enum Diagnostic {
+    Json(String),
+    Log(log::Level, String),
+    // rendered by diagnostics.rs, printed as is
+    Text(String),
+    // rendered warning, printed after the build by warnings.rs
+    Warning(String),
+}
 
+// while inside capture(), diagnostics of this thread go here instead of the terminal
+thread_local! {
+    static CAPTURED : RefCell<Option<Vec<Diagnostic>>> = const { RefCell::new(None) };
+}
 
-pub fn parse(n: &Path, features: &HashMap<String, bool>, stage: &Stage) -> Module
-{
-    match p(&n, features, stage){
-        Err(e) => {
-            let e = e.with_path(&n.to_string_lossy());
-            if ERRORS_AS_JSON.load(Ordering::SeqCst) {
+// unwinds from abort() to capture()
+struct Abort;
 
+fn report(d: Diagnostic) {
+    let d = CAPTURED.with(|c| {
+        if let Some(v) = c.borrow_mut().as_mut() {
+            v.push(d);
+            return None;
+        }
+        Some(d)
+    });
+    match d {
+        None => (),
+        Some(Diagnostic::Json(s))          => println!("{}", s),
+        Some(Diagnostic::Log(level, s))    => log!(level, "{}", s),
+        Some(Diagnostic::Text(s))          => eprintln!("{}", s),
+        Some(Diagnostic::Warning(s))       => warnings::hold(s),
+    }
+}
 
-                let mut j = JsonError::default();
-                j.message       = format!("syntax error:\n{}", e);
-                j.level         = "error".to_string();
-                j.file_name     = n.to_string_lossy().to_string();
+/// report diagnostics returned by capture(), in order
+pub fn replay(diagnostics: Vec<Diagnostic>) {
+    for d in diagnostics {
+        report(d);
+    }
+}
 
-                match e.line_col {
-                    pest::error::LineColLocation::Span((l1,c1),(l2,c2)) => {
-                        j.line_start    = l1;
-                        j.column_start  = c1;
-                        j.line_end      = l2;
-                        j.column_end    = c2;
-                    },
-                    pest::error::LineColLocation::Pos((l1,c1)) => {
-                        j.line_start    = l1;
-                        j.column_start  = c1;
-                        j.line_end      = l1;
-                        j.column_end    = c1;
-                    }
-                };
-                println!("{}", serde_json::to_string(&j).unwrap());
-            } else {
-                error!("syntax error\n{}", e);
-            }
-            std::process::exit(9);
+/// stop because of a previously reported error.
+/// inside capture() this only unwinds to it, so parallel work on other modules can finish and report in order
+/// outside, the warnings held so far are printed before exiting
+pub fn abort() -> ! {
+    if CAPTURED.with(|c| c.borrow().is_some()) {
+        std::panic::resume_unwind(Box::new(Abort));
+    }
+    warnings::print();
+    std::process::exit(9);
+}
+
+/// run f with all diagnostics of this thread held back.
+/// returns None as result if f called abort()
+pub fn capture<T, F: FnOnce() -> T>(f: F) -> (Option<T>, Vec<Diagnostic>) {
+    let outer = CAPTURED.with(|c| c.replace(Some(Vec::new())));
+    let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f));
+    let diagnostics = CAPTURED.with(|c| c.replace(outer)).unwrap_or_default();
+    match r {
+        Ok(v) => (Some(v), diagnostics),
+        Err(e) if e.is::<Abort>() => (None, diagnostics),
+        Err(e) => {
+            replay(diagnostics);
+            std::panic::resume_unwind(e);
         }
+    }
+}
+
+
+
+pub fn parse(n: &Path, features: &HashMap<String, bool>, stage: &Stage, edition: Edition) -> Module
+{
+    match p(&n, features, stage, edition){
+        Err(e) => syntax_error(n, e),
         Ok(md) => {
             md
         }
     }
 }
 
-fn p(n: &Path, features: &HashMap<String, bool> , stage: &Stage) -> Result<Module, pest::error::Error<Rule>> {
+/// parse a file holding a single expression, like the one given to zz eval
+pub fn parse_expression(n: &Path) -> Expression {
+    let file_str = std::fs::read_to_string(n).expect(&format!("read {:?}", n));
+    let file = super::sourcemap::add(&n.to_string_lossy(), file_str);
+    let file_str = file.source();
+    match ZZParser::parse(Rule::eval, &file_str) {
+        Err(e) => syntax_error(n, e),
+        Ok(mut pairs) => {
+            let expr = pairs.next().unwrap().into_inner().next().unwrap();
+            parse_expr(file, expr)
+        }
+    }
+}
+
+fn syntax_error(n: &Path, e: pest::error::Error<Rule>) -> ! {
+    let e = e.with_path(&n.to_string_lossy());
+    if ERRORS_AS_JSON.load(Ordering::SeqCst) {
+
+
+        let mut j = JsonError::default();
+        j.message       = format!("{}: {}", msg!("syntax_error"), expected(&e.variant));
+        j.code          = Some("syntax_error".to_string());
+        j.level         = "error".to_string();
+        j.file_name     = n.to_string_lossy().to_string();
+
+        match e.line_col {
+            pest::error::LineColLocation::Span((l1,c1),(l2,c2)) => {
+                j.line_start    = l1;
+                j.column_start  = c1;
+                j.line_end      = l2;
+                j.column_end    = c2;
+            },
+            pest::error::LineColLocation::Pos((l1,c1)) => {
+                j.line_start    = l1;
+                j.column_start  = c1;
+                j.line_end      = l1;
+                j.column_end    = c1;
+            }
+        };
+        report(Diagnostic::Json(serde_json::to_string(&j).unwrap()));
+    } else {
+        let (line, col) = match e.line_col {
+            pest::error::LineColLocation::Span(start, _) => start,
+            pest::error::LineColLocation::Pos(pos) => pos,
+        };
+        report(Diagnostic::Text(diagnostics::render_syntax(n, line, col, &expected(&e.variant))));
+    }
+    abort();
+}
+
+/// what the grammar wanted instead, like "expected ident or expr"
+fn expected(variant: &pest::error::ErrorVariant<Rule>) -> String {
+    let rules = |rules: &[Rule]| rules.iter().map(|r| format!("{:?}", r)).collect::<Vec<_>>().join(" or ");
+    match variant {
+        pest::error::ErrorVariant::ParsingError{positives, negatives} => match (positives.is_empty(), negatives.is_empty()) {
+            (false, true)   => format!("expected {}", rules(positives)),
+            (true, false)   => format!("unexpected {}", rules(negatives)),
+            (false, false)  => format!("unexpected {}, expected {}", rules(negatives), rules(positives)),
+            (true, true)    => "unknown parsing error".to_string(),
+        },
+        pest::error::ErrorVariant::CustomError{message} => message.clone(),
+    }
+}
+
+/// pub, or pub(package) for declarations only the modules of the same project can use
+fn shared(part: &pest::iterators::Pair<Rule>) -> Visibility {
+    match part.clone().into_inner().next() {
+        Some(_) => Visibility::Package,
+        None    => Visibility::Shared,
+    }
+}
+
+fn p(n: &Path, features: &HashMap<String, bool> , stage: &Stage, edition: Edition) -> Result<Module, pest::error::Error<Rule>> {
 
     let mut module = Module::default();
+    module.edition = edition;
     module.source = n.to_path_buf();
+    super::crash::working_on(n);
     module.sources.insert(n.canonicalize().unwrap());
-    module.name.push(n.file_stem().expect(&format!("stem {:?}", n)).to_string_lossy().into());
+    module.name.push(n.file_stem().expect(&format!("stem {:?}", n)).to_string_lossy());
 
     let mut f = std::fs::File::open(n).expect(&format!("cannot open file {:?}", n));
     let mut file_str = String::new();
     f.read_to_string(&mut file_str).expect(&format!("read {:?}", n));
-    let file_str = Box::leak(Box::new(file_str));
-    let mut file = ZZParser::parse(Rule::file, file_str)?;
+    let file = super::sourcemap::add(&n.to_string_lossy(), file_str);
+    let file_str = file.source();
+    let file_str : &str = &file_str;
+    let mut pairs = ZZParser::parse(Rule::file, file_str)?;
     let mut doccomments = String::new();
 
-    for decl in PP::new(n, features.clone(), stage.clone(), file.next().unwrap().into_inner()) {
+    let migrations = edition::migrations(pairs.clone(), edition);
+    for m in &migrations {
+        emit_error(msg!("edition_syntax", found = m.span.as_str(), since = m.since), &[(
+            Location::new(file, m.span.clone()),
+            msg!("edition_syntax.note", replace = m.replace),
+        )]);
+    }
+    if !migrations.is_empty() {
+        abort();
+    }
+
+    let comment_spans = comments(file_str);
+    for &(start, end) in &comment_spans {
+        module.comments.push(Location::at(file, start, end));
+    }
+
+    let mut deprecated : Option<Deprecation> = None;
+    let mut unstable   : Option<Unstable> = None;
+    let mut derive     : Option<Derive> = None;
+    let mut weak       : Option<Location> = None;
+    let mut overrides  : Option<Override> = None;
+    let mut symbol     : Option<Symbol> = None;
+    for decl in PP::new(file, features.clone(), stage.clone(), pairs.next().unwrap().into_inner()) {
+        let declared = module.locals.len();
         match decl.as_rule() {
+            Rule::deprecated => {
+                let loc = Location::new(file, decl.as_span());
+                let mut strings = decl.into_inner().map(|part| attribute_string(file, part));
+                deprecated = Some(Deprecation{
+                    message:    strings.next().unwrap(),
+                    replace:    strings.next(),
+                    loc,
+                });
+            }
+            Rule::unstable => {
+                let loc = Location::new(file, decl.as_span());
+                let feature = attribute_string(file, decl.into_inner().next().unwrap());
+                unstable = Some(Unstable{
+                    feature,
+                    loc,
+                });
+            }
+            Rule::derive => {
+                let loc = Location::new(file, decl.as_span());
+                derive = Some(Derive{
+                    plugins: decl.into_inner().map(|part| part.as_str().to_string()).collect(),
+                    loc,
+                });
+            }
+            Rule::weak => {
+                weak = Some(Location::new(file, decl.as_span()));
+            }
+            Rule::overrides => {
+                let loc = Location::new(file, decl.as_span());
+                let target = Name::from(&format!("::{}", decl.into_inner().next().unwrap().as_str()));
+                overrides = Some(Override{
+                    target,
+                    loc,
+                });
+            }
+            Rule::symbol => {
+                let loc = Location::new(file, decl.as_span());
+                let name = attribute_string(file, decl.into_inner().next().unwrap());
+                symbol = Some(Symbol{
+                    name,
+                    loc,
+                });
+            }
+            Rule::interface => {
+                module.interface = Some(Location::new(file, decl.as_span()));
+            }
             Rule::doccomment => {
                 let mut s = decl.as_str().to_string();
                 s.remove(0);
@@ -79,10 +273,7 @@ fn p(n: &Path, features: &HashMap<String, bool> , stage: &Stage) -> Result<Modul
                 doccomments.push_str(&s);
             }
             Rule::imacro => {
-                let loc = Location{
-                    file: n.to_string_lossy().into(),
-                    span: decl.as_span(),
-                };
+                let loc = Location::new(file, decl.as_span());
                 let decl = decl.into_inner();
                 let mut name = None;
                 let mut args = Vec::new();
@@ -91,7 +282,7 @@ fn p(n: &Path, features: &HashMap<String, bool> , stage: &Stage) -> Result<Modul
                 for part in decl {
                     match part.as_rule() {
                         Rule::key_shared => {
-                            vis = Visibility::Shared;
+                            vis = shared(&part);
                         }
                         Rule::exported => {
                             vis = Visibility::Export;
@@ -105,7 +296,7 @@ fn p(n: &Path, features: &HashMap<String, bool> , stage: &Stage) -> Result<Modul
                             }
                         }
                         Rule::block if body.is_none() => {
-                            body = Some(parse_block((file_str, n), features, stage, part));
+                            body = Some(parse_block(file, features, stage, part));
                         },
                         e => panic!("unexpected rule {:?} in macro ", e),
                     }
@@ -124,146 +315,8 @@ fn p(n: &Path, features: &HashMap<String, bool> , stage: &Stage) -> Result<Modul
 
             }
             Rule::function | Rule::fntype | Rule::theory => {
-                let loc = Location{
-                    file: n.to_string_lossy().into(),
-                    span: decl.as_span(),
-                };
-                let mut nameloc = loc.clone();
-                let declrule = decl.as_rule().clone();
-                let decl = decl.into_inner();
-                let mut name = String::new();
-                let mut args = Vec::new();
-                let mut ret  = None;
-                let mut body = None;
-                let mut attr = HashMap::new();
-                let mut vararg = false;
-                let mut callassert = Vec::new();
-                let mut calleffect = Vec::new();
-                let mut vis = Visibility::Object;
-                let mut hints = HashMap::new();
-
-                for part in decl {
-                    match part.as_rule() {
-                        Rule::key_shared => {
-                            vis = Visibility::Shared;
-                        }
-                        Rule::exported => {
-                            vis = Visibility::Export;
-                        }
-                        Rule::ident => {
-                            nameloc = Location{
-                                file: n.to_string_lossy().into(),
-                                span: part.as_span(),
-                            };
-                            name = part.as_str().into();
-                        }
-                        Rule::ret_arg => {
-                            let part = part.into_inner().next().unwrap();
-                            ret = Some(AnonArg{
-                                typed: parse_anon_type((file_str, n), part),
-                            });
-                        },
-                        Rule::fn_attr => {
-                            let loc  = Location{
-                                file: n.to_string_lossy().into(),
-                                span: part.as_span(),
-                            };
-                            attr.insert(part.as_str().into(), loc);
-                        },
-                        Rule::fn_args => {
-                            for arg in part.into_inner() {
-
-                                let argloc  = Location{
-                                    file: n.to_string_lossy().into(),
-                                    span: arg.as_span(),
-                                };
-
-                                if arg.as_rule() == Rule::vararg {
-                                    vararg = true;
-                                } else {
-                                    let TypedName{typed, name, tags} = parse_named_type((file_str, n), arg);
-
-                                    args.push(NamedArg{
-                                        name,
-                                        typed,
-                                        tags,
-                                        loc: argloc,
-                                    });
-                                }
-                            }
-                        },
-                        Rule::call_assert => {
-                            let part = part.into_inner().next().unwrap();
-                            callassert.push(parse_expr((file_str, n), part));
-                        },
-                        Rule::call_effect => {
-                            let part = part.into_inner().next().unwrap();
-                            calleffect.push(parse_expr((file_str, n), part));
-                        },
-                        Rule::block => {
-                            body = Some(parse_block((file_str, n), features, stage, part));
-                        },
-                        Rule::fn_vattr => {
-                            let mut part = part.into_inner();
-                            let key  = part.next().unwrap().as_str().to_string();
-                            let val  = part.next().unwrap().as_str().to_string();
-                            hints.insert(key, val);
-                        }
-                        e => panic!("unexpected rule {:?} in function", e),
-                    }
-                }
-
-                match declrule {
-                    Rule::function => {
-                        module.locals.push(Local{
-                            doc: std::mem::replace(&mut doccomments, String::new()),
-                            name,
-                            vis,
-                            loc,
-                            def:Def::Function{
-                                nameloc,
-                                ret,
-                                attr,
-                                hints,
-                                args,
-                                body: body.unwrap(),
-                                vararg,
-                                callassert,
-                                calleffect,
-                                callattests: Vec::new(),
-                            }
-                        });
-                    }
-                    Rule::theory => {
-                        module.locals.push(Local{
-                            doc: std::mem::replace(&mut doccomments, String::new()),
-                            name,
-                            vis,
-                            loc,
-                            def:Def::Theory{
-                                ret,
-                                attr,
-                                args,
-                            }
-                        });
-                    },
-                    Rule::fntype => {
-                        module.locals.push(Local{
-                            doc: std::mem::replace(&mut doccomments, String::new()),
-                            name,
-                            vis,
-                            loc,
-                            def:Def::Fntype{
-                                nameloc,
-                                ret,
-                                attr,
-                                args,
-                                vararg,
-                            }
-                        });
-                    },
-                    _ => unreachable!()
-                }
+                let doc = std::mem::replace(&mut doccomments, String::new());
+                module.locals.push(parse_function(file, features, stage, decl, doc));
             },
             Rule::EOI => {},
             Rule::ienum => {
@@ -273,44 +326,59 @@ fn p(n: &Path, features: &HashMap<String, bool> , stage: &Stage) -> Result<Modul
                 let mut name   = None;
                 let mut names  = Vec::new();
                 let mut loc    = None;
+                let mut flags  = false;
+                let mut next   = Some(1u64);
 
-                for part in PP::new(n, features.clone(), stage.clone(), decl) {
+                for part in PP::new(file, features.clone(), stage.clone(), decl) {
                     match part.as_rule() {
                         Rule::key_shared => {
-                            vis = Visibility::Shared;
+                            vis = shared(&part);
+                        }
+                        Rule::key_flags => {
+                            flags = true;
                         }
                         Rule::exported => {
                             vis = Visibility::Export;
                         }
                         Rule::ident if name.is_none() => {
-                            loc  = Some(Location{
-                                file: n.to_string_lossy().into(),
-                                span: part.as_span(),
-                            });
+                            loc  = Some(Location::new(file, part.as_span()));
                             name = Some(part.as_str().into());
 
                         }
                         Rule::enum_i => {
+                            let iloc  = Location::new(file, part.as_span());
                             let mut part = part.into_inner();
                             let name = part.next().unwrap().as_str().to_string();
                             let mut literal = None;
                             if let Some(part) = part.next() {
-                                literal = Some(match part.as_str().to_string().parse() {
+                                literal = Some(match part.as_str().replace('_', "").parse() {
                                     Err(e) => {
-                                        let loc  = Location{
-                                            file: n.to_string_lossy().into(),
-                                            span: part.as_span(),
-                                        };
+                                        let loc  = Location::new(file, part.as_span());
                                         emit_error(
                                             "enums must be positive integer literals",
                                             &[(loc.clone(), format!("{}", e))]
                                         );
-                                        std::process::exit(9);
+                                        abort();
                                     },
                                     Ok(v) => v,
                                 });
                             }
 
+                            // every value of a flags enum is its own bit
+                            if flags {
+                                let value = match literal.or(next) {
+                                    Some(v) if v.is_power_of_two() => v,
+                                    v => {
+                                        emit_error(msg!("flags_value", name = name), &[
+                                            (iloc.clone(), msg!("flags_value.note", value = v.map(|v| v.to_string()).unwrap_or("2^64".to_string()))),
+                                        ]);
+                                        abort();
+                                    }
+                                };
+                                next  = value.checked_mul(2);
+                                literal = Some(value);
+                            }
+
                             names.push((name, literal));
 
 
@@ -326,33 +394,54 @@ fn p(n: &Path, features: &HashMap<String, bool> , stage: &Stage) -> Result<Modul
                     loc: loc.unwrap(),
                     def: Def::Enum{
                         names,
+                        flags,
                     }
                 });
 
             },
+            Rule::unittest => {
+                let loc = Location::new(file, decl.as_span());
+                let mut decl = decl.into_inner();
+                let name = decl.next().unwrap();
+                let name_loc = Location::new(file, name.as_span());
+                let name = name.as_str();
+                // kept with c escapes, like every other string literal
+                let name = match raw_string(name) {
+                    Some(raw) => raw.replace('\\', "\\\\").replace('"', "\\\""),
+                    None => {
+                        unescape(&name_loc);
+                        name[1..name.len()-1].to_string()
+                    }
+                };
+                let body = parse_block(file, features, stage, decl.next().unwrap());
+                module.locals.push(Local{
+                    doc: std::mem::replace(&mut doccomments, String::new()),
+                    name: format!("__test_{}", loc.line()),
+                    vis: Visibility::Object,
+                    loc,
+                    def: Def::Test {
+                        name,
+                        body,
+                    }
+                });
+            },
             Rule::testcase => {
                 let mut name   = None;
                 let mut fields = Vec::new();
-                let mut loc    = Location{
-                    file: n.to_string_lossy().into(),
-                    span: decl.as_span(),
-                };
+                let mut loc    = Location::new(file, decl.as_span());
 
                 let decl = decl.into_inner();
-                for part in PP::new(n,features.clone(), stage.clone(), decl) {
+                for part in PP::new(file, features.clone(), stage.clone(), decl) {
                     match part.as_rule() {
                         Rule::ident => {
-                            loc  = Location{
-                                file: n.to_string_lossy().into(),
-                                span: part.as_span(),
-                            };
+                            loc  = Location::new(file, part.as_span());
                             name= Some(part.as_str().into());
                         },
                         Rule::testfield => {
                             let mut part = part.into_inner();
                             let fname   = part.next().unwrap().as_str().to_string();
                             let _op      = part.next().unwrap().as_str().to_string();
-                            let expr    = parse_expr((file_str, n), part.next().unwrap());
+                            let expr    = parse_expr(file, part.next().unwrap());
                             fields.push((fname,expr));
                         }
                         e => panic!("unexpected rule {:?} in testcase", e),
@@ -375,14 +464,17 @@ fn p(n: &Path, features: &HashMap<String, bool> , stage: &Stage) -> Result<Modul
                 let decl = decl.into_inner();
 
                 let mut vis    = Visibility::Object;
-                let mut name   = None;
+                let mut name : Option<String> = None;
                 let mut fields = Vec::new();
                 let mut loc    = None;
                 let mut packed = false;
                 let mut tail   = Tail::None;
                 let mut union  = false;
+                let mut abi    = None;
+                let mut methods = Vec::new();
+                let mut anon   = Vec::new();
 
-                for part in PP::new(n,features.clone(), stage.clone(), decl) {
+                for part in PP::new(file, features.clone(), stage.clone(), decl) {
                     match part.as_rule() {
                         Rule::tail => {
                             tail = Tail::Dynamic;
@@ -390,8 +482,21 @@ fn p(n: &Path, features: &HashMap<String, bool> , stage: &Stage) -> Result<Modul
                         Rule::key_packed => {
                             packed = true;
                         }
+                        Rule::abi => {
+                            abi = match part.into_inner().next() {
+                                None => Some(0),
+                                Some(version) => match LiteralValue::parse(version.as_str()).as_u64() {
+                                    Some(v) => Some(v),
+                                    None => {
+                                        let loc = Location::new(file, version.as_span());
+                                        emit_error(msg!("layout_version_invalid"), &[(loc, msg!("layout_version_invalid.note"))]);
+                                        abort();
+                                    }
+                                },
+                            };
+                        }
                         Rule::key_shared => {
-                            vis = Visibility::Shared;
+                            vis = shared(&part);
                         }
                         Rule::key_struct => {
                             union = false;
@@ -403,72 +508,291 @@ fn p(n: &Path, features: &HashMap<String, bool> , stage: &Stage) -> Result<Modul
                             vis = Visibility::Export;
                         }
                         Rule::ident => {
-                            loc  = Some(Location{
-                                file: n.to_string_lossy().into(),
-                                span: part.as_span(),
-                            });
+                            loc  = Some(Location::new(file, part.as_span()));
                             name= Some(part.as_str().into());
                         }
                         Rule::struct_f => {
+                            fields.push(parse_struct_field(file, part));
+                        }
+                        Rule::struct_anon => {
+                            let outer = name.clone().unwrap();
+                            fields.push(parse_anon_struct(file, features, stage, part, &outer, vis.clone(), &mut anon));
+                        }
+                        Rule::function => {
+                            methods.push(parse_function(file, features, stage, part, String::new()));
+                        }
+                        e => panic!("unexpected rule {:?} in struct ", e),
+                    }
+                };
 
-                            let loc  = Location{
-                                file: n.to_string_lossy().into(),
-                                span: part.as_span(),
-                            };
 
+                // an unsized array is a c99 flexible array member. it makes the struct a tail type
+                for (i, field) in fields.iter().enumerate() {
+                    if let Some(None) = field.array {
+                        if i + 1 != fields.len() {
+                            emit_error(msg!("flexible_array_not_last", name = field.name), &[
+                                (field.loc.clone(), msg!("flexible_array_not_last.note")),
+                            ]);
+                            abort();
+                        }
+                        if tail == Tail::None && !union {
+                            tail = Tail::Dynamic;
+                        }
+                    }
+                }
 
-                            let mut part = part.into_inner();
+                if union {
+                    no_union_default(&fields);
+                }
+
+                let name : String = name.unwrap();
+                module.locals.extend(anon);
+                module.locals.push(Local{
+                    doc: std::mem::replace(&mut doccomments, String::new()),
+                    name: name.clone(),
+                    vis,
+                    loc: loc.unwrap(),
+                    def: Def::Struct {
+                        fields,
+                        packed,
+                        tail,
+                        union,
+                        impls: HashMap::new(),
+                        abi,
+                    }
+                });
 
-                            let TypedName{typed, name, tags} = parse_named_type((file_str, n), part.next().unwrap());
+                // methods are plain functions namespaced by the struct, see abs.rs
+                for mut method in methods {
+                    method.name = format!("{}::{}", name, method.name);
+                    module.locals.push(method);
+                }
+            }
+            Rule::statemachine => {
+                let loc = Location::new(file, decl.as_span());
+                let at = |part: &pest::iterators::Pair<Rule>| (part.as_str().to_string(), Location::new(file, part.as_span()));
 
-                            let array = match part.next() {
-                                None => None,
-                                Some(array) => {
-                                    match array.into_inner().next() {
-                                        Some(expr) => {
-                                            Some(Some(parse_expr((file_str, n), expr)))
-                                        },
-                                        None => {
-                                            Some(None)
+                let mut sm = StateMachine{
+                    name:           String::new(),
+                    vis:            Visibility::Object,
+                    loc,
+                    states:         Vec::new(),
+                    events:         Vec::new(),
+                    initial:        None,
+                    transitions:    Vec::new(),
+                    fields:         Vec::new(),
+                    doc:            std::mem::replace(&mut doccomments, String::new()),
+                };
+                for part in PP::new(file, features.clone(), stage.clone(), decl.into_inner()) {
+                    match part.as_rule() {
+                        Rule::key_shared => {
+                            sm.vis = shared(&part);
+                        }
+                        Rule::exported => {
+                            sm.vis = Visibility::Export;
+                        }
+                        Rule::ident => {
+                            sm.name = part.as_str().to_string();
+                        }
+                        Rule::sm_states => {
+                            sm.states.extend(part.into_inner().map(|p| at(&p)));
+                        }
+                        Rule::sm_events => {
+                            sm.events.extend(part.into_inner().map(|p| at(&p)));
+                        }
+                        Rule::sm_initial => {
+                            sm.initial = Some(at(&part.into_inner().next().unwrap()));
+                        }
+                        Rule::sm_transition => {
+                            let loc = at(&part).1;
+                            let mut parts = part.into_inner();
+                            let from    = at(&parts.next().unwrap());
+                            let to      = at(&parts.next().unwrap());
+                            let event   = at(&parts.next().unwrap());
+                            let guard   = parts.next().map(|g| at(&g.into_inner().next().unwrap()));
+                            sm.transitions.push(Transition{from, to, event, guard, loc});
+                        }
+                        Rule::struct_f => {
+                            sm.fields.push(part.as_str().to_string());
+                        }
+                        e => panic!("unexpected rule {:?} in statemachine", e),
+                    }
+                }
+                module.statemachines.push(sm);
+            }
+            Rule::wire_d => {
+                let loc = Location::new(file, decl.as_span());
+                let at = |part: &pest::iterators::Pair<Rule>| (part.as_str().to_string(), Location::new(file, part.as_span()));
+
+                let mut wire = Wire{
+                    name:   String::new(),
+                    vis:    Visibility::Object,
+                    loc,
+                    fields: Vec::new(),
+                    doc:    std::mem::replace(&mut doccomments, String::new()),
+                };
+                for part in PP::new(file, features.clone(), stage.clone(), decl.into_inner()) {
+                    match part.as_rule() {
+                        Rule::key_shared => {
+                            wire.vis = shared(&part);
+                        }
+                        Rule::exported => {
+                            wire.vis = Visibility::Export;
+                        }
+                        Rule::ident => {
+                            wire.name = part.as_str().to_string();
+                        }
+                        Rule::wire_field => {
+                            let loc = at(&part).1;
+                            let mut parts = part.into_inner();
+                            let typed = at(&parts.next().unwrap());
+                            let name  = at(&parts.next().unwrap());
+                            let mut array = None;
+                            let mut cond  = None;
+                            for part in parts {
+                                match part.as_rule() {
+                                    Rule::wire_len => {
+                                        let mut len = None;
+                                        let mut max = 0;
+                                        for part in part.into_inner() {
+                                            match part.as_rule() {
+                                                Rule::ident => len = Some(at(&part)),
+                                                _ => match part.as_str().replace('_', "").parse::<u64>() {
+                                                    Ok(v) => max = v,
+                                                    Err(_) => {
+                                                        emit_error(msg!("wire_size_invalid"), &[(at(&part).1, msg!("here"))]);
+                                                        abort();
+                                                    }
+                                                },
+                                            }
                                         }
+                                        array = Some((len, max));
                                     }
+                                    Rule::wire_cond => {
+                                        let mut parts = part.into_inner();
+                                        let field = at(&parts.next().unwrap());
+                                        let op    = parts.next().unwrap().as_str().to_string();
+                                        let value = parts.next().unwrap().as_str().to_string();
+                                        cond = Some((field, op, value));
+                                    }
+                                    e => panic!("unexpected rule {:?} in wire field", e),
                                 }
-                            };
+                            }
+                            wire.fields.push(WireField{typed, name, array, cond, loc});
+                        }
+                        e => panic!("unexpected rule {:?} in wire", e),
+                    }
+                }
+                module.wires.push(wire);
+            }
+            Rule::closure_d => {
+                let loc = Location::new(file, decl.as_span());
+                let decl = decl.into_inner();
 
+                let mut vis     = Visibility::Object;
+                let mut name    = None;
+                let mut nameloc = loc.clone();
+                let mut types   = Vec::new();
+                let mut fields  = Vec::new();
 
-                            fields.push(Field{
-                                typed,
-                                array,
-                                tags,
-                                name,
-                                loc,
+                for part in PP::new(file, features.clone(), stage.clone(), decl) {
+                    match part.as_rule() {
+                        Rule::key_shared => {
+                            vis = shared(&part);
+                        }
+                        Rule::exported => {
+                            vis = Visibility::Export;
+                        }
+                        Rule::ident => {
+                            nameloc = Location::new(file, part.as_span());
+                            name = Some(part.as_str().to_string());
+                        }
+                        Rule::type_name => {
+                            types.push(Typed{
+                                t:      Type::Other(Name::from(part.as_str())),
+                                ptr:    Vec::new(),
+                                loc:    Location::new(file, part.as_span()),
+                                tail:   Tail::None,
                             });
                         }
-                        e => panic!("unexpected rule {:?} in struct ", e),
+                        Rule::struct_f => {
+                            fields.push(parse_struct_field(file, part));
+                        }
+                        e => panic!("unexpected rule {:?} in closure ", e),
                     }
-                };
+                }
+
+                let name    = name.unwrap();
+                let ctx     = format!("{}_ctx", name);
+                let target  = types.pop().unwrap();
+                let fntype  = types.pop().unwrap();
 
+                // the context struct holds the captured variables
+                module.locals.push(Local{
+                    doc:    String::new(),
+                    name:   ctx.clone(),
+                    vis:    vis.clone(),
+                    loc:    nameloc.clone(),
+                    def:    Def::Struct {
+                        fields,
+                        packed: false,
+                        tail:   Tail::None,
+                        union:  false,
+                        impls:  HashMap::new(),
+                        abi:    None,
+                    }
+                });
+                module.locals.push(Local{
+                    doc: std::mem::replace(&mut doccomments, String::new()),
+                    name,
+                    vis,
+                    loc,
+                    def: Def::Closure {
+                        nameloc,
+                        fntype,
+                        target,
+                        ctx,
+                        inline: false,
+                    }
+                });
+            }
+            Rule::typealias => {
+                let loc = Location::new(file, decl.as_span());
 
+                let mut vis     = Visibility::Object;
+                let mut name    = None;
+                let mut typed   = None;
+
+                for part in decl.into_inner() {
+                    match part.as_rule() {
+                        Rule::key_shared => {
+                            vis = shared(&part);
+                        }
+                        Rule::exported => {
+                            vis = Visibility::Export;
+                        }
+                        Rule::ident => {
+                            name = Some(part.as_str().to_string());
+                        }
+                        Rule::anon_type => {
+                            typed = Some(parse_anon_type(file, part));
+                        }
+                        e => panic!("unexpected rule {:?} in type alias", e),
+                    }
+                }
 
                 module.locals.push(Local{
                     doc: std::mem::replace(&mut doccomments, String::new()),
                     name: name.unwrap(),
                     vis,
-                    loc: loc.unwrap(),
-                    def: Def::Struct {
-                        fields,
-                        packed,
-                        tail,
-                        union,
-                        impls: HashMap::new(),
+                    loc,
+                    def: Def::TypeAlias {
+                        typed: typed.unwrap(),
                     }
                 });
             }
             Rule::import => {
-                let loc  = Location{
-                    file: n.to_string_lossy().into(),
-                    span: decl.as_span(),
-                };
+                let loc  = Location::new(file, decl.as_span());
                 let mut vis = Visibility::Object;
                 let mut importname = None;
                 let mut alias      = None;
@@ -484,7 +808,7 @@ fn p(n: &Path, features: &HashMap<String, bool> , stage: &Stage) -> Result<Modul
                             vis = Visibility::Export;
                         }
                         Rule::key_shared => {
-                            vis = Visibility::Shared;
+                            vis = shared(&part);
                         }
                         Rule::importalias => {
                             alias = Some(part.into_inner().next().unwrap().as_str().to_string());
@@ -498,16 +822,10 @@ fn p(n: &Path, features: &HashMap<String, bool> , stage: &Stage) -> Result<Modul
                                         Typed{
                                             t:      Type::Other(Name::from(ident.as_str())),
                                             ptr:    Vec::new(),
-                                            loc:    Location{
-                                                file: n.to_string_lossy().into(),
-                                                span: ident.as_span(),
-                                            },
+                                            loc:    Location::new(file, ident.as_span()),
                                             tail:   Tail::None,
                                         },
-                                        Location{
-                                            file: n.to_string_lossy().into(),
-                                            span: ident.as_span(),
-                                        }
+                                        Location::new(file, ident.as_span())
                                 ));
                             }
                         }
@@ -531,18 +849,19 @@ fn p(n: &Path, features: &HashMap<String, bool> , stage: &Stage) -> Result<Modul
             Rule::comment => {},
             Rule::istatic | Rule::constant => {
                 let rule = decl.as_rule();
-                let loc  = Location{
-                    file: n.to_string_lossy().into(),
-                    span: decl.as_span(),
-                };
+                let loc  = Location::new(file, decl.as_span());
                 let mut storage = Storage::Static;
                 let mut vis     = Visibility::Object;
                 let mut typed   = None;
                 let mut expr    = None;
                 let mut array   = None;
+                let mut comptime = false;
 
                 for part in decl.into_inner() {
                     match part.as_rule() {
+                        Rule::key_comptime => {
+                            comptime = true;
+                        }
                         Rule::key_thread_local => {
                             storage = Storage::ThreadLocal;
                         }
@@ -554,35 +873,31 @@ fn p(n: &Path, features: &HashMap<String, bool> , stage: &Stage) -> Result<Modul
                         }
                         Rule::key_shared =>  {
                             if let Rule::istatic = rule {
-                                let e = pest::error::Error::<Rule>::new_from_span(pest::error::ErrorVariant::CustomError {
-                                    message: format!("cannot change visibility of static variable"),
-                                }, part.as_span());
-                                error!("{} : {}", n.to_string_lossy(), e);
-                                std::process::exit(9);
+                                let loc = Location::new(file, part.as_span());
+                                emit_error(msg!("static_visibility"), &[(loc, msg!("static_visibility.note"))]);
+                                abort();
                             } else {
-                                vis = Visibility::Shared;
+                                vis = shared(&part);
                             }
                         }
                         Rule::exported => {
                             if let Rule::istatic = rule {
-                                let e = pest::error::Error::<Rule>::new_from_span(pest::error::ErrorVariant::CustomError {
-                                    message: format!("cannot change visibility of static variable"),
-                                }, part.as_span());
-                                error!("{} : {}", n.to_string_lossy(), e);
-                                std::process::exit(9);
+                                let loc = Location::new(file, part.as_span());
+                                emit_error(msg!("static_visibility"), &[(loc, msg!("static_visibility.note"))]);
+                                abort();
                             } else {
                                 vis = Visibility::Export;
                             }
                         },
                         Rule::named_type => {
-                            typed = Some(parse_named_type((file_str, n), part));
+                            typed = Some(parse_named_type(file, part));
                         },
                         Rule::expr if expr.is_none() => {
-                            expr = Some(parse_expr((file_str, n), part));
+                            expr = Some(parse_expr(file, part));
                         }
                         Rule::array => {
                             if let Some(expr) = part.into_inner().next() {
-                                array = Some(Some(parse_expr((file_str, n), expr)));
+                                array = Some(Some(parse_expr(file, expr)));
                             } else {
                                 array = Some(None);
                             }
@@ -594,12 +909,36 @@ fn p(n: &Path, features: &HashMap<String, bool> , stage: &Stage) -> Result<Modul
                 let TypedName{typed, name, tags} = typed.unwrap();
                 match rule {
 
+                    Rule::constant if array.is_some() => {
+                        if vis != Visibility::Object {
+                            emit_error(msg!("const_array_visibility"), &[(loc.clone(), msg!("const_array_visibility.note"))]);
+                            abort();
+                        }
+                        if let Some(tag) = tags.get("mut") {
+                            emit_error(msg!("const_array_mut"), &[(tag.values().next().unwrap().clone(), msg!("const_array_mut.note"))]);
+                            abort();
+                        }
+                        module.locals.push(Local{
+                            doc: std::mem::replace(&mut doccomments, String::new()),
+                            name: name,
+                            loc,
+                            vis,
+                            def: Def::Static {
+                                array,
+                                tags,
+                                storage: Storage::Const,
+                                typed,
+                                expr: expr.unwrap(),
+                                comptime,
+                            }
+                        });
+                    },
                     Rule::constant => {
                         for (_,tag) in tags.0 {
-                            emit_error("syntax error", &[(
+                            emit_error(msg!("anonymous_type_tags"), &[(
                                        tag.iter().next().unwrap().1.clone(),
-                                       "anonymous type cannot have storage tags (yet)")]);
-                            std::process::exit(9);
+                                       msg!("anonymous_type_tags.note"))]);
+                            abort();
                         }
 
                         module.locals.push(Local{
@@ -610,6 +949,7 @@ fn p(n: &Path, features: &HashMap<String, bool> , stage: &Stage) -> Result<Modul
                             def: Def::Const {
                                 typed,
                                 expr: expr.unwrap(),
+                                comptime,
                             }
                         });
                     },
@@ -625,6 +965,7 @@ fn p(n: &Path, features: &HashMap<String, bool> , stage: &Stage) -> Result<Modul
                                 storage,
                                 typed,
                                 expr: expr.unwrap(),
+                                comptime,
                             }
                         });
                     },
@@ -635,12 +976,94 @@ fn p(n: &Path, features: &HashMap<String, bool> , stage: &Stage) -> Result<Modul
 
         }
 
+        if module.locals.len() > declared {
+            let local = &module.locals[declared];
+            if let Some(d) = deprecated.take() {
+                module.deprecated.insert(local.name.clone(), d);
+            }
+            if let Some(u) = unstable.take() {
+                if local.vis == Visibility::Object {
+                    emit_error(msg!("unstable_private", name = local.name), &[
+                        (u.loc.clone(), msg!("unstable_private.note")),
+                    ]);
+                    abort();
+                }
+                module.unstable.insert(local.name.clone(), u);
+            }
+            if let Some(d) = derive.take() {
+                module.derive.insert(local.name.clone(), d);
+            }
+            let function = if let Def::Function{..} = local.def { true } else { false };
+            if let Some(loc) = weak.take() {
+                if !function || local.vis == Visibility::Object {
+                    emit_error(msg!("weak_not_function", name = local.name), &[(loc, msg!("weak_not_function.note"))]);
+                    abort();
+                }
+                if let Def::Function{attr, ..} = &mut module.locals[declared].def {
+                    attr.insert("weak".to_string(), loc);
+                }
+            }
+            if let Some(o) = overrides.take() {
+                if !function {
+                    emit_error(msg!("override_not_function", name = module.locals[declared].name), &[(o.loc, msg!("here"))]);
+                    abort();
+                }
+                module.overrides.insert(module.locals[declared].name.clone(), o);
+            }
+            if let Some(s) = symbol.take() {
+                let local = &module.locals[declared];
+                let linked = match &local.def {
+                    Def::Function{attr, ..} => !attr.contains_key("inline") && !attr.contains_key("extern"),
+                    _ => false,
+                };
+                if !linked || local.vis != Visibility::Export {
+                    emit_error(msg!("symbol_not_export", name = local.name), &[(s.loc, msg!("symbol_not_export.note"))]);
+                    abort();
+                }
+                module.symbols.insert(local.name.clone(), s);
+            }
+        }
+    }
+    let dangling = weak.into_iter().chain(overrides.map(|o| o.loc)).chain(symbol.map(|s| s.loc));
+    for loc in deprecated.map(|d| d.loc).into_iter().chain(unstable.map(|u| u.loc)).chain(derive.map(|d| d.loc)).chain(dangling) {
+        emit_error(msg!("attribute_dangling"), &[(loc, msg!("attribute_dangling.note"))]);
+        abort();
+    }
+
+    for local in &mut module.locals {
+        let doc = line_doc(file_str, &comment_spans, local.loc.start);
+        if !doc.is_empty() {
+            local.doc = doc + &local.doc;
+        }
     }
 
     Ok(module)
 }
 
-pub(crate) fn parse_expr(n: (&'static str, &Path), decl: pest::iterators::Pair<'static, Rule>) -> Expression {
+/// the text of the /// lines directly in front of a declaration at start.
+/// they document it the same way as /!
+pub fn line_doc(src: &str, comments: &[(usize, usize)], start: usize) -> String {
+    let mut lines = Vec::new();
+    // locations of some declarations only cover the name
+    let mut at = src[..start].rfind('\n').map(|p| p + 1).unwrap_or(0);
+    for &(s, e) in comments.iter().rev() {
+        if e > at {
+            continue;
+        }
+        let text = &src[s..e];
+        let own_line = src[..s].rsplit('\n').next().unwrap_or("").trim().is_empty();
+        // only /! lines may be in between
+        let adjacent = src[e..at].lines().all(|l| l.trim().is_empty() || l.trim().starts_with("/!"));
+        if !text.starts_with("///") || text.starts_with("////") || !own_line || !adjacent {
+            break;
+        }
+        lines.push(&text[3..]);
+        at = s;
+    }
+    lines.iter().rev().map(|l| format!("{}\n", l)).collect()
+}
+
+pub(crate) fn parse_expr(file: FileId, decl: pest::iterators::Pair<Rule>) -> Expression {
     match decl.as_rule() {
         Rule::expr  => { }
         Rule::expr_to_precedence_2 => {}
@@ -681,12 +1104,9 @@ pub(crate) fn parse_expr(n: (&'static str, &Path), decl: pest::iterators::Pair<'
 
     ]);
 
-    let reduce = |lhs: Expression, op: pest::iterators::Pair<'static, Rule>, rhs: Expression | {
+    let reduce = |lhs: Expression, op: pest::iterators::Pair<Rule>, rhs: Expression | {
 
-        let loc = Location{
-            file: n.1.to_string_lossy().into(),
-            span: op.as_span(),
-        };
+        let loc = Location::new(file, op.as_span());
 
         if op.as_rule()  == Rule::memberaccess {
             if let Expression::Name(typed) = &rhs {
@@ -702,7 +1122,7 @@ pub(crate) fn parse_expr(n: (&'static str, &Path), decl: pest::iterators::Pair<'
             emit_error(format!("ICE: unexpected rhs {:?}", rhs), &[
                        (loc.clone(), "in this memberaccess ")
             ]);
-            std::process::exit(9);
+            abort();
         } else if op.as_rule()  == Rule::ptraccess {
             if let Expression::Name(typed) = &rhs {
                 if let Type::Other(n) = &typed.t {
@@ -717,7 +1137,7 @@ pub(crate) fn parse_expr(n: (&'static str, &Path), decl: pest::iterators::Pair<'
             emit_error(format!("ICE: unexpected rhs {:?}", rhs), &[
                        (loc.clone(), "in this ptraccess ")
             ]);
-            std::process::exit(9);
+            abort();
         } else if op.as_rule()  == Rule::callstart {
             if let Expression::Call{loc, args, .. }  = &rhs {
                 return Expression::Call{
@@ -731,7 +1151,7 @@ pub(crate) fn parse_expr(n: (&'static str, &Path), decl: pest::iterators::Pair<'
             emit_error(format!("ICE: unexpected rhs {:?}", rhs), &[
                        (loc.clone(), "in this call ")
             ]);
-            std::process::exit(9);
+            abort();
         } else if op.as_rule()  == Rule::arraystart {
             return Expression::ArrayAccess {
                 loc:    loc.clone(),
@@ -767,20 +1187,17 @@ pub(crate) fn parse_expr(n: (&'static str, &Path), decl: pest::iterators::Pair<'
                     emit_error(format!("ICE: unexpected operator {}", op), &[
                         (loc.clone(), "in this infix")
                     ]);
-                    std::process::exit(9);
+                    abort();
                 }
             },
         }
     };
-    climber.climb(decl.into_inner(), |pair|parse_expr_inner(n, pair), reduce)
+    climber.climb(decl.into_inner(), |pair|parse_expr_inner(file, pair), reduce)
 }
 
 
-pub(crate) fn parse_expr_inner(n: (&'static str, &Path), expr: pest::iterators::Pair<'static, Rule>) -> Expression {
-    let loc = Location{
-        file: n.1.to_string_lossy().into(),
-        span: expr.as_span(),
-    };
+pub(crate) fn parse_expr_inner(file: FileId, expr: pest::iterators::Pair<Rule>) -> Expression {
+    let loc = Location::new(file, expr.as_span());
 
     let asrule = expr.as_rule();
     match asrule {
@@ -796,16 +1213,13 @@ pub(crate) fn parse_expr_inner(n: (&'static str, &Path), expr: pest::iterators::
                     emit_error("ICE: unexpected operator", &[
                                (loc.clone(), "in this expr")
                     ]);
-                    std::process::exit(9);
+                    abort();
                 }
             };
             let part   = expr.next().unwrap();
             let iexpr   = match part.as_rule() {
                 Rule::type_name => {
-                    let loc = Location{
-                        file: n.1.to_string_lossy().into(),
-                        span: part.as_span(),
-                    };
+                    let loc = Location::new(file, part.as_span());
                     let name = Name::from(part.as_str());
                     Expression::Name(Typed{
                         t:   Type::Other(name),
@@ -815,7 +1229,7 @@ pub(crate) fn parse_expr_inner(n: (&'static str, &Path), expr: pest::iterators::
                     })
                 },
                 Rule::expr_to_precedence_2 => {
-                    parse_expr(n, part)
+                    parse_expr(file, part)
                 }
                 e => panic!("unexpected rule {:?} in unary pre lhs", e),
             };
@@ -830,10 +1244,7 @@ pub(crate) fn parse_expr_inner(n: (&'static str, &Path), expr: pest::iterators::
             let part   = expr.next().unwrap();
             let iexpr   = match part.as_rule() {
                 Rule::type_name => {
-                    let loc = Location{
-                        file: n.1.to_string_lossy().into(),
-                        span: part.as_span(),
-                    };
+                    let loc = Location::new(file, part.as_span());
                     let name = Name::from(part.as_str());
                     Expression::Name(Typed{
                         t:   Type::Other(name),
@@ -843,7 +1254,7 @@ pub(crate) fn parse_expr_inner(n: (&'static str, &Path), expr: pest::iterators::
                     })
                 },
                 Rule::expr => {
-                    parse_expr(n, part)
+                    parse_expr(file, part)
                 }
                 e => panic!("unexpected rule {:?} in unary post lhs", e),
             };
@@ -856,7 +1267,7 @@ pub(crate) fn parse_expr_inner(n: (&'static str, &Path), expr: pest::iterators::
                     emit_error("ICE: unexpected operator", &[
                                (loc.clone(), "in this expr")
                     ]);
-                    std::process::exit(9);
+                    abort();
                 }
             };
 
@@ -869,13 +1280,14 @@ pub(crate) fn parse_expr_inner(n: (&'static str, &Path), expr: pest::iterators::
         Rule::cast => {
             let mut expr = expr.into_inner();
             let part  = expr.next().unwrap();
-            let into = parse_anon_type(n, part);
+            let (into, tags) = parse_anon_type_tags(file, part);
             let part  = expr.next().unwrap();
-            let expr = parse_expr(n, part);
+            let expr = parse_expr(file, part);
             Expression::Cast{
                 loc,
                 into,
                 expr: Box::new(expr),
+                tags,
             }
         },
         Rule::type_name => {
@@ -887,19 +1299,39 @@ pub(crate) fn parse_expr_inner(n: (&'static str, &Path), expr: pest::iterators::
                 tail: Tail::None,
             })
         },
+        Rule::sizeof_tail => {
+            let mut parts = expr.into_inner().next().unwrap().into_inner();
+            let typename = parts.next().unwrap();
+            let typeloc = Location::new(file, typename.as_span());
+            let tail = parse_tail(file, parts.next().unwrap());
+            if tail == Tail::Dynamic {
+                emit_error(msg!("sizeof_tail_unbound"), &[
+                    (typeloc.clone(), msg!("sizeof_tail_unbound.note"))
+                ]);
+                abort();
+            }
+            Expression::Call{
+                name: Box::new(Expression::Name(Typed{
+                    t:   Type::Other(Name::from("sizeof")),
+                    ptr: Vec::new(),
+                    loc: loc.clone(),
+                    tail: Tail::None,
+                })),
+                args: vec![Box::new(Expression::Name(Typed{
+                    t:   Type::Other(Name::from(typename.as_str())),
+                    ptr: Vec::new(),
+                    loc: typeloc,
+                    tail,
+                }))],
+                loc,
+                expanded:   false,
+                emit:       EmitBehaviour::Default,
+            }
+        },
         Rule::string_literal => {
-            let mut val = expr.as_str().to_string();
-            let v = if val.starts_with("r#") {
-                val.remove(0);
-                val.remove(0);
-                val.remove(0);
-                val.pop();
-                val.pop();
-                val.as_bytes().to_vec()
-            } else {
-                val.remove(0);
-                val.pop();
-                unescape(&val, &loc)
+            let v = match raw_string(expr.as_str()) {
+                Some(raw) => raw.as_bytes().to_vec(),
+                None => unescape(&loc),
             };
 
             Expression::LiteralString {
@@ -908,24 +1340,50 @@ pub(crate) fn parse_expr_inner(n: (&'static str, &Path), expr: pest::iterators::
             }
         }
         Rule::char_literal => {
-            let mut val = expr.as_str().to_string();
-            val.remove(0);
-            val.pop();
-            let v = unescape(&val, &loc);
+            let v = unescape(&loc);
+            if v.len() != 1 {
+                emit_error(msg!("literal_char_length", len = v.len()), &[
+                    (loc.clone(), msg!("literal_char_length.note")),
+                ]);
+                abort();
+            }
 
             Expression::LiteralChar {
                 v: v[0],
                 loc,
             }
         }
-        Rule::number_literal | Rule::bool_literal=> {
-            Expression::Literal {
-                v: expr.as_str().to_string(),
+        Rule::number_literal => {
+            let value = LiteralValue::parse(expr.as_str());
+            if !value.fits() {
+                let t = Typed{
+                    t:      value.suffix().unwrap().clone(),
+                    loc:    loc.clone(),
+                    ptr:    Vec::new(),
+                    tail:   Tail::None,
+                };
+                emit_error(msg!("literal_out_of_range", value = expr.as_str(), t = t), &[
+                    (loc.clone(), msg!("literal_out_of_range.note")),
+                ]);
+                abort();
+            }
+            if let Some(digit) = bad_octal_digit(expr.as_str()) {
+                emit_error(msg!("literal_octal_digit", value = expr.as_str(), digit = digit), &[
+                    (loc.clone(), msg!("literal_octal_digit.note")),
+                ]);
+                abort();
+            }
+            Expression::Literal{
+                v: c_number(expr.as_str()),
+                value,
                 loc,
             }
         },
+        Rule::bool_literal => {
+            Expression::literal(loc, expr.as_str().to_string())
+        },
         Rule::expr => {
-            parse_expr(n, expr)
+            parse_expr(file, expr)
         },
         Rule::deref | Rule::takeref => {
             let op = match expr.as_rule() {
@@ -937,10 +1395,7 @@ pub(crate) fn parse_expr_inner(n: (&'static str, &Path), expr: pest::iterators::
             let part = expr.into_inner().next().unwrap();
             let expr = match part.as_rule() {
                 Rule::type_name => {
-                    let loc = Location{
-                        file: n.1.to_string_lossy().into(),
-                        span: part.as_span(),
-                    };
+                    let loc = Location::new(file, part.as_span());
                     let name = Name::from(part.as_str());
                     Expression::Name(Typed{
                         t:   Type::Other(name),
@@ -950,7 +1405,7 @@ pub(crate) fn parse_expr_inner(n: (&'static str, &Path), expr: pest::iterators::
                     })
                 },
                 Rule::expr_to_precedence_2 => {
-                    parse_expr(n, part)
+                    parse_expr(file, part)
                 }
                 e => panic!("unexpected rule {:?} in deref lhs", e),
             };
@@ -961,31 +1416,75 @@ pub(crate) fn parse_expr_inner(n: (&'static str, &Path), expr: pest::iterators::
             }
         },
         Rule::call => {
-            parse_call(n, expr)
+            parse_call(file, expr)
         },
         Rule::array_init => {
-            let mut fields = Vec::new();
+            let mut fields : Vec<Box<Expression>> = Vec::new();
+            let mut index = Vec::new();
+            let mut designated = false;
             let expr = expr.into_inner();
             for part in expr {
-                match part.as_rule()  {
+                let at = Location::new(file, part.as_span());
+                let (i, field) = match part.as_rule()  {
                     Rule::expr => {
-                        let expr = parse_expr(n, part);
-                        fields.push(Box::new(expr));
+                        (index.last().map(|i| i + 1).unwrap_or(0), parse_expr(file, part))
                     }
-                    e => panic!("unexpected rule {:?} in struct init", e),
+                    Rule::array_index => {
+                        let mut part = part.into_inner();
+                        let i = part.next().unwrap();
+                        let i = match i.as_rule() {
+                            Rule::char_literal => unescape(&Location::new(file, i.as_span())).first().map(|c| *c as u64),
+                            _ => LiteralValue::parse(i.as_str()).as_u64(),
+                        };
+                        let i = match i {
+                            Some(i) => i,
+                            None => {
+                                emit_error(msg!("array_index_invalid"), &[(at, msg!("array_index_invalid.note"))]);
+                                abort();
+                            }
+                        };
+                        designated = true;
+                        (i, parse_expr(file, part.next().unwrap()))
+                    }
+                    // {value; count}, the copies share the location of value
+                    Rule::number_literal => {
+                        let count = match LiteralValue::parse(part.as_str()).as_u64() {
+                            Some(count) if count > 0 => count,
+                            _ => {
+                                emit_error(msg!("array_repeat_count"), &[(at, msg!("array_repeat_count.note"))]);
+                                abort();
+                            }
+                        };
+                        let value = fields.pop().unwrap();
+                        fields = (0..count).map(|_| value.clone()).collect();
+                        continue;
+                    }
+                    e => panic!("unexpected rule {:?} in array init", e),
+                };
+                if let Some(first) = index.iter().position(|x| *x == i) {
+                    emit_error(msg!("array_index_twice", index = i), &[
+                        (at, msg!("here")),
+                        (fields[first].loc().clone(), msg!("array_index_twice.first")),
+                    ]);
+                    abort();
                 }
-
+                index.push(i);
+                fields.push(Box::new(field));
+            }
+            if !designated {
+                index.clear();
             }
             Expression::ArrayInit{
                 loc,
                 fields,
+                index,
             }
         }
         Rule::struct_init => {
             let mut expr = expr.into_inner();
             let part  = expr.next().unwrap();
 
-            let typed = parse_anon_type(n, part);
+            let typed = parse_anon_type(file, part);
 
             let mut fields = Vec::new();
             for part in expr {
@@ -993,7 +1492,7 @@ pub(crate) fn parse_expr_inner(n: (&'static str, &Path), expr: pest::iterators::
                     Rule::struct_init_field => {
                         let mut part = part.into_inner();
                         let name = part.next().unwrap().as_str().to_string();
-                        let expr = parse_expr(n, part.next().unwrap());
+                        let expr = parse_expr(file, part.next().unwrap());
                         fields.push((name, Box::new(expr)));
                     }
                     e => panic!("unexpected rule {:?} in struct init", e),
@@ -1011,24 +1510,94 @@ pub(crate) fn parse_expr_inner(n: (&'static str, &Path), expr: pest::iterators::
     }
 }
 
+/// the condition of if, while and for. assignments parse here only to be rejected
+fn parse_condition(file: FileId, decl: pest::iterators::Pair<Rule>) -> Expression {
+    if decl.as_rule() == Rule::cond_assign {
+        let loc = Location::new(file, decl.as_span());
+        emit_error(msg!("assign_in_condition"), &[
+            (loc, msg!("assign_in_condition.note"))
+        ]);
+        abort();
+    }
+    parse_expr(file, decl)
+}
+
+/// the body of if, while and for. a ; before it is rejected
+fn parse_body(
+    file:       FileId,
+    features:   &HashMap<String, bool>,
+    stage:      &Stage,
+    decl:       pest::iterators::Pair<Rule>,
+    kind:       &str,
+) -> Block {
+    if decl.as_rule() == Rule::stray_semicolon {
+        let loc = Location::new(file, decl.as_span());
+        emit_error(msg!("stray_semicolon", kind = kind), &[
+            (loc, msg!("stray_semicolon.note"))
+        ]);
+        abort();
+    }
+    parse_block(file, features, stage, decl)
+}
+
+/// arguments, return type and body of a closure or a function declared in a function body
+fn parse_closure_tail(
+    file:       FileId,
+    features:   &HashMap<String, bool>,
+    stage:      &Stage,
+    parts:      pest::iterators::Pairs<Rule>,
+) -> (Vec<NamedArg>, Option<AnonArg>, Block) {
+    let mut args = Vec::new();
+    let mut ret = None;
+    let mut body = None;
+    for part in parts {
+        match part.as_rule() {
+            Rule::closure_args => {
+                for arg in part.into_inner() {
+                    let argloc = Location::new(file, arg.as_span());
+                    let TypedName{typed, name, tags} = parse_named_type(file, arg);
+                    args.push(NamedArg{typed, name, tags, loc: argloc});
+                }
+            }
+            Rule::ret_arg => {
+                let mut part = part.into_inner();
+                let (typed, tags) = parse_ret_type(file, part.next().unwrap());
+                let array = match part.next() {
+                    Some(array) => match array.into_inner().next() {
+                        Some(expr) => Some(parse_expr(file, expr)),
+                        None => {
+                            emit_error(msg!("returned_array_length"), &[(typed.loc.clone(), msg!("returned_array_length.note"))]);
+                            abort();
+                        }
+                    },
+                    None => None,
+                };
+                ret = Some(AnonArg{typed, array, tags});
+            }
+            Rule::block => {
+                body = Some(parse_block(file, features, stage, part));
+            }
+            e => panic!("unexpected rule {:?} in closure", e),
+        }
+    }
+    (args, ret, body.unwrap())
+}
+
 pub(crate) fn parse_statement(
-    n: (&'static str, &Path),
+    file:       FileId,
     features:   &HashMap<String, bool>,
     stage:      &Stage,
-    stm:        pest::iterators::Pair<'static, Rule>,
+    stm:        pest::iterators::Pair<Rule>,
     into:       &mut Vec<Box<Statement>>,
     current_if_statement: &mut Option<usize>,
 ) {
 
-    let loc = Location{
-        file: n.1.to_string_lossy().into(),
-        span: stm.as_span(),
-    };
+    let loc = Location::new(file, stm.as_span());
     match stm.as_rule() {
         Rule::mark_stm => {
             let mut stm = stm.into_inner();
             let part    = stm.next().unwrap();
-            let lhs     = parse_expr(n, part);
+            let lhs     = parse_expr(file, part);
             let part    = stm.next().unwrap();
             let mut part = part.into_inner();
             let key   = part.next().unwrap().as_str().into();
@@ -1049,6 +1618,28 @@ pub(crate) fn parse_statement(
                 label,
             }));
         },
+        Rule::await_stm | Rule::yield_stm | Rule::try_stm => {
+            let mut stm = stm.into_inner();
+            let key     = stm.next().unwrap();
+            let expr    = parse_expr(file, stm.next().unwrap());
+            // a call to await, yield or try, which only exists until coroutine.rs or propagate.rs lowers it
+            let name = Expression::Name(Typed{
+                t:      Type::Other(Name::from(key.as_str())),
+                loc:    Location::new(file, key.as_span()),
+                ptr:    Vec::new(),
+                tail:   Tail::None,
+            });
+            into.push(Box::new(Statement::Expr{
+                expr: Expression::Call{
+                    loc:        loc.clone(),
+                    name:       Box::new(name),
+                    args:       vec![Box::new(expr)],
+                    expanded:   false,
+                    emit:       EmitBehaviour::Default,
+                },
+                loc,
+            }));
+        },
         Rule::continue_stm => {
             into.push(Box::new(Statement::Continue{
                 loc,
@@ -1060,7 +1651,7 @@ pub(crate) fn parse_statement(
             }));
         },
         Rule::block => {
-            into.push(Box::new(Statement::Block(Box::new(parse_block(n, features, stage, stm)))))
+            into.push(Box::new(Statement::Block(Box::new(parse_block(file, features, stage, stm)))))
         },
         Rule::return_stm  => {
             let mut stm = stm.into_inner();
@@ -1070,7 +1661,7 @@ pub(crate) fn parse_statement(
                 a => { panic!("expected key_return instead of {:?}", a );}
             };
             let expr = if let Some(expr) = stm.next() {
-                Some(parse_expr(n, expr))
+                Some(parse_expr(file, expr))
             } else {
                 None
             };
@@ -1080,7 +1671,7 @@ pub(crate) fn parse_statement(
             }));
         },
         Rule::expr => {
-            let expr = parse_expr(n, stm);
+            let expr = parse_expr(file, stm);
             into.push(Box::new(Statement::Expr{
                 expr,
                 loc: loc.clone(),
@@ -1089,9 +1680,9 @@ pub(crate) fn parse_statement(
         Rule::while_stm => {
             let mut stm = stm.into_inner();
             let part    = stm.next().unwrap();
-            let expr    = parse_expr(n, part);
+            let expr    = parse_condition(file, part);
             let part    = stm.next().unwrap();
-            let body    = parse_block(n, features, stage, part);
+            let body    = parse_body(file, features, stage, part, "while");
             into.push(Box::new(Statement::While {
                 expr,
                 body,
@@ -1100,9 +1691,9 @@ pub(crate) fn parse_statement(
         Rule::if_stm => {
             let mut stm = stm.into_inner();
             let part    = stm.next().unwrap();
-            let expr    = parse_expr(n, part);
+            let expr    = parse_condition(file, part);
             let part    = stm.next().unwrap();
-            let body    = parse_block(n, features, stage, part);
+            let body    = parse_body(file, features, stage, part, "if");
             *current_if_statement = Some(into.len());
             into.push(Box::new(Statement::If{
                 branches: vec![(loc.clone(), Some(expr), body)],
@@ -1111,15 +1702,15 @@ pub(crate) fn parse_statement(
         Rule::elseif_stm => {
             let mut stm = stm.into_inner();
             let part    = stm.next().unwrap();
-            let expr    = parse_expr(n, part);
+            let expr    = parse_condition(file, part);
             let part    = stm.next().unwrap();
-            let body    = parse_block(n, features, stage, part);
+            let body    = parse_body(file, features, stage, part, "else if");
             match *current_if_statement {
                 None => {
-                    emit_error("else without if", &[
-                        (loc.clone(), "this else branch does not follow an if condition")
+                    emit_error(msg!("else_without_if"), &[
+                        (loc.clone(), msg!("else_without_if.note"))
                     ]);
-                    std::process::exit(9);
+                    abort();
                 }
                 Some(c) => {
                     if let Statement::If{ref mut branches} = *into[c] {
@@ -1131,13 +1722,13 @@ pub(crate) fn parse_statement(
         Rule::else_stm => {
             let mut stm = stm.into_inner();
             let part    = stm.next().unwrap();
-            let body    = parse_block(n, features, stage, part);
+            let body    = parse_block(file, features, stage, part);
             match *current_if_statement {
                 None => {
-                    emit_error("else without if", &[
-                        (loc.clone(), "this else branch does not follow an if condition")
+                    emit_error(msg!("else_without_if"), &[
+                        (loc.clone(), msg!("else_without_if.note"))
                     ]);
-                    std::process::exit(9);
+                    abort();
                 }
                 Some(c) => {
                     if let Statement::If{ref mut branches} = *into[c] {
@@ -1147,6 +1738,112 @@ pub(crate) fn parse_statement(
             }
             *current_if_statement = None;
         }
+        Rule::closure_stm => {
+            let mut stm = stm.into_inner();
+            let part    = stm.next().unwrap();
+            let nameloc = Location::new(file, part.as_span());
+            let name    = part.as_str().to_string();
+            let part    = stm.next().unwrap();
+            let fntype  = Typed{
+                t:      Type::Other(Name::from(part.as_str())),
+                ptr:    Vec::new(),
+                loc:    Location::new(file, part.as_span()),
+                tail:   Tail::None,
+            };
+            let mut captures = Vec::new();
+            for capture in stm.next().unwrap().into_inner() {
+                let caploc = Location::new(file, capture.as_span());
+                let mut capture = capture.into_inner();
+                let TypedName{typed, name, tags} = parse_named_type(file, capture.next().unwrap());
+                // [int scale] is short for [int scale = scale]
+                let value = match capture.next() {
+                    Some(expr) => parse_expr(file, expr),
+                    None => Expression::Name(Typed{
+                        t:      Type::Other(Name::from(name.as_str())),
+                        ptr:    Vec::new(),
+                        loc:    caploc.clone(),
+                        tail:   Tail::None,
+                    }),
+                };
+                captures.push((Field{
+                    typed,
+                    name,
+                    array:  None,
+                    bits:   None,
+                    range:  None,
+                    default: None,
+                    tags,
+                    loc:    caploc,
+                }, value));
+            }
+            let (args, ret, body) = parse_closure_tail(file, features, stage, stm);
+            // a block of declarations, until closure.rs lowers it
+            into.push(Box::new(Statement::Block(Box::new(closure::marker(closure::Inline{
+                name,
+                loc:    nameloc,
+                fntype,
+                captures,
+                args,
+                ret,
+                body,
+                nested: false,
+            })))));
+        }
+        Rule::nested_fn => {
+            let mut stm = stm.into_inner();
+            let part    = stm.next().unwrap();
+            let nameloc = Location::new(file, part.as_span());
+            let name    = part.as_str().to_string();
+            let (args, ret, body) = parse_closure_tail(file, features, stage, stm);
+            // a block of declarations, until closure.rs lifts it
+            into.push(Box::new(Statement::Block(Box::new(closure::marker(closure::Inline{
+                name,
+                loc:    nameloc.clone(),
+                fntype: Typed{
+                    t:      Type::Elided,
+                    ptr:    Vec::new(),
+                    loc:    nameloc,
+                    tail:   Tail::None,
+                },
+                captures: Vec::new(),
+                args,
+                ret,
+                body,
+                nested: true,
+            })))));
+        }
+        Rule::range_for => {
+            let mut stm = stm.into_inner();
+            let TypedName{typed, name, tags} = parse_named_type(file, stm.next().unwrap());
+            let over    = parse_expr(file, stm.next().unwrap());
+            let body    = parse_block(file, features, stage, stm.next().unwrap());
+            // a for declaring the variable from a marker call, until coroutine.rs lowers it
+            let marker = Expression::Call{
+                loc:        over.loc().clone(),
+                name:       Box::new(Expression::Name(Typed{
+                    t:      Type::Other(Name::from(coroutine::RANGE)),
+                    loc:    loc.clone(),
+                    ptr:    Vec::new(),
+                    tail:   Tail::None,
+                })),
+                args:       vec![Box::new(over)],
+                expanded:   false,
+                emit:       EmitBehaviour::Default,
+            };
+            into.push(Box::new(Statement::For{
+                e1:     vec![Box::new(Statement::Var{
+                    loc,
+                    typed,
+                    tags,
+                    name,
+                    array:  None,
+                    assign: Some(marker),
+                })],
+                e2:     None,
+                e3:     Vec::new(),
+                body,
+            }));
+        }
         Rule::for_stm => {
 
 
@@ -1165,19 +1862,22 @@ pub(crate) fn parse_statement(
                     Rule::semicolon => {
                         cur += 1;
                     },
+                    Rule::stray_semicolon => {
+                        parse_body(file, features, stage, part, "for");
+                    },
                     Rule::block if cur == 3 && block.is_none() => {
-                        block = Some(parse_block(n, features, stage, part));
+                        block = Some(parse_block(file, features, stage, part));
                     },
                     _ if cur == 1 => {
                         let mut cif = None;
-                        parse_statement(n, features, stage, part, &mut expr1, &mut cif);
+                        parse_statement(file, features, stage, part, &mut expr1, &mut cif);
                     },
                     _ if cur == 2 => {
-                        expr2 = Some(parse_expr(n, part));
+                        expr2 = Some(parse_condition(file, part));
                     },
                     _ if cur == 3 => {
                         let mut cif = None;
-                        parse_statement(n, features, stage, part, &mut expr3, &mut cif);
+                        parse_statement(file, features, stage, part, &mut expr3, &mut cif);
                     },
                     e => panic!("unexpected rule {:?} in for ", e),
                 }
@@ -1199,14 +1899,14 @@ pub(crate) fn parse_statement(
             for part in stm {
                 match part.as_rule() {
                     Rule::named_type => {
-                        typed = Some(parse_named_type(n, part));
+                        typed = Some(parse_named_type(file, part));
                     },
                     Rule::expr => {
-                        assign = Some(parse_expr(n, part));
+                        assign = Some(parse_expr(file, part));
                     }
                     Rule::array => {
                         if let Some(expr) = part.into_inner().next() {
-                            array = Some(Some(parse_expr(n, expr)));
+                            array = Some(Some(parse_expr(file, expr)));
                         } else {
                             array = Some(None);
                         }
@@ -1235,7 +1935,7 @@ pub(crate) fn parse_statement(
             for part in stm {
                 match part.as_rule() {
                     Rule::expr if lhs.is_none() => {
-                        lhs = Some(parse_expr(n, part));
+                        lhs = Some(parse_expr(file, part));
                     }
                     Rule::assignop => {
                         op = Some(match part.into_inner().next().unwrap().as_rule() {
@@ -1248,12 +1948,12 @@ pub(crate) fn parse_statement(
                                 emit_error("ICE: unexpected operator", &[
                                     (loc.clone(), "in this assign expr")
                                 ]);
-                                std::process::exit(9);
+                                abort();
                             }
                         });
                     }
                     Rule::expr if rhs.is_none() => {
-                        rhs = Some(parse_expr(n, part));
+                        rhs = Some(parse_expr(file, part));
                     }
                     e => panic!("unexpected rule {:?} in assign", e),
                 }
@@ -1269,7 +1969,7 @@ pub(crate) fn parse_statement(
         Rule::switch_stm => {
             let mut stm  = stm.into_inner();
             let mut default = None;
-            let expr = parse_expr(n, stm.next().unwrap());
+            let expr = parse_expr(file, stm.next().unwrap());
 
             let mut cases = Vec::new();
 
@@ -1278,20 +1978,20 @@ pub(crate) fn parse_statement(
                 let ppart = part.next().unwrap();
                 if ppart.as_rule() == Rule::key_default {
                     if default.is_some() {
-                        emit_error("multiple default cases", &[
-                            (loc.clone(), "in this switch")
+                        emit_error(msg!("multiple_default_cases"), &[
+                            (loc.clone(), msg!("multiple_default_cases.note"))
                         ]);
-                        std::process::exit(9);
+                        abort();
                     } else {
-                        default = Some(parse_block(n, features,  stage,part.next().unwrap()));
+                        default = Some(parse_block(file, features,  stage,part.next().unwrap()));
                     }
                 } else {
                     let mut case_cond = Vec::new();
                     for case in ppart.into_inner() {
-                        case_cond.push(parse_expr(n, case));
+                        case_cond.push(parse_expr(file, case));
                     }
 
-                    let block = parse_block(n, features,  stage,part.next().unwrap());
+                    let block = parse_block(file, features,  stage,part.next().unwrap());
                     cases.push((case_cond,block));
                 }
             }
@@ -1304,14 +2004,11 @@ pub(crate) fn parse_statement(
             }))
         },
         Rule::unsafe_block => {
-            into.push(Box::new(Statement::Unsafe(Box::new(parse_block(n, features, stage, stm.into_inner().next().unwrap())))));
+            into.push(Box::new(Statement::Unsafe(Box::new(parse_block(file, features, stage, stm.into_inner().next().unwrap())))));
         },
         Rule::cblock => {
             let stm = stm.into_inner().next().unwrap();
-            let loc = Location{
-                file: n.1.to_string_lossy().into(),
-                span: stm.as_span(),
-            };
+            let loc = Location::new(file, stm.as_span());
             into.push(Box::new(Statement::CBlock{
                 loc,
                 lit: stm.as_str().to_string()
@@ -1322,25 +2019,22 @@ pub(crate) fn parse_statement(
 }
 
 pub(crate) fn parse_block(
-        n:          (&'static str, &Path),
+        file:       FileId,
         features:   &HashMap<String,bool>,
         stage:      &Stage,
-        decl:       pest::iterators::Pair<'static, Rule>
+        decl:       pest::iterators::Pair<Rule>
 ) -> Block {
     match decl.as_rule() {
         Rule::block => { }
         _ => { panic!("parse_block called with {:?}", decl); }
     };
 
-    let end = Location{
-        file: n.1.to_string_lossy().into(),
-        span: pest::Span::new(n.0, decl.as_span().end(), decl.as_span().end()).unwrap(),
-    };
+    let end = Location::at(file, decl.as_span().end(), decl.as_span().end());
 
     let mut statements = Vec::new();
     let mut cif_state = None;
-    for stm in PP::new(n.1, features.clone(), stage.clone(), decl.into_inner()) {
-        parse_statement(n, features, stage, stm, &mut statements, &mut cif_state)
+    for stm in PP::new(file, features.clone(), stage.clone(), decl.into_inner()) {
+        parse_statement(file, features, stage, stm, &mut statements, &mut cif_state)
     }
     Block{
         statements,
@@ -1352,6 +2046,370 @@ pub(crate) fn parse_block(
 
 // typed is parsed left to right
 
+fn parse_function(
+        file:       FileId,
+        features:   &HashMap<String,bool>,
+        stage:      &Stage,
+        decl:       pest::iterators::Pair<Rule>,
+        doc:        String,
+    ) -> Local
+{
+    let loc = Location::new(file, decl.as_span());
+    let mut nameloc = loc.clone();
+    let declrule = decl.as_rule().clone();
+    let decl = decl.into_inner();
+    let mut name = String::new();
+    let mut args = Vec::new();
+    let mut ret  = None;
+    let mut body = None;
+    let mut attr = HashMap::new();
+    let mut vararg = false;
+    let mut callassert = Vec::new();
+    let mut calleffect = Vec::new();
+    let mut vis = Visibility::Object;
+    let mut hints = HashMap::new();
+
+    for part in decl {
+        match part.as_rule() {
+            Rule::key_shared => {
+                vis = shared(&part);
+            }
+            Rule::exported => {
+                vis = Visibility::Export;
+            }
+            Rule::ident => {
+                nameloc = Location::new(file, part.as_span());
+                name = part.as_str().into();
+            }
+            Rule::ret_arg => {
+                let mut part = part.into_inner();
+                let (typed, tags) = parse_ret_type(file, part.next().unwrap());
+                let array = match part.next() {
+                    Some(array) => match array.into_inner().next() {
+                        Some(expr) => Some(parse_expr(file, expr)),
+                        None => {
+                            emit_error(msg!("returned_array_length"), &[(typed.loc.clone(), msg!("returned_array_length.note"))]);
+                            abort();
+                        }
+                    },
+                    None => None,
+                };
+                ret = Some(AnonArg{
+                    typed,
+                    array,
+                    tags,
+                });
+            },
+            Rule::fn_attr => {
+                let loc  = Location::new(file, part.as_span());
+                attr.insert(part.as_str().into(), loc);
+            },
+            Rule::fn_args => {
+                for arg in part.into_inner() {
+
+                    let argloc  = Location::new(file, arg.as_span());
+
+                    if arg.as_rule() == Rule::vararg {
+                        vararg = true;
+                    } else if arg.as_rule() == Rule::len_tag {
+                        let of : &NamedArg = args.last().unwrap();
+                        if declrule != Rule::function {
+                            emit_error(msg!("len_tag_declaration"), &[(argloc, msg!("len_tag_declaration.note"))]);
+                            abort();
+                        }
+                        if of.typed.ptr.is_empty() {
+                            emit_error(msg!("len_tag_not_pointer", name = of.name), &[(argloc, msg!("here"))]);
+                            abort();
+                        }
+                        let name = |name: &str| Box::new(Expression::Name(Typed{
+                            t:      Type::Other(Name::from(name)),
+                            ptr:    Vec::new(),
+                            loc:    argloc.clone(),
+                            tail:   Tail::None,
+                        }));
+                        callassert.push(Expression::Infix{
+                            loc:    argloc.clone(),
+                            op:     InfixOperator::Moreeq,
+                            lhs:    Box::new(Expression::Call{
+                                loc:        argloc.clone(),
+                                name:       name("len"),
+                                args:       vec![name(&of.name)],
+                                expanded:   false,
+                                emit:       EmitBehaviour::Default,
+                            }),
+                            rhs:    Box::new(parse_expr(file, arg.into_inner().next().unwrap())),
+                        });
+                    } else if arg.as_rule() == Rule::range_tag {
+                        let of : &NamedArg = args.last().unwrap();
+                        if declrule != Rule::function {
+                            emit_error(msg!("range_tag_declaration"), &[(argloc, msg!("range_tag_declaration.note"))]);
+                            abort();
+                        }
+                        if !of.typed.ptr.is_empty() {
+                            emit_error(msg!("range_tag_not_integer", name = of.name), &[(argloc, msg!("here"))]);
+                            abort();
+                        }
+                        let (lo, hi) = parse_range_tag(file, arg);
+                        for (op, bound) in vec![(InfixOperator::Moreeq, lo), (InfixOperator::Lesseq, hi)] {
+                            callassert.push(Expression::Infix{
+                                loc:    argloc.clone(),
+                                op,
+                                lhs:    Box::new(Expression::Name(Typed{
+                                    t:      Type::Other(Name::from(of.name.as_str())),
+                                    ptr:    Vec::new(),
+                                    loc:    argloc.clone(),
+                                    tail:   Tail::None,
+                                })),
+                                rhs:    Box::new(bound),
+                            });
+                        }
+                    } else {
+                        let TypedName{typed, name, tags} = parse_named_type(file, arg);
+
+                        args.push(NamedArg{
+                            name,
+                            typed,
+                            tags,
+                            loc: argloc,
+                        });
+                    }
+                }
+            },
+            Rule::call_assert => {
+                let part = part.into_inner().next().unwrap();
+                callassert.push(parse_expr(file, part));
+            },
+            Rule::call_effect => {
+                let part = part.into_inner().next().unwrap();
+                calleffect.push(parse_expr(file, part));
+            },
+            Rule::block => {
+                body = Some(parse_block(file, features, stage, part));
+            },
+            Rule::fn_vattr => {
+                let mut part = part.into_inner();
+                let key  = part.next().unwrap().as_str().to_string();
+                let val  = part.next().unwrap().as_str().to_string();
+                hints.insert(key, val);
+            }
+            e => panic!("unexpected rule {:?} in function", e),
+        }
+    }
+
+    if let Some(AnonArg{typed, array: Some(_), ..}) = &ret {
+        if declrule != Rule::function || !attr.contains_key("comptime") {
+            emit_error(msg!("returned_array_not_comptime"), &[(typed.loc.clone(), msg!("returned_array_not_comptime.note"))]);
+            abort();
+        }
+    }
+
+    let def = match declrule {
+        Rule::function => Def::Function{
+            nameloc,
+            ret,
+            attr,
+            hints,
+            args,
+            body: body.unwrap(),
+            vararg,
+            callassert,
+            calleffect,
+            callattests: Vec::new(),
+        },
+        Rule::theory => Def::Theory{
+            ret,
+            attr,
+            args,
+        },
+        Rule::fntype => Def::Fntype{
+            nameloc,
+            ret,
+            attr,
+            args,
+            vararg,
+        },
+        _ => unreachable!()
+    };
+
+    Local{
+        doc,
+        name,
+        vis,
+        loc,
+        def,
+    }
+}
+
+fn parse_struct_field(file: FileId, decl: pest::iterators::Pair<Rule>) -> Field {
+    let loc  = Location::new(file, decl.as_span());
+
+    let mut decl = decl.into_inner();
+
+    let TypedName{typed, name, tags} = parse_named_type(file, decl.next().unwrap());
+
+    let mut array = None;
+    let mut bits  = None;
+    let mut range = None;
+    let mut default = None;
+    for part in decl {
+        match part.as_rule() {
+            Rule::bitwidth => {
+                let part = part.into_inner().next().unwrap();
+                match LiteralValue::parse(part.as_str()).as_u64() {
+                    Some(v) if v > 0 => bits = Some(v),
+                    _ => {
+                        let loc = Location::new(file, part.as_span());
+                        emit_error(msg!("bitfield_width", name = name, bits = part.as_str()), &[
+                            (loc, msg!("bitfield_width.zero")),
+                        ]);
+                        abort();
+                    }
+                }
+            }
+            Rule::range_tag => {
+                if !typed.ptr.is_empty() || array.is_some() {
+                    let loc = Location::new(file, part.as_span());
+                    emit_error(msg!("range_tag_not_integer", name = name), &[(loc, msg!("here"))]);
+                    abort();
+                }
+                range = Some(parse_range_tag(file, part));
+            }
+            Rule::field_default => {
+                default = Some(parse_expr(file, part.into_inner().next().unwrap()));
+            }
+            _ => {
+                match part.into_inner().next() {
+                    Some(expr) => {
+                        array = Some(Some(parse_expr(file, expr)));
+                    },
+                    None => {
+                        array = Some(None);
+                    }
+                }
+            }
+        }
+    }
+
+    Field{
+        typed,
+        array,
+        bits,
+        range,
+        default,
+        tags,
+        name,
+        loc,
+    }
+}
+
+/// a struct or union declared inline as a field of outer, like
+///
+///   struct { u32 a; u32 b; } header;
+///
+/// becomes a struct of its own named outer_header, pushed to out before outer.
+/// the field has that struct as type.
+fn parse_anon_struct(
+    file:       FileId,
+    features:   &HashMap<String, bool>,
+    stage:      &Stage,
+    decl:       pest::iterators::Pair<Rule>,
+    outer:      &str,
+    vis:        Visibility,
+    out:        &mut Vec<Local>,
+) -> Field {
+    let loc = Location::new(file, decl.as_span());
+
+    // the field name comes after the body, but nested structs are named after it
+    let name = match decl.clone().into_inner().find(|part| part.as_rule() == Rule::ident) {
+        Some(name) => name.as_str().to_string(),
+        None => {
+            emit_error(msg!("anon_struct_unnamed", name = outer), &[(loc, msg!("anon_struct_unnamed.note"))]);
+            abort();
+        }
+    };
+    let typename = format!("{}_{}", outer, name);
+
+    let mut union  = false;
+    let mut fields = Vec::new();
+    for part in PP::new(file, features.clone(), stage.clone(), decl.into_inner()) {
+        match part.as_rule() {
+            Rule::key_struct => {
+                union = false;
+            }
+            Rule::key_union => {
+                union = true;
+            }
+            Rule::struct_f => {
+                fields.push(parse_struct_field(file, part));
+            }
+            Rule::struct_anon => {
+                fields.push(parse_anon_struct(file, features, stage, part, &typename, vis.clone(), out));
+            }
+            Rule::ident => (),
+            e => panic!("unexpected rule {:?} in struct", e),
+        }
+    }
+
+    if union {
+        no_union_default(&fields);
+    }
+    if let Some(field) = fields.iter().find(|field| matches!(field.array, Some(None))) {
+        emit_error(msg!("flexible_array_not_last", name = field.name), &[
+            (field.loc.clone(), msg!("anon_struct_flexible")),
+        ]);
+        abort();
+    }
+
+    out.push(Local{
+        doc:    String::new(),
+        name:   typename.clone(),
+        vis,
+        loc:    loc.clone(),
+        def:    Def::Struct {
+            fields,
+            packed: false,
+            tail:   Tail::None,
+            union,
+            impls:  HashMap::new(),
+            abi:    None,
+        }
+    });
+
+    Field{
+        typed: Typed{
+            t:      Type::Other(Name::from(&typename)),
+            loc:    loc.clone(),
+            ptr:    Vec::new(),
+            tail:   Tail::None,
+        },
+        array:  None,
+        bits:   None,
+        range:  None,
+        default: None,
+        tags:   Tags::new(),
+        name,
+        loc,
+    }
+}
+
+/// an initializer of a union sets one field, it can't also set another to its default
+fn no_union_default(fields: &[Field]) {
+    for field in fields {
+        if let Some(default) = &field.default {
+            emit_error(msg!("field_default_union", name = field.name), &[(default.loc().clone(), msg!("field_default_union.note"))]);
+            abort();
+        }
+    }
+}
+
+/// the bounds in @range(lo, hi)
+fn parse_range_tag(file: FileId, decl: pest::iterators::Pair<Rule>) -> (Expression, Expression) {
+    let mut decl = decl.into_inner();
+    let lo = parse_expr(file, decl.next().unwrap());
+    let hi = parse_expr(file, decl.next().unwrap());
+    (lo, hi)
+}
+
 #[derive(Debug)]
 pub(crate) struct TypedName {
     name:   String,
@@ -1359,16 +2417,13 @@ pub(crate) struct TypedName {
     tags:   Tags,
 }
 
-pub(crate) fn parse_named_type(n: (&'static str, &Path), decl: pest::iterators::Pair<'static, Rule>) -> TypedName {
+pub(crate) fn parse_named_type(file: FileId, decl: pest::iterators::Pair<Rule>) -> TypedName {
     match decl.as_rule() {
         Rule::named_type => { }
         _ => { panic!("parse_named_type called with {:?}", decl); }
     };
 
-    let loc = Location{
-        file: n.1.to_string_lossy().into(),
-        span: decl.as_span(),
-    };
+    let loc = Location::new(file, decl.as_span());
 
     let mut tail = Tail::None;
 
@@ -1379,10 +2434,7 @@ pub(crate) fn parse_named_type(n: (&'static str, &Path), decl: pest::iterators::
     for lhs in lhsdecl {
         match lhs.as_rule() {
             Rule::tail => {
-                let loc = Location{
-                    file: n.1.to_string_lossy().into(),
-                    span: lhs.as_span(),
-                };
+                let loc = Location::new(file, lhs.as_span());
                 let mut part = lhs.as_str().to_string();
                 part.remove(0);
                 if part.len() > 0 {
@@ -1401,32 +2453,26 @@ pub(crate) fn parse_named_type(n: (&'static str, &Path), decl: pest::iterators::
     
 
     // the local variable name is on the right;
-    let mut decl : Vec<pest::iterators::Pair<'static, Rule>> = decl.collect();
+    let mut decl : Vec<pest::iterators::Pair<Rule>> = decl.collect();
     let name_part = decl.pop().unwrap();
     let name = match name_part.as_rule() {
         Rule::ident => {
             let name = name_part.as_str().to_string();
             if name == "return" {
-                let loc = Location{
-                    file: n.1.to_string_lossy().into(),
-                    span: name_part.as_span(),
-                };
-                emit_error("syntax error", &[
-                    (loc.clone(), "llegal use of keyword 'return'"),
+                let loc = Location::new(file, name_part.as_span());
+                emit_error(msg!("keyword_as_name", name = "return"), &[
+                    (loc.clone(), msg!("here")),
                 ]);
-                std::process::exit(9);
+                abort();
             }
             name
         }
         _ => {
-            let loc = Location{
-                file: n.1.to_string_lossy().into(),
-                span: name_part.as_span(),
-            };
-            emit_error("syntax error", &[
-                (loc.clone(), "expected a name")
+            let loc = Location::new(file, name_part.as_span());
+            emit_error(msg!("expected_name"), &[
+                (loc.clone(), msg!("here"))
             ]);
-            std::process::exit(9);
+            abort();
         }
     };
 
@@ -1434,10 +2480,7 @@ pub(crate) fn parse_named_type(n: (&'static str, &Path), decl: pest::iterators::
     let mut ptr = Vec::new();
 
     for part in decl {
-        let loc = Location{
-            file: n.1.to_string_lossy().into(),
-            span: part.as_span(),
-        };
+        let loc = Location::new(file, part.as_span());
         match part.as_rule() {
             Rule::ptr => {
                 ptr.push(Pointer{
@@ -1454,6 +2497,11 @@ pub(crate) fn parse_named_type(n: (&'static str, &Path), decl: pest::iterators::
                 let value = part.next().as_ref().map(|s|s.as_str().to_string()).unwrap_or(String::new());
                 tags.insert(name, value, loc);
             }
+            Rule::unit_tag => {
+                let unit = part.into_inner().next().unwrap().as_str();
+                let unit = raw_string(unit).unwrap_or(&unit[1..unit.len() - 1]);
+                tags.insert(units::TAG.to_string(), unit.to_string(), loc);
+            }
             e => panic!("unexpected rule {:?} in named_type ", e),
         }
     }
@@ -1472,16 +2520,39 @@ pub(crate) fn parse_named_type(n: (&'static str, &Path), decl: pest::iterators::
     }
 }
 
-pub(crate) fn parse_anon_type(n: (&'static str, &Path), decl: pest::iterators::Pair<'static, Rule>) -> Typed {
+pub(crate) fn parse_anon_type(file: FileId, decl: pest::iterators::Pair<Rule>) -> Typed {
+    let (typed, tags) = parse_anon_type_tags(file, decl);
+    for (_,tag) in tags.0 {
+        emit_error(msg!("anonymous_type_tags"), &[
+            (tag.iter().next().unwrap().1.clone(), msg!("anonymous_type_tags.note")),
+        ]);
+        abort();
+    }
+    typed
+}
+
+/// the type of a return value, which may only be tagged with a unit
+fn parse_ret_type(file: FileId, decl: pest::iterators::Pair<Rule>) -> (Typed, Tags) {
+    let (typed, tags) = parse_anon_type_tags(file, decl);
+    for (k, tag) in &tags.0 {
+        if k != units::TAG {
+            emit_error(msg!("anonymous_type_tags"), &[
+                (tag.iter().next().unwrap().1.clone(), msg!("anonymous_type_tags.note")),
+            ]);
+            abort();
+        }
+    }
+    (typed, tags)
+}
+
+/// an anonymous type, and the tags after its last pointer
+pub(crate) fn parse_anon_type_tags(file: FileId, decl: pest::iterators::Pair<Rule>) -> (Typed, Tags) {
     match decl.as_rule() {
         Rule::anon_type => { }
         _ => { panic!("parse_anon_type called with {:?}", decl); }
     };
 
-    let loc = Location{
-        file: n.1.to_string_lossy().into(),
-        span: decl.as_span(),
-    };
+    let loc = Location::new(file, decl.as_span());
     //the actual type name is always on the left hand side
     let mut decl = decl.into_inner();
     let name = Name::from(decl.next().unwrap().as_str());
@@ -1491,10 +2562,7 @@ pub(crate) fn parse_anon_type(n: (&'static str, &Path), decl: pest::iterators::P
     let mut tail = Tail::None;
 
     for part in decl {
-        let loc = Location{
-            file: n.1.to_string_lossy().into(),
-            span: part.as_span(),
-        };
+        let loc = Location::new(file, part.as_span());
         match part.as_rule() {
             Rule::ptr => {
                 ptr.push(Pointer{
@@ -1511,40 +2579,90 @@ pub(crate) fn parse_anon_type(n: (&'static str, &Path), decl: pest::iterators::P
                 let value = part.next().as_ref().map(|s|s.as_str().to_string()).unwrap_or(String::new());
                 tags.insert(name, value, loc);
             }
+            Rule::unit_tag => {
+                let unit = part.into_inner().next().unwrap().as_str();
+                let unit = raw_string(unit).unwrap_or(&unit[1..unit.len() - 1]);
+                tags.insert(units::TAG.to_string(), unit.to_string(), loc);
+            }
             Rule::tail => {
-                let loc = Location{
-                    file: n.1.to_string_lossy().into(),
-                    span: part.as_span(),
-                };
-                let mut part = part.as_str().to_string();
-                part.remove(0);
-                if part.len() > 0 {
-                    if let Ok(n) = part.parse::<u64>() {
-                        tail = Tail::Static(n, loc);
-                    } else {
-                        tail = Tail::Bind(part, loc);
-                    }
-                } else {
-                    tail = Tail::Dynamic
-                }
+                tail = parse_tail(file, part);
             },
             e => panic!("unexpected rule {:?} in anon_type", e),
         }
     }
 
-    for (_,tag) in tags.0 {
-        emit_error("syntax error", &[
-            (tag.iter().next().unwrap().1.clone(), "anonymous type cannot have storage tags (yet)"),
-        ]);
-        std::process::exit(9);
-    }
-
-    Typed {
+    (Typed {
         t: Type::Other(name),
         loc, ptr, tail,
+    }, tags)
+}
+
+
+/// +, +100 or +n behind a type
+fn parse_tail(file: FileId, part: pest::iterators::Pair<Rule>) -> Tail {
+    let loc = Location::new(file, part.as_span());
+    let mut part = part.as_str().to_string();
+    part.remove(0);
+    if part.len() > 0 {
+        if let Ok(n) = part.parse::<u64>() {
+            Tail::Static(n, loc)
+        } else {
+            Tail::Bind(part, loc)
+        }
+    } else {
+        Tail::Dynamic
     }
 }
 
+/// byte ranges of all // and /* */ comments.
+/// the grammar skips them implicitly, so this scans the source again
+pub fn comments(src: &str) -> Vec<(usize, usize)> {
+    let b = src.as_bytes();
+    let mut r = Vec::new();
+    let mut i = 0;
+    let skip_to = |from: usize, end: &str| -> usize {
+        match src[from..].find(end) {
+            Some(p) => from + p + end.len(),
+            None => b.len(),
+        }
+    };
+    while i < b.len() {
+        // raw strings, but not an r at the end of a name
+        if b[i] == b'r' && (i == 0 || !(b[i-1].is_ascii_alphanumeric() || b[i-1] == b'_')) {
+            let hashes = src[i + 1..].bytes().take_while(|c| *c == b'#').count();
+            if b.get(i + 1 + hashes) == Some(&b'"') {
+                i = skip_to(i + 2 + hashes, &format!("\"{}", "#".repeat(hashes)));
+                continue;
+            }
+        }
+        if src[i..].starts_with("@{{") {
+            i = skip_to(i + 3, "}}@");
+        } else if src[i..].starts_with("@{{") {
+            i = skip_to(i + 3, "}}@");
+        } else if b[i] == b'"' || b[i] == b'\'' {
+            let q = b[i];
+            i += 1;
+            while i < b.len() && b[i] != q {
+                if b[i] == b'\\' {
+                    i += 1;
+                }
+                i += 1;
+            }
+            i += 1;
+        } else if src[i..].starts_with("//") {
+            let end = src[i..].find('\n').map(|p| i + p).unwrap_or(b.len());
+            r.push((i, src[i..end].trim_end().len() + i));
+            i = end;
+        } else if src[i..].starts_with("/*") {
+            let end = skip_to(i + 2, "*/");
+            r.push((i, end));
+            i = end;
+        } else {
+            i += 1;
+        }
+    }
+    r
+}
 
 pub(crate) fn parse_importname(decl: pest::iterators::Pair<Rule>) -> (Name, Vec<(String, Option<String>)>) {
     let mut locals = Vec::new();
@@ -1552,7 +2670,7 @@ pub(crate) fn parse_importname(decl: pest::iterators::Pair<Rule>) -> (Name, Vec<
     for part in decl.into_inner() {
         match part.as_rule() {
             Rule::cimport => {
-                v = vec![String::new(), "ext".into(), part.as_str().into()];
+                v = vec!["".into(), "ext".into(), part.as_str().into()];
             }
             Rule::ident => {
                 v.push(part.as_str().into());
@@ -1594,18 +2712,12 @@ pub(crate) fn parse_importname(decl: pest::iterators::Pair<Rule>) -> (Name, Vec<
     (Name(v), locals)
 }
 
-fn parse_call(n: (&'static str, &Path), expr: pest::iterators::Pair<'static, Rule>) -> Expression {
-    let loc = Location{
-        file: n.1.to_string_lossy().into(),
-        span: expr.as_span(),
-    };
+fn parse_call(file: FileId, expr: pest::iterators::Pair<Rule>) -> Expression {
+    let loc = Location::new(file, expr.as_span());
     let expr = expr.into_inner();
     //let name = expr.next().unwrap();
-    //let nameloc = Location{
-    //    file: n.1.to_string_lossy().into(),
-    //    span: name.as_span(),
-    //};
-    //let name = Box::new(parse_expr(n, name));
+    //let nameloc = Location::new(file, name.as_span());
+    //let name = Box::new(parse_expr(file, name));
 
 
     let mut args = Vec::new();
@@ -1615,7 +2727,7 @@ fn parse_call(n: (&'static str, &Path), expr: pest::iterators::Pair<'static, Rul
         match part.as_rule() {
             Rule::call_args => {
                 args = part.into_inner().into_iter().map(|arg|{
-                    Box::new(parse_expr(n, arg))
+                    Box::new(parse_expr(file, arg))
                 }).collect();
             },
             e => panic!("unexpected rule {:?} in function call", e),
@@ -1623,10 +2735,7 @@ fn parse_call(n: (&'static str, &Path), expr: pest::iterators::Pair<'static, Rul
     };
 
     Expression::Call{
-        name : Box::new(Expression::Literal{
-            v: "#error ICE this was supposed to be removed by pre climber pass".to_string(),
-            loc: loc.clone()
-        }),
+        name : Box::new(Expression::literal(loc.clone(), "#error ICE this was supposed to be removed by pre climber pass".to_string())),
         loc: loc,
         args,
         expanded:       false,
@@ -1634,11 +2743,13 @@ fn parse_call(n: (&'static str, &Path), expr: pest::iterators::Pair<'static, Rul
     }
 }
 
-use serde::{Serialize};
+use serde::{Serialize, Deserialize};
 
-#[derive(Serialize, Default)]
+#[derive(Serialize, Deserialize, Default)]
 pub struct JsonError {
     pub message:        String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code:           Option<String>,
     pub level:          String,
     pub file_name:      String,
     pub line_start:     usize,
@@ -1652,45 +2763,52 @@ pub fn emit_error<'a, S1, S2, I>(message: S1, v: I)
           S2: std::string::ToString + 'a,
           I:  std::iter::IntoIterator<Item=&'a (Location, S2)>,
 {
+    let message = message.to_string();
     if ERRORS_AS_JSON.load(Ordering::SeqCst) {
         let mut j = JsonError::default();
-        j.message   = message.to_string();
+        j.code      = messages::code_of(&message).map(String::from);
+        j.message   = message;
         j.level     = "error".to_string();
         j.file_name = "<anon>".to_string();
 
 
         let mut first  = true;
         for (loc, message) in v.into_iter() {
-            j.file_name     = loc.file.clone();
-            j.line_start    = loc.span.start_pos().line_col().0;
-            j.column_start  = loc.span.start_pos().line_col().1;
-            j.line_end      = loc.span.end_pos().line_col().0;
-            j.column_end    = loc.span.end_pos().line_col().1;
+            j.file_name     = loc.file.to_string();
+            j.line_start    = loc.line_col().0;
+            j.column_start  = loc.line_col().1;
+            j.line_end      = loc.end_line_col().0;
+            j.column_end    = loc.end_line_col().1;
 
 
             if first {
-                println!("{}", serde_json::to_string(&j).unwrap());
+                report(Diagnostic::Json(serde_json::to_string(&j).unwrap()));
                 first = false;
+                j.code = None;
             }
 
             j.level     = "W".to_string();
             j.message   = message.to_string();
-            println!("{}", serde_json::to_string(&j).unwrap());
+            report(Diagnostic::Json(serde_json::to_string(&j).unwrap()));
+
+            for expansion in loc.expansions() {
+                let call = &expansion.call;
+                j.file_name     = call.file.to_string();
+                j.line_start    = call.line_col().0;
+                j.column_start  = call.line_col().1;
+                j.line_end      = call.end_line_col().0;
+                j.column_end    = call.end_line_col().1;
+                j.message       = msg!("expanded_from_macro", name = expansion.name);
+                report(Diagnostic::Json(serde_json::to_string(&j).unwrap()));
+            }
         }
 
 
         return;
     }
 
-
-    let mut s : String = message.to_string();
-    for (loc, message)  in v.into_iter() {
-        let e = pest::error::Error::<Rule>::new_from_span(pest::error::ErrorVariant::CustomError {
-            message: message.to_string(),
-        }, loc.span.clone()).with_path(&loc.file);
-        s += &format!("\n{}\n", e);
-    }
-    error!("{}", s);
+    let labels : Vec<(&Location, String)> = v.into_iter().map(|(loc, m)| (loc, m.to_string())).collect();
+    report(Diagnostic::Text(diagnostics::render(diagnostics::Level::Error, &message, &labels)));
 }
 
 pub fn emit_warn<'a, S1, S2, I>(message: S1, v: I)
@@ -1698,32 +2816,28 @@ pub fn emit_warn<'a, S1, S2, I>(message: S1, v: I)
           S2: std::string::ToString + 'a,
           I:  std::iter::IntoIterator<Item=&'a (Location, S2)>,
 {
+    let message = message.to_string();
     if ERRORS_AS_JSON.load(Ordering::SeqCst) {
         let mut j = JsonError::default();
-        j.message   = message.to_string();
+        j.code      = messages::code_of(&message).map(String::from);
+        j.message   = message;
         j.level     = "warn".to_string();
         j.file_name = "<anon>".to_string();
 
         if let Some((loc,_)) = v.into_iter().next() {
-            j.file_name     = loc.file.clone();
-            j.line_start    = loc.span.start_pos().line_col().0;
-            j.column_start  = loc.span.start_pos().line_col().1;
-            j.line_end      = loc.span.end_pos().line_col().0;
-            j.column_end    = loc.span.end_pos().line_col().1;
+            j.file_name     = loc.file.to_string();
+            j.line_start    = loc.line_col().0;
+            j.column_start  = loc.line_col().1;
+            j.line_end      = loc.end_line_col().0;
+            j.column_end    = loc.end_line_col().1;
         }
 
-        println!("{}", serde_json::to_string(&j).unwrap());
+        report(Diagnostic::Json(serde_json::to_string(&j).unwrap()));
         return;
     }
 
-    let mut s : String = message.to_string();
-    for (loc, message)  in v.into_iter() {
-        let e = pest::error::Error::<Rule>::new_from_span(pest::error::ErrorVariant::CustomError {
-            message: message.to_string(),
-        }, loc.span.clone()).with_path(&loc.file);
-        s += &format!("\n{}", e);
-    }
-    warn!("{}", s);
+    let labels : Vec<(&Location, String)> = v.into_iter().map(|(loc, m)| (loc, m.to_string())).collect();
+    report(Diagnostic::Warning(diagnostics::render(diagnostics::Level::Warning, &message, &labels)));
 }
 
 pub fn emit_debug<'a, S1, S2, I>(message: S1, v: I)
@@ -1735,70 +2849,131 @@ pub fn emit_debug<'a, S1, S2, I>(message: S1, v: I)
         return;
     }
 
+    let labels : Vec<(&Location, String)> = v.into_iter().map(|(loc, m)| (loc, m.to_string())).collect();
+    report(Diagnostic::Log(log::Level::Debug, diagnostics::render(diagnostics::Level::Debug, &message.to_string(), &labels)));
+}
+
+/// the text of a string literal in an attribute like @deprecated
+fn attribute_string(file: FileId, part: pest::iterators::Pair<Rule>) -> String {
+    let loc = Location::new(file, part.as_span());
+    let v = match raw_string(part.as_str()) {
+        Some(raw) => raw.as_bytes().to_vec(),
+        None => unescape(&loc),
+    };
+    String::from_utf8_lossy(&v).to_string()
+}
 
-    let mut s : String = message.to_string();
-    for (loc, message)  in v.into_iter() {
-        let e = pest::error::Error::<Rule>::new_from_span(pest::error::ErrorVariant::CustomError {
-            message: message.to_string(),
-        }, loc.span.clone()).with_path(&loc.file);
-        s += &format!("\n{}", e);
+/// the text between the delimiters of a raw string literal like r#"text"#, if it is one
+pub fn raw_string(s: &str) -> Option<&str> {
+    if !s.starts_with('r') {
+        return None;
     }
-    debug!("{}", s);
+    let hashes = s[1..].chars().take_while(|c| *c == '#').count();
+    Some(&s[hashes + 2 .. s.len() - hashes - 1])
 }
 
-fn unescape(s: &str, loc: &Location) -> Vec<u8> {
+/// the bytes of the quoted string or char literal at loc in input.
+/// invalid escapes are reported at the escape itself, not at the c code they would end up in
+fn unescape(loc: &Location) -> Vec<u8> {
+    let s       = loc.text();
+    let s       = &s[1..s.len() - 1];
+    let offset  = loc.start + 1;
+
     let mut result = Vec::with_capacity(s.len());
-    let mut chars = s.chars();
-    while let Some(ch) = chars.next() {
-        result.push(
-            if ch != '\\' {
-                ch as u8
-            } else {
-                match chars.next() {
-                    Some('x') => {
-                        let value = chars.by_ref().take(2).fold(0, |acc, c| acc * 16 + c.to_digit(16).unwrap());
-                        if value > 255 {
-                            emit_error("octal value too big for char", &[
-                                (loc.clone(), "in this literal string")
-                            ]);
-                            std::process::exit(9);
+    let mut bad = false;
+    let mut chars = s.char_indices().peekable();
+    while let Some((at, ch)) = chars.next() {
+        if ch != '\\' {
+            let mut buf = [0; 4];
+            result.extend_from_slice(ch.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        let (_, esc) = match chars.next() {
+            Some(v) => v,
+            None => break,
+        };
+        match esc {
+            'x' => {
+                let mut value = 0;
+                let mut digits = 0;
+                while let Some((_, c)) = chars.peek() {
+                    match c.to_digit(16) {
+                        Some(d) if digits < 2 => {
+                            value = value * 16 + d;
+                            digits += 1;
+                            chars.next();
                         }
-                        value as u8
+                        _ => break,
                     }
-                    Some('?') => 0x3f,
-                    Some('\\')=> '\\' as u8,
-                    Some('a') => 0x07,
-                    Some('b') => 0x08,
-                    Some('f') => 0x0c,
-                    Some('n') => '\n' as u8,
-                    Some('r') => '\r' as u8,
-                    Some('t') => '\t' as u8,
-                    Some('v') => 0x0b,
-                    Some('"') => '"' as u8,
-                    Some('\'') => '\'' as u8,
-                    _ => {
-                        emit_error("unsupported escape character", &[
-                            (loc.clone(), "in this literal string")
-                        ]);
-                        std::process::exit(9);
+                }
+                if digits == 0 {
+                    emit_error(msg!("literal_hex_escape"), &[
+                        (escape_loc(loc, offset + at, 2), msg!("literal_escape.here")),
+                    ]);
+                    bad = true;
+                }
+                result.push(value as u8);
+            }
+            '0'..='7' => {
+                let mut value = esc.to_digit(8).unwrap();
+                let mut len = 2;
+                while let Some((_, c)) = chars.peek() {
+                    match c.to_digit(8) {
+                        Some(d) if len < 4 => {
+                            value = value * 8 + d;
+                            len += 1;
+                            chars.next();
+                        }
+                        _ => break,
+                    }
+                }
+                if value > 255 {
+                    emit_error(msg!("literal_octal_escape", escape = &s[at..at + len]), &[
+                        (escape_loc(loc, offset + at, len), msg!("literal_escape.here")),
+                    ]);
+                    bad = true;
+                }
+                result.push(value as u8);
+            }
+            // a backslash at the end of a line continues the string on the next, without the line break and indentation
+            '\n' | '\r' => {
+                if esc == '\r' {
+                    if let Some((_, '\n')) = chars.peek() {
+                        chars.next();
                     }
                 }
+                while let Some((_, ' ')) | Some((_, '\t')) = chars.peek() {
+                    chars.next();
+                }
+            }
+            '?' => result.push(0x3f),
+            '\\'=> result.push(b'\\'),
+            'a' => result.push(0x07),
+            'b' => result.push(0x08),
+            'f' => result.push(0x0c),
+            'n' => result.push(b'\n'),
+            'r' => result.push(b'\r'),
+            't' => result.push(b'\t'),
+            'v' => result.push(0x0b),
+            '"' => result.push(b'"'),
+            '\'' => result.push(b'\''),
+            _ => {
+                emit_error(msg!("literal_unknown_escape", escape = format!("\\{}", esc)), &[
+                    (escape_loc(loc, offset + at, 1 + esc.len_utf8()), msg!("literal_unknown_escape.note")),
+                ]);
+                bad = true;
             }
-        )
+        }
+    }
+    if bad {
+        abort();
     }
     result
 }
 
+fn escape_loc(loc: &Location, start: usize, len: usize) -> Location {
+    Location::at(loc.file, start, (start + len).min(loc.end))
+}
 
 
-pub fn parse_u64(s: &str) -> Option<u64> {
-    if s.len() > 2 && s.chars().nth(0) == Some('0') && s.chars().nth(1) == Some('x') {
-        return u64::from_str_radix(&s[2..], 16).ok();
-    }
-
-    if let Ok(v) = s.parse::<u64>() {
-        return Some(v)
-    }
 
-    None
-}
@@ -0,0 +1,289 @@
+/// the pitfalls lint: c habits that compile, but rarely mean what they say
+
+use super::ast;
+use super::sourcemap;
+use super::parser::{emit_error, emit_warn};
+use super::project::LintLevel;
+use std::collections::HashSet;
+
+struct Finding {
+    loc:        ast::Location,
+    message:    String,
+    note:       String,
+}
+
+struct Lint {
+    // names of bool variables and arguments in the current function
+    bools:      HashSet<String>,
+    findings:   Vec<Finding>,
+}
+
+fn op_str(op: &ast::InfixOperator) -> &'static str {
+    match op {
+        ast::InfixOperator::Bitand  => "&",
+        ast::InfixOperator::Bitor   => "|",
+        ast::InfixOperator::Bitxor  => "^",
+        ast::InfixOperator::Booland => "&&",
+        ast::InfixOperator::Boolor  => "||",
+        ast::InfixOperator::Equals  => "==",
+        ast::InfixOperator::Nequals => "!=",
+        ast::InfixOperator::Lessthan    => "<",
+        ast::InfixOperator::Morethan    => ">",
+        ast::InfixOperator::Lesseq      => "<=",
+        ast::InfixOperator::Moreeq      => ">=",
+        _ => "",
+    }
+}
+
+fn bitwise(op: &ast::InfixOperator) -> bool {
+    match op {
+        ast::InfixOperator::Bitand | ast::InfixOperator::Bitor | ast::InfixOperator::Bitxor => true,
+        _ => false,
+    }
+}
+
+fn comparison(op: &ast::InfixOperator) -> bool {
+    match op {
+        ast::InfixOperator::Equals
+        | ast::InfixOperator::Nequals
+        | ast::InfixOperator::Lessthan
+        | ast::InfixOperator::Morethan
+        | ast::InfixOperator::Lesseq
+        | ast::InfixOperator::Moreeq => true,
+        _ => false,
+    }
+}
+
+fn is_bool(typed: &ast::Typed) -> bool {
+    typed.t == ast::Type::Bool && typed.ptr.is_empty()
+}
+
+/// where the source text of expr starts
+fn start(expr: &ast::Expression) -> &ast::Location {
+    match expr {
+        ast::Expression::Infix{lhs, ..}
+        | ast::Expression::MemberAccess{lhs, ..}
+        | ast::Expression::ArrayAccess{lhs, ..} => start(lhs),
+        ast::Expression::Call{name, ..} => start(name),
+        _ => expr.loc(),
+    }
+}
+
+/// whether expr is written inside parentheses.
+/// the ast doesn't keep them, so this looks at the source before it.
+/// at the start of a line this can't tell, and says yes so nothing is flagged
+fn parenthesized(expr: &ast::Expression) -> bool {
+    let loc = start(expr);
+    let (line, col) = loc.line_col();
+    let before : String = sourcemap::line(loc.file, line).chars().take(col.saturating_sub(1)).collect();
+    match before.trim_end().chars().last() {
+        Some(c) => c == '(',
+        None => true,
+    }
+}
+
+impl Lint {
+    /// whether expr is obviously a bool, without running the type checker
+    fn boolean(&self, expr: &ast::Expression) -> bool {
+        match expr {
+            ast::Expression::Infix{op, ..} => comparison(op) || !op.takes_integer(),
+            ast::Expression::UnaryPre{op: ast::PrefixOperator::Boolnot, ..} => true,
+            ast::Expression::Literal{v, ..} => v == "true" || v == "false",
+            ast::Expression::Name(ast::Typed{t: ast::Type::Other(name), ..}) => {
+                !name.is_absolute() && name.len() == 1 && self.bools.contains(name.0[0].as_str())
+            }
+            _ => false,
+        }
+    }
+
+    fn infix(&mut self, op: &ast::InfixOperator, lhs: &ast::Expression, rhs: &ast::Expression, loc: &ast::Location) {
+        if loc.expansion.is_some() {
+            return;
+        }
+        if bitwise(op) {
+            for side in &[lhs, rhs] {
+                if let ast::Expression::Infix{op: inner, ..} = side {
+                    if comparison(inner) && !parenthesized(side) {
+                        self.findings.push(Finding{
+                            loc:        loc.clone(),
+                            message:    msg!("pitfall_precedence", inner = op_str(inner), outer = op_str(op)),
+                            note:       msg!("pitfall_precedence.note", inner = op_str(inner), outer = op_str(op)),
+                        });
+                        return;
+                    }
+                }
+            }
+            if self.boolean(lhs) || self.boolean(rhs) {
+                let instead = match op {
+                    ast::InfixOperator::Bitand  => "&&",
+                    ast::InfixOperator::Bitor   => "||",
+                    _                           => "!=",
+                };
+                self.findings.push(Finding{
+                    loc:        loc.clone(),
+                    message:    msg!("pitfall_bitwise_bool", op = op_str(op)),
+                    note:       msg!("pitfall_bitwise_bool.note", instead = instead),
+                });
+            }
+        } else if *op == ast::InfixOperator::Boolor {
+            for side in &[lhs, rhs] {
+                if let ast::Expression::Infix{op: ast::InfixOperator::Booland, ..} = side {
+                    if !parenthesized(side) {
+                        self.findings.push(Finding{
+                            loc:        loc.clone(),
+                            message:    msg!("pitfall_mixed_bool"),
+                            note:       msg!("pitfall_mixed_bool.note"),
+                        });
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    fn block(&mut self, block: &ast::Block) {
+        for stm in &block.statements {
+            self.statement(stm);
+        }
+    }
+
+    fn statement(&mut self, stm: &ast::Statement) {
+        match stm {
+            ast::Statement::Mark{lhs, ..} => self.expr(lhs),
+            ast::Statement::Label{..}
+            | ast::Statement::Break{..}
+            | ast::Statement::Continue{..}
+            | ast::Statement::CBlock{..} => {}
+            ast::Statement::Block(b) | ast::Statement::Unsafe(b) => self.block(b),
+            ast::Statement::For{e1, e2, e3, body} => {
+                for s in e1.iter().chain(e3.iter()) {
+                    self.statement(s);
+                }
+                if let Some(e2) = e2 {
+                    self.expr(e2);
+                }
+                self.block(body);
+            }
+            ast::Statement::While{expr, body} => {
+                self.expr(expr);
+                self.block(body);
+            }
+            ast::Statement::If{branches} => {
+                for (_, e, body) in branches {
+                    if let Some(e) = e {
+                        self.expr(e);
+                    }
+                    self.block(body);
+                }
+            }
+            ast::Statement::Assign{lhs, rhs, ..} => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            ast::Statement::Var{name, typed, array, assign, ..} => {
+                if is_bool(typed) && array.is_none() {
+                    self.bools.insert(name.clone());
+                } else {
+                    self.bools.remove(name);
+                }
+                if let Some(Some(size)) = array {
+                    self.expr(size);
+                }
+                if let Some(assign) = assign {
+                    self.expr(assign);
+                }
+            }
+            ast::Statement::Expr{expr, ..} => self.expr(expr),
+            ast::Statement::Return{expr, ..} => {
+                if let Some(expr) = expr {
+                    self.expr(expr);
+                }
+            }
+            ast::Statement::Switch{expr, cases, default, ..} => {
+                self.expr(expr);
+                for (conds, body) in cases {
+                    for cond in conds {
+                        self.expr(cond);
+                    }
+                    self.block(body);
+                }
+                if let Some(default) = default {
+                    self.block(default);
+                }
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &ast::Expression) {
+        match expr {
+            ast::Expression::Name(_)
+            | ast::Expression::LiteralString{..}
+            | ast::Expression::LiteralChar{..}
+            | ast::Expression::Literal{..} => {}
+            ast::Expression::MemberAccess{lhs, ..} => self.expr(lhs),
+            ast::Expression::ArrayAccess{lhs, rhs, ..} => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            ast::Expression::Infix{lhs, rhs, op, loc} => {
+                self.infix(op, lhs, rhs, loc);
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            ast::Expression::Call{name, args, ..} => {
+                self.expr(name);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            ast::Expression::Cast{expr, ..}
+            | ast::Expression::UnaryPost{expr, ..}
+            | ast::Expression::UnaryPre{expr, ..} => self.expr(expr),
+            ast::Expression::StructInit{fields, ..} => {
+                for (_, e) in fields {
+                    self.expr(e);
+                }
+            }
+            ast::Expression::ArrayInit{fields, ..} => {
+                for e in fields {
+                    self.expr(e);
+                }
+            }
+        }
+    }
+}
+
+/// run the lint on all function bodies of md. returns false if it found anything and level is deny
+pub fn check(md: &ast::Module, level: LintLevel) -> bool {
+    if level == LintLevel::Allow {
+        return true;
+    }
+    let mut lint = Lint {
+        bools:      HashSet::new(),
+        findings:   Vec::new(),
+    };
+    for local in &md.locals {
+        lint.bools.clear();
+        match &local.def {
+            ast::Def::Function{args, body, ..} => {
+                for arg in args {
+                    if is_bool(&arg.typed) {
+                        lint.bools.insert(arg.name.clone());
+                    }
+                }
+                lint.block(body);
+            }
+            ast::Def::Test{body, ..} => lint.block(body),
+            _ => (),
+        }
+    }
+
+    for finding in &lint.findings {
+        if level == LintLevel::Deny {
+            emit_error(&finding.message, &[(finding.loc.clone(), &finding.note)]);
+        } else {
+            emit_warn(&finding.message, &[(finding.loc.clone(), &finding.note)]);
+        }
+    }
+    level != LintLevel::Deny || lint.findings.is_empty()
+}
@@ -0,0 +1,79 @@
+/// commands from zz.toml that run over every emitted c file before the c compiler sees it
+
+use super::emitter;
+use super::make;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::Command;
+
+#[derive(Serialize)]
+pub struct Manifest<'a> {
+    /// like foo::bar
+    pub module:     String,
+    /// the project the module is from, which may be a dependency
+    pub project:    &'a str,
+    pub file:       &'a str,
+    /// the header the module was emitted with
+    pub header:     String,
+    /// the zz sources it was emitted from
+    pub sources:    Vec<&'a Path>,
+    /// modules it includes
+    pub deps:       Vec<String>,
+    /// symbols visible in the shared library
+    pub exports:    &'a [String],
+    pub stage:      String,
+    pub variant:    &'a str,
+}
+
+pub fn run(
+    commands:   &BTreeMap<String, Vec<String>>,
+    project:    &super::project::Project,
+    root:       &Path,
+    variant:    &str,
+    stage:      &make::Stage,
+    cf:         &emitter::CFile,
+) -> Result<(), super::Error> {
+    if commands.is_empty() {
+        return Ok(());
+    }
+
+    let mut sources : Vec<&Path> = cf.sources.iter().map(|p| p.as_path()).collect();
+    sources.sort();
+    let mut deps : Vec<String> = cf.deps.iter().map(|d| d.human_name()).collect();
+    deps.sort();
+    let manifest = Manifest {
+        module:     cf.name.human_name(),
+        project:    cf.name.0.get(1).map(|s| s.as_str()).unwrap_or(""),
+        file:       &cf.filepath,
+        header:     emitter::outname(project, stage, &cf.name, true).1,
+        sources,
+        deps,
+        exports:    &cf.exports,
+        stage:      stage.to_string(),
+        variant,
+    };
+    let path = format!("{}.postprocess.json", cf.filepath);
+    std::fs::write(&path, serde_json::to_string_pretty(&manifest).unwrap())
+        .expect(&format!("cannot write {}", path));
+
+    for (name, command) in commands {
+        let failed = |error: String| super::Error::new(
+            msg!("postprocess_failed", name = name, file = cf.filepath, error = error), Vec::new());
+        if command.is_empty() {
+            return Err(failed(msg!("postprocess_empty")));
+        }
+        let out = Command::new(&command[0])
+            .args(&command[1..])
+            .arg(&cf.filepath)
+            .arg(&path)
+            .current_dir(root)
+            .output()
+            .map_err(|e| failed(format!("{}: {}", command[0], e)))?;
+        if !out.status.success() {
+            return Err(failed(format!("{} exited with {}\n{}{}", command[0], out.status,
+                String::from_utf8_lossy(&out.stdout), String::from_utf8_lossy(&out.stderr))));
+        }
+    }
+    Ok(())
+}
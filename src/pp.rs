@@ -1,14 +1,14 @@
 use pest;
-use super::parser::{Rule, emit_warn, emit_error};
+use super::parser::{self, Rule, emit_warn, emit_error};
 use super::ast;
+use super::sourcemap::FileId;
 use super::name::Name;
-use std::path::{Path, PathBuf};
 use std::collections::HashMap;
 use crate::make::Stage;
 
-pub struct PP {
-    decl:       pest::iterators::Pairs<'static, Rule>,
-    n:          PathBuf,
+pub struct PP<'a> {
+    decl:       pest::iterators::Pairs<'a, Rule>,
+    file:       FileId,
     stack:      Vec<bool>,
     features:   HashMap<String,bool>,
     stage:      Stage,
@@ -21,24 +21,46 @@ pub enum Value {
 }
 
 
-impl PP {
-    pub fn new(n: &Path, features: HashMap<String,bool>, stage: Stage, decl: pest::iterators::Pairs<'static, Rule>) -> PP {
+impl<'a> PP<'a> {
+    pub fn new(file: FileId, features: HashMap<String,bool>, stage: Stage, decl: pest::iterators::Pairs<'a, Rule>) -> Self {
         PP {
             features,
             stage,
             decl,
-            n: n.into(),
+            file,
             stack: Vec::new(),
         }
     }
 
-    pub fn eval(&self, termish: pest::iterators::Pair<'static, Rule>)  -> Value {
-        let loc = ast::Location{
-            file: self.n.to_string_lossy().into(),
-            span: termish.as_span(),
-        };
-        let expr = termish.into_inner().next().unwrap();
+    pub fn eval(&self, termish: pest::iterators::Pair<'a, Rule>)  -> Value {
+        self.value(termish.into_inner().next().unwrap())
+    }
+
+    fn value(&self, expr: pest::iterators::Pair<'a, Rule>)  -> Value {
+        let loc = ast::Location::new(self.file, expr.as_span());
         match expr.as_rule() {
+            // a || b and a && b. the right side is not evaluated if the left decides, so it doesn't warn either
+            Rule::pp_expr | Rule::pp_and => {
+                let or = expr.as_rule() == Rule::pp_expr;
+                let mut parts = expr.into_inner();
+                let mut v = self.value(parts.next().unwrap());
+                for part in parts {
+                    let b = self.boolean(&loc, v);
+                    if b == or {
+                        return Value::Bool(b);
+                    }
+                    v = self.value(part);
+                }
+                v
+            },
+            Rule::pp_not => {
+                let v = self.value(expr.into_inner().next().unwrap());
+                Value::Bool(!self.boolean(&loc, v))
+            },
+            // bare names, as in def(TARGET_LINUX)
+            Rule::type_name => {
+                Value::String(expr.as_str().to_string())
+            },
             Rule::number_literal => {
                 if expr.as_str() == "0" {
                     Value::Bool(false)
@@ -47,10 +69,11 @@ impl PP {
                 }
             },
             Rule::string_literal => {
-                let mut s = expr.as_str().to_string();
-                s.remove(0);
-                s.remove(s.len() -1);
-                Value::String(s)
+                let s = expr.as_str();
+                match super::parser::raw_string(s) {
+                    Some(raw)   => Value::String(raw.to_string()),
+                    None        => Value::String(s[1..s.len()-1].to_string()),
+                }
 
             },
             Rule::pp_call => {
@@ -70,26 +93,26 @@ impl PP {
                 match name.0.join("::").as_str() {
                     "feature" => {
                         if args.len() != 1 {
-                            emit_error("wrong number of arguments to feature. expected 1", &[
-                                   (loc, "called here"),
+                            emit_error(msg!("pp_argument_count", name = "feature", expected = 1), &[
+                                   (loc, msg!("called_here")),
                             ]);
-                            std::process::exit(9);
+                            parser::abort();
                         }
 
                         let s = match &args[0] {
                             Value::String(s) => s,
                             _ => {
-                                emit_error("argument to feature must be a string", &[
-                                           (loc, "called here"),
+                                emit_error(msg!("pp_feature_not_string"), &[
+                                           (loc, msg!("called_here")),
                                 ]);
-                                std::process::exit(9);
+                                parser::abort();
                             },
                         };
 
                         match self.features.get(s) {
                             None => {
-                                emit_warn("undefined feature defaults to false", &[
-                                          (loc, "avoid this warning by defining it explicitly in zz.toml"),
+                                emit_warn(msg!("pp_undefined_feature"), &[
+                                          (loc, msg!("pp_undefined_feature.note")),
                                 ]);
                                 Value::Bool(false)
                             },
@@ -99,17 +122,23 @@ impl PP {
                     },
                     "def" => {
                         if args.len() != 1 {
-                            emit_error("wrong number of arguments to def. expected 1", &[
-                                   (loc, "called here"),
+                            emit_error(msg!("pp_argument_count", name = "def", expected = 1), &[
+                                   (loc, msg!("called_here")),
                             ]);
-                            std::process::exit(9);
+                            parser::abort();
                         }
 
                         match &args[0] {
                             Value::String(s) if s == "debug" => Value::Bool(true),
+                            Value::String(s) if self.stage.defines.iter().any(|d| d.split('=').next() == Some(s.as_str())) => {
+                                Value::Bool(true)
+                            },
+                            Value::String(s) if self.features.contains_key(s) => {
+                                Value::Bool(self.features[s])
+                            },
                             _ => {
-                                emit_warn("undefined def defaults to false", &[
-                                       (loc, "avoid this warning by defining it explicitly"),
+                                emit_warn(msg!("pp_undefined_def"), &[
+                                       (loc, msg!("pp_undefined_def.note")),
                                 ]);
                                 Value::Bool(false)
                             }
@@ -117,10 +146,10 @@ impl PP {
                     },
                     "target" => {
                         if args.len() != 2 {
-                            emit_error("wrong number of arguments to def. expected 2", &[
-                                   (loc, "called here"),
+                            emit_error(msg!("pp_argument_count", name = "target", expected = 2), &[
+                                   (loc, msg!("called_here")),
                             ]);
-                            std::process::exit(9);
+                            parser::abort();
                         }
 
                         match &args[0] {
@@ -130,8 +159,8 @@ impl PP {
                                         Value::Bool(s == &self.stage.asan)
                                     }
                                     _ => {
-                                        emit_warn("invalid attribute value defaults to false", &[
-                                                  (loc, "target attribute needs to be a string"),
+                                        emit_warn(msg!("pp_invalid_target_value"), &[
+                                                  (loc, msg!("pp_target_not_string")),
                                         ]);
                                         Value::Bool(false)
                                     }
@@ -143,77 +172,89 @@ impl PP {
                                         Value::Bool(s == &self.stage.debug)
                                     }
                                     _ => {
-                                        emit_warn("invalid attribute value defaults to false", &[
-                                                  (loc, "target attribute needs to be a string"),
+                                        emit_warn(msg!("pp_invalid_target_value"), &[
+                                                  (loc, msg!("pp_target_not_string")),
+                                        ]);
+                                        Value::Bool(false)
+                                    }
+                                }
+                            },
+                            Value::String(s) if s == "wasm32" => {
+                                match &args[1] {
+                                    Value::Bool(s)  => {
+                                        Value::Bool(s == &self.stage.wasm)
+                                    }
+                                    _ => {
+                                        emit_warn(msg!("pp_invalid_target_value"), &[
+                                                  (loc, msg!("pp_target_not_string")),
                                         ]);
                                         Value::Bool(false)
                                     }
                                 }
                             },
                             Value::String(s)  => {
-                                emit_warn("undefined target attribute defaults to false", &[
-                                          (loc, format!("{} is not a known target attribute", s)),
+                                emit_warn(msg!("pp_undefined_target"), &[
+                                          (loc, msg!("pp_undefined_target.note", name = s)),
                                 ]);
                                 Value::Bool(false)
                             },
                             _ => {
-                                emit_warn("invalid target attribute defaults to false", &[
-                                          (loc, "target attribute needs to be a string"),
+                                emit_warn(msg!("pp_invalid_target"), &[
+                                          (loc, msg!("pp_target_not_string")),
                                 ]);
                                 Value::Bool(false)
                             }
                         }
                     },
                     n => {
-                        emit_error(format!("function '{}' not available in preprocessor directive",n),  &[
-                               (loc, "used here"),
+                        emit_error(msg!("pp_unknown_function", name = n),  &[
+                               (loc, msg!("used_here")),
                         ]);
-                        std::process::exit(9);
+                        parser::abort();
                     }
                 }
 
 
             },
             _ => {
-                emit_error(format!("{:?} expression cannot (yet) be used in preprocessor directive", expr.as_rule()), &[
-                       (loc, "used here"),
+                emit_error(msg!("pp_unsupported_expression", name = format!("{:?}", expr.as_rule())), &[
+                       (loc, msg!("used_here")),
                 ]);
-                std::process::exit(9);
+                parser::abort();
             }
         }
     }
 
-    fn push(&mut self, loc: ast::Location, v: Value) {
+    fn boolean(&self, loc: &ast::Location, v: Value) -> bool {
         match v {
-            Value::Bool(false) => {
-                self.stack.push(false);
-            }
-            Value::Bool(true) => {
-                self.stack.push(true);
-            },
+            Value::Bool(b) => b,
             _ => {
-                emit_error("preprocessor directive must evaluate to boolean", &[
-                       (loc, format!("this expression = '{:?}'", v)),
+                emit_error(msg!("pp_not_boolean"), &[
+                       (loc.clone(), msg!("pp_not_boolean.note", value = format!("{:?}", v))),
                 ]);
-                std::process::exit(9);
+                parser::abort();
             }
-
         }
     }
 
+    fn push(&mut self, loc: ast::Location, v: Value) {
+        let v = self.boolean(&loc, v);
+        self.stack.push(v);
+    }
+
     fn pop(&mut self, loc: &ast::Location) -> bool {
         if self.stack.len() < 1 {
-            emit_error("missing preceeding #if directive", &[
-                (loc.clone(), "here"),
+            emit_error(msg!("pp_missing_if"), &[
+                (loc.clone(), msg!("here")),
             ]);
-            std::process::exit(9);
+            parser::abort();
         }
         self.stack.pop().unwrap()
     }
 }
 
-impl Iterator for PP {
-    type Item = pest::iterators::Pair<'static, Rule>;
+impl<'a> Iterator for PP<'a> {
+    type Item = pest::iterators::Pair<'a, Rule>;
     fn next(&mut self) -> Option<Self::Item> {
         let decl = match self.decl.next() {
             None => return None,
@@ -222,10 +263,7 @@ impl Iterator for PP {
 
         if let Rule::pp = decl.as_rule() {
             let decl = decl.into_inner().next().unwrap();
-            let loc = ast::Location{
-                file: self.n.to_string_lossy().into(),
-                span: decl.as_span(),
-            };
+            let loc = ast::Location::new(self.file, decl.as_span());
             match decl.as_rule() {
                 Rule::ppelif  => {
                     let previous = self.pop(&loc);
@@ -233,20 +271,14 @@ impl Iterator for PP {
                         self.push(loc, Value::Bool(previous));
                     } else {
                         let expr = decl.into_inner().next().unwrap();
-                        let loc = ast::Location{
-                            file: self.n.to_string_lossy().into(),
-                            span: expr.as_span(),
-                        };
-                        self.push(loc, self.eval(expr));
+                        let loc = ast::Location::new(self.file, expr.as_span());
+                        self.push(loc, self.value(expr));
                     }
                 },
                 Rule::ppif => {
                     let expr = decl.into_inner().next().unwrap();
-                    let loc = ast::Location{
-                        file: self.n.to_string_lossy().into(),
-                        span: expr.as_span(),
-                    };
-                    self.push(loc, self.eval(expr));
+                    let loc = ast::Location::new(self.file, expr.as_span());
+                    self.push(loc, self.value(expr));
                 },
                 Rule::ppelse => {
                     let invert = Value::Bool(!self.pop(&loc));
@@ -260,7 +292,8 @@ impl Iterator for PP {
             return self.next();
         }
 
-        if let Some(false) = self.stack.last() {
+        // inside a nested #if, every enclosing directive must hold
+        if self.stack.iter().any(|v| !v) {
             return self.next();
         } else {
             return Some(decl);
@@ -2,8 +2,10 @@ use serde::{Serialize, Deserialize};
 use std::fs::File;
 use std::io::{Read, Write};
 use std::path::PathBuf;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use toml::Value;
+use super::edition::Edition;
+use super::schema;
 
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -29,6 +31,8 @@ pub struct Artifact {
     #[serde(rename = "type")]
     pub typ:        ArtifactType,
     pub indexjs:    Option<String>,
+    // version node of all exported symbols in a shared library, like FOO_1.0
+    pub symbol_version: Option<String>,
 }
 
 
@@ -39,6 +43,7 @@ impl Default for Artifact {
             main:       String::new(),
             typ:        ArtifactType::Lib,
             indexjs:    None,
+            symbol_version: None,
         }
     }
 }
@@ -53,6 +58,8 @@ pub struct Feature {
     #[serde(default)]
     pub pkgconfig:  Vec<String>,
     #[serde(default)]
+    pub libs:       Vec<String>,
+    #[serde(default)]
     pub cflags:     Vec<String>,
     #[serde(default)]
     pub lflags:     Vec<String>,
@@ -63,17 +70,205 @@ pub struct Project {
     pub version:    String,
     pub name:       String,
     pub std:        Option<String>,
-    
+    // syntax and checks for the modules of this project. see edition.rs
     #[serde(default)]
+    pub edition:    Edition,
+    // #line markers in the emitted c, so compiler errors and debuggers show the zz source. on by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub line_directives: Option<bool>,
+    // the layout of zz.toml, 1 if not given. see schema.rs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema:     Option<i64>,
+    // put in front of the c symbol of every export fn instead of its module path. see symbols.rs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symbol_prefix: Option<String>,
+    // export headers that c++ can include. see cxxheader.rs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cxx_headers: Option<bool>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub cincludes:  Vec<String>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub cobjects:   Vec<String>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub pkgconfig:  Vec<String>,
+    // system libraries, linked as -lname after all objects
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub libs:       Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cflags:     Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lflags:     Vec<String>,
+    // left out of zz package, like lines of .gitignore. see package.rs
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude:    Vec<String>,
+}
+
+impl Project {
+    pub fn line_directives(&self) -> bool {
+        self.line_directives.unwrap_or(true)
+    }
+
+    pub fn cxx_headers(&self) -> bool {
+        self.cxx_headers.unwrap_or(false)
+    }
+
+    /// whether the c standard has stdbool.h. before c99 bool is emitted as int
+    pub fn stdbool(&self) -> bool {
+        match self.std.as_ref().map(|s| s.as_str()) {
+            Some("c89") | Some("c90") | Some("ansi") | Some("gnu89") | Some("gnu90") | Some("iso9899:1990") => false,
+            _ => true,
+        }
+    }
+}
+
+/// a cross compilation profile, selected with zz build --target name
+///
+///   [target.armv7-unknown-linux]
+///   cc      = "arm-linux-gnueabihf-gcc"
+///   sysroot = "/opt/sysroots/armv7"
+///   cflags  = ["-march=armv7-a"]
+///   defines = ["TARGET_LINUX", "BOARD=3"]
+///
+///   [target.armv7-unknown-linux.lints]
+///   vla = "deny"
+///
+///   [target.armv7-unknown-linux.interfaces]
+///   log_backend = "log_uart"
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Target {
+    pub cc:         Option<String>,
+    pub cxx:        Option<String>,
+    pub ar:         Option<String>,
+    pub sysroot:    Option<String>,
     #[serde(default)]
     pub cflags:     Vec<String>,
     #[serde(default)]
     pub lflags:     Vec<String>,
+    // passed to the C compiler as -D, and visible to def() in #if
+    #[serde(default)]
+    pub defines:    Vec<String>,
+    // made stricter for this target, like forbidding vla on small stacks
+    #[serde(default, skip_serializing_if = "Lints::is_empty")]
+    pub lints:      Lints,
+    // backends of interface modules for this target, over those of [interfaces]
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub interfaces: BTreeMap<String, String>,
+}
+
+// ordered from least to most strict
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, PartialOrd)]
+pub enum LintLevel {
+    #[serde(rename = "allow")]
+    Allow,
+    #[serde(rename = "warn")]
+    Warn,
+    #[serde(rename = "deny")]
+    Deny,
+}
+
+impl Default for LintLevel {
+    fn default() -> Self {
+        LintLevel::Allow
+    }
+}
+
+/// checks that are off unless asked for, on the modules of the project itself
+///
+///   [lints]
+///   provenance = "warn"
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Lints {
+    // casts between pointers and integers, arithmetic on void*, comparing unrelated pointers. see provenance.rs
+    #[serde(default)]
+    pub provenance: LintLevel,
+    // variable length arrays and alloca. see vla.rs
+    #[serde(default)]
+    pub vla:        LintLevel,
+    // bitwise operators on bools and misleading precedence. see pitfalls.rs
+    #[serde(default)]
+    pub pitfalls:   LintLevel,
+    // struct initializers that leave out fields. see struct_init.rs
+    #[serde(default)]
+    pub struct_init: LintLevel,
+    // imports, arguments and locals that are never read. see unused.rs
+    #[serde(default)]
+    pub unused:     LintLevel,
+}
+
+impl Lints {
+    pub fn is_empty(&self) -> bool {
+        self.provenance == LintLevel::Allow
+            && self.vla == LintLevel::Allow
+            && self.pitfalls == LintLevel::Allow
+            && self.struct_init == LintLevel::Allow
+            && self.unused == LintLevel::Allow
+    }
+
+    /// the level of a lint by its name in warnings::LINTS
+    pub fn get(&self, name: &str) -> LintLevel {
+        match name {
+            "provenance"    => self.provenance,
+            "vla"           => self.vla,
+            "pitfalls"      => self.pitfalls,
+            "struct_init"   => self.struct_init,
+            "unused"        => self.unused,
+            _ => panic!("ICE: unknown lint {}", name),
+        }
+    }
+
+    pub fn set(&mut self, name: &str, level: LintLevel) {
+        match name {
+            "provenance"    => self.provenance = level,
+            "vla"           => self.vla = level,
+            "pitfalls"      => self.pitfalls = level,
+            "struct_init"   => self.struct_init = level,
+            "unused"        => self.unused = level,
+            _ => panic!("ICE: unknown lint {}", name),
+        }
+    }
+
+    /// each lint at the stricter level of self and other
+    pub fn stricter(&self, other: &Lints) -> Lints {
+        let max = |a: LintLevel, b: LintLevel| if b > a { b } else { a };
+        Lints {
+            provenance: max(self.provenance, other.provenance),
+            vla:        max(self.vla, other.vla),
+            pitfalls:   max(self.pitfalls, other.pitfalls),
+            struct_init: max(self.struct_init, other.struct_init),
+            unused:     max(self.unused, other.unused),
+        }
+    }
+}
+
+/// language features the modules of a directory may not use, see analyze.rs
+///
+///   [policy."src/core"]
+///   deny = ["unsafe", "inline_c"]
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Policy {
+    #[serde(default)]
+    pub deny:       Vec<String>,
+}
+
+/// catalogs of user facing strings, see i18n.rs
+///
+///   [i18n]
+///   default = "en"
+///   dir     = "i18n"
+#[derive(Clone, Serialize, Deserialize)]
+pub struct I18n {
+    // the language the others are checked against, and that fills in the strings they miss
+    pub default:    String,
+    // the directory of the <lang>.toml catalogs, relative to zz.toml
+    #[serde(default = "I18n::default_dir")]
+    pub dir:        String,
+}
+
+impl I18n {
+    fn default_dir() -> String {
+        "i18n".to_string()
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -88,25 +283,75 @@ pub struct Config {
     pub artifacts:      Option<Vec<Artifact>>,
 
     pub features:       Option<HashMap<String, Feature>>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub variants:       HashMap<String, Vec<String>>,
 
     pub dependencies:   Option<HashMap<String, Value>>,
+
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub target:         HashMap<String, Target>,
+
+    #[serde(default, skip_serializing_if = "Lints::is_empty")]
+    pub lints:          Lints,
+
+    // unstable features of dependencies this project opts in to, see stability.rs
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub unstable:       HashMap<String, Vec<String>>,
+
+    // by directory relative to zz.toml
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub policy:         BTreeMap<String, Policy>,
+
+    // code generation plugins for @derive, by name. see derive.rs
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub derive:         HashMap<String, Vec<String>>,
+    // the module that takes the place of each interface module, see interface.rs
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub interfaces:     BTreeMap<String, String>,
+    // files and directories embedded into the generated resources module, see resources.rs
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub resources:      BTreeMap<String, String>,
+    // string catalogs that become the generated i18n module
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub i18n:           Option<I18n>,
+    // commands run over every emitted c file before it is compiled, by name. see postprocess.rs
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub postprocess:    BTreeMap<String, Vec<String>>,
 }
 
-pub fn init() {
-    let mut c  = Config {
+/// the zz.toml of a new project
+pub fn new_config(name: String) -> Config {
+    Config {
         artifacts: None,
         project: Project {
-            name: std::env::current_dir().unwrap().file_name().unwrap().to_string_lossy().into(),
+            name,
             version: "0.1.0".to_string(),
+            edition: Edition::LATEST,
+            schema:  Some(schema::CURRENT),
             ..Default::default()
         },
-        dependencies:   Some(HashMap::new()),
+        dependencies:   None,
         features:       None,
         variants:       HashMap::new(),
-    };
-    c.variants.insert("default".to_string(), Vec::new());
+        target:         HashMap::new(),
+        unstable:       HashMap::new(),
+        policy:         BTreeMap::new(),
+        derive:         HashMap::new(),
+        interfaces:     BTreeMap::new(),
+        resources:      BTreeMap::new(),
+        i18n:           None,
+        postprocess:    BTreeMap::new(),
+        lints:          Lints {
+            pitfalls:       LintLevel::Warn,
+            struct_init:    LintLevel::Warn,
+            unused:         LintLevel::Warn,
+            ..Default::default()
+        },
+    }
+}
+
+pub fn init() {
+    let c = new_config(std::env::current_dir().unwrap().file_name().unwrap().to_string_lossy().into());
 
     if !std::env::current_dir().unwrap().join("zz.toml").exists() {
         let s = toml::to_string(&c).unwrap();
@@ -138,31 +383,86 @@ export fn main() -> int {{
     println!("project '{}' created", c.project.name);
 }
 
-pub fn load_cwd() -> (PathBuf, Config) {
-    let mut search = std::env::current_dir().unwrap();
-    loop {
-        if !search.join("zz.toml").exists() {
-            search = match search.parent() {
-                Some(v) => v.into(),
-                None => {
-                    error!("error: could not find \"zz.toml\" in {:?} or any parent directory",
-                              std::env::current_dir().unwrap());
-                    std::process::exit(9);
-                }
+/// set the edition in zz.toml at root, keeping everything else as written
+pub fn set_edition(root: &std::path::Path, edition: Edition) {
+    let path = root.join("zz.toml");
+    let s = std::fs::read_to_string(&path).expect(&format!("cannot read {:?}", path));
+    let s = set_project_key(&s, "edition", &format!("\"{}\"", edition));
+    let mut f = File::create(&path).expect(&format!("cannot write {:?}", path));
+    f.write_all(s.as_bytes()).expect(&format!("cannot write {:?}", path));
+}
+
+/// the text of a zz.toml with key in [project] set to value, which is written as is
+pub fn set_project_key(s: &str, key: &str, value: &str) -> String {
+    let line = format!("{} = {}", key, value);
+
+    let mut out     = Vec::new();
+    let mut section = String::new();
+    let mut done    = false;
+    for l in s.lines() {
+        let t = l.trim();
+        if t.starts_with('[') {
+            if section == "[project]" && !done {
+                // after the last key, not after the blank lines before the next section
+                let at = out.iter().rposition(|l: &String| !l.trim().is_empty()).map(|i| i + 1).unwrap_or(0);
+                out.insert(at, line.clone());
+                done = true;
             }
-        } else {
-            break
+            section = t.to_string();
+        } else if section == "[project]" && t.starts_with(key) && t[key.len()..].trim_start().starts_with('=') {
+            out.push(line.clone());
+            done = true;
+            continue;
+        }
+        out.push(l.to_string());
+    }
+    if !done {
+        if section != "[project]" {
+            out.push("[project]".to_string());
+        }
+        out.push(line);
+    }
+    out.join("\n") + "\n"
+}
+
+pub fn load_cwd() -> (PathBuf, Config) {
+    load(&root_cwd())
+}
+
+/// the directory of the zz.toml in the current directory or the closest parent
+pub fn root_cwd() -> PathBuf {
+    match find_root_cwd() {
+        Some(root) => root,
+        None => {
+            error!("error: could not find \"zz.toml\" in {:?} or any parent directory",
+                      std::env::current_dir().unwrap());
+            std::process::exit(9);
         }
     }
-    load(&search)
+}
+
+/// like root_cwd, but none outside of a project
+pub fn find_root_cwd() -> Option<PathBuf> {
+    let mut search = std::env::current_dir().unwrap();
+    while !search.join("zz.toml").exists() {
+        search = search.parent()?.into();
+    }
+    Some(search)
 }
 
 pub fn load(search: &std::path::Path) -> (PathBuf, Config) {
 
-    let mut f = File::open(&search.join("zz.toml")).expect(&format!("cannot open {:?}", search));
+    let path = search.join("zz.toml");
+    let mut f = File::open(&path).expect(&format!("cannot open {:?}", search));
     let mut s = String::new();
     f.read_to_string(&mut s).expect(&format!("cannot read {:?}", search));
-    let mut c : Config = toml::from_str(&mut s).expect(&format!("cannot read {:?}", search));
+    let mut c : Config = match schema::check(&path, s).try_into() {
+        Ok(c) => c,
+        Err(e) => {
+            error!("{}", msg!("config_invalid", path = path.display(), error = e));
+            super::parser::abort();
+        }
+    };
 
 
     // implicit features
@@ -198,6 +498,12 @@ pub fn load(search: &std::path::Path) -> (PathBuf, Config) {
                 typ:  ArtifactType::Lib,
                 ..Default::default()
             });
+            a.push(Artifact{
+                name: c.project.name.clone(),
+                main: format!("{}", c.project.name),
+                typ:  ArtifactType::Staticlib,
+                ..Default::default()
+            });
         }
         c.artifacts = Some(a);
     }
@@ -0,0 +1,220 @@
+/// try, returning early when a call failed
+
+use super::ast;
+use super::coroutine;
+use super::name::Name;
+use super::parser::emit_error;
+
+/// try e is parsed as a call to this
+pub const TRY : &str = "try";
+
+/// the call after try, if expr is a try
+pub fn tried(expr: &ast::Expression) -> Option<&ast::Expression> {
+    coroutine::marker(expr, &[TRY]).map(|(_, e)| e)
+}
+
+struct Ctx {
+    func:   String,
+    /// the err argument and the module its type is from
+    err:    Option<(String, Name)>,
+    /// what to return on failure, none if the return type has nothing that means failed
+    fail:   Option<Option<ast::Expression>>,
+    ok:     bool,
+    /// modules err::check was called from
+    used:   Vec<Name>,
+}
+
+fn name_expr(n: Name, loc: &ast::Location) -> ast::Expression {
+    ast::Expression::Name(ast::Typed{
+        t:      ast::Type::Other(n),
+        loc:    loc.clone(),
+        ptr:    Vec::new(),
+        tail:   ast::Tail::None,
+    })
+}
+
+/// the module of the Err type of an err::Err+ mut* argument, as it is named in this module
+fn err_module(md: &ast::Module, arg: &ast::NamedArg) -> Option<Name> {
+    let n = match &arg.typed.t {
+        ast::Type::Other(n) if !arg.typed.ptr.is_empty() && n.0.last().map(|s| s == "Err").unwrap_or(false) => n,
+        _ => return None,
+    };
+    if n.0.len() > 1 {
+        let mut m = n.clone();
+        m.pop();
+        return Some(m);
+    }
+    // using err::{Err}, err::check is named through the module
+    md.imports.iter().find(|i| i.local.iter().any(|(l, alias)| l == "Err" && alias.is_none())).map(|i| i.name.clone())
+}
+
+fn fail_value(ret: &Option<ast::AnonArg>, loc: &ast::Location) -> Option<Option<ast::Expression>> {
+    let ret = match ret {
+        None => return Some(None),
+        Some(ret) => ret,
+    };
+    if !ret.typed.ptr.is_empty() {
+        return None;
+    }
+    // this runs before abs, so builtin types are still names
+    let builtin = match &ret.typed.t {
+        ast::Type::Other(n) if n.0.len() == 1 => n.0[0].as_str(),
+        _ => return None,
+    };
+    match builtin {
+        "bool" => Some(Some(ast::Expression::literal(loc.clone(), "false".to_string()))),
+        "u8" | "u16" | "u32" | "u64" | "u128" | "i8" | "i16" | "i32" | "i64" | "i128"
+        | "int" | "uint" | "isize" | "usize" => {
+            Some(Some(ast::Expression::literal(loc.clone(), "0".to_string())))
+        }
+        _ => None,
+    }
+}
+
+fn passes(call: &ast::Expression, err: &str) -> bool {
+    match call {
+        ast::Expression::Call{args, ..} => args.iter().any(|a| matches!(a.as_ref(),
+            ast::Expression::Name(ast::Typed{t: ast::Type::Other(n), ..}) if n.0.len() == 1 && n.0[0] == err)),
+        _ => false,
+    }
+}
+
+fn lower_statement(stm: Box<ast::Statement>, ctx: &mut Ctx, into: &mut Vec<Box<ast::Statement>>) {
+    let (loc, call) = match *stm {
+        ast::Statement::Expr{loc, expr} => match tried(&expr).cloned() {
+            Some(call) => (loc, call),
+            None => {
+                into.push(Box::new(ast::Statement::Expr{loc, expr}));
+                return;
+            }
+        },
+        mut other => {
+            match &mut other {
+                ast::Statement::While{body, ..} | ast::Statement::For{body, ..} => lower_block(body, ctx),
+                ast::Statement::If{branches} => {
+                    for (_, _, body) in branches {
+                        lower_block(body, ctx);
+                    }
+                }
+                ast::Statement::Switch{cases, default, ..} => {
+                    for (_, body) in cases.iter_mut() {
+                        lower_block(body, ctx);
+                    }
+                    if let Some(body) = default {
+                        lower_block(body, ctx);
+                    }
+                }
+                ast::Statement::Block(body) | ast::Statement::Unsafe(body) => lower_block(body, ctx),
+                _ => (),
+            }
+            into.push(Box::new(other));
+            return;
+        }
+    };
+
+    let (err, module) = match &ctx.err {
+        Some(v) => v.clone(),
+        None => {
+            emit_error(msg!("try_without_err", name = ctx.func), &[
+                (loc.clone(), msg!("try_without_err.note")),
+            ]);
+            ctx.ok = false;
+            return;
+        }
+    };
+    let fail = match &ctx.fail {
+        Some(v) => v.clone(),
+        None => {
+            emit_error(msg!("try_return_type", name = ctx.func), &[
+                (loc.clone(), msg!("try_return_type.note")),
+            ]);
+            ctx.ok = false;
+            return;
+        }
+    };
+    if !matches!(call, ast::Expression::Call{..}) || !passes(&call, &err) {
+        emit_error(msg!("try_err_not_passed", err = err), &[
+            (call.loc().clone(), msg!("try_err_not_passed.note", err = err)),
+        ]);
+        ctx.ok = false;
+        return;
+    }
+
+    if !ctx.used.contains(&module) {
+        ctx.used.push(module.clone());
+    }
+    let mut check = module;
+    check.push("check".to_string());
+    let failed = ast::Expression::Call{
+        loc:        loc.clone(),
+        name:       Box::new(name_expr(check, &loc)),
+        args:       vec![Box::new(name_expr(Name::from(&err), &loc))],
+        expanded:   false,
+        emit:       ast::EmitBehaviour::Default,
+    };
+    into.push(Box::new(ast::Statement::Expr{loc: loc.clone(), expr: call}));
+    into.push(Box::new(ast::Statement::If{
+        branches: vec![(loc.clone(), Some(failed), ast::Block{
+            end:        loc.clone(),
+            statements: vec![Box::new(ast::Statement::Return{loc: loc.clone(), expr: fail})],
+            expanded:   false,
+        })],
+    }));
+}
+
+fn lower_block(block: &mut ast::Block, ctx: &mut Ctx) {
+    let mut statements = Vec::new();
+    for stm in std::mem::take(&mut block.statements) {
+        lower_statement(stm, ctx, &mut statements);
+    }
+    block.statements = statements;
+}
+
+pub fn lower(md: &mut ast::Module) {
+    let mut ok = true;
+    let mut used = Vec::new();
+    let mut locals = std::mem::take(&mut md.locals);
+    for local in &mut locals {
+        let mut ctx = match &local.def {
+            ast::Def::Function{args, ret, ..} => Ctx {
+                func:   local.name.clone(),
+                err:    args.iter().find_map(|a| err_module(md, a).map(|m| (a.name.clone(), m))),
+                fail:   fail_value(ret, &local.loc),
+                ok:     true,
+                used:   Vec::new(),
+            },
+            ast::Def::Test{..} => Ctx {
+                func:   local.name.clone(),
+                err:    None,
+                fail:   Some(None),
+                ok:     true,
+                used:   Vec::new(),
+            },
+            _ => continue,
+        };
+        match &mut local.def {
+            ast::Def::Function{body, ..} | ast::Def::Test{body, ..} => lower_block(body, &mut ctx),
+            _ => (),
+        }
+        ok &= ctx.ok;
+        used.extend(ctx.used);
+    }
+    md.locals = locals;
+
+    // with only using err::{Err}, err::check needs the module itself imported
+    for module in used {
+        if md.imports.iter().any(|i| i.name == module && i.local.is_empty() && i.alias.is_none()) {
+            continue;
+        }
+        let import = md.imports.iter().find(|i| i.name == module).cloned();
+        if let Some(mut import) = import {
+            import.local = Vec::new();
+            import.alias = None;
+            import.vis   = ast::Visibility::Object;
+            md.imports.push(import);
+        }
+    }
+    if !ok {
+        super::parser::abort();
+    }
+}
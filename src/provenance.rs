@@ -0,0 +1,403 @@
+/// the provenance lint: code that won't survive targets where pointers are more than addresses
+
+use super::ast;
+use super::loader;
+use super::name::Name;
+use super::parser::{emit_error, emit_warn};
+use super::project::LintLevel;
+use std::collections::HashMap;
+
+pub const ALLOW_TAG : &str = "provenance";
+
+// c integer types from headers, besides the builtin ones
+const C_INTEGERS : &[&str] = &[
+    "char", "short", "int", "long", "unsigned",
+    "size_t", "ssize_t", "ptrdiff_t", "intptr_t", "uintptr_t",
+    "int8_t", "int16_t", "int32_t", "int64_t",
+    "uint8_t", "uint16_t", "uint32_t", "uint64_t",
+];
+
+fn integer(t: &ast::Typed) -> bool {
+    if !t.ptr.is_empty() {
+        return false;
+    }
+    match &t.t {
+        ast::Type::U8 | ast::Type::U16 | ast::Type::U32 | ast::Type::U64 | ast::Type::U128
+        | ast::Type::I8 | ast::Type::I16 | ast::Type::I32 | ast::Type::I64 | ast::Type::I128
+        | ast::Type::Int | ast::Type::UInt | ast::Type::ISize | ast::Type::USize
+        | ast::Type::ULiteral | ast::Type::ILiteral => true,
+        ast::Type::Other(n) => n.0.len() == 4 && n.0[1] == "ext" && C_INTEGERS.contains(&n.0[3].as_str()),
+        _ => false,
+    }
+}
+
+fn void_pointer(t: &ast::Typed) -> bool {
+    t.ptr.len() == 1 && match &t.t {
+        ast::Type::Other(n) => n.0.len() == 4 && n.0[1] == "ext" && n.0[3] == "void",
+        _ => false,
+    }
+}
+
+fn integer_typed(loc: &ast::Location) -> ast::Typed {
+    ast::Typed{
+        t:      ast::Type::ULiteral,
+        loc:    loc.clone(),
+        ptr:    Vec::new(),
+        tail:   ast::Tail::None,
+    }
+}
+
+struct Finding {
+    loc:        ast::Location,
+    message:    String,
+    note:       String,
+}
+
+struct Lint<'a> {
+    modules:    &'a HashMap<Name, loader::Module>,
+    scopes:     Vec<HashMap<String, ast::Typed>>,
+    findings:   Vec<Finding>,
+}
+
+impl<'a> Lint<'a> {
+    /// the declaration of an absolute name in any module
+    fn local(&self, name: &Name) -> Option<&'a ast::Local> {
+        let mut module = name.clone();
+        let local = module.pop()?;
+        match self.modules.get(&module) {
+            Some(loader::Module::ZZ(md)) => md.locals.iter().find(|l| l.name == local),
+            _ => None,
+        }
+    }
+
+    fn declare(&mut self, name: &str, typed: ast::Typed) {
+        self.scopes.last_mut().unwrap().insert(name.to_string(), typed);
+    }
+
+    fn type_of(&self, expr: &ast::Expression) -> Option<ast::Typed> {
+        match expr {
+            ast::Expression::Name(ast::Typed{t: ast::Type::Other(name), ..}) => {
+                if !name.is_absolute() && name.len() == 1 {
+                    return self.scopes.iter().rev().find_map(|s| s.get(name.0[0].as_str())).cloned();
+                }
+                match &self.local(name)?.def {
+                    ast::Def::Static{typed, array, ..} => {
+                        let mut typed = typed.clone();
+                        if array.is_some() {
+                            typed.ptr.push(ast::Pointer{tags: ast::Tags::new(), loc: typed.loc.clone()});
+                        }
+                        Some(typed)
+                    }
+                    ast::Def::Const{typed, ..} => Some(typed.clone()),
+                    _ => None,
+                }
+            }
+            ast::Expression::Name(_) => None,
+            ast::Expression::Literal{loc, value, ..} => match value {
+                ast::LiteralValue::Integer{..} => Some(integer_typed(loc)),
+                _ => None,
+            },
+            ast::Expression::LiteralChar{loc, ..} => Some(integer_typed(loc)),
+            ast::Expression::LiteralString{..} => None,
+            ast::Expression::Cast{into, ..} => Some(into.clone()),
+            ast::Expression::StructInit{typed, ..} => Some(typed.clone()),
+            ast::Expression::ArrayInit{..} => None,
+            ast::Expression::MemberAccess{lhs, rhs, ..} => {
+                let name = match self.type_of(lhs)?.t {
+                    ast::Type::Other(name) if name.is_absolute() => name,
+                    _ => return None,
+                };
+                match &self.local(&name)?.def {
+                    ast::Def::Struct{fields, ..} => {
+                        let field = fields.iter().find(|f| &f.name == rhs)?;
+                        let mut typed = field.typed.clone();
+                        if field.array.is_some() {
+                            typed.ptr.push(ast::Pointer{tags: ast::Tags::new(), loc: field.loc.clone()});
+                        }
+                        Some(typed)
+                    }
+                    _ => None,
+                }
+            }
+            ast::Expression::ArrayAccess{lhs, ..} => {
+                let mut typed = self.type_of(lhs)?;
+                typed.ptr.pop()?;
+                Some(typed)
+            }
+            ast::Expression::Call{name, ..} => {
+                let name = match name.as_ref() {
+                    ast::Expression::Name(ast::Typed{t: ast::Type::Other(name), ..}) if name.is_absolute() => name,
+                    _ => return None,
+                };
+                match &self.local(name)?.def {
+                    ast::Def::Function{ret: Some(ret), ..} => Some(ret.typed.clone()),
+                    _ => None,
+                }
+            }
+            ast::Expression::Infix{lhs, rhs, op, loc} => {
+                if op.returns_boolean() {
+                    return None;
+                }
+                let lhs = self.type_of(lhs);
+                let rhs = self.type_of(rhs);
+                match (lhs, rhs) {
+                    // pointer difference
+                    (Some(l), Some(r)) if !l.ptr.is_empty() && !r.ptr.is_empty() => Some(integer_typed(loc)),
+                    (Some(l), _) if !l.ptr.is_empty() => Some(l),
+                    (_, Some(r)) if !r.ptr.is_empty() => Some(r),
+                    (Some(l), _) => Some(l),
+                    _ => None,
+                }
+            }
+            ast::Expression::UnaryPost{expr, ..} => self.type_of(expr),
+            ast::Expression::UnaryPre{expr, op, loc} => match op {
+                ast::PrefixOperator::Deref => {
+                    let mut typed = self.type_of(expr)?;
+                    typed.ptr.pop()?;
+                    Some(typed)
+                }
+                ast::PrefixOperator::AddressOf => {
+                    let mut typed = self.type_of(expr)?;
+                    typed.ptr.push(ast::Pointer{tags: ast::Tags::new(), loc: loc.clone()});
+                    Some(typed)
+                }
+                ast::PrefixOperator::Boolnot => None,
+                _ => self.type_of(expr),
+            },
+        }
+    }
+
+    fn void_arithmetic(&mut self, target: &ast::Expression, loc: &ast::Location) {
+        if let Some(t) = self.type_of(target) {
+            if void_pointer(&t) {
+                self.findings.push(Finding{
+                    loc:        loc.clone(),
+                    message:    msg!("provenance_void_arithmetic"),
+                    note:       msg!("provenance_void_arithmetic.note"),
+                });
+            }
+        }
+    }
+
+    fn block(&mut self, block: &ast::Block) {
+        self.scopes.push(HashMap::new());
+        for stm in &block.statements {
+            self.statement(stm);
+        }
+        self.scopes.pop();
+    }
+
+    fn statement(&mut self, stm: &ast::Statement) {
+        match stm {
+            ast::Statement::Mark{lhs, ..} => self.expr(lhs),
+            ast::Statement::Label{..}
+            | ast::Statement::Break{..}
+            | ast::Statement::Continue{..}
+            | ast::Statement::CBlock{..} => {}
+            ast::Statement::Block(b) | ast::Statement::Unsafe(b) => self.block(b),
+            ast::Statement::For{e1, e2, e3, body} => {
+                self.scopes.push(HashMap::new());
+                for s in e1 {
+                    self.statement(s);
+                }
+                if let Some(e2) = e2 {
+                    self.expr(e2);
+                }
+                for s in e3 {
+                    self.statement(s);
+                }
+                self.block(body);
+                self.scopes.pop();
+            }
+            ast::Statement::While{expr, body} => {
+                self.expr(expr);
+                self.block(body);
+            }
+            ast::Statement::If{branches} => {
+                for (_, expr, body) in branches {
+                    if let Some(expr) = expr {
+                        self.expr(expr);
+                    }
+                    self.block(body);
+                }
+            }
+            ast::Statement::Assign{lhs, rhs, op, loc} => {
+                self.expr(lhs);
+                self.expr(rhs);
+                if *op == ast::AssignOperator::Add || *op == ast::AssignOperator::Sub {
+                    self.void_arithmetic(lhs, loc);
+                }
+            }
+            ast::Statement::Var{typed, name, array, assign, ..} => {
+                if let Some(Some(array)) = array {
+                    self.expr(array);
+                }
+                if let Some(assign) = assign {
+                    self.expr(assign);
+                }
+                let mut typed = match (&typed.t, assign) {
+                    (ast::Type::Elided, Some(assign)) => match self.type_of(assign) {
+                        Some(t) => t,
+                        None => return,
+                    },
+                    (ast::Type::Elided, None) => return,
+                    _ => typed.clone(),
+                };
+                if array.is_some() {
+                    typed.ptr.push(ast::Pointer{tags: ast::Tags::new(), loc: typed.loc.clone()});
+                }
+                self.declare(name, typed);
+            }
+            ast::Statement::Expr{expr, ..} => self.expr(expr),
+            ast::Statement::Return{expr, ..} => {
+                if let Some(expr) = expr {
+                    self.expr(expr);
+                }
+            }
+            ast::Statement::Switch{expr, cases, default, ..} => {
+                self.expr(expr);
+                for (conds, block) in cases {
+                    for cond in conds {
+                        self.expr(cond);
+                    }
+                    self.block(block);
+                }
+                if let Some(default) = default {
+                    self.block(default);
+                }
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &ast::Expression) {
+        match expr {
+            ast::Expression::Name(_)
+            | ast::Expression::LiteralString{..}
+            | ast::Expression::LiteralChar{..}
+            | ast::Expression::Literal{..} => {}
+            ast::Expression::MemberAccess{lhs, ..} => self.expr(lhs),
+            ast::Expression::ArrayAccess{lhs, rhs, ..} => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            ast::Expression::Infix{lhs, rhs, op, loc} => {
+                self.expr(lhs);
+                self.expr(rhs);
+                match op {
+                    ast::InfixOperator::Add | ast::InfixOperator::Subtract => {
+                        self.void_arithmetic(lhs, loc);
+                        self.void_arithmetic(rhs, loc);
+                    }
+                    ast::InfixOperator::Equals
+                    | ast::InfixOperator::Nequals
+                    | ast::InfixOperator::Lessthan
+                    | ast::InfixOperator::Lesseq
+                    | ast::InfixOperator::Morethan
+                    | ast::InfixOperator::Moreeq => {
+                        if let (Some(l), Some(r)) = (self.type_of(lhs), self.type_of(rhs)) {
+                            let unrelated = !l.ptr.is_empty() && !r.ptr.is_empty()
+                                && !void_pointer(&l) && !void_pointer(&r)
+                                && (l.t != r.t || l.ptr.len() != r.ptr.len());
+                            if unrelated {
+                                self.findings.push(Finding{
+                                    loc:        loc.clone(),
+                                    message:    msg!("provenance_unrelated_compare", lhs = l, rhs = r),
+                                    note:       msg!("provenance_unrelated_compare.note"),
+                                });
+                            }
+                        }
+                    }
+                    _ => (),
+                }
+            }
+            ast::Expression::Call{name, args, ..} => {
+                self.expr(name);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            ast::Expression::Cast{expr: inner, into, tags, loc} => {
+                self.expr(inner);
+                let allowed = tags.contains_key(ALLOW_TAG) || into.ptr.iter().any(|p| p.tags.contains_key(ALLOW_TAG));
+                if allowed {
+                    return;
+                }
+                let from = match self.type_of(inner) {
+                    Some(v) => v,
+                    None => return,
+                };
+                if !from.ptr.is_empty() && integer(into) {
+                    self.findings.push(Finding{
+                        loc:        loc.clone(),
+                        message:    msg!("provenance_pointer_to_integer", from = from, into = into),
+                        note:       msg!("provenance_cast.note", into = into),
+                    });
+                } else if integer(&from) && !into.ptr.is_empty() {
+                    // (T*)0 is the null pointer, it never pointed anywhere
+                    if let ast::Expression::Literal{value: ast::LiteralValue::Integer{value: 0, ..}, ..} = inner.as_ref() {
+                        return;
+                    }
+                    self.findings.push(Finding{
+                        loc:        loc.clone(),
+                        message:    msg!("provenance_integer_to_pointer", from = from, into = into),
+                        note:       msg!("provenance_cast.note", into = into),
+                    });
+                }
+            }
+            ast::Expression::UnaryPost{expr, loc, ..} => {
+                self.expr(expr);
+                self.void_arithmetic(expr, loc);
+            }
+            ast::Expression::UnaryPre{expr, op, loc} => {
+                self.expr(expr);
+                if *op == ast::PrefixOperator::Increment || *op == ast::PrefixOperator::Decrement {
+                    self.void_arithmetic(expr, loc);
+                }
+            }
+            ast::Expression::StructInit{fields, ..} => {
+                for (_, expr) in fields {
+                    self.expr(expr);
+                }
+            }
+            ast::Expression::ArrayInit{fields, ..} => {
+                for expr in fields {
+                    self.expr(expr);
+                }
+            }
+        }
+    }
+}
+
+/// run the lint on all function bodies of md. returns false if it found anything and level is deny
+pub fn check(md: &ast::Module, modules: &HashMap<Name, loader::Module>, level: LintLevel) -> bool {
+    if level == LintLevel::Allow {
+        return true;
+    }
+    let mut lint = Lint {
+        modules,
+        scopes:     Vec::new(),
+        findings:   Vec::new(),
+    };
+    for local in &md.locals {
+        match &local.def {
+            ast::Def::Function{args, body, ..} => {
+                lint.scopes.push(args.iter().map(|a| (a.name.clone(), a.typed.clone())).collect());
+                lint.block(body);
+                lint.scopes.pop();
+            }
+            ast::Def::Test{body, ..} => {
+                lint.block(body);
+            }
+            _ => (),
+        }
+    }
+
+    for finding in &lint.findings {
+        if level == LintLevel::Deny {
+            emit_error(&finding.message, &[(finding.loc.clone(), &finding.note)]);
+        } else {
+            emit_warn(&finding.message, &[(finding.loc.clone(), &finding.note)]);
+        }
+    }
+    level != LintLevel::Deny || lint.findings.is_empty()
+}
@@ -0,0 +1,176 @@
+/// shrink an input while a predicate still holds, for bug reports
+
+use super::parser::{Rule, ZZParser};
+use pest::Parser;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+struct Reducer {
+    path:   PathBuf,
+    check:  String,
+    best:   String,
+    tests:  usize,
+}
+
+impl Reducer {
+    /// whether the check holds on src. leaves the best input so far in the file otherwise
+    fn test(&mut self, src: &str) -> bool {
+        self.tests += 1;
+        std::fs::write(&self.path, src).expect(&format!("cannot write {}", self.path.display()));
+        let ok = Command::new("sh").arg("-c").arg(&self.check)
+            .env("ZZ_REDUCE_FILE", &self.path)
+            .stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null())
+            .status()
+            .map(|s| s.success())
+            .unwrap_or(false);
+        if ok {
+            self.best = src.to_string();
+        } else {
+            std::fs::write(&self.path, &self.best).expect(&format!("cannot write {}", self.path.display()));
+        }
+        ok
+    }
+
+    /// ddmin over the spans of the best input. returns whether anything was removed
+    fn ddmin(&mut self, mut units: Vec<(usize, usize)>) -> bool {
+        let mut removed = false;
+        let mut chunks = 2;
+        while !units.is_empty() {
+            let size = (units.len() + chunks - 1) / chunks;
+            let mut progress = false;
+            let mut at = 0;
+            while at < units.len() {
+                let end = (at + size).min(units.len());
+                let candidate = without(&self.best, &units[at..end]);
+                if self.test(&candidate) {
+                    let cuts = merged(&units[at..end]);
+                    let first = units[at].0;
+                    units.drain(at..end);
+                    units = units.into_iter().filter_map(|(s, e)| shift(&cuts, s, e)).collect();
+                    at = units.iter().take_while(|u| u.0 < first).count();
+                    progress = true;
+                    removed = true;
+                } else {
+                    at = end;
+                }
+            }
+            if progress {
+                chunks = (chunks - 1).max(2);
+            } else if chunks >= units.len() {
+                break;
+            } else {
+                chunks = (chunks * 2).min(units.len());
+            }
+        }
+        removed
+    }
+}
+
+/// spans with the ones inside others left out
+fn merged(spans: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    let mut sorted = spans.to_vec();
+    sorted.sort();
+    let mut r : Vec<(usize, usize)> = Vec::new();
+    for (s, e) in sorted {
+        match r.last_mut() {
+            Some(last) if s < last.1 => last.1 = last.1.max(e),
+            _ => r.push((s, e)),
+        }
+    }
+    r
+}
+
+/// where a span is after cuts were removed before and inside it. none if it was removed with them
+fn shift(cuts: &[(usize, usize)], s: usize, e: usize) -> Option<(usize, usize)> {
+    if cuts.iter().any(|(cs, ce)| *cs <= s && e <= *ce) {
+        return None;
+    }
+    let before = |at: usize| -> usize { cuts.iter().filter(|(_, ce)| *ce <= at).map(|(cs, ce)| ce - cs).sum() };
+    Some((s - before(s), e - before(e)))
+}
+
+fn without(src: &str, spans: &[(usize, usize)]) -> String {
+    let mut out = String::new();
+    let mut at = 0;
+    for (s, e) in merged(spans) {
+        out.push_str(&src[at..s]);
+        at = e;
+    }
+    out.push_str(&src[at..]);
+    out
+}
+
+/// the top level declarations of src, or its lines if it doesn't parse
+fn declarations(src: &str) -> Vec<(usize, usize)> {
+    match ZZParser::parse(Rule::file, src) {
+        Ok(mut file) => file.next().unwrap().into_inner()
+            .filter(|p| p.as_rule() != Rule::EOI)
+            .map(|p| (p.as_span().start(), p.as_span().end()))
+            .collect(),
+        Err(_) => lines(src),
+    }
+}
+
+/// the statements of every block in src, outer ones first
+fn statements(src: &str) -> Vec<(usize, usize)> {
+    fn walk(pair: pest::iterators::Pair<Rule>, into: &mut Vec<(usize, usize)>) {
+        let block = pair.as_rule() == Rule::block;
+        for inner in pair.into_inner() {
+            if block {
+                into.push((inner.as_span().start(), inner.as_span().end()));
+            }
+            walk(inner, into);
+        }
+    }
+    let mut r = Vec::new();
+    if let Ok(file) = ZZParser::parse(Rule::file, src) {
+        for pair in file {
+            walk(pair, &mut r);
+        }
+    }
+    r
+}
+
+fn lines(src: &str) -> Vec<(usize, usize)> {
+    let mut r = Vec::new();
+    let mut at = 0;
+    for line in src.split_inclusive('\n') {
+        r.push((at, at + line.len()));
+        at += line.len();
+    }
+    r
+}
+
+pub fn main(path: &Path, check: &str) {
+    let src = match std::fs::read_to_string(path) {
+        Ok(src) => src,
+        Err(e) => {
+            error!("{}: {}", path.display(), e);
+            std::process::exit(9);
+        }
+    };
+    let orig = PathBuf::from(format!("{}.orig", path.display()));
+    std::fs::write(&orig, &src).expect(&format!("cannot write {}", orig.display()));
+
+    let mut r = Reducer {
+        path:   path.to_path_buf(),
+        check:  check.to_string(),
+        best:   src.clone(),
+        tests:  0,
+    };
+    if !r.test(&src) {
+        error!("{}", msg!("reduce_not_interesting", path = path.display(), check = check));
+        std::process::exit(9);
+    }
+
+    loop {
+        let a = r.ddmin(declarations(&r.best));
+        let b = r.ddmin(statements(&r.best));
+        info!("{}", msg!("reduce_progress", bytes = r.best.len(), tests = r.tests));
+        if !a && !b {
+            break;
+        }
+    }
+
+    info!("{}", msg!("reduce_done", path = path.display(), from = src.len(), to = r.best.len(), tests = r.tests, orig = orig.display()));
+}
@@ -0,0 +1,145 @@
+/// embedded resources
+
+use super::ast;
+use super::loader;
+use super::make::Stage;
+use super::name::Name;
+use super::parser;
+use super::project::Config;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+pub const MODULE : &str = "resources";
+
+/// the files under dir, relative to it and sorted
+pub fn list(dir: &Path) -> Vec<String> {
+    let mut r = Vec::new();
+    let mut need = vec![PathBuf::new()];
+    while let Some(rel) = need.pop() {
+        let entries = match std::fs::read_dir(dir.join(&rel)) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let rel = rel.join(entry.file_name());
+            if entry.path().is_dir() {
+                need.push(rel);
+            } else {
+                r.push(rel.to_string_lossy().replace('\\', "/"));
+            }
+        }
+    }
+    r.sort();
+    r
+}
+
+/// the files of [resources], by their name in the index
+fn files(root: &Path, resources: &BTreeMap<String, String>) -> Result<BTreeMap<String, PathBuf>, String> {
+    let mut r = BTreeMap::new();
+    for (key, path) in resources {
+        let full = root.join(path);
+        if full.is_dir() {
+            for rel in list(&full) {
+                r.insert(format!("{}/{}", key, rel), full.join(&rel));
+            }
+        } else if full.is_file() {
+            r.insert(key.clone(), full);
+        } else {
+            return Err(msg!("resource_missing", name = key, path = path));
+        }
+    }
+    Ok(r)
+}
+
+/// the name of the constant holding a file
+fn ident(name: &str) -> String {
+    let mut r : String = name.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+    if r.starts_with(|c: char| c.is_ascii_digit()) {
+        r.insert(0, '_');
+    }
+    r
+}
+
+/// the generated module, from the name, constant and content of every file
+fn source(files: &[(String, String, Vec<u8>)]) -> String {
+    let mut s = String::new();
+    s.push_str("/// generated from [resources] in zz.toml\n\n");
+    s.push_str("pub struct Resource {\n    char* name;\n    u8* data;\n    usize len;\n}\n\n");
+    for (_, id, bytes) in files {
+        write!(s, "pub const usize {}_len = {};\n", id, bytes.len()).unwrap();
+        write!(s, "const u8 {}[{}] = {{", id, bytes.len() + 1).unwrap();
+        for (i, b) in bytes.iter().chain(std::iter::once(&0)).enumerate() {
+            if i % 16 == 0 {
+                s.push_str("\n    ");
+            }
+            write!(s, "0x{:02x},", b).unwrap();
+        }
+        s.push_str("\n};\n\n");
+    }
+    write!(s, "pub const usize count = {};\n", files.len()).unwrap();
+    if !files.is_empty() {
+        write!(s, "\nconst Resource index[{}] = {{\n", files.len()).unwrap();
+        for (name, id, bytes) in files {
+            let name = name.replace('\\', "\\\\").replace('"', "\\\"");
+            write!(s, "    Resource{{name: \"{}\", data: {}, len: {}}},\n", name, id, bytes.len()).unwrap();
+        }
+        s.push_str("};\n");
+    }
+    s
+}
+
+/// generate and parse the resources module of the project, if zz.toml has [resources]
+pub fn load(
+    modules:        &mut HashMap<Name, loader::Module>,
+    project_name:   &Name,
+    root:           &Path,
+    config:         &Config,
+    features:       &HashMap<String, bool>,
+    stage:          &Stage,
+) {
+    if config.resources.is_empty() {
+        return;
+    }
+    let mut name = project_name.clone();
+    name.push(MODULE.to_string());
+    if modules.contains_key(&name) {
+        error!("{}", msg!("resource_module_taken", name = name));
+        parser::abort();
+    }
+
+    let files = match files(root, &config.resources) {
+        Ok(files) => files,
+        Err(e) => {
+            error!("{}", e);
+            parser::abort();
+        }
+    };
+    let mut generated : Vec<(String, String, Vec<u8>)> = Vec::new();
+    let mut idents : HashMap<String, String> = HashMap::new();
+    for (file, path) in &files {
+        let id = ident(file);
+        if let Some(other) = idents.insert(id.clone(), file.clone()) {
+            error!("{}", msg!("resource_clash", name = file, other = other, ident = id));
+            parser::abort();
+        }
+        let bytes = std::fs::read(path).expect(&format!("cannot read {}", path.display()));
+        generated.push((file.clone(), id, bytes));
+    }
+
+    let dir = root.join("target").join(stage.to_string()).join(MODULE);
+    std::fs::create_dir_all(&dir).expect(&format!("cannot create {}", dir.display()));
+    let path = dir.join(format!("{}.zz", MODULE));
+    std::fs::write(&path, source(&generated)).expect(&format!("cannot write {}", path.display()));
+
+    let mut md = parser::parse(&path, features, stage, config.project.edition);
+    md.name = name.clone();
+    // const arrays can't be pub in zz code. these are defined once in the module, and declared extern where they are used
+    for local in &mut md.locals {
+        if let ast::Def::Static{storage: ast::Storage::Const, ..} = local.def {
+            local.vis = ast::Visibility::Shared;
+        }
+    }
+    md.sources.extend(files.into_iter().map(|(_, path)| path));
+    modules.insert(name, loader::Module::ZZ(md));
+}
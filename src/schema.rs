@@ -0,0 +1,436 @@
+/// the layout of zz.toml, checked before it is read
+
+use super::ast;
+use super::parser::{self, emit_error};
+use super::sourcemap;
+use super::warnings;
+use std::path::Path;
+use toml::Value;
+
+/// the schema zz init writes and migrate-config upgrades to
+pub const CURRENT : i64 = 2;
+
+enum Kind {
+    Str,
+    Bool,
+    Int,
+    // one of these strings
+    Word(&'static [&'static str]),
+    List(&'static Kind),
+    // these keys, each with its own kind
+    Table(&'static [(&'static str, Kind)]),
+    // these keys, all of one kind
+    Keys(&'static [&'static str], &'static Kind),
+    // any key
+    Map(&'static Kind),
+    Either(&'static Kind, &'static Kind),
+}
+
+const STRINGS : Kind = Kind::List(&Kind::Str);
+const LINTS : Kind = Kind::Keys(warnings::LINTS, &Kind::Word(&["allow", "warn", "deny"]));
+
+const PROJECT : &[(&str, Kind)] = &[
+    ("version",         Kind::Str),
+    ("name",            Kind::Str),
+    ("std",             Kind::Str),
+    ("edition",         Kind::Word(&["2020", "2021"])),
+    ("line_directives", Kind::Bool),
+    ("schema",          Kind::Int),
+    ("symbol_prefix",   Kind::Str),
+    ("cxx_headers",     Kind::Bool),
+    ("cincludes",       STRINGS),
+    ("cobjects",        STRINGS),
+    ("pkgconfig",       STRINGS),
+    ("libs",            STRINGS),
+    ("cflags",          STRINGS),
+    ("lflags",          STRINGS),
+    ("exclude",         STRINGS),
+];
+
+const ARTIFACT : &[(&str, Kind)] = &[
+    ("name",            Kind::Str),
+    ("main",            Kind::Str),
+    ("type",            Kind::Word(&["lib", "staticlib", "exe", "test", "header", "npm"])),
+    ("indexjs",         Kind::Str),
+    ("symbol_version",  Kind::Str),
+];
+
+const FEATURE : &[(&str, Kind)] = &[
+    ("cincludes",       STRINGS),
+    ("cobjects",        STRINGS),
+    ("pkgconfig",       STRINGS),
+    ("libs",            STRINGS),
+    ("cflags",          STRINGS),
+    ("lflags",          STRINGS),
+];
+
+const DEPENDENCY : Kind = Kind::Either(&Kind::Str, &Kind::Table(&[
+    ("path",            Kind::Str),
+    ("git",             Kind::Str),
+    ("rev",             Kind::Str),
+]));
+
+const TARGET : &[(&str, Kind)] = &[
+    ("cc",              Kind::Str),
+    ("cxx",             Kind::Str),
+    ("ar",              Kind::Str),
+    ("sysroot",         Kind::Str),
+    ("cflags",          STRINGS),
+    ("lflags",          STRINGS),
+    ("defines",         STRINGS),
+    ("lints",           LINTS),
+    ("interfaces",      Kind::Map(&Kind::Str)),
+];
+
+const CONFIG : Kind = Kind::Table(&[
+    ("project",         Kind::Table(PROJECT)),
+    ("artifacts",       Kind::List(&Kind::Table(ARTIFACT))),
+    ("features",        Kind::Map(&Kind::Table(FEATURE))),
+    ("variants",        Kind::Map(&STRINGS)),
+    ("dependencies",    Kind::Map(&DEPENDENCY)),
+    ("target",          Kind::Map(&Kind::Table(TARGET))),
+    ("lints",           LINTS),
+    ("unstable",        Kind::Map(&STRINGS)),
+    ("policy",          Kind::Map(&Kind::Table(&[("deny", STRINGS)]))),
+    ("derive",          Kind::Map(&STRINGS)),
+    ("interfaces",      Kind::Map(&Kind::Str)),
+    ("resources",       Kind::Map(&Kind::Str)),
+    ("i18n",            Kind::Table(&[("default", Kind::Str), ("dir", Kind::Str)])),
+    ("postprocess",     Kind::Map(&STRINGS)),
+]);
+
+impl Kind {
+    /// whether v is of this kind, not looking inside
+    fn fits(&self, v: &Value) -> bool {
+        match (self, v) {
+            (Kind::Str, Value::String(_))
+            | (Kind::Word(_), Value::String(_))
+            | (Kind::Bool, Value::Boolean(_))
+            | (Kind::Int, Value::Integer(_))
+            | (Kind::List(_), Value::Array(_))
+            | (Kind::Table(_), Value::Table(_))
+            | (Kind::Keys(_, _), Value::Table(_))
+            | (Kind::Map(_), Value::Table(_)) => true,
+            (Kind::Either(a, b), v) => a.fits(v) || b.fits(v),
+            _ => false,
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Kind::Str | Kind::Word(_)   => "a string".to_string(),
+            Kind::Bool                  => "true or false".to_string(),
+            Kind::Int                   => "an integer".to_string(),
+            Kind::List(&Kind::Str)      => "a list of strings".to_string(),
+            Kind::List(_)               => "a list".to_string(),
+            Kind::Table(_) | Kind::Keys(_, _) | Kind::Map(_) => "a table".to_string(),
+            Kind::Either(a, b)          => format!("{} or {}", a.describe(), b.describe()),
+        }
+    }
+}
+
+/// something wrong at a key
+struct Problem {
+    path:       Vec<String>,
+    message:    String,
+    note:       String,
+}
+
+fn key(path: &[String]) -> String {
+    path.join(".")
+}
+
+fn child(path: &[String], k: &str) -> Vec<String> {
+    let mut path = path.to_vec();
+    path.push(k.to_string());
+    path
+}
+
+/// the number of single character edits from a to b
+fn distance(a: &str, b: &str) -> usize {
+    let b : Vec<char> = b.chars().collect();
+    let mut row : Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for j in 0..b.len() {
+            let here = row[j + 1];
+            row[j + 1] = if ca == b[j] { prev } else { 1 + prev.min(row[j]).min(here) };
+            prev = here;
+        }
+    }
+    row[b.len()]
+}
+
+fn unknown(path: &[String], k: &str, known: &[&str], out: &mut Vec<Problem>) {
+    let closest = known.iter().min_by_key(|n| distance(k, n)).filter(|n| distance(k, n) <= 2);
+    let note = match closest {
+        Some(n) => msg!("config_unknown_key.suggest", name = n),
+        None if path.is_empty() => msg!("config_unknown_key.known", known = known.join(", ")),
+        None => msg!("config_unknown_key.known_in", known = known.join(", "), table = key(path)),
+    };
+    out.push(Problem{
+        path:       child(path, k),
+        message:    msg!("config_unknown_key", key = key(&child(path, k))),
+        note,
+    });
+}
+
+fn walk(v: &Value, kind: &Kind, path: &[String], out: &mut Vec<Problem>) {
+    if !kind.fits(v) {
+        out.push(Problem{
+            path:       path.to_vec(),
+            message:    msg!("config_type", key = key(path), want = kind.describe(), found = v.type_str()),
+            note:       msg!("config_type.note", want = kind.describe()),
+        });
+        return;
+    }
+    match (kind, v) {
+        (Kind::Word(words), Value::String(s)) if !words.contains(&s.as_str()) => {
+            out.push(Problem{
+                path:       path.to_vec(),
+                message:    msg!("config_value", key = key(path), value = s),
+                note:       msg!("config_value.note", values = words.join(", ")),
+            });
+        }
+        (Kind::List(of), Value::Array(items)) => {
+            for (i, item) in items.iter().enumerate() {
+                walk(item, of, &child(path, &i.to_string()), out);
+            }
+        }
+        (Kind::Table(keys), Value::Table(t)) => {
+            for (k, v) in t {
+                match keys.iter().find(|(n, _)| *n == k.as_str()) {
+                    Some((_, kind)) => walk(v, kind, &child(path, k), out),
+                    None => unknown(path, k, &keys.iter().map(|(n, _)| *n).collect::<Vec<_>>(), out),
+                }
+            }
+        }
+        (Kind::Keys(keys, of), Value::Table(t)) => {
+            for (k, v) in t {
+                if keys.contains(&k.as_str()) {
+                    walk(v, of, &child(path, k), out);
+                } else {
+                    unknown(path, k, keys, out);
+                }
+            }
+        }
+        (Kind::Map(of), Value::Table(t)) => {
+            for (k, v) in t {
+                walk(v, of, &child(path, k), out);
+            }
+        }
+        (Kind::Either(a, b), v) => {
+            walk(v, if a.fits(v) { a } else { b }, path, out);
+        }
+        _ => (),
+    }
+}
+
+/// options that are fine alone but not together
+fn conflicts(config: &Value, out: &mut Vec<Problem>) {
+    let deps = config.get("dependencies").and_then(|d| d.as_table());
+    for (name, dep) in deps.into_iter().flatten() {
+        let dep = match dep.as_table() {
+            Some(dep) if dep.contains_key("path") => dep,
+            _ => continue,
+        };
+        for other in &["git", "rev"] {
+            if dep.contains_key(*other) {
+                let path = vec!["dependencies".to_string(), name.clone(), other.to_string()];
+                out.push(Problem{
+                    message:    msg!("config_conflict", key = key(&path), other = format!("dependencies.{}.path", name)),
+                    note:       msg!("config_conflict.dependency"),
+                    path,
+                });
+            }
+        }
+    }
+
+    let artifacts = config.get("artifacts").and_then(|a| a.as_array());
+    for (i, artifact) in artifacts.into_iter().flatten().enumerate() {
+        let typ = artifact.get("type").and_then(|t| t.as_str());
+        for (k, only) in &[("indexjs", "npm"), ("symbol_version", "lib")] {
+            if artifact.get(*k).is_some() && typ != Some(*only) {
+                let path = vec!["artifacts".to_string(), i.to_string(), k.to_string()];
+                out.push(Problem{
+                    message:    msg!("config_conflict", key = key(&path), other = format!("artifacts.{}.type", i)),
+                    note:       msg!("config_conflict.artifact", key = k, typ = only),
+                    path,
+                });
+            }
+        }
+    }
+
+    let schema = config.get("project").and_then(|p| p.get("schema")).and_then(|s| s.as_integer());
+    if let Some(schema) = schema {
+        if schema > CURRENT || schema < 1 {
+            out.push(Problem{
+                path:       vec!["project".to_string(), "schema".to_string()],
+                message:    msg!("config_schema", schema = schema, current = CURRENT),
+                note:       msg!("config_schema.note"),
+            });
+        }
+    }
+}
+
+/// the parts of a dotted key, without quotes
+fn split_key(s: &str) -> Vec<String> {
+    let mut r = Vec::new();
+    let mut part = String::new();
+    let mut quoted = false;
+    for c in s.chars() {
+        match c {
+            '"' | '\'' => quoted = !quoted,
+            '.' if !quoted => r.push(std::mem::replace(&mut part, String::new()).trim().to_string()),
+            c => part.push(c),
+        }
+    }
+    r.push(part.trim().to_string());
+    r
+}
+
+/// where the key at path is written in text, or where the closest table around it is.
+/// the index into an array of tables is not written, the first [[artifacts]] stands for all of them
+fn span(text: &str, path: &[String]) -> (usize, usize) {
+    let path : Vec<String> = path.iter().filter(|p| !p.chars().all(|c| c.is_ascii_digit())).cloned().collect();
+    let mut best = (0, 0, 0);
+    let mut section : Vec<String> = Vec::new();
+    let mut start = 0;
+    for line in text.split('\n') {
+        let trimmed = line.trim_start();
+        let at = start + line.len() - trimmed.len();
+        start += line.len() + 1;
+
+        let (full, len) = if trimmed.starts_with('[') {
+            let inner = trimmed.trim_start_matches('[');
+            let end = inner.find(']').unwrap_or(inner.len());
+            section = split_key(&inner[..end]);
+            (section.clone(), trimmed.rfind(']').map(|e| e + 1).unwrap_or(trimmed.len()))
+        } else if let Some(eq) = trimmed.find('=') {
+            let mut full = section.clone();
+            full.extend(split_key(&trimmed[..eq]));
+            (full, trimmed[..eq].trim_end().len())
+        } else {
+            continue;
+        };
+        if full == path {
+            return (at, at + len);
+        }
+        if path.starts_with(&full) && full.len() > best.0 {
+            best = (full.len(), at, at + len);
+        }
+    }
+    (best.1, best.2)
+}
+
+/// parse zz.toml and check it against the schema. there is nothing to build from a broken zz.toml, so problems abort
+pub fn check(path: &Path, text: String) -> Value {
+    let value : Value = match toml::from_str(&text) {
+        Ok(value) => value,
+        Err(e) => {
+            error!("{}", msg!("config_invalid", path = path.display(), error = e));
+            parser::abort();
+        }
+    };
+
+    let mut problems = Vec::new();
+    walk(&value, &CONFIG, &[], &mut problems);
+    conflicts(&value, &mut problems);
+    if problems.is_empty() {
+        return value;
+    }
+
+    let file = sourcemap::add(&path.to_string_lossy(), text);
+    let text = file.source();
+    for p in problems {
+        let (start, end) = span(&text, &p.path);
+        let loc = ast::Location::at(file, start, end);
+        emit_error(p.message, &[(loc, p.note)]);
+    }
+    parser::abort();
+}
+
+/// the schema of the text of a zz.toml
+fn schema_of(text: &str) -> i64 {
+    let value : Option<Value> = toml::from_str(text).ok();
+    value.as_ref()
+        .and_then(|v| v.get("project"))
+        .and_then(|p| p.get("schema"))
+        .and_then(|s| s.as_integer())
+        .unwrap_or(1)
+}
+
+/// 1 to 2: drop the empty lists zz init used to write into [project], an empty [dependencies],
+/// and a [variants] with nothing but default = []
+fn to_2(text: &str) -> String {
+    const DEFAULTS : &[(&str, &str)] = &[
+        ("project", "cincludes"),
+        ("project", "cobjects"),
+        ("project", "pkgconfig"),
+        ("project", "libs"),
+        ("project", "cflags"),
+        ("project", "lflags"),
+        ("variants", "default"),
+    ];
+
+    let mut out : Vec<String> = Vec::new();
+    let mut section = String::new();
+    for l in text.lines() {
+        let t = l.trim();
+        if t.starts_with('[') {
+            section = t.trim_matches(|c: char| c == '[' || c == ']').trim().to_string();
+        } else if let Some(eq) = t.find('=') {
+            let k = t[..eq].trim();
+            let v = t[eq + 1..].trim();
+            if v == "[]" && DEFAULTS.contains(&(section.as_str(), k)) {
+                continue;
+            }
+        }
+        out.push(l.to_string());
+    }
+
+    // sections that are left without a key
+    let mut r : Vec<String> = Vec::new();
+    let mut i = 0;
+    while i < out.len() {
+        let t = out[i].trim();
+        if t == "[dependencies]" || t == "[variants]" {
+            let next = out[i + 1..].iter().position(|l| !l.trim().is_empty()).map(|n| i + 1 + n);
+            if next.map(|n| out[n].trim().starts_with('[')).unwrap_or(true) {
+                i = next.unwrap_or(out.len());
+                continue;
+            }
+        }
+        r.push(out[i].clone());
+        i += 1;
+    }
+    while r.last().map(|l| l.trim().is_empty()).unwrap_or(false) {
+        r.pop();
+    }
+    super::project::set_project_key(&(r.join("\n") + "\n"), "schema", "2")
+}
+
+/// the migrations, by the schema they start from
+const MIGRATIONS : &[(i64, fn(&str) -> String)] = &[
+    (1, to_2),
+];
+
+/// rewrite zz.toml at root in the layout of the current schema
+pub fn migrate(root: &Path) {
+    let path = root.join("zz.toml");
+    let mut text = std::fs::read_to_string(&path).expect(&format!("cannot read {:?}", path));
+    let from = schema_of(&text);
+    if from >= CURRENT {
+        println!("{}", msg!("config_migrate_current", schema = from));
+        return;
+    }
+    for (schema, migration) in MIGRATIONS {
+        if *schema >= from {
+            text = migration(&text);
+        }
+    }
+    std::fs::write(&path, &text).expect(&format!("cannot write {:?}", path));
+    check(&path, text);
+    println!("{}", msg!("config_migrated", from = from, to = CURRENT));
+}
@@ -0,0 +1,191 @@
+/// zz build --self-profile: where the compiler itself spends its time and memory
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use serde::Serialize;
+
+pub static ENABLED: AtomicBool = AtomicBool::new(false);
+
+static HEAP:        AtomicUsize = AtomicUsize::new(0);
+static PEAK_HEAP:   AtomicUsize = AtomicUsize::new(0);
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static THREAD_ALLOCATIONS:  Cell<usize> = Cell::new(0);
+    static THREAD_BYTES:        Cell<usize> = Cell::new(0);
+}
+
+/// the system allocator, counting what goes through it while profiling is enabled.
+/// installed as #[global_allocator] by the zz binary
+pub struct Counting;
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let p = System.alloc(layout);
+        if !p.is_null() && ENABLED.load(Ordering::Relaxed) {
+            count(layout.size());
+        }
+        p
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        if ENABLED.load(Ordering::Relaxed) {
+            uncount(layout.size());
+        }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let p = System.realloc(ptr, layout, new_size);
+        if !p.is_null() && ENABLED.load(Ordering::Relaxed) {
+            uncount(layout.size());
+            count(new_size);
+        }
+        p
+    }
+}
+
+fn count(size: usize) {
+    ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    let heap = HEAP.fetch_add(size, Ordering::Relaxed) + size;
+    PEAK_HEAP.fetch_max(heap, Ordering::Relaxed);
+    // thread locals may already be gone while a thread exits
+    let _ = THREAD_ALLOCATIONS.try_with(|v| v.set(v.get() + 1));
+    let _ = THREAD_BYTES.try_with(|v| v.set(v.get() + size));
+}
+
+fn uncount(size: usize) {
+    // memory allocated before profiling was enabled is not in HEAP
+    let _ = HEAP.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |v| Some(v.saturating_sub(size)));
+}
+
+fn thread_counters() -> (usize, usize) {
+    (
+        THREAD_ALLOCATIONS.try_with(|v| v.get()).unwrap_or(0),
+        THREAD_BYTES.try_with(|v| v.get()).unwrap_or(0),
+    )
+}
+
+#[derive(Default, Clone, Serialize)]
+pub struct Pass {
+    pub name:           String,
+    pub calls:          usize,
+    pub time:           Duration,
+    pub allocations:    usize,
+    pub bytes:          usize,
+}
+
+#[derive(Serialize)]
+pub struct Report {
+    pub total:          Duration,
+    pub allocations:    usize,
+    pub peak_heap:      usize,
+    // as reported by the os, includes code and memory from before profiling started
+    pub peak_rss:       Option<usize>,
+    pub passes:         Vec<Pass>,
+}
+
+struct State {
+    start:  Instant,
+    order:  Vec<&'static str>,
+    passes: HashMap<&'static str, Pass>,
+}
+
+static STATE: Mutex<Option<State>> = Mutex::new(None);
+
+pub fn enable() {
+    *STATE.lock().unwrap() = Some(State {
+        start:  Instant::now(),
+        order:  Vec::new(),
+        passes: HashMap::new(),
+    });
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// run f as part of the named pass
+pub fn pass<T, F: FnOnce() -> T>(name: &'static str, f: F) -> T {
+    let _pass = super::crash::enter_pass(name);
+    if !enabled() {
+        return f();
+    }
+    let (allocations, bytes) = thread_counters();
+    let start = Instant::now();
+    let r = f();
+    let time = start.elapsed();
+    let (allocations_after, bytes_after) = thread_counters();
+
+    let mut state = STATE.lock().unwrap();
+    if let Some(state) = state.as_mut() {
+        if !state.passes.contains_key(name) {
+            state.order.push(name);
+        }
+        let pass = state.passes.entry(name).or_insert_with(|| Pass {
+            name: name.to_string(),
+            ..Default::default()
+        });
+        pass.calls       += 1;
+        pass.time        += time;
+        pass.allocations += allocations_after - allocations;
+        pass.bytes       += bytes_after - bytes;
+    }
+    r
+}
+
+/// peak resident set size of the process in bytes, where the os tells
+fn peak_rss() -> Option<usize> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+    let kb : usize = line["VmHWM:".len()..].trim().trim_end_matches("kB").trim().parse().ok()?;
+    Some(kb * 1024)
+}
+
+pub fn report() -> Option<Report> {
+    let state = STATE.lock().unwrap();
+    let state = state.as_ref()?;
+    Some(Report {
+        total:          state.start.elapsed(),
+        allocations:    ALLOCATIONS.load(Ordering::Relaxed),
+        peak_heap:      PEAK_HEAP.load(Ordering::Relaxed),
+        peak_rss:       peak_rss(),
+        passes:         state.order.iter().map(|n| state.passes[n].clone()).collect(),
+    })
+}
+
+fn mb(bytes: usize) -> String {
+    format!("{:.1}MB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+/// print the report and write it as json into the target directory
+pub fn finish(dir: &std::path::Path) {
+    let report = match report() {
+        Some(v) => v,
+        None => return,
+    };
+
+    eprintln!("\nself profile:");
+    eprintln!("  {:<12} {:>6} {:>10} {:>12} {:>10}", "pass", "calls", "time", "allocations", "allocated");
+    for pass in &report.passes {
+        eprintln!("  {:<12} {:>6} {:>8}ms {:>12} {:>10}",
+            pass.name, pass.calls, pass.time.as_millis(), pass.allocations, mb(pass.bytes));
+    }
+    eprintln!("  total {}ms, {} allocations, peak heap {}, peak rss {}",
+        report.total.as_millis(),
+        report.allocations,
+        mb(report.peak_heap),
+        report.peak_rss.map(mb).unwrap_or("unknown".to_string()),
+    );
+
+    let path = dir.join("self-profile.json");
+    match std::fs::write(&path, serde_json::to_string_pretty(&report).unwrap()) {
+        Ok(_)  => eprintln!("  written to {}", path.display()),
+        Err(e) => warn!("cannot write {}: {}", path.display(), e),
+    }
+}
@@ -0,0 +1,98 @@
+/// the contents of every source file, owned in one place, so locations are a file id and a byte range
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FileId(u32);
+
+impl FileId {
+    pub const BUILTIN : FileId = FileId(0);
+
+    pub fn name(self) -> Arc<str> {
+        get(self).name
+    }
+
+    pub fn source(self) -> Arc<str> {
+        get(self).src
+    }
+}
+
+impl fmt::Display for FileId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Clone)]
+struct File {
+    name:   Arc<str>,
+    src:    Arc<str>,
+    /// byte offsets at which lines start
+    lines:  Arc<[usize]>,
+}
+
+impl File {
+    fn new(name: Arc<str>, src: Arc<str>) -> File {
+        let lines = std::iter::once(0).chain(src.match_indices('\n').map(|(i, _)| i + 1)).collect();
+        File{name, src, lines}
+    }
+}
+
+#[derive(Default)]
+struct Map {
+    files:  Vec<File>,
+    ids:    HashMap<Arc<str>, FileId>,
+}
+
+static MAP: RwLock<Option<Map>> = RwLock::new(None);
+
+/// the contents of file name, which are the new contents if it was added before
+pub fn add(name: &str, src: String) -> FileId {
+    let mut map = MAP.write().unwrap();
+    let map = map.get_or_insert_with(Map::default);
+    let file = File::new(name.into(), src.into());
+    if let Some(&id) = map.ids.get(name) {
+        map.files[id.0 as usize - 1] = file;
+        return id;
+    }
+    map.files.push(file);
+    let id = FileId(map.files.len() as u32);
+    map.ids.insert(name.into(), id);
+    id
+}
+
+/// the id of a file that was added by name
+pub fn find(name: &str) -> Option<FileId> {
+    MAP.read().unwrap().as_ref().and_then(|map| map.ids.get(name).copied())
+}
+
+fn get(id: FileId) -> File {
+    if id == FileId::BUILTIN {
+        return File::new("prelude".into(), " ".into());
+    }
+    let map = MAP.read().unwrap();
+    map.as_ref().expect("ICE: file id without a source map").files[id.0 as usize - 1].clone()
+}
+
+/// the text of file from start to end
+pub fn text(file: FileId, start: usize, end: usize) -> String {
+    get(file).src.get(start..end).unwrap_or_default().to_string()
+}
+
+/// line and column of the byte offset at in file, both from 1. columns count chars
+pub fn line_col(file: FileId, at: usize) -> (usize, usize) {
+    let file = get(file);
+    let at = at.min(file.src.len());
+    let line = file.lines.partition_point(|start| *start <= at);
+    let start = file.lines[line - 1];
+    let col = file.src.get(start..at).map(|s| s.chars().count()).unwrap_or(at - start);
+    (line, col + 1)
+}
+
+/// line number line of file from 1, without the newline
+pub fn line(file: FileId, line: usize) -> String {
+    let file = get(file);
+    file.src.lines().nth(line.saturating_sub(1)).unwrap_or_default().to_string()
+}
@@ -0,0 +1,53 @@
+/// unstable api
+
+use super::loader;
+use super::name::Name;
+use super::parser::emit_error;
+use super::uses;
+use std::collections::HashMap;
+
+/// check the uses in all modules. returns false if something unstable was used without opting in
+pub fn check(modules: &HashMap<Name, loader::Module>, names: &[Name]) -> bool {
+    let mut unstable = HashMap::new();
+    for name in names {
+        if let Some(loader::Module::ZZ(md)) = modules.get(name) {
+            for local in &md.locals {
+                if let Some(u) = md.unstable.get(&local.name) {
+                    let mut name = md.name.clone();
+                    name.push(local.name.clone());
+                    unstable.insert(name, u);
+                }
+            }
+        }
+    }
+    if unstable.is_empty() {
+        return true;
+    }
+
+    let mut ok = true;
+    for name in names {
+        let md = match modules.get(name) {
+            Some(loader::Module::ZZ(md)) => md,
+            _ => continue,
+        };
+        let package = &md.name.0[1];
+        let used = uses::uses(md, &|name| {
+            name.0.get(1) != Some(package) && unstable.contains_key(name)
+        });
+        for u in used {
+            let declared = &unstable[&u.name];
+            let from = &u.name.0[1];
+            let allowed = md.allow_unstable.get(from.as_str()).map(|f| f.contains(&declared.feature)).unwrap_or(false);
+            if allowed {
+                continue;
+            }
+            emit_error(msg!("unstable_use", name = u.name, feature = declared.feature, package = from), &[
+                (u.loc.clone(), msg!("unstable_use.note")),
+                (declared.loc.clone(), msg!("unstable_use.declared")),
+                (u.loc.clone(), msg!("unstable_use.suggestion", feature = declared.feature, package = from)),
+            ]);
+            ok = false;
+        }
+    }
+    ok
+}
@@ -0,0 +1,240 @@
+/// state machines
+
+use super::ast;
+use super::derive;
+use super::edition::Edition;
+use super::make::Stage;
+use super::parser::{self, emit_error};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::path::Path;
+
+fn find<'a>(list: &'a [(String, ast::Location)], name: &str) -> Option<&'a ast::Location> {
+    list.iter().find(|(n, _)| n == name).map(|(_, loc)| loc)
+}
+
+fn check(sm: &ast::StateMachine) {
+    if sm.states.is_empty() || sm.events.is_empty() {
+        emit_error(msg!("statemachine_empty", name = sm.name), &[
+            (sm.loc.clone(), msg!("statemachine_empty.note")),
+        ]);
+        parser::abort();
+    }
+    for list in &[&sm.states, &sm.events] {
+        for (i, (name, loc)) in list.iter().enumerate() {
+            if let Some((_, first)) = list[..i].iter().find(|(n, _)| n == name) {
+                emit_error(msg!("statemachine_duplicate", name = name, machine = sm.name), &[
+                    (loc.clone(), msg!("statemachine_duplicate.note")),
+                    (first.clone(), msg!("defined_here")),
+                ]);
+                parser::abort();
+            }
+        }
+    }
+
+    let mut ok = true;
+    let initial = sm.initial.iter();
+    let states = sm.transitions.iter().flat_map(|t| vec![&t.from, &t.to]).chain(initial);
+    for (name, loc) in states {
+        if find(&sm.states, name).is_none() {
+            emit_error(msg!("statemachine_undefined_state", name = name, machine = sm.name), &[
+                (loc.clone(), msg!("statemachine_undefined.note")),
+            ]);
+            ok = false;
+        }
+    }
+    for t in &sm.transitions {
+        if find(&sm.events, &t.event.0).is_none() {
+            emit_error(msg!("statemachine_undefined_event", name = t.event.0, machine = sm.name), &[
+                (t.event.1.clone(), msg!("statemachine_undefined.note")),
+            ]);
+            ok = false;
+        }
+    }
+    if !ok {
+        parser::abort();
+    }
+
+    for (i, t) in sm.transitions.iter().enumerate() {
+        let earlier = sm.transitions[..i].iter().find(|e| e.from.0 == t.from.0 && e.event.0 == t.event.0 && e.guard.is_none());
+        if let Some(e) = earlier {
+            emit_error(msg!("statemachine_shadowed"), &[
+                (t.loc.clone(), msg!("statemachine_shadowed.note", from = e.from.0, to = e.to.0, event = e.event.0)),
+                (e.loc.clone(), msg!("defined_here")),
+            ]);
+            ok = false;
+        }
+    }
+
+    let initial = initial_state(sm);
+    let mut reached : HashSet<&str> = HashSet::new();
+    let mut todo = vec![initial];
+    while let Some(state) = todo.pop() {
+        if !reached.insert(state) {
+            continue;
+        }
+        for t in &sm.transitions {
+            if t.from.0 == state {
+                todo.push(t.to.0.as_str());
+            }
+        }
+    }
+    for (name, loc) in &sm.states {
+        if !reached.contains(name.as_str()) {
+            emit_error(msg!("statemachine_unreachable", name = name, machine = sm.name), &[
+                (loc.clone(), msg!("statemachine_unreachable.note", initial = initial)),
+            ]);
+            ok = false;
+        }
+    }
+    if !ok {
+        parser::abort();
+    }
+}
+
+fn initial_state(sm: &ast::StateMachine) -> &str {
+    sm.initial.as_ref().map(|(n, _)| n.as_str()).unwrap_or(&sm.states[0].0)
+}
+
+/// the states in the order of the enum, initial state first
+fn states(sm: &ast::StateMachine) -> Vec<&str> {
+    let initial = initial_state(sm);
+    std::iter::once(initial).chain(sm.states.iter().map(|(n, _)| n.as_str()).filter(|n| *n != initial)).collect()
+}
+
+/// zz source of the enums, the table and the struct with its methods
+fn source(sm: &ast::StateMachine) -> String {
+    let vis = match sm.vis {
+        ast::Visibility::Object => "",
+        ast::Visibility::Shared => "pub ",
+        ast::Visibility::Package => "pub(package) ",
+        ast::Visibility::Export => "export ",
+    };
+    let name    = &sm.name;
+    let states  = states(sm);
+    let events  : Vec<&str> = sm.events.iter().map(|(n, _)| n.as_str()).collect();
+
+    // transitions with the same state and event are one entry in the table, tried in order
+    let mut groups : Vec<((&str, &str), Vec<&ast::Transition>)> = Vec::new();
+    for t in &sm.transitions {
+        let key = (t.from.0.as_str(), t.event.0.as_str());
+        match groups.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, v)) => v.push(t),
+            None => groups.push((key, vec![t])),
+        }
+    }
+
+    let mut s = String::new();
+    writeln!(s, "// generated from statemachine {} in {}", name, sm.loc.file).unwrap();
+    writeln!(s).unwrap();
+    writeln!(s, "{}enum {}_state {{", vis, name).unwrap();
+    for state in &states {
+        writeln!(s, "    {},", state).unwrap();
+    }
+    writeln!(s, "}}").unwrap();
+    writeln!(s).unwrap();
+    writeln!(s, "{}enum {}_event {{", vis, name).unwrap();
+    for event in &events {
+        writeln!(s, "    {},", event).unwrap();
+    }
+    writeln!(s, "}}").unwrap();
+    writeln!(s).unwrap();
+
+    // entry + 1 of the transitions at state * events + event, 0 if the event does nothing in that state
+    let size = states.len() * events.len();
+    writeln!(s, "static u32 {}_table[{}] = {{", name, size).unwrap();
+    for state in &states {
+        let row : Vec<String> = events.iter().map(|event| {
+            groups.iter().position(|(k, _)| *k == (*state, *event)).map(|i| i + 1).unwrap_or(0).to_string()
+        }).collect();
+        writeln!(s, "    {},", row.join(", ")).unwrap();
+    }
+    writeln!(s, "}};").unwrap();
+    writeln!(s).unwrap();
+
+    writeln!(s, "{}struct {} {{", vis, name).unwrap();
+    writeln!(s, "    {}_state state;", name).unwrap();
+    for field in &sm.fields {
+        writeln!(s, "    {}", field).unwrap();
+    }
+    writeln!(s).unwrap();
+    writeln!(s, "    {}fn init({} mut* self) {{", vis, name).unwrap();
+    writeln!(s, "        self->state = {}_state::{};", name, states[0]).unwrap();
+    writeln!(s, "    }}").unwrap();
+    writeln!(s).unwrap();
+    writeln!(s, "    {}fn fire({} mut* self, {}_event ev) -> bool {{", vis, name, name).unwrap();
+    writeln!(s, "        usize at = as<usize>(self->state) * {} + as<usize>(ev);", events.len()).unwrap();
+    writeln!(s, "        if at >= {} {{", size).unwrap();
+    writeln!(s, "            return false;").unwrap();
+    writeln!(s, "        }}").unwrap();
+    writeln!(s, "        switch {}_table[at] {{", name).unwrap();
+    for (i, (_, transitions)) in groups.iter().enumerate() {
+        writeln!(s, "            {} => {{", i + 1).unwrap();
+        for t in transitions {
+            let indent = match &t.guard {
+                Some((guard, _)) => {
+                    writeln!(s, "                if {}(self) {{", guard).unwrap();
+                    "    "
+                }
+                None => "",
+            };
+            writeln!(s, "{}                self->state = {}_state::{};", indent, name, t.to.0).unwrap();
+            writeln!(s, "{}                return true;", indent).unwrap();
+            if t.guard.is_some() {
+                writeln!(s, "                }}").unwrap();
+            }
+        }
+        writeln!(s, "            }}").unwrap();
+    }
+    writeln!(s, "        }}").unwrap();
+    writeln!(s, "        return false;").unwrap();
+    writeln!(s, "    }}").unwrap();
+    writeln!(s, "}}").unwrap();
+    s
+}
+
+/// the machine as a graphviz digraph
+fn dot(sm: &ast::StateMachine) -> String {
+    let mut s = String::new();
+    writeln!(s, "digraph {} {{", sm.name).unwrap();
+    writeln!(s, "    rankdir=LR;").unwrap();
+    writeln!(s, "    \"{}\" [shape=doublecircle];", initial_state(sm)).unwrap();
+    for t in &sm.transitions {
+        let label = match &t.guard {
+            Some((guard, _)) => format!("{} [{}]", t.event.0, guard),
+            None => t.event.0.clone(),
+        };
+        writeln!(s, "    \"{}\" -> \"{}\" [label=\"{}\"];", t.from.0, t.to.0, label).unwrap();
+    }
+    writeln!(s, "}}").unwrap();
+    s
+}
+
+/// check the state machines of md and add what they lower to
+pub fn lower(
+    md:         &mut ast::Module,
+    root:       &Path,
+    features:   &HashMap<String, bool>,
+    stage:      &Stage,
+    edition:    Edition,
+) {
+    if md.statemachines.is_empty() {
+        return;
+    }
+    let dir = root.join("target").join(stage.to_string()).join("statemachine");
+    std::fs::create_dir_all(&dir).expect("create target dir");
+    let stem = md.source.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+
+    for sm in md.statemachines.clone() {
+        check(&sm);
+        let path = dir.join(format!("{}_{}.zz", stem, sm.name));
+        std::fs::write(&path, source(&sm)).expect(&format!("cannot write {}", path.display()));
+        let graph = dir.join(format!("{}_{}.dot", stem, sm.name));
+        std::fs::write(&graph, dot(&sm)).expect(&format!("cannot write {}", graph.display()));
+
+        derive::splice(md, &path, features, stage, edition);
+        if let Some(local) = md.locals.iter_mut().find(|l| l.name == sm.name) {
+            local.doc = sm.doc.clone();
+        }
+    }
+}
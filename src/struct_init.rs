@@ -0,0 +1,184 @@
+/// the struct_init lint: struct initializers that leave out fields
+
+use super::ast;
+use super::loader;
+use super::name::Name;
+use super::parser::{emit_error, emit_warn};
+use super::project::LintLevel;
+use std::collections::HashMap;
+
+struct Finding {
+    loc:        ast::Location,
+    message:    String,
+    note:       String,
+}
+
+struct Lint<'a> {
+    modules:    &'a HashMap<Name, loader::Module>,
+    findings:   Vec<Finding>,
+}
+
+impl<'a> Lint<'a> {
+    /// the declaration of an absolute name in any module
+    fn local(&self, name: &Name) -> Option<&'a ast::Local> {
+        let mut module = name.clone();
+        let local = module.pop()?;
+        match self.modules.get(&module) {
+            Some(loader::Module::ZZ(md)) => md.locals.iter().find(|l| l.name == local),
+            _ => None,
+        }
+    }
+
+    fn init(&mut self, typed: &ast::Typed, fields: &[(String, Box<ast::Expression>)], loc: &ast::Location) {
+        let name = match &typed.t {
+            ast::Type::Other(name) => name,
+            _ => return,
+        };
+        let declared = match self.local(name).map(|l| &l.def) {
+            Some(ast::Def::Struct{fields, union: false, ..}) => fields,
+            _ => return,
+        };
+        let missing : Vec<&str> = declared.iter()
+            .filter(|f| !matches!(f.array, Some(None)))
+            .filter(|f| !fields.iter().any(|(n, _)| n == &f.name))
+            .map(|f| f.name.as_str())
+            .collect();
+        if missing.is_empty() {
+            return;
+        }
+        self.findings.push(Finding{
+            loc:        loc.clone(),
+            message:    msg!("struct_init_missing", name = name, fields = missing.join(", ")),
+            note:       msg!("struct_init_missing.note"),
+        });
+    }
+
+    fn block(&mut self, block: &ast::Block) {
+        for stm in &block.statements {
+            self.statement(stm);
+        }
+    }
+
+    fn statement(&mut self, stm: &ast::Statement) {
+        match stm {
+            ast::Statement::Mark{lhs, ..} => self.expr(lhs),
+            ast::Statement::Label{..}
+            | ast::Statement::Break{..}
+            | ast::Statement::Continue{..}
+            | ast::Statement::CBlock{..} => {}
+            ast::Statement::Block(b) | ast::Statement::Unsafe(b) => self.block(b),
+            ast::Statement::For{e1, e2, e3, body} => {
+                for s in e1.iter().chain(e3.iter()) {
+                    self.statement(s);
+                }
+                if let Some(e2) = e2 {
+                    self.expr(e2);
+                }
+                self.block(body);
+            }
+            ast::Statement::While{expr, body} => {
+                self.expr(expr);
+                self.block(body);
+            }
+            ast::Statement::If{branches} => {
+                for (_, e, body) in branches {
+                    if let Some(e) = e {
+                        self.expr(e);
+                    }
+                    self.block(body);
+                }
+            }
+            ast::Statement::Assign{lhs, rhs, ..} => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            ast::Statement::Var{array, assign, ..} => {
+                if let Some(Some(size)) = array {
+                    self.expr(size);
+                }
+                if let Some(assign) = assign {
+                    self.expr(assign);
+                }
+            }
+            ast::Statement::Expr{expr, ..} => self.expr(expr),
+            ast::Statement::Return{expr, ..} => {
+                if let Some(expr) = expr {
+                    self.expr(expr);
+                }
+            }
+            ast::Statement::Switch{expr, cases, default, ..} => {
+                self.expr(expr);
+                for (conds, body) in cases {
+                    for cond in conds {
+                        self.expr(cond);
+                    }
+                    self.block(body);
+                }
+                if let Some(default) = default {
+                    self.block(default);
+                }
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &ast::Expression) {
+        match expr {
+            ast::Expression::Name(_)
+            | ast::Expression::LiteralString{..}
+            | ast::Expression::LiteralChar{..}
+            | ast::Expression::Literal{..} => {}
+            ast::Expression::MemberAccess{lhs, ..} => self.expr(lhs),
+            ast::Expression::ArrayAccess{lhs, rhs, ..} | ast::Expression::Infix{lhs, rhs, ..} => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            ast::Expression::Call{name, args, ..} => {
+                self.expr(name);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            ast::Expression::Cast{expr, ..}
+            | ast::Expression::UnaryPost{expr, ..}
+            | ast::Expression::UnaryPre{expr, ..} => self.expr(expr),
+            ast::Expression::StructInit{typed, fields, loc} => {
+                self.init(typed, fields, loc);
+                for (_, e) in fields {
+                    self.expr(e);
+                }
+            }
+            ast::Expression::ArrayInit{fields, ..} => {
+                for e in fields {
+                    self.expr(e);
+                }
+            }
+        }
+    }
+}
+
+/// run the lint on function bodies and statics of md. returns false if it found anything and level is deny
+pub fn check(md: &ast::Module, modules: &HashMap<Name, loader::Module>, level: LintLevel) -> bool {
+    if level == LintLevel::Allow {
+        return true;
+    }
+    let mut lint = Lint {
+        modules,
+        findings:   Vec::new(),
+    };
+    for local in &md.locals {
+        match &local.def {
+            ast::Def::Function{body, ..} | ast::Def::Test{body, ..} => lint.block(body),
+            ast::Def::Static{expr, ..} | ast::Def::Const{expr, ..} => lint.expr(expr),
+            _ => (),
+        }
+    }
+
+    for finding in &lint.findings {
+        if level == LintLevel::Deny {
+            emit_error(&finding.message, &[(finding.loc.clone(), &finding.note)]);
+        } else {
+            emit_warn(&finding.message, &[(finding.loc.clone(), &finding.note)]);
+        }
+    }
+    level != LintLevel::Deny || lint.findings.is_empty()
+}
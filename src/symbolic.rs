@@ -6,6 +6,9 @@ use super::parser::{self, emit_warn, emit_debug};
 use ast::Tags;
 use crate::smt::{Solver, self};
 use super::Error;
+use super::format;
+use super::macros::{Visit, walk_statement, walk_expr};
+use crate::edition::Edition;
 
 pub type Symbol = usize;
 pub type TemporalSymbol = (Symbol, u64);
@@ -86,6 +89,8 @@ struct Storage {
     tags:           ast::Tags,
     temporal:       u64,
     assignments:    HashMap<u64, ast::Location>,
+    // bounds of a field declared with @range, see check_range
+    range:          Option<(ast::Expression, ast::Expression)>,
 
     //TODO not actually implemented
     borrows:        Vec<(Symbol, ast::Location)>,
@@ -111,6 +116,7 @@ pub struct Symbolic {
     current_call:           Vec<ast::Location>,
     in_loop:    bool,
     in_model:   bool,
+    edition:    Edition,
 }
 
 
@@ -125,6 +131,7 @@ impl Symbolic {
         debug!("~~~~~\nexecuting {}", module.name);
 
         self.current_module_name = module.name.human_name();
+        self.edition = module.edition;
 
         // built in len theory
         let sym = self.alloc(Name::from("len"), ast::Typed{
@@ -352,7 +359,7 @@ impl Symbolic {
                     self.copy(sym, esym, &d.loc)?;
                     self.tail_into_ssa(sym, &d.loc)?;
                 },
-                ast::Def::Const { typed, expr} => {
+                ast::Def::Const { typed, expr, ..} => {
                     let sym = self.alloc(
                         Name::from(&d.name),
                         typed.clone(),
@@ -361,7 +368,7 @@ impl Symbolic {
                     let esym = self.execute_expr(expr)?;
                     self.copy(sym, esym, &d.loc)?;
                 },
-                ast::Def::Fntype {..} => {
+                ast::Def::Fntype {..} | ast::Def::TypeAlias {..} => {
                     let sym = self.alloc(
                         Name::from(&d.name),
                         ast::Typed{
@@ -393,7 +400,7 @@ impl Symbolic {
                         Ok(v) => v,
                     };
                 },
-                ast::Def::Enum{names} => {
+                ast::Def::Enum{names, ..} => {
                     self.alloc(
                         Name::from(&d.name),
                         ast::Typed{
@@ -447,6 +454,8 @@ impl Symbolic {
                 },
                 ast::Def::Testcase {..} => {},
                 ast::Def::Include {..} => {},
+                ast::Def::Closure {..} => {},
+                ast::Def::Test {..} => {},
             }
         }
 
@@ -670,6 +679,40 @@ impl Symbolic {
     }
 
 
+    /// whether expr is an entry of a const table, like HANDLERS[i] or DRIVERS[i].open.
+    /// comptime checked that every function in it is set
+    fn fn_table(&self, expr: &ast::Expression) -> bool {
+        let lhs = match expr {
+            ast::Expression::ArrayAccess{lhs, ..} | ast::Expression::MemberAccess{lhs, ..} => lhs,
+            _ => return false,
+        };
+        match lhs.as_ref() {
+            ast::Expression::Name(ast::Typed{t: ast::Type::Other(n), ..}) => matches!(
+                self.defs.get(n),
+                Some(ast::Def::Const{..}) | Some(ast::Def::Static{storage: ast::Storage::Const, ..})
+            ),
+            ast::Expression::ArrayAccess{..} => self.fn_table(lhs),
+            _ => false,
+        }
+    }
+
+    /// check the arguments after a literal format string against its conversions, see format.rs
+    fn check_format(&self, at: usize, args: &[Box<ast::Expression>], syms: &[Symbol], loc: &ast::Location) -> Result<(), Error> {
+        let fmt = match args.get(at).map(|a| a.as_ref()) {
+            Some(ast::Expression::LiteralString{v, ..}) => v,
+            _ => return Ok(()),
+        };
+        let types : Vec<ast::Typed> = syms.iter().skip(at + 1).map(|s| self.memory[*s].typed.clone()).collect();
+        if let Err((i, message)) = format::check(fmt, &types) {
+            let here = i.and_then(|i| args.get(at + 1 + i)).map(|a| a.loc().clone()).unwrap_or(loc.clone());
+            return Err(self.trace("format string does not match its arguments".to_string(), vec![
+                (here, message),
+                (args[at].loc().clone(), "format string".to_string()),
+            ]));
+        }
+        Ok(())
+    }
+
     fn type_coersion(&mut self, a: Symbol, b: Symbol, here: &ast::Location) -> Result<(ast::Typed, Symbol, Symbol), Error>  {
         if let Value::Theory{..} =  self.memory[a].value {
             return Err(self.trace(format!("theory '{}' is not a real world object", self.memory[a].name ), vec![
@@ -700,6 +743,18 @@ impl Symbolic {
         }
 
 
+        // integers are not bools. write true, false or a comparison
+        for &(lit, other) in &[(a, b), (b, a)] {
+            if self.memory[lit].typed.t == ast::Type::ULiteral
+                && self.memory[other].typed.t == ast::Type::Bool
+                && self.memory[other].typed.ptr.is_empty()
+            {
+                return Err(self.trace(msg!("integer_as_bool", name = self.memory[lit].name), vec![
+                    (here.clone(), msg!("integer_as_bool.note"))
+                ]));
+            }
+        }
+
         // if one is an unsigned literal, cast it into the other type
         if self.memory[a].typed.t == ast::Type::ULiteral {
             let tmp = self.temporary(
@@ -738,6 +793,23 @@ impl Symbolic {
         }
 
 
+        // since edition 2021 an integer is not a pointer without a cast. 0 is still null, it's a literal
+        if self.edition >= Edition::E2021 && self.memory[a].typed.ptr.len() > 0 && self.memory[b].typed.ptr.is_empty() {
+            let integer = match self.memory[b].typed.t {
+                ast::Type::U8 | ast::Type::U16 | ast::Type::U32 | ast::Type::U64 | ast::Type::U128
+                | ast::Type::I8 | ast::Type::I16 | ast::Type::I32 | ast::Type::I64 | ast::Type::I128
+                | ast::Type::Int | ast::Type::UInt | ast::Type::ISize | ast::Type::USize
+                | ast::Type::ILiteral => true,
+                _ => false,
+            };
+            if integer {
+                return Err(self.trace(msg!("edition_int_to_ptr", from = self.memory[b].typed, into = self.memory[a].typed), vec![
+                    (here.clone(), msg!("edition_int_to_ptr.note", into = self.memory[a].typed)),
+                    (self.memory[b].declared.clone(), format!("{} := {}", self.memory[b].name, self.memory[b].typed)),
+                ]));
+            }
+        }
+
         // TODO if the lhs is a pointer, do an implicit cast
         if self.memory[a].typed.ptr.len() > 0 {
             let tmp = self.temporary(
@@ -776,6 +848,19 @@ impl Symbolic {
     }
 
 
+    fn noreturn(&self, expr: &ast::Expression) -> bool {
+        let name = match expr {
+            ast::Expression::Call{name, ..} => name,
+            _ => return false,
+        };
+        match name.as_ref() {
+            ast::Expression::Name(ast::Typed{t: ast::Type::Other(name), ..}) => {
+                matches!(self.defs.get(name), Some(ast::Def::Function{attr, ..}) if attr.contains_key("noreturn"))
+            }
+            _ => false,
+        }
+    }
+
     fn execute_scope(&mut self, body: &mut Vec<Box<ast::Statement>>) -> Result<ScopeReturn, Error> {
         for i in 0..body.len() {
             let (body, _rest) = body.split_at_mut(i + 1);
@@ -791,6 +876,15 @@ impl Symbolic {
                         });
                     }
 
+                    // reading a field assumes its @range, which garbage doesn't have
+                    if assign.is_none() {
+                        if let Some((path, _)) = self.ranged_fields(typed_o).first() {
+                            return Err(self.trace(format!("{} is not initialized, but {}.{} has a @range", name, short_name(typed_o), path), vec![
+                                (loc.clone(), format!("initialize it, like with {}{{ ... }}", short_name(typed_o))),
+                            ]));
+                        }
+                    }
+
                     let sym = self.alloc(Name::from(name.as_str()), typed, loc.clone(), tags.clone())?;
 
                     if let Some(array) = array {
@@ -928,8 +1022,12 @@ impl Symbolic {
 
                     // continue execution as if no condition was met
                 }
-                ast::Statement::Expr{expr, ..} => {
+                ast::Statement::Expr{expr, loc} => {
                     self.execute_expr(expr)?;
+                    // nothing runs after a call to a noreturn function, like after return
+                    if self.noreturn(expr) {
+                        return Ok(ScopeReturn::Return(loc.clone()));
+                    }
                 }
                 ast::Statement::Return{loc, expr} => {
                     if let Some(expr) = expr  {
@@ -1022,8 +1120,10 @@ impl Symbolic {
 
 
                     if let ast::AssignOperator::Eq = op {
+                        self.check_range(lhs, rhs, false, loc)?;
                         if self.in_loop {
                             self.memory[lhs].temporal += 1;
+                            self.check_range(lhs, lhs, true, loc)?;
                         } else {
                             self.copy(lhs, rhs, loc)?;
                         }
@@ -1034,13 +1134,6 @@ impl Symbolic {
                             self.memory[lhs].tags.clone(),
                         )?;
 
-                        if newtype.ptr.len() > 0 {
-                            return Err(self.trace(format!("assign arithmetic is not yet implemented"), vec![
-                                (loc.clone(), format!("use a=a+n instead of a+=n"))
-                            ]));
-                        }
-
-
                         let infix = match op {
                             ast::AssignOperator::Bitor  => ast::InfixOperator::Bitor,
                             ast::AssignOperator::Bitand => ast::InfixOperator::Bitand,
@@ -1055,19 +1148,25 @@ impl Symbolic {
                             rhs:    (rhs, self.memory[rhs].temporal),
                             op:     infix.clone(),
                         };
-                        self.memory[tmp].value = value;
+                        if newtype.ptr.len() > 0 {
+                            self.pointer_arithmetic(lhs, rhs, tmp, &infix, newtype.t.signed(), loc)?;
+                        } else {
+                            self.memory[tmp].value = value;
 
-                        self.ssa.infix_op(
-                            tmp,
-                            (lhs, self.memory[lhs].temporal),
-                            (rhs, self.memory[rhs].temporal),
-                            infix,
-                            self.memory[tmp].t.clone(),
-                            newtype.t.signed(),
-                         );
+                            self.ssa.infix_op(
+                                tmp,
+                                (lhs, self.memory[lhs].temporal),
+                                (rhs, self.memory[rhs].temporal),
+                                infix,
+                                self.memory[tmp].t.clone(),
+                                newtype.t.signed(),
+                             );
+                        }
 
+                        self.check_range(lhs, tmp, false, loc)?;
                         if self.in_loop {
                             self.memory[lhs].temporal += 1;
+                            self.check_range(lhs, lhs, true, loc)?;
                         } else {
                             self.copy(lhs, tmp, loc)?;
                         }
@@ -1091,6 +1190,10 @@ impl Symbolic {
                     let prev_loop = self.in_loop;
                     self.in_loop = false;
                     self.execute_scope(e1)?;
+                    let counter = induction(e1, e2.as_ref(), e3, body).and_then(|(name, op)| {
+                        let sym = self.stack.iter().rev().find_map(|scope| scope.locals.get(&name).copied())?;
+                        Some((sym, self.memory[sym].temporal, op))
+                    });
                     self.in_loop = true;
 
                     self.execute_scope(e3)?;
@@ -1109,6 +1212,10 @@ impl Symbolic {
                                 (expr.loc().clone(), format!("there may be conflicting constraints"))
                             ]));
                         }
+
+                        if let Some((sym, initial, op)) = counter {
+                            self.induction_bound(sym, initial, op, expr.loc())?;
+                        }
                     }
 
 
@@ -1172,14 +1279,11 @@ impl Symbolic {
                     "file" => {
                         ast::Expression::LiteralString {
                             loc: loc.clone(),
-                            v: callloc.file.as_bytes().to_vec(),
+                            v: callloc.file.name().as_bytes().to_vec(),
                         }
                     },
                     "line" => {
-                        ast::Expression::Literal{
-                            loc: loc.clone(),
-                            v:   format!("{}", callloc.line())
-                        }
+                        ast::Expression::literal(loc.clone(), format!("{}", callloc.line()))
                     },
                     "module" => {
                         ast::Expression::LiteralString {
@@ -1224,6 +1328,7 @@ impl Symbolic {
                     into: into_type,
                     expr: prev.clone(),
                     loc:  prev.loc().clone(),
+                    tags: ast::Tags::new(),
                 });
 
                 match &self.memory[callptr].typed.tail.clone() {
@@ -1243,10 +1348,7 @@ impl Symbolic {
                         ]));
                     },
                     ast::Tail::Static(v,loc) => {
-                        let genarg = Box::new(ast::Expression::Literal{
-                            loc: loc.clone(),
-                            v:   format!("{}", v),
-                        });
+                        let genarg = Box::new(ast::Expression::literal(loc.clone(), format!("{}", v)));
                         called.push(genarg);
                     }
                 }
@@ -1300,6 +1402,7 @@ impl Symbolic {
                                 into: into_type,
                                 expr: calledarg.clone(),
                                 loc:  calledarg.loc().clone(),
+                                tags: ast::Tags::new(),
                             };
                         }
                     }
@@ -1333,6 +1436,7 @@ impl Symbolic {
 
                                         *calledarg = ast::Expression::Cast {
                                             into: self.memory[callptr].typed.clone(),
+                                            tags: ast::Tags::new(),
                                             loc:  calledarg.loc().clone(),
                                             expr: Box::new(ast::Expression::Call {
                                                 loc:        calledarg.loc().clone(),
@@ -1492,7 +1596,11 @@ impl Symbolic {
         }
         self.memory[tmp].value = fieldvalue;
 
-
+        // every write is checked against the range, so reading can assume it
+        if field.1.range.is_some() {
+            self.memory[tmp].range = field.1.range.clone();
+            self.check_range(tmp, tmp, true, loc)?;
+        }
 
 
         match &mut self.memory[lhs_sym].value {
@@ -1528,10 +1636,18 @@ impl Symbolic {
                     Ok(v) => Ok(v),
                     Err(e) => {
                         if let ast::Type::Other(name) = &self.memory[lhs_sym].typed.t {
+                            // methods declared in the struct are namespaced by it,
+                            // other functions taking self are next to it in its module
+                            let mut method = name.clone();
+                            method.push(rhs.to_string());
                             let mut name = name.clone();
                             name.pop();
                             name.push(rhs.to_string());
-                            if let Ok(sym) = self.name(&name, loc) {
+                            let found = match self.name(&method, loc) {
+                                Ok(sym) => Ok((method, sym)),
+                                Err(_) => self.name(&name, loc).map(|sym| (name, sym)),
+                            };
+                            if let Ok((name, sym)) = found {
                                 if let Value::Function{..} = &self.memory[sym].value {
                                     let tmp = self.temporary(format!("desugar of self call {}", name),
                                         ast::Typed{
@@ -1727,9 +1843,9 @@ impl Symbolic {
                 Ok(tmp)
             }
 
-            ast::Expression::Literal { loc, v } => {
+            ast::Expression::Literal { loc, v, value } => {
                 self.ssa.debug("literal expr");
-                if v == "true" {
+                if *value == ast::LiteralValue::Bool(true) {
                     let t = ast::Typed {
                         t:      ast::Type::Bool,
                         loc:    loc.clone(),
@@ -1737,7 +1853,7 @@ impl Symbolic {
                         tail:   ast::Tail::None,
                     };
                     self.literal(loc, Value::Integer(0xffffffff), t)
-                } else if v == "false" {
+                } else if *value == ast::LiteralValue::Bool(false) {
                     let t = ast::Typed {
                         t:      ast::Type::Bool,
                         loc:    loc.clone(),
@@ -1745,9 +1861,10 @@ impl Symbolic {
                         tail:   ast::Tail::None,
                     };
                     self.literal(loc, Value::Integer(0), t)
-                } else if let Some(v) = parser::parse_u64(&v) {
+                } else if let Some(v) = value.as_u64() {
+                    // a suffix gives the literal a type, like 0xff_u8
                     let t = ast::Typed {
-                        t:      ast::Type::ULiteral,
+                        t:      value.suffix().cloned().unwrap_or(ast::Type::ULiteral),
                         loc:    loc.clone(),
                         ptr:    Vec::new(),
                         tail:   ast::Tail::None,
@@ -1755,7 +1872,7 @@ impl Symbolic {
                     self.literal(loc, Value::Integer(v), t)
                 } else {
                     let t = ast::Typed {
-                        t:      ast::Type::ULiteral,
+                        t:      value.suffix().cloned().unwrap_or(ast::Type::ULiteral),
                         loc:    loc.clone(),
                         ptr:    Vec::new(),
                         tail:   ast::Tail::None,
@@ -1797,10 +1914,7 @@ impl Symbolic {
                             tail:   ast::Tail::None,
                         });
 
-                        *expr = ast::Expression::Literal{
-                            loc: loc.clone(),
-                            v:  format!("\"{}\"", self.memory[sym].typed),
-                        };
+                        *expr = ast::Expression::literal(loc.clone(), format!("\"{}\"", self.memory[sym].typed));
                         self.current_call.pop();
                         return r;
                     },
@@ -1891,10 +2005,7 @@ impl Symbolic {
                             ptr:    Vec::new(),
                             tail:   ast::Tail::None,
                         });
-                        *expr = ast::Expression::Literal{
-                            loc: loc.clone(),
-                            v:  "".to_string(),
-                        };
+                        *expr = ast::Expression::literal(loc.clone(), "".to_string());
                         self.current_call.pop();
                         return r;
                     },
@@ -1928,10 +2039,7 @@ impl Symbolic {
                             ptr:    Vec::new(),
                             tail:   ast::Tail::None,
                         });
-                        *expr = ast::Expression::Literal{
-                            loc: loc.clone(),
-                            v:  format!("{}",val),
-                        };
+                        *expr = ast::Expression::literal(loc.clone(), format!("{}",val));
                         self.current_call.pop();
                         return r;
 
@@ -1948,7 +2056,9 @@ impl Symbolic {
                     Value::Unconstrained(_) | Value::Uninitialized => {
                         if let ast::Type::Other(n) = &self.memory[name_sym].typed.t {
                             if let Some(ast::Def::Fntype {ret,args,vararg,nameloc,..}) = self.defs.get(&n).cloned() {
-                                self.deref(name_sym, loc)?;
+                                if !self.fn_table(name) {
+                                    self.deref(name_sym, loc)?;
+                                }
                                 self.memory[name_sym].value = Value::Function {
                                     loc: nameloc,
                                     args,
@@ -2069,7 +2179,12 @@ impl Symbolic {
                             syms.push((s,self.memory[s].temporal));
                         }
 
-
+                        if vararg {
+                            if let Some(at) = format::tagged(&fargs) {
+                                let arg_syms : Vec<Symbol> = syms.iter().map(|s| s.0).collect();
+                                self.check_format(at, args, &arg_syms, loc)?;
+                            }
+                        }
 
                         //dont expose any symbols during callsite assert
                         let global_only = vec![self.stack[0].clone()];
@@ -2220,8 +2335,14 @@ impl Symbolic {
                         emit_debug(format!("call expression on {} is unprovable", s), &[
                             (loc.clone(), format!("consider using an unsafe block"))
                         ]);
-                        for arg in args {
-                            self.execute_expr(arg)?;
+                        let mut arg_syms = Vec::new();
+                        for arg in args.iter_mut() {
+                            arg_syms.push(self.execute_expr(arg)?);
+                        }
+                        if let ast::Expression::Name(ast::Typed{t: ast::Type::Other(n), ..}) = name.as_ref() {
+                            if let Some(at) = format::known(n) {
+                                self.check_format(at, args, &arg_syms, loc)?;
+                            }
                         }
                         let tmp = self.temporary(
                             format!("return value of {}", self.memory[name_sym].name),
@@ -2294,108 +2415,7 @@ impl Symbolic {
 
                 // pointer arithmetic
                 if newtype.ptr.len() > 0 {
-                    self.ssa.debug("begin pointer arithmetic");
-                    let len_of_lhs = self.temporary(format!("len({})", self.memory[lhs_sym].name),
-                        ast::Typed{
-                            t:      ast::Type::USize,
-                            ptr:    Vec::new(),
-                            loc:    loc.clone(),
-                            tail:   ast::Tail::None,
-                        },
-                        loc.clone(),
-                        Tags::new(),
-                    )?;
-                    let lensym = self.builtin.get("len").expect("ICE: len theory not built in");
-                    self.ssa.invocation(*lensym, vec![(lhs_sym, self.memory[lhs_sym].temporal)], (len_of_lhs, 0));
-
-
-
-                    let len_assert = self.temporary(format!("{} < len({})", self.memory[rhs_sym].name, self.memory[lhs_sym].name),
-                        ast::Typed{
-                            t:      ast::Type::Bool,
-                            ptr:    Vec::new(),
-                            loc:    loc.clone(),
-                            tail:   ast::Tail::None,
-                        },
-                        loc.clone(),
-                        Tags::new(),
-                    )?;
-                    self.memory[len_assert].value = Value::InfixOp {
-                        lhs:    (rhs_sym, self.memory[rhs_sym].temporal),
-                        rhs:    (len_of_lhs, self.memory[len_of_lhs].temporal),
-                        op:     ast::InfixOperator::Lessthan,
-                    };
-                    self.ssa.infix_op(
-                        len_assert,
-                        (rhs_sym, self.memory[rhs_sym].temporal),
-                        (len_of_lhs, 0),
-                        ast::InfixOperator::Lessthan,
-                        smt::Type::Bool,
-                        false,
-                    );
-                    self.ssa.debug("assert that length less than index is true");
-                    self.ssa.assert(vec![(len_assert, self.memory[len_assert].temporal)], |a, model| match a {
-                        false => {
-                            let mut estack = Vec::new();
-                            estack.extend(self.demonstrate(model.as_ref().unwrap(), (len_assert, self.memory[len_assert].temporal), 0));
-                            Err(self.trace(format!("possible out of bounds pointer arithmetic"), estack))
-                        }
-                        true => {
-                            Ok(())
-                        }
-                    })?;
-                    self.ssa_mark_safe(tmp, loc)?;
-
-                    let len_of_opresult = self.temporary(format!("len({})", self.memory[lhs_sym].name),
-                        ast::Typed{
-                            t:      ast::Type::USize,
-                            ptr:    Vec::new(),
-                            loc:    loc.clone(),
-                            tail:   ast::Tail::None,
-                        },
-                        loc.clone(),
-                        Tags::new(),
-                    )?;
-                    let lensym = self.builtin.get("len").expect("ICE: len theory not built in");
-                    self.ssa.invocation(*lensym, vec![(tmp, self.memory[tmp].temporal)], (len_of_opresult, 0));
-
-
-                    let opposite_op =  match op {
-                        ast::InfixOperator::Add      => ast::InfixOperator::Subtract,
-
-                        //TODO need to check for wrap
-                        //ast::InfixOperator::Subtract => ast::InfixOperator::Add,
-                        _ => {
-                            return Err(self.trace(format!("unprovable pointer arithmetic"), vec![
-                                (expr.loc().clone(), format!("only + is possible"))
-                            ]));
-                        }
-                    };
-
-                    self.ssa.infix_op(
-                        len_of_opresult,
-                        (len_of_lhs, self.memory[len_of_lhs].temporal),
-                        (rhs_sym, self.memory[rhs_sym].temporal),
-                        opposite_op,
-                        self.memory[tmp].t.clone(),
-                        signed,
-                    );
-
-
-                    let mut value = Value::Unconstrained("pointer arithmetic".into());
-                    if let Value::Array{..} = &self.memory[lhs_sym].value {
-                        if let Some(nuval) = self.ssa.value((len_of_opresult, self.memory[len_of_opresult].temporal), |a,_|match a{
-                            smt::Assertion::Constrained(val) => {
-                                let nuarray = HashMap::new();
-                                Some(Value::Array{len: val as usize, array: nuarray})
-                            }
-                            _ => None,
-                        }) {
-                            value = nuval;
-                        }
-                    };
-                    self.memory[tmp].value = value;
-
+                    self.pointer_arithmetic(lhs_sym, rhs_sym, tmp, op, signed, loc)?;
                     return Ok(tmp);
                 }
 
@@ -2419,7 +2439,7 @@ impl Symbolic {
                 );
                 Ok(tmp)
             }
-            ast::Expression::Cast { expr, into, loc } => {
+            ast::Expression::Cast { expr, into, loc, .. } => {
                 let rhs = self.execute_expr(expr)?;
                 let tmp = self.temporary(format!("cast of {}", self.memory[rhs].name),
                     into.clone(),
@@ -2446,6 +2466,8 @@ impl Symbolic {
 
                 if self.in_loop {
                     self.memory[tmp].temporal += 1;
+                    self.memory[tmp].range = self.memory[lhs_sym].range.clone();
+                    self.check_range(tmp, tmp, true, loc)?;
                 } else {
                     self.copy(tmp, lhs_sym, loc)?;
                 }
@@ -2468,7 +2490,7 @@ impl Symbolic {
                     op.clone(),
                     self.memory[lhs_sym].t.clone(),
                 );
-
+                self.check_range(lhs_sym, lhs_sym, false, loc)?;
 
                 Ok(tmp)
             }
@@ -2480,6 +2502,11 @@ impl Symbolic {
                     }
                     ast::PrefixOperator::AddressOf => {
                         let lhs_sym = self.execute_expr(expr)?;
+                        if self.memory[lhs_sym].range.is_some() {
+                            return Err(self.trace(format!("cannot take the address of {}", self.memory[lhs_sym].name), vec![
+                                (loc.clone(), format!("writes through it would skip the @range of {}", self.memory[lhs_sym].name)),
+                            ]));
+                        }
                         let mut typed =  self.memory[lhs_sym].typed.clone();
                         typed.ptr.push(ast::Pointer{
                             tags:   self.memory[lhs_sym].tags.clone(),
@@ -2580,32 +2607,94 @@ impl Symbolic {
                     loc.clone(),
                     Tags::new()
                 )?;
+                let ranges : HashMap<String, ast::Field> = match &typed.t {
+                    ast::Type::Other(n) => match self.defs.get(n) {
+                        Some(ast::Def::Struct{fields, ..}) => fields.iter()
+                            .filter(|f| f.range.is_some())
+                            .map(|f| (f.name.clone(), f.clone()))
+                            .collect(),
+                        _ => HashMap::new(),
+                    },
+                    _ => HashMap::new(),
+                };
                 let mut members = HashMap::new();
                 for (name, expr) in fields.iter_mut() {
-                    let to = self.execute_expr(expr)?;
+                    let mut to = self.execute_expr(expr)?;
+                    if let Some(field) = ranges.get(name.as_str()) {
+                        let member = self.temporary(
+                            format!("{}.{}", self.memory[aptr].name, name),
+                            field.typed.clone(),
+                            expr.loc().clone(),
+                            field.tags.clone(),
+                        )?;
+                        self.memory[member].range = field.range.clone();
+                        self.check_range(member, to, false, expr.loc())?;
+                        self.memory[member].value = self.memory[to].value.clone();
+                        self.ssa.assign((member, 0), (to, self.memory[to].temporal), Self::smt_type(&field.typed));
+                        to = member;
+                    }
                     members.insert(name.clone(), to);
                 }
 
+                // c zeroes what the initializer leaves out
+                let left_out : Vec<ast::Field> = match &typed.t {
+                    ast::Type::Other(n) => match self.defs.get(n) {
+                        Some(ast::Def::Struct{fields: declared, ..}) => declared.iter()
+                            .filter(|f| !fields.iter().any(|(name, _)| name == &f.name))
+                            .cloned()
+                            .collect(),
+                        _ => Vec::new(),
+                    },
+                    _ => Vec::new(),
+                };
+                for f in left_out {
+                    let mut ranged = self.ranged_fields(&f.typed);
+                    if f.range.is_some() {
+                        ranged.insert(0, (String::new(), f.clone()));
+                    }
+                    for (path, field) in ranged {
+                        let path = if path.is_empty() { f.name.clone() } else { format!("{}.{}", f.name, path) };
+                        let member = self.temporary(
+                            format!("{}.{}, left out and so 0", short_name(typed), path),
+                            field.typed.clone(),
+                            loc.clone(),
+                            field.tags.clone(),
+                        )?;
+                        self.memory[member].range = field.range.clone();
+                        let mut zero = ast::Expression::Literal{
+                            loc:    loc.clone(),
+                            v:      "0".to_string(),
+                            value:  ast::LiteralValue::Integer{value: 0, radix: 10, suffix: None},
+                        };
+                        let zero = self.execute_expr(&mut zero)?;
+                        self.check_range(member, zero, false, loc)?;
+                    }
+                }
+
                 self.memory[aptr].value = Value::Struct{
                     members,
                 };
 
                 Ok(aptr)
             }
-            ast::Expression::ArrayInit {fields, loc} => {
+            ast::Expression::ArrayInit {fields, loc, index} => {
                 if fields.len() < 1 {
                     return Err(self.trace(format!("empty literal array not possible"), vec![
                         (loc.clone(), format!("here"))
                     ]));
                 }
 
+                // {[7] = 2} leaves 0 to 6 unknown
                 let mut array = HashMap::new();
+                let mut first = None;
                 for (i, expr) in fields.iter_mut().enumerate() {
                     let to = self.execute_expr(expr)?;
-                    array.insert(i, to);
+                    first.get_or_insert(to);
+                    array.insert(index.get(i).map(|i| *i as usize).unwrap_or(i), to);
                 }
+                let len = index.iter().max().map(|i| *i as usize + 1).unwrap_or(array.len());
 
-                let mut typed = self.memory[array[&0]].typed.clone();
+                let mut typed = self.memory[first.unwrap()].typed.clone();
                 typed.ptr.push(ast::Pointer{
                     loc:  loc.clone(),
                     tags: ast::Tags::new(),
@@ -2618,7 +2707,7 @@ impl Symbolic {
                 )?;
 
                 self.memory[aptr].value = Value::Array{
-                    len:    array.len(),
+                    len,
                     array,
                 };
                 self.ssa_mark_safe(aptr, loc)?;
@@ -2630,6 +2719,269 @@ impl Symbolic {
 
 
 
+    /// the fields with a @range of a struct, and of the structs embedded in it, by their path
+    fn ranged_fields(&self, typed: &ast::Typed) -> Vec<(String, ast::Field)> {
+        let mut r = Vec::new();
+        if !typed.ptr.is_empty() {
+            return r;
+        }
+        if let ast::Type::Other(n) = &typed.t {
+            if let Some(ast::Def::Struct{fields, ..}) = self.defs.get(n) {
+                for f in fields {
+                    if f.range.is_some() {
+                        r.push((f.name.clone(), f.clone()));
+                    }
+                    for (path, inner) in self.ranged_fields(&f.typed) {
+                        r.push((format!("{}.{}", f.name, path), inner));
+                    }
+                }
+            }
+        }
+        r
+    }
+
+    /// value must be within the @range of field, before it is written to field.
+    /// with attest the range is assumed instead, for reading a field that was checked when it was written.
+    fn check_range(&mut self, field: Symbol, value: Symbol, attest: bool, loc: &ast::Location) -> Result<(), Error> {
+        let (mut lo, mut hi) = match &self.memory[field].range {
+            Some(v) => v.clone(),
+            None => return Ok(()),
+        };
+        let typed   = self.memory[field].typed.clone();
+        let signed  = typed.t.signed();
+        let lo      = self.execute_expr(&mut lo)?;
+        let hi      = self.execute_expr(&mut hi)?;
+
+        // the value and bounds as the type of the field, like the c compiler would convert them
+        let mut bounds = Vec::new();
+        for sym in vec![value, lo, hi] {
+            let tmp = self.temporary(format!("{} as {}", self.memory[sym].name, typed), typed.clone(), loc.clone(), Tags::new())?;
+            self.memory[tmp].value = self.memory[sym].value.clone();
+            self.ssa.assign((tmp, 0), (sym, self.memory[sym].temporal), Self::smt_type(&typed));
+            bounds.push(tmp);
+        }
+
+        let mut checks = Vec::new();
+        for (bound, op) in vec![(bounds[1], ast::InfixOperator::Moreeq), (bounds[2], ast::InfixOperator::Lesseq)] {
+            let tmp = self.temporary(format!("{} {} {}", self.memory[value].name,
+                    if op == ast::InfixOperator::Moreeq { ">=" } else { "<=" }, self.memory[bound].name),
+                ast::Typed{
+                    t:      ast::Type::Bool,
+                    ptr:    Vec::new(),
+                    loc:    loc.clone(),
+                    tail:   ast::Tail::None,
+                },
+                loc.clone(),
+                Tags::new(),
+            )?;
+            self.memory[tmp].value = Value::InfixOp {
+                lhs:    (bounds[0], 0),
+                rhs:    (bound, 0),
+                op:     op.clone(),
+            };
+            self.ssa.infix_op(tmp, (bounds[0], 0), (bound, 0), op, smt::Type::Bool, signed);
+            checks.push((tmp, 0));
+        }
+
+        if attest {
+            for check in checks {
+                if !self.ssa.attest(check, true) {
+                    return Err(self.trace(format!("@range of {} breaks ssa", self.memory[field].name), vec![
+                        (loc.clone(), format!("there may be conflicting constraints"))
+                    ]));
+                }
+            }
+            return Ok(());
+        }
+
+        self.ssa.debug("assert that value is within @range");
+        self.ssa.assert(checks.clone(), |a, model| match a {
+            false => {
+                let mut estack = Vec::new();
+                for check in &checks {
+                    estack.extend(self.demonstrate(model.as_ref().unwrap(), *check, 0));
+                }
+                estack.push((loc.clone(), format!("{} is declared with a @range", self.memory[field].name)));
+                Err(self.trace(format!("possible value out of @range of {}", self.memory[field].name), estack))
+            }
+            true => {
+                Ok(())
+            }
+        })
+    }
+
+    /// the loop variable in this pass against its value before the loop, see induction()
+    fn induction_bound(&mut self, sym: Symbol, initial: u64, op: ast::InfixOperator, loc: &ast::Location) -> Result<(), Error> {
+        let bound = self.temporary(format!("{} since loop start", self.memory[sym].name),
+            ast::Typed{
+                t:      ast::Type::Bool,
+                ptr:    Vec::new(),
+                loc:    loc.clone(),
+                tail:   ast::Tail::None,
+            },
+            loc.clone(),
+            Tags::new(),
+        )?;
+        self.ssa.infix_op(
+            bound,
+            (sym, self.memory[sym].temporal),
+            (sym, initial),
+            op,
+            smt::Type::Bool,
+            self.memory[sym].typed.t.signed(),
+        );
+        let bound = (bound, self.memory[bound].temporal);
+        self.cur().trace.push((bound, loc.clone(), false));
+        if !self.ssa.attest(bound, true) {
+            return Err(self.trace(format!("loop variable breaks ssa"), vec![
+                (loc.clone(), format!("there may be conflicting constraints"))
+            ]));
+        }
+        Ok(())
+    }
+
+    /// ptr + offset is only provable if offset < len(ptr). the result is then len(ptr) - offset long.
+    /// an offset that can never be in bounds is rejected outright, one that might be needs a check before.
+    fn pointer_arithmetic(
+        &mut self,
+        lhs_sym:    Symbol,
+        rhs_sym:    Symbol,
+        tmp:        Symbol,
+        op:         &ast::InfixOperator,
+        signed:     bool,
+        loc:        &ast::Location,
+    ) -> Result<(), Error> {
+        let opposite_op =  match op {
+            ast::InfixOperator::Add      => ast::InfixOperator::Subtract,
+
+            //TODO need to check for wrap
+            //ast::InfixOperator::Subtract => ast::InfixOperator::Add,
+            _ => {
+                return Err(self.trace(format!("unprovable pointer arithmetic"), vec![
+                    (loc.clone(), format!("only + is possible"))
+                ]));
+            }
+        };
+
+        self.ssa.debug("begin pointer arithmetic");
+        let len_of_lhs = self.temporary(format!("len({})", self.memory[lhs_sym].name),
+            ast::Typed{
+                t:      ast::Type::USize,
+                ptr:    Vec::new(),
+                loc:    loc.clone(),
+                tail:   ast::Tail::None,
+            },
+            loc.clone(),
+            Tags::new(),
+        )?;
+        let lensym = self.builtin.get("len").expect("ICE: len theory not built in");
+        self.ssa.invocation(*lensym, vec![(lhs_sym, self.memory[lhs_sym].temporal)], (len_of_lhs, 0));
+
+        let len_assert = self.temporary(format!("{} < len({})", self.memory[rhs_sym].name, self.memory[lhs_sym].name),
+            ast::Typed{
+                t:      ast::Type::Bool,
+                ptr:    Vec::new(),
+                loc:    loc.clone(),
+                tail:   ast::Tail::None,
+            },
+            loc.clone(),
+            Tags::new(),
+        )?;
+        self.memory[len_assert].value = Value::InfixOp {
+            lhs:    (rhs_sym, self.memory[rhs_sym].temporal),
+            rhs:    (len_of_lhs, self.memory[len_of_lhs].temporal),
+            op:     ast::InfixOperator::Lessthan,
+        };
+        self.ssa.infix_op(
+            len_assert,
+            (rhs_sym, self.memory[rhs_sym].temporal),
+            (len_of_lhs, 0),
+            ast::InfixOperator::Lessthan,
+            smt::Type::Bool,
+            false,
+        );
+
+        // if the offset might be out of bounds, check if it always is
+        if !self.ssa.assert(vec![(len_assert, self.memory[len_assert].temporal)], |a, _| a) {
+            let overflow = self.temporary(format!("{} >= len({})", self.memory[rhs_sym].name, self.memory[lhs_sym].name),
+                ast::Typed{
+                    t:      ast::Type::Bool,
+                    ptr:    Vec::new(),
+                    loc:    loc.clone(),
+                    tail:   ast::Tail::None,
+                },
+                loc.clone(),
+                Tags::new(),
+            )?;
+            self.ssa.infix_op(
+                overflow,
+                (rhs_sym, self.memory[rhs_sym].temporal),
+                (len_of_lhs, 0),
+                ast::InfixOperator::Moreeq,
+                smt::Type::Bool,
+                false,
+            );
+            if self.ssa.assert(vec![(overflow, self.memory[overflow].temporal)], |a, _| a) {
+                return Err(self.trace(format!("out of bounds pointer arithmetic"), vec![
+                    (loc.clone(), format!("{} is never less than len({})", self.memory[rhs_sym].name, self.memory[lhs_sym].name))
+                ]));
+            }
+        }
+
+        self.ssa.debug("assert that length less than index is true");
+        self.ssa.assert(vec![(len_assert, self.memory[len_assert].temporal)], |a, model| match a {
+            false => {
+                let mut estack = Vec::new();
+                estack.extend(self.demonstrate(model.as_ref().unwrap(), (len_assert, self.memory[len_assert].temporal), 0));
+                estack.push((loc.clone(), format!("check that {} < len({}) before the arithmetic",
+                    self.memory[rhs_sym].name, self.memory[lhs_sym].name)));
+                Err(self.trace(format!("possible out of bounds pointer arithmetic"), estack))
+            }
+            true => {
+                Ok(())
+            }
+        })?;
+        self.ssa_mark_safe(tmp, loc)?;
+
+        let len_of_opresult = self.temporary(format!("len({})", self.memory[lhs_sym].name),
+            ast::Typed{
+                t:      ast::Type::USize,
+                ptr:    Vec::new(),
+                loc:    loc.clone(),
+                tail:   ast::Tail::None,
+            },
+            loc.clone(),
+            Tags::new(),
+        )?;
+        let lensym = self.builtin.get("len").expect("ICE: len theory not built in");
+        self.ssa.invocation(*lensym, vec![(tmp, self.memory[tmp].temporal)], (len_of_opresult, 0));
+
+        self.ssa.infix_op(
+            len_of_opresult,
+            (len_of_lhs, self.memory[len_of_lhs].temporal),
+            (rhs_sym, self.memory[rhs_sym].temporal),
+            opposite_op,
+            self.memory[tmp].t.clone(),
+            signed,
+        );
+
+
+        let mut value = Value::Unconstrained("pointer arithmetic".into());
+        if let Value::Array{..} = &self.memory[lhs_sym].value {
+            if let Some(nuval) = self.ssa.value((len_of_opresult, self.memory[len_of_opresult].temporal), |a,_|match a{
+                smt::Assertion::Constrained(val) => {
+                    let nuarray = HashMap::new();
+                    Some(Value::Array{len: val as usize, array: nuarray})
+                }
+                _ => None,
+            }) {
+                value = nuval;
+            }
+        };
+        self.memory[tmp].value = value;
+        Ok(())
+    }
+
     // a pointer value has been borrowed, so everything it points to might have been tampered with
     fn borrow_away(&mut self, sym: Symbol) {
         match &self.memory[sym].value.clone() {
@@ -2797,6 +3149,7 @@ impl Symbolic {
             tags,
             temporal:   0,
             assignments: HashMap::new(),
+            range:      None,
             borrows:    Vec::new(),
         });
         debug!("{} := {}", name, symbol);
@@ -2899,6 +3252,7 @@ impl Symbolic {
             tags,
             temporal:   0,
             assignments: HashMap::new(),
+            range:      None,
             borrows:    Vec::new(),
         });
         debug!("{} {} := {}", name, typed, symbol);
@@ -2996,8 +3350,9 @@ impl Symbolic {
                            }
                        }
 
-                       for i in 0..array.len() {
-                           self.memory[prev[&i]].value = self.memory[array[&i]].value.clone();
+                       // designated initializers leave gaps, so array is not always 0..array.len()
+                       for (i, member) in &array {
+                           self.memory[prev[i]].value = self.memory[*member].value.clone();
                        }
 
                        self.len_into_ssa(lhs, used_here, prev.len())?;
@@ -3171,6 +3526,7 @@ impl Symbolic {
             current_call:           Vec::new(),
             in_loop: false,
             in_model:false,
+            edition: Edition::default(),
         }
     }
 
@@ -3327,6 +3683,131 @@ impl Symbolic {
 }
 
 
+/// Ring for ::project::module::Ring
+fn short_name(typed: &ast::Typed) -> String {
+    match &typed.t {
+        ast::Type::Other(n) => n.0.last().map(|s| s.to_string()).unwrap_or_default(),
+        _ => typed.to_string(),
+    }
+}
+
+fn is_local(expr: &ast::Expression, name: &Name) -> bool {
+    match expr {
+        ast::Expression::Name(typed) => typed.t == ast::Type::Other(name.clone()),
+        _ => false,
+    }
+}
+
+/// finds anything in a loop body that could change a local other than the loop increment.
+/// inline c could change any of them
+struct Writes<'a> {
+    name:   &'a Name,
+    found:  bool,
+}
+
+impl<'a> Visit for Writes<'a> {
+    fn statement(&mut self, stm: &mut ast::Statement) {
+        match stm {
+            ast::Statement::Assign{lhs, ..} if is_local(lhs, self.name) => self.found = true,
+            ast::Statement::CBlock{..} => self.found = true,
+            _ => (),
+        }
+        walk_statement(self, stm);
+    }
+    fn expr(&mut self, expr: &mut ast::Expression) {
+        let changes = match expr {
+            ast::Expression::UnaryPost{expr: inner, ..} => is_local(inner, self.name),
+            ast::Expression::UnaryPre{op, expr: inner, ..} => match op {
+                ast::PrefixOperator::Increment
+                | ast::PrefixOperator::Decrement
+                | ast::PrefixOperator::AddressOf => is_local(inner, self.name),
+                _ => false,
+            },
+            _ => false,
+        };
+        if changes {
+            self.found = true;
+        }
+        walk_expr(self, expr);
+    }
+}
+
+/// an induction variable of a for loop: declared or assigned in the init, stepped by 1 towards the bound
+/// of the condition and not touched in the body. in every pass it is then at least (counting up)
+/// or at most (counting down) its initial value, since the condition stops it before it wraps.
+/// returns the variable and how it compares to its initial value.
+fn induction(
+    e1:     &[Box<ast::Statement>],
+    e2:     Option<&ast::Expression>,
+    e3:     &[Box<ast::Statement>],
+    body:   &mut ast::Block,
+) -> Option<(Name, ast::InfixOperator)> {
+    let name = match e1.last().map(|s| s.as_ref()) {
+        Some(ast::Statement::Var{name, array: None, assign: Some(_), ..}) => Name::from(name.as_str()),
+        Some(ast::Statement::Assign{lhs: ast::Expression::Name(typed), op: ast::AssignOperator::Eq, ..}) => {
+            match &typed.t {
+                ast::Type::Other(name) => name.clone(),
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+
+    if e3.len() != 1 {
+        return None;
+    }
+    let up = match e3[0].as_ref() {
+        ast::Statement::Expr{expr: ast::Expression::UnaryPost{op, expr, ..}, ..} if is_local(expr, &name) => {
+            *op == ast::PostfixOperator::Increment
+        }
+        ast::Statement::Expr{expr: ast::Expression::UnaryPre{op, expr, ..}, ..} if is_local(expr, &name) => {
+            match op {
+                ast::PrefixOperator::Increment => true,
+                ast::PrefixOperator::Decrement => false,
+                _ => return None,
+            }
+        }
+        ast::Statement::Assign{lhs, op, rhs: ast::Expression::Literal{value: ast::LiteralValue::Integer{value, ..}, ..}, ..}
+            if is_local(lhs, &name) && *value == 1 =>
+        {
+            match op {
+                ast::AssignOperator::Add => true,
+                ast::AssignOperator::Sub => false,
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+
+    // only a strict comparison keeps a step of 1 from wrapping around. a larger step can jump over the bound
+    let bounded = match e2 {
+        Some(ast::Expression::Infix{lhs, rhs, op, ..}) => match op {
+            ast::InfixOperator::Lessthan    => if up { is_local(lhs, &name) } else { is_local(rhs, &name) },
+            ast::InfixOperator::Morethan    => if up { is_local(rhs, &name) } else { is_local(lhs, &name) },
+            _ => false,
+        },
+        _ => false,
+    };
+    if !bounded {
+        return None;
+    }
+
+    // the condition is run in every pass too, like f(&i) in i < f(&i)
+    let mut writes = Writes{name: &name, found: false};
+    if let Some(e2) = e2 {
+        writes.expr(&mut e2.clone());
+    }
+    for stm in &mut body.statements {
+        writes.statement(stm);
+    }
+    if writes.found {
+        return None;
+    }
+
+    Some((name, if up { ast::InfixOperator::Moreeq } else { ast::InfixOperator::Lesseq }))
+}
+
+
 pub fn execute(module: &mut flatten::Module) -> bool {
     use rayon::prelude::*;
 
@@ -3352,28 +3833,44 @@ pub fn execute(module: &mut flatten::Module) -> bool {
         }
     }
 
+    // stolen jobs may run on a thread that is working on another module,
+    // so every function reports into its own buffer, replayed here in order
     let repl = function_at.into_par_iter().map(|(at, name, mut module, hints)|{
-        let mut sym = Symbolic::new(&Name::from(&name), &hints);
-        match sym.execute_module(&mut module, at) {
-            Err(e) => {
-                parser::emit_error(e.message.clone(), &e.details);
-                None
+        parser::capture(||{
+            let mut sym = Symbolic::new(&Name::from(&name), &hints);
+            match sym.execute_module(&mut module, at) {
+                Err(e) => {
+                    parser::emit_error(e.message.clone(), &e.details);
+                    None
+                }
+                Ok(_)  => {
+                    Some((at, module.d.remove(at).0))
+                }
             }
-            Ok(_)  => {
-                Some((at, module.d.remove(at).0))
+        })
+    }).collect::<Vec<(Option<Option<(usize, ast::Local)>>, Vec<parser::Diagnostic>)>>();
+
+    let mut ok = true;
+    let mut aborted = false;
+    for (r, diagnostics) in repl {
+        parser::replay(diagnostics);
+        match r {
+            Some(Some((at,l))) => {
+                module.d[at].0 = l;
+            }
+            Some(None) => {
+                ok = false;
+            }
+            None => {
+                aborted = true;
             }
         }
-    }).collect::<Vec<Option<(usize, ast::Local)>>>();
-
-    for r in repl {
-        if let Some((at,l)) = r {
-            module.d[at].0 = l;
-        } else {
-            return false;
-        }
+    }
+    if aborted {
+        parser::abort();
     }
 
-    true
+    ok
 }
 
 
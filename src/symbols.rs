@@ -0,0 +1,85 @@
+/// the c symbols of export functions
+
+use super::ast;
+use super::loader;
+use super::name::Name;
+use super::parser::emit_error;
+use std::collections::HashMap;
+
+fn is_c_ident(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => chars.all(|c| c.is_ascii_alphanumeric() || c == '_'),
+        _ => false,
+    }
+}
+
+/// whether local is emitted as a symbol other packages link against
+fn exported(local: &ast::Local) -> bool {
+    match &local.def {
+        ast::Def::Function{attr, ..} => {
+            local.vis == ast::Visibility::Export && local.name != "main"
+                && !attr.contains_key("inline") && !attr.contains_key("extern")
+        }
+        _ => false,
+    }
+}
+
+/// give every export fn its symbol from symbol_prefix, and check all of them. returns false on errors
+pub fn resolve(modules: &mut HashMap<Name, loader::Module>) -> bool {
+    let mut ok = true;
+    let mut names : Vec<Name> = modules.keys().cloned().collect();
+    names.sort_unstable();
+
+    let mut seen : HashMap<String, (Name, ast::Location)> = HashMap::new();
+    for name in &names {
+        let md = match modules.get_mut(name) {
+            Some(loader::Module::ZZ(md)) => md,
+            _ => continue,
+        };
+        let mut prefixed = Vec::new();
+        for local in md.locals.iter().filter(|l| exported(l)) {
+            let mut full = name.clone();
+            full.push(local.name.clone());
+
+            let symbol = match (md.symbols.get(&local.name), &md.symbol_prefix) {
+                (Some(s), _) => {
+                    if !is_c_ident(&s.name) {
+                        emit_error(msg!("symbol_invalid", symbol = s.name), &[(s.loc.clone(), msg!("symbol_invalid.note"))]);
+                        ok = false;
+                        continue;
+                    }
+                    s.name.clone()
+                }
+                (None, Some(prefix)) => {
+                    let symbol = format!("{}{}", prefix, local.name);
+                    if !is_c_ident(&symbol) {
+                        emit_error(msg!("symbol_prefix_invalid", prefix = prefix, symbol = symbol), &[
+                            (local.loc.clone(), msg!("symbol_prefix_invalid.note")),
+                        ]);
+                        ok = false;
+                        continue;
+                    }
+                    prefixed.push((local.name.clone(), ast::Symbol{name: symbol.clone(), loc: local.loc.clone()}));
+                    symbol
+                }
+                (None, None) => full.0[1..].join("_"),
+            };
+
+            match seen.get(&symbol) {
+                Some((other, loc)) => {
+                    emit_error(msg!("symbol_collision", symbol = symbol, name = full, other = other), &[
+                        (local.loc.clone(), msg!("symbol_collision.note")),
+                        (loc.clone(), msg!("symbol_collision.other", other = other)),
+                    ]);
+                    ok = false;
+                }
+                None => {
+                    seen.insert(symbol, (full, local.loc.clone()));
+                }
+            }
+        }
+        md.symbols.extend(prefixed);
+    }
+    ok
+}
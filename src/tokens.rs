@@ -0,0 +1,236 @@
+/// lossless tokens of zz source, for formatters, linters and diffing tools that don't need the ast
+
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    // trivia
+    Whitespace,
+    Newline,
+    Comment,
+    /// /// and /! comments
+    DocComment,
+
+    Keyword,
+    Ident,
+    /// integers and floats, including their type suffix, like 0xff_u8
+    Number,
+    /// "..", r".." and r#".."#
+    String,
+    Char,
+    /// @name, like @len or @deprecated
+    Attribute,
+    /// @{{ inline c }}@
+    CBlock,
+    /// #if, #elif, #else and #endif
+    Directive,
+    Punct,
+    Unknown,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Token<'a> {
+    pub kind:   Kind,
+    pub text:   &'a str,
+    /// byte offsets in the source
+    pub start:  usize,
+    pub end:    usize,
+    /// of start, both from 1. columns count chars
+    pub line:   usize,
+    pub column: usize,
+}
+
+impl<'a> Token<'a> {
+    pub fn is_trivia(&self) -> bool {
+        matches!(self.kind, Kind::Whitespace | Kind::Newline | Kind::Comment | Kind::DocComment)
+    }
+}
+
+pub const KEYWORDS : &[&str] = &[
+    "as", "async", "atomic", "await", "break", "closure", "comptime", "const", "continue", "default",
+    "else", "enum", "export", "extern", "false", "flags", "fn", "fntype", "for", "gen", "if", "inline",
+    "interface", "is", "macro", "model", "mut", "mutable", "pub", "return", "sizeof", "statemachine",
+    "static", "struct", "switch", "test", "theory", "thread_local", "true", "try", "type", "union", "unsafe",
+    "using", "where", "while", "wire", "yield",
+];
+
+/// longest first, so >>= isn't lexed as >> =
+const PUNCTS : &[&str] = &[
+    "...", "<<=", ">>=",
+    "::", "->", "=>", "==", "!=", "<=", ">=", "&&", "||", "<<", ">>", "++", "--",
+    "+=", "-=", "*=", "/=", "%=", "&=", "|=", "^=", "..",
+];
+
+pub fn tokenize(src: &str) -> Vec<Token<'_>> {
+    let mut r = Vec::new();
+    let mut at = 0;
+    let mut line = 1;
+    let mut column = 1;
+    while at < src.len() {
+        let (kind, len) = next(&src[at..], r.last().map(|t: &Token| t.kind));
+        let text = &src[at..at + len];
+        r.push(Token { kind, text, start: at, end: at + len, line, column });
+        for c in text.chars() {
+            if c == '\n' {
+                line += 1;
+                column = 1;
+            } else {
+                column += 1;
+            }
+        }
+        at += len;
+    }
+    r
+}
+
+/// the tokens of src without trivia
+pub fn significant(src: &str) -> Vec<Token<'_>> {
+    tokenize(src).into_iter().filter(|t| !t.is_trivia()).collect()
+}
+
+fn is_ident(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// bytes until end, including it, or the rest of s
+fn until(s: &str, from: usize, end: &str) -> usize {
+    s[from..].find(end).map(|p| from + p + end.len()).unwrap_or(s.len())
+}
+
+/// bytes of the quoted literal at the start of s
+fn quoted(s: &str) -> usize {
+    let quote = s.as_bytes()[0];
+    let mut i = 1;
+    let b = s.as_bytes();
+    while i < b.len() {
+        if b[i] == b'\\' {
+            i += 2;
+            continue;
+        }
+        // "" and '' are an escaped quote
+        if b[i] == quote {
+            if b.get(i + 1) == Some(&quote) {
+                i += 2;
+                continue;
+            }
+            return i + 1;
+        }
+        if b[i] == b'\n' {
+            return i;
+        }
+        i += 1;
+    }
+    b.len()
+}
+
+fn number(s: &str) -> usize {
+    let b = s.as_bytes();
+    let mut i = 0;
+    let prefixed = s.starts_with("0x") || s.starts_with("0b") || s.starts_with("0o");
+    if prefixed {
+        i = 2;
+        while i < b.len() && (b[i].is_ascii_hexdigit() || b[i] == b'_') {
+            i += 1;
+        }
+    } else {
+        while i < b.len() && (b[i].is_ascii_digit() || b[i] == b'_') {
+            i += 1;
+        }
+        if b.get(i) == Some(&b'.') && b.get(i + 1).map(|c| c.is_ascii_digit()).unwrap_or(false) {
+            i += 1;
+            while i < b.len() && (b[i].is_ascii_digit() || b[i] == b'_') {
+                i += 1;
+            }
+        }
+        if matches!(b.get(i), Some(b'e') | Some(b'E')) {
+            let mut j = i + 1;
+            if matches!(b.get(j), Some(b'+') | Some(b'-')) {
+                j += 1;
+            }
+            if b.get(j).map(|c| c.is_ascii_digit()).unwrap_or(false) {
+                i = j;
+                while i < b.len() && b[i].is_ascii_digit() {
+                    i += 1;
+                }
+            }
+        }
+    }
+    // type suffixes, like 1u8 and 0xff_u8
+    let suffix = s[i..].bytes().take_while(|c| is_ident(*c as char)).count();
+    i + suffix
+}
+
+fn next(s: &str, prev: Option<Kind>) -> (Kind, usize) {
+    let c = s.chars().next().unwrap();
+    let b = s.as_bytes();
+
+    if c == '\n' {
+        return (Kind::Newline, 1);
+    }
+    if s.starts_with("\r\n") {
+        return (Kind::Newline, 2);
+    }
+    if c.is_whitespace() {
+        let len = s.char_indices().find(|(_, c)| !c.is_whitespace() || *c == '\n' || *c == '\r')
+            .map(|(i, _)| i).unwrap_or(s.len());
+        return (Kind::Whitespace, len.max(c.len_utf8()));
+    }
+    if s.starts_with("//") {
+        let len = s.find('\n').unwrap_or(s.len());
+        let len = s[..len].trim_end_matches('\r').len();
+        let doc = s.starts_with("///") && !s.starts_with("////");
+        return (if doc { Kind::DocComment } else { Kind::Comment }, len);
+    }
+    if s.starts_with("/*") {
+        return (Kind::Comment, until(s, 2, "*/"));
+    }
+    if s.starts_with("/!") {
+        let len = s.find('\n').unwrap_or(s.len());
+        return (Kind::DocComment, s[..len].trim_end_matches('\r').len());
+    }
+    if s.starts_with("@{{") {
+        return (Kind::CBlock, until(s, 3, "}}@"));
+    }
+    if c == '@' {
+        let len = 1 + s[1..].bytes().take_while(|c| is_ident(*c as char)).count();
+        return (if len > 1 { Kind::Attribute } else { Kind::Punct }, len);
+    }
+    // only at the start of a line, # is also how raw strings end
+    if c == '#' && matches!(prev, None | Some(Kind::Newline)) {
+        let len = 1 + s[1..].bytes().take_while(|c| c.is_ascii_alphabetic()).count();
+        if matches!(&s[..len], "#if" | "#elif" | "#else" | "#endif") {
+            return (Kind::Directive, len);
+        }
+    }
+    if c == 'r' {
+        let hashes = s[1..].bytes().take_while(|c| *c == b'#').count();
+        if b.get(1 + hashes) == Some(&b'"') {
+            let end = format!("\"{}", "#".repeat(hashes));
+            return (Kind::String, until(s, 2 + hashes, &end));
+        }
+    }
+    if c == '"' {
+        return (Kind::String, quoted(s));
+    }
+    if c == '\'' {
+        return (Kind::Char, quoted(s));
+    }
+    if c.is_ascii_digit() {
+        return (Kind::Number, number(s));
+    }
+    if c.is_ascii_alphabetic() || c == '_' {
+        let len = s.bytes().take_while(|c| is_ident(*c as char)).count();
+        let kind = if KEYWORDS.contains(&&s[..len]) { Kind::Keyword } else { Kind::Ident };
+        return (kind, len);
+    }
+    for p in PUNCTS {
+        if s.starts_with(p) {
+            return (Kind::Punct, p.len());
+        }
+    }
+    if c.is_ascii_punctuation() {
+        return (Kind::Punct, 1);
+    }
+    (Kind::Unknown, c.len_utf8())
+}
@@ -0,0 +1,324 @@
+/// units of measure on numeric values
+
+use super::ast;
+use super::closure::find_def;
+use super::loader;
+use super::name::Name;
+use super::parser::emit_error;
+use std::collections::HashMap;
+
+pub const TAG : &str = "unit";
+
+/// the unit declared on a value of this type, or on what it points to
+pub fn declared(typed: &ast::Typed, tags: &ast::Tags) -> Option<String> {
+    let tags = match typed.ptr.first() {
+        Some(ptr) => &ptr.tags,
+        None => tags,
+    };
+    tags.get(TAG).and_then(|v| v.keys().next().cloned())
+}
+
+/// what an assignment writes to, for messages
+fn target(expr: &ast::Expression) -> String {
+    match expr {
+        ast::Expression::Name(ast::Typed{t: ast::Type::Other(name), ..}) => name.0.last().map(|n| n.to_string()).unwrap_or_default(),
+        ast::Expression::MemberAccess{rhs, ..} => rhs.clone(),
+        ast::Expression::ArrayAccess{lhs, ..} | ast::Expression::UnaryPre{expr: lhs, ..} => target(lhs),
+        _ => "the left side".to_string(),
+    }
+}
+
+struct Checker<'a> {
+    md:             &'a ast::Module,
+    modules:        &'a HashMap<Name, loader::Module>,
+    locals:         HashMap<String, (ast::Typed, Option<String>)>,
+    ret:            Option<String>,
+    findings:       Vec<(ast::Location, String, String)>,
+}
+
+impl<'a> Checker<'a> {
+    fn def(&self, expr: &ast::Expression) -> Option<&'a ast::Local> {
+        match expr {
+            ast::Expression::Name(ast::Typed{t: ast::Type::Other(name), ..}) if name.is_absolute() => {
+                find_def(name, self.md, self.modules)
+            }
+            _ => None,
+        }
+    }
+
+    /// the field of the struct named by typed
+    fn field(&self, typed: &ast::Typed, name: &str) -> Option<&'a ast::Field> {
+        let def = match &typed.t {
+            ast::Type::Other(n) => find_def(n, self.md, self.modules)?,
+            _ => return None,
+        };
+        match &def.def {
+            ast::Def::Struct{fields, ..} => fields.iter().find(|f| f.name == name),
+            _ => None,
+        }
+    }
+
+    /// the type of expr, as far as it is needed to find struct fields
+    fn typed(&self, expr: &ast::Expression) -> Option<ast::Typed> {
+        match expr {
+            ast::Expression::Name(ast::Typed{t: ast::Type::Other(name), ..}) if !name.is_absolute() => {
+                self.locals.get(&name.to_string()).map(|(t, _)| t.clone())
+            }
+            ast::Expression::Name(_) => match self.def(expr) {
+                Some(ast::Local{def: ast::Def::Static{typed, ..}, ..}) => Some(typed.clone()),
+                _ => None,
+            },
+            ast::Expression::MemberAccess{lhs, rhs, ..} => {
+                let typed = self.typed(lhs)?;
+                self.field(&typed, rhs).map(|f| f.typed.clone())
+            }
+            ast::Expression::ArrayAccess{lhs, ..}
+            | ast::Expression::UnaryPre{expr: lhs, ..}
+            | ast::Expression::UnaryPost{expr: lhs, ..} => self.typed(lhs),
+            ast::Expression::Cast{into, ..} => Some(into.clone()),
+            ast::Expression::Call{name, ..} => match self.def(name) {
+                Some(ast::Local{def: ast::Def::Function{ret: Some(ret), ..}, ..}) => Some(ret.typed.clone()),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// the unit of the value of expr
+    fn unit(&mut self, expr: &ast::Expression) -> Option<String> {
+        match expr {
+            ast::Expression::Name(ast::Typed{t: ast::Type::Other(name), ..}) if !name.is_absolute() => {
+                self.locals.get(&name.to_string()).and_then(|(_, u)| u.clone())
+            }
+            ast::Expression::Name(_) => match self.def(expr) {
+                Some(ast::Local{def: ast::Def::Static{typed, tags, ..}, ..}) => declared(typed, tags),
+                _ => None,
+            },
+            ast::Expression::MemberAccess{lhs, rhs, ..} => {
+                let typed = self.typed(lhs)?;
+                self.field(&typed, rhs).and_then(|f| declared(&f.typed, &f.tags))
+            }
+            ast::Expression::ArrayAccess{lhs, ..} => self.unit(lhs),
+            ast::Expression::UnaryPre{op: ast::PrefixOperator::Boolnot, ..} => None,
+            ast::Expression::UnaryPre{expr, ..} | ast::Expression::UnaryPost{expr, ..} => self.unit(expr),
+            ast::Expression::Cast{into, tags, expr, ..} => {
+                let inner = self.unit(expr);
+                declared(into, tags).or(inner)
+            }
+            ast::Expression::Call{name, ..} => match self.def(name) {
+                Some(ast::Local{def: ast::Def::Function{ret: Some(ret), ..}, ..}) => declared(&ret.typed, &ret.tags),
+                _ => None,
+            },
+            ast::Expression::Infix{lhs, rhs, op, loc} => {
+                let l = self.unit(lhs);
+                let r = self.unit(rhs);
+                match op {
+                    ast::InfixOperator::Multiply => match (l, r) {
+                        (Some(l), Some(r)) => Some(format!("{}*{}", l, r)),
+                        (l, r) => l.or(r),
+                    },
+                    ast::InfixOperator::Divide => match (l, r) {
+                        (Some(l), Some(r)) if l == r => None,
+                        (Some(l), Some(r)) => Some(format!("{}/{}", l, r)),
+                        (None, Some(r)) => Some(format!("1/{}", r)),
+                        (l, None) => l,
+                    },
+                    ast::InfixOperator::Add
+                    | ast::InfixOperator::Subtract
+                    | ast::InfixOperator::Modulo
+                    | ast::InfixOperator::Equals
+                    | ast::InfixOperator::Nequals
+                    | ast::InfixOperator::Lessthan
+                    | ast::InfixOperator::Morethan
+                    | ast::InfixOperator::Lesseq
+                    | ast::InfixOperator::Moreeq => {
+                        if let (Some(l), Some(r)) = (&l, &r) {
+                            if l != r {
+                                self.report(loc, msg!("unit_mismatch", lhs = l, rhs = r), msg!("unit_mismatch.note", unit = l));
+                            }
+                        }
+                        if op.returns_boolean() { None } else { l.or(r) }
+                    }
+                    ast::InfixOperator::Booland | ast::InfixOperator::Boolor => None,
+                    _ => l,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn report(&mut self, loc: &ast::Location, message: String, note: String) {
+        let seen = self.findings.iter().any(|(l, m, _)| l.file == loc.file && (l.start, l.end) == (loc.start, loc.end) && *m == message);
+        if !seen {
+            self.findings.push((loc.clone(), message, note));
+        }
+    }
+
+    /// a value of expr stored where want is declared
+    fn store(&mut self, want: &Option<String>, expr: &ast::Expression, name: &str) {
+        let have = self.unit(expr);
+        if let (Some(want), Some(have)) = (want, have) {
+            if *want != have {
+                self.report(expr.loc(), msg!("unit_assign", have = have, want = want), msg!("unit_assign.note", name = name, unit = want));
+            }
+        }
+    }
+
+    /// report mixed units in the arguments and operands of expr
+    fn expr(&mut self, expr: &ast::Expression) {
+        match expr {
+            ast::Expression::Name(_)
+            | ast::Expression::LiteralString{..}
+            | ast::Expression::LiteralChar{..}
+            | ast::Expression::Literal{..} => {}
+            ast::Expression::MemberAccess{lhs, ..} => self.expr(lhs),
+            ast::Expression::ArrayAccess{lhs, rhs, ..} => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            ast::Expression::Infix{lhs, rhs, ..} => {
+                self.expr(lhs);
+                self.expr(rhs);
+                self.unit(expr);
+            }
+            ast::Expression::Call{name, args, ..} => {
+                self.expr(name);
+                for arg in args {
+                    self.expr(arg);
+                }
+                let fargs = match self.def(name) {
+                    Some(ast::Local{def: ast::Def::Function{args, ..}, ..}) => args,
+                    _ => return,
+                };
+                for (farg, arg) in fargs.iter().zip(args.iter()) {
+                    self.store(&declared(&farg.typed, &farg.tags), arg, &farg.name);
+                }
+            }
+            ast::Expression::Cast{expr, ..}
+            | ast::Expression::UnaryPost{expr, ..}
+            | ast::Expression::UnaryPre{expr, ..} => self.expr(expr),
+            ast::Expression::StructInit{typed, fields, ..} => {
+                for (name, e) in fields {
+                    self.expr(e);
+                    if let Some(field) = self.field(typed, name) {
+                        self.store(&declared(&field.typed, &field.tags), e, name);
+                    }
+                }
+            }
+            ast::Expression::ArrayInit{fields, ..} => {
+                for e in fields {
+                    self.expr(e);
+                }
+            }
+        }
+    }
+
+    fn block(&mut self, block: &ast::Block) {
+        for stm in &block.statements {
+            self.statement(stm);
+        }
+    }
+
+    fn statement(&mut self, stm: &ast::Statement) {
+        match stm {
+            ast::Statement::Mark{lhs, ..} => self.expr(lhs),
+            ast::Statement::Label{..}
+            | ast::Statement::CBlock{..}
+            | ast::Statement::Break{..}
+            | ast::Statement::Continue{..} => {}
+            ast::Statement::Return{expr, ..} => {
+                if let Some(expr) = expr {
+                    self.expr(expr);
+                    let ret = self.ret.clone();
+                    self.store(&ret, expr, "the return value");
+                }
+            }
+            ast::Statement::Block(b) | ast::Statement::Unsafe(b) => self.block(b),
+            ast::Statement::Expr{expr, ..} => self.expr(expr),
+            ast::Statement::Var{name, typed, tags, array, assign, ..} => {
+                if let Some(Some(size)) = array {
+                    self.expr(size);
+                }
+                let unit = declared(typed, tags);
+                if let Some(assign) = assign {
+                    self.expr(assign);
+                    self.store(&unit, assign, name);
+                }
+                self.locals.insert(name.clone(), (typed.clone(), unit));
+            }
+            ast::Statement::Assign{lhs, rhs, op, ..} => {
+                self.expr(lhs);
+                self.expr(rhs);
+                match op {
+                    ast::AssignOperator::Eq | ast::AssignOperator::Add | ast::AssignOperator::Sub => {
+                        let unit = self.unit(lhs);
+                        self.store(&unit, rhs, &target(lhs));
+                    }
+                    _ => (),
+                }
+            }
+            ast::Statement::If{branches} => {
+                for (_, cond, body) in branches {
+                    if let Some(cond) = cond {
+                        self.expr(cond);
+                    }
+                    self.block(body);
+                }
+            }
+            ast::Statement::While{expr, body} => {
+                self.expr(expr);
+                self.block(body);
+            }
+            ast::Statement::For{e1, e2, e3, body} => {
+                for s in e1 {
+                    self.statement(s);
+                }
+                if let Some(e2) = e2 {
+                    self.expr(e2);
+                }
+                for s in e3 {
+                    self.statement(s);
+                }
+                self.block(body);
+            }
+            ast::Statement::Switch{expr, cases, default, ..} => {
+                self.expr(expr);
+                for (_, body) in cases {
+                    self.block(body);
+                }
+                if let Some(default) = default {
+                    self.block(default);
+                }
+            }
+        }
+    }
+}
+
+/// check every function of md. returns false if values of different units are mixed
+pub fn check(md: &ast::Module, modules: &HashMap<Name, loader::Module>) -> bool {
+    let mut findings = Vec::new();
+    for local in &md.locals {
+        let (args, ret, body) = match &local.def {
+            ast::Def::Function{args, ret, body, ..} => (&args[..], ret.as_ref(), body),
+            ast::Def::Test{body, ..} => (&[][..], None, body),
+            _ => continue,
+        };
+        let mut checker = Checker{
+            md,
+            modules,
+            locals:     HashMap::new(),
+            ret:        ret.and_then(|r| declared(&r.typed, &r.tags)),
+            findings:   Vec::new(),
+        };
+        for arg in args {
+            checker.locals.insert(arg.name.clone(), (arg.typed.clone(), declared(&arg.typed, &arg.tags)));
+        }
+        checker.block(body);
+        findings.extend(checker.findings);
+    }
+
+    for (loc, message, note) in &findings {
+        emit_error(message, &[(loc.clone(), note)]);
+    }
+    findings.is_empty()
+}
@@ -0,0 +1,156 @@
+/// test "name" { } declarations
+
+use super::ast;
+use super::make::Stage;
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;
+use std::io::Write;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Test {
+    pub symbol:     String,
+    pub name:       String,
+    pub file:       String,
+    pub line:       usize,
+}
+
+pub fn lower(module: &mut ast::Module, stage: &Stage) {
+    for local in std::mem::take(&mut module.locals) {
+        let (name, body) = match local.def {
+            ast::Def::Test{name, body} => (name, body),
+            _ => {
+                module.locals.push(local);
+                continue;
+            }
+        };
+        if !stage.tests {
+            continue;
+        }
+        let mut hints = HashMap::new();
+        hints.insert("test".to_string(), name);
+        module.locals.push(ast::Local{
+            name:   local.name,
+            vis:    ast::Visibility::Shared,
+            doc:    local.doc,
+            def:    ast::Def::Function{
+                nameloc:        local.loc.clone(),
+                ret:            None,
+                args:           Vec::new(),
+                hints,
+                attr:           HashMap::new(),
+                body,
+                vararg:         false,
+                callassert:     Vec::new(),
+                calleffect:     Vec::new(),
+                callattests:    Vec::new(),
+            },
+            loc:    local.loc,
+        });
+    }
+}
+
+fn cstr(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// write the main of the harness. without arguments it runs all tests in order,
+/// --list prints index, name and location of every test, and a number runs only that test
+pub fn harness(path: &str, tests: &[Test]) {
+    let mut f = Vec::new();
+
+    // the modules are built with main renamed, so a project with a main can still have tests
+    write!(f, "#undef main\n").unwrap();
+    write!(f, "#include <stdio.h>\n").unwrap();
+    write!(f, "#include <stdlib.h>\n").unwrap();
+    write!(f, "#include <string.h>\n\n").unwrap();
+
+    for test in tests {
+        write!(f, "void {}();\n", test.symbol).unwrap();
+    }
+    write!(f, "\nstatic const struct {{ void (*fn)(); const char *name; const char *loc; }} tests[] = {{\n").unwrap();
+    for test in tests {
+        let loc = format!("{}:{}", test.file, test.line);
+        write!(f, "    {{ {}, \"{}\", {} }},\n", test.symbol, test.name, cstr(&loc)).unwrap();
+    }
+    write!(f, "}};\n").unwrap();
+    write!(f, "static const size_t ntests = {};\n\n", tests.len()).unwrap();
+
+    write!(f, "int main(int argc, char **argv) {{\n").unwrap();
+    write!(f, "    if (argc > 1 && strcmp(argv[1], \"--list\") == 0) {{\n").unwrap();
+    write!(f, "        for (size_t i = 0; i < ntests; i++) {{\n").unwrap();
+    write!(f, "            printf(\"%zu\\t%s\\t%s\\n\", i, tests[i].loc, tests[i].name);\n").unwrap();
+    write!(f, "        }}\n").unwrap();
+    write!(f, "        return 0;\n").unwrap();
+    write!(f, "    }}\n").unwrap();
+    write!(f, "    if (argc > 1) {{\n").unwrap();
+    write!(f, "        size_t i = (size_t)strtoul(argv[1], NULL, 10);\n").unwrap();
+    write!(f, "        if (i >= ntests) {{\n").unwrap();
+    write!(f, "            fprintf(stderr, \"no test %s\\n\", argv[1]);\n").unwrap();
+    write!(f, "            return 2;\n").unwrap();
+    write!(f, "        }}\n").unwrap();
+    write!(f, "        tests[i].fn();\n").unwrap();
+    write!(f, "        return 0;\n").unwrap();
+    write!(f, "    }}\n").unwrap();
+    write!(f, "    for (size_t i = 0; i < ntests; i++) {{\n").unwrap();
+    write!(f, "        printf(\"test %s ... \", tests[i].name);\n").unwrap();
+    write!(f, "        fflush(stdout);\n").unwrap();
+    write!(f, "        tests[i].fn();\n").unwrap();
+    write!(f, "        printf(\"ok\\n\");\n").unwrap();
+    write!(f, "    }}\n").unwrap();
+    write!(f, "    return 0;\n").unwrap();
+    write!(f, "}}\n").unwrap();
+
+    // keep the mtime, so make doesn't rebuild an unchanged harness
+    if std::fs::read(path).ok().as_ref() != Some(&f) {
+        std::fs::write(path, &f).expect(&format!("cannot write {}", path));
+    }
+}
+
+/// run every test of the harness in its own process. returns false if any failed
+pub fn run(root: &std::path::Path, stage: &Stage, filter: Option<&str>) -> bool {
+    let harness = root.join("target").join(stage.to_string()).join("bin").join("unittests");
+    if !harness.exists() {
+        return true;
+    }
+    let list = std::process::Command::new(&harness)
+        .arg("--list")
+        .output()
+        .expect("failed to execute process");
+
+    let mut failed = Vec::new();
+    let mut passed = 0;
+    for line in String::from_utf8_lossy(&list.stdout).lines() {
+        let mut line = line.splitn(3, '\t');
+        let (i, loc, name) = match (line.next(), line.next(), line.next()) {
+            (Some(i), Some(loc), Some(name)) => (i, loc, name),
+            _ => continue,
+        };
+        if let Some(filter) = filter {
+            if !name.contains(filter) {
+                continue;
+            }
+        }
+
+        let start = std::time::Instant::now();
+        let output = std::process::Command::new(&harness)
+            .arg(i)
+            .output()
+            .expect("failed to execute process");
+        if output.status.success() {
+            info!("PASS {} in {}ms", name, start.elapsed().as_millis());
+            passed += 1;
+        } else {
+            error!("FAIL {} ({})\n{}{}", name, loc,
+                String::from_utf8_lossy(&output.stdout),
+                String::from_utf8_lossy(&output.stderr));
+            failed.push(name.to_string());
+        }
+    }
+
+    if !failed.is_empty() {
+        error!("{} passed, {} failed: {}", passed, failed.len(), failed.join(", "));
+    } else if passed > 0 {
+        info!("{} passed", passed);
+    }
+    failed.is_empty()
+}
@@ -0,0 +1,317 @@
+/// the unused lint: imports, arguments and locals that nothing reads
+
+use super::ast;
+use super::name::Name;
+use super::parser::{emit_error, emit_warn};
+use super::project::LintLevel;
+use std::collections::HashSet;
+
+struct Finding {
+    loc:        ast::Location,
+    message:    String,
+    note:       String,
+}
+
+/// relative names read in a function body
+#[derive(Default)]
+struct Reads {
+    names:      HashSet<String>,
+    cblock:     bool,
+}
+
+impl Reads {
+    fn block(&mut self, block: &ast::Block) {
+        for stm in &block.statements {
+            self.statement(stm);
+        }
+    }
+
+    fn statement(&mut self, stm: &ast::Statement) {
+        match stm {
+            ast::Statement::Mark{lhs, ..} => self.expr(lhs),
+            ast::Statement::CBlock{..} => self.cblock = true,
+            ast::Statement::Label{..}
+            | ast::Statement::Break{..}
+            | ast::Statement::Continue{..} => {}
+            ast::Statement::Block(b) | ast::Statement::Unsafe(b) => self.block(b),
+            ast::Statement::For{e1, e2, e3, body} => {
+                for s in e1.iter().chain(e3.iter()) {
+                    self.statement(s);
+                }
+                if let Some(e2) = e2 {
+                    self.expr(e2);
+                }
+                self.block(body);
+            }
+            ast::Statement::While{expr, body} => {
+                self.expr(expr);
+                self.block(body);
+            }
+            ast::Statement::If{branches} => {
+                for (_, e, body) in branches {
+                    if let Some(e) = e {
+                        self.expr(e);
+                    }
+                    self.block(body);
+                }
+            }
+            ast::Statement::Assign{lhs, rhs, op, ..} => {
+                // x = 1 only writes x, x += 1 reads it too
+                match (lhs, op) {
+                    (ast::Expression::Name(_), ast::AssignOperator::Eq) => (),
+                    _ => self.expr(lhs),
+                }
+                self.expr(rhs);
+            }
+            ast::Statement::Var{typed, array, assign, ..} => {
+                self.tail(typed);
+                if let Some(Some(size)) = array {
+                    self.expr(size);
+                }
+                if let Some(assign) = assign {
+                    self.expr(assign);
+                }
+            }
+            ast::Statement::Expr{expr, ..} => self.expr(expr),
+            ast::Statement::Return{expr, ..} => {
+                if let Some(expr) = expr {
+                    self.expr(expr);
+                }
+            }
+            ast::Statement::Switch{expr, cases, default, ..} => {
+                self.expr(expr);
+                for (conds, body) in cases {
+                    for cond in conds {
+                        self.expr(cond);
+                    }
+                    self.block(body);
+                }
+                if let Some(default) = default {
+                    self.block(default);
+                }
+            }
+        }
+    }
+
+    // String+n reads n
+    fn tail(&mut self, typed: &ast::Typed) {
+        if let ast::Tail::Bind(name, _) = &typed.tail {
+            self.names.insert(name.clone());
+        }
+    }
+
+    fn expr(&mut self, expr: &ast::Expression) {
+        match expr {
+            ast::Expression::Name(typed) => {
+                if let ast::Type::Other(name) = &typed.t {
+                    if !name.is_absolute() {
+                        self.names.insert(name.to_string());
+                    }
+                }
+            }
+            ast::Expression::LiteralString{..}
+            | ast::Expression::LiteralChar{..}
+            | ast::Expression::Literal{..} => {}
+            ast::Expression::MemberAccess{lhs, ..} => self.expr(lhs),
+            ast::Expression::ArrayAccess{lhs, rhs, ..} | ast::Expression::Infix{lhs, rhs, ..} => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            ast::Expression::Call{name, args, ..} => {
+                self.expr(name);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            ast::Expression::Cast{expr, ..}
+            | ast::Expression::UnaryPost{expr, ..}
+            | ast::Expression::UnaryPre{expr, ..} => self.expr(expr),
+            ast::Expression::StructInit{fields, ..} => {
+                for (_, e) in fields {
+                    self.expr(e);
+                }
+            }
+            ast::Expression::ArrayInit{fields, ..} => {
+                for e in fields {
+                    self.expr(e);
+                }
+            }
+        }
+    }
+}
+
+/// the locals declared in a block, with where
+fn declared(block: &ast::Block, into: &mut Vec<(String, ast::Location)>) {
+    for stm in &block.statements {
+        match stm.as_ref() {
+            ast::Statement::Var{name, loc, ..} => into.push((name.clone(), loc.clone())),
+            ast::Statement::Block(b) | ast::Statement::Unsafe(b) => declared(b, into),
+            ast::Statement::For{e1, body, ..} => {
+                for s in e1 {
+                    if let ast::Statement::Var{name, loc, ..} = s.as_ref() {
+                        into.push((name.clone(), loc.clone()));
+                    }
+                }
+                declared(body, into);
+            }
+            ast::Statement::While{body, ..} => declared(body, into),
+            ast::Statement::If{branches} => {
+                for (_, _, body) in branches {
+                    declared(body, into);
+                }
+            }
+            ast::Statement::Switch{cases, default, ..} => {
+                for (_, body) in cases {
+                    declared(body, into);
+                }
+                if let Some(default) = default {
+                    declared(default, into);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+fn ignored(name: &str) -> bool {
+    name.starts_with('_') || name == "self"
+}
+
+fn function(local: &ast::Local, findings: &mut Vec<Finding>) {
+    let (args, body, conditions) : (&[ast::NamedArg], &ast::Block, Vec<&ast::Expression>) = match &local.def {
+        ast::Def::Function{args, body, callassert, calleffect, callattests, ..} => {
+            (&args[..], body, callassert.iter().chain(calleffect.iter()).chain(callattests.iter()).collect())
+        }
+        ast::Def::Test{body, ..} => (&[][..], body, Vec::new()),
+        _ => return,
+    };
+    let mut reads = Reads::default();
+    reads.block(body);
+    if reads.cblock {
+        return;
+    }
+    for expr in conditions {
+        reads.expr(expr);
+    }
+    for arg in args {
+        reads.tail(&arg.typed);
+    }
+
+    for arg in args {
+        if !ignored(&arg.name) && !reads.names.contains(&arg.name) {
+            findings.push(Finding{
+                loc:        arg.loc.clone(),
+                message:    msg!("unused_argument", name = arg.name),
+                note:       msg!("unused_argument.note", name = arg.name),
+            });
+        }
+    }
+
+    let mut locals = Vec::new();
+    declared(body, &mut locals);
+    for (name, loc) in locals {
+        if !ignored(&name) && !reads.names.contains(&name) {
+            findings.push(Finding{
+                loc,
+                message:    msg!("unused_local", name = name),
+                note:       msg!("unused_local.note"),
+            });
+        }
+    }
+}
+
+/// whether word is in src outside of the byte ranges in skip
+fn mentioned(src: &str, word: &str, skip: &[(usize, usize)]) -> bool {
+    let ident = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut from = 0;
+    while let Some(i) = src[from..].find(word).map(|i| i + from) {
+        let before = src[..i].chars().next_back().map(ident).unwrap_or(false);
+        let after  = src[i + word.len()..].chars().next().map(ident).unwrap_or(false);
+        if !before && !after && !skip.iter().any(|(start, end)| i >= *start && i < *end) {
+            return true;
+        }
+        from = i + word.len();
+    }
+    false
+}
+
+fn imports(md: &ast::Module, findings: &mut Vec<Finding>) {
+    let mut used : Vec<Name> = Vec::new();
+    for u in super::uses::uses(md, &|name| name.is_absolute()) {
+        used.push(u.name);
+    }
+    for import in &md.imports {
+        for (typed, _) in &import.needs {
+            if let ast::Type::Other(name) = &typed.t {
+                used.push(name.clone());
+            }
+        }
+    }
+
+    let src = std::fs::read_to_string(&md.source).unwrap_or_default();
+    let mut skip : Vec<(usize, usize)> = md.imports.iter().map(|i| (i.loc.start, i.loc.end)).collect();
+    skip.extend(md.comments.iter().map(|c| (c.start, c.end)));
+
+    // does a use resolve to name, or to something inside it, like an enum member or a method
+    let resolved = |name: &Name| used.iter().any(|u| {
+        u.0.len() >= name.0.len()
+            && u.0[..name.0.len() - 1] == name.0[..name.0.len() - 1]
+            && (u.0[name.0.len() - 1] == name.0[name.0.len() - 1]
+                || u.0[name.0.len() - 1].starts_with(&format!("{}::", name.0[name.0.len() - 1])))
+    });
+
+    for import in &md.imports {
+        if import.vis != ast::Visibility::Object || *import.loc.file.name() != *md.source.to_string_lossy() {
+            continue;
+        }
+        let ext = import.name.0.get(1).map(|s| s == "ext").unwrap_or(false);
+        if import.local.is_empty() {
+            // c headers are included for everything in them
+            if ext {
+                continue;
+            }
+            let local = import.alias.clone().unwrap_or_else(|| import.name.0.last().map(|n| n.to_string()).unwrap_or_default());
+            if !resolved(&import.name) && !mentioned(&src, &local, &skip) {
+                findings.push(Finding{
+                    loc:        import.loc.clone(),
+                    message:    msg!("unused_import", name = import.name),
+                    note:       msg!("unused_import.note"),
+                });
+            }
+            continue;
+        }
+        for (local, alias) in &import.local {
+            let mut name = import.name.clone();
+            name.push(local.clone());
+            let written = alias.as_ref().unwrap_or(local);
+            if !resolved(&name) && !mentioned(&src, written, &skip) {
+                findings.push(Finding{
+                    loc:        import.loc.clone(),
+                    message:    msg!("unused_import", name = name),
+                    note:       msg!("unused_import.note"),
+                });
+            }
+        }
+    }
+}
+
+/// run the lint on md. returns false if it found anything and level is deny
+pub fn check(md: &ast::Module, level: LintLevel) -> bool {
+    if level == LintLevel::Allow {
+        return true;
+    }
+    let mut findings = Vec::new();
+    imports(md, &mut findings);
+    for local in &md.locals {
+        function(local, &mut findings);
+    }
+
+    for finding in &findings {
+        if level == LintLevel::Deny {
+            emit_error(&finding.message, &[(finding.loc.clone(), &finding.note)]);
+        } else {
+            emit_warn(&finding.message, &[(finding.loc.clone(), &finding.note)]);
+        }
+    }
+    level != LintLevel::Deny || findings.is_empty()
+}
@@ -0,0 +1,216 @@
+/// names a module uses, after abs
+
+use super::ast;
+use super::name::Name;
+
+/// a name used where a declaration is expected
+pub struct Use {
+    pub name:   Name,
+    pub loc:    ast::Location,
+    // the absolute name of the declaration it is used in
+    pub by:     Name,
+}
+
+struct Walk<'a> {
+    wanted:     &'a dyn Fn(&Name) -> bool,
+    current:    Name,
+    uses:       Vec<Use>,
+}
+
+impl<'a> Walk<'a> {
+    fn typed(&mut self, typed: &ast::Typed) {
+        if let ast::Type::Other(name) = &typed.t {
+            if (self.wanted)(name) {
+                self.uses.push(Use{
+                    name:   name.clone(),
+                    loc:    typed.loc.clone(),
+                    by:     self.current.clone(),
+                });
+            }
+        }
+    }
+
+    fn args(&mut self, args: &[ast::NamedArg]) {
+        for arg in args {
+            self.typed(&arg.typed);
+        }
+    }
+
+    fn block(&mut self, block: &ast::Block) {
+        for stm in &block.statements {
+            self.statement(stm);
+        }
+    }
+
+    fn statement(&mut self, stm: &ast::Statement) {
+        match stm {
+            ast::Statement::Mark{lhs, ..} => self.expr(lhs),
+            ast::Statement::Label{..}
+            | ast::Statement::Break{..}
+            | ast::Statement::Continue{..}
+            | ast::Statement::CBlock{..} => {}
+            ast::Statement::Block(b) | ast::Statement::Unsafe(b) => self.block(b),
+            ast::Statement::For{e1, e2, e3, body} => {
+                for s in e1.iter().chain(e3.iter()) {
+                    self.statement(s);
+                }
+                if let Some(e2) = e2 {
+                    self.expr(e2);
+                }
+                self.block(body);
+            }
+            ast::Statement::While{expr, body} => {
+                self.expr(expr);
+                self.block(body);
+            }
+            ast::Statement::If{branches} => {
+                for (_, e, body) in branches {
+                    if let Some(e) = e {
+                        self.expr(e);
+                    }
+                    self.block(body);
+                }
+            }
+            ast::Statement::Assign{lhs, rhs, ..} => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            ast::Statement::Var{typed, array, assign, ..} => {
+                self.typed(typed);
+                if let Some(Some(size)) = array {
+                    self.expr(size);
+                }
+                if let Some(assign) = assign {
+                    self.expr(assign);
+                }
+            }
+            ast::Statement::Expr{expr, ..} => self.expr(expr),
+            ast::Statement::Return{expr, ..} => {
+                if let Some(expr) = expr {
+                    self.expr(expr);
+                }
+            }
+            ast::Statement::Switch{expr, cases, default, ..} => {
+                self.expr(expr);
+                for (conds, body) in cases {
+                    for cond in conds {
+                        self.expr(cond);
+                    }
+                    self.block(body);
+                }
+                if let Some(default) = default {
+                    self.block(default);
+                }
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &ast::Expression) {
+        match expr {
+            ast::Expression::Name(typed) => self.typed(typed),
+            ast::Expression::LiteralString{..}
+            | ast::Expression::LiteralChar{..}
+            | ast::Expression::Literal{..} => {}
+            ast::Expression::MemberAccess{lhs, ..} => self.expr(lhs),
+            ast::Expression::ArrayAccess{lhs, rhs, ..} | ast::Expression::Infix{lhs, rhs, ..} => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            ast::Expression::Call{name, args, ..} => {
+                self.expr(name);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            ast::Expression::Cast{into, expr, ..} => {
+                self.typed(into);
+                self.expr(expr);
+            }
+            ast::Expression::UnaryPost{expr, ..}
+            | ast::Expression::UnaryPre{expr, ..} => self.expr(expr),
+            ast::Expression::StructInit{typed, fields, ..} => {
+                self.typed(typed);
+                for (_, e) in fields {
+                    self.expr(e);
+                }
+            }
+            ast::Expression::ArrayInit{fields, ..} => {
+                for e in fields {
+                    self.expr(e);
+                }
+            }
+        }
+    }
+
+    fn local(&mut self, local: &ast::Local) {
+        match &local.def {
+            ast::Def::Static{typed, expr, array, ..} => {
+                self.typed(typed);
+                self.expr(expr);
+                if let Some(Some(size)) = array {
+                    self.expr(size);
+                }
+            }
+            ast::Def::Const{typed, expr, ..} => {
+                self.typed(typed);
+                self.expr(expr);
+            }
+            ast::Def::Function{ret, args, body, callassert, calleffect, callattests, ..} => {
+                if let Some(ret) = ret {
+                    self.typed(&ret.typed);
+                }
+                self.args(args);
+                for expr in callassert.iter().chain(calleffect.iter()).chain(callattests.iter()) {
+                    self.expr(expr);
+                }
+                self.block(body);
+            }
+            ast::Def::Theory{ret, args, ..} | ast::Def::Fntype{ret, args, ..} => {
+                if let Some(ret) = ret {
+                    self.typed(&ret.typed);
+                }
+                self.args(args);
+            }
+            ast::Def::Closure{fntype, target, ..} => {
+                self.typed(fntype);
+                self.typed(target);
+            }
+            ast::Def::Struct{fields, ..} => {
+                for field in fields {
+                    self.typed(&field.typed);
+                    if let Some(Some(size)) = &field.array {
+                        self.expr(size);
+                    }
+                    if let Some((lo, hi)) = &field.range {
+                        self.expr(lo);
+                        self.expr(hi);
+                    }
+                }
+            }
+            ast::Def::TypeAlias{typed} => self.typed(typed),
+            ast::Def::Macro{body, ..} | ast::Def::Test{body, ..} => self.block(body),
+            ast::Def::Testcase{fields} => {
+                for (_, expr) in fields {
+                    self.expr(expr);
+                }
+            }
+            ast::Def::Enum{..} | ast::Def::Include{..} => {}
+        }
+    }
+}
+
+/// the uses in md of absolute names that wanted returns true for, in source order
+pub fn uses(md: &ast::Module, wanted: &dyn Fn(&Name) -> bool) -> Vec<Use> {
+    let mut walk = Walk {
+        wanted,
+        current:    Name::default(),
+        uses:       Vec::new(),
+    };
+    for local in &md.locals {
+        walk.current = md.name.clone();
+        walk.current.push(local.name.clone());
+        walk.local(local);
+    }
+    walk.uses.sort_by_key(|u| (u.loc.file.name(), u.loc.start));
+    walk.uses
+}
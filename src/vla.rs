@@ -0,0 +1,184 @@
+/// the vla lint: stack allocations whose size is only known at runtime
+
+use super::ast;
+use super::parser::{emit_error, emit_warn};
+use super::project::LintLevel;
+
+const ALLOCA : &[&str] = &["alloca", "__builtin_alloca"];
+
+struct Finding {
+    loc:        ast::Location,
+    message:    String,
+    note:       String,
+}
+
+/// whether an array size is known at compile time
+pub fn constant(expr: &ast::Expression) -> bool {
+    match expr {
+        ast::Expression::Literal{..} | ast::Expression::LiteralChar{..} => true,
+        // constants and macros from c headers. locals are relative
+        ast::Expression::Name(ast::Typed{t: ast::Type::Other(name), ..}) => name.is_absolute(),
+        ast::Expression::Name(_) => true,
+        ast::Expression::Call{name, ..} => match name.as_ref() {
+            ast::Expression::Name(ast::Typed{t: ast::Type::Other(name), ..}) => {
+                name.len() == 4 && name.0[1] == "ext" && name.0[3] == "sizeof"
+            }
+            _ => false,
+        },
+        ast::Expression::Infix{lhs, rhs, ..} => constant(lhs) && constant(rhs),
+        ast::Expression::Cast{expr, ..} | ast::Expression::UnaryPre{expr, ..} => constant(expr),
+        _ => false,
+    }
+}
+
+pub fn alloca(name: &ast::Expression) -> Option<&str> {
+    match name {
+        ast::Expression::Name(ast::Typed{t: ast::Type::Other(name), ..}) => {
+            let last = name.0.last()?;
+            if ALLOCA.contains(&last.as_str()) {
+                Some(last)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+fn block(block: &ast::Block, findings: &mut Vec<Finding>) {
+    for stm in &block.statements {
+        statement(stm, findings);
+    }
+}
+
+fn statement(stm: &ast::Statement, findings: &mut Vec<Finding>) {
+    match stm {
+        ast::Statement::Mark{lhs, ..} => expr(lhs, findings),
+        ast::Statement::Label{..}
+        | ast::Statement::Break{..}
+        | ast::Statement::Continue{..}
+        | ast::Statement::CBlock{..} => {}
+        ast::Statement::Block(b) | ast::Statement::Unsafe(b) => block(b, findings),
+        ast::Statement::For{e1, e2, e3, body} => {
+            for s in e1.iter().chain(e3.iter()) {
+                statement(s, findings);
+            }
+            if let Some(e2) = e2 {
+                expr(e2, findings);
+            }
+            block(body, findings);
+        }
+        ast::Statement::While{expr: e, body} => {
+            expr(e, findings);
+            block(body, findings);
+        }
+        ast::Statement::If{branches} => {
+            for (_, e, body) in branches {
+                if let Some(e) = e {
+                    expr(e, findings);
+                }
+                block(body, findings);
+            }
+        }
+        ast::Statement::Assign{lhs, rhs, ..} => {
+            expr(lhs, findings);
+            expr(rhs, findings);
+        }
+        ast::Statement::Var{loc, name, array, assign, ..} => {
+            if let Some(Some(size)) = array {
+                expr(size, findings);
+                if !constant(size) {
+                    findings.push(Finding{
+                        loc:        loc.clone(),
+                        message:    msg!("vla_local", name = name),
+                        note:       msg!("vla_local.note"),
+                    });
+                }
+            }
+            if let Some(assign) = assign {
+                expr(assign, findings);
+            }
+        }
+        ast::Statement::Expr{expr: e, ..} => expr(e, findings),
+        ast::Statement::Return{expr: e, ..} => {
+            if let Some(e) = e {
+                expr(e, findings);
+            }
+        }
+        ast::Statement::Switch{expr: e, cases, default, ..} => {
+            expr(e, findings);
+            for (conds, body) in cases {
+                for cond in conds {
+                    expr(cond, findings);
+                }
+                block(body, findings);
+            }
+            if let Some(default) = default {
+                block(default, findings);
+            }
+        }
+    }
+}
+
+fn expr(e: &ast::Expression, findings: &mut Vec<Finding>) {
+    match e {
+        ast::Expression::Name(_)
+        | ast::Expression::LiteralString{..}
+        | ast::Expression::LiteralChar{..}
+        | ast::Expression::Literal{..} => {}
+        ast::Expression::MemberAccess{lhs, ..} => expr(lhs, findings),
+        ast::Expression::ArrayAccess{lhs, rhs, ..} | ast::Expression::Infix{lhs, rhs, ..} => {
+            expr(lhs, findings);
+            expr(rhs, findings);
+        }
+        ast::Expression::Call{name, args, loc, ..} => {
+            if let Some(f) = alloca(name) {
+                findings.push(Finding{
+                    loc:        loc.clone(),
+                    message:    msg!("vla_alloca", name = f),
+                    note:       msg!("vla_alloca.note"),
+                });
+            }
+            expr(name, findings);
+            for arg in args {
+                expr(arg, findings);
+            }
+        }
+        ast::Expression::Cast{expr: inner, ..}
+        | ast::Expression::UnaryPost{expr: inner, ..}
+        | ast::Expression::UnaryPre{expr: inner, ..} => expr(inner, findings),
+        ast::Expression::StructInit{fields, ..} => {
+            for (_, e) in fields {
+                expr(e, findings);
+            }
+        }
+        ast::Expression::ArrayInit{fields, ..} => {
+            for e in fields {
+                expr(e, findings);
+            }
+        }
+    }
+}
+
+/// run the lint on all function bodies of md. returns false if it found anything and level is deny
+pub fn check(md: &ast::Module, level: LintLevel) -> bool {
+    if level == LintLevel::Allow {
+        return true;
+    }
+    let mut findings = Vec::new();
+    for local in &md.locals {
+        match &local.def {
+            ast::Def::Function{body, ..} | ast::Def::Test{body, ..} => block(body, &mut findings),
+            _ => (),
+        }
+    }
+
+    for finding in &findings {
+        if level == LintLevel::Deny {
+            emit_error(&finding.message, &[(finding.loc.clone(), &finding.note)]);
+        } else {
+            emit_warn(&finding.message, &[(finding.loc.clone(), &finding.note)]);
+        }
+    }
+    level != LintLevel::Deny || findings.is_empty()
+}
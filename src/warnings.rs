@@ -0,0 +1,101 @@
+/// warning levels from the command line, and warnings held back until the end of the build
+
+use super::project::{LintLevel, Lints};
+use std::sync::Mutex;
+
+/// every lint that has a level, and can be named in [lints] and on the command line
+pub const LINTS : &[&str] = &["provenance", "vla", "pitfalls", "struct_init", "unused"];
+
+/// all lints at once
+pub const ALL : &str = "warnings";
+
+static LEVELS: Mutex<Vec<(String, LintLevel)>> = Mutex::new(Vec::new());
+static HELD: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// set a lint from the command line. fails if there is no such lint
+pub fn set(name: &str, level: LintLevel) -> Result<(), String> {
+    // lints of the project and registered passes, see lintpass.rs
+    if name != ALL && !LINTS.contains(&name) && !super::lintpass::known(name) {
+        return Err(format!("unknown lint '{}'. known lints are {} and {}", name, LINTS.join(", "), ALL));
+    }
+    LEVELS.lock().unwrap().push((name.to_string(), level));
+    Ok(())
+}
+
+/// the lints after applying the levels from the command line
+pub fn apply(mut lints: Lints) -> Lints {
+    for (name, level) in LEVELS.lock().unwrap().iter() {
+        if name == ALL {
+            for name in LINTS {
+                let current = lints.get(name);
+                match level {
+                    // only lints that are on, -W warnings would turn on all of them otherwise
+                    LintLevel::Deny if current == LintLevel::Warn => lints.set(name, LintLevel::Deny),
+                    LintLevel::Allow => lints.set(name, LintLevel::Allow),
+                    _ => (),
+                }
+            }
+        } else {
+            lints.set(name, *level);
+        }
+    }
+    lints
+}
+
+/// the level of a lint that isn't in Lints, like those of lintpass.rs, after the command line
+pub fn level(name: &str, mut level: LintLevel) -> LintLevel {
+    for (n, l) in LEVELS.lock().unwrap().iter() {
+        if n == name {
+            level = *l;
+        } else if n == ALL {
+            match l {
+                LintLevel::Deny if level == LintLevel::Warn => level = LintLevel::Deny,
+                LintLevel::Allow => level = LintLevel::Allow,
+                _ => (),
+            }
+        }
+    }
+    level
+}
+
+/// whether -D warnings was given, and not undone by a later -W or -A warnings
+pub fn denied() -> bool {
+    LEVELS.lock().unwrap().iter().rev()
+        .find(|(name, _)| name == ALL)
+        .map(|(_, level)| *level == LintLevel::Deny)
+        .unwrap_or(false)
+}
+
+/// the levels from the command line, as part of the build cache key. they decide what a build warns about and whether it fails
+pub fn key() -> String {
+    LEVELS.lock().unwrap().iter().map(|(name, level)| {
+        let level = match level {
+            LintLevel::Allow    => "A",
+            LintLevel::Warn     => "W",
+            LintLevel::Deny     => "D",
+        };
+        format!("-{} {}", level, name)
+    }).collect::<Vec<_>>().join(" ")
+}
+
+/// the warnings held so far, which the build cache keeps to print them again when nothing changed
+pub fn held() -> Vec<String> {
+    HELD.lock().unwrap().clone()
+}
+
+/// keep a rendered warning for print()
+pub fn hold(warning: String) {
+    HELD.lock().unwrap().push(warning);
+}
+
+/// print the held warnings. returns how many there were
+pub fn print() -> usize {
+    let held = std::mem::replace(&mut *HELD.lock().unwrap(), Vec::new());
+    for warning in &held {
+        eprintln!("{}", warning);
+    }
+    if !held.is_empty() {
+        warn!("{}", msg!("warnings_emitted", count = held.len()));
+    }
+    held.len()
+}
@@ -0,0 +1,82 @@
+/// zz watch, build again whenever a source changes
+
+use super::cache;
+use super::make;
+use super::project;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, Instant, SystemTime};
+
+type Snapshot = HashMap<PathBuf, SystemTime>;
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// modification times of the files the last build read, and of everything in the directories it listed
+fn snapshot(root: &Path, stage: &make::Stage, variant: &str) -> Snapshot {
+    let (files, dirs) = cache::watched(root, stage, variant).unwrap_or_else(|| {
+        (vec![root.join("zz.toml")], vec![root.join("src"), root.join("tests")])
+    });
+    let mut r = Snapshot::new();
+    for file in files {
+        if let Some(m) = modified(&file) {
+            r.insert(file, m);
+        }
+    }
+    for dir in dirs {
+        for entry in std::fs::read_dir(&dir).into_iter().flatten().flatten() {
+            let path = entry.path();
+            if path.is_file() {
+                if let Some(m) = modified(&path) {
+                    r.insert(path, m);
+                }
+            }
+        }
+    }
+    r
+}
+
+/// whether anything in now changed since last, which was taken before a build that started at since.
+/// files only now has are new if they were written after since, the build may just have found them
+fn changed(last: &Snapshot, now: &Snapshot, since: SystemTime) -> bool {
+    now.iter().any(|(path, m)| match last.get(path) {
+        Some(before) => before != m,
+        None => *m > since,
+    }) || last.keys().any(|path| !now.contains_key(path) && !path.exists())
+}
+
+pub fn main(command: &str, variant: &str) {
+    let (root, project) = project::load_cwd();
+    let stage = make::Stage::test().resolve(&project);
+    let exe = std::env::current_exe().expect("cannot find zz executable");
+
+    loop {
+        let since = SystemTime::now();
+        let last = snapshot(&root, &stage, variant);
+
+        let start = Instant::now();
+        let mut cmd = Command::new(&exe);
+        cmd.current_dir(&root).arg(command);
+        if command == "build" {
+            cmd.arg("--variant").arg(variant);
+        }
+        let ok = cmd.status().map(|s| s.success()).unwrap_or(false);
+        let time = format!("{:.1}s", start.elapsed().as_secs_f64());
+        if ok {
+            info!("{}", msg!("watch_pass", command = command, time = time));
+        } else {
+            error!("{}", msg!("watch_fail", command = command, time = time));
+        }
+
+        let mut now = snapshot(&root, &stage, variant);
+        info!("{}", msg!("watch_waiting", files = now.len()));
+        while !changed(&last, &now, since) {
+            std::thread::sleep(Duration::from_millis(300));
+            now = snapshot(&root, &stage, variant);
+        }
+        // editors often write a file in more than one step
+        std::thread::sleep(Duration::from_millis(100));
+    }
+}
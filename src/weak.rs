@@ -0,0 +1,115 @@
+/// link time defaults
+
+use super::ast;
+use super::closure;
+use super::loader;
+use super::name::Name;
+use super::parser::emit_error;
+use std::collections::{BTreeMap, HashMap};
+
+/// the functions overriding each @weak function, by the name of the @weak function
+fn overrides(modules: &HashMap<Name, loader::Module>) -> BTreeMap<Name, Vec<(Name, &ast::Override)>> {
+    let mut r : BTreeMap<Name, Vec<(Name, &ast::Override)>> = BTreeMap::new();
+    for (name, module) in modules {
+        if let loader::Module::ZZ(md) = module {
+            for (local, o) in &md.overrides {
+                let mut by = name.clone();
+                by.push(local.clone());
+                r.entry(o.target.clone()).or_default().push((by, o));
+            }
+        }
+    }
+    for by in r.values_mut() {
+        by.sort_by(|a, b| a.0.cmp(&b.0));
+    }
+    r
+}
+
+fn find<'a>(name: &Name, modules: &'a HashMap<Name, loader::Module>) -> Option<&'a ast::Local> {
+    let mut module = name.clone();
+    let local = module.pop()?;
+    match modules.get(&module) {
+        Some(loader::Module::ZZ(md)) => md.locals.iter().find(|l| l.name == local),
+        _ => None,
+    }
+}
+
+/// check every @override against the @weak function it replaces. returns false on errors
+pub fn check(modules: &HashMap<Name, loader::Module>) -> bool {
+    let mut ok = true;
+    for (target, by) in overrides(modules) {
+        for (name, o) in &by {
+            let (fargs, fret, fvararg) = match find(name, modules).map(|l| &l.def) {
+                Some(ast::Def::Function{args, ret, vararg, ..}) => (args, ret, *vararg),
+                _ => continue,
+            };
+            match find(&target, modules).map(|l| (&l.def, &l.loc)) {
+                Some((ast::Def::Function{args, ret, attr, vararg, ..}, loc)) if attr.contains_key("weak") => {
+                    let mismatch = if *vararg || fvararg {
+                        Some((None, "functions with ... cannot be overridden".to_string()))
+                    } else {
+                        closure::signature_mismatch(args, ret, fargs, fret, None)
+                    };
+                    if let Some((at, message)) = mismatch {
+                        let here = at.map(|i| fargs[i].loc.clone()).unwrap_or(o.loc.clone());
+                        emit_error(msg!("override_signature", name = name, target = target), &[
+                            (here, message),
+                            (loc.clone(), msg!("defined_here")),
+                        ]);
+                        ok = false;
+                    }
+                }
+                Some((_, loc)) => {
+                    emit_error(msg!("override_not_weak", target = target), &[
+                        (o.loc.clone(), msg!("override_not_weak.note")),
+                        (loc.clone(), msg!("defined_here")),
+                    ]);
+                    ok = false;
+                }
+                None => {
+                    emit_error(msg!("override_unknown", target = target), &[(o.loc.clone(), msg!("here"))]);
+                    ok = false;
+                }
+            }
+        }
+        if by.len() > 1 {
+            emit_error(msg!("override_twice", target = target), &[
+                (by[1].1.loc.clone(), msg!("here")),
+                (by[0].1.loc.clone(), msg!("override_twice.first")),
+            ]);
+            ok = false;
+        }
+    }
+    ok
+}
+
+/// print every @weak function of the build, and what overrides it
+pub fn print(names: &[Name], modules: &HashMap<Name, loader::Module>) {
+    let by = overrides(modules);
+    let mut weak = Vec::new();
+    for name in names {
+        if let Some(loader::Module::ZZ(md)) = modules.get(name) {
+            for local in &md.locals {
+                if let ast::Def::Function{attr, ..} = &local.def {
+                    if attr.contains_key("weak") {
+                        let mut fqn = name.clone();
+                        fqn.push(local.name.clone());
+                        weak.push(fqn);
+                    }
+                }
+            }
+        }
+    }
+    if weak.is_empty() {
+        return;
+    }
+    println!("\nweak functions:");
+    let width = weak.iter().map(|n| n.to_string().len()).max().unwrap_or(0);
+    for name in &weak {
+        let active = match by.get(name).and_then(|by| by.first()) {
+            Some((over, o)) => format!("overridden by {} at {}:{}", over, o.loc.file, o.loc.line()),
+            None => "default".to_string(),
+        };
+        println!("  {:w$}  {}", name.to_string(), active, w = width);
+    }
+}
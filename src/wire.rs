@@ -0,0 +1,207 @@
+/// wire formats: framed binary messages with generated, bounds checked parse and write
+
+use super::ast;
+use super::derive;
+use super::edition::Edition;
+use super::make::Stage;
+use super::parser::{self, emit_error};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::Path;
+
+const INTEGERS : &[(&str, usize)] = &[("u8", 1), ("u16", 2), ("u32", 4), ("u64", 8)];
+
+fn width(typed: &str) -> Option<usize> {
+    INTEGERS.iter().find(|(t, _)| *t == typed).map(|(_, w)| *w)
+}
+
+/// an earlier field that is always on the wire and holds an integer
+fn integer_before<'a>(wire: &'a ast::Wire, i: usize, name: &str) -> Option<&'a ast::WireField> {
+    wire.fields[..i].iter().find(|f| f.name.0 == name && f.array.is_none() && f.cond.is_none())
+}
+
+fn check(wire: &ast::Wire) {
+    if wire.fields.is_empty() {
+        emit_error(msg!("wire_empty", name = wire.name), &[(wire.loc.clone(), msg!("here"))]);
+        parser::abort();
+    }
+    let mut ok = true;
+    for (i, field) in wire.fields.iter().enumerate() {
+        if let Some(first) = wire.fields[..i].iter().find(|f| f.name.0 == field.name.0) {
+            emit_error(msg!("wire_duplicate", name = field.name.0), &[
+                (field.name.1.clone(), msg!("wire_duplicate.note")),
+                (first.name.1.clone(), msg!("defined_here")),
+            ]);
+            ok = false;
+        }
+        match (&field.array, width(&field.typed.0)) {
+            (_, None) => {
+                emit_error(msg!("wire_type", typed = field.typed.0), &[(field.typed.1.clone(), msg!("wire_type.note"))]);
+                ok = false;
+            }
+            (Some(_), Some(w)) if w != 1 => {
+                emit_error(msg!("wire_array_type", name = field.name.0), &[(field.typed.1.clone(), msg!("wire_array_type.note"))]);
+                ok = false;
+            }
+            (Some((_, 0)), _) => {
+                emit_error(msg!("wire_size_invalid"), &[(field.loc.clone(), msg!("here"))]);
+                ok = false;
+            }
+            _ => (),
+        }
+        if let Some((Some((len, loc)), _)) = &field.array {
+            if integer_before(wire, i, len).is_none() {
+                emit_error(msg!("wire_field_before", name = len), &[(loc.clone(), msg!("wire_field_before.note"))]);
+                ok = false;
+            }
+        }
+        if let Some(((name, loc), _, _)) = &field.cond {
+            if integer_before(wire, i, name).is_none() {
+                emit_error(msg!("wire_field_before", name = name), &[(loc.clone(), msg!("wire_field_before.note"))]);
+                ok = false;
+            }
+        }
+    }
+    if !ok {
+        parser::abort();
+    }
+}
+
+/// the condition of a field as zz, or None if it is always on the wire
+fn condition(field: &ast::WireField) -> Option<String> {
+    field.cond.as_ref().map(|((name, _), op, value)| match op.as_str() {
+        "&" => format!("(self->{} & {}) != 0", name, value),
+        _   => format!("self->{} {} {}", name, op, value),
+    })
+}
+
+/// statements reading field from buf at at, or writing it there
+fn field(s: &mut String, field: &ast::WireField, write: bool) {
+    let name = &field.name.0;
+    let w = width(&field.typed.0).unwrap();
+    let indent = if let Some(cond) = condition(field) {
+        writeln!(s, "        if {} {{", cond).unwrap();
+        "    "
+    } else {
+        ""
+    };
+    match &field.array {
+        None => {
+            writeln!(s, "{}        if at + {} > n {{", indent, w).unwrap();
+            writeln!(s, "{}            return 0;", indent).unwrap();
+            writeln!(s, "{}        }}", indent).unwrap();
+            // most significant byte first
+            let at = |b: usize| if b == 0 { "buf[at]".to_string() } else { format!("buf[at + {}]", b) };
+            if write {
+                for b in 0..w {
+                    let shift = (w - b - 1) * 8;
+                    let value = match (w, shift) {
+                        (1, _) => format!("self->{}", name),
+                        (_, 0) => format!("as<u8>(self->{})", name),
+                        _      => format!("as<u8>(self->{} >> {})", name, shift),
+                    };
+                    writeln!(s, "{}        {} = {};", indent, at(b), value).unwrap();
+                }
+            } else {
+                let parts : Vec<String> = (0..w).map(|b| {
+                    let shift = (w - b - 1) * 8;
+                    match (w, shift) {
+                        (1, _) => at(b),
+                        (_, 0) => format!("as<{}>({})", field.typed.0, at(b)),
+                        _      => format!("(as<{}>({}) << {})", field.typed.0, at(b), shift),
+                    }
+                }).collect();
+                writeln!(s, "{}        self->{} = {};", indent, name, parts.join(" | ")).unwrap();
+            }
+            writeln!(s, "{}        at += {};", indent, w).unwrap();
+        }
+        Some((len, max)) => {
+            let size = match len {
+                Some((len, _)) => format!("as<usize>(self->{})", len),
+                None => max.to_string(),
+            };
+            writeln!(s, "{}        usize size_{} = {};", indent, name, size).unwrap();
+            writeln!(s, "{}        if size_{} > {} || at + size_{} > n {{", indent, name, max, name).unwrap();
+            writeln!(s, "{}            return 0;", indent).unwrap();
+            writeln!(s, "{}        }}", indent).unwrap();
+            writeln!(s, "{}        for (usize mut i = 0; i < size_{}; i++) {{", indent, name).unwrap();
+            if write {
+                writeln!(s, "{}            buf[at + i] = self->{}[i];", indent, name).unwrap();
+            } else {
+                writeln!(s, "{}            self->{}[i] = buf[at + i];", indent, name).unwrap();
+            }
+            writeln!(s, "{}        }}", indent).unwrap();
+            writeln!(s, "{}        at += size_{};", indent, name).unwrap();
+        }
+    }
+    if field.cond.is_some() {
+        writeln!(s, "        }}").unwrap();
+    }
+}
+
+/// zz source of the struct with parse and write
+fn source(wire: &ast::Wire) -> String {
+    let vis = match wire.vis {
+        ast::Visibility::Object => "",
+        ast::Visibility::Shared => "pub ",
+        ast::Visibility::Package => "pub(package) ",
+        ast::Visibility::Export => "export ",
+    };
+    let name = &wire.name;
+
+    let mut s = String::new();
+    writeln!(s, "// generated from wire {} in {}", name, wire.loc.file).unwrap();
+    writeln!(s).unwrap();
+    writeln!(s, "{}struct {} {{", vis, name).unwrap();
+    for f in &wire.fields {
+        match &f.array {
+            Some((_, max)) => writeln!(s, "    {} {}[{}];", f.typed.0, f.name.0, max).unwrap(),
+            None => writeln!(s, "    {} {};", f.typed.0, f.name.0).unwrap(),
+        }
+    }
+    writeln!(s).unwrap();
+    writeln!(s, "    {}fn parse({} mut* self, u8* buf @len(n), usize n) -> usize {{", vis, name).unwrap();
+    writeln!(s, "        usize mut at = 0;").unwrap();
+    for f in &wire.fields {
+        field(&mut s, f, false);
+    }
+    writeln!(s, "        return at;").unwrap();
+    writeln!(s, "    }}").unwrap();
+    writeln!(s).unwrap();
+    writeln!(s, "    {}fn write({}* self, u8 mut* buf @len(n), usize n) -> usize {{", vis, name).unwrap();
+    writeln!(s, "        usize mut at = 0;").unwrap();
+    for f in &wire.fields {
+        field(&mut s, f, true);
+    }
+    writeln!(s, "        return at;").unwrap();
+    writeln!(s, "    }}").unwrap();
+    writeln!(s, "}}").unwrap();
+    s
+}
+
+/// check the wire formats of md and add the structs they lower to
+pub fn lower(
+    md:         &mut ast::Module,
+    root:       &Path,
+    features:   &HashMap<String, bool>,
+    stage:      &Stage,
+    edition:    Edition,
+) {
+    if md.wires.is_empty() {
+        return;
+    }
+    let dir = root.join("target").join(stage.to_string()).join("wire");
+    std::fs::create_dir_all(&dir).expect("create target dir");
+    let stem = md.source.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+
+    for wire in md.wires.clone() {
+        check(&wire);
+        let path = dir.join(format!("{}_{}.zz", stem, wire.name));
+        std::fs::write(&path, source(&wire)).expect(&format!("cannot write {}", path.display()));
+
+        derive::splice(md, &path, features, stage, edition);
+        if let Some(local) = md.locals.iter_mut().find(|l| l.name == wire.name) {
+            local.doc = wire.doc.clone();
+        }
+    }
+}
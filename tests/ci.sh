@@ -19,11 +19,53 @@ do
 done
 
 
+cd $THIS/mustpass/comptime
+if [ "$(../../../target/release/zz eval 'FIB10 + primes_below(10)' | tail -n 1)" = 59 ]; then
+    echo zz eval passed
+else
+    echo zz eval failed
+    exit 1
+fi
+
+cd $THIS/mustpass/public_header
+../../../target/release/zz build
+if [ -f target/test/include/public_header.h ] && [ -f target/test/lib/libpublic_header.a ] && [ -f target/test/lib/libpublic_header.so ]; then
+    echo public header passed
+else
+    echo public header failed
+    exit 1
+fi
+
+../../../target/release/zz build --amalgamate --archive tar
+../../../target/release/zz build --amalgamate --archive zip
+if [ "$(tar tf target/test/amalgamation/public_header.tar | sort | tr '\n' ' ')" = "public_header.c public_header.h " ] \
+    && python3 -m zipfile -t target/test/amalgamation/public_header.zip; then
+    echo amalgamation archives passed
+else
+    echo amalgamation archives failed
+    exit 1
+fi
+
+cd $THIS/mustpass/fmt_roundtrip
+if ../../../target/release/zz fmt --check; then
+    echo fmt round trip passed
+else
+    echo fmt round trip failed
+    exit 1
+fi
+
+
 for i in $THIS/mustfail/*
 do
     cd $i
     ../../../target/release/zz clean;
-    if ../../../target/release/zz check;  then
+    # fixtures that fail in more than one build have a fail.sh instead
+    if [ -x fail.sh ]; then
+        check=./fail.sh
+    else
+        check="../../../target/release/zz check"
+    fi
+    if $check;  then
         echo "$i" passed, but it should not
         exit 1
     else
@@ -0,0 +1 @@
+void scale(int *values, unsigned int count, int by);
@@ -0,0 +1 @@
+unsigned int *counts(void);
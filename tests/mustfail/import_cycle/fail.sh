@@ -0,0 +1,4 @@
+#!/bin/sh
+# the cycle has to be what is reported, not a module along it that can't be found
+../../../target/release/zz check 2>&1 | grep -q "'scale' is imported in a cycle: ::import_cycle::a -> ::import_cycle::b -> ::import_cycle::a" && exit 1
+exit 0
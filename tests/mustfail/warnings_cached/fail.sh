@@ -0,0 +1,5 @@
+#!/bin/sh
+# a warning only build is cached, and -D warnings must still fail the same build afterwards
+../../../target/release/zz clean
+../../../target/release/zz build || exit 0
+../../../target/release/zz build -D warnings
@@ -0,0 +1,6 @@
+// callback style api, as some c library declares it
+static void each(void (*cb)(void *user, int item), void *user) {
+    for (int i = 1; i < 4; i++) {
+        cb(user, i);
+    }
+}